@@ -118,7 +118,9 @@ async fn analyze_qmdl(qmdl_path: &str, show_skipped: bool) {
         .await
         .expect("failed to get QMDL file metadata")
         .len();
-    let mut qmdl_reader = QmdlReader::new(qmdl_file, Some(file_size as usize));
+    let mut qmdl_reader = QmdlReader::new_maybe_compressed(qmdl_file, Some(file_size as usize))
+        .await
+        .expect("failed to read QMDL file");
     let mut qmdl_stream = pin!(
         qmdl_reader
             .as_stream()
@@ -135,6 +137,17 @@ async fn analyze_qmdl(qmdl_path: &str, show_skipped: bool) {
         }
     }
     report.print_summary(show_skipped);
+    let diag_versions = harness.get_diag_versions();
+    info!(
+        "{}: LTE RRC OTA ext header versions {:?}, NAS ext header versions {:?}",
+        qmdl_path, diag_versions.lte_rrc_ota, diag_versions.nas_4g
+    );
+    if !diag_versions.unsupported_lte_rrc_ota.is_empty() {
+        warn!(
+            "{}: LTE RRC OTA ext header versions {:?} have an unknown layout, so their messages were skipped",
+            qmdl_path, diag_versions.unsupported_lte_rrc_ota
+        );
+    }
 }
 
 async fn pcapify(qmdl_path: &PathBuf) {
@@ -142,8 +155,15 @@ async fn pcapify(qmdl_path: &PathBuf) {
         .await
         .expect("failed to open qmdl file");
     let qmdl_file_size = qmdl_file.metadata().await.unwrap().len();
-    let mut qmdl_reader = QmdlReader::new(qmdl_file, Some(qmdl_file_size as usize));
+    let mut qmdl_reader =
+        QmdlReader::new_maybe_compressed(qmdl_file, Some(qmdl_file_size as usize))
+            .await
+            .expect("failed to read qmdl file");
     let mut pcap_path = qmdl_path.clone();
+    // foo.qmdl.gz should become foo.pcapng rather than foo.qmdl.pcapng
+    if pcap_path.extension().is_some_and(|ext| ext == "gz") {
+        pcap_path.set_extension("");
+    }
     pcap_path.set_extension("pcapng");
     let pcap_file = &mut File::create(&pcap_path)
         .await
@@ -206,7 +226,7 @@ async fn main() {
         let path_str = path.to_str().unwrap();
         // instead of relying on the QMDL extension, can we check if a file is
         // QMDL by inspecting the contents?
-        if name_str.ends_with(".qmdl") {
+        if name_str.ends_with(".qmdl") || name_str.ends_with(".qmdl.gz") {
             info!("**** Beginning analysis of {name_str}");
             analyze_qmdl(path_str, args.show_skipped).await;
             if args.pcapify {
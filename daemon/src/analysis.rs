@@ -1,29 +1,423 @@
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use std::{future, pin};
 
 use axum::Json;
+use axum::body::Body;
+use axum::extract::Query;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::http::header::{CONTENT_TYPE, VARY};
+use axum::response::{IntoResponse, Response};
 use axum::{
     extract::{Path, State},
     http::StatusCode,
 };
+use chrono::{DateTime, FixedOffset};
 use futures::TryStreamExt;
-use log::{error, info};
-use rayhunter::analysis::analyzer::{AnalyzerConfig, Harness};
-use rayhunter::diag::{DataType, MessagesContainer};
-use rayhunter::qmdl::QmdlReader;
-use serde::Serialize;
+use log::{error, info, warn};
+use rayhunter::Device;
+use rayhunter::analysis::analyzer::{
+    AnalysisRow, AnalyzerConfig, CaptureMetadata, DeadLetter, Harness,
+};
+use rayhunter::diag::{DataType, DiagLayoutVersions, MessagesContainer};
+use rayhunter::qmdl::{InterleavedQmdlReader, QmdlInput, QmdlReader};
+use rayhunter::util::LogLimiter;
+use serde::{Deserialize, Serialize};
 use tokio::fs::File;
-use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
+use tokio::runtime::Handle;
+use tokio::sync::broadcast::{self, error::RecvError};
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::{RwLock, RwLockWriteGuard};
+use tokio::time::{Instant, sleep_until};
+use tokio_util::io::ReaderStream;
 use tokio_util::task::TaskTracker;
 
-use crate::qmdl_store::RecordingStore;
+use crate::analysis_log::with_recording;
+use crate::capture_pressure::CapturePressure;
+use crate::config::Config;
+use crate::flush::AdaptiveFlush;
+use crate::hook::PostAnalysisHook;
+use crate::middleware::{current_request_id, with_request_id};
+use crate::notification_queue::NotificationQueue;
+use crate::qmdl_store::{ManifestEntry, RecordingStore, RecordingStoreError};
+use crate::recent_findings::RecentFindings;
 use crate::server::ServerState;
+use crate::summary::{AnalysisSummary, summarize_analysis_file, summarize_analysis_path};
+use crate::syslog::FindingSyslog;
+use crate::wire_format::{
+    MESSAGEPACK_CONTENT_TYPE, WireFormat, ndjson_to_messagepack, ndjson_to_messagepack_body,
+};
+
+// Where an AnalysisWriter's rows end up
+enum AnalysisOutput<W> {
+    Writer(BufWriter<W>),
+    // used when the analysis file can't be written and the in-memory
+    // fallback is enabled
+    Memory(String),
+}
 
-pub struct AnalysisWriter {
+// Caps each recording's dead-letter file, so a capture full of messages we
+// can't decode can't fill up the storage
+const DEAD_LETTER_MAX_BYTES: usize = 1024 * 1024;
+
+// Records the messages an analysis couldn't decode, one JSON object per line,
+// ending with a {"truncated":true} line if the cap was hit
+struct DeadLetterWriter {
     writer: BufWriter<File>,
+    bytes_written: usize,
+    truncated: bool,
+}
+
+impl DeadLetterWriter {
+    async fn write_all(&mut self, dead_letters: &[DeadLetter]) -> Result<(), std::io::Error> {
+        for dead_letter in dead_letters {
+            if self.truncated {
+                break;
+            }
+            let mut line = serde_json::to_string(dead_letter).unwrap();
+            line.push('\n');
+            if self.bytes_written + line.len() > DEAD_LETTER_MAX_BYTES {
+                self.truncated = true;
+                line = "{\"truncated\":true}\n".to_string();
+            }
+            self.writer.write_all(line.as_bytes()).await?;
+            self.bytes_written += line.len();
+        }
+        if !dead_letters.is_empty() {
+            self.writer.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+// Stands in for a row that failed to serialize, keeping whichever of its
+// findings still can be
+fn placeholder_row(
+    name: &str,
+    analyzer_names: &[String],
+    row: &AnalysisRow,
+    err: &serde_json::Error,
+) -> AnalysisRow {
+    let mut failed = Vec::new();
+    let mut events = Vec::with_capacity(row.events.len());
+    for (event, analyzer) in row.events.iter().zip(analyzer_names) {
+        let Some(event) = event else {
+            events.push(None);
+            continue;
+        };
+        if let Err(event_err) = serde_json::to_string(event) {
+            error!("{name}: failed to serialize finding from {analyzer}: {event_err}: {event:?}");
+            failed.push(analyzer.as_str());
+            events.push(None);
+        } else {
+            events.push(Some(event.clone()));
+        }
+    }
+    let culprit = if failed.is_empty() {
+        // it wasn't any one finding, so none of them can be kept
+        error!("{name}: failed to serialize analysis row: {err}: {row:?}");
+        events.fill(None);
+        "this row".to_string()
+    } else {
+        failed.join(", ")
+    };
+    AnalysisRow {
+        packet_timestamp: row.packet_timestamp,
+        skipped_message_reason: Some(format!(
+            "failed to serialize findings from {culprit}: {err}"
+        )),
+        events,
+        correlation_id: row.correlation_id,
+    }
+}
+
+// What goes into a report's metadata besides the analyzers
+#[derive(Default)]
+struct ReportOptions {
+    // the recordings a merged analysis covers
+    recordings: Option<Vec<String>>,
+    capture: Option<CaptureMetadata>,
+    // analyze only one in every this many containers, or all of them for 0
+    // or 1
+    sample_every: usize,
+}
+
+// Writes to an analysis file by default, but can write to anything that's
+// AsyncWrite, such as a Vec<u8> for tests and other analyses that don't need
+// to be kept
+pub struct AnalysisWriter<W = File> {
+    output: AnalysisOutput<W>,
     harness: Harness,
+    name: String,
+    update_sender: broadcast::Sender<AnalysisUpdate>,
+    lines_written: usize,
+    skipped_messages: LogLimiter,
+    findings_syslog: Option<FindingSyslog>,
+    // the names of the harness's analyzers, in the same order as each row's
+    // events
+    analyzer_names: Vec<String>,
+    dead_letters: Option<DeadLetterWriter>,
+    recent_findings: Option<RecentFindings>,
+    decode_errors: DecodeErrorCounts,
+    flush: AdaptiveFlush,
+    // updates for lines that haven't been flushed to the analysis file yet,
+    // which are held back so that a websocket client replaying the file
+    // can't miss them
+    pending_updates: Vec<AnalysisUpdate>,
+    // only one in every this many containers is analyzed, if it's above 1
+    sample_every: usize,
+    containers_seen: usize,
+}
+
+// Containers an analysis couldn't make anything of, either because they
+// couldn't be read from the QMDL at all or because none of their messages
+// could be decoded
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DecodeErrorCounts {
+    // how many of the latest containers in a row were affected
+    pub consecutive: usize,
+    pub undecodable: usize,
+    pub unreadable: usize,
+}
+
+impl DecodeErrorCounts {
+    pub fn total(&self) -> usize {
+        self.undecodable + self.unreadable
+    }
+}
+
+// How many bad containers perform_analysis puts up with before giving up on
+// a recording. With neither limit set, undecodable containers are skipped
+// however many there are, but the first unreadable one ends the analysis,
+// since nothing would stop a reader that keeps failing.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DecodeErrorTolerance {
+    pub max_consecutive: Option<usize>,
+    pub max_total: Option<usize>,
+}
+
+// What the analysis thread does about a queue that's grown past
+// analysis_max_queue_depth
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueOverflow {
+    // leave the oldest queued recordings unanalyzed, listing them as skipped
+    #[default]
+    SkipOldest,
+    // analyze everything, but only sample the containers of each recording
+    // until the queue's short enough again
+    Sample,
+}
+
+// Keeps the analysis queue from growing without bound on devices that record
+// faster than they can analyze, which would otherwise leave the analysis
+// further behind with every recording, and the storage full of captures
+// nobody's looked at
+#[derive(Debug, Default, Clone, Copy)]
+pub struct QueueGuard {
+    // how many recordings can be queued before the guard steps in, if
+    // there's a limit
+    pub max_depth: Option<usize>,
+    pub overflow: QueueOverflow,
+    // how sparsely to sample while the queue's too long, for
+    // QueueOverflow::Sample
+    pub sample_every: usize,
+}
+
+impl QueueGuard {
+    pub fn from_config(config: &Config) -> Self {
+        QueueGuard {
+            max_depth: match config.analysis_max_queue_depth {
+                0 => None,
+                depth => Some(depth),
+            },
+            overflow: config.analysis_queue_overflow,
+            sample_every: config.analysis_overflow_sample_every,
+        }
+    }
+
+    // Deals with an overlong queue before the next recording's analyzed, and
+    // returns how sparsely that analysis should sample
+    async fn apply(
+        &self,
+        analysis_status_lock: &RwLock<AnalysisStatus>,
+        sample_every: usize,
+    ) -> usize {
+        let Some(max_depth) = self.max_depth else {
+            return sample_every;
+        };
+        let mut analysis_status = analysis_status_lock.write().await;
+        let depth = analysis_status.queued.len();
+        if depth <= max_depth {
+            return sample_every;
+        }
+        match self.overflow {
+            QueueOverflow::SkipOldest => {
+                let skipped = analysis_status.skip_oldest_queued(max_depth);
+                warn!(
+                    "{depth} recordings are queued for analysis, more than analysis_max_queue_depth ({max_depth}), so skipping the oldest: {}",
+                    skipped.join(", ")
+                );
+                sample_every
+            }
+            QueueOverflow::Sample => {
+                let sample_every = sample_every.max(self.sample_every);
+                info!(
+                    "{depth} recordings are queued for analysis, more than analysis_max_queue_depth ({max_depth}), so sampling one in every {sample_every} containers"
+                );
+                sample_every
+            }
+        }
+    }
+}
+
+// Which recordings in the analysis queue to analyze first (see
+// AnalysisPriorities). A rule matches a recording when everything it sets
+// does, so one that sets nothing matches every recording.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct AnalysisPriorityRule {
+    // matches recordings whose notes contain this, ignoring case
+    pub notes_contains: Option<String>,
+    // matches recordings that were imported, rather than recorded on this
+    // device, or the other way around
+    pub imported: Option<bool>,
+    // matches recordings made on this kind of device
+    pub device: Option<Device>,
+    pub priority: i32,
+}
+
+impl AnalysisPriorityRule {
+    fn matches(&self, entry: &ManifestEntry) -> bool {
+        let notes_match = self.notes_contains.as_ref().is_none_or(|wanted| {
+            entry
+                .notes
+                .as_ref()
+                .is_some_and(|notes| notes.to_lowercase().contains(&wanted.to_lowercase()))
+        });
+        let imported_match = self
+            .imported
+            .is_none_or(|imported| imported == entry.imported);
+        let device_match = self
+            .device
+            .as_ref()
+            .is_none_or(|device| entry.device.as_ref() == Some(device));
+        notes_match && imported_match && device_match
+    }
+}
+
+// Decides which queued recording is analyzed next: the one with the highest
+// priority, and of those, the one that was queued first. A recording's
+// priority is that of the highest priority analysis_priority rule that
+// matches it, or 0 if none do, so a rule with a negative priority puts the
+// recordings it matches behind the rest.
+#[derive(Debug, Default, Clone)]
+pub struct AnalysisPriorities {
+    rules: Vec<AnalysisPriorityRule>,
+}
+
+impl AnalysisPriorities {
+    pub fn from_config(config: &Config) -> Self {
+        AnalysisPriorities {
+            rules: config.analysis_priority.clone(),
+        }
+    }
+
+    fn priority(&self, entry: &ManifestEntry) -> i32 {
+        self.rules
+            .iter()
+            .filter(|rule| rule.matches(entry))
+            .map(|rule| rule.priority)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // The priority of every recording in the store
+    async fn priorities(&self, qmdl_store_lock: &RwLock<RecordingStore>) -> HashMap<String, i32> {
+        if self.rules.is_empty() {
+            return HashMap::new();
+        }
+        let qmdl_store = qmdl_store_lock.read().await;
+        qmdl_store
+            .manifest
+            .entries
+            .iter()
+            .map(|entry| (entry.name.clone(), self.priority(entry)))
+            .collect()
+    }
+}
+
+// The index of the recording to analyze next, given each recording's
+// priority. max_by_key picks the last of several equally high ones, so the
+// queue's searched from the back to get the first.
+fn next_in_queue(queued: &[String], priorities: &HashMap<String, i32>) -> Option<usize> {
+    queued
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, name)| priorities.get(*name).copied().unwrap_or(0))
+        .map(|(index, _)| index)
+}
+
+impl DecodeErrorTolerance {
+    pub fn from_config(config: &Config) -> Self {
+        DecodeErrorTolerance {
+            max_consecutive: config.analysis_max_consecutive_decode_errors,
+            max_total: config.analysis_max_decode_errors,
+        }
+    }
+
+    fn skips_unreadable(&self) -> bool {
+        self.max_consecutive.is_some() || self.max_total.is_some()
+    }
+
+    fn check(&self, errors: &DecodeErrorCounts) -> Result<(), String> {
+        if self
+            .max_consecutive
+            .is_some_and(|max| errors.consecutive > max)
+        {
+            return Err(format!(
+                "giving up after {} containers in a row couldn't be decoded",
+                errors.consecutive
+            ));
+        }
+        if self.max_total.is_some_and(|max| errors.total() > max) {
+            return Err(format!(
+                "giving up after {} containers couldn't be decoded",
+                errors.total()
+            ));
+        }
+        Ok(())
+    }
+}
+
+// How many skipped messages to log per analysis before suppressing the rest, so
+// a pathological capture doesn't drown out everything else in the log
+const SKIPPED_MESSAGE_LOG_LIMIT: usize = 100;
+
+// How long an analysis file is left unflushed at most, unless configured
+// otherwise
+pub const DEFAULT_FLUSH_MAX_INTERVAL: Duration = Duration::from_secs(1);
+
+// How many unread updates a slow websocket client can fall behind by before it
+// starts missing rows
+pub const ANALYSIS_UPDATE_CHANNEL_CAPACITY: usize = 64;
+
+// Broadcast by every AnalysisWriter so websocket clients can follow an analysis
+// as it's written, rather than repeatedly downloading the report
+#[derive(Debug, Clone)]
+pub enum AnalysisUpdate {
+    Line {
+        name: String,
+        // the line's index in the analysis file, where 0 is the metadata
+        line_num: usize,
+        line: String,
+    },
+    Finished {
+        name: String,
+    },
 }
 
 // We write our analysis results to a file immediately to minimize the amount of
@@ -32,43 +426,405 @@ pub struct AnalysisWriter {
 // (https://docs.mulesoft.com/dataweave/latest/dataweave-formats-ndjson), which
 // lets us simply append new rows to the end without parsing the entire JSON
 // object beforehand.
-impl AnalysisWriter {
-    pub async fn new(file: File, analyzer_config: &AnalyzerConfig) -> Result<Self, std::io::Error> {
-        let harness = Harness::new_with_config(analyzer_config);
+impl AnalysisWriter<File> {
+    // The capture metadata, if any, describes the recording being analyzed
+    pub async fn new(
+        file: File,
+        analyzer_config: &AnalyzerConfig,
+        name: &str,
+        update_sender: broadcast::Sender<AnalysisUpdate>,
+        findings_syslog: Option<FindingSyslog>,
+        capture: Option<CaptureMetadata>,
+    ) -> Result<Self, std::io::Error> {
+        Self::with_writer(
+            file,
+            analyzer_config,
+            name,
+            update_sender,
+            findings_syslog,
+            capture,
+        )
+        .await
+    }
+
+    // Like new, but keeps the analysis in memory rather than writing it to a
+    // file. The report is returned by close.
+    pub async fn new_in_memory(
+        analyzer_config: &AnalyzerConfig,
+        name: &str,
+        update_sender: broadcast::Sender<AnalysisUpdate>,
+        findings_syslog: Option<FindingSyslog>,
+    ) -> Result<Self, std::io::Error> {
+        Self::with_output(
+            AnalysisOutput::Memory(String::new()),
+            analyzer_config,
+            name,
+            update_sender,
+            findings_syslog,
+            ReportOptions::default(),
+        )
+        .await
+    }
+
+    // Like new_in_memory, but for a single analysis of several recordings,
+    // which are listed in the report's metadata. The recordings' containers
+    // are all fed to the same harness, so analyzer state carries over from
+    // one recording to the next.
+    pub async fn new_merged(
+        analyzer_config: &AnalyzerConfig,
+        recordings: &[String],
+        update_sender: broadcast::Sender<AnalysisUpdate>,
+    ) -> Result<Self, std::io::Error> {
+        Self::with_output(
+            AnalysisOutput::Memory(String::new()),
+            analyzer_config,
+            &recordings.join("+"),
+            update_sender,
+            None,
+            ReportOptions {
+                recordings: Some(recordings.to_vec()),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    // Like new_in_memory, but for an analysis of a time window of one
+    // recording, which is only saved once it's complete, so concurrent ones
+    // can't write over each other's report partway through
+    pub async fn new_window(
+        analyzer_config: &AnalyzerConfig,
+        name: &str,
+        update_sender: broadcast::Sender<AnalysisUpdate>,
+        capture: CaptureMetadata,
+    ) -> Result<Self, std::io::Error> {
+        Self::with_output(
+            AnalysisOutput::Memory(String::new()),
+            analyzer_config,
+            name,
+            update_sender,
+            None,
+            ReportOptions {
+                capture: Some(capture),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AnalysisWriter<W> {
+    // Like new, but writes the analysis to the given writer, which into_writer
+    // hands back once it's done
+    pub async fn with_writer(
+        writer: W,
+        analyzer_config: &AnalyzerConfig,
+        name: &str,
+        update_sender: broadcast::Sender<AnalysisUpdate>,
+        findings_syslog: Option<FindingSyslog>,
+        capture: Option<CaptureMetadata>,
+    ) -> Result<Self, std::io::Error> {
+        Self::with_output(
+            AnalysisOutput::Writer(BufWriter::new(writer)),
+            analyzer_config,
+            name,
+            update_sender,
+            findings_syslog,
+            ReportOptions {
+                capture,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    async fn with_output(
+        output: AnalysisOutput<W>,
+        analyzer_config: &AnalyzerConfig,
+        name: &str,
+        update_sender: broadcast::Sender<AnalysisUpdate>,
+        findings_syslog: Option<FindingSyslog>,
+        options: ReportOptions,
+    ) -> Result<Self, std::io::Error> {
+        let mut harness = Harness::new_with_config(analyzer_config);
+        harness.set_sampling(options.sample_every);
+        let mut metadata = harness.get_metadata();
+        metadata.recordings = options.recordings;
+        metadata.capture = options.capture;
 
         let mut result = Self {
-            writer: BufWriter::new(file),
+            output,
             harness,
+            name: name.to_string(),
+            update_sender,
+            lines_written: 0,
+            skipped_messages: LogLimiter::new(SKIPPED_MESSAGE_LOG_LIMIT),
+            findings_syslog,
+            analyzer_names: metadata.analyzers.iter().map(|a| a.name.clone()).collect(),
+            dead_letters: None,
+            recent_findings: None,
+            decode_errors: DecodeErrorCounts::default(),
+            flush: AdaptiveFlush::new(DEFAULT_FLUSH_MAX_INTERVAL, Instant::now()),
+            pending_updates: Vec::new(),
+            sample_every: options.sample_every,
+            containers_seen: 0,
         };
-        let metadata = result.harness.get_metadata();
         result.write(&metadata).await?;
         Ok(result)
     }
 
+    // Also records the messages that fail to decode to the given file, if any
+    pub fn with_dead_letters(mut self, file: Option<File>) -> Self {
+        if let Some(file) = file {
+            self.harness.record_dead_letters();
+            self.dead_letters = Some(DeadLetterWriter {
+                writer: BufWriter::new(file),
+                bytes_written: 0,
+                truncated: false,
+            });
+        }
+        self
+    }
+
+    // Leaves the analysis file unflushed for at most the given interval while
+    // rows are pouring in, or flushes every row if it's zero
+    pub fn with_flush_max_interval(mut self, max_interval: Duration) -> Self {
+        self.flush = AdaptiveFlush::new(max_interval, Instant::now());
+        self
+    }
+
+    // Also records any warnings found in the given buffer of recent findings
+    pub fn with_recent_findings(mut self, recent_findings: RecentFindings) -> Self {
+        self.recent_findings = Some(recent_findings);
+        self
+    }
+
     // Runs the analysis harness on the given container, serializing the results
     // to the analysis file, returning the whether any warnings were detected
     pub async fn analyze(&mut self, container: MessagesContainer) -> Result<bool, std::io::Error> {
+        self.containers_seen += 1;
+        if self.sample_every > 1 && (self.containers_seen - 1) % self.sample_every != 0 {
+            self.harness.skip_qmdl_messages(&container);
+            return Ok(false);
+        }
         let mut warning_detected = false;
-        for row in self.harness.analyze_qmdl_messages(container) {
+        let rows = self.harness.analyze_qmdl_messages(container);
+        if !rows.is_empty() && rows.iter().all(|row| row.skipped_message_reason.is_some()) {
+            self.decode_errors.undecodable += 1;
+            self.decode_errors.consecutive += 1;
+        } else {
+            self.decode_errors.consecutive = 0;
+        }
+        for row in rows {
+            if let Some(reason) = &row.skipped_message_reason {
+                if self.skipped_messages.check("skipped messages") {
+                    warn!("{}: skipped message: {reason}", self.name);
+                }
+            }
             if !row.is_empty() {
-                self.write(&row).await?;
+                self.write_row(&row).await?;
+            }
+            for (event, analyzer) in row.events.iter().zip(&self.analyzer_names) {
+                let Some(event) = event else {
+                    continue;
+                };
+                if let Some(syslog) = &self.findings_syslog {
+                    syslog.emit(&self.name, analyzer, row.packet_timestamp, event);
+                }
+                if let Some(recent_findings) = &self.recent_findings {
+                    recent_findings.push(&self.name, analyzer, row.packet_timestamp, event);
+                }
             }
             warning_detected |= row.contains_warnings();
         }
+        if let Some(dead_letters) = self.dead_letters.as_mut() {
+            // the dead-letter log is only a debugging aid, so it's not worth
+            // failing the analysis over
+            if let Err(err) = dead_letters
+                .write_all(&self.harness.take_dead_letters())
+                .await
+            {
+                warn!(
+                    "{}: failed to write dead letters, giving up on them: {err}",
+                    self.name
+                );
+                self.dead_letters = None;
+            }
+        }
         Ok(warning_detected)
     }
 
+    // Records a container that couldn't be read from the QMDL, as a skipped
+    // row in the report
+    pub async fn skip_unreadable_container(
+        &mut self,
+        err: &std::io::Error,
+    ) -> Result<(), std::io::Error> {
+        self.decode_errors.unreadable += 1;
+        self.decode_errors.consecutive += 1;
+        self.write(&AnalysisRow {
+            packet_timestamp: None,
+            skipped_message_reason: Some(format!("failed to read QMDL container: {err}")),
+            events: Vec::new(),
+            correlation_id: None,
+        })
+        .await
+    }
+
+    // Records that the analysis was stopped before reaching the end of the
+    // recording, as a skipped row in the report
+    pub async fn mark_timed_out(&mut self, max_runtime: Duration) -> Result<(), std::io::Error> {
+        self.write(&AnalysisRow {
+            packet_timestamp: None,
+            skipped_message_reason: Some(format!(
+                "analysis truncated: stopped after exceeding the maximum runtime of {}s",
+                max_runtime.as_secs()
+            )),
+            events: Vec::new(),
+            correlation_id: None,
+        })
+        .await
+    }
+
+    // Records that the recording had nothing for the analyzers to look at, as
+    // a skipped row in the report, so an empty report isn't mistaken for a
+    // clean one
+    pub async fn mark_no_relevant_messages(&mut self) -> Result<(), std::io::Error> {
+        self.write(&AnalysisRow {
+            packet_timestamp: None,
+            skipped_message_reason: Some(
+                "no RRC or NAS messages found: the recording isn't empty, but the modem doesn't seem to have logged any of the messages the analyzers look at, which usually means its diag logging isn't configured for them".to_string(),
+            ),
+            events: Vec::new(),
+            correlation_id: None,
+        })
+        .await
+    }
+
+    pub fn get_decode_errors(&self) -> DecodeErrorCounts {
+        self.decode_errors
+    }
+
+    pub fn get_diag_versions(&self) -> &DiagLayoutVersions {
+        self.harness.get_diag_versions()
+    }
+
+    pub fn get_relevant_message_count(&self) -> usize {
+        self.harness.get_relevant_message_count()
+    }
+
+    pub fn get_skipped_message_count(&self) -> usize {
+        self.skipped_messages.count()
+    }
+
     async fn write<T: Serialize>(&mut self, value: &T) -> Result<(), std::io::Error> {
-        let mut value_str = serde_json::to_string(value).unwrap();
+        let value_str = serde_json::to_string(value).map_err(std::io::Error::other)?;
+        self.write_line(value_str).await
+    }
+
+    // Writes a row of the analysis. If it can't be serialized, the findings
+    // to blame are logged and left out, and the row is marked as skipped with
+    // the reason, so one bad finding costs only itself rather than the whole
+    // analysis.
+    async fn write_row(&mut self, row: &AnalysisRow) -> Result<(), std::io::Error> {
+        match serde_json::to_string(row) {
+            Ok(value_str) => self.write_line(value_str).await,
+            Err(err) => {
+                let placeholder = placeholder_row(&self.name, &self.analyzer_names, row, &err);
+                self.write(&placeholder).await
+            }
+        }
+    }
+
+    async fn write_line(&mut self, mut value_str: String) -> Result<(), std::io::Error> {
         value_str.push('\n');
-        self.writer.write_all(value_str.as_bytes()).await?;
-        self.writer.flush().await?;
+        let flush = match &mut self.output {
+            AnalysisOutput::Writer(writer) => {
+                writer.write_all(value_str.as_bytes()).await?;
+                self.flush.on_write(Instant::now())
+            }
+            AnalysisOutput::Memory(report) => {
+                report.push_str(&value_str);
+                true
+            }
+        };
+        value_str.pop();
+        self.pending_updates.push(AnalysisUpdate::Line {
+            name: self.name.clone(),
+            line_num: self.lines_written,
+            line: value_str,
+        });
+        self.lines_written += 1;
+        if flush {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    // Flushes the analysis file, and lets anyone following along know about
+    // the lines that made it in
+    async fn flush(&mut self) -> Result<(), std::io::Error> {
+        if let AnalysisOutput::Writer(writer) = &mut self.output {
+            writer.flush().await?;
+        }
+        self.flush.flushed(Instant::now());
+        for update in self.pending_updates.drain(..) {
+            // an error here just means nobody's listening
+            let _ = self.update_sender.send(update);
+        }
         Ok(())
     }
 
-    // Flushes any pending I/O to disk before dropping the writer
-    pub async fn close(mut self) -> Result<(), std::io::Error> {
-        self.writer.flush().await?;
+    // Flushes any rows that have been left unflushed for too long, for when
+    // no more might be written for a while
+    pub async fn flush_if_due(&mut self) -> Result<(), std::io::Error> {
+        if self.flush.is_due(Instant::now()) {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    // Flushes any pending I/O to disk before dropping the writer. For an
+    // in-memory analysis, returns the report instead.
+    //
+    // If any containers couldn't be decoded, the report ends with a skipped
+    // row saying how many.
+    pub async fn close(mut self) -> Result<Option<String>, std::io::Error> {
+        self.finish().await?;
+        Ok(match self.output {
+            AnalysisOutput::Writer(_) => None,
+            AnalysisOutput::Memory(report) => Some(report),
+        })
+    }
+
+    // Like close, but hands back the writer the analysis was written to, or
+    // None for an in-memory analysis
+    pub async fn into_writer(mut self) -> Result<Option<W>, std::io::Error> {
+        self.finish().await?;
+        Ok(match self.output {
+            AnalysisOutput::Writer(writer) => Some(writer.into_inner()),
+            AnalysisOutput::Memory(_) => None,
+        })
+    }
+
+    async fn finish(&mut self) -> Result<(), std::io::Error> {
+        let errors = self.decode_errors;
+        if errors.total() > 0 {
+            self.write(&AnalysisRow {
+                packet_timestamp: None,
+                skipped_message_reason: Some(format!(
+                    "{} container(s) couldn't be decoded and {} couldn't be read",
+                    errors.undecodable, errors.unreadable
+                )),
+                events: Vec::new(),
+                correlation_id: None,
+            })
+            .await?;
+        }
+        self.flush().await?;
+        let _ = self.update_sender.send(AnalysisUpdate::Finished {
+            name: self.name.clone(),
+        });
         Ok(())
     }
 }
@@ -77,27 +833,166 @@ impl AnalysisWriter {
 pub struct AnalysisStatus {
     queued: Vec<String>,
     running: Option<String>,
+    // the most recently finished recordings, oldest first, up to
+    // analysis_status_max_finished of them
     finished: Vec<String>,
+    // how many finished recordings were dropped from the start of `finished`
+    // to keep it under analysis_status_max_finished. They're still analyzed,
+    // and their reports are still in the store.
+    finished_trimmed: usize,
+    // recordings that have never been analyzed, or whose analysis file is
+    // missing or empty
+    unanalyzed: Vec<String>,
+    // why the last analysis of these recordings failed
+    failed: HashMap<String, String>,
+    // recordings whose last analysis was stopped for taking longer than
+    // analysis_max_runtime_secs. Their reports only cover what was analyzed
+    // up to then.
+    timed_out: Vec<String>,
+    // recordings whose last analysis found none of the messages the
+    // analyzers look at, despite the recording not being empty
+    no_relevant_messages: Vec<String>,
+    // recordings taken off the queue unanalyzed because it had grown past
+    // analysis_max_queue_depth. They're unanalyzed too, and can be queued
+    // again.
+    skipped: Vec<String>,
+    // reports of analyses that couldn't be written to disk
+    #[serde(skip)]
+    in_memory_reports: HashMap<String, String>,
+    #[serde(skip)]
+    max_finished: Option<usize>,
 }
 
 impl AnalysisStatus {
-    pub fn new(store: &RecordingStore) -> Self {
-        let existing_recordings: Vec<String> = store
-            .manifest
-            .entries
-            .iter()
-            .map(|entry| entry.name.clone())
-            .collect();
-        AnalysisStatus {
-            queued: Vec::new(),
+    // Only recordings with an analysis file are considered finished. The
+    // rest are queued for analysis if `queue_unanalyzed` is set, or marked
+    // unanalyzed otherwise. Only the latest `max_finished` finished
+    // recordings are listed, if that's not 0.
+    pub fn new(store: &RecordingStore, queue_unanalyzed: bool, max_finished: usize) -> Self {
+        let mut queued = Vec::new();
+        let mut finished = Vec::new();
+        let mut unanalyzed = Vec::new();
+        for entry in &store.manifest.entries {
+            if entry.has_valid_analysis(&store.path) {
+                finished.push(entry.name.clone());
+            } else if queue_unanalyzed && !entry.qmdl_pruned {
+                queued.push(entry.name.clone());
+            } else {
+                unanalyzed.push(entry.name.clone());
+            }
+        }
+        let mut status = AnalysisStatus {
+            queued,
             running: None,
-            finished: existing_recordings,
+            finished,
+            finished_trimmed: 0,
+            unanalyzed,
+            failed: HashMap::new(),
+            timed_out: Vec::new(),
+            no_relevant_messages: Vec::new(),
+            skipped: Vec::new(),
+            in_memory_reports: HashMap::new(),
+            max_finished: (max_finished > 0).then_some(max_finished),
+        };
+        status.trim_finished();
+        status
+    }
+
+    // Drops the oldest finished recordings past max_finished
+    fn trim_finished(&mut self) {
+        let Some(max_finished) = self.max_finished else {
+            return;
+        };
+        let excess = self.finished.len().saturating_sub(max_finished);
+        self.finished.drain(..excess);
+        self.finished_trimmed += excess;
+    }
+
+    pub fn has_queued(&self) -> bool {
+        !self.queued.is_empty()
+    }
+
+    pub fn is_running(&self, name: &str) -> bool {
+        self.running.as_deref() == Some(name)
+    }
+
+    pub fn failure(&self, name: &str) -> Option<&str> {
+        self.failed.get(name).map(String::as_str)
+    }
+
+    // Whether the named recording's last analysis was stopped early for
+    // running too long, leaving its report incomplete
+    pub fn is_timed_out(&self, name: &str) -> bool {
+        self.timed_out.iter().any(|n| n == name)
+    }
+
+    pub fn in_memory_report(&self, name: &str) -> Option<&str> {
+        self.in_memory_reports.get(name).map(String::as_str)
+    }
+
+    pub fn mark_finished(&mut self, name: &str) {
+        self.unanalyzed.retain(|n| n != name);
+        // moved to the end if it was already there, as the latest to finish
+        self.finished.retain(|n| n != name);
+        self.finished.push(name.to_string());
+        self.trim_finished();
+    }
+
+    pub fn mark_unanalyzed(&mut self, name: &str) {
+        self.unanalyzed.push(name.to_string());
+    }
+
+    // Forgets the named recording's last analysis, after its report was
+    // deleted
+    fn mark_analysis_deleted(&mut self, name: &str) {
+        self.finished.retain(|n| n != name);
+        self.failed.remove(name);
+        self.timed_out.retain(|n| n != name);
+        self.no_relevant_messages.retain(|n| n != name);
+        self.skipped.retain(|n| n != name);
+        self.in_memory_reports.remove(name);
+        if !self.unanalyzed.iter().any(|n| n == name) {
+            self.mark_unanalyzed(name);
         }
     }
+
+    // Whether the named recording is waiting to be analyzed or being analyzed
+    pub fn is_pending(&self, name: &str) -> bool {
+        self.queued.iter().any(|n| n == name) || self.is_running(name)
+    }
+
+    // Forgets the named recording altogether, after it was deleted
+    pub fn forget(&mut self, name: &str) {
+        self.mark_analysis_deleted(name);
+        self.unanalyzed.retain(|n| n != name);
+    }
+
+    // Takes the oldest recordings off the queue until only max_depth are
+    // left, marking them unanalyzed, and returns them
+    fn skip_oldest_queued(&mut self, max_depth: usize) -> Vec<String> {
+        let excess = self.queued.len().saturating_sub(max_depth);
+        let skipped: Vec<String> = self.queued.drain(..excess).collect();
+        for name in &skipped {
+            self.unanalyzed.push(name.clone());
+            self.skipped.push(name.clone());
+        }
+        skipped
+    }
+
+    // Whether the named recording has a complete analysis report, i.e. it's
+    // finished and not waiting on a (re-)analysis. Once finished recordings
+    // have been trimmed, any recording that isn't listed anywhere is one of
+    // them, so this mustn't be asked about the current recording.
+    pub fn is_analyzed(&self, name: &str) -> bool {
+        let finished = self.finished.iter().any(|n| n == name)
+            || (self.finished_trimmed > 0 && !self.unanalyzed.iter().any(|n| n == name));
+        finished && !self.queued.iter().any(|n| n == name) && !self.is_running(name)
+    }
 }
 
 pub enum AnalysisCtrlMessage {
-    NewFilesQueued,
+    // the ID of the HTTP request that queued them, if any
+    NewFilesQueued(Option<String>),
     RecordingFinished(String),
     Exit,
 }
@@ -106,109 +1001,447 @@ async fn queued_len(analysis_status_lock: Arc<RwLock<AnalysisStatus>>) -> usize
     analysis_status_lock.read().await.queued.len()
 }
 
-async fn dequeue_to_running(analysis_status_lock: Arc<RwLock<AnalysisStatus>>) -> String {
+async fn dequeue_to_running(
+    analysis_status_lock: Arc<RwLock<AnalysisStatus>>,
+    qmdl_store_lock: &RwLock<RecordingStore>,
+    priorities: &AnalysisPriorities,
+) -> Option<String> {
+    // read before taking the status lock, so the two are never held at once
+    let priorities = priorities.priorities(qmdl_store_lock).await;
     let mut analysis_status = analysis_status_lock.write().await;
-    let name = analysis_status.queued.remove(0);
+    let index = next_in_queue(&analysis_status.queued, &priorities)?;
+    let name = analysis_status.queued.remove(index);
     assert!(analysis_status.running.is_none());
     analysis_status.running = Some(name.clone());
-    name
+    // forget about whatever the last analysis left behind
+    analysis_status.failed.remove(&name);
+    analysis_status.timed_out.retain(|n| *n != name);
+    analysis_status.no_relevant_messages.retain(|n| *n != name);
+    analysis_status.in_memory_reports.remove(&name);
+    Some(name)
 }
 
-async fn finish_running_analysis(analysis_status_lock: Arc<RwLock<AnalysisStatus>>) {
+// What a completed analysis left behind
+struct FinishedAnalysis {
+    // the report, if it could only be kept in memory
+    in_memory_report: Option<String>,
+    // whether the analysis was stopped early for running too long
+    timed_out: bool,
+    // whether the recording had data, but none of it was for the analyzers
+    no_relevant_messages: bool,
+}
+
+async fn finish_running_analysis(
+    analysis_status_lock: Arc<RwLock<AnalysisStatus>>,
+    result: &Result<FinishedAnalysis, String>,
+) {
     let mut analysis_status = analysis_status_lock.write().await;
     let finished = analysis_status.running.take().unwrap();
-    analysis_status.finished.push(finished);
+    match result {
+        Ok(analysis) => {
+            if let Some(report) = &analysis.in_memory_report {
+                analysis_status
+                    .in_memory_reports
+                    .insert(finished.clone(), report.clone());
+            }
+            if analysis.timed_out {
+                analysis_status.timed_out.push(finished.clone());
+            }
+            if analysis.no_relevant_messages {
+                analysis_status.no_relevant_messages.push(finished.clone());
+            }
+        }
+        Err(err) => {
+            analysis_status.failed.insert(finished.clone(), err.clone());
+        }
+    }
+    analysis_status.mark_finished(&finished);
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn perform_analysis(
     name: &str,
     qmdl_store_lock: Arc<RwLock<RecordingStore>>,
     analyzer_config: &AnalyzerConfig,
-) -> Result<(), String> {
+    update_sender: broadcast::Sender<AnalysisUpdate>,
+    findings_syslog: Option<FindingSyslog>,
+    recent_findings: RecentFindings,
+    in_memory_fallback: bool,
+    dead_letter_log: bool,
+    decode_error_tolerance: DecodeErrorTolerance,
+    max_runtime: Option<Duration>,
+    flush_max_interval: Duration,
+    sample_every: usize,
+    capture_pressure: &CapturePressure,
+) -> Result<FinishedAnalysis, String> {
     info!("Opening QMDL and analysis file for {name}...");
-    let (analysis_file, qmdl_file) = {
+    let (analysis_file, dead_letter_file, qmdl_file, paired_qmdl_file, capture) = {
         let mut qmdl_store = qmdl_store_lock.write().await;
-        let (entry_index, _) = qmdl_store
+        let (entry_index, entry) = qmdl_store
             .entry_for_name(name)
             .ok_or(format!("failed to find QMDL store entry for {name}"))?;
-        let analysis_file = qmdl_store
-            .clear_and_open_entry_analysis(entry_index)
-            .await
-            .map_err(|e| format!("{e:?}"))?;
+        let capture = entry.capture_metadata();
+        // there'd be nothing to analyze, and we'd lose the analysis that's left
+        if entry.qmdl_pruned {
+            return Err(RecordingStoreError::QmdlPrunedError.to_string());
+        }
+        let analysis_file = match qmdl_store.clear_and_open_entry_analysis(entry_index).await {
+            Ok(file) => Some(file),
+            Err(RecordingStoreError::AnalysisNotWritable(err)) if in_memory_fallback => {
+                warn!(
+                    "analysis directory not writable ({err}), keeping the analysis of {name} in memory"
+                );
+                None
+            }
+            // this one's meant to be read by users, so keep it readable
+            Err(err @ RecordingStoreError::AnalysisNotWritable(_)) => return Err(err.to_string()),
+            Err(err) => return Err(format!("{err:?}")),
+        };
+        // there's nowhere to put dead letters if the analysis itself can't
+        // be written
+        let dead_letter_file = if dead_letter_log && analysis_file.is_some() {
+            match qmdl_store
+                .clear_and_open_entry_dead_letters(entry_index)
+                .await
+            {
+                Ok(file) => Some(file),
+                Err(err) => {
+                    warn!("failed to open dead-letter file for {name}: {err}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
         let qmdl_file = qmdl_store
             .open_entry_qmdl(entry_index)
             .await
             .map_err(|e| format!("{e:?}"))?;
+        let paired_qmdl_file = qmdl_store
+            .open_entry_paired_qmdl(entry_index)
+            .await
+            .map_err(|e| format!("{e:?}"))?;
 
-        (analysis_file, qmdl_file)
+        (
+            analysis_file,
+            dead_letter_file,
+            qmdl_file,
+            paired_qmdl_file,
+            capture,
+        )
     };
 
-    let mut analysis_writer = AnalysisWriter::new(analysis_file, analyzer_config)
-        .await
-        .map_err(|e| format!("{e:?}"))?;
-    let file_size = qmdl_file
-        .metadata()
-        .await
-        .expect("failed to get QMDL file metadata")
-        .len();
-    let mut qmdl_reader = QmdlReader::new(qmdl_file, Some(file_size as usize));
-    let mut qmdl_stream = pin::pin!(
-        qmdl_reader
-            .as_stream()
-            .try_filter(|container| future::ready(container.data_type == DataType::UserSpace))
-    );
+    let output = match analysis_file {
+        Some(file) => AnalysisOutput::Writer(BufWriter::new(file)),
+        None => AnalysisOutput::Memory(String::new()),
+    };
+    let mut analysis_writer = AnalysisWriter::with_output(
+        output,
+        analyzer_config,
+        name,
+        update_sender,
+        findings_syslog,
+        ReportOptions {
+            recordings: None,
+            capture: Some(capture),
+            sample_every,
+        },
+    )
+    .await
+    .map_err(|e| format!("{e:?}"))?
+    .with_dead_letters(dead_letter_file)
+    .with_recent_findings(recent_findings)
+    .with_flush_max_interval(flush_max_interval);
+    // returns the file's size along with its reader
+    let open_reader = |file: File| async move {
+        let file_size = file
+            .metadata()
+            .await
+            .expect("failed to get QMDL file metadata")
+            .len();
+        let reader = QmdlReader::new_maybe_compressed(file, Some(file_size as usize))
+            .await
+            .map_err(|e| format!("{e:?}"))?;
+        Ok::<_, String>((reader, file_size))
+    };
+    let paired_reader = match paired_qmdl_file {
+        Some(file) => {
+            info!("{name} was captured in two streams, analyzing them together");
+            Some(open_reader(file).await?.0)
+        }
+        None => None,
+    };
+    let (primary_reader, file_size) = open_reader(qmdl_file).await?;
+    let mut qmdl_reader = InterleavedQmdlReader::new(primary_reader, paired_reader);
 
-    info!("Starting analysis for {name}...");
-    while let Some(container) = qmdl_stream
-        .try_next()
-        .await
-        .expect("failed getting QMDL container")
-    {
+    if sample_every > 1 {
+        info!("Starting analysis for {name}, sampling one in every {sample_every} containers...");
+    } else {
+        info!("Starting analysis for {name}...");
+    }
+    let mut deadline = max_runtime.map(|max_runtime| Instant::now() + max_runtime);
+    let mut timed_out = false;
+    loop {
+        // the recording comes first, and time spent waiting on it isn't the
+        // analysis taking too long
+        let paused = capture_pressure.wait_for_capture().await;
+        deadline = deadline.map(|deadline| deadline + paused);
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            timed_out = true;
+            break;
+        }
+        let container = match qmdl_reader.get_next_messages_container().await {
+            Ok(Some(container)) => container,
+            Ok(None) => break,
+            Err(err) if decode_error_tolerance.skips_unreadable() => {
+                warn!("{name}: skipping unreadable QMDL container: {err}");
+                analysis_writer
+                    .skip_unreadable_container(&err)
+                    .await
+                    .map_err(|e| format!("{e:?}"))?;
+                decode_error_tolerance.check(&analysis_writer.get_decode_errors())?;
+                continue;
+            }
+            Err(err) => return Err(format!("failed getting QMDL container: {err:?}")),
+        };
+        if container.data_type != DataType::UserSpace {
+            continue;
+        }
         let _ = analysis_writer
             .analyze(container)
             .await
             .map_err(|e| format!("{e:?}"))?;
+        decode_error_tolerance.check(&analysis_writer.get_decode_errors())?;
     }
 
-    analysis_writer
+    // a recording that's still being written to can be analyzed before
+    // anything's made it into it
+    let no_relevant_messages = file_size > 0 && analysis_writer.get_relevant_message_count() == 0;
+    if no_relevant_messages {
+        warn!("{name}: no RRC or NAS messages found, check the modem's diag configuration");
+        analysis_writer
+            .mark_no_relevant_messages()
+            .await
+            .map_err(|e| format!("{e:?}"))?;
+    } else {
+        info!(
+            "{name}: analyzed {} relevant messages",
+            analysis_writer.get_relevant_message_count()
+        );
+    }
+    let skipped = analysis_writer.get_skipped_message_count();
+    if skipped > 0 {
+        info!("{name}: skipped {skipped} messages during analysis");
+    }
+    let decode_errors = analysis_writer.get_decode_errors();
+    if decode_errors.total() > 0 {
+        info!(
+            "{name}: {} containers couldn't be decoded and {} couldn't be read",
+            decode_errors.undecodable, decode_errors.unreadable
+        );
+    }
+    if let (true, Some(max_runtime)) = (timed_out, max_runtime) {
+        warn!(
+            "{name}: analysis took longer than {}s, stopping it early",
+            max_runtime.as_secs()
+        );
+        analysis_writer
+            .mark_timed_out(max_runtime)
+            .await
+            .map_err(|e| format!("{e:?}"))?;
+    }
+    let diag_versions = analysis_writer.get_diag_versions().clone();
+    let in_memory_report = analysis_writer
         .close()
         .await
         .map_err(|e| format!("{e:?}"))?;
+    // the manifest lives in the same directory, so there's no point trying to
+    // update it if the analysis couldn't be written there
+    if in_memory_report.is_some() {
+        info!("Analysis for {name} complete, results are only kept in memory");
+        return Ok(FinishedAnalysis {
+            in_memory_report,
+            timed_out,
+            no_relevant_messages,
+        });
+    }
+    {
+        let mut qmdl_store = qmdl_store_lock.write().await;
+        if let Some((entry_index, _)) = qmdl_store.entry_for_name(name) {
+            qmdl_store
+                .update_entry_diag_versions(entry_index, &diag_versions)
+                .await
+                .map_err(|e| format!("{e:?}"))?;
+        }
+    }
     info!("Analysis for {name} complete!");
 
-    Ok(())
+    Ok(FinishedAnalysis {
+        in_memory_report: None,
+        timed_out,
+        no_relevant_messages,
+    })
+}
+
+async fn summarize_entry(
+    qmdl_store_lock: &RwLock<RecordingStore>,
+    name: &str,
+) -> Option<AnalysisSummary> {
+    let analysis_path = {
+        let qmdl_store = qmdl_store_lock.read().await;
+        let (_, entry) = qmdl_store.entry_for_name(name)?;
+        entry.get_analysis_filepath(&qmdl_store.path)
+    };
+    match summarize_analysis_path(&analysis_path).await {
+        Ok(summary) => summary,
+        Err(err) => {
+            warn!("failed to summarize analysis for {name}: {err}");
+            None
+        }
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn run_analysis_thread(
     task_tracker: &TaskTracker,
     mut analysis_rx: Receiver<AnalysisCtrlMessage>,
     qmdl_store_lock: Arc<RwLock<RecordingStore>>,
     analysis_status_lock: Arc<RwLock<AnalysisStatus>>,
     analyzer_config: AnalyzerConfig,
+    update_sender: broadcast::Sender<AnalysisUpdate>,
+    post_analysis_hook: Option<PostAnalysisHook>,
+    notification_queue: Option<NotificationQueue>,
+    findings_syslog: Option<FindingSyslog>,
+    recent_findings: RecentFindings,
+    in_memory_fallback: bool,
+    dead_letter_log: bool,
+    decode_error_tolerance: DecodeErrorTolerance,
+    max_runtime: Option<Duration>,
+    start_delay: Duration,
+    flush_max_interval: Duration,
+    sample_every: usize,
+    queue_guard: QueueGuard,
+    priorities: AnalysisPriorities,
+    capture_pressure: CapturePressure,
+    runtime: Option<Handle>,
 ) {
-    task_tracker.spawn(async move {
+    let analysis = async move {
+        let mut settle_until = None;
         loop {
             match analysis_rx.recv().await {
-                Some(AnalysisCtrlMessage::NewFilesQueued) => {
-                    let count = queued_len(analysis_status_lock.clone()).await;
-                    for _ in 0..count {
-                        let name = dequeue_to_running(analysis_status_lock.clone()).await;
-                        if let Err(err) =
-                            perform_analysis(&name, qmdl_store_lock.clone(), &analyzer_config).await
-                        {
-                            error!("failed to analyze {name}: {err}");
-                        }
-                        finish_running_analysis(analysis_status_lock.clone()).await;
+                Some(AnalysisCtrlMessage::NewFilesQueued(request_id)) => {
+                    if !wait_to_settle(
+                        &mut settle_until,
+                        &mut analysis_rx,
+                        &analysis_status_lock,
+                        start_delay,
+                    )
+                    .await
+                    {
+                        return;
                     }
+                    // so the analysis can be traced back to whoever queued it
+                    with_request_id(request_id, async {
+                        let count = queued_len(analysis_status_lock.clone()).await;
+                        info!("analyzing {count} queued recording(s)");
+                        for _ in 0..count {
+                            let sample_every =
+                                queue_guard.apply(&analysis_status_lock, sample_every).await;
+                            // the guard may have skipped the rest
+                            let Some(name) = dequeue_to_running(
+                                analysis_status_lock.clone(),
+                                &qmdl_store_lock,
+                                &priorities,
+                            )
+                            .await
+                            else {
+                                break;
+                            };
+                            with_recording(name.clone(), async {
+                                let result = perform_analysis(
+                                    &name,
+                                    qmdl_store_lock.clone(),
+                                    &analyzer_config,
+                                    update_sender.clone(),
+                                    findings_syslog.clone(),
+                                    recent_findings.clone(),
+                                    in_memory_fallback,
+                                    dead_letter_log,
+                                    decode_error_tolerance,
+                                    max_runtime,
+                                    flush_max_interval,
+                                    sample_every,
+                                    &capture_pressure,
+                                )
+                                .await;
+                                if let Err(err) = &result {
+                                    error!("failed to analyze {name}: {err}");
+                                }
+                                finish_running_analysis(analysis_status_lock.clone(), &result)
+                                    .await;
+                                if let Some(hook) = &post_analysis_hook {
+                                    let summary = match &result {
+                                        Ok(FinishedAnalysis {
+                                            in_memory_report: Some(report),
+                                            ..
+                                        }) => summarize_analysis_file(report.as_bytes())
+                                            .await
+                                            .ok()
+                                            .flatten(),
+                                        Ok(_) => summarize_entry(&qmdl_store_lock, &name).await,
+                                        Err(_) => None,
+                                    };
+                                    hook.run(&name, summary.as_ref(), notification_queue.as_ref())
+                                        .await;
+                                }
+                            })
+                            .await;
+                        }
+                    })
+                    .await;
                 }
                 Some(AnalysisCtrlMessage::RecordingFinished(name)) => {
-                    let mut status = analysis_status_lock.write().await;
-                    status.finished.push(name);
+                    analysis_status_lock.write().await.mark_finished(&name);
+                    if !start_delay.is_zero() {
+                        settle_until = Some(Instant::now() + start_delay);
+                    }
                 }
                 Some(AnalysisCtrlMessage::Exit) | None => return,
             }
         }
-    });
+    };
+    match runtime {
+        Some(runtime) => task_tracker.spawn_on(analysis, &runtime),
+        None => task_tracker.spawn(analysis),
+    };
+}
+
+// Holds off on analyzing until `settle_until`, so a recording that just
+// finished (and the one that's likely starting after it) get some time to
+// themselves. Other messages are still handled in the meantime, and another
+// recording finishing starts the wait over. Returns false if the analysis
+// thread should exit.
+async fn wait_to_settle(
+    settle_until: &mut Option<Instant>,
+    analysis_rx: &mut Receiver<AnalysisCtrlMessage>,
+    analysis_status_lock: &Arc<RwLock<AnalysisStatus>>,
+    start_delay: Duration,
+) -> bool {
+    while let Some(until) = *settle_until {
+        tokio::select! {
+            _ = sleep_until(until) => *settle_until = None,
+            msg = analysis_rx.recv() => match msg {
+                Some(AnalysisCtrlMessage::RecordingFinished(name)) => {
+                    analysis_status_lock.write().await.mark_finished(&name);
+                    *settle_until = Some(Instant::now() + start_delay);
+                }
+                // the whole queue gets analyzed once the wait is over anyway
+                Some(AnalysisCtrlMessage::NewFilesQueued(_)) => {}
+                Some(AnalysisCtrlMessage::Exit) | None => return false,
+            },
+        }
+    }
+    true
 }
 
 pub async fn get_analysis_status(
@@ -217,16 +1450,34 @@ pub async fn get_analysis_status(
     Ok(Json(state.analysis_status_lock.read().await.clone()))
 }
 
-fn queue_qmdl(name: &str, analysis_status: &mut RwLockWriteGuard<AnalysisStatus>) -> bool {
+pub fn queue_qmdl(name: &str, analysis_status: &mut RwLockWriteGuard<AnalysisStatus>) -> bool {
     if analysis_status.queued.iter().any(|n| n == name)
         || analysis_status.running.iter().any(|n| n == name)
     {
         return false;
     }
+    analysis_status.unanalyzed.retain(|n| n != name);
+    // it's getting another chance, so it's no longer considered failed, or
+    // skipped
+    analysis_status.failed.remove(name);
+    analysis_status.skipped.retain(|n| n != name);
     analysis_status.queued.push(name.to_string());
     true
 }
 
+async fn notify_queued(state: &ServerState) -> Result<(), (StatusCode, String)> {
+    state
+        .analysis_sender
+        .send(AnalysisCtrlMessage::NewFilesQueued(current_request_id()))
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("failed to queue new analysis files: {e:?}"),
+            )
+        })
+}
+
 pub async fn start_analysis(
     State(state): State<Arc<ServerState>>,
     Path(qmdl_name): Path<String>,
@@ -234,32 +1485,626 @@ pub async fn start_analysis(
     let mut analysis_status = state.analysis_status_lock.write().await;
     let store = state.qmdl_store_lock.read().await;
     let queued = if qmdl_name.is_empty() {
-        let mut entry_names: Vec<&str> = store
+        let entry_names: Vec<&str> = store
             .manifest
             .entries
             .iter()
-            .map(|e| e.name.as_str())
+            .enumerate()
+            .filter(|(entry_index, e)| store.current_entry != Some(*entry_index) && !e.qmdl_pruned)
+            .map(|(_, e)| e.name.as_str())
             .collect();
-        if let Some(current_entry) = store.current_entry {
-            entry_names.remove(current_entry);
-        }
         entry_names
             .iter()
             .any(|name| queue_qmdl(name, &mut analysis_status))
     } else {
+        if store
+            .entry_for_name(&qmdl_name)
+            .is_some_and(|(_, entry)| entry.qmdl_pruned)
+        {
+            return Err((
+                StatusCode::CONFLICT,
+                RecordingStoreError::QmdlPrunedError.to_string(),
+            ));
+        }
         queue_qmdl(&qmdl_name, &mut analysis_status)
     };
     if queued {
-        state
-            .analysis_sender
-            .send(AnalysisCtrlMessage::NewFilesQueued)
-            .await
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("failed to queue new analysis files: {e:?}"),
-                )
-            })?;
+        notify_queued(&state).await?;
     }
     Ok((StatusCode::ACCEPTED, Json(analysis_status.clone())))
 }
+
+// Deletes a recording's analysis report, leaving its QMDL alone, and marks it
+// unanalyzed. Analyzing it again (see start_analysis) writes a new report.
+pub async fn delete_analysis(
+    State(state): State<Arc<ServerState>>,
+    Path(qmdl_name): Path<String>,
+) -> Result<Json<AnalysisStatus>, (StatusCode, String)> {
+    let mut analysis_status = state.analysis_status_lock.write().await;
+    let mut store = state.qmdl_store_lock.write().await;
+    let (entry_index, entry) = store.entry_for_name(&qmdl_name).ok_or((
+        StatusCode::NOT_FOUND,
+        format!("Couldn't find QMDL entry with name \"{qmdl_name}\""),
+    ))?;
+    // the analysis is all that's left of a pruned recording
+    if entry.qmdl_pruned {
+        return Err((
+            StatusCode::CONFLICT,
+            RecordingStoreError::QmdlPrunedError.to_string(),
+        ));
+    }
+    // duplicates of a recording share its report, so they lose it too
+    let sharing: Vec<String> = store
+        .manifest
+        .entries
+        .iter()
+        .filter(|other| other.file_stem() == entry.file_stem())
+        .map(|other| other.name.clone())
+        .collect();
+    let busy = store.current_entry == Some(entry_index)
+        || sharing
+            .iter()
+            .any(|name| analysis_status.is_running(name) || analysis_status.queued.contains(name));
+    if busy {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("\"{qmdl_name}\" is still being recorded or analyzed"),
+        ));
+    }
+    store
+        .delete_entry_analysis(entry_index)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    for name in &sharing {
+        analysis_status.mark_analysis_deleted(name);
+    }
+    Ok(Json(analysis_status.clone()))
+}
+
+// Queues every recording whose last analysis failed to be analyzed again
+pub async fn retry_failed_analyses(
+    State(state): State<Arc<ServerState>>,
+) -> Result<(StatusCode, Json<AnalysisStatus>), (StatusCode, String)> {
+    let mut analysis_status = state.analysis_status_lock.write().await;
+    let mut failed: Vec<String> = analysis_status.failed.keys().cloned().collect();
+    failed.sort();
+    let mut queued = false;
+    for name in &failed {
+        queued |= queue_qmdl(name, &mut analysis_status);
+    }
+    if queued {
+        notify_queued(&state).await?;
+    }
+    Ok((StatusCode::ACCEPTED, Json(analysis_status.clone())))
+}
+
+// Queues the named recording to be analyzed again if its last analysis failed
+pub async fn retry_failed_analysis(
+    State(state): State<Arc<ServerState>>,
+    Path(qmdl_name): Path<String>,
+) -> Result<(StatusCode, Json<AnalysisStatus>), (StatusCode, String)> {
+    let mut analysis_status = state.analysis_status_lock.write().await;
+    if analysis_status.failure(&qmdl_name).is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("The analysis of \"{qmdl_name}\" hasn't failed"),
+        ));
+    }
+    if queue_qmdl(&qmdl_name, &mut analysis_status) {
+        notify_queued(&state).await?;
+    }
+    Ok((StatusCode::ACCEPTED, Json(analysis_status.clone())))
+}
+
+#[derive(Deserialize)]
+pub struct AnalysisWindowParams {
+    start_ts: DateTime<FixedOffset>,
+    end_ts: DateTime<FixedOffset>,
+}
+
+// Analyzes only the containers of a recording with messages between start_ts
+// and end_ts, and serves the result. It's saved to a report of its own, so
+// the full analysis is left alone, and replaces the last window's report only
+// once it's complete. Analyzers start out knowing nothing, so findings that
+// depend on something from before the window (e.g. the serving cell's SIB1)
+// can be missed near its start.
+pub async fn analyze_window(
+    State(state): State<Arc<ServerState>>,
+    Path(qmdl_name): Path<String>,
+    Query(params): Query<AnalysisWindowParams>,
+    format: WireFormat,
+) -> Result<Response, (StatusCode, String)> {
+    let AnalysisWindowParams { start_ts, end_ts } = params;
+    if end_ts < start_ts {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "end_ts can't be before start_ts".to_string(),
+        ));
+    }
+    let internal_error =
+        |e: &dyn std::fmt::Debug| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}"));
+    let (qmdl_file, paired_qmdl_file, capture) = {
+        let qmdl_store = state.qmdl_store_lock.read().await;
+        let (entry_index, entry) = qmdl_store.entry_for_name(&qmdl_name).ok_or((
+            StatusCode::NOT_FOUND,
+            format!("Couldn't find QMDL entry with name \"{qmdl_name}\""),
+        ))?;
+        if entry.qmdl_pruned {
+            return Err((
+                StatusCode::CONFLICT,
+                RecordingStoreError::QmdlPrunedError.to_string(),
+            ));
+        }
+        let capture = entry.capture_metadata();
+        let qmdl_file = qmdl_store
+            .open_entry_qmdl(entry_index)
+            .await
+            .map_err(|e| internal_error(&e))?;
+        let paired_qmdl_file = qmdl_store
+            .open_entry_paired_qmdl(entry_index)
+            .await
+            .map_err(|e| internal_error(&e))?;
+        (qmdl_file, paired_qmdl_file, capture)
+    };
+
+    info!("analyzing {qmdl_name} between {start_ts} and {end_ts}");
+    // nobody follows these over the websocket, and their findings already
+    // went to syslog with the full analysis
+    let (update_sender, _) = broadcast::channel(1);
+    let mut analysis_writer =
+        AnalysisWriter::new_window(&state.config.analyzers, &qmdl_name, update_sender, capture)
+            .await
+            .map_err(|e| internal_error(&e))?;
+    let open_reader = |file: File| async move {
+        let file_size = file.metadata().await?.len();
+        QmdlReader::new_maybe_compressed(file, Some(file_size as usize)).await
+    };
+    let paired_reader = match paired_qmdl_file {
+        Some(file) => Some(open_reader(file).await.map_err(|e| internal_error(&e))?),
+        None => None,
+    };
+    let mut qmdl_reader = InterleavedQmdlReader::new(
+        open_reader(qmdl_file)
+            .await
+            .map_err(|e| internal_error(&e))?,
+        paired_reader,
+    );
+    // containers without any timestamps are kept, since they may still
+    // carry something the harness needs, like the diag layout versions
+    let mut qmdl_stream = pin::pin!(qmdl_reader.as_stream().try_filter(|container| {
+        let in_window = container
+            .timestamp_range()
+            .is_none_or(|(first, last)| last >= start_ts && first <= end_ts);
+        future::ready(container.data_type == DataType::UserSpace && in_window)
+    }));
+    while let Some(container) = qmdl_stream
+        .try_next()
+        .await
+        .map_err(|e| internal_error(&e))?
+    {
+        analysis_writer
+            .analyze(container)
+            .await
+            .map_err(|e| internal_error(&e))?;
+    }
+    let report = analysis_writer
+        .close()
+        .await
+        .map_err(|e| internal_error(&e))?
+        .unwrap_or_default();
+
+    {
+        let qmdl_store = state.qmdl_store_lock.read().await;
+        // the recording may have been deleted in the meantime, in which case
+        // there's nothing to keep the report next to
+        if let Some((entry_index, _)) = qmdl_store.entry_for_name(&qmdl_name) {
+            qmdl_store
+                .replace_entry_window_analysis(entry_index, &report)
+                .await
+                .map_err(|e| internal_error(&e))?;
+        }
+    }
+    report_response(report, format)
+}
+
+// Serves a report that's only been kept in memory
+fn report_response(report: String, format: WireFormat) -> Result<Response, (StatusCode, String)> {
+    let content_type = match format {
+        WireFormat::Json => "application/x-ndjson",
+        WireFormat::MessagePack => MESSAGEPACK_CONTENT_TYPE,
+    };
+    let headers = [(CONTENT_TYPE, content_type), (VARY, "Accept")];
+    let body = match format {
+        WireFormat::Json => report.into_bytes(),
+        WireFormat::MessagePack => {
+            ndjson_to_messagepack(&report).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        }
+    };
+    Ok((headers, body).into_response())
+}
+
+// Serves the report of the last time window analyzed with analyze_window
+pub async fn get_window_analysis(
+    State(state): State<Arc<ServerState>>,
+    Path(qmdl_name): Path<String>,
+    format: WireFormat,
+) -> Result<Response, (StatusCode, String)> {
+    let qmdl_store = state.qmdl_store_lock.read().await;
+    let (entry_index, _) = qmdl_store.entry_for_name(&qmdl_name).ok_or((
+        StatusCode::NOT_FOUND,
+        format!("Couldn't find QMDL entry with name \"{qmdl_name}\""),
+    ))?;
+    let analysis_file = match qmdl_store.open_entry_window_analysis(entry_index).await {
+        Ok(file) => file,
+        Err(RecordingStoreError::ReadFileError(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("No time window of \"{qmdl_name}\" has been analyzed"),
+            ));
+        }
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}"))),
+    };
+    let content_type = match format {
+        WireFormat::Json => "application/x-ndjson",
+        WireFormat::MessagePack => MESSAGEPACK_CONTENT_TYPE,
+    };
+    let headers = [(CONTENT_TYPE, content_type), (VARY, "Accept")];
+    let body = match format {
+        WireFormat::Json => Body::from_stream(ReaderStream::new(analysis_file)),
+        WireFormat::MessagePack => ndjson_to_messagepack_body(analysis_file),
+    };
+    Ok((headers, body).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct MergedAnalysisRequest {
+    // the recordings to analyze, in order
+    recordings: Vec<String>,
+}
+
+// Analyzes several recordings as if they were one, and serves the result.
+// Recordings are split up by time, so something that happens around the end
+// of one (e.g. a cell's SIB1 right before the rotation, and what it does
+// right after) is otherwise analyzed in two halves that can each look
+// harmless. The report is only kept for the response.
+pub async fn analyze_merged(
+    State(state): State<Arc<ServerState>>,
+    format: WireFormat,
+    Json(request): Json<MergedAnalysisRequest>,
+) -> Result<Response, (StatusCode, String)> {
+    let recordings = request.recordings;
+    if recordings.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "at least one recording is needed".to_string(),
+        ));
+    }
+    if let Some(name) = recordings
+        .iter()
+        .enumerate()
+        .find_map(|(i, name)| recordings[..i].contains(name).then_some(name))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("recording \"{name}\" is listed more than once"),
+        ));
+    }
+    let internal_error =
+        |e: &dyn std::fmt::Debug| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}"));
+    let mut qmdl_files = Vec::with_capacity(recordings.len());
+    {
+        let qmdl_store = state.qmdl_store_lock.read().await;
+        for name in &recordings {
+            let (entry_index, entry) = qmdl_store.entry_for_name(name).ok_or((
+                StatusCode::NOT_FOUND,
+                format!("Couldn't find QMDL entry with name \"{name}\""),
+            ))?;
+            if entry.qmdl_pruned {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!("{name}: {}", RecordingStoreError::QmdlPrunedError),
+                ));
+            }
+            let qmdl_file = qmdl_store
+                .open_entry_qmdl(entry_index)
+                .await
+                .map_err(|e| internal_error(&e))?;
+            let paired_qmdl_file = qmdl_store
+                .open_entry_paired_qmdl(entry_index)
+                .await
+                .map_err(|e| internal_error(&e))?;
+            qmdl_files.push((qmdl_file, paired_qmdl_file));
+        }
+    }
+
+    info!("analyzing {} as one recording", recordings.join(", "));
+    // nobody follows these over the websocket, and their findings already
+    // went to syslog with each recording's own analysis
+    let (update_sender, _) = broadcast::channel(1);
+    let mut analysis_writer =
+        AnalysisWriter::new_merged(&state.config.analyzers, &recordings, update_sender)
+            .await
+            .map_err(|e| internal_error(&e))?;
+    let open_reader = |file: File| async move {
+        let file_size = file.metadata().await?.len();
+        QmdlReader::new_maybe_compressed(file, Some(file_size as usize)).await
+    };
+    for (qmdl_file, paired_qmdl_file) in qmdl_files {
+        // a recording captured in two streams is analyzed as one, like it is
+        // on its own
+        let paired_reader = match paired_qmdl_file {
+            Some(file) => Some(open_reader(file).await.map_err(|e| internal_error(&e))?),
+            None => None,
+        };
+        let mut qmdl_reader = InterleavedQmdlReader::new(
+            open_reader(qmdl_file)
+                .await
+                .map_err(|e| internal_error(&e))?,
+            paired_reader,
+        );
+        while let Some(container) = qmdl_reader
+            .get_next_messages_container()
+            .await
+            .map_err(|e| internal_error(&e))?
+        {
+            if container.data_type != DataType::UserSpace {
+                continue;
+            }
+            analysis_writer
+                .analyze(container)
+                .await
+                .map_err(|e| internal_error(&e))?;
+        }
+    }
+    let report = analysis_writer
+        .close()
+        .await
+        .map_err(|e| internal_error(&e))?
+        .unwrap_or_default();
+    report_response(report, format)
+}
+
+#[derive(Deserialize)]
+pub struct AnalysisWsParams {
+    // whether to send the rows already in the analysis file before following
+    // new ones
+    #[serde(default)]
+    replay: bool,
+}
+
+// Streams an analysis file's lines over a websocket as they're written, closing
+// the socket once the analysis (or live recording) finishes
+pub async fn get_analysis_ws(
+    State(state): State<Arc<ServerState>>,
+    Path(qmdl_name): Path<String>,
+    Query(params): Query<AnalysisWsParams>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, (StatusCode, String)> {
+    let analysis_path = {
+        let qmdl_store = state.qmdl_store_lock.read().await;
+        let (_, entry) = qmdl_store.entry_for_name(&qmdl_name).ok_or((
+            StatusCode::NOT_FOUND,
+            format!("Couldn't find QMDL entry with name \"{qmdl_name}\""),
+        ))?;
+        entry.get_analysis_filepath(&qmdl_store.path)
+    };
+    // subscribe before replaying anything, so that no rows written in the
+    // meantime get lost
+    let updates = state.analysis_update_sender.subscribe();
+    let is_active = state
+        .analysis_status_lock
+        .read()
+        .await
+        .is_running(&qmdl_name)
+        || state
+            .qmdl_store_lock
+            .read()
+            .await
+            .get_current_entry()
+            .is_some_and(|(_, entry)| entry.name == qmdl_name);
+    Ok(ws.on_upgrade(move |mut socket| async move {
+        let lines_sent = if params.replay {
+            match replay_analysis(&mut socket, &analysis_path).await {
+                Ok(lines_sent) => lines_sent,
+                Err(err) => {
+                    warn!("failed to replay analysis for {qmdl_name}: {err}");
+                    return;
+                }
+            }
+        } else {
+            0
+        };
+        if is_active {
+            follow_analysis(&mut socket, &qmdl_name, lines_sent, updates).await;
+        }
+        let _ = socket.close().await;
+    }))
+}
+
+async fn replay_analysis(
+    socket: &mut WebSocket,
+    analysis_path: &std::path::Path,
+) -> Result<usize, anyhow::Error> {
+    let file = QmdlInput::detect(File::open(analysis_path).await?).await?;
+    let mut lines = BufReader::new(file).lines();
+    let mut lines_sent = 0;
+    while let Some(line) = lines.next_line().await? {
+        socket.send(Message::Text(line.into())).await?;
+        lines_sent += 1;
+    }
+    Ok(lines_sent)
+}
+
+async fn follow_analysis(
+    socket: &mut WebSocket,
+    name: &str,
+    lines_sent: usize,
+    mut updates: broadcast::Receiver<AnalysisUpdate>,
+) {
+    loop {
+        tokio::select! {
+            update = updates.recv() => match update {
+                Ok(AnalysisUpdate::Line { name: line_name, line_num, line }) if line_name == name => {
+                    // skip anything we already sent while replaying
+                    if line_num < lines_sent {
+                        continue;
+                    }
+                    if socket.send(Message::Text(line.into())).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(AnalysisUpdate::Finished { name: finished_name }) if finished_name == name => return,
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("analysis websocket for {name} fell behind, skipped {skipped} updates");
+                }
+                Err(RecvError::Closed) => return,
+            },
+            msg = socket.recv() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => continue,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_analyze_into_memory() {
+        let (update_sender, mut update_rx) = broadcast::channel(8);
+        let writer = AnalysisWriter::with_writer(
+            Vec::new(),
+            &AnalyzerConfig::default(),
+            "test",
+            update_sender,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        let report = writer.into_writer().await.unwrap().unwrap();
+
+        // just the metadata, which lists the analyzers
+        let report = String::from_utf8(report).unwrap();
+        assert_eq!(report.lines().count(), 1);
+        let metadata: serde_json::Value = serde_json::from_str(report.trim_end()).unwrap();
+        assert!(metadata["analyzers"].is_array());
+
+        assert!(matches!(
+            update_rx.recv().await,
+            Ok(AnalysisUpdate::Line { line_num: 0, .. })
+        ));
+        assert!(matches!(
+            update_rx.recv().await,
+            Ok(AnalysisUpdate::Finished { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_queue_guard_skips_oldest() {
+        let status = AnalysisStatus {
+            queued: ["a", "b", "c", "d"].map(String::from).to_vec(),
+            running: None,
+            finished: Vec::new(),
+            finished_trimmed: 0,
+            unanalyzed: Vec::new(),
+            failed: HashMap::new(),
+            timed_out: Vec::new(),
+            no_relevant_messages: Vec::new(),
+            skipped: Vec::new(),
+            in_memory_reports: HashMap::new(),
+            max_finished: None,
+        };
+        let status_lock = RwLock::new(status);
+        let guard = QueueGuard {
+            max_depth: Some(2),
+            overflow: QueueOverflow::SkipOldest,
+            sample_every: 10,
+        };
+        assert_eq!(guard.apply(&status_lock, 0).await, 0);
+        let status = status_lock.read().await;
+        assert_eq!(status.queued, ["c", "d"]);
+        assert_eq!(status.skipped, ["a", "b"]);
+        assert_eq!(status.unanalyzed, ["a", "b"]);
+        drop(status);
+
+        let sampling = QueueGuard {
+            overflow: QueueOverflow::Sample,
+            max_depth: Some(1),
+            ..guard
+        };
+        assert_eq!(sampling.apply(&status_lock, 0).await, 10);
+        assert_eq!(status_lock.read().await.queued.len(), 2);
+    }
+
+    #[test]
+    fn test_analysis_priorities() {
+        let priorities = AnalysisPriorities {
+            rules: vec![
+                AnalysisPriorityRule {
+                    notes_contains: Some("site A".to_string()),
+                    priority: 10,
+                    ..AnalysisPriorityRule::default()
+                },
+                AnalysisPriorityRule {
+                    imported: Some(true),
+                    priority: -1,
+                    ..AnalysisPriorityRule::default()
+                },
+            ],
+        };
+        let mut entry = ManifestEntry::new();
+        assert_eq!(priorities.priority(&entry), 0);
+        entry.imported = true;
+        assert_eq!(priorities.priority(&entry), -1);
+        // the highest priority rule that matches wins
+        entry.notes = Some("Captured at Site A".to_string());
+        assert_eq!(priorities.priority(&entry), 10);
+
+        let queued = ["a", "b", "c", "d"].map(String::from);
+        let by_name = |pairs: &[(&str, i32)]| -> HashMap<String, i32> {
+            pairs
+                .iter()
+                .map(|(name, priority)| (name.to_string(), *priority))
+                .collect()
+        };
+        assert_eq!(next_in_queue(&queued, &HashMap::new()), Some(0));
+        assert_eq!(
+            next_in_queue(&queued, &by_name(&[("a", -1), ("c", 5), ("d", 5)])),
+            Some(2)
+        );
+        assert_eq!(next_in_queue(&queued, &by_name(&[("a", -1)])), Some(1));
+        assert_eq!(next_in_queue(&[], &HashMap::new()), None);
+    }
+
+    #[test]
+    fn test_trim_finished() {
+        let mut status = AnalysisStatus {
+            queued: Vec::new(),
+            running: None,
+            finished: Vec::new(),
+            finished_trimmed: 0,
+            unanalyzed: vec!["d".to_string()],
+            failed: HashMap::new(),
+            timed_out: Vec::new(),
+            no_relevant_messages: Vec::new(),
+            skipped: Vec::new(),
+            in_memory_reports: HashMap::new(),
+            max_finished: Some(2),
+        };
+        for name in ["a", "b", "c", "b"] {
+            status.mark_finished(name);
+        }
+        assert_eq!(status.finished, ["c", "b"]);
+        assert_eq!(status.finished_trimmed, 1);
+        // trimmed recordings are still analyzed
+        assert!(status.is_analyzed("a"));
+        assert!(!status.is_analyzed("d"));
+
+        status.queued.push("a".to_string());
+        assert!(!status.is_analyzed("a"));
+    }
+}
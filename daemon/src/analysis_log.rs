@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{LazyLock, Mutex};
+
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{IntoResponse, Response};
+
+tokio::task_local! {
+    static RECORDING: String;
+}
+
+// The log lines written while analyzing each recording, oldest first, shared
+// by every recording and capped at `capacity` lines in all. Capturing is off
+// while the capacity's 0.
+#[derive(Default)]
+struct CapturedLog {
+    capacity: usize,
+    lines: VecDeque<(String, String)>,
+}
+
+// The logger is global, so the captured lines have to be too
+static CAPTURED: LazyLock<Mutex<CapturedLog>> = LazyLock::new(Mutex::default);
+
+// Sets how many lines to keep, from analysis_log_buffer_lines, dropping the
+// oldest ones if there are already too many. 0 turns capturing off.
+pub fn set_capacity(capacity: usize) {
+    let mut captured = CAPTURED.lock().unwrap();
+    captured.capacity = capacity;
+    let excess = captured.lines.len().saturating_sub(capacity);
+    captured.lines.drain(..excess);
+}
+
+// The recording the current task is analyzing, if any
+pub fn current_recording() -> Option<String> {
+    RECORDING.try_with(String::clone).ok()
+}
+
+// Runs `future` as part of analyzing the named recording, so that everything
+// it logs is tagged with the recording and captured for it, in place of what
+// was captured the last time it was analyzed
+pub async fn with_recording<F: Future>(name: String, future: F) -> F::Output {
+    CAPTURED
+        .lock()
+        .unwrap()
+        .lines
+        .retain(|(recording, _)| *recording != name);
+    RECORDING.scope(name, future).await
+}
+
+// Called by the logger with every line it writes. Lines written outside of
+// an analysis are ignored.
+pub fn capture(line: &str) {
+    let Some(recording) = current_recording() else {
+        return;
+    };
+    let mut captured = CAPTURED.lock().unwrap();
+    if captured.capacity == 0 {
+        return;
+    }
+    if captured.lines.len() == captured.capacity {
+        captured.lines.pop_front();
+    }
+    captured.lines.push_back((recording, line.to_string()));
+}
+
+// The captured lines for a recording, oldest first, or None if capturing is
+// off
+fn lines_for(name: &str) -> Option<Vec<String>> {
+    let captured = CAPTURED.lock().unwrap();
+    if captured.capacity == 0 {
+        return None;
+    }
+    Some(
+        captured
+            .lines
+            .iter()
+            .filter(|(recording, _)| recording == name)
+            .map(|(_, line)| line.clone())
+            .collect(),
+    )
+}
+
+// Returns the log lines written while the recording was last analyzed, as
+// far back as the buffer goes, as plain text
+pub async fn get_analysis_log(Path(name): Path<String>) -> Result<Response, (StatusCode, String)> {
+    let lines = lines_for(&name).ok_or((
+        StatusCode::NOT_FOUND,
+        "Analysis logs aren't being captured, set analysis_log_buffer_lines to enable them"
+            .to_string(),
+    ))?;
+    let body: String = lines.iter().map(|line| format!("{line}\n")).collect();
+    Ok(([(CONTENT_TYPE, "text/plain; charset=utf-8")], body).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_capture_analysis_log() {
+        set_capacity(0);
+        with_recording("a".to_string(), async { capture("ignored") }).await;
+        assert_eq!(lines_for("a"), None);
+
+        set_capacity(3);
+        capture("outside of any analysis");
+        with_recording("a".to_string(), async {
+            capture("a1");
+            capture("a2");
+        })
+        .await;
+        with_recording("b".to_string(), async {
+            capture("b1");
+            capture("b2");
+        })
+        .await;
+        // the oldest line was dropped to make room
+        assert_eq!(lines_for("a").unwrap(), ["a2"]);
+        assert_eq!(lines_for("b").unwrap(), ["b1", "b2"]);
+        assert_eq!(lines_for("c").unwrap(), Vec::<String>::new());
+
+        // analyzing a recording again replaces its lines
+        with_recording("b".to_string(), async { capture("b3") }).await;
+        assert_eq!(lines_for("b").unwrap(), ["b3"]);
+        assert_eq!(lines_for("a").unwrap(), ["a2"]);
+
+        set_capacity(1);
+        assert_eq!(lines_for("a").unwrap(), Vec::<String>::new());
+        assert_eq!(lines_for("b").unwrap(), ["b3"]);
+        set_capacity(0);
+    }
+}
@@ -0,0 +1,267 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use chrono::{DateTime, Local, TimeDelta};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::AnalysisStatus;
+use crate::qmdl_store::{ManifestEntry, RecordingStore};
+use crate::server::ServerState;
+use crate::summary::summarize_analysis_path;
+
+fn days(days: u64) -> TimeDelta {
+    TimeDelta::try_days(i64::try_from(days).unwrap_or(i64::MAX)).unwrap_or(TimeDelta::MAX)
+}
+
+// Whether a name matches a pattern where `*` matches any run of characters
+// and `?` any one character
+fn name_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // where the last `*` was, and where in the name it started matching
+    let mut backtrack: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == '?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => {
+                let Some((star, start)) = backtrack else {
+                    return false;
+                };
+                // let the `*` take one more character and try again
+                p = star + 1;
+                n = start + 1;
+                backtrack = Some((star, start + 1));
+            }
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+// Which recordings to delete. A recording has to match every criterion
+// that's given, and at least one has to be given, so that an empty filter
+// can't delete everything by accident.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct DeleteFilter {
+    // only recordings started at least this many days ago
+    pub min_age_days: Option<u64>,
+    // only recordings started at most this many days ago
+    pub max_age_days: Option<u64>,
+    // only recordings whose name matches, with `*` and `?` as wildcards
+    pub name_pattern: Option<String>,
+    // only recordings whose analysis is finished and has no warnings
+    pub zero_findings_only: bool,
+    // report what would be deleted without deleting anything
+    pub dry_run: bool,
+}
+
+impl DeleteFilter {
+    fn is_empty(&self) -> bool {
+        self.min_age_days.is_none()
+            && self.max_age_days.is_none()
+            && self.name_pattern.is_none()
+            && !self.zero_findings_only
+    }
+
+    // Whether the entry matches the criteria that only need the manifest,
+    // i.e. everything but zero_findings_only
+    fn matches_entry(&self, entry: &ManifestEntry, now: DateTime<Local>) -> bool {
+        let age = now - entry.start_time;
+        self.min_age_days.is_none_or(|min| age >= days(min))
+            && self.max_age_days.is_none_or(|max| age <= days(max))
+            && self
+                .name_pattern
+                .as_ref()
+                .is_none_or(|pattern| name_matches(pattern, &entry.name))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FailedDeletion {
+    pub name: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteResult {
+    // what was deleted, or would have been for a dry run
+    pub deleted: Vec<String>,
+    pub failed: Vec<FailedDeletion>,
+    pub dry_run: bool,
+}
+
+// The recording being made, and ones queued for or being analyzed, are never
+// deleted in bulk
+fn is_deletable(
+    qmdl_store: &RecordingStore,
+    analysis_status: &AnalysisStatus,
+    entry_index: usize,
+) -> bool {
+    let entry = &qmdl_store.manifest.entries[entry_index];
+    qmdl_store.current_entry != Some(entry_index) && !analysis_status.is_pending(&entry.name)
+}
+
+// Deletes the recordings matching a filter, analyses included, and returns
+// which ones. The candidates are picked under a read lock, and each one's
+// checked again before it's deleted, in case it started being analyzed in
+// the meantime.
+pub async fn delete_matching_recordings(
+    State(state): State<Arc<ServerState>>,
+    Json(filter): Json<DeleteFilter>,
+) -> Result<Json<DeleteResult>, (StatusCode, String)> {
+    if state.config.debug_mode {
+        return Err((StatusCode::FORBIDDEN, "server is in debug mode".to_string()));
+    }
+    if filter.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "at least one of min_age_days, max_age_days, name_pattern or zero_findings_only is needed".to_string(),
+        ));
+    }
+
+    let now = Local::now();
+    let candidates: Vec<(String, Option<PathBuf>)> = {
+        let analysis_status = state.analysis_status_lock.read().await;
+        let qmdl_store = state.qmdl_store_lock.read().await;
+        qmdl_store
+            .manifest
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(entry_index, entry)| {
+                filter.matches_entry(entry, now)
+                    && is_deletable(&qmdl_store, &analysis_status, *entry_index)
+            })
+            // an unfinished analysis can't vouch for there being no findings,
+            // and neither can one that failed or timed out partway through
+            .filter(|(_, entry)| {
+                !filter.zero_findings_only
+                    || (analysis_status.is_analyzed(&entry.name)
+                        && analysis_status.failure(&entry.name).is_none()
+                        && !analysis_status.is_timed_out(&entry.name))
+            })
+            .map(|(_, entry)| {
+                let analysis_path = filter
+                    .zero_findings_only
+                    .then(|| entry.get_analysis_filepath(&qmdl_store.path));
+                (entry.name.clone(), analysis_path)
+            })
+            .collect()
+    };
+
+    let mut to_delete = Vec::with_capacity(candidates.len());
+    for (name, analysis_path) in candidates {
+        let Some(analysis_path) = analysis_path else {
+            to_delete.push(name);
+            continue;
+        };
+        match summarize_analysis_path(&analysis_path).await {
+            Ok(Some(summary)) if summary.warning_count == 0 => to_delete.push(name),
+            Ok(_) => {}
+            Err(err) => warn!("failed to summarize analysis for {name}, not deleting it: {err}"),
+        }
+    }
+
+    if filter.dry_run {
+        return Ok(Json(DeleteResult {
+            deleted: to_delete,
+            failed: Vec::new(),
+            dry_run: true,
+        }));
+    }
+
+    let mut deleted = Vec::new();
+    let mut failed = Vec::new();
+    for name in to_delete {
+        let mut analysis_status = state.analysis_status_lock.write().await;
+        let mut qmdl_store = state.qmdl_store_lock.write().await;
+        let still_deletable = qmdl_store
+            .entry_for_name(&name)
+            .is_some_and(|(entry_index, _)| {
+                is_deletable(&qmdl_store, &analysis_status, entry_index)
+            });
+        if !still_deletable {
+            continue;
+        }
+        match qmdl_store.delete_entry(&name).await {
+            Ok(_) => {
+                analysis_status.forget(&name);
+                deleted.push(name);
+            }
+            Err(err) => {
+                warn!("failed to delete {name}: {err}");
+                failed.push(FailedDeletion {
+                    name,
+                    error: err.to_string(),
+                });
+            }
+        }
+    }
+    info!("bulk deleted {} recording(s)", deleted.len());
+    Ok(Json(DeleteResult {
+        deleted,
+        failed,
+        dry_run: false,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_matches() {
+        assert!(name_matches("1700000000", "1700000000"));
+        assert!(!name_matches("1700000000", "17000000001"));
+        assert!(name_matches("17*", "1700000000"));
+        assert!(name_matches("*", ""));
+        assert!(name_matches("*000", "1700000000"));
+        assert!(name_matches("1?00*0", "1700000000"));
+        assert!(name_matches("*imported*", "an-imported-capture"));
+        assert!(!name_matches("*imported", "an-imported-capture"));
+        assert!(!name_matches("?", ""));
+    }
+
+    #[test]
+    fn test_filter_matches_entry() {
+        let now = Local::now();
+        let entry = |name: &str, age_days: i64| {
+            let mut entry = ManifestEntry::new();
+            entry.name = name.to_string();
+            entry.start_time = now - TimeDelta::days(age_days);
+            entry
+        };
+        let filter = DeleteFilter {
+            min_age_days: Some(7),
+            max_age_days: Some(30),
+            name_pattern: Some("17*".to_string()),
+            ..Default::default()
+        };
+        assert!(filter.matches_entry(&entry("1700", 10), now));
+        assert!(!filter.matches_entry(&entry("1700", 3), now));
+        assert!(!filter.matches_entry(&entry("1700", 40), now));
+        assert!(!filter.matches_entry(&entry("1600", 10), now));
+
+        assert!(DeleteFilter::default().is_empty());
+        // every entry matches a filter that's only zero_findings_only, which
+        // the handler checks against the summaries separately
+        let zero_findings = DeleteFilter {
+            zero_findings_only: true,
+            ..Default::default()
+        };
+        assert!(!zero_findings.is_empty());
+        assert!(zero_findings.matches_entry(&entry("anything", 0), now));
+    }
+}
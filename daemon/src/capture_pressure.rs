@@ -0,0 +1,121 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{info, warn};
+use tokio::time::{Instant, sleep_until};
+
+use crate::config::Config;
+
+struct Thresholds {
+    // how long writing one container of the recording can take before
+    // analysis has to make way for it
+    write_latency: Duration,
+    // how long analysis stays paused after the last slow write
+    cooldown: Duration,
+}
+
+// Lets the recording tell analysis to hold off while writing the recording
+// is slow, so that analyzing old recordings can't starve the live one of the
+// storage it needs and make it drop data. A container whose write takes
+// longer than capture_pressure_write_latency_ms puts the recording under
+// pressure, and analysis pauses between containers until
+// capture_pressure_cooldown_secs have passed without another slow write.
+// Cheap to clone, and clones share the same state.
+#[derive(Clone)]
+pub struct CapturePressure {
+    thresholds: Option<Arc<Thresholds>>,
+    // until when analysis should stay paused, after the last slow write
+    paused_until: Arc<Mutex<Option<Instant>>>,
+}
+
+impl CapturePressure {
+    pub fn new(write_latency: Option<Duration>, cooldown: Duration) -> Self {
+        CapturePressure {
+            thresholds: write_latency.map(|write_latency| {
+                Arc::new(Thresholds {
+                    write_latency,
+                    cooldown,
+                })
+            }),
+            paused_until: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        let write_latency = match config.capture_pressure_write_latency_ms {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        };
+        Self::new(
+            write_latency,
+            Duration::from_secs(config.capture_pressure_cooldown_secs),
+        )
+    }
+
+    // Called by the recording with how long writing a container took
+    pub fn record_write(&self, elapsed: Duration) {
+        let Some(thresholds) = &self.thresholds else {
+            return;
+        };
+        if elapsed < thresholds.write_latency {
+            return;
+        }
+        let now = Instant::now();
+        let mut paused_until = self.paused_until.lock().unwrap();
+        if paused_until.is_none_or(|until| until <= now) {
+            warn!(
+                "writing the recording took {}ms, pausing analysis until it catches up",
+                elapsed.as_millis()
+            );
+        }
+        *paused_until = Some(now + thresholds.cooldown);
+    }
+
+    // Waits for as long as the recording is under pressure, and returns how
+    // long that was
+    pub async fn wait_for_capture(&self) -> Duration {
+        let started = Instant::now();
+        let mut paused = false;
+        loop {
+            let until = *self.paused_until.lock().unwrap();
+            match until {
+                Some(until) if until > Instant::now() => {
+                    paused = true;
+                    sleep_until(until).await;
+                }
+                _ => break,
+            }
+        }
+        if !paused {
+            return Duration::ZERO;
+        }
+        let waited = started.elapsed();
+        info!(
+            "resuming analysis after pausing {}ms for the recording",
+            waited.as_millis()
+        );
+        waited
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_capture_pressure_pauses_analysis() {
+        let disabled = CapturePressure::new(None, Duration::from_secs(60));
+        disabled.record_write(Duration::from_secs(10));
+        assert!(disabled.wait_for_capture().await.is_zero());
+
+        let pressure =
+            CapturePressure::new(Some(Duration::from_millis(100)), Duration::from_millis(50));
+        pressure.record_write(Duration::from_millis(10));
+        assert!(pressure.wait_for_capture().await.is_zero());
+
+        pressure.clone().record_write(Duration::from_millis(200));
+        assert!(pressure.wait_for_capture().await >= Duration::from_millis(50));
+        // the pause is over once the cooldown has passed
+        assert!(pressure.wait_for_capture().await.is_zero());
+    }
+}
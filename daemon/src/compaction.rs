@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use async_compression::tokio::write::GzipEncoder;
+use chrono::{DateTime, FixedOffset, Local};
+use log::{info, warn};
+use rayhunter::analysis::analyzer::{AnalysisRow, Event};
+use serde::Serialize;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{RwLock, oneshot};
+use tokio::time::{Instant, interval_at};
+use tokio_util::task::TaskTracker;
+
+use crate::analysis::AnalysisStatus;
+use crate::qmdl_store::RecordingStore;
+
+// Evidence added to a finding that compaction collapsed repeats of into it
+const REPEAT_COUNT_EVIDENCE: &str = "repeat_count";
+const LAST_SEEN_EVIDENCE: &str = "last_seen";
+
+// What the background compaction task has been up to, for the health endpoint
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CompactionStatus {
+    pub enabled: bool,
+    pub interval_secs: Option<u64>,
+    pub last_run: Option<DateTime<Local>>,
+    pub last_run_compacted: usize,
+    pub last_error: Option<String>,
+    pub total_compacted: usize,
+    pub total_bytes_saved: u64,
+}
+
+impl CompactionStatus {
+    pub fn new(interval_secs: Option<u64>) -> Self {
+        CompactionStatus {
+            enabled: interval_secs.is_some(),
+            interval_secs,
+            ..Default::default()
+        }
+    }
+}
+
+// Returns how many extra occurrences of a finding were collapsed into it
+// during compaction, and when the last of them happened
+pub fn collapsed_repeats(event: &Event) -> (usize, Option<DateTime<FixedOffset>>) {
+    let evidence = |name: &str| {
+        event
+            .evidence
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let repeats = evidence(REPEAT_COUNT_EVIDENCE)
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0);
+    let last_seen = evidence(LAST_SEEN_EVIDENCE)
+        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok());
+    (repeats, last_seen)
+}
+
+// Writes a compacted, gzipped copy of the analysis file at `input` to
+// `output`. Each analyzer's repeats of a finding (same type and message) are
+// collapsed into its first occurrence, and rows left without any findings are
+// dropped. Analysis files only contain rows with warnings or skipped
+// messages, so holding the rows in memory is fine.
+async fn compact_file(input: &Path, output: &Path) -> anyhow::Result<()> {
+    let mut lines = BufReader::new(File::open(input).await?).lines();
+    let metadata = lines.next_line().await?.context("analysis file is empty")?;
+
+    let mut rows: Vec<AnalysisRow> = Vec::new();
+    // (analyzer index, finding) -> (index of the row it was first seen in,
+    // repeats, last seen)
+    type Occurrences = (usize, usize, Option<DateTime<FixedOffset>>);
+    let mut findings: HashMap<(usize, String), Occurrences> = HashMap::new();
+    while let Some(line) = lines.next_line().await? {
+        // bail rather than silently dropping rows we don't understand
+        let mut row: AnalysisRow =
+            serde_json::from_str(&line).context("unparseable analysis row")?;
+        let row_index = rows.len();
+        for (analyzer_index, maybe_event) in row.events.iter_mut().enumerate() {
+            let Some(event) = maybe_event.as_ref() else {
+                continue;
+            };
+            let key = (
+                analyzer_index,
+                format!("{:?} {}", event.event_type, event.message),
+            );
+            match findings.entry(key) {
+                Entry::Occupied(mut occurrences) => {
+                    let (_, repeats, last_seen) = occurrences.get_mut();
+                    *repeats += 1;
+                    *last_seen = row.packet_timestamp.or(*last_seen);
+                    *maybe_event = None;
+                }
+                Entry::Vacant(vacant) => {
+                    vacant.insert((row_index, 0, row.packet_timestamp));
+                }
+            }
+        }
+        if row.skipped_message_reason.is_some() || row.events.iter().any(Option::is_some) {
+            rows.push(row);
+        }
+    }
+
+    for ((analyzer_index, _), (row_index, repeats, last_seen)) in findings {
+        if repeats == 0 {
+            continue;
+        }
+        if let Some(Some(event)) = rows[row_index].events.get_mut(analyzer_index) {
+            event
+                .evidence
+                .push((REPEAT_COUNT_EVIDENCE.to_string(), repeats.to_string()));
+            if let Some(last_seen) = last_seen {
+                event
+                    .evidence
+                    .push((LAST_SEEN_EVIDENCE.to_string(), last_seen.to_rfc3339()));
+            }
+        }
+    }
+
+    let mut encoder = GzipEncoder::new(File::create(output).await?);
+    encoder.write_all(metadata.as_bytes()).await?;
+    encoder.write_all(b"\n").await?;
+    for row in rows {
+        let mut line = serde_json::to_string(&row)?;
+        line.push('\n');
+        encoder.write_all(line.as_bytes()).await?;
+    }
+    // writes the gzip trailer
+    encoder.shutdown().await?;
+    Ok(())
+}
+
+// Compacts a single entry's analysis file, returning how many bytes were
+// saved, or None if the entry was re-analyzed, deleted or otherwise changed
+// while we were at it
+async fn compact_entry(
+    name: &str,
+    analysis_path: &Path,
+    qmdl_store_lock: &RwLock<RecordingStore>,
+    analysis_status_lock: &RwLock<AnalysisStatus>,
+) -> anyhow::Result<Option<u64>> {
+    let before = fs::metadata(analysis_path).await?;
+    let mut compressed_path: PathBuf = analysis_path.to_path_buf();
+    compressed_path.set_extension("ndjson.gz.tmp");
+    if let Err(err) = compact_file(analysis_path, &compressed_path).await {
+        let _ = fs::remove_file(&compressed_path).await;
+        return Err(err);
+    }
+    let compressed_size = fs::metadata(&compressed_path).await?.len();
+
+    let analysis_status = analysis_status_lock.read().await;
+    let mut qmdl_store = qmdl_store_lock.write().await;
+    let unchanged = fs::metadata(analysis_path).await.is_ok_and(|after| {
+        after.len() == before.len() && after.modified().ok() == before.modified().ok()
+    });
+    let entry_index = match qmdl_store.entry_for_name(name) {
+        Some((entry_index, entry))
+            if unchanged
+                && !entry.analysis_compressed
+                && qmdl_store.current_entry != Some(entry_index)
+                && analysis_status.is_analyzed(name) =>
+        {
+            entry_index
+        }
+        _ => {
+            let _ = fs::remove_file(&compressed_path).await;
+            return Ok(None);
+        }
+    };
+    qmdl_store
+        .replace_entry_analysis_compressed(entry_index, &compressed_path)
+        .await?;
+    Ok(Some(before.len().saturating_sub(compressed_size)))
+}
+
+async fn compact_finished_entries(
+    qmdl_store_lock: &RwLock<RecordingStore>,
+    analysis_status_lock: &RwLock<AnalysisStatus>,
+    status_lock: &RwLock<CompactionStatus>,
+) {
+    // never touch the entry being recorded, or anything queued for or in
+    // the middle of analysis
+    let candidates: Vec<(String, PathBuf)> = {
+        let analysis_status = analysis_status_lock.read().await;
+        let qmdl_store = qmdl_store_lock.read().await;
+        qmdl_store
+            .manifest
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(entry_index, _)| qmdl_store.current_entry != Some(*entry_index))
+            // duplicates share the original's analysis file
+            .filter(|(_, entry)| entry.duplicate_of.is_none() && !entry.analysis_compressed)
+            .filter(|(_, entry)| analysis_status.is_analyzed(&entry.name))
+            .map(|(_, entry)| {
+                let path = entry.get_analysis_filepath(&qmdl_store.path);
+                (entry.name.clone(), path)
+            })
+            .collect()
+    };
+
+    let mut compacted = 0;
+    let mut bytes_saved = 0;
+    let mut last_error = None;
+    for (name, path) in candidates {
+        match compact_entry(&name, &path, qmdl_store_lock, analysis_status_lock).await {
+            Ok(Some(saved)) => {
+                compacted += 1;
+                bytes_saved += saved;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                warn!("failed to compact analysis for {name}: {err:#}");
+                last_error = Some(format!("{name}: {err:#}"));
+            }
+        }
+    }
+    if compacted > 0 {
+        info!("compacted {compacted} analysis files, saving {bytes_saved} bytes");
+    }
+
+    let mut status = status_lock.write().await;
+    status.last_run = Some(Local::now());
+    status.last_run_compacted = compacted;
+    status.last_error = last_error;
+    status.total_compacted += compacted;
+    status.total_bytes_saved += bytes_saved;
+}
+
+pub fn run_compaction_thread(
+    task_tracker: &TaskTracker,
+    interval: Duration,
+    qmdl_store_lock: Arc<RwLock<RecordingStore>>,
+    analysis_status_lock: Arc<RwLock<AnalysisStatus>>,
+    status_lock: Arc<RwLock<CompactionStatus>>,
+    mut shutdown_rx: oneshot::Receiver<()>,
+) {
+    task_tracker.spawn(async move {
+        // wait a full interval before the first run, rather than competing
+        // with everything else going on at startup
+        let mut ticks = interval_at(Instant::now() + interval, interval);
+        loop {
+            tokio::select! {
+                _ = &mut shutdown_rx => return,
+                _ = ticks.tick() => {
+                    compact_finished_entries(
+                        &qmdl_store_lock,
+                        &analysis_status_lock,
+                        &status_lock,
+                    )
+                    .await;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rayhunter::qmdl::QmdlInput;
+
+    #[tokio::test]
+    async fn test_compact_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("1.ndjson");
+        let output = dir.path().join("1.ndjson.gz");
+        let warning = |timestamp: &str, message: &str| {
+            format!(
+                r#"{{"packet_timestamp":"{timestamp}","skipped_message_reason":null,"events":[{{"event_type":{{"type":"QualitativeWarning","severity":"Low"}},"message":"{message}"}},null]}}"#
+            )
+        };
+        let report = [
+            r#"{"analyzers":[],"rayhunter":{},"report_version":3}"#.to_string(),
+            warning("2024-01-01T00:00:00+00:00", "a"),
+            warning("2024-01-01T00:01:00+00:00", "b"),
+            warning("2024-01-01T00:02:00+00:00", "a"),
+            r#"{"packet_timestamp":null,"skipped_message_reason":"bad message","events":[]}"#
+                .to_string(),
+            warning("2024-01-01T00:03:00+00:00", "a"),
+        ];
+        fs::write(&input, report.join("\n")).await.unwrap();
+        compact_file(&input, &output).await.unwrap();
+
+        let file = File::open(&output).await.unwrap();
+        let input = QmdlInput::detect(file).await.unwrap();
+        assert!(input.is_compressed());
+        let mut lines = BufReader::new(input).lines();
+        assert_eq!(lines.next_line().await.unwrap().unwrap(), report[0]);
+        let mut rows = Vec::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            rows.push(serde_json::from_str::<AnalysisRow>(&line).unwrap());
+        }
+        assert_eq!(rows.len(), 3);
+        let first = rows[0].events[0].as_ref().unwrap();
+        assert_eq!(first.message, "a");
+        assert_eq!(
+            collapsed_repeats(first),
+            (
+                2,
+                Some(DateTime::parse_from_rfc3339("2024-01-01T00:03:00+00:00").unwrap())
+            )
+        );
+        assert_eq!(rows[1].events[0].as_ref().unwrap().message, "b");
+        assert_eq!(
+            rows[2].skipped_message_reason.as_deref(),
+            Some("bad message")
+        );
+    }
+}
@@ -1,45 +1,224 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
 use log::warn;
 use serde::{Deserialize, Serialize};
 
 use rayhunter::Device;
 use rayhunter::analysis::analyzer::AnalyzerConfig;
 
+use crate::analysis::{AnalysisPriorityRule, QueueOverflow};
 use crate::error::RayhunterError;
+use crate::quiet_hours::{QuietHours, QuietHoursConfig};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(default)]
 pub struct Config {
     pub qmdl_store_path: String,
+    pub bind_address: String,
     pub port: u16,
+    pub tls_cert_path: Option<String>,
+    pub tls_key_path: Option<String>,
+    pub api_token: Option<String>,
+    pub api_token_protects_reads: bool,
     pub debug_mode: bool,
     pub device: Device,
     pub ui_level: u8,
     pub colorblind_mode: bool,
     pub key_input_mode: u8,
     pub analyzers: AnalyzerConfig,
+    pub required_analyzers: Vec<String>,
+    pub request_timeout_secs: u64,
+    pub streaming_request_timeout_secs: u64,
+    pub post_analysis_hook: Option<String>,
+    pub post_analysis_hook_timeout_secs: u64,
+    pub notification_queue_max_len: usize,
+    pub notification_retry_interval_secs: u64,
+    pub syslog_findings: bool,
+    pub syslog_socket_path: String,
+    pub finding_socket_path: Option<String>,
+    pub findings_stdout: bool,
+    pub quiet_hours: Option<QuietHoursConfig>,
+    pub analysis_compaction_interval_secs: Option<u64>,
+    pub in_memory_analysis_fallback: bool,
+    pub analyze_on_startup: bool,
+    pub dead_letter_log: bool,
+    pub analysis_max_consecutive_decode_errors: Option<usize>,
+    pub analysis_max_decode_errors: Option<usize>,
+    pub analysis_start_delay_secs: u64,
+    pub analysis_max_runtime_secs: u64,
+    pub analysis_flush_max_interval_ms: u64,
+    pub analysis_sample_every: usize,
+    pub analysis_max_queue_depth: usize,
+    pub analysis_queue_overflow: QueueOverflow,
+    pub analysis_overflow_sample_every: usize,
+    pub analysis_priority: Vec<AnalysisPriorityRule>,
+    pub analysis_log_buffer_lines: usize,
+    pub analysis_status_max_finished: usize,
+    pub capture_pressure_write_latency_ms: u64,
+    pub capture_pressure_cooldown_secs: u64,
+    pub analysis_worker_threads: usize,
+    pub analysis_worker_cores: Vec<usize>,
+    pub summary_read_concurrency: usize,
+    pub qmdl_retention_max_age_days: Option<u64>,
+    pub qmdl_retention_max_total_bytes: Option<u64>,
+    pub analysis_retention_max_age_days: Option<u64>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
             qmdl_store_path: "/data/rayhunter/qmdl".to_string(),
+            bind_address: "0.0.0.0".to_string(),
             port: 8080,
+            tls_cert_path: None,
+            tls_key_path: None,
+            api_token: None,
+            api_token_protects_reads: false,
             debug_mode: false,
             device: Device::Orbic,
             ui_level: 1,
             colorblind_mode: false,
             key_input_mode: 0,
             analyzers: AnalyzerConfig::default(),
+            required_analyzers: Vec::new(),
+            request_timeout_secs: 30,
+            streaming_request_timeout_secs: 300,
+            post_analysis_hook: None,
+            post_analysis_hook_timeout_secs: 60,
+            notification_queue_max_len: 0,
+            notification_retry_interval_secs: 60,
+            syslog_findings: false,
+            syslog_socket_path: "/dev/log".to_string(),
+            finding_socket_path: None,
+            findings_stdout: false,
+            quiet_hours: None,
+            analysis_compaction_interval_secs: None,
+            in_memory_analysis_fallback: false,
+            analyze_on_startup: false,
+            dead_letter_log: false,
+            analysis_max_consecutive_decode_errors: None,
+            analysis_max_decode_errors: None,
+            analysis_start_delay_secs: 0,
+            analysis_max_runtime_secs: 3600,
+            analysis_flush_max_interval_ms: 1000,
+            analysis_sample_every: 0,
+            analysis_max_queue_depth: 0,
+            analysis_queue_overflow: QueueOverflow::SkipOldest,
+            analysis_overflow_sample_every: 10,
+            analysis_priority: Vec::new(),
+            analysis_log_buffer_lines: 0,
+            analysis_status_max_finished: 0,
+            capture_pressure_write_latency_ms: 500,
+            capture_pressure_cooldown_secs: 5,
+            analysis_worker_threads: 0,
+            analysis_worker_cores: Vec::new(),
+            summary_read_concurrency: 2,
+            qmdl_retention_max_age_days: None,
+            qmdl_retention_max_total_bytes: None,
+            analysis_retention_max_age_days: None,
         }
     }
 }
 
+impl Config {
+    // How long a single recording may be analyzed for, if there's a limit
+    pub fn analysis_max_runtime(&self) -> Option<Duration> {
+        match self.analysis_max_runtime_secs {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    // How often queued notifications are retried, at least once a second
+    pub fn notification_retry_interval(&self) -> Duration {
+        Duration::from_secs(self.notification_retry_interval_secs.max(1))
+    }
+
+    pub fn analysis_flush_max_interval(&self) -> Duration {
+        Duration::from_millis(self.analysis_flush_max_interval_ms)
+    }
+
+    // Refuses configs that leave out any of the analyzers listed in
+    // required_analyzers, so a hardened deployment can't lose one of them to
+    // a typo or a careless edit
+    pub fn check_required_analyzers(&self) -> Result<(), RayhunterError> {
+        let problems = self.analyzers.check_required(&self.required_analyzers);
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(RayhunterError::RequiredAnalyzersMissing(
+                problems.join(", "),
+            ))
+        }
+    }
+
+    pub fn listen_address(&self) -> Result<SocketAddr, RayhunterError> {
+        let ip: IpAddr = self
+            .bind_address
+            .trim()
+            .parse()
+            .map_err(|_| RayhunterError::InvalidBindAddress(self.bind_address.clone()))?;
+        Ok(SocketAddr::new(ip, self.port))
+    }
+
+    // The certificate and key paths, if the server should use TLS. Setting
+    // only one of them is a mistake rather than a request for plain HTTP.
+    pub fn tls_paths(&self) -> Result<Option<(&str, &str)>, RayhunterError> {
+        match (&self.tls_cert_path, &self.tls_key_path) {
+            (Some(cert), Some(key)) => Ok(Some((cert, key))),
+            (None, None) => Ok(None),
+            (Some(_), None) => Err(RayhunterError::TlsConfigError(
+                "tls_cert_path is set but tls_key_path isn't".to_string(),
+            )),
+            (None, Some(_)) => Err(RayhunterError::TlsConfigError(
+                "tls_key_path is set but tls_cert_path isn't".to_string(),
+            )),
+        }
+    }
+
+    pub fn quiet_hours(&self) -> Result<Option<QuietHours>, RayhunterError> {
+        self.quiet_hours.as_ref().map(QuietHours::parse).transpose()
+    }
+
+    // The token is sent in a cookie as well as a header, so it's limited to
+    // characters that don't need escaping in either
+    fn check_api_token(&self) -> Result<(), RayhunterError> {
+        let Some(token) = &self.api_token else {
+            return Ok(());
+        };
+        let valid = !token.is_empty()
+            && token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~'));
+        if valid {
+            Ok(())
+        } else {
+            Err(RayhunterError::InvalidApiToken)
+        }
+    }
+
+    pub fn check_server(&self) -> Result<(), RayhunterError> {
+        self.listen_address()?;
+        self.tls_paths()?;
+        self.quiet_hours()?;
+        self.check_api_token()
+    }
+}
+
 pub async fn parse_config<P>(path: P) -> Result<Config, RayhunterError>
 where
     P: AsRef<std::path::Path>,
 {
     if let Ok(config_file) = tokio::fs::read_to_string(&path).await {
-        Ok(toml::from_str(&config_file).map_err(RayhunterError::ConfigFileParsingError)?)
+        let config: Config =
+            toml::from_str(&config_file).map_err(RayhunterError::ConfigFileParsingError)?;
+        for problem in config.analyzers.validate_settings() {
+            warn!("{problem}");
+        }
+        config.check_required_analyzers()?;
+        config.check_server()?;
+        Ok(config)
     } else {
         warn!("unable to read config file, using default config");
         Ok(Config::default())
@@ -1,27 +1,45 @@
+use std::io::ErrorKind;
+use std::ops::Range;
+use std::path::PathBuf;
 use std::pin::pin;
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::body::Body;
-use axum::extract::{Path, State};
-use axum::http::StatusCode;
-use axum::http::header::CONTENT_TYPE;
+use axum::extract::{Path, Query, State};
+use axum::http::header::{CONTENT_TYPE, VARY};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 use futures::{StreamExt, TryStreamExt};
 use log::{debug, error, info, warn};
-use rayhunter::analysis::analyzer::AnalyzerConfig;
+use rayhunter::analysis::analyzer::{AnalyzerConfig, CaptureMetadata};
 use rayhunter::diag::DataType;
 use rayhunter::diag_device::DiagDevice;
-use rayhunter::qmdl::QmdlWriter;
+use rayhunter::qmdl::{QmdlInput, QmdlWriter};
+use serde::Deserialize;
 use tokio::fs::File;
-use tokio::sync::RwLock;
 use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::{RwLock, broadcast};
+use tokio::time::{Instant, MissedTickBehavior, interval};
 use tokio_util::io::ReaderStream;
 use tokio_util::task::TaskTracker;
 
-use crate::analysis::{AnalysisCtrlMessage, AnalysisWriter};
+use crate::analysis::{AnalysisCtrlMessage, AnalysisUpdate, AnalysisWriter};
+use crate::capture_pressure::CapturePressure;
 use crate::display;
+use crate::etag::ETagBuilder;
+use crate::line_index::read_report_lines;
 use crate::qmdl_store::{EntryType, RecordingStore, RecordingStoreError};
+use crate::recent_findings::RecentFindings;
 use crate::server::ServerState;
+use crate::syslog::FindingSyslog;
+use crate::wire_format::{
+    MESSAGEPACK_CONTENT_TYPE, WireFormat, ndjson_to_messagepack, ndjson_to_messagepack_body,
+};
+
+// How often the diag thread checks for analysis rows that have been left
+// unflushed for too long, at most
+const MIN_FLUSH_CHECK_INTERVAL: Duration = Duration::from_millis(100);
 
 pub enum DiagDeviceCtrlMessage {
     StopRecording,
@@ -37,16 +55,39 @@ pub fn run_diag_read_thread(
     ui_update_sender: Sender<display::DisplayState>,
     qmdl_store_lock: Arc<RwLock<RecordingStore>>,
     analysis_sender: Sender<AnalysisCtrlMessage>,
+    analysis_update_sender: broadcast::Sender<AnalysisUpdate>,
     analyzer_config: AnalyzerConfig,
+    findings_syslog: Option<FindingSyslog>,
+    recent_findings: RecentFindings,
+    dead_letter_log: bool,
+    flush_max_interval: Duration,
+    capture_pressure: CapturePressure,
 ) {
     task_tracker.spawn(async move {
-        let (initial_qmdl_file, initial_analysis_file) = qmdl_store_lock.write().await.new_entry().await.expect("failed creating QMDL file entry");
+        let (initial_qmdl_file, initial_analysis_file, initial_dead_letter_file, initial_name, initial_capture) = {
+            let mut qmdl_store = qmdl_store_lock.write().await;
+            let (qmdl_file, analysis_file) = qmdl_store.new_entry().await.expect("failed creating QMDL file entry");
+            let dead_letter_file = open_dead_letters(&qmdl_store, dead_letter_log).await;
+            (qmdl_file, analysis_file, dead_letter_file, current_entry_name(&qmdl_store), current_entry_capture(&qmdl_store))
+        };
         let mut maybe_qmdl_writer: Option<QmdlWriter<File>> = Some(QmdlWriter::new(initial_qmdl_file));
         let mut diag_stream = pin!(dev.as_stream().into_stream());
-        let mut maybe_analysis_writer = Some(AnalysisWriter::new(initial_analysis_file, &analyzer_config).await
-            .expect("failed to create analysis writer"));
+        let mut maybe_analysis_writer = Some(AnalysisWriter::new(initial_analysis_file, &analyzer_config, &initial_name, analysis_update_sender.clone(), findings_syslog.clone(), initial_capture).await
+            .expect("failed to create analysis writer")
+            .with_dead_letters(initial_dead_letter_file)
+            .with_recent_findings(recent_findings.clone())
+            .with_flush_max_interval(flush_max_interval));
+        // the analysis might not be written to again for a while, so check
+        // on whatever it's left unflushed
+        let mut flush_ticks = interval((flush_max_interval / 2).max(MIN_FLUSH_CHECK_INTERVAL));
+        flush_ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
         loop {
             tokio::select! {
+                _ = flush_ticks.tick() => {
+                    if let Some(analysis_writer) = maybe_analysis_writer.as_mut() {
+                        analysis_writer.flush_if_due().await.expect("failed to flush analysis writer");
+                    }
+                }
                 msg = qmdl_file_rx.recv() => {
                     match msg {
                         Some(DiagDeviceCtrlMessage::StartRecording) => {
@@ -65,8 +106,14 @@ pub fn run_diag_read_thread(
                                 analysis_writer.close().await.expect("failed to close analysis writer");
                             }
 
-                            maybe_analysis_writer = Some(AnalysisWriter::new(new_analysis_file, &analyzer_config).await
-                                .expect("failed to write to analysis file"));
+                            let name = current_entry_name(&qmdl_store);
+                            let capture = current_entry_capture(&qmdl_store);
+                            let dead_letter_file = open_dead_letters(&qmdl_store, dead_letter_log).await;
+                            maybe_analysis_writer = Some(AnalysisWriter::new(new_analysis_file, &analyzer_config, &name, analysis_update_sender.clone(), findings_syslog.clone(), capture).await
+                                .expect("failed to write to analysis file")
+                                .with_dead_letters(dead_letter_file)
+                                .with_recent_findings(recent_findings.clone())
+                                .with_flush_max_interval(flush_max_interval));
 
                             if let Err(e) = ui_update_sender.send(display::DisplayState::Recording).await {
                                 warn!("couldn't send ui update message: {e}");
@@ -118,12 +165,14 @@ pub fn run_diag_read_thread(
                             // keep track of how many bytes were written to the QMDL file so we can read
                             // a valid block of data from it in the HTTP server
                             if let Some(qmdl_writer) = maybe_qmdl_writer.as_mut() {
+                                let write_started = Instant::now();
                                 qmdl_writer.write_container(&container).await.expect("failed to write to QMDL writer");
                                 debug!("total QMDL bytes written: {}, updating manifest...", qmdl_writer.total_written);
                                 let mut qmdl_store = qmdl_store_lock.write().await;
                                 let index = qmdl_store.current_entry.expect("DiagDevice had qmdl_writer, but QmdlStore didn't have current entry???");
                                 qmdl_store.update_entry_qmdl_size(index, qmdl_writer.total_written).await
                                     .expect("failed to update qmdl file size");
+                                capture_pressure.record_write(write_started.elapsed());
                                 debug!("done!");
                             } else {
                                 debug!("no qmdl_writer set, continuing...");
@@ -137,6 +186,11 @@ pub fn run_diag_read_thread(
                                     ui_update_sender.send(display::DisplayState::WarningDetected).await
                                         .expect("couldn't send ui update message: {}");
                                 }
+                                let mut qmdl_store = qmdl_store_lock.write().await;
+                                if let Some(index) = qmdl_store.current_entry {
+                                    qmdl_store.update_entry_diag_versions(index, analysis_writer.get_diag_versions()).await
+                                        .expect("failed to update diag layout versions");
+                                }
                             }
                         },
                         Err(err) => {
@@ -150,6 +204,37 @@ pub fn run_diag_read_thread(
     });
 }
 
+// Opens the current entry's dead-letter file, if they're enabled
+async fn open_dead_letters(qmdl_store: &RecordingStore, dead_letter_log: bool) -> Option<File> {
+    if !dead_letter_log {
+        return None;
+    }
+    let (entry_index, _) = qmdl_store.get_current_entry()?;
+    match qmdl_store
+        .clear_and_open_entry_dead_letters(entry_index)
+        .await
+    {
+        Ok(file) => Some(file),
+        Err(e) => {
+            warn!("couldn't open dead-letter file: {e}");
+            None
+        }
+    }
+}
+
+fn current_entry_name(qmdl_store: &RecordingStore) -> String {
+    let (_, entry) = qmdl_store
+        .get_current_entry()
+        .expect("QmdlStore didn't have a current entry after creating one");
+    entry.name.clone()
+}
+
+fn current_entry_capture(qmdl_store: &RecordingStore) -> Option<CaptureMetadata> {
+    qmdl_store
+        .get_current_entry()
+        .map(|(_, entry)| entry.capture_metadata())
+}
+
 pub async fn start_recording(
     State(state): State<Arc<ServerState>>,
 ) -> Result<(StatusCode, String), (StatusCode, String)> {
@@ -271,29 +356,230 @@ pub async fn delete_all_recordings(
     Ok((StatusCode::ACCEPTED, "ok".to_string()))
 }
 
-pub async fn get_analysis_report(
-    State(state): State<Arc<ServerState>>,
-    Path(qmdl_name): Path<String>,
-) -> Result<Response, (StatusCode, String)> {
+// Where a recording's analysis report can be read from
+enum ReportSource {
+    InMemory(String),
+    OnDisk {
+        name: String,
+        report_path: PathBuf,
+        index_path: PathBuf,
+        compressed: bool,
+    },
+}
+
+async fn find_report(
+    state: &ServerState,
+    qmdl_name: &str,
+) -> Result<ReportSource, (StatusCode, String)> {
+    {
+        let analysis_status = state.analysis_status_lock.read().await;
+        if let Some(report) = analysis_status.in_memory_report(qmdl_name) {
+            return Ok(ReportSource::InMemory(report.to_string()));
+        }
+        // whatever's on disk is stale or incomplete
+        if let Some(reason) = analysis_status.failure(qmdl_name) {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Analysis of \"{qmdl_name}\" failed: {reason}"),
+            ));
+        }
+    }
     let qmdl_store = state.qmdl_store_lock.read().await;
-    let (entry_index, _) = if qmdl_name == "live" {
+    let (_, entry) = if qmdl_name == "live" {
         qmdl_store.get_current_entry().ok_or((
             StatusCode::SERVICE_UNAVAILABLE,
             "No QMDL data's being recorded to analyze, try starting a new recording!".to_string(),
         ))?
     } else {
-        qmdl_store.entry_for_name(&qmdl_name).ok_or((
+        qmdl_store.entry_for_name(qmdl_name).ok_or((
             StatusCode::NOT_FOUND,
             format!("Couldn't find QMDL entry with name \"{qmdl_name}\""),
         ))?
     };
-    let analysis_file = qmdl_store
-        .open_entry_analysis(entry_index)
-        .await
+    Ok(ReportSource::OnDisk {
+        name: entry.name.clone(),
+        report_path: entry.get_analysis_filepath(&qmdl_store.path),
+        index_path: entry.get_analysis_index_filepath(&qmdl_store.path),
+        compressed: entry.analysis_compressed,
+    })
+}
+
+// Reads the given ranges of lines of a report, along with how many lines it
+// has in total
+async fn read_report_source_lines(
+    source: &ReportSource,
+    ranges: &[Range<usize>],
+) -> Result<(Vec<String>, usize), (StatusCode, String)> {
+    match source {
+        ReportSource::InMemory(report) => {
+            let lines: Vec<&str> = report.lines().filter(|line| !line.is_empty()).collect();
+            let read = lines
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| ranges.iter().any(|range| range.contains(i)))
+                .map(|(_, line)| line.to_string())
+                .collect();
+            Ok((read, lines.len()))
+        }
+        ReportSource::OnDisk {
+            report_path,
+            index_path,
+            compressed,
+            ..
+        } => read_report_lines(report_path, index_path, *compressed, ranges)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}"))),
+    }
+}
+
+// Selects a page of a report's rows. The report's metadata is always
+// included, so each page can be read on its own.
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct ReportPageParams {
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl ReportPageParams {
+    fn is_paged(&self) -> bool {
+        self.offset > 0 || self.limit.is_some()
+    }
+}
+
+pub static TOTAL_ROWS_HEADER: HeaderName = HeaderName::from_static("x-total-rows");
+
+// Serves a recording's analysis report as NDJSON, or as a sequence of
+// MessagePack values (one per line of the NDJSON) if the client asks for it.
+// Clients polling a report can send back its ETag to get a 304 until it
+// changes. Given an offset or limit, only that page of the report's rows is
+// served, following its metadata, with the total number of rows in the
+// X-Total-Rows header.
+pub async fn get_analysis_report(
+    State(state): State<Arc<ServerState>>,
+    Path(qmdl_name): Path<String>,
+    Query(page): Query<ReportPageParams>,
+    format: WireFormat,
+    request_headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let content_type = match format {
+        WireFormat::Json => "application/x-ndjson",
+        WireFormat::MessagePack => MESSAGEPACK_CONTENT_TYPE,
+    };
+    let headers = [(CONTENT_TYPE, content_type), (VARY, "Accept")];
+    let source = find_report(&state, &qmdl_name).await?;
+    let mut etag = ETagBuilder::new(format);
+    etag = match &source {
+        ReportSource::InMemory(report) => etag.content(report.as_bytes()),
+        ReportSource::OnDisk {
+            name, report_path, ..
+        } => {
+            let metadata = tokio::fs::metadata(report_path)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}")))?;
+            etag.file(name, &metadata)
+        }
+    };
+    if page.is_paged() {
+        etag = etag.content(format!("{}:{:?}", page.offset, page.limit).as_bytes());
+    }
+    let etag = etag.finish();
+    if etag.matches(&request_headers) {
+        return Ok(etag.not_modified());
+    }
+
+    if page.is_paged() {
+        // rows follow the metadata on the first line
+        let first_row = page.offset.saturating_add(1);
+        let rows = first_row..first_row.saturating_add(page.limit.unwrap_or(usize::MAX));
+        let (lines, total_lines) = read_report_source_lines(&source, &[0..1, rows]).await?;
+        let mut report = lines.join("\n");
+        report.push('\n');
+        let body = match format {
+            WireFormat::Json => report.into_bytes(),
+            WireFormat::MessagePack => ndjson_to_messagepack(&report)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?,
+        };
+        let total_rows = [(
+            TOTAL_ROWS_HEADER.clone(),
+            HeaderValue::from(total_lines.saturating_sub(1)),
+        )];
+        return Ok((headers, etag.header(), total_rows, body).into_response());
+    }
+
+    let body = match source {
+        ReportSource::InMemory(report) => match format {
+            WireFormat::Json => Body::from(report),
+            WireFormat::MessagePack => Body::from(
+                ndjson_to_messagepack(&report)
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?,
+            ),
+        },
+        ReportSource::OnDisk { report_path, .. } => {
+            let file = File::open(&report_path)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}")))?;
+            let analysis_file = QmdlInput::detect(file)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}")))?;
+            match format {
+                WireFormat::Json => Body::from_stream(ReaderStream::new(analysis_file)),
+                WireFormat::MessagePack => ndjson_to_messagepack_body(analysis_file),
+            }
+        }
+    };
+    Ok((headers, etag.header(), body).into_response())
+}
+
+// Serves a single row of a recording's analysis report by its index, counting
+// from 0 for the first row after the metadata
+pub async fn get_analysis_row(
+    State(state): State<Arc<ServerState>>,
+    Path((qmdl_name, index)): Path<(String, usize)>,
+    format: WireFormat,
+) -> Result<Response, (StatusCode, String)> {
+    let source = find_report(&state, &qmdl_name).await?;
+    let line = index.saturating_add(1);
+    let (lines, total_lines) =
+        read_report_source_lines(&source, &[line..line.saturating_add(1)]).await?;
+    let Some(row) = lines.first() else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!(
+                "\"{qmdl_name}\" has no row {index}, its report has {} rows",
+                total_lines.saturating_sub(1)
+            ),
+        ));
+    };
+    let row: serde_json::Value = serde_json::from_str(row)
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}")))?;
-    let analysis_stream = ReaderStream::new(analysis_file);
+    Ok(format.respond(&row))
+}
 
+// Serves the messages that couldn't be decoded while analyzing a recording,
+// as recorded when dead_letter_log is enabled
+pub async fn get_dead_letters(
+    State(state): State<Arc<ServerState>>,
+    Path(qmdl_name): Path<String>,
+) -> Result<Response, (StatusCode, String)> {
+    let qmdl_store = state.qmdl_store_lock.read().await;
+    let (entry_index, _) = qmdl_store.entry_for_name(&qmdl_name).ok_or((
+        StatusCode::NOT_FOUND,
+        format!("Couldn't find QMDL entry with name \"{qmdl_name}\""),
+    ))?;
+    let dead_letter_file = match qmdl_store.open_entry_dead_letters(entry_index).await {
+        Ok(file) => file,
+        Err(RecordingStoreError::ReadFileError(e)) if e.kind() == ErrorKind::NotFound => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!(
+                    "No dead letters were recorded for \"{qmdl_name}\", enable dead_letter_log and re-analyze it"
+                ),
+            ));
+        }
+        Err(e) => return Err((StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}"))),
+    };
     let headers = [(CONTENT_TYPE, "application/x-ndjson")];
-    let body = Body::from_stream(analysis_stream);
+    let body = Body::from_stream(ReaderStream::new(dead_letter_file));
     Ok((headers, body).into_response())
 }
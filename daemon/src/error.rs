@@ -15,4 +15,18 @@ pub enum RayhunterError {
     QmdlStoreError(#[from] RecordingStoreError),
     #[error("No QMDL store found at path {0}, but can't create a new one due to debug mode")]
     NoStoreDebugMode(String),
+    #[error("Config leaves out required analyzers: {0}")]
+    RequiredAnalyzersMissing(String),
+    #[error("Invalid bind_address {0:?}, expected an IP address such as 0.0.0.0 or ::1")]
+    InvalidBindAddress(String),
+    #[error("TLS configuration error: {0}")]
+    TlsConfigError(String),
+    #[error(
+        "Invalid api_token, it must be non-empty and only use letters, digits and the characters - . _ ~"
+    )]
+    InvalidApiToken,
+    #[error("Failed to start the analysis runtime: {0}")]
+    AnalysisRuntimeError(std::io::Error),
+    #[error("Invalid quiet_hours: {0}")]
+    InvalidQuietHours(String),
 }
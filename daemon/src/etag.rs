@@ -0,0 +1,150 @@
+use std::fs::Metadata;
+use std::time::UNIX_EPOCH;
+
+use axum::http::header::{ETAG, IF_NONE_MATCH, VARY};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+
+use crate::wire_format::WireFormat;
+
+// A weak entity tag for a response built from one or more analysis files.
+// It's derived from the files' sizes and modification times rather than
+// their contents, so revalidating a report costs a stat instead of a read.
+// The daemon's version goes in too, since a new version may render the same
+// files differently.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ETag(String);
+
+pub struct ETagBuilder(Sha256);
+
+impl ETagBuilder {
+    // The same files are served differently depending on the Accept header,
+    // so each format gets its own tags
+    pub fn new(format: WireFormat) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(env!("CARGO_PKG_VERSION"));
+        hasher.update(match format {
+            WireFormat::Json => [0],
+            WireFormat::MessagePack => [1],
+        });
+        ETagBuilder(hasher)
+    }
+
+    fn field(mut self, bytes: &[u8]) -> Self {
+        self.0.update((bytes.len() as u64).to_le_bytes());
+        self.0.update(bytes);
+        self
+    }
+
+    pub fn file(self, name: &str, metadata: &Metadata) -> Self {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|since_epoch| since_epoch.as_nanos())
+            .unwrap_or(0);
+        self.field(name.as_bytes())
+            .field(&metadata.len().to_le_bytes())
+            .field(&modified.to_le_bytes())
+    }
+
+    // For responses that aren't backed by a file
+    pub fn content(self, content: &[u8]) -> Self {
+        self.field(content)
+    }
+
+    pub fn finish(self) -> ETag {
+        let digest = self.0.finalize();
+        let hex: String = digest[..12]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        ETag(format!("W/\"{hex}\""))
+    }
+}
+
+impl ETag {
+    // Whether the If-None-Match header lists this tag, using the weak
+    // comparison RFC 9110 prescribes for it
+    pub fn matches(&self, headers: &HeaderMap) -> bool {
+        let ours = self.0.trim_start_matches("W/");
+        headers
+            .get_all(IF_NONE_MATCH)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == ours)
+    }
+
+    pub fn header(&self) -> [(HeaderName, HeaderValue); 1] {
+        // the tag is only ever hex digits, quotes and a slash
+        [(ETAG, HeaderValue::from_str(&self.0).unwrap())]
+    }
+
+    pub fn not_modified(&self) -> Response {
+        (StatusCode::NOT_MODIFIED, self.header(), [(VARY, "Accept")]).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(if_none_match: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_str(if_none_match).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        let etag = ETagBuilder::new(WireFormat::Json).content(b"{}").finish();
+        let value = etag.0.clone();
+        let strong = value.trim_start_matches("W/").to_string();
+
+        assert!(etag.matches(&headers(&value)));
+        assert!(etag.matches(&headers(&strong)));
+        assert!(etag.matches(&headers(&format!("\"other\", {value}"))));
+        assert!(etag.matches(&headers("*")));
+        assert!(!etag.matches(&headers("\"other\"")));
+        assert!(!etag.matches(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_etag_depends_on_format_and_files() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let metadata = file.as_file().metadata().unwrap();
+        let json = ETagBuilder::new(WireFormat::Json)
+            .file("1", &metadata)
+            .finish();
+        assert_eq!(
+            json,
+            ETagBuilder::new(WireFormat::Json)
+                .file("1", &metadata)
+                .finish()
+        );
+        assert_ne!(
+            json,
+            ETagBuilder::new(WireFormat::MessagePack)
+                .file("1", &metadata)
+                .finish()
+        );
+        assert_ne!(
+            json,
+            ETagBuilder::new(WireFormat::Json)
+                .file("2", &metadata)
+                .finish()
+        );
+
+        file.as_file().set_len(10).unwrap();
+        let metadata = file.as_file().metadata().unwrap();
+        assert_ne!(
+            json,
+            ETagBuilder::new(WireFormat::Json)
+                .file("1", &metadata)
+                .finish()
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use log::{info, warn};
+use rayhunter::analysis::analyzer::{AnalysisRow, Event};
+use rayhunter::util::LogLimiter;
+use serde::Serialize;
+use tokio::io::{AsyncWriteExt, Stdout};
+use tokio::net::UnixStream;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, oneshot};
+use tokio::time::{Instant, timeout};
+use tokio_util::task::TaskTracker;
+
+use crate::analysis::AnalysisUpdate;
+use crate::quiet_hours::QuietHours;
+use crate::report_csv::parse_analyzer_names;
+
+// How long to wait for the consumer to take a finding before giving up on it
+// and reconnecting
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+static CONNECT_ERRORS: LogLimiter = LogLimiter::new(10);
+static STDOUT_ERRORS: LogLimiter = LogLimiter::new(10);
+
+#[derive(Serialize)]
+struct SocketFinding<'a> {
+    recording: &'a str,
+    analyzer: &'a str,
+    packet_timestamp: Option<DateTime<FixedOffset>>,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+// Formats each finding in an analysis row as a line of JSON, leaving out the
+// ones quiet hours hold back
+fn format_findings(
+    recording: &str,
+    analyzer_names: &[String],
+    line: &str,
+    quiet_hours: Option<&QuietHours>,
+) -> Vec<String> {
+    let row: AnalysisRow = match serde_json::from_str(line) {
+        Ok(row) => row,
+        Err(err) => {
+            warn!("skipping unparseable analysis row: {err}");
+            return Vec::new();
+        }
+    };
+    let now = Utc::now();
+    row.events
+        .iter()
+        .enumerate()
+        .filter_map(|(i, event)| {
+            let event = event.as_ref()?;
+            if quiet_hours.is_some_and(|quiet_hours| !quiet_hours.allows_event(event, &now)) {
+                return None;
+            }
+            let fallback_name = format!("analyzer {i}");
+            let analyzer = analyzer_names.get(i).unwrap_or(&fallback_name);
+            let finding = SocketFinding {
+                recording,
+                analyzer,
+                packet_timestamp: row.packet_timestamp,
+                event,
+            };
+            let mut json = serde_json::to_string(&finding).ok()?;
+            json.push('\n');
+            Some(json)
+        })
+        .collect()
+}
+
+// The connection to the consumer, which comes and goes as it pleases
+struct Connection {
+    path: PathBuf,
+    stream: Option<UnixStream>,
+    next_attempt: Instant,
+    reconnect_delay: Duration,
+}
+
+impl Connection {
+    fn new(path: PathBuf) -> Self {
+        Connection {
+            path,
+            stream: None,
+            next_attempt: Instant::now(),
+            reconnect_delay: MIN_RECONNECT_DELAY,
+        }
+    }
+
+    // Connects if there's no connection yet, backing off while nobody's
+    // listening
+    async fn connect(&mut self) -> Option<&mut UnixStream> {
+        if self.stream.is_none() && Instant::now() >= self.next_attempt {
+            match UnixStream::connect(&self.path).await {
+                Ok(stream) => {
+                    info!("connected to finding socket at {}", self.path.display());
+                    self.stream = Some(stream);
+                    self.reconnect_delay = MIN_RECONNECT_DELAY;
+                }
+                Err(err) => {
+                    if CONNECT_ERRORS.check("finding socket connection errors") {
+                        warn!(
+                            "failed to connect to finding socket at {}: {err}",
+                            self.path.display()
+                        );
+                    }
+                    self.next_attempt = Instant::now() + self.reconnect_delay;
+                    self.reconnect_delay = (self.reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                }
+            }
+        }
+        self.stream.as_mut()
+    }
+
+    // Sends a finding, dropping it if there's no consumer or it isn't keeping
+    // up (it's still in the analysis file)
+    async fn send(&mut self, finding: &str) {
+        let Some(stream) = self.connect().await else {
+            return;
+        };
+        let err = match timeout(WRITE_TIMEOUT, stream.write_all(finding.as_bytes())).await {
+            Ok(Ok(())) => return,
+            Ok(Err(err)) => err.to_string(),
+            Err(_) => "timed out".to_string(),
+        };
+        warn!(
+            "lost connection to finding socket at {}: {err}",
+            self.path.display()
+        );
+        self.stream = None;
+    }
+}
+
+// Writes a finding to stdout in one go, so that findings never end up
+// interleaved with anything else written there
+async fn write_stdout(stdout: &mut Stdout, finding: &str) {
+    let result = match stdout.write_all(finding.as_bytes()).await {
+        Ok(()) => stdout.flush().await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = result {
+        if STDOUT_ERRORS.check("stdout finding errors") {
+            warn!("failed to write finding to stdout: {err}");
+        }
+    }
+}
+
+// Publishes each finding as a line of JSON as analyses are written: to a Unix
+// socket, for local tools that want findings as they happen, and/or to
+// stdout, for log pipelines like `docker logs` or fluentd to pick up. The log
+// itself goes to stderr, so stdout only ever has findings on it. This follows
+// the same updates the analysis websocket does, so a slow consumer only
+// makes this miss findings, and never holds up the analysis. During quiet
+// hours, only findings at or above their minimum severity are published to
+// the socket, while stdout, like syslog, gets all of them.
+pub fn run_finding_stream_thread(
+    task_tracker: &TaskTracker,
+    socket_path: Option<PathBuf>,
+    to_stdout: bool,
+    mut updates: broadcast::Receiver<AnalysisUpdate>,
+    mut shutdown_rx: oneshot::Receiver<()>,
+    quiet_hours: Option<QuietHours>,
+) {
+    task_tracker.spawn(async move {
+        let mut connection = socket_path.map(Connection::new);
+        let mut stdout = to_stdout.then(tokio::io::stdout);
+        // recording name -> its analyzers' names, from the metadata line
+        let mut analyzer_names: HashMap<String, Vec<String>> = HashMap::new();
+        loop {
+            let update = tokio::select! {
+                _ = &mut shutdown_rx => return,
+                update = updates.recv() => update,
+            };
+            match update {
+                Ok(AnalysisUpdate::Line {
+                    name,
+                    line_num: 0,
+                    line,
+                }) => {
+                    let names = parse_analyzer_names(Some(&line), &name);
+                    analyzer_names.insert(name, names);
+                }
+                Ok(AnalysisUpdate::Line { name, line, .. }) => {
+                    let names = analyzer_names.get(&name).map(Vec::as_slice).unwrap_or(&[]);
+                    if let Some(connection) = &mut connection {
+                        for finding in format_findings(&name, names, &line, quiet_hours.as_ref()) {
+                            connection.send(&finding).await;
+                        }
+                    }
+                    if let Some(stdout) = &mut stdout {
+                        for finding in format_findings(&name, names, &line, None) {
+                            write_stdout(stdout, &finding).await;
+                        }
+                    }
+                }
+                Ok(AnalysisUpdate::Finished { name }) => {
+                    analyzer_names.remove(&name);
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("finding socket fell behind, skipped {skipped} analysis updates");
+                }
+                Err(RecvError::Closed) => return,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_findings() {
+        let names = vec!["Null Cipher".to_string()];
+        let line = r#"{"packet_timestamp":"2024-01-01T00:00:00+00:00","skipped_message_reason":null,"events":[{"event_type":{"type":"QualitativeWarning","severity":"High"},"message":"Cell suggested use of null cipher","explanation":""},{"event_type":{"type":"Informational"},"message":"unnamed","explanation":""}]}"#;
+        let findings = format_findings("1720000000", &names, line, None);
+        assert_eq!(findings.len(), 2);
+        assert!(findings.iter().all(|f| f.ends_with('\n')));
+        let first: serde_json::Value = serde_json::from_str(&findings[0]).unwrap();
+        assert_eq!(first["recording"], "1720000000");
+        assert_eq!(first["analyzer"], "Null Cipher");
+        assert_eq!(first["message"], "Cell suggested use of null cipher");
+        assert_eq!(first["event_type"]["severity"], "High");
+        let second: serde_json::Value = serde_json::from_str(&findings[1]).unwrap();
+        assert_eq!(second["analyzer"], "analyzer 1");
+
+        let skipped =
+            r#"{"packet_timestamp":null,"skipped_message_reason":"bad message","events":[]}"#;
+        assert!(format_findings("1720000000", &names, skipped, None).is_empty());
+    }
+}
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, FixedOffset};
+use log::{info, warn};
+use rayhunter::analysis::analyzer::{AnalysisRow, Event};
+use rayhunter::diag::DataType;
+use rayhunter::qmdl::QmdlReader;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
+use tokio::sync::broadcast;
+
+use crate::analysis::AnalysisWriter;
+use crate::qmdl_store::RecordingStoreError;
+use crate::report_csv::parse_analyzer_names;
+use crate::server::ServerState;
+
+// The analysis runs of a recording that can be compared. Reports are
+// overwritten when a recording is reanalyzed, so there's no history of
+// earlier runs to pick from: just the report on disk, and a fresh analysis
+// with the current config, which is what shows the effect of a config or
+// analyzer change.
+const SAVED_RUN: &str = "saved";
+const CURRENT_RUN: &str = "current";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffedFinding {
+    pub analyzer: String,
+    pub packet_timestamp: Option<DateTime<FixedOffset>>,
+    pub event: Event,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangedFinding {
+    pub analyzer: String,
+    pub packet_timestamp: Option<DateTime<FixedOffset>>,
+    pub from: Event,
+    pub to: Event,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FindingsDiff {
+    pub from: String,
+    pub to: String,
+    pub added: Vec<DiffedFinding>,
+    pub removed: Vec<DiffedFinding>,
+    pub changed: Vec<ChangedFinding>,
+}
+
+// Findings are matched up across reports by their analyzer and timestamp,
+// plus how many findings by the same analyzer came before them at that
+// timestamp, since row positions shift whenever skipped rows come and go
+type FindingKey = (String, Option<DateTime<FixedOffset>>, usize);
+
+// Returns a report's findings in the order they appear, keyed for diffing
+fn report_findings(report: &str, name: &str) -> Vec<(FindingKey, DiffedFinding)> {
+    let mut lines = report.lines();
+    let analyzer_names = parse_analyzer_names(lines.next(), name);
+    let mut occurrences: HashMap<(String, Option<DateTime<FixedOffset>>), usize> = HashMap::new();
+    let mut findings = Vec::new();
+    for line in lines {
+        let row: AnalysisRow = match serde_json::from_str(line) {
+            Ok(row) => row,
+            Err(err) => {
+                warn!("skipping unparseable analysis row: {err}");
+                continue;
+            }
+        };
+        for (i, event) in row.events.into_iter().enumerate() {
+            let Some(event) = event else {
+                continue;
+            };
+            let analyzer = analyzer_names
+                .get(i)
+                .cloned()
+                .unwrap_or_else(|| format!("analyzer {i}"));
+            let occurrence = occurrences
+                .entry((analyzer.clone(), row.packet_timestamp))
+                .or_default();
+            let key = (analyzer.clone(), row.packet_timestamp, *occurrence);
+            *occurrence += 1;
+            findings.push((
+                key,
+                DiffedFinding {
+                    analyzer,
+                    packet_timestamp: row.packet_timestamp,
+                    event,
+                },
+            ));
+        }
+    }
+    findings
+}
+
+// Events don't implement PartialEq, but comparing them serialized is just as
+// good
+fn same_event(a: &Event, b: &Event) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+// Compares the findings of two reports of the same recording
+pub fn diff_reports(
+    from_run: &str,
+    from: &str,
+    to_run: &str,
+    to: &str,
+    name: &str,
+) -> FindingsDiff {
+    let from_findings = report_findings(from, name);
+    let from_order: Vec<FindingKey> = from_findings.iter().map(|(key, _)| key.clone()).collect();
+    let mut unmatched: HashMap<FindingKey, DiffedFinding> = from_findings.into_iter().collect();
+    let mut diff = FindingsDiff {
+        from: from_run.to_string(),
+        to: to_run.to_string(),
+        added: Vec::new(),
+        removed: Vec::new(),
+        changed: Vec::new(),
+    };
+    for (key, finding) in report_findings(to, name) {
+        match unmatched.remove(&key) {
+            None => diff.added.push(finding),
+            Some(old) if same_event(&old.event, &finding.event) => {}
+            Some(old) => diff.changed.push(ChangedFinding {
+                analyzer: finding.analyzer,
+                packet_timestamp: finding.packet_timestamp,
+                from: old.event,
+                to: finding.event,
+            }),
+        }
+    }
+    // keep the removed findings in report order
+    diff.removed = from_order
+        .iter()
+        .filter_map(|key| unmatched.remove(key))
+        .collect();
+    diff
+}
+
+#[derive(Deserialize)]
+pub struct AnalysisDiffParams {
+    from: Option<String>,
+    to: Option<String>,
+}
+
+// Reads the report on disk
+async fn saved_report(state: &ServerState, name: &str) -> Result<String, (StatusCode, String)> {
+    let qmdl_store = state.qmdl_store_lock.read().await;
+    let (entry_index, _) = qmdl_store.entry_for_name(name).ok_or((
+        StatusCode::NOT_FOUND,
+        format!("Couldn't find QMDL entry with name \"{name}\""),
+    ))?;
+    let mut analysis_file = qmdl_store
+        .open_entry_analysis(entry_index)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("{name} hasn't been analyzed: {e}"),
+            )
+        })?;
+    let mut report = String::new();
+    analysis_file
+        .read_to_string(&mut report)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}")))?;
+    Ok(report)
+}
+
+// Analyzes the recording again with the current config, without touching
+// its saved report
+async fn current_report(state: &ServerState, name: &str) -> Result<String, (StatusCode, String)> {
+    let internal_error =
+        |e: &dyn std::fmt::Debug| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}"));
+    let qmdl_file = {
+        let qmdl_store = state.qmdl_store_lock.read().await;
+        let (entry_index, entry) = qmdl_store.entry_for_name(name).ok_or((
+            StatusCode::NOT_FOUND,
+            format!("Couldn't find QMDL entry with name \"{name}\""),
+        ))?;
+        if entry.qmdl_pruned {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("{name}: {}", RecordingStoreError::QmdlPrunedError),
+            ));
+        }
+        qmdl_store
+            .open_entry_qmdl(entry_index)
+            .await
+            .map_err(|e| internal_error(&e))?
+    };
+
+    info!("analyzing {name} with the current config to diff it");
+    // nobody follows these over the websocket, and the findings would only
+    // be repeats of the saved analysis in syslog
+    let (update_sender, _) = broadcast::channel(1);
+    let mut analysis_writer =
+        AnalysisWriter::new_in_memory(&state.config.analyzers, name, update_sender, None)
+            .await
+            .map_err(|e| internal_error(&e))?;
+    let file_size = qmdl_file
+        .metadata()
+        .await
+        .map_err(|e| internal_error(&e))?
+        .len();
+    let mut qmdl_reader = QmdlReader::new_maybe_compressed(qmdl_file, Some(file_size as usize))
+        .await
+        .map_err(|e| internal_error(&e))?;
+    while let Some(container) = qmdl_reader
+        .get_next_messages_container()
+        .await
+        .map_err(|e| internal_error(&e))?
+    {
+        if container.data_type != DataType::UserSpace {
+            continue;
+        }
+        analysis_writer
+            .analyze(container)
+            .await
+            .map_err(|e| internal_error(&e))?;
+    }
+    Ok(analysis_writer
+        .close()
+        .await
+        .map_err(|e| internal_error(&e))?
+        .unwrap_or_default())
+}
+
+async fn run_report(
+    state: &ServerState,
+    name: &str,
+    run: &str,
+) -> Result<String, (StatusCode, String)> {
+    match run {
+        SAVED_RUN => saved_report(state, name).await,
+        CURRENT_RUN => current_report(state, name).await,
+        _ => Err((
+            StatusCode::NOT_FOUND,
+            format!(
+                "no analysis run \"{run}\" for {name}: earlier runs aren't kept, so only \"{SAVED_RUN}\" and \"{CURRENT_RUN}\" are available"
+            ),
+        )),
+    }
+}
+
+// Lists the findings that were added, removed or changed between two
+// analysis runs of a recording. By default, that's from the saved report to
+// what the current config finds.
+pub async fn get_analysis_diff(
+    State(state): State<Arc<ServerState>>,
+    Path(qmdl_name): Path<String>,
+    Query(params): Query<AnalysisDiffParams>,
+) -> Result<Json<FindingsDiff>, (StatusCode, String)> {
+    let from_run = params.from.unwrap_or_else(|| SAVED_RUN.to_string());
+    let to_run = params.to.unwrap_or_else(|| CURRENT_RUN.to_string());
+    let from = run_report(&state, &qmdl_name, &from_run).await?;
+    let to = if to_run == from_run {
+        from.clone()
+    } else {
+        run_report(&state, &qmdl_name, &to_run).await?
+    };
+    Ok(Json(diff_reports(
+        &from_run, &from, &to_run, &to, &qmdl_name,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA: &str = r#"{"analyzers":[{"name":"Null Cipher"},{"name":"PCI Conflict"}]}"#;
+
+    fn report(rows: &[&str]) -> String {
+        std::iter::once(METADATA)
+            .chain(rows.iter().copied())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_diff_reports() {
+        let from = report(&[
+            r#"{"packet_timestamp":"2024-01-01T00:00:00+00:00","skipped_message_reason":null,"events":[{"event_type":{"type":"QualitativeWarning","severity":"High"},"message":"kept","explanation":""},null]}"#,
+            r#"{"packet_timestamp":"2024-01-01T00:00:01+00:00","skipped_message_reason":null,"events":[null,{"event_type":{"type":"Informational"},"message":"removed","explanation":""}]}"#,
+            r#"{"packet_timestamp":"2024-01-01T00:00:02+00:00","skipped_message_reason":null,"events":[{"event_type":{"type":"QualitativeWarning","severity":"Low"},"message":"changed","explanation":""},null]}"#,
+        ]);
+        let to = report(&[
+            r#"{"packet_timestamp":null,"skipped_message_reason":"bad message","events":[]}"#,
+            r#"{"packet_timestamp":"2024-01-01T00:00:00+00:00","skipped_message_reason":null,"events":[{"event_type":{"type":"QualitativeWarning","severity":"High"},"message":"kept","explanation":""},null]}"#,
+            r#"{"packet_timestamp":"2024-01-01T00:00:02+00:00","skipped_message_reason":null,"events":[{"event_type":{"type":"QualitativeWarning","severity":"High"},"message":"changed","explanation":""},{"event_type":{"type":"Informational"},"message":"added","explanation":""}]}"#,
+        ]);
+        let diff = diff_reports("saved", &from, "current", &to, "test");
+        let messages = |findings: &[DiffedFinding]| -> Vec<String> {
+            findings.iter().map(|f| f.event.message.clone()).collect()
+        };
+        assert_eq!(messages(&diff.added), vec!["added"]);
+        assert_eq!(diff.added[0].analyzer, "PCI Conflict");
+        assert_eq!(messages(&diff.removed), vec!["removed"]);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].analyzer, "Null Cipher");
+        assert_eq!(diff.changed[0].to.message, "changed");
+
+        let unchanged = diff_reports("saved", &from, "saved", &from, "test");
+        assert!(unchanged.added.is_empty());
+        assert!(unchanged.removed.is_empty());
+        assert!(unchanged.changed.is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+// How long the write rate is measured over
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+// Up to this many lines per RATE_WINDOW are flushed as soon as they're
+// written. Past that, they're batched.
+const BATCH_THRESHOLD: usize = 10;
+
+// Decides when a writer flushes. While lines are few and far between, each is
+// flushed as soon as it's written, so anyone following along sees findings
+// right away, but once they start pouring in they're flushed in batches, to
+// keep up. Either way, none is left unflushed for longer than max_interval,
+// and a max_interval of zero flushes every line.
+#[derive(Debug)]
+pub struct AdaptiveFlush {
+    max_interval: Duration,
+    window_start: Instant,
+    window_lines: usize,
+    last_flush: Instant,
+    unflushed: bool,
+}
+
+impl AdaptiveFlush {
+    pub fn new(max_interval: Duration, now: Instant) -> Self {
+        AdaptiveFlush {
+            max_interval,
+            window_start: now,
+            window_lines: 0,
+            last_flush: now,
+            unflushed: false,
+        }
+    }
+
+    // Records a line having been written, returning whether to flush now
+    pub fn on_write(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.window_start) >= RATE_WINDOW {
+            self.window_start = now;
+            self.window_lines = 0;
+        }
+        self.window_lines += 1;
+        self.unflushed = true;
+        !self.is_batching() || self.is_due(now)
+    }
+
+    // Whether lines are being written too quickly to flush each one
+    pub fn is_batching(&self) -> bool {
+        !self.max_interval.is_zero() && self.window_lines > BATCH_THRESHOLD
+    }
+
+    // Whether there are lines that have waited long enough to be flushed
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.unflushed && now.duration_since(self.last_flush) >= self.max_interval
+    }
+
+    pub fn flushed(&mut self, now: Instant) {
+        self.last_flush = now;
+        self.unflushed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_flush() {
+        let start = Instant::now();
+        let mut flush = AdaptiveFlush::new(Duration::from_millis(500), start);
+        // sparse lines are flushed right away
+        for _ in 0..BATCH_THRESHOLD {
+            assert!(flush.on_write(start));
+            flush.flushed(start);
+        }
+        // a flood gets batched
+        let flood = start + Duration::from_millis(100);
+        assert!(!flush.on_write(flood));
+        assert!(flush.is_batching());
+        assert!(!flush.on_write(flood));
+        // but not for longer than max_interval
+        let late = start + Duration::from_millis(600);
+        assert!(flush.is_due(late));
+        assert!(flush.on_write(late));
+        flush.flushed(late);
+        assert!(!flush.is_due(late + Duration::from_secs(10)));
+
+        // once the flood is over, lines are flushed right away again
+        let quiet = late + Duration::from_secs(2);
+        assert!(flush.on_write(quiet));
+        assert!(!flush.is_batching());
+
+        // a zero max_interval never batches
+        let mut flush = AdaptiveFlush::new(Duration::ZERO, start);
+        assert!((0..100).all(|_| flush.on_write(start)));
+    }
+}
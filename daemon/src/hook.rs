@@ -0,0 +1,129 @@
+use std::process::Stdio;
+use std::time::Duration;
+
+use chrono::Utc;
+use log::{error, info, warn};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::config::Config;
+use crate::notification_queue::NotificationQueue;
+use crate::quiet_hours::QuietHours;
+use crate::summary::AnalysisSummary;
+
+// A user-configured command that's run after each analysis finishes, as an
+// escape hatch for integrations rayhunter doesn't support itself. The command
+// is passed the recording's name as its only argument, and the analysis
+// summary as JSON on stdin. During quiet hours, it's only run for analyses
+// with findings severe enough to get through. With a notification queue,
+// runs that fail are queued to be retried later.
+#[derive(Debug, Clone)]
+pub struct PostAnalysisHook {
+    command: String,
+    timeout: Duration,
+    quiet_hours: Option<QuietHours>,
+}
+
+impl PostAnalysisHook {
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let command = config.post_analysis_hook.as_ref()?;
+        Some(PostAnalysisHook {
+            command: command.clone(),
+            timeout: Duration::from_secs(config.post_analysis_hook_timeout_secs),
+            quiet_hours: config.quiet_hours().ok().flatten(),
+        })
+    }
+
+    // Runs the hook, logging its output. Any failure is only logged, since a
+    // broken hook shouldn't affect the analysis itself. While there are
+    // notifications queued, this one's queued behind them rather than
+    // overtaking them.
+    pub async fn run(
+        &self,
+        name: &str,
+        summary: Option<&AnalysisSummary>,
+        queue: Option<&NotificationQueue>,
+    ) {
+        if let Some(quiet_hours) = &self.quiet_hours {
+            let max_severity = summary.and_then(|summary| summary.max_severity);
+            if !quiet_hours.allows(max_severity, &Utc::now()) {
+                info!("not running post-analysis hook for {name} during quiet hours");
+                return;
+            }
+        }
+        let summary = match serde_json::to_value(summary) {
+            Ok(summary) => summary,
+            Err(err) => {
+                error!("failed to serialize analysis summary for hook: {err}");
+                return;
+            }
+        };
+        let backlog = match queue {
+            Some(queue) => !queue.is_empty().await,
+            None => false,
+        };
+        if let Some(queue) = queue.filter(|_| backlog) {
+            queue.push(name, summary).await;
+            queue.flush_soon();
+            return;
+        }
+        let summary_json = serde_json::to_vec(&summary).unwrap_or_default();
+        let delivered = self.deliver(name, &summary_json).await;
+        if let Some(queue) = queue.filter(|_| !delivered) {
+            info!("queueing the post-analysis hook for {name} to retry later");
+            queue.push(name, summary).await;
+        }
+    }
+
+    // Runs the hook with the given summary, returning whether it succeeded
+    pub async fn deliver(&self, name: &str, summary_json: &[u8]) -> bool {
+        let child = Command::new(&self.command)
+            .arg(name)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(err) => {
+                error!("failed to run post-analysis hook {:?}: {err}", self.command);
+                return false;
+            }
+        };
+        // if the hook doesn't read stdin, writing to it fails, which is fine
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(summary_json).await;
+        }
+
+        let output = match tokio::time::timeout(self.timeout, child.wait_with_output()).await {
+            Ok(Ok(output)) => output,
+            Ok(Err(err)) => {
+                error!("failed to wait for post-analysis hook: {err}");
+                return false;
+            }
+            Err(_) => {
+                // dropping the child's future kills it
+                warn!(
+                    "post-analysis hook for {name} timed out after {:?}",
+                    self.timeout
+                );
+                return false;
+            }
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if output.status.success() {
+            info!("post-analysis hook for {name} finished: {}", stdout.trim());
+            true
+        } else {
+            warn!(
+                "post-analysis hook for {name} failed ({}): {} {}",
+                output.status,
+                stdout.trim(),
+                stderr.trim()
+            );
+            false
+        }
+    }
+}
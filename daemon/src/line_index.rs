@@ -0,0 +1,247 @@
+use std::io::{self, ErrorKind, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::UNIX_EPOCH;
+
+use log::warn;
+use rayhunter::qmdl::QmdlInput;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncSeekExt, BufReader};
+
+const MAGIC: &[u8; 4] = b"RHLI";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 8 + 16 + 8;
+
+// Tells apart the temporary files of indexes being written at the same time
+static NEXT_TEMP_ID: AtomicU64 = AtomicU64::new(0);
+
+fn modified_nanos(metadata: &std::fs::Metadata) -> u128 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|since_epoch| since_epoch.as_nanos())
+        .unwrap_or(0)
+}
+
+// The byte offsets of each line of an analysis report, so that a page of the
+// report or a single row of it can be read with a seek instead of by scanning
+// everything before it. It's stored next to the report as a small sidecar
+// and is rebuilt whenever the report's size or modification time stop
+// matching the ones it was built from. Only complete lines are indexed, so a
+// row that's still being written by a live analysis shows up once it's done.
+#[derive(Debug, PartialEq)]
+pub struct LineIndex {
+    file_len: u64,
+    modified: u128,
+    // where each line starts, followed by where the last one ends
+    boundaries: Vec<u64>,
+}
+
+impl LineIndex {
+    async fn build<R: AsyncRead + Unpin>(
+        reader: R,
+        metadata: &std::fs::Metadata,
+    ) -> io::Result<Self> {
+        let file_len = metadata.len();
+        // the report may grow while it's being indexed
+        let mut reader = BufReader::new(reader.take(file_len));
+        let mut boundaries = vec![0];
+        let mut position = 0;
+        loop {
+            let buf = reader.fill_buf().await?;
+            if buf.is_empty() {
+                break;
+            }
+            for (i, byte) in buf.iter().enumerate() {
+                if *byte == b'\n' {
+                    boundaries.push(position + i as u64 + 1);
+                }
+            }
+            let read = buf.len();
+            position += read as u64;
+            reader.consume(read);
+        }
+        Ok(LineIndex {
+            file_len,
+            modified: modified_nanos(metadata),
+            boundaries,
+        })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(HEADER_LEN + self.boundaries.len() * 8);
+        encoded.extend(MAGIC);
+        encoded.push(VERSION);
+        encoded.extend(self.file_len.to_le_bytes());
+        encoded.extend(self.modified.to_le_bytes());
+        encoded.extend((self.boundaries.len() as u64).to_le_bytes());
+        for boundary in &self.boundaries {
+            encoded.extend(boundary.to_le_bytes());
+        }
+        encoded
+    }
+
+    fn decode(encoded: &[u8]) -> Option<Self> {
+        let (header, rest) = encoded.split_at_checked(HEADER_LEN)?;
+        let (magic, header) = header.split_at(MAGIC.len());
+        if magic != MAGIC || header[0] != VERSION {
+            return None;
+        }
+        let file_len = u64::from_le_bytes(header[1..9].try_into().ok()?);
+        let modified = u128::from_le_bytes(header[9..25].try_into().ok()?);
+        let count = u64::from_le_bytes(header[25..33].try_into().ok()?);
+        if count == 0 || rest.len() as u64 != count.checked_mul(8)? {
+            return None;
+        }
+        let boundaries = rest
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        Some(LineIndex {
+            file_len,
+            modified,
+            boundaries,
+        })
+    }
+
+    // Loads the report's index from its sidecar, building it (and writing the
+    // sidecar) if there's none yet or the report has changed since
+    pub async fn load_or_build(report_path: &Path, index_path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(report_path).await?;
+        match fs::read(index_path).await {
+            Ok(encoded) => {
+                if let Some(index) = LineIndex::decode(&encoded) {
+                    if index.file_len == metadata.len()
+                        && index.modified == modified_nanos(&metadata)
+                    {
+                        return Ok(index);
+                    }
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => warn!("failed to read line index {index_path:?}: {err}"),
+        }
+        let index = LineIndex::build(File::open(report_path).await?, &metadata).await?;
+        // the index still works without its sidecar, it just has to be
+        // rebuilt next time
+        if let Err(err) = write_sidecar(index_path, &index.encode()).await {
+            warn!("failed to write line index {index_path:?}: {err}");
+        }
+        Ok(index)
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.boundaries.len() - 1
+    }
+
+    // Reads the given lines of the report, without their newlines. Lines past
+    // the end of the report are left out.
+    pub async fn read_lines(
+        &self,
+        file: &mut File,
+        lines: Range<usize>,
+    ) -> io::Result<Vec<String>> {
+        let end = lines.end.min(self.line_count());
+        let start = lines.start.min(end);
+        let first_byte = self.boundaries[start];
+        let mut buf = vec![0; (self.boundaries[end] - first_byte) as usize];
+        file.seek(SeekFrom::Start(first_byte)).await?;
+        file.read_exact(&mut buf).await?;
+        let mut read = Vec::with_capacity(end - start);
+        for line in start..end {
+            let from = (self.boundaries[line] - first_byte) as usize;
+            let to = (self.boundaries[line + 1] - first_byte) as usize;
+            let line = String::from_utf8_lossy(&buf[from..to]);
+            read.push(line.trim_end_matches(['\n', '\r']).to_string());
+        }
+        Ok(read)
+    }
+}
+
+// Writes the sidecar under a temporary name first, so a concurrent reader
+// never sees half of it
+async fn write_sidecar(index_path: &Path, encoded: &[u8]) -> io::Result<()> {
+    let temp_id = NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed);
+    let mut temp_path = PathBuf::from(index_path);
+    temp_path.as_mut_os_string().push(format!(".{temp_id}.tmp"));
+    fs::write(&temp_path, encoded).await?;
+    if let Err(err) = fs::rename(&temp_path, index_path).await {
+        let _ = fs::remove_file(&temp_path).await;
+        return Err(err);
+    }
+    Ok(())
+}
+
+// Reads the given ranges of lines of an analysis report on disk, in order,
+// along with how many complete lines it has in total. Compacted reports are
+// gzipped, and offsets into those can't be seeked to, so they're scanned from
+// the start instead.
+pub async fn read_report_lines(
+    report_path: &Path,
+    index_path: &Path,
+    compressed: bool,
+    ranges: &[Range<usize>],
+) -> io::Result<(Vec<String>, usize)> {
+    let mut read = Vec::new();
+    if !compressed {
+        let index = LineIndex::load_or_build(report_path, index_path).await?;
+        let mut file = File::open(report_path).await?;
+        for range in ranges {
+            read.extend(index.read_lines(&mut file, range.clone()).await?);
+        }
+        return Ok((read, index.line_count()));
+    }
+    let file = QmdlInput::detect(File::open(report_path).await?).await?;
+    let mut report_lines = BufReader::new(file).lines();
+    let mut total = 0;
+    while let Some(line) = report_lines.next_line().await? {
+        if ranges.iter().any(|range| range.contains(&total)) {
+            read.push(line);
+        }
+        total += 1;
+    }
+    Ok((read, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_line_index() {
+        let dir = TempDir::new().unwrap();
+        let report_path = dir.path().join("1.ndjson");
+        let index_path = dir.path().join("1.ndjson.idx");
+        fs::write(
+            &report_path,
+            "{\"a\":1}\n{\"b\":2}\r\n{\"c\":3}\n{\"partial\"",
+        )
+        .await
+        .unwrap();
+
+        let index = LineIndex::load_or_build(&report_path, &index_path)
+            .await
+            .unwrap();
+        assert_eq!(index.line_count(), 3);
+        let (lines, total) = read_report_lines(&report_path, &index_path, false, &[1..10])
+            .await
+            .unwrap();
+        assert_eq!(lines, ["{\"b\":2}", "{\"c\":3}"]);
+        assert_eq!(total, 3);
+
+        // the sidecar is reused while the report stays the same
+        let encoded = fs::read(&index_path).await.unwrap();
+        assert_eq!(LineIndex::decode(&encoded).unwrap(), index);
+
+        // and rebuilt once it changes
+        fs::write(&report_path, "{\"a\":1}\n").await.unwrap();
+        let (lines, total) = read_report_lines(&report_path, &index_path, false, &[0..1])
+            .await
+            .unwrap();
+        assert_eq!(lines, ["{\"a\":1}"]);
+        assert_eq!(total, 1);
+    }
+}
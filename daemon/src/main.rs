@@ -1,89 +1,216 @@
 mod analysis;
+mod analysis_log;
+mod bulk_delete;
+mod capture_pressure;
+mod compaction;
 mod config;
 mod diag;
 mod display;
 mod error;
+mod etag;
+mod finding_socket;
+mod findings_diff;
+mod flush;
+mod hook;
 mod key_input;
+mod line_index;
+mod middleware;
+mod notification_queue;
 mod pcap;
 mod qmdl_store;
+mod quiet_hours;
+mod recent_findings;
+mod report_csv;
+mod report_geojson;
+mod report_stix;
+mod retention;
+mod selftest;
 mod server;
 mod stats;
+mod summary;
+mod syslog;
+mod tls;
+mod wire_format;
+mod worker_runtime;
 
-use std::net::SocketAddr;
+use std::io::Write;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
+use crate::analysis_log::get_analysis_log;
+use crate::capture_pressure::CapturePressure;
+use crate::compaction::{CompactionStatus, run_compaction_thread};
 use crate::config::{parse_args, parse_config};
 use crate::diag::run_diag_read_thread;
 use crate::error::RayhunterError;
+use crate::finding_socket::run_finding_stream_thread;
+use crate::findings_diff::get_analysis_diff;
+use crate::hook::PostAnalysisHook;
+use crate::middleware::{
+    ApiAuth, current_request_id, request_id, request_timeout, require_api_token,
+};
+use crate::notification_queue::{NotificationQueue, run_notification_retry_thread};
 use crate::pcap::get_pcap;
 use crate::qmdl_store::RecordingStore;
-use crate::server::{ServerState, get_config, get_qmdl, get_zip, serve_static, set_config};
-use crate::stats::{get_qmdl_manifest, get_system_stats};
+use crate::recent_findings::{RECENT_FINDINGS_CAPACITY, RecentFindings, get_recent_findings};
+use crate::report_csv::get_analysis_report_csv;
+use crate::report_geojson::{get_analysis_report_geojson, load_configured_cell_database};
+use crate::report_stix::get_analysis_report_stix;
+use crate::retention::{RetentionPolicy, run_retention_thread};
+use crate::selftest::post_selftest;
+use crate::server::{
+    ServerState, get_config, get_qmdl, get_recording_raw, get_zip, import_recording,
+    pair_recording, serve_static, set_config,
+};
+use crate::stats::{get_health, get_qmdl_manifest, get_system_stats, get_version};
+use crate::summary::{get_analysis_summaries, get_analysis_summary};
+use crate::syslog::FindingSyslog;
+use crate::tls::load_tls_config;
+use crate::worker_runtime::{AnalysisRuntime, build_analysis_runtime};
 
 use analysis::{
-    AnalysisCtrlMessage, AnalysisStatus, get_analysis_status, run_analysis_thread, start_analysis,
+    ANALYSIS_UPDATE_CHANNEL_CAPACITY, AnalysisCtrlMessage, AnalysisPriorities, AnalysisStatus,
+    DecodeErrorTolerance, QueueGuard, analyze_merged, analyze_window, delete_analysis,
+    get_analysis_status, get_analysis_ws, get_window_analysis, retry_failed_analyses,
+    retry_failed_analysis, run_analysis_thread, start_analysis,
 };
 use axum::Router;
+use axum::middleware::{from_fn, from_fn_with_state};
 use axum::response::Redirect;
 use axum::routing::{get, post};
+use axum_server::tls_rustls::RustlsConfig;
+use bulk_delete::delete_matching_recordings;
+use chrono::{SecondsFormat, Utc};
 use diag::{
     DiagDeviceCtrlMessage, delete_all_recordings, delete_recording, get_analysis_report,
-    start_recording, stop_recording,
+    get_analysis_row, get_dead_letters, start_recording, stop_recording,
 };
 use log::{error, info};
 use qmdl_store::RecordingStoreError;
 use rayhunter::Device;
+use rayhunter::analysis::cell_distance::CellDistanceSettings;
 use rayhunter::diag_device::DiagDevice;
 use tokio::net::TcpListener;
 use tokio::select;
 use tokio::sync::mpsc::{self, Sender};
-use tokio::sync::{RwLock, oneshot};
+use tokio::sync::{RwLock, Semaphore, broadcast, oneshot};
 use tokio::task::JoinHandle;
 use tokio_util::task::TaskTracker;
 
 type AppRouter = Router<Arc<ServerState>>;
 
-fn get_router() -> AppRouter {
-    Router::new()
+fn get_router(config: &config::Config) -> AppRouter {
+    // streaming endpoints can legitimately take a while to produce a
+    // response, so they get their own (longer) timeout
+    let streaming_routes = Router::new()
         .route("/api/pcap/{name}", get(get_pcap))
         .route("/api/qmdl/{name}", get(get_qmdl))
+        .route("/api/recordings/{name}/raw", get(get_recording_raw))
         .route("/api/zip/{name}", get(get_zip))
+        .route("/api/analysis-report/{name}", get(get_analysis_report))
+        .route(
+            "/api/analysis/{name}/report.csv",
+            get(get_analysis_report_csv),
+        )
+        .route("/api/analysis/{name}/stix", get(get_analysis_report_stix))
+        .route(
+            "/api/analysis/{name}/geojson",
+            get(get_analysis_report_geojson),
+        )
+        .route("/api/analysis/summaries", get(get_analysis_summaries))
+        .route("/api/recordings/import", post(import_recording))
+        .route("/api/recordings/{name}/paired", post(pair_recording))
+        .route("/api/analysis/{name}/ws", get(get_analysis_ws))
+        .route("/api/analysis/merged", post(analyze_merged))
+        .route("/api/analysis/{name}/diff", get(get_analysis_diff))
+        .route(
+            "/api/analysis/{name}/window",
+            post(analyze_window).get(get_window_analysis),
+        )
+        .route_layer(from_fn_with_state(
+            Duration::from_secs(config.streaming_request_timeout_secs),
+            request_timeout,
+        ));
+
+    Router::new()
         .route("/api/system-stats", get(get_system_stats))
+        .route("/api/version", get(get_version))
+        .route("/api/health", get(get_health))
         .route("/api/qmdl-manifest", get(get_qmdl_manifest))
         .route("/api/start-recording", post(start_recording))
         .route("/api/stop-recording", post(stop_recording))
         .route("/api/delete-recording/{name}", post(delete_recording))
         .route("/api/delete-all-recordings", post(delete_all_recordings))
-        .route("/api/analysis-report/{name}", get(get_analysis_report))
+        .route("/api/recordings/delete", post(delete_matching_recordings))
         .route("/api/analysis", get(get_analysis_status))
-        .route("/api/analysis/{name}", post(start_analysis))
+        .route("/api/analysis/recent", get(get_recent_findings))
+        .route(
+            "/api/analysis/{name}",
+            post(start_analysis).delete(delete_analysis),
+        )
+        .route("/api/analysis/retry", post(retry_failed_analyses))
+        .route("/api/analysis/selftest", post(post_selftest))
+        .route("/api/analysis/{name}/retry", post(retry_failed_analysis))
+        .route("/api/analysis/{name}/summary", get(get_analysis_summary))
+        .route("/api/analysis/{name}/log", get(get_analysis_log))
+        .route("/api/analysis/{name}/rows/{index}", get(get_analysis_row))
+        .route("/api/recordings/{name}/deadletter", get(get_dead_letters))
         .route("/api/config", get(get_config))
         .route("/api/config", post(set_config))
+        .route_layer(from_fn_with_state(
+            Duration::from_secs(config.request_timeout_secs),
+            request_timeout,
+        ))
+        .merge(streaming_routes)
+        // only the API is protected, so the web UI can still load and ask for
+        // the token
+        .route_layer(from_fn_with_state(
+            ApiAuth::from_config(config),
+            require_api_token,
+        ))
         .route("/", get(|| async { Redirect::permanent("/index.html") }))
         .route("/{*path}", get(serve_static))
+        .layer(from_fn(request_id))
 }
 
 // Runs the axum server, taking all the elements needed to build up our
 // ServerState and a oneshot Receiver that'll fire when it's time to shutdown
-// (i.e. user hit ctrl+c)
+// (i.e. user hit ctrl+c). It's served over TLS if a certificate is configured.
 async fn run_server(
     task_tracker: &TaskTracker,
     state: Arc<ServerState>,
+    tls_config: Option<RustlsConfig>,
     server_shutdown_rx: oneshot::Receiver<()>,
-) -> JoinHandle<()> {
+) -> Result<JoinHandle<()>, RayhunterError> {
     info!("spinning up server");
-    let addr = SocketAddr::from(([0, 0, 0, 0], state.config.port));
-    let listener = TcpListener::bind(&addr).await.unwrap();
-    let app = get_router().with_state(state);
+    let addr = state.config.listen_address()?;
+    let listener = TcpListener::bind(&addr).await?;
+    let app = get_router(&state.config).with_state(state);
 
+    let Some(tls_config) = tls_config else {
+        info!("listening on http://{addr}");
+        return Ok(task_tracker.spawn(async move {
+            info!("The orca is hunting for stingrays...");
+            axum::serve(listener, app)
+                .with_graceful_shutdown(server_shutdown_signal(server_shutdown_rx))
+                .await
+                .unwrap();
+        }));
+    };
+    info!("listening on https://{addr}");
+    let handle = axum_server::Handle::new();
+    let shutdown_handle = handle.clone();
     task_tracker.spawn(async move {
+        server_shutdown_signal(server_shutdown_rx).await;
+        shutdown_handle.graceful_shutdown(None);
+    });
+    let server = axum_server::from_tcp_rustls(listener.into_std()?, tls_config).handle(handle);
+    Ok(task_tracker.spawn(async move {
         info!("The orca is hunting for stingrays...");
-        axum::serve(listener, app)
-            .with_graceful_shutdown(server_shutdown_signal(server_shutdown_rx))
-            .await
-            .unwrap();
-    })
+        server.serve(app.into_make_service()).await.unwrap();
+    }))
 }
 
 async fn server_shutdown_signal(server_shutdown_rx: oneshot::Receiver<()>) {
@@ -131,6 +258,10 @@ fn run_shutdown_thread(
     server_shutdown_tx: oneshot::Sender<()>,
     maybe_ui_shutdown_tx: Option<oneshot::Sender<()>>,
     maybe_key_input_shutdown_tx: Option<oneshot::Sender<()>>,
+    maybe_compaction_shutdown_tx: Option<oneshot::Sender<()>>,
+    maybe_retention_shutdown_tx: Option<oneshot::Sender<()>>,
+    maybe_finding_socket_shutdown_tx: Option<oneshot::Sender<()>>,
+    maybe_notification_retry_shutdown_tx: Option<oneshot::Sender<()>>,
     qmdl_store_lock: Arc<RwLock<RecordingStore>>,
     analysis_tx: Sender<AnalysisCtrlMessage>,
 ) -> JoinHandle<Result<(), RayhunterError>> {
@@ -170,6 +301,18 @@ fn run_shutdown_thread(
         if let Some(key_input_shutdown_tx) = maybe_key_input_shutdown_tx {
             let _ = key_input_shutdown_tx.send(());
         }
+        if let Some(compaction_shutdown_tx) = maybe_compaction_shutdown_tx {
+            let _ = compaction_shutdown_tx.send(());
+        }
+        if let Some(retention_shutdown_tx) = maybe_retention_shutdown_tx {
+            let _ = retention_shutdown_tx.send(());
+        }
+        if let Some(finding_socket_shutdown_tx) = maybe_finding_socket_shutdown_tx {
+            let _ = finding_socket_shutdown_tx.send(());
+        }
+        if let Some(notification_retry_shutdown_tx) = maybe_notification_retry_shutdown_tx {
+            let _ = notification_retry_shutdown_tx.send(());
+        }
         diag_device_sender
             .send(DiagDeviceCtrlMessage::Exit)
             .await
@@ -182,9 +325,33 @@ fn run_shutdown_thread(
     })
 }
 
+// Like env_logger's default format, but with the ID of the HTTP request being
+// handled, and the recording being analyzed, if any
+fn init_logging() {
+    env_logger::Builder::from_default_env()
+        .format(|buf, record| {
+            let request = current_request_id()
+                .map(|request_id| format!(" request={request_id}"))
+                .unwrap_or_default();
+            let recording = analysis_log::current_recording()
+                .map(|recording| format!(" recording={recording}"))
+                .unwrap_or_default();
+            let line = format!(
+                "[{} {:<5} {}{request}{recording}] {}",
+                Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+                record.level(),
+                record.target(),
+                record.args()
+            );
+            analysis_log::capture(&line);
+            writeln!(buf, "{line}")
+        })
+        .init();
+}
+
 #[tokio::main]
 async fn main() -> Result<(), RayhunterError> {
-    env_logger::init();
+    init_logging();
 
     let args = parse_args();
 
@@ -203,14 +370,37 @@ async fn run_with_config(
     // TaskTrackers give us an interface to spawn tokio threads, and then
     // eventually await all of them ending
     let task_tracker = TaskTracker::new();
-    println!("R A Y H U N T E R 🐳");
+    // stdout is for nothing but findings when they're published there
+    if !config.findings_stdout {
+        println!("R A Y H U N T E R 🐳");
+    }
+
+    let tls_config = load_tls_config(&config).await?;
+
+    // every harness the daemon builds shares the cell_distance analyzer's
+    // database, so read it now, off the runtime, rather than in whichever
+    // request or thread first builds one
+    let cell_distance: CellDistanceSettings = config.analyzers.get_settings();
+    if config.analyzers.cell_distance && cell_distance.device_location.is_some() {
+        load_configured_cell_database(cell_distance.cell_database_path).await;
+    }
 
-    let store = init_qmdl_store(&config).await?;
-    let analysis_status = AnalysisStatus::new(&store);
+    let mut store = init_qmdl_store(&config).await?;
+    store.device = Some(config.device.clone());
+    let analysis_status = AnalysisStatus::new(
+        &store,
+        config.analyze_on_startup,
+        config.analysis_status_max_finished,
+    );
+    let startup_analysis_queued = analysis_status.has_queued();
     let qmdl_store_lock = Arc::new(RwLock::new(store));
     let (diag_tx, diag_rx) = mpsc::channel::<DiagDeviceCtrlMessage>(1);
     let (ui_update_tx, ui_update_rx) = mpsc::channel::<display::DisplayState>(1);
     let (analysis_tx, analysis_rx) = mpsc::channel::<AnalysisCtrlMessage>(5);
+    let (analysis_update_tx, _) = broadcast::channel(ANALYSIS_UPDATE_CHANNEL_CAPACITY);
+    let recent_findings = RecentFindings::new(RECENT_FINDINGS_CAPACITY);
+    analysis_log::set_capacity(config.analysis_log_buffer_lines);
+    let capture_pressure = CapturePressure::from_config(&config);
     let mut maybe_ui_shutdown_tx = None;
     let mut maybe_key_input_shutdown_tx = None;
     if !config.debug_mode {
@@ -232,7 +422,13 @@ async fn run_with_config(
             ui_update_tx.clone(),
             qmdl_store_lock.clone(),
             analysis_tx.clone(),
+            analysis_update_tx.clone(),
             config.analyzers.clone(),
+            FindingSyslog::from_config(&config),
+            recent_findings.clone(),
+            config.dead_letter_log,
+            config.analysis_flush_max_interval(),
+            capture_pressure.clone(),
         );
         info!("Starting UI");
 
@@ -258,13 +454,115 @@ async fn run_with_config(
     let (daemon_restart_tx, daemon_restart_rx) = oneshot::channel::<()>();
     let (server_shutdown_tx, server_shutdown_rx) = oneshot::channel::<()>();
     let analysis_status_lock = Arc::new(RwLock::new(analysis_status));
+    let analysis_runtime =
+        build_analysis_runtime(&config).map_err(RayhunterError::AnalysisRuntimeError)?;
+    let post_analysis_hook = PostAnalysisHook::from_config(&config);
+    let notification_queue = match &post_analysis_hook {
+        Some(_) if config.notification_queue_max_len > 0 => Some(
+            NotificationQueue::load(&config.qmdl_store_path, config.notification_queue_max_len)
+                .await,
+        ),
+        _ => None,
+    };
+    let mut maybe_notification_retry_shutdown_tx = None;
+    if let (Some(hook), Some(queue)) = (&post_analysis_hook, &notification_queue) {
+        info!("Starting notification retry thread");
+        let (notification_retry_shutdown_tx, notification_retry_shutdown_rx) = oneshot::channel();
+        maybe_notification_retry_shutdown_tx = Some(notification_retry_shutdown_tx);
+        run_notification_retry_thread(
+            &task_tracker,
+            hook.clone(),
+            queue.clone(),
+            config.notification_retry_interval(),
+            notification_retry_shutdown_rx,
+        );
+    }
     run_analysis_thread(
         &task_tracker,
         analysis_rx,
         qmdl_store_lock.clone(),
         analysis_status_lock.clone(),
         config.analyzers.clone(),
+        analysis_update_tx.clone(),
+        post_analysis_hook,
+        notification_queue.clone(),
+        FindingSyslog::from_config(&config),
+        recent_findings.clone(),
+        config.in_memory_analysis_fallback,
+        config.dead_letter_log,
+        DecodeErrorTolerance::from_config(&config),
+        config.analysis_max_runtime(),
+        Duration::from_secs(config.analysis_start_delay_secs),
+        config.analysis_flush_max_interval(),
+        config.analysis_sample_every,
+        QueueGuard::from_config(&config),
+        AnalysisPriorities::from_config(&config),
+        capture_pressure,
+        analysis_runtime.as_ref().map(AnalysisRuntime::handle),
     );
+    if startup_analysis_queued {
+        info!("Queueing analysis of recordings without an analysis report");
+        if analysis_tx
+            .send(AnalysisCtrlMessage::NewFilesQueued(None))
+            .await
+            .is_err()
+        {
+            error!("failed to start analysis of unanalyzed recordings");
+        }
+    }
+    let compaction_status_lock = Arc::new(RwLock::new(CompactionStatus::new(
+        config.analysis_compaction_interval_secs,
+    )));
+    let mut maybe_compaction_shutdown_tx = None;
+    if let Some(interval_secs) = config.analysis_compaction_interval_secs {
+        info!("Starting analysis compaction thread");
+        let (compaction_shutdown_tx, compaction_shutdown_rx) = oneshot::channel();
+        maybe_compaction_shutdown_tx = Some(compaction_shutdown_tx);
+        run_compaction_thread(
+            &task_tracker,
+            Duration::from_secs(interval_secs),
+            qmdl_store_lock.clone(),
+            analysis_status_lock.clone(),
+            compaction_status_lock.clone(),
+            compaction_shutdown_rx,
+        );
+    }
+    let mut maybe_retention_shutdown_tx = None;
+    if let Some(policy) = RetentionPolicy::new(
+        config.qmdl_retention_max_age_days,
+        config.qmdl_retention_max_total_bytes,
+        config.analysis_retention_max_age_days,
+    ) {
+        info!("Starting retention thread with {policy:?}");
+        let (retention_shutdown_tx, retention_shutdown_rx) = oneshot::channel();
+        maybe_retention_shutdown_tx = Some(retention_shutdown_tx);
+        run_retention_thread(
+            &task_tracker,
+            policy,
+            qmdl_store_lock.clone(),
+            analysis_status_lock.clone(),
+            retention_shutdown_rx,
+        );
+    }
+    let mut maybe_finding_socket_shutdown_tx = None;
+    if config.finding_socket_path.is_some() || config.findings_stdout {
+        if let Some(path) = &config.finding_socket_path {
+            info!("Publishing findings to {path}");
+        }
+        if config.findings_stdout {
+            info!("Publishing findings to stdout");
+        }
+        let (finding_socket_shutdown_tx, finding_socket_shutdown_rx) = oneshot::channel();
+        maybe_finding_socket_shutdown_tx = Some(finding_socket_shutdown_tx);
+        run_finding_stream_thread(
+            &task_tracker,
+            config.finding_socket_path.as_ref().map(PathBuf::from),
+            config.findings_stdout,
+            analysis_update_tx.subscribe(),
+            finding_socket_shutdown_rx,
+            config.quiet_hours().ok().flatten(),
+        );
+    }
     let should_restart_flag = Arc::new(AtomicBool::new(false));
 
     run_shutdown_thread(
@@ -275,9 +573,14 @@ async fn run_with_config(
         server_shutdown_tx,
         maybe_ui_shutdown_tx,
         maybe_key_input_shutdown_tx,
+        maybe_compaction_shutdown_tx,
+        maybe_retention_shutdown_tx,
+        maybe_finding_socket_shutdown_tx,
+        maybe_notification_retry_shutdown_tx,
         qmdl_store_lock.clone(),
         analysis_tx.clone(),
     );
+    let summary_read_concurrency = config.summary_read_concurrency.max(1);
     let state = Arc::new(ServerState {
         config_path: args.config_path.clone(),
         config,
@@ -286,9 +589,14 @@ async fn run_with_config(
         ui_update_sender: ui_update_tx,
         analysis_status_lock,
         analysis_sender: analysis_tx,
+        analysis_update_sender: analysis_update_tx,
+        compaction_status_lock,
         daemon_restart_tx: Arc::new(RwLock::new(Some(daemon_restart_tx))),
+        summary_read_permits: Arc::new(Semaphore::new(summary_read_concurrency)),
+        recent_findings,
+        notification_queue,
     });
-    run_server(&task_tracker, state, server_shutdown_rx).await;
+    run_server(&task_tracker, state, tls_config, server_shutdown_rx).await?;
 
     task_tracker.close();
     task_tracker.wait().await;
@@ -304,6 +612,6 @@ mod test {
     #[test]
     fn test_get_router() {
         // assert that creating the router does not panic from invalid route patterns.
-        let _ = get_router();
+        let _ = get_router(&config::Config::default());
     }
 }
@@ -0,0 +1,345 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::BoxError;
+use axum::body::{Body, Bytes};
+use axum::extract::{Request, State};
+use axum::http::header::{AUTHORIZATION, COOKIE, WWW_AUTHENTICATE};
+use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use futures::StreamExt;
+use log::warn;
+
+use crate::config::Config;
+
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// Request IDs are this process's start time plus a counter, which keeps them
+// unique across restarts without needing a source of randomness
+static REQUEST_ID_PREFIX: LazyLock<String> = LazyLock::new(|| {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!("{:x}", started.as_secs())
+});
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+// The ID of the request the current task is handling, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(String::clone).ok()
+}
+
+// Runs `future` as part of handling the given request, so that everything it
+// logs is tagged with the request's ID
+pub async fn with_request_id<F: Future>(request_id: Option<String>, future: F) -> F::Output {
+    match request_id {
+        Some(request_id) => REQUEST_ID.scope(request_id, future).await,
+        None => future.await,
+    }
+}
+
+// Reuse an ID given by a client or proxy in front of us, as long as it's
+// something sensible to put in a log line
+fn client_request_id(request: &Request) -> Option<String> {
+    let request_id = request.headers().get(&REQUEST_ID_HEADER)?.to_str().ok()?;
+    let valid = !request_id.is_empty()
+        && request_id.len() <= 64
+        && request_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    valid.then(|| request_id.to_string())
+}
+
+// Tags each request with an ID, which is echoed back in the X-Request-Id
+// response header and included in every log line written while handling it
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let request_id = client_request_id(&request).unwrap_or_else(|| {
+        let counter = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+        format!("{}-{counter}", *REQUEST_ID_PREFIX)
+    });
+    let header_value = HeaderValue::from_str(&request_id);
+    let mut response = REQUEST_ID.scope(request_id, next.run(request)).await;
+    if let Ok(header_value) = header_value {
+        response
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER.clone(), header_value);
+    }
+    response
+}
+
+// Aborts a handler that takes longer than the given duration to produce a
+// response, returning a 504 to the client instead, and cuts off a response
+// body that goes that long without sending anything. Streaming handlers
+// return as soon as they've set up their body, so a stream that gets stuck is
+// only caught by the latter, while a large download that keeps making
+// progress is never cut off. Dropping the handler's future or its body also
+// drops anything it owns (open files, spawned stream halves, etc.), so
+// there's nothing else to clean up here. A duration of zero turns both off.
+pub async fn request_timeout(
+    State(timeout): State<Duration>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if timeout.is_zero() {
+        return next.run(request).await;
+    }
+    let path = request.uri().path().to_string();
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => with_body_idle_timeout(response, timeout, path),
+        Err(_) => {
+            warn!("request to {path} timed out after {timeout:?}");
+            (
+                StatusCode::GATEWAY_TIMEOUT,
+                format!("request timed out after {} seconds", timeout.as_secs()),
+            )
+                .into_response()
+        }
+    }
+}
+
+// Ends the response's body with an error if it goes longer than the timeout
+// between chunks. Its status has already been sent by then, so this makes
+// hyper abort the connection, which at least tells the client the body is
+// incomplete.
+fn with_body_idle_timeout(response: Response, timeout: Duration, path: String) -> Response {
+    let (parts, body) = response.into_parts();
+    let state = Some((body.into_data_stream(), path));
+    let body = futures::stream::unfold(state, move |state| async move {
+        let (mut data, path) = state?;
+        match tokio::time::timeout(timeout, data.next()).await {
+            Ok(Some(chunk)) => {
+                let chunk: Result<Bytes, BoxError> = chunk.map_err(Into::into);
+                Some((chunk, Some((data, path))))
+            }
+            Ok(None) => None,
+            Err(_) => {
+                warn!("response to {path} sent nothing for {timeout:?}, cutting it off");
+                let err = std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("response stalled for {} seconds", timeout.as_secs()),
+                );
+                Some((Err(err.into()), None))
+            }
+        }
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+// The web UI sends the token in this cookie, since browsers can't add headers
+// to plain links or websockets
+pub const API_TOKEN_COOKIE: &str = "rayhunter_api_token";
+
+#[derive(Clone)]
+pub struct ApiAuth {
+    token: Option<Arc<str>>,
+    protects_reads: bool,
+}
+
+impl ApiAuth {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            token: config.api_token.as_deref().map(Arc::from),
+            protects_reads: config.api_token_protects_reads,
+        }
+    }
+
+    fn protects(&self, method: &Method) -> bool {
+        self.protects_reads || !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+    }
+
+    // Whether the request carries the token, either as a bearer token or in
+    // the web UI's cookie
+    fn authorized(token: &str, headers: &HeaderMap) -> bool {
+        let bearer = headers
+            .get_all(AUTHORIZATION)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .filter_map(|value| value.strip_prefix("Bearer "));
+        let cookies = headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .filter(|(name, _)| *name == API_TOKEN_COOKIE)
+            .map(|(_, value)| value);
+        bearer
+            .chain(cookies)
+            .any(|candidate| constant_time_eq(candidate.trim().as_bytes(), token.as_bytes()))
+    }
+}
+
+// Compares without returning early, so the time taken doesn't tell how much
+// of a guessed token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Rejects API requests without the configured token with a 401. Only requests
+// that change something need it, unless api_token_protects_reads is set, and
+// nothing needs it if no token is configured.
+pub async fn require_api_token(
+    State(auth): State<ApiAuth>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(token) = &auth.token else {
+        return next.run(request).await;
+    };
+    if !auth.protects(request.method()) || ApiAuth::authorized(token, request.headers()) {
+        return next.run(request).await;
+    }
+    warn!(
+        "rejecting {} {} without a valid API token",
+        request.method(),
+        request.uri().path()
+    );
+    (
+        StatusCode::UNAUTHORIZED,
+        [(WWW_AUTHENTICATE, "Bearer")],
+        "missing or invalid API token",
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::Router;
+    use axum::middleware::from_fn_with_state;
+    use axum::routing::get;
+    use futures::stream;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn headers(name: HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_api_token_authorized() {
+        let token = "s3cret";
+        assert!(ApiAuth::authorized(
+            token,
+            &headers(AUTHORIZATION, "Bearer s3cret")
+        ));
+        assert!(ApiAuth::authorized(
+            token,
+            &headers(COOKIE, "other=1; rayhunter_api_token=s3cret")
+        ));
+        assert!(!ApiAuth::authorized(
+            token,
+            &headers(AUTHORIZATION, "Bearer s3cre")
+        ));
+        assert!(!ApiAuth::authorized(
+            token,
+            &headers(AUTHORIZATION, "Basic s3cret")
+        ));
+        assert!(!ApiAuth::authorized(
+            token,
+            &headers(COOKIE, "other_token=s3cret")
+        ));
+        assert!(!ApiAuth::authorized(token, &HeaderMap::new()));
+    }
+
+    fn timeout_router(timeout: Duration) -> Router {
+        Router::new()
+            .route(
+                "/hang",
+                get(|| async { std::future::pending::<()>().await }),
+            )
+            .route(
+                "/stall",
+                get(|| async {
+                    let partial = stream::once(async { Ok::<_, BoxError>("partial") });
+                    Body::from_stream(partial.chain(stream::pending()))
+                }),
+            )
+            .route(
+                "/slow",
+                get(|| async {
+                    // takes longer than the timeout overall, but never
+                    // between chunks
+                    let chunks = stream::unfold(0, |sent| async move {
+                        if sent == 4 {
+                            return None;
+                        }
+                        tokio::time::sleep(Duration::from_millis(30)).await;
+                        Some((Ok::<_, BoxError>("chunk"), sent + 1))
+                    });
+                    Body::from_stream(chunks)
+                }),
+            )
+            .route(
+                "/sleep",
+                get(|| async { tokio::time::sleep(Duration::from_millis(20)).await }),
+            )
+            .route_layer(from_fn_with_state(timeout, request_timeout))
+    }
+
+    async fn get_response(path: &str) -> Response {
+        get_response_with_timeout(path, Duration::from_millis(50)).await
+    }
+
+    async fn get_response_with_timeout(path: &str, timeout: Duration) -> Response {
+        let request = axum::http::Request::get(path).body(Body::empty()).unwrap();
+        timeout_router(timeout).oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let response = get_response("/hang").await;
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+
+        // a streaming body's status goes out right away, but the body itself
+        // is still cut off once it stalls
+        let response = get_response("/stall").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body().into_data_stream();
+        assert_eq!(&body.next().await.unwrap().unwrap()[..], b"partial");
+        let end = tokio::time::timeout(Duration::from_secs(5), body.next())
+            .await
+            .expect("stalled body wasn't cut off");
+        assert!(matches!(end, Some(Err(_))));
+
+        // one that keeps sending is left to finish
+        let response = get_response("/slow").await;
+        let mut body = response.into_body().into_data_stream();
+        let mut chunks = 0;
+        while let Some(chunk) = body.next().await {
+            assert_eq!(&chunk.unwrap()[..], b"chunk");
+            chunks += 1;
+        }
+        assert_eq!(chunks, 4);
+
+        // and a timeout of zero is no timeout at all
+        let response = get_response_with_timeout("/sleep", Duration::ZERO).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_api_token_protected_methods() {
+        let config = Config {
+            api_token: Some("s3cret".to_string()),
+            ..Config::default()
+        };
+        let auth = ApiAuth::from_config(&config);
+        assert!(!auth.protects(&Method::GET));
+        assert!(auth.protects(&Method::POST));
+        assert!(auth.protects(&Method::DELETE));
+
+        let config = Config {
+            api_token_protects_reads: true,
+            ..config
+        };
+        assert!(ApiAuth::from_config(&config).protects(&Method::GET));
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::{Mutex, Notify, oneshot};
+use tokio::time::{Instant, interval_at};
+use tokio_util::task::TaskTracker;
+
+use crate::hook::PostAnalysisHook;
+
+const QUEUE_FILE_NAME: &str = "notification_queue.json";
+
+// A notification the post-analysis hook failed to deliver
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedNotification {
+    pub recording: String,
+    // the analysis summary, exactly as it would have been passed to the hook
+    pub summary: serde_json::Value,
+    pub queued_at: DateTime<Utc>,
+}
+
+// Notifications waiting for the post-analysis hook to succeed again, kept in
+// the QMDL store so they survive a restart. A device that's offline (which
+// for a sensor left somewhere is common) makes a hook that sends findings on
+// to a webhook or ntfy fail, so rather than dropping those notifications,
+// they're queued and retried until the hook goes through. The queue holds at
+// most max_len notifications, dropping the oldest to make room. Cheap to
+// clone, and clones share the same queue.
+#[derive(Clone)]
+pub struct NotificationQueue {
+    path: PathBuf,
+    max_len: usize,
+    pending: Arc<Mutex<VecDeque<QueuedNotification>>>,
+    wake: Arc<Notify>,
+}
+
+impl NotificationQueue {
+    // Loads the queue left over from a previous run, if there is one. A queue
+    // file that can't be parsed is logged and started over, since losing a
+    // few notifications isn't worth refusing to start for.
+    pub async fn load<P: AsRef<Path>>(store_path: P, max_len: usize) -> Self {
+        let path = store_path.as_ref().join(QUEUE_FILE_NAME);
+        let mut pending: VecDeque<QueuedNotification> = match fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|err| {
+                error!(
+                    "failed to parse notification queue {}: {err}",
+                    path.display()
+                );
+                VecDeque::new()
+            }),
+            Err(_) => VecDeque::new(),
+        };
+        while pending.len() > max_len {
+            pending.pop_front();
+        }
+        if !pending.is_empty() {
+            info!("{} notification(s) still queued from before", pending.len());
+        }
+        NotificationQueue {
+            path,
+            max_len,
+            pending: Arc::new(Mutex::new(pending)),
+            wake: Arc::new(Notify::new()),
+        }
+    }
+
+    pub async fn len(&self) -> usize {
+        self.pending.lock().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.pending.lock().await.is_empty()
+    }
+
+    // Queues a notification, dropping the oldest one if the queue's full
+    pub async fn push(&self, recording: &str, summary: serde_json::Value) {
+        if self.max_len == 0 {
+            return;
+        }
+        let mut pending = self.pending.lock().await;
+        while pending.len() >= self.max_len {
+            let Some(dropped) = pending.pop_front() else {
+                break;
+            };
+            warn!(
+                "notification queue is full, dropping the one for {} queued at {}",
+                dropped.recording, dropped.queued_at
+            );
+        }
+        pending.push_back(QueuedNotification {
+            recording: recording.to_string(),
+            summary,
+            queued_at: Utc::now(),
+        });
+        self.save(&pending).await;
+    }
+
+    // Asks the retry thread to try delivering the queue now, rather than
+    // waiting for its next attempt
+    pub fn flush_soon(&self) {
+        self.wake.notify_one();
+    }
+
+    // Delivers queued notifications oldest first, stopping at the first one
+    // the hook fails on, so notifications go out in the order they were made
+    async fn flush(&self, hook: &PostAnalysisHook) {
+        let mut pending = self.pending.lock().await;
+        let mut delivered = 0;
+        while let Some(notification) = pending.front() {
+            let summary_json = serde_json::to_vec(&notification.summary).unwrap_or_default();
+            if !hook.deliver(&notification.recording, &summary_json).await {
+                break;
+            }
+            pending.pop_front();
+            delivered += 1;
+        }
+        if delivered > 0 {
+            info!(
+                "delivered {delivered} queued notification(s), {} still pending",
+                pending.len()
+            );
+            self.save(&pending).await;
+        }
+    }
+
+    async fn save(&self, pending: &VecDeque<QueuedNotification>) {
+        let json = match serde_json::to_vec(pending) {
+            Ok(json) => json,
+            Err(err) => {
+                error!("failed to serialize notification queue: {err}");
+                return;
+            }
+        };
+        let tmp_path = self.path.with_extension("json.new");
+        let result = match fs::write(&tmp_path, json).await {
+            Ok(()) => fs::rename(&tmp_path, &self.path).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            error!(
+                "failed to save notification queue to {}: {err}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+// Retries the queued notifications every retry_interval, and whenever one's
+// queued, so they go out soon after the device is back online
+pub fn run_notification_retry_thread(
+    task_tracker: &TaskTracker,
+    hook: PostAnalysisHook,
+    queue: NotificationQueue,
+    retry_interval: Duration,
+    mut shutdown_rx: oneshot::Receiver<()>,
+) {
+    task_tracker.spawn(async move {
+        let mut ticks = interval_at(Instant::now() + retry_interval, retry_interval);
+        loop {
+            tokio::select! {
+                _ = &mut shutdown_rx => return,
+                _ = ticks.tick() => {}
+                _ = queue.wake.notified() => {}
+            }
+            queue.flush(&hook).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_notification_queue() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = NotificationQueue::load(dir.path(), 2).await;
+        assert!(queue.is_empty().await);
+        for name in ["1", "2", "3"] {
+            queue
+                .push(name, serde_json::json!({ "warning_count": 1 }))
+                .await;
+        }
+        // the oldest is dropped to make room
+        assert_eq!(queue.len().await, 2);
+
+        // and what's left survives a restart
+        let reloaded = NotificationQueue::load(dir.path(), 2).await;
+        let recordings: Vec<String> = reloaded
+            .pending
+            .lock()
+            .await
+            .iter()
+            .map(|notification| notification.recording.clone())
+            .collect();
+        assert_eq!(recordings, vec!["2", "3"]);
+
+        // a smaller limit after a config change keeps the newest
+        let shrunk = NotificationQueue::load(dir.path(), 1).await;
+        assert_eq!(shrunk.pending.lock().await[0].recording, "3");
+    }
+}
@@ -8,7 +8,7 @@ use axum::http::header::CONTENT_TYPE;
 use axum::response::{IntoResponse, Response};
 use log::error;
 use rayhunter::diag::DataType;
-use rayhunter::gsmtap_parser;
+use rayhunter::gsmtap_parser::{self, ModemProfile};
 use rayhunter::pcap::GsmtapPcapWriter;
 use rayhunter::qmdl::QmdlReader;
 use std::sync::Arc;
@@ -44,9 +44,11 @@ pub async fn get_pcap(
     // the QMDL reader should stop at the last successfully written data chunk
     // (entry.size_bytes)
     let (reader, writer) = duplex(1024);
+    let modem_profile = state.config.analyzers.modem_profile.clone();
 
     tokio::spawn(async move {
-        if let Err(e) = generate_pcap_data(writer, qmdl_file, qmdl_size_bytes).await {
+        if let Err(e) = generate_pcap_data(writer, qmdl_file, qmdl_size_bytes, &modem_profile).await
+        {
             error!("failed to generate PCAP: {e:?}");
         }
     });
@@ -60,6 +62,7 @@ pub async fn generate_pcap_data<R, W>(
     writer: W,
     qmdl_file: R,
     qmdl_size_bytes: usize,
+    modem_profile: &ModemProfile,
 ) -> Result<(), Error>
 where
     W: AsyncWrite + Unpin + Send,
@@ -68,7 +71,7 @@ where
     let mut pcap_writer = GsmtapPcapWriter::new(writer).await?;
     pcap_writer.write_iface_header().await?;
 
-    let mut reader = QmdlReader::new(qmdl_file, Some(qmdl_size_bytes));
+    let mut reader = QmdlReader::new_maybe_compressed(qmdl_file, Some(qmdl_size_bytes)).await?;
     while let Some(container) = reader.get_next_messages_container().await? {
         if container.data_type != DataType::UserSpace {
             continue;
@@ -77,7 +80,7 @@ where
         for maybe_msg in container.into_messages() {
             match maybe_msg {
                 Ok(msg) => {
-                    let maybe_gsmtap_msg = gsmtap_parser::parse(msg)?;
+                    let maybe_gsmtap_msg = gsmtap_parser::parse_with_profile(msg, modem_profile)?;
                     if let Some((timestamp, gsmtap_msg)) = maybe_gsmtap_msg {
                         pcap_writer
                             .write_gsmtap_message(gsmtap_msg, timestamp)
@@ -1,9 +1,14 @@
-use std::io::{self, ErrorKind};
+use std::io::{self, BufRead, ErrorKind};
 use std::os::unix::fs::MetadataExt;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use chrono::{DateTime, Local};
 use log::{info, warn};
+use rayhunter::Device;
+use rayhunter::analysis::analyzer::CaptureMetadata;
+use rayhunter::diag::DiagLayoutVersions;
+use rayhunter::qmdl::QmdlInput;
 use rayhunter::util::RuntimeMetadata;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -12,16 +17,25 @@ use tokio::{
     io::AsyncWriteExt,
 };
 
+// Tells apart the temporary files of window analyses saved at the same time
+static NEXT_TEMP_ID: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Debug, Error)]
 pub enum RecordingStoreError {
     #[error("Can't close an entry when there's no current entry")]
     NoCurrentEntry,
     #[error("An entry with that name doesn't exist")]
     NoSuchEntryError,
+    #[error("An entry with that name already exists")]
+    EntryExistsError,
+    #[error("Invalid entry name {0:?}, only letters, numbers, '-' and '_' are allowed")]
+    InvalidEntryNameError(String),
     #[error("Couldn't create file: {0}")]
     CreateFileError(tokio::io::Error),
     #[error("Couldn't read file: {0}")]
     ReadFileError(tokio::io::Error),
+    #[error("Analysis directory not writable, check the storage isn't full or read-only: {0}")]
+    AnalysisNotWritable(tokio::io::Error),
     #[error("Couldn't delete file: {0}")]
     DeleteFileError(tokio::io::Error),
     #[error("Couldn't open directory at path: {0}")]
@@ -32,12 +46,22 @@ pub enum RecordingStoreError {
     WriteManifestError(tokio::io::Error),
     #[error("Couldn't parse QMDL store manifest file: {0}")]
     ParseManifestError(toml::de::Error),
+    #[error(
+        "This recording's QMDL file was deleted by the retention policy, only its analysis is left"
+    )]
+    QmdlPrunedError,
+    #[error(
+        "This recording shares its files with a duplicate, so it can't have a paired QMDL file of its own"
+    )]
+    SharedFilesError,
 }
 
 pub struct RecordingStore {
     pub path: PathBuf,
     pub manifest: Manifest,
     pub current_entry: Option<usize>, // index into manifest
+    // the device new recordings are made on, if it's known
+    pub device: Option<Device>,
 }
 
 #[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
@@ -54,6 +78,45 @@ pub struct ManifestEntry {
     pub rayhunter_version: Option<String>,
     pub system_os: Option<String>,
     pub arch: Option<String>,
+    #[serde(default)]
+    pub diag_versions: DiagLayoutVersions,
+    // SHA-256 of the QMDL file, only computed for imported recordings
+    pub qmdl_sha256: Option<String>,
+    // If this entry was imported with the same contents as an existing entry,
+    // the name of the entry whose files it shares
+    pub duplicate_of: Option<String>,
+    // Free-form notes given when importing, e.g. which tool made the capture
+    pub notes: Option<String>,
+    // Whether the analysis file has been gzipped by the background compaction
+    // task
+    #[serde(default)]
+    pub analysis_compressed: bool,
+    // Whether the retention policy has deleted the QMDL file, leaving only
+    // the analysis
+    #[serde(default)]
+    pub qmdl_pruned: bool,
+    // The device the recording was made on, which isn't known for imported
+    // recordings or ones made by older versions
+    #[serde(default)]
+    pub device: Option<Device>,
+    // The size of the second QMDL file of a recording that was captured as
+    // two streams (e.g. control and user plane), if it has one. The two are
+    // analyzed together, interleaved by timestamp.
+    #[serde(default)]
+    pub paired_qmdl_size_bytes: Option<usize>,
+    // Whether the recording was imported rather than made on this device.
+    // Manifests from older versions don't say, so their imports count as
+    // made here.
+    #[serde(default)]
+    pub imported: bool,
+}
+
+// Optional details about a recording being imported
+#[derive(Debug, Default)]
+pub struct ImportMetadata {
+    pub name: Option<String>,
+    pub start_time: Option<DateTime<Local>>,
+    pub notes: Option<String>,
 }
 
 #[derive(PartialEq, Eq)]
@@ -63,7 +126,7 @@ pub enum EntryType {
 }
 
 impl ManifestEntry {
-    fn new() -> Self {
+    pub fn new() -> Self {
         let now = Local::now();
         let metadata = RuntimeMetadata::new();
         ManifestEntry {
@@ -74,20 +137,104 @@ impl ManifestEntry {
             rayhunter_version: Some(metadata.rayhunter_version),
             system_os: Some(metadata.system_os),
             arch: Some(metadata.arch),
+            diag_versions: DiagLayoutVersions::default(),
+            qmdl_sha256: None,
+            duplicate_of: None,
+            notes: None,
+            analysis_compressed: false,
+            qmdl_pruned: false,
+            device: None,
+            paired_qmdl_size_bytes: None,
+            imported: false,
         }
     }
 
+    // What the analysis of this recording should say about where it came
+    // from
+    pub fn capture_metadata(&self) -> CaptureMetadata {
+        CaptureMetadata {
+            recording: Some(self.name.clone()),
+            device: self.device.clone(),
+            start_time: Some(self.start_time.fixed_offset()),
+            last_message_time: self.last_message_time.map(|time| time.fixed_offset()),
+            qmdl_size_bytes: Some(self.qmdl_size_bytes),
+            recorded_with: self.rayhunter_version.clone(),
+            system_os: self.system_os.clone(),
+            arch: self.arch.clone(),
+            diag_versions: self.diag_versions.clone(),
+            notes: self.notes.clone(),
+        }
+    }
+
+    // Duplicate entries don't have files of their own, so their paths point at
+    // the original entry's files
+    pub fn file_stem(&self) -> &str {
+        self.duplicate_of.as_deref().unwrap_or(&self.name)
+    }
+
     pub fn get_qmdl_filepath<P: AsRef<Path>>(&self, path: P) -> PathBuf {
-        let mut filepath = path.as_ref().join(&self.name);
+        let mut filepath = path.as_ref().join(self.file_stem());
         filepath.set_extension("qmdl");
         filepath
     }
 
-    pub fn get_analysis_filepath<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+    // Paired files belong to the entry itself, since entries that share files
+    // can't have them
+    pub fn get_paired_qmdl_filepath<P: AsRef<Path>>(&self, path: P) -> PathBuf {
         let mut filepath = path.as_ref().join(&self.name);
-        filepath.set_extension("ndjson");
+        filepath.set_extension("paired.qmdl");
+        filepath
+    }
+
+    pub fn get_analysis_filepath<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let mut filepath = path.as_ref().join(self.file_stem());
+        if self.analysis_compressed {
+            filepath.set_extension("ndjson.gz");
+        } else {
+            filepath.set_extension("ndjson");
+        }
+        filepath
+    }
+
+    // The sidecar holding the offsets of each line of the (uncompressed)
+    // analysis file
+    pub fn get_analysis_index_filepath<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let mut filepath = path.as_ref().join(self.file_stem());
+        filepath.set_extension("ndjson.idx");
         filepath
     }
+
+    pub fn get_dead_letter_filepath<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let mut filepath = path.as_ref().join(self.file_stem());
+        filepath.set_extension("deadletter.ndjson");
+        filepath
+    }
+
+    // Where the report of the last analysis of a time window of this entry
+    // goes, so it doesn't replace the full one
+    pub fn get_window_analysis_filepath<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let mut filepath = path.as_ref().join(self.file_stem());
+        filepath.set_extension("window.ndjson");
+        filepath
+    }
+
+    // Whether this entry has an analysis file that at least starts with a
+    // report's metadata. Only used at startup, so blocking is fine.
+    pub fn has_valid_analysis<P: AsRef<Path>>(&self, path: P) -> bool {
+        let Ok(file) = std::fs::File::open(self.get_analysis_filepath(path)) else {
+            return false;
+        };
+        // compaction only ever produces files from complete analyses
+        if self.analysis_compressed {
+            return file.metadata().is_ok_and(|metadata| metadata.len() > 0);
+        }
+        let mut metadata_line = String::new();
+        match std::io::BufReader::new(file).read_line(&mut metadata_line) {
+            Ok(0) | Err(_) => false,
+            Ok(_) => serde_json::from_str::<serde_json::Value>(&metadata_line)
+                .is_ok_and(|metadata| metadata.is_object()),
+        }
+    }
 }
 
 impl RecordingStore {
@@ -119,6 +266,7 @@ impl RecordingStore {
             path,
             manifest,
             current_entry: None,
+            device: None,
         })
     }
 
@@ -138,6 +286,7 @@ impl RecordingStore {
                 entries: Vec::new(),
             },
             current_entry: None,
+            device: None,
         };
 
         store.write_manifest().await?;
@@ -171,6 +320,10 @@ impl RecordingStore {
             }
 
             let stem = filename.trim_end_matches(".qmdl");
+            // picked up along with the entry they're paired with
+            if stem.ends_with(".paired") {
+                continue;
+            }
             let Ok(start_timestamp) = stem.parse::<i64>() else {
                 warn!("QMDL file has invalid name {os_filename:?}, skipping");
                 continue;
@@ -194,6 +347,12 @@ impl RecordingStore {
                 continue;
             };
 
+            let paired_qmdl_size_bytes =
+                fs::metadata(path.as_ref().join(format!("{stem}.paired.qmdl")))
+                    .await
+                    .ok()
+                    .map(|metadata| metadata.size() as usize);
+
             info!("successfully recovered QMDL entry {os_filename:?}!");
             manifest_entries.push(ManifestEntry {
                 name: stem.to_string(),
@@ -203,6 +362,15 @@ impl RecordingStore {
                 rayhunter_version: None,
                 system_os: None,
                 arch: None,
+                diag_versions: DiagLayoutVersions::default(),
+                qmdl_sha256: None,
+                duplicate_of: None,
+                notes: None,
+                analysis_compressed: false,
+                qmdl_pruned: false,
+                device: None,
+                paired_qmdl_size_bytes,
+                imported: false,
             });
         }
 
@@ -215,6 +383,7 @@ impl RecordingStore {
                 entries: manifest_entries,
             },
             current_entry: None,
+            device: None,
         };
         store.write_manifest().await?;
 
@@ -240,7 +409,8 @@ impl RecordingStore {
         if self.current_entry.is_some() {
             self.close_current_entry().await?;
         }
-        let new_entry = ManifestEntry::new();
+        let mut new_entry = ManifestEntry::new();
+        new_entry.device = self.device.clone();
         let qmdl_filepath = new_entry.get_qmdl_filepath(&self.path);
         let qmdl_file = File::create(&qmdl_filepath)
             .await
@@ -255,21 +425,140 @@ impl RecordingStore {
         Ok((qmdl_file, analysis_file))
     }
 
+    // Adds an already written QMDL file (e.g. one uploaded to us) as a new,
+    // closed entry. If an existing entry has the same contents, the uploaded
+    // file is removed and the new entry is marked as a duplicate sharing the
+    // existing entry's files. Returns the index of the new entry.
+    pub async fn import_entry(
+        &mut self,
+        qmdl_path: &Path,
+        qmdl_sha256: String,
+        qmdl_size_bytes: usize,
+        metadata: ImportMetadata,
+    ) -> Result<usize, RecordingStoreError> {
+        let mut new_entry = ManifestEntry::new();
+        if let Some(name) = metadata.name {
+            // the name doubles as the entry's filename
+            let valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid {
+                return Err(RecordingStoreError::InvalidEntryNameError(name));
+            }
+            if self.entry_for_name(&name).is_some() {
+                return Err(RecordingStoreError::EntryExistsError);
+            }
+            new_entry.name = name;
+        } else {
+            // several imports can happen within the same second, so bump the
+            // timestamp-based name until it's unique
+            let mut timestamp = new_entry.start_time.timestamp();
+            while self.entry_for_name(&new_entry.name).is_some() {
+                timestamp += 1;
+                new_entry.name = format!("{timestamp}");
+            }
+        }
+        if let Some(start_time) = metadata.start_time {
+            new_entry.start_time = start_time;
+        }
+        new_entry.notes = metadata.notes;
+        new_entry.imported = true;
+        new_entry.qmdl_size_bytes = qmdl_size_bytes;
+        new_entry.duplicate_of = self
+            .manifest
+            .entries
+            .iter()
+            .find(|entry| entry.qmdl_sha256.as_ref() == Some(&qmdl_sha256))
+            .map(|entry| entry.file_stem().to_string());
+        new_entry.qmdl_sha256 = Some(qmdl_sha256);
+        if new_entry.duplicate_of.is_some() {
+            remove_file_if_exists(qmdl_path)
+                .await
+                .map_err(RecordingStoreError::DeleteFileError)?;
+        } else {
+            fs::rename(qmdl_path, new_entry.get_qmdl_filepath(&self.path))
+                .await
+                .map_err(RecordingStoreError::CreateFileError)?;
+            File::create(new_entry.get_analysis_filepath(&self.path))
+                .await
+                .map_err(RecordingStoreError::CreateFileError)?;
+        }
+        self.manifest.entries.push(new_entry);
+        self.write_manifest().await?;
+        Ok(self.manifest.entries.len() - 1)
+    }
+
     // Returns the corresponding QMDL file for a given entry
     pub async fn open_entry_qmdl(&self, entry_index: usize) -> Result<File, RecordingStoreError> {
         let entry = &self.manifest.entries[entry_index];
+        if entry.qmdl_pruned {
+            return Err(RecordingStoreError::QmdlPrunedError);
+        }
         File::open(entry.get_qmdl_filepath(&self.path))
             .await
             .map_err(RecordingStoreError::ReadFileError)
     }
 
-    // Returns the corresponding QMDL file for a given entry
+    // Returns the given entry's paired QMDL file, if it has one
+    pub async fn open_entry_paired_qmdl(
+        &self,
+        entry_index: usize,
+    ) -> Result<Option<File>, RecordingStoreError> {
+        let entry = &self.manifest.entries[entry_index];
+        if entry.paired_qmdl_size_bytes.is_none() {
+            return Ok(None);
+        }
+        if entry.qmdl_pruned {
+            return Err(RecordingStoreError::QmdlPrunedError);
+        }
+        File::open(entry.get_paired_qmdl_filepath(&self.path))
+            .await
+            .map(Some)
+            .map_err(RecordingStoreError::ReadFileError)
+    }
+
+    // Moves an already written QMDL file into place as the given entry's
+    // paired file, replacing any it already had. The entry has to have files
+    // of its own, since its analysis will cover both.
+    pub async fn pair_entry_qmdl(
+        &mut self,
+        entry_index: usize,
+        qmdl_path: &Path,
+        qmdl_size_bytes: usize,
+    ) -> Result<(), RecordingStoreError> {
+        let entry = &self.manifest.entries[entry_index];
+        if entry.qmdl_pruned {
+            return Err(RecordingStoreError::QmdlPrunedError);
+        }
+        let shares_files = self
+            .manifest
+            .entries
+            .iter()
+            .filter(|other| other.file_stem() == entry.file_stem())
+            .count()
+            > 1;
+        if shares_files {
+            return Err(RecordingStoreError::SharedFilesError);
+        }
+        fs::rename(qmdl_path, entry.get_paired_qmdl_filepath(&self.path))
+            .await
+            .map_err(RecordingStoreError::CreateFileError)?;
+        self.manifest.entries[entry_index].paired_qmdl_size_bytes = Some(qmdl_size_bytes);
+        self.write_manifest().await
+    }
+
+    // Returns the corresponding analysis file for a given entry, decompressing
+    // it if it's been compacted
     pub async fn open_entry_analysis(
         &self,
         entry_index: usize,
-    ) -> Result<File, RecordingStoreError> {
+    ) -> Result<QmdlInput<File>, RecordingStoreError> {
         let entry = &self.manifest.entries[entry_index];
-        File::open(entry.get_analysis_filepath(&self.path))
+        let file = File::open(entry.get_analysis_filepath(&self.path))
+            .await
+            .map_err(RecordingStoreError::ReadFileError)?;
+        QmdlInput::detect(file)
             .await
             .map_err(RecordingStoreError::ReadFileError)
     }
@@ -278,16 +567,153 @@ impl RecordingStore {
         &mut self,
         entry_index: usize,
     ) -> Result<File, RecordingStoreError> {
+        let entry = &self.manifest.entries[entry_index];
+        if entry.analysis_compressed {
+            // a fresh analysis is written uncompressed, so the compacted
+            // file is stale
+            let compressed_path = entry.get_analysis_filepath(&self.path);
+            let file_stem = entry.file_stem().to_string();
+            self.set_analysis_compressed(&file_stem, false);
+            self.write_manifest().await?;
+            remove_file_if_exists(&compressed_path)
+                .await
+                .map_err(|e| analysis_write_error(e, RecordingStoreError::DeleteFileError))?;
+        }
         let entry = &self.manifest.entries[entry_index];
         let file = OpenOptions::new()
             .write(true)
+            .create(true)
             .truncate(true)
             .open(entry.get_analysis_filepath(&self.path))
             .await
-            .map_err(RecordingStoreError::ReadFileError)?;
+            .map_err(|e| analysis_write_error(e, RecordingStoreError::CreateFileError))?;
         Ok(file)
     }
 
+    // Deletes the given entry's analysis, along with its line index and
+    // dead letters, leaving the QMDL alone so it can be analyzed again
+    pub async fn delete_entry_analysis(
+        &mut self,
+        entry_index: usize,
+    ) -> Result<(), RecordingStoreError> {
+        let entry = &self.manifest.entries[entry_index];
+        let analysis_filepath = entry.get_analysis_filepath(&self.path);
+        let analysis_index_filepath = entry.get_analysis_index_filepath(&self.path);
+        let dead_letter_filepath = entry.get_dead_letter_filepath(&self.path);
+        if entry.analysis_compressed {
+            let file_stem = entry.file_stem().to_string();
+            self.set_analysis_compressed(&file_stem, false);
+            self.write_manifest().await?;
+        }
+        for filepath in [
+            analysis_filepath,
+            analysis_index_filepath,
+            dead_letter_filepath,
+        ] {
+            remove_file_if_exists(&filepath)
+                .await
+                .map_err(RecordingStoreError::DeleteFileError)?;
+        }
+        Ok(())
+    }
+
+    // Opens the given entry's dead-letter file for writing, dropping whatever
+    // an earlier analysis recorded there
+    pub async fn clear_and_open_entry_dead_letters(
+        &self,
+        entry_index: usize,
+    ) -> Result<File, RecordingStoreError> {
+        let entry = &self.manifest.entries[entry_index];
+        File::create(entry.get_dead_letter_filepath(&self.path))
+            .await
+            .map_err(RecordingStoreError::CreateFileError)
+    }
+
+    pub async fn open_entry_dead_letters(
+        &self,
+        entry_index: usize,
+    ) -> Result<File, RecordingStoreError> {
+        let entry = &self.manifest.entries[entry_index];
+        File::open(entry.get_dead_letter_filepath(&self.path))
+            .await
+            .map_err(RecordingStoreError::ReadFileError)
+    }
+
+    // Saves the report of a time window's analysis of the given entry under
+    // a temporary name first, so that a reader or another window's analysis
+    // never sees half of it
+    pub async fn replace_entry_window_analysis(
+        &self,
+        entry_index: usize,
+        report: &str,
+    ) -> Result<(), RecordingStoreError> {
+        let entry = &self.manifest.entries[entry_index];
+        let window_analysis_path = entry.get_window_analysis_filepath(&self.path);
+        let temp_id = NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed);
+        let mut temp_path = window_analysis_path.clone();
+        temp_path.as_mut_os_string().push(format!(".{temp_id}.tmp"));
+        fs::write(&temp_path, report)
+            .await
+            .map_err(RecordingStoreError::CreateFileError)?;
+        if let Err(e) = fs::rename(&temp_path, &window_analysis_path).await {
+            let _ = fs::remove_file(&temp_path).await;
+            return Err(RecordingStoreError::CreateFileError(e));
+        }
+        Ok(())
+    }
+
+    pub async fn open_entry_window_analysis(
+        &self,
+        entry_index: usize,
+    ) -> Result<File, RecordingStoreError> {
+        let entry = &self.manifest.entries[entry_index];
+        File::open(entry.get_window_analysis_filepath(&self.path))
+            .await
+            .map_err(RecordingStoreError::ReadFileError)
+    }
+
+    // Replaces the given entry's analysis file with a gzipped copy of it at
+    // `compressed_path`
+    pub async fn replace_entry_analysis_compressed(
+        &mut self,
+        entry_index: usize,
+        compressed_path: &Path,
+    ) -> Result<(), RecordingStoreError> {
+        let entry = &self.manifest.entries[entry_index];
+        let plain_path = entry.get_analysis_filepath(&self.path);
+        let file_stem = entry.file_stem().to_string();
+        self.set_analysis_compressed(&file_stem, true);
+        let new_path = self.manifest.entries[entry_index].get_analysis_filepath(&self.path);
+        let result = async {
+            fs::rename(compressed_path, &new_path)
+                .await
+                .map_err(RecordingStoreError::CreateFileError)?;
+            self.write_manifest().await
+        }
+        .await;
+        if let Err(err) = result {
+            self.set_analysis_compressed(&file_stem, false);
+            return Err(err);
+        }
+        remove_file_if_exists(&plain_path)
+            .await
+            .map_err(RecordingStoreError::DeleteFileError)?;
+        let index_path = self.manifest.entries[entry_index].get_analysis_index_filepath(&self.path);
+        remove_file_if_exists(&index_path)
+            .await
+            .map_err(RecordingStoreError::DeleteFileError)
+    }
+
+    // Duplicate entries share the original's analysis file, so they all need
+    // to agree on whether it's compressed
+    fn set_analysis_compressed(&mut self, file_stem: &str, compressed: bool) {
+        for entry in &mut self.manifest.entries {
+            if entry.file_stem() == file_stem {
+                entry.analysis_compressed = compressed;
+            }
+        }
+    }
+
     // Unsets the current entry
     pub async fn close_current_entry(&mut self) -> Result<(), RecordingStoreError> {
         match self.current_entry {
@@ -310,6 +736,57 @@ impl RecordingStore {
         self.write_manifest().await
     }
 
+    // Records the diag layout versions seen in the given entry, only rewriting
+    // the manifest if they've changed
+    pub async fn update_entry_diag_versions(
+        &mut self,
+        entry_index: usize,
+        diag_versions: &DiagLayoutVersions,
+    ) -> Result<(), RecordingStoreError> {
+        if self.manifest.entries[entry_index].diag_versions == *diag_versions {
+            return Ok(());
+        }
+        self.manifest.entries[entry_index].diag_versions = diag_versions.clone();
+        self.write_manifest().await
+    }
+
+    // Deletes the given entry's QMDL file but keeps its analysis, marking
+    // every entry that shares the file as pruned, and deleting their paired
+    // files too. Returns how many bytes were freed.
+    pub async fn prune_entry_qmdl(
+        &mut self,
+        entry_index: usize,
+    ) -> Result<u64, RecordingStoreError> {
+        let entry = &self.manifest.entries[entry_index];
+        let file_stem = entry.file_stem().to_string();
+        let qmdl_filepath = entry.get_qmdl_filepath(&self.path);
+        let mut size = fs::metadata(&qmdl_filepath)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        remove_file_if_exists(&qmdl_filepath)
+            .await
+            .map_err(RecordingStoreError::DeleteFileError)?;
+        for entry in &mut self.manifest.entries {
+            if entry.file_stem() != file_stem {
+                continue;
+            }
+            entry.qmdl_pruned = true;
+            if entry.paired_qmdl_size_bytes.take().is_some() {
+                let paired_filepath = entry.get_paired_qmdl_filepath(&self.path);
+                size += fs::metadata(&paired_filepath)
+                    .await
+                    .map(|metadata| metadata.len())
+                    .unwrap_or(0);
+                remove_file_if_exists(&paired_filepath)
+                    .await
+                    .map_err(RecordingStoreError::DeleteFileError)?;
+            }
+        }
+        self.write_manifest().await?;
+        Ok(size)
+    }
+
     async fn write_manifest(&mut self) -> Result<(), RecordingStoreError> {
         // we don't technically need a mutable reference to `self` here, but it
         // does prevent multiple concurrent writes across different threads
@@ -359,22 +836,50 @@ impl RecordingStore {
                 self.close_current_entry().await?;
                 EntryType::Current
             }
+            // imports are added to the end, so the entry may come after the
+            // current one, which then stays where it is
             Some(current_entry) => {
-                self.current_entry = Some(current_entry - 1);
+                if entry_to_delete_idx < current_entry {
+                    self.current_entry = Some(current_entry - 1);
+                }
                 EntryType::Past
             }
             None => EntryType::Past,
         };
         let entry_to_delete = self.manifest.entries.remove(entry_to_delete_idx);
         self.write_manifest().await?;
+        remove_file_if_exists(&entry_to_delete.get_paired_qmdl_filepath(&self.path))
+            .await
+            .map_err(RecordingStoreError::DeleteFileError)?;
+        // leave the files alone if another entry is a duplicate of this one
+        if self
+            .manifest
+            .entries
+            .iter()
+            .any(|entry| entry.file_stem() == entry_to_delete.file_stem())
+        {
+            return Ok(is_current);
+        }
         let qmdl_filepath = entry_to_delete.get_qmdl_filepath(&self.path);
         let analysis_filepath = entry_to_delete.get_analysis_filepath(&self.path);
+        let analysis_index_filepath = entry_to_delete.get_analysis_index_filepath(&self.path);
+        let dead_letter_filepath = entry_to_delete.get_dead_letter_filepath(&self.path);
+        let window_analysis_filepath = entry_to_delete.get_window_analysis_filepath(&self.path);
         remove_file_if_exists(&qmdl_filepath)
             .await
             .map_err(RecordingStoreError::DeleteFileError)?;
         remove_file_if_exists(&analysis_filepath)
             .await
             .map_err(RecordingStoreError::DeleteFileError)?;
+        remove_file_if_exists(&analysis_index_filepath)
+            .await
+            .map_err(RecordingStoreError::DeleteFileError)?;
+        remove_file_if_exists(&dead_letter_filepath)
+            .await
+            .map_err(RecordingStoreError::DeleteFileError)?;
+        remove_file_if_exists(&window_analysis_filepath)
+            .await
+            .map_err(RecordingStoreError::DeleteFileError)?;
         Ok(is_current)
     }
 
@@ -401,6 +906,18 @@ impl RecordingStore {
                 continue;
             }
 
+            // losing track of these doesn't matter
+            for filepath in [
+                entry.get_paired_qmdl_filepath(&self.path),
+                entry.get_analysis_index_filepath(&self.path),
+                entry.get_dead_letter_filepath(&self.path),
+                entry.get_window_analysis_filepath(&self.path),
+            ] {
+                if let Err(e) = remove_file_if_exists(&filepath).await {
+                    log::warn!("failed to remove {filepath:?}: {e:?}");
+                }
+            }
+
             keep.push(false);
         }
 
@@ -411,6 +928,20 @@ impl RecordingStore {
     }
 }
 
+// Tells failures that mean the analysis directory can't be written to at
+// all apart from other errors, which are wrapped with `otherwise`
+fn analysis_write_error(
+    err: io::Error,
+    otherwise: fn(io::Error) -> RecordingStoreError,
+) -> RecordingStoreError {
+    match err.kind() {
+        ErrorKind::PermissionDenied | ErrorKind::ReadOnlyFilesystem | ErrorKind::StorageFull => {
+            RecordingStoreError::AnalysisNotWritable(err)
+        }
+        _ => otherwise(err),
+    }
+}
+
 async fn remove_file_if_exists(path: &Path) -> Result<(), io::Error> {
     match tokio::fs::remove_file(path).await {
         Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
@@ -422,6 +953,7 @@ async fn remove_file_if_exists(path: &Path) -> Result<(), io::Error> {
 mod tests {
     use super::*;
     use tempfile::{Builder, TempDir};
+    use tokio::io::AsyncReadExt;
 
     fn make_temp_dir() -> TempDir {
         Builder::new().prefix("qmdl_store_test").tempdir().unwrap()
@@ -500,6 +1032,279 @@ mod tests {
         assert_eq!(store.manifest.entries.len(), 2);
     }
 
+    #[tokio::test]
+    async fn test_import_duplicate_entries() {
+        let dir = make_temp_dir();
+        let mut store = RecordingStore::create(dir.path()).await.unwrap();
+        let upload_path = dir.path().join("upload.tmp");
+
+        fs::write(&upload_path, b"qmdl").await.unwrap();
+        let original_idx = store
+            .import_entry(
+                &upload_path,
+                "abc".to_string(),
+                4,
+                ImportMetadata::default(),
+            )
+            .await
+            .unwrap();
+        fs::write(&upload_path, b"qmdl").await.unwrap();
+        let duplicate_idx = store
+            .import_entry(
+                &upload_path,
+                "abc".to_string(),
+                4,
+                ImportMetadata::default(),
+            )
+            .await
+            .unwrap();
+        assert!(!upload_path.exists());
+
+        let original = store.manifest.entries[original_idx].clone();
+        let duplicate = store.manifest.entries[duplicate_idx].clone();
+        assert_ne!(original.name, duplicate.name);
+        assert_eq!(original.duplicate_of, None);
+        assert_eq!(duplicate.duplicate_of, Some(original.name.clone()));
+        assert_eq!(
+            duplicate.get_qmdl_filepath(dir.path()),
+            original.get_qmdl_filepath(dir.path())
+        );
+
+        // deleting the original shouldn't remove the files the duplicate uses
+        store.delete_entry(&original.name).await.unwrap();
+        assert!(duplicate.get_qmdl_filepath(dir.path()).exists());
+        store.delete_entry(&duplicate.name).await.unwrap();
+        assert!(!duplicate.get_qmdl_filepath(dir.path()).exists());
+    }
+
+    async fn import_test_entry(store: &mut RecordingStore) -> usize {
+        let upload_path = store.path.join("upload.tmp");
+        fs::write(&upload_path, b"qmdl").await.unwrap();
+        store
+            .import_entry(
+                &upload_path,
+                "abc".to_string(),
+                4,
+                ImportMetadata::default(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_delete_import_during_recording() {
+        let dir = make_temp_dir();
+        let mut store = RecordingStore::create(dir.path()).await.unwrap();
+        let _ = store.new_entry().await.unwrap();
+        let current_name = store.manifest.entries[store.current_entry.unwrap()]
+            .name
+            .clone();
+        let import_index = import_test_entry(&mut store).await;
+        assert!(import_index > store.current_entry.unwrap());
+
+        let import_name = store.manifest.entries[import_index].name.clone();
+        assert!(matches!(
+            store.delete_entry(&import_name).await.unwrap(),
+            EntryType::Past
+        ));
+        let current_entry = store.current_entry.unwrap();
+        assert_eq!(store.manifest.entries[current_entry].name, current_name);
+    }
+
+    #[tokio::test]
+    async fn test_replace_window_analysis() {
+        let dir = make_temp_dir();
+        let mut store = RecordingStore::create(dir.path()).await.unwrap();
+        let entry_index = import_test_entry(&mut store).await;
+        for report in ["first window\n", "second window\n"] {
+            store
+                .replace_entry_window_analysis(entry_index, report)
+                .await
+                .unwrap();
+            let mut saved = String::new();
+            store
+                .open_entry_window_analysis(entry_index)
+                .await
+                .unwrap()
+                .read_to_string(&mut saved)
+                .await
+                .unwrap();
+            assert_eq!(saved, report);
+        }
+        // no temporary files are left behind
+        let mut dir_entries = fs::read_dir(dir.path()).await.unwrap();
+        while let Some(dir_entry) = dir_entries.next_entry().await.unwrap() {
+            let file_name = dir_entry.file_name();
+            assert!(!file_name.to_string_lossy().contains("window.ndjson."));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pair_entry_qmdl() {
+        let dir = make_temp_dir();
+        let mut store = RecordingStore::create(dir.path()).await.unwrap();
+        let upload_path = dir.path().join("upload.tmp");
+        let entry_index = import_test_entry(&mut store).await;
+        assert!(
+            store
+                .open_entry_paired_qmdl(entry_index)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        fs::write(&upload_path, b"paired").await.unwrap();
+        store
+            .pair_entry_qmdl(entry_index, &upload_path, 6)
+            .await
+            .unwrap();
+        let entry = store.manifest.entries[entry_index].clone();
+        assert_eq!(entry.paired_qmdl_size_bytes, Some(6));
+        assert!(
+            store
+                .open_entry_paired_qmdl(entry_index)
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        // a duplicate would share the paired entry's analysis
+        let duplicate_index = import_test_entry(&mut store).await;
+        fs::write(&upload_path, b"paired").await.unwrap();
+        assert!(matches!(
+            store
+                .pair_entry_qmdl(duplicate_index, &upload_path, 6)
+                .await,
+            Err(RecordingStoreError::SharedFilesError)
+        ));
+
+        // pruning removes the paired file along with the QMDL
+        assert_eq!(store.prune_entry_qmdl(entry_index).await.unwrap(), 10);
+        assert!(!entry.get_paired_qmdl_filepath(dir.path()).exists());
+        assert_eq!(
+            store.manifest.entries[entry_index].paired_qmdl_size_bytes,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_named_entry() {
+        let dir = make_temp_dir();
+        let mut store = RecordingStore::create(dir.path()).await.unwrap();
+        let upload_path = dir.path().join("upload.tmp");
+        let metadata = || ImportMetadata {
+            name: Some("field-test_1".to_string()),
+            start_time: None,
+            notes: Some("captured with another tool".to_string()),
+        };
+
+        fs::write(&upload_path, b"qmdl").await.unwrap();
+        let idx = store
+            .import_entry(&upload_path, "abc".to_string(), 4, metadata())
+            .await
+            .unwrap();
+        let entry = store.manifest.entries[idx].clone();
+        assert_eq!(entry.name, "field-test_1");
+        assert_eq!(entry.notes.as_deref(), Some("captured with another tool"));
+        assert!(entry.imported);
+        assert!(entry.get_qmdl_filepath(dir.path()).exists());
+        assert!(entry.get_analysis_filepath(dir.path()).exists());
+
+        fs::write(&upload_path, b"other").await.unwrap();
+        assert!(matches!(
+            store
+                .import_entry(&upload_path, "def".to_string(), 5, metadata())
+                .await,
+            Err(RecordingStoreError::EntryExistsError)
+        ));
+        let bad_name = ImportMetadata {
+            name: Some("../escape".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            store
+                .import_entry(&upload_path, "def".to_string(), 5, bad_name)
+                .await,
+            Err(RecordingStoreError::InvalidEntryNameError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_has_valid_analysis() {
+        let dir = make_temp_dir();
+        let mut store = RecordingStore::create(dir.path()).await.unwrap();
+        let _ = store.new_entry().await.unwrap();
+        let entry = store.manifest.entries[0].clone();
+        let analysis_path = entry.get_analysis_filepath(dir.path());
+
+        assert!(!entry.has_valid_analysis(dir.path()));
+        fs::write(&analysis_path, b"").await.unwrap();
+        assert!(!entry.has_valid_analysis(dir.path()));
+        fs::write(&analysis_path, b"not json\n").await.unwrap();
+        assert!(!entry.has_valid_analysis(dir.path()));
+        fs::write(&analysis_path, b"{\"analyzers\":[]}\n")
+            .await
+            .unwrap();
+        assert!(entry.has_valid_analysis(dir.path()));
+    }
+
+    #[tokio::test]
+    async fn test_compressed_analysis() {
+        let dir = make_temp_dir();
+        let mut store = RecordingStore::create(dir.path()).await.unwrap();
+        let upload_path = dir.path().join("upload.tmp");
+        fs::write(&upload_path, b"qmdl").await.unwrap();
+        let original_idx = store
+            .import_entry(
+                &upload_path,
+                "abc".to_string(),
+                4,
+                ImportMetadata::default(),
+            )
+            .await
+            .unwrap();
+        fs::write(&upload_path, b"qmdl").await.unwrap();
+        let duplicate_idx = store
+            .import_entry(
+                &upload_path,
+                "abc".to_string(),
+                4,
+                ImportMetadata::default(),
+            )
+            .await
+            .unwrap();
+        let plain_path = store.manifest.entries[original_idx].get_analysis_filepath(dir.path());
+
+        let compressed_tmp = dir.path().join("compressed.tmp");
+        fs::write(&compressed_tmp, b"gzipped").await.unwrap();
+        store
+            .replace_entry_analysis_compressed(original_idx, &compressed_tmp)
+            .await
+            .unwrap();
+        assert!(!plain_path.exists());
+        // the duplicate shares the original's analysis file
+        for idx in [original_idx, duplicate_idx] {
+            let entry = &store.manifest.entries[idx];
+            assert!(entry.analysis_compressed);
+            assert!(entry.get_analysis_filepath(dir.path()).exists());
+        }
+        assert_eq!(
+            RecordingStore::read_manifest(dir.path()).await.unwrap(),
+            store.manifest
+        );
+
+        // re-analyzing goes back to a plain file
+        let compressed_path =
+            store.manifest.entries[original_idx].get_analysis_filepath(dir.path());
+        store
+            .clear_and_open_entry_analysis(duplicate_idx)
+            .await
+            .unwrap();
+        assert!(!compressed_path.exists());
+        assert!(plain_path.exists());
+        assert!(!store.manifest.entries[original_idx].analysis_compressed);
+    }
+
     #[tokio::test]
     async fn test_delete_all_entries() {
         let dir = make_temp_dir();
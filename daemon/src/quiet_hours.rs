@@ -0,0 +1,152 @@
+use chrono::{DateTime, FixedOffset, NaiveTime, TimeZone};
+use rayhunter::analysis::analyzer::{Event, EventType, Severity};
+use serde::{Deserialize, Serialize};
+
+use crate::error::RayhunterError;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct QuietHoursConfig {
+    // when quiet hours start and end each day, as HH:MM. An end before the
+    // start means quiet hours run past midnight.
+    pub start: String,
+    pub end: String,
+    // the UTC offset start and end are in, such as "+01:00". It's a fixed
+    // offset rather than a timezone name, so quiet hours don't shift or
+    // become ambiguous when daylight saving time starts or ends.
+    pub utc_offset: String,
+    // findings at or above this severity still notify during quiet hours
+    pub min_severity: Severity,
+}
+
+impl Default for QuietHoursConfig {
+    fn default() -> Self {
+        QuietHoursConfig {
+            start: "22:00".to_string(),
+            end: "07:00".to_string(),
+            utc_offset: "+00:00".to_string(),
+            min_severity: Severity::High,
+        }
+    }
+}
+
+fn parse_time(name: &str, value: &str) -> Result<NaiveTime, RayhunterError> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M").map_err(|_| {
+        RayhunterError::InvalidQuietHours(format!("{name} {value:?} isn't a time such as 22:00"))
+    })
+}
+
+// Parses an offset such as +01:00 or -05:30
+fn parse_utc_offset(value: &str) -> Result<FixedOffset, RayhunterError> {
+    let invalid = || {
+        RayhunterError::InvalidQuietHours(format!(
+            "utc_offset {value:?} isn't an offset such as +01:00"
+        ))
+    };
+    let value = value.trim();
+    let (sign, rest) = match value.split_at_checked(1) {
+        Some(("+", rest)) => (1, rest),
+        Some(("-", rest)) => (-1, rest),
+        _ => return Err(invalid()),
+    };
+    let (hours, minutes) = rest.split_once(':').ok_or_else(invalid)?;
+    let hours: i32 = hours.parse().map_err(|_| invalid())?;
+    let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+    if !(0..60).contains(&minutes) {
+        return Err(invalid());
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).ok_or_else(invalid)
+}
+
+// A daily window during which only findings at or above min_severity are
+// sent on to the things that notify people, the post-analysis hook and the
+// finding socket, so that running rayhunter around the clock doesn't mean
+// being woken up by every low severity finding. Everything is still written
+// to the analysis files, and to syslog.
+#[derive(Debug, Clone, Copy)]
+pub struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+    utc_offset: FixedOffset,
+    min_severity: Severity,
+}
+
+impl QuietHours {
+    pub fn parse(config: &QuietHoursConfig) -> Result<Self, RayhunterError> {
+        Ok(QuietHours {
+            start: parse_time("start", &config.start)?,
+            end: parse_time("end", &config.end)?,
+            utc_offset: parse_utc_offset(&config.utc_offset)?,
+            min_severity: config.min_severity,
+        })
+    }
+
+    pub fn is_quiet<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> bool {
+        let time = now.with_timezone(&self.utc_offset).time();
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+
+    // Whether something this severe (None being informational) should
+    // notify at the given time
+    pub fn allows<Tz: TimeZone>(&self, severity: Option<Severity>, now: &DateTime<Tz>) -> bool {
+        !self.is_quiet(now) || severity.is_some_and(|severity| severity >= self.min_severity)
+    }
+
+    pub fn allows_event<Tz: TimeZone>(&self, event: &Event, now: &DateTime<Tz>) -> bool {
+        let severity = match event.event_type {
+            EventType::QualitativeWarning { severity } => Some(severity),
+            _ => None,
+        };
+        self.allows(severity, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: &str) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(timestamp).unwrap()
+    }
+
+    #[test]
+    fn test_quiet_hours() {
+        let quiet_hours = QuietHours::parse(&QuietHoursConfig {
+            start: "22:00".to_string(),
+            end: "07:00".to_string(),
+            utc_offset: "+02:00".to_string(),
+            min_severity: Severity::High,
+        })
+        .unwrap();
+        // 23:30 and 06:59 at +02:00
+        assert!(quiet_hours.is_quiet(&at("2024-06-01T21:30:00Z")));
+        assert!(quiet_hours.is_quiet(&at("2024-06-02T04:59:00Z")));
+        // 07:00 and 12:00 at +02:00, whatever offset the time is given in
+        assert!(!quiet_hours.is_quiet(&at("2024-06-02T05:00:00Z")));
+        assert!(!quiet_hours.is_quiet(&at("2024-06-02T05:00:00-05:00")));
+
+        let night = at("2024-06-01T23:00:00+02:00");
+        assert!(quiet_hours.allows(Some(Severity::High), &night));
+        assert!(!quiet_hours.allows(Some(Severity::Medium), &night));
+        assert!(!quiet_hours.allows(None, &night));
+        assert!(quiet_hours.allows(None, &at("2024-06-01T12:00:00+02:00")));
+    }
+
+    #[test]
+    fn test_parse_quiet_hours() {
+        let config = |start: &str, utc_offset: &str| QuietHoursConfig {
+            start: start.to_string(),
+            utc_offset: utc_offset.to_string(),
+            ..Default::default()
+        };
+        assert!(QuietHours::parse(&config("22:00", "-05:30")).is_ok());
+        assert!(QuietHours::parse(&config("10pm", "+00:00")).is_err());
+        assert!(QuietHours::parse(&config("22:00", "Europe/Berlin")).is_err());
+        assert!(QuietHours::parse(&config("22:00", "+01:60")).is_err());
+        assert!(QuietHours::parse(&config("22:00", "+25:00")).is_err());
+    }
+}
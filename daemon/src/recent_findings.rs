@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use axum::Json;
+use axum::extract::State;
+use chrono::{DateTime, FixedOffset};
+use rayhunter::analysis::analyzer::{Event, EventType, Severity};
+use serde::Serialize;
+
+use crate::server::ServerState;
+
+// How many findings are kept across all recordings before the oldest are
+// dropped
+pub const RECENT_FINDINGS_CAPACITY: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecentFinding {
+    pub recording: String,
+    pub analyzer: String,
+    pub severity: Severity,
+    pub message: String,
+    pub timestamp: Option<DateTime<FixedOffset>>,
+}
+
+// The latest warnings found by any analysis, so a freshly loaded UI has
+// something to show without reading every analysis file. Cheap to clone, and
+// clones share the same buffer.
+#[derive(Clone)]
+pub struct RecentFindings {
+    findings: Arc<Mutex<VecDeque<RecentFinding>>>,
+    capacity: usize,
+}
+
+impl RecentFindings {
+    pub fn new(capacity: usize) -> Self {
+        RecentFindings {
+            findings: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    // Records a warning, evicting the oldest finding if the buffer's full.
+    // Informational events are ignored.
+    pub fn push(
+        &self,
+        recording: &str,
+        analyzer: &str,
+        timestamp: Option<DateTime<FixedOffset>>,
+        event: &Event,
+    ) {
+        let EventType::QualitativeWarning { severity } = event.event_type else {
+            return;
+        };
+        if self.capacity == 0 {
+            return;
+        }
+        let mut findings = self.findings.lock().unwrap();
+        if findings.len() == self.capacity {
+            findings.pop_front();
+        }
+        findings.push_back(RecentFinding {
+            recording: recording.to_string(),
+            analyzer: analyzer.to_string(),
+            severity,
+            message: event.message.clone(),
+            timestamp,
+        });
+    }
+
+    // Returns the findings, newest first
+    pub fn snapshot(&self) -> Vec<RecentFinding> {
+        let findings = self.findings.lock().unwrap();
+        findings.iter().rev().cloned().collect()
+    }
+}
+
+pub async fn get_recent_findings(
+    State(state): State<Arc<ServerState>>,
+) -> Json<Vec<RecentFinding>> {
+    Json(state.recent_findings.snapshot())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warning(message: &str) -> Event {
+        Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: message.to_string(),
+            explanation: String::new(),
+            evidence: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_recent_findings_evicts_oldest() {
+        let recent = RecentFindings::new(2);
+        recent.push("1", "Analyzer", None, &warning("a"));
+        let informational = Event {
+            event_type: EventType::Informational,
+            ..warning("ignored")
+        };
+        recent.push("1", "Analyzer", None, &informational);
+        recent.clone().push("2", "Analyzer", None, &warning("b"));
+        recent.push("2", "Analyzer", None, &warning("c"));
+
+        let messages: Vec<String> = recent
+            .snapshot()
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect();
+        assert_eq!(messages, ["c", "b"]);
+    }
+}
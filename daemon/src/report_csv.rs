@@ -0,0 +1,248 @@
+use std::future;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use futures::{StreamExt, stream};
+use log::warn;
+use rayhunter::analysis::analyzer::{Event, EventType};
+use rayhunter::analysis::report_format::{ReportFormat, ReportFormatError};
+use serde::Deserialize;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::server::ServerState;
+
+const COLUMNS: [&str; 5] = ["timestamp", "analyzer", "severity", "cell", "explanation"];
+
+// Evidence keys analyzers use to name the cell a finding is about, in order
+// of preference
+const CELL_EVIDENCE_KEYS: [&str; 3] = ["cell", "new_cell", "phy_cell_id"];
+
+// Returns the cell a finding is about, as named in its evidence
+pub(crate) fn event_cell(event: &Event) -> Option<String> {
+    CELL_EVIDENCE_KEYS.iter().find_map(|key| {
+        event
+            .evidence
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.clone())
+    })
+}
+
+// Just enough of the report metadata to name each event's analyzer
+#[derive(Deserialize)]
+struct CsvReportMetadata {
+    analyzers: Vec<CsvAnalyzerMetadata>,
+}
+
+#[derive(Deserialize)]
+struct CsvAnalyzerMetadata {
+    name: String,
+}
+
+// One CSV row per event. Every field maps to a column in COLUMNS, so rows
+// have the same shape regardless of the analyzer or report version that
+// produced them, with missing fields left as empty cells.
+#[derive(Debug, Default, PartialEq)]
+struct CsvFinding {
+    timestamp: Option<DateTime<FixedOffset>>,
+    analyzer: Option<String>,
+    severity: Option<String>,
+    cell: Option<String>,
+    explanation: Option<String>,
+}
+
+impl CsvFinding {
+    fn new(
+        timestamp: Option<DateTime<FixedOffset>>,
+        analyzer: Option<&str>,
+        event: &Event,
+    ) -> Self {
+        let severity = match &event.event_type {
+            EventType::Informational => "Informational".to_string(),
+            EventType::QualitativeWarning { severity } => format!("{severity:?}"),
+        };
+        CsvFinding {
+            timestamp,
+            analyzer: analyzer.map(str::to_string),
+            severity: Some(severity),
+            cell: event_cell(event),
+            explanation: Some(event.explanation.clone()).filter(|e| !e.is_empty()),
+        }
+    }
+
+    fn to_record(&self) -> String {
+        let timestamp = self
+            .timestamp
+            .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true));
+        csv_record([
+            timestamp.as_deref(),
+            self.analyzer.as_deref(),
+            self.severity.as_deref(),
+            self.cell.as_deref(),
+            self.explanation.as_deref(),
+        ])
+    }
+}
+
+// Quotes a field per RFC 4180 if it contains anything that would otherwise
+// break the row apart
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_record(fields: [Option<&str>; COLUMNS.len()]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| csv_field(field.unwrap_or_default()))
+        .collect();
+    format!("{}\r\n", fields.join(","))
+}
+
+// Returns the names of a report's analyzers, in the same order as each row's
+// events, given its metadata line
+pub(crate) fn parse_analyzer_names(metadata_line: Option<&str>, qmdl_name: &str) -> Vec<String> {
+    match metadata_line.map(serde_json::from_str::<CsvReportMetadata>) {
+        Some(Ok(metadata)) => metadata.analyzers.into_iter().map(|a| a.name).collect(),
+        Some(Err(err)) => {
+            warn!("failed to parse analysis report metadata for {qmdl_name}: {err}");
+            Vec::new()
+        }
+        None => Vec::new(),
+    }
+}
+
+// Returns the format of a report given its metadata line, refusing reports
+// from a newer version of rayhunter. Metadata that can't be parsed is
+// assumed to be current, as with the analyzer names.
+pub(crate) fn parse_report_format(
+    metadata_line: Option<&str>,
+) -> Result<ReportFormat, (StatusCode, String)> {
+    match metadata_line.map(ReportFormat::from_metadata_line) {
+        Some(Ok(format)) => Ok(format),
+        Some(Err(err @ ReportFormatError::UnsupportedVersion(_))) => {
+            Err((StatusCode::UNPROCESSABLE_ENTITY, err.to_string()))
+        }
+        Some(Err(_)) | None => Ok(ReportFormat::default()),
+    }
+}
+
+// Converts one line of an analysis report to CSV records, one per event
+fn row_to_csv(line: &str, format: &ReportFormat, analyzer_names: &[String]) -> String {
+    let rows = match format.parse_row(line) {
+        Ok(rows) => rows,
+        Err(err) => {
+            warn!("skipping unparseable analysis row: {err}");
+            return String::new();
+        }
+    };
+    rows.iter()
+        .flat_map(|row| {
+            row.events.iter().enumerate().filter_map(|(i, event)| {
+                let analyzer = analyzer_names.get(i).map(String::as_str);
+                event
+                    .as_ref()
+                    .map(|event| CsvFinding::new(row.packet_timestamp, analyzer, event).to_record())
+            })
+        })
+        .collect()
+}
+
+pub async fn get_analysis_report_csv(
+    State(state): State<Arc<ServerState>>,
+    Path(qmdl_name): Path<String>,
+) -> Result<Response, (StatusCode, String)> {
+    let analysis_file = {
+        let qmdl_store = state.qmdl_store_lock.read().await;
+        let (entry_index, _) = if qmdl_name == "live" {
+            qmdl_store.get_current_entry().ok_or((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "No QMDL data's being recorded to analyze, try starting a new recording!"
+                    .to_string(),
+            ))?
+        } else {
+            qmdl_store.entry_for_name(&qmdl_name).ok_or((
+                StatusCode::NOT_FOUND,
+                format!("Couldn't find QMDL entry with name \"{qmdl_name}\""),
+            ))?
+        };
+        qmdl_store
+            .open_entry_analysis(entry_index)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}")))?
+    };
+
+    let mut lines = BufReader::new(analysis_file).lines();
+    let metadata_line = lines
+        .next_line()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}")))?;
+    let format = parse_report_format(metadata_line.as_deref())?;
+    let analyzer_names = parse_analyzer_names(metadata_line.as_deref(), &qmdl_name);
+
+    // read the report a line at a time rather than loading it all in memory,
+    // stopping at the first read error
+    let records = stream::unfold(Some(lines), |lines| async move {
+        let mut lines = lines?;
+        match lines.next_line().await {
+            Ok(Some(line)) => Some((Ok(line), Some(lines))),
+            Ok(None) => None,
+            Err(err) => Some((Err(err), None)),
+        }
+    })
+    .map(move |line| line.map(|line| row_to_csv(&line, &format, &analyzer_names)));
+    let header = csv_record(COLUMNS.map(Some));
+    let body_stream = stream::once(future::ready(Ok(header))).chain(records);
+
+    let headers = [
+        (CONTENT_TYPE, "text/csv".to_string()),
+        (
+            CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{qmdl_name}.csv\""),
+        ),
+    ];
+    Ok((headers, Body::from_stream(body_stream)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row_to_csv() {
+        let format = ReportFormat::default();
+        let analyzer_names = vec!["Null Cipher".to_string(), "PCI Conflict".to_string()];
+        let line = r#"{"packet_timestamp":"2024-01-01T00:00:00+00:00","skipped_message_reason":null,"events":[{"event_type":{"type":"QualitativeWarning","severity":"High"},"message":"a","explanation":"has \"quotes\", commas"},{"event_type":{"type":"Informational"},"message":"b","evidence":[["phy_cell_id","42"]]}]}"#;
+        assert_eq!(
+            row_to_csv(line, &format, &analyzer_names),
+            "2024-01-01T00:00:00Z,Null Cipher,High,,\"has \"\"quotes\"\", commas\"\r\n\
+             2024-01-01T00:00:00Z,PCI Conflict,Informational,42,\r\n"
+        );
+
+        // rows without any events, and events from analyzers missing from
+        // the metadata, still produce consistent rows
+        let line =
+            r#"{"packet_timestamp":null,"skipped_message_reason":"bad message","events":[]}"#;
+        assert_eq!(row_to_csv(line, &format, &analyzer_names), "");
+        let line = r#"{"packet_timestamp":null,"skipped_message_reason":null,"events":[{"event_type":{"type":"Informational"},"message":"c"}]}"#;
+        assert_eq!(row_to_csv(line, &format, &[]), ",,Informational,,\r\n");
+
+        // version 1 rows are upgraded, and newer versions are refused
+        let v1 = parse_report_format(Some(r#"{"analyzers":[]}"#)).unwrap();
+        let line = r#"{"skipped_message_reasons":[],"analysis":[{"timestamp":null,"events":[{"event_type":{"type":"Informational"},"message":"d"}]}]}"#;
+        assert_eq!(row_to_csv(line, &v1, &[]), ",,Informational,,\r\n");
+        let future = r#"{"analyzers":[],"report_version":1000}"#;
+        assert_eq!(
+            parse_report_format(Some(future)).unwrap_err().0,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, FixedOffset};
+use log::warn;
+use rayhunter::analysis::analyzer::{AnalysisRow, Event, EventType, Severity};
+use rayhunter::analysis::cell_distance::{CellDatabase, CellDistanceSettings};
+use rayhunter::analysis::util::CellGlobalIdentity;
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::compaction::collapsed_repeats;
+use crate::report_csv::{event_cell, parse_analyzer_names, parse_report_format};
+use crate::server::ServerState;
+
+// Parses a location as the cell_distance analyzer writes it in evidence:
+// "latitude, longitude"
+fn parse_location(value: &str) -> Option<[f64; 2]> {
+    let (latitude, longitude) = value.split_once(',')?;
+    Some([
+        latitude.trim().parse().ok()?,
+        longitude.trim().parse().ok()?,
+    ])
+}
+
+// GeoJSON positions are longitude first
+fn point(location: [f64; 2]) -> Value {
+    json!({
+        "type": "Point",
+        "coordinates": [location[1], location[0]],
+    })
+}
+
+struct CellFindings {
+    location: [f64; 2],
+    analyzers: BTreeSet<String>,
+    severity: Severity,
+    findings: usize,
+    first_seen: Option<DateTime<FixedOffset>>,
+}
+
+// Builds a GeoJSON FeatureCollection out of a report's warnings: a point for
+// every warning, and one for every cell a warning was about. A finding about
+// a cell is placed where the cell is, per its evidence or the cell database,
+// and any other finding where the device is, per its evidence or the
+// configured device_location. Findings that can't be placed are left out.
+struct GeoJsonBuilder {
+    recording: String,
+    cell_database: Arc<CellDatabase>,
+    device_location: Option<[f64; 2]>,
+    cells: BTreeMap<String, CellFindings>,
+    features: Vec<Value>,
+}
+
+impl GeoJsonBuilder {
+    fn new(
+        recording: &str,
+        cell_database: Arc<CellDatabase>,
+        device_location: Option<[f64; 2]>,
+    ) -> Self {
+        GeoJsonBuilder {
+            recording: recording.to_string(),
+            cell_database,
+            device_location,
+            cells: BTreeMap::new(),
+            features: Vec::new(),
+        }
+    }
+
+    fn add_row(&mut self, row: &AnalysisRow, analyzer_names: &[String]) {
+        for (i, event) in row.events.iter().enumerate() {
+            let Some(event) = event else {
+                continue;
+            };
+            let analyzer = analyzer_names
+                .get(i)
+                .map(String::as_str)
+                .unwrap_or("Unknown analyzer");
+            self.add_finding(row.packet_timestamp, analyzer, event);
+        }
+    }
+
+    // Where a finding happened, and what that's according to
+    fn locate(&self, event: &Event, cell: Option<&str>) -> Option<([f64; 2], &'static str)> {
+        let evidence = |name: &str| {
+            event
+                .evidence
+                .iter()
+                .find(|(key, _)| key == name)
+                .and_then(|(_, value)| parse_location(value))
+        };
+        if let Some(cell) = cell {
+            if let Some(location) = evidence("cell_location") {
+                return Some((location, "cell"));
+            }
+            let location =
+                CellGlobalIdentity::parse(cell).and_then(|cell| self.cell_database.location(&cell));
+            if let Some(location) = location {
+                return Some((location, "cell_database"));
+            }
+        }
+        evidence("device_location")
+            .or(self.device_location)
+            .map(|location| (location, "device"))
+    }
+
+    fn add_finding(
+        &mut self,
+        timestamp: Option<DateTime<FixedOffset>>,
+        analyzer: &str,
+        event: &Event,
+    ) {
+        let EventType::QualitativeWarning { severity } = &event.event_type else {
+            return;
+        };
+        let cell = event_cell(event);
+        let Some((location, source)) = self.locate(event, cell.as_deref()) else {
+            return;
+        };
+        let (repeats, _) = collapsed_repeats(event);
+        self.features.push(json!({
+            "type": "Feature",
+            "geometry": point(location),
+            "properties": {
+                "kind": "finding",
+                "recording": self.recording,
+                "analyzer": analyzer,
+                "severity": format!("{severity:?}"),
+                "message": event.message,
+                "cell": cell,
+                "timestamp": timestamp.map(|timestamp| timestamp.to_rfc3339()),
+                "count": repeats + 1,
+                "location_source": source,
+            },
+        }));
+
+        // findings placed at the device don't say where their cell is
+        let Some(cell) = cell.filter(|_| source != "device") else {
+            return;
+        };
+        let findings = self.cells.entry(cell).or_insert(CellFindings {
+            location,
+            analyzers: BTreeSet::new(),
+            severity: *severity,
+            findings: 0,
+            first_seen: None,
+        });
+        findings.analyzers.insert(analyzer.to_string());
+        findings.severity = findings.severity.max(*severity);
+        findings.findings += 1;
+        findings.first_seen = match (findings.first_seen, timestamp) {
+            (Some(first_seen), Some(timestamp)) => Some(first_seen.min(timestamp)),
+            (first_seen, timestamp) => first_seen.or(timestamp),
+        };
+    }
+
+    fn finish(self) -> Value {
+        let mut features: Vec<Value> = self
+            .cells
+            .iter()
+            .map(|(cell, findings)| {
+                json!({
+                    "type": "Feature",
+                    "geometry": point(findings.location),
+                    "properties": {
+                        "kind": "cell",
+                        "recording": self.recording,
+                        "cell": cell,
+                        "analyzers": findings.analyzers,
+                        "severity": format!("{:?}", findings.severity),
+                        "findings": findings.findings,
+                        "first_seen": findings.first_seen.map(|timestamp| timestamp.to_rfc3339()),
+                    },
+                })
+            })
+            .collect();
+        features.extend(self.features);
+        json!({
+            "type": "FeatureCollection",
+            "features": features,
+        })
+    }
+}
+
+// Loads the cell database the cell_distance analyzer is configured with, if
+// any. It can be large, so it's read off the async runtime, and only if it
+// hasn't been already (see CellDatabase::load_shared).
+pub async fn load_configured_cell_database(path: Option<String>) -> Arc<CellDatabase> {
+    let Some(path) = path else {
+        return Arc::default();
+    };
+    let result = tokio::task::spawn_blocking(move || {
+        let result = CellDatabase::load_shared(&path);
+        (path, result)
+    })
+    .await;
+    match result {
+        Ok((_, Ok(cell_database))) => cell_database,
+        Ok((path, Err(err))) => {
+            warn!("failed to load cell database {path}, not locating cells with it: {err}");
+            Arc::default()
+        }
+        Err(err) => {
+            warn!("failed to load cell database: {err}");
+            Arc::default()
+        }
+    }
+}
+
+pub async fn get_analysis_report_geojson(
+    State(state): State<Arc<ServerState>>,
+    Path(qmdl_name): Path<String>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let analysis_file = {
+        let qmdl_store = state.qmdl_store_lock.read().await;
+        let (entry_index, _) = qmdl_store.entry_for_name(&qmdl_name).ok_or((
+            StatusCode::NOT_FOUND,
+            format!("Couldn't find QMDL entry with name \"{qmdl_name}\""),
+        ))?;
+        qmdl_store
+            .open_entry_analysis(entry_index)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}")))?
+    };
+
+    let settings: CellDistanceSettings = state.config.analyzers.get_settings();
+    let cell_database = load_configured_cell_database(settings.cell_database_path).await;
+    let mut lines = BufReader::new(analysis_file).lines();
+    let read_error = |e: std::io::Error| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}"));
+    let metadata_line = lines.next_line().await.map_err(read_error)?;
+    let format = parse_report_format(metadata_line.as_deref())?;
+    let analyzer_names = parse_analyzer_names(metadata_line.as_deref(), &qmdl_name);
+    let mut builder = GeoJsonBuilder::new(&qmdl_name, cell_database, settings.device_location);
+    while let Some(line) = lines.next_line().await.map_err(read_error)? {
+        match format.parse_row(&line) {
+            Ok(rows) => rows
+                .iter()
+                .for_each(|row| builder.add_row(row, &analyzer_names)),
+            Err(err) => warn!("skipping unparseable analysis row: {err}"),
+        }
+    }
+    Ok(Json(builder.finish()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_geojson_features() {
+        let analyzer_names = vec![
+            "Cell Distance From Fixed Location".to_string(),
+            "PCI Conflict".to_string(),
+            "Null Cipher".to_string(),
+        ];
+        let line = r#"{"packet_timestamp":"2024-01-01T01:00:00+01:00","skipped_message_reason":null,"events":[{"event_type":{"type":"QualitativeWarning","severity":"Medium"},"message":"a","evidence":[["cell","310-410 (TAC 1, cell ID 2)"],["cell_location","40.00000, -75.00000"],["device_location","41.00000, -74.00000"]]},{"event_type":{"type":"QualitativeWarning","severity":"High"},"message":"b","evidence":[["cell","310-410 (TAC 1, cell ID 3)"]]},{"event_type":{"type":"QualitativeWarning","severity":"Low"},"message":"c"}]}"#;
+        let row: AnalysisRow = serde_json::from_str(line).unwrap();
+
+        // without a device location, only the finding whose evidence says
+        // where its cell is can be placed
+        let mut builder = GeoJsonBuilder::new("1720000000", Arc::default(), None);
+        builder.add_row(&row, &analyzer_names);
+        let collection = builder.finish();
+        let features = collection["features"].as_array().unwrap();
+        assert_eq!(collection["type"], "FeatureCollection");
+        assert_eq!(features.len(), 2);
+        let cell = &features[0];
+        assert_eq!(cell["properties"]["kind"], "cell");
+        assert_eq!(cell["properties"]["cell"], "310-410 (TAC 1, cell ID 2)");
+        assert_eq!(cell["geometry"]["coordinates"], json!([-75.0, 40.0]));
+        let finding = &features[1];
+        assert_eq!(finding["properties"]["kind"], "finding");
+        assert_eq!(
+            finding["properties"]["analyzer"],
+            "Cell Distance From Fixed Location"
+        );
+        assert_eq!(finding["properties"]["severity"], "Medium");
+        assert_eq!(finding["properties"]["location_source"], "cell");
+
+        // with one, the rest are placed at the device but don't place cells
+        let mut builder = GeoJsonBuilder::new("1720000000", Arc::default(), Some([42.0, -73.0]));
+        builder.add_row(&row, &analyzer_names);
+        let collection = builder.finish();
+        let features = collection["features"].as_array().unwrap();
+        let kinds: Vec<&str> = features
+            .iter()
+            .map(|feature| feature["properties"]["kind"].as_str().unwrap())
+            .collect();
+        assert_eq!(kinds, ["cell", "finding", "finding", "finding"]);
+        assert_eq!(features[2]["properties"]["location_source"], "device");
+        assert_eq!(features[3]["properties"]["analyzer"], "Null Cipher");
+        assert_eq!(features[3]["geometry"]["coordinates"], json!([-73.0, 42.0]));
+    }
+}
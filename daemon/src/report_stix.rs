@@ -0,0 +1,282 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat, Utc};
+use log::warn;
+use rayhunter::analysis::analyzer::{AnalysisRow, Event, EventType};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::compaction::collapsed_repeats;
+use crate::report_csv::{event_cell, parse_analyzer_names, parse_report_format};
+use crate::server::ServerState;
+
+// Observed cells don't have a STIX Cyber-observable type of their own, so
+// indicator patterns refer to them with this custom one, whose only property
+// is `id`: the cell as named in the finding's evidence
+const CELL_OBJECT_TYPE: &str = "x-rayhunter-cell";
+
+// Derives a stable STIX identifier from a key, so exporting the same report
+// twice produces the same objects. The UUID is the first 16 bytes of the
+// key's SHA-256, marked as a custom (version 8) UUID per RFC 9562.
+fn stix_id(object_type: &str, key: &str) -> String {
+    let digest = Sha256::digest(format!("{object_type}:{key}").as_bytes());
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    bytes[6] = (bytes[6] & 0x0f) | 0x80;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!(
+        "{object_type}--{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+// STIX timestamps are always UTC
+fn stix_timestamp<Tz: chrono::TimeZone>(timestamp: &DateTime<Tz>) -> String {
+    timestamp
+        .with_timezone(&Utc)
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+// Escapes a string for use in a STIX pattern's single-quoted literal
+fn pattern_literal(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+#[derive(Default)]
+struct CellFindings {
+    analyzers: BTreeSet<String>,
+    first_seen: Option<DateTime<FixedOffset>>,
+}
+
+// Builds a STIX 2.1 bundle out of a report's warnings: an indicator for every
+// cell a warning was about, a sighting of it for each of those warnings, and a
+// note for each warning that isn't about any particular cell. Everything is
+// attributed to an identity for the rayhunter device.
+struct StixBundleBuilder {
+    recording: String,
+    // when the recording started, used as every object's creation time so
+    // exports are reproducible
+    created: String,
+    identity_id: String,
+    cells: BTreeMap<String, CellFindings>,
+    objects: Vec<Value>,
+    finding_num: usize,
+}
+
+impl StixBundleBuilder {
+    fn new(recording: &str, start_time: DateTime<Local>) -> Self {
+        StixBundleBuilder {
+            recording: recording.to_string(),
+            created: stix_timestamp(&start_time),
+            identity_id: stix_id("identity", "rayhunter"),
+            cells: BTreeMap::new(),
+            objects: Vec::new(),
+            finding_num: 0,
+        }
+    }
+
+    fn add_row(&mut self, row: &AnalysisRow, analyzer_names: &[String]) {
+        for (i, event) in row.events.iter().enumerate() {
+            let Some(event) = event else {
+                continue;
+            };
+            let analyzer = analyzer_names
+                .get(i)
+                .map(String::as_str)
+                .unwrap_or("Unknown analyzer");
+            self.add_finding(row.packet_timestamp, analyzer, event);
+        }
+    }
+
+    fn add_finding(
+        &mut self,
+        timestamp: Option<DateTime<FixedOffset>>,
+        analyzer: &str,
+        event: &Event,
+    ) {
+        let EventType::QualitativeWarning { severity } = &event.event_type else {
+            return;
+        };
+        self.finding_num += 1;
+        let key = format!("{}:{}", self.recording, self.finding_num);
+        let evidence: BTreeMap<&str, &str> = event
+            .evidence
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect();
+
+        let Some(cell) = event_cell(event) else {
+            self.objects.push(json!({
+                "type": "note",
+                "spec_version": "2.1",
+                "id": stix_id("note", &key),
+                "created": self.created,
+                "modified": self.created,
+                "created_by_ref": self.identity_id,
+                "abstract": format!("{analyzer}: {}", event.message),
+                "content": event.explanation,
+                "object_refs": [self.identity_id],
+                "x_rayhunter_recording": self.recording,
+                "x_rayhunter_severity": format!("{severity:?}"),
+                "x_rayhunter_observed": timestamp.as_ref().map(stix_timestamp),
+                "x_rayhunter_evidence": evidence,
+            }));
+            return;
+        };
+
+        let (repeats, last_seen) = collapsed_repeats(event);
+        let mut sighting = json!({
+            "type": "sighting",
+            "spec_version": "2.1",
+            "id": stix_id("sighting", &key),
+            "created": self.created,
+            "modified": self.created,
+            "created_by_ref": self.identity_id,
+            "description": event.message,
+            "count": repeats + 1,
+            "sighting_of_ref": stix_id("indicator", &cell),
+            "where_sighted_refs": [self.identity_id],
+            "x_rayhunter_recording": self.recording,
+            "x_rayhunter_analyzer": analyzer,
+            "x_rayhunter_severity": format!("{severity:?}"),
+            "x_rayhunter_evidence": evidence,
+        });
+        if let Some(timestamp) = timestamp {
+            sighting["first_seen"] = json!(stix_timestamp(&timestamp));
+            sighting["last_seen"] = json!(stix_timestamp(&last_seen.unwrap_or(timestamp)));
+        }
+        self.objects.push(sighting);
+
+        let findings = self.cells.entry(cell).or_default();
+        findings.analyzers.insert(analyzer.to_string());
+        findings.first_seen = match (findings.first_seen, timestamp) {
+            (Some(first_seen), Some(timestamp)) => Some(first_seen.min(timestamp)),
+            (first_seen, timestamp) => first_seen.or(timestamp),
+        };
+    }
+
+    fn finish(self) -> Value {
+        let mut objects = vec![json!({
+            "type": "identity",
+            "spec_version": "2.1",
+            "id": self.identity_id,
+            "created": self.created,
+            "modified": self.created,
+            "name": "Rayhunter",
+            "identity_class": "system",
+        })];
+        for (cell, findings) in &self.cells {
+            let analyzers: Vec<&str> = findings.analyzers.iter().map(String::as_str).collect();
+            objects.push(json!({
+                "type": "indicator",
+                "spec_version": "2.1",
+                "id": stix_id("indicator", cell),
+                "created": self.created,
+                "modified": self.created,
+                "created_by_ref": self.identity_id,
+                "name": format!("Suspicious cell {cell}"),
+                "description": format!("Flagged by rayhunter's {} analyzer(s)", analyzers.join(", ")),
+                "indicator_types": ["anomalous-activity"],
+                "pattern": format!("[{CELL_OBJECT_TYPE}:id = '{}']", pattern_literal(cell)),
+                "pattern_type": "stix",
+                "valid_from": findings
+                    .first_seen
+                    .as_ref()
+                    .map(stix_timestamp)
+                    .unwrap_or_else(|| self.created.clone()),
+            }));
+        }
+        objects.extend(self.objects);
+        json!({
+            "type": "bundle",
+            "id": stix_id("bundle", &self.recording),
+            "objects": objects,
+        })
+    }
+}
+
+pub async fn get_analysis_report_stix(
+    State(state): State<Arc<ServerState>>,
+    Path(qmdl_name): Path<String>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let (analysis_file, start_time) = {
+        let qmdl_store = state.qmdl_store_lock.read().await;
+        let (entry_index, entry) = qmdl_store.entry_for_name(&qmdl_name).ok_or((
+            StatusCode::NOT_FOUND,
+            format!("Couldn't find QMDL entry with name \"{qmdl_name}\""),
+        ))?;
+        let start_time = entry.start_time;
+        let analysis_file = qmdl_store
+            .open_entry_analysis(entry_index)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}")))?;
+        (analysis_file, start_time)
+    };
+
+    let mut lines = BufReader::new(analysis_file).lines();
+    let read_error = |e: std::io::Error| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}"));
+    let metadata_line = lines.next_line().await.map_err(read_error)?;
+    let format = parse_report_format(metadata_line.as_deref())?;
+    let analyzer_names = parse_analyzer_names(metadata_line.as_deref(), &qmdl_name);
+    let mut builder = StixBundleBuilder::new(&qmdl_name, start_time);
+    while let Some(line) = lines.next_line().await.map_err(read_error)? {
+        match format.parse_row(&line) {
+            Ok(rows) => rows
+                .iter()
+                .for_each(|row| builder.add_row(row, &analyzer_names)),
+            Err(err) => warn!("skipping unparseable analysis row: {err}"),
+        }
+    }
+    Ok(Json(builder.finish()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stix_bundle() {
+        let start_time = DateTime::parse_from_rfc3339("2024-01-01T00:00:00+00:00")
+            .unwrap()
+            .with_timezone(&Local);
+        let analyzer_names = vec!["PCI Conflict".to_string(), "Null Cipher".to_string()];
+        let line = r#"{"packet_timestamp":"2024-01-01T01:00:00+01:00","skipped_message_reason":null,"events":[{"event_type":{"type":"QualitativeWarning","severity":"Medium"},"message":"a","evidence":[["cell","310-410 (TAC 1, cell ID 2)"]]},{"event_type":{"type":"QualitativeWarning","severity":"High"},"message":"b"}]}"#;
+        let row: AnalysisRow = serde_json::from_str(line).unwrap();
+        let mut builder = StixBundleBuilder::new("1720000000", start_time);
+        builder.add_row(&row, &analyzer_names);
+        let bundle = builder.finish();
+
+        let objects = bundle["objects"].as_array().unwrap();
+        let types: Vec<&str> = objects
+            .iter()
+            .map(|object| object["type"].as_str().unwrap())
+            .collect();
+        assert_eq!(types, ["identity", "indicator", "sighting", "note"]);
+        let indicator = &objects[1];
+        assert_eq!(
+            indicator["pattern"],
+            "[x-rayhunter-cell:id = '310-410 (TAC 1, cell ID 2)']"
+        );
+        assert_eq!(indicator["valid_from"], "2024-01-01T00:00:00.000Z");
+        assert_eq!(objects[2]["sighting_of_ref"], indicator["id"]);
+        assert_eq!(objects[2]["x_rayhunter_analyzer"], "PCI Conflict");
+        assert_eq!(objects[3]["abstract"], "Null Cipher: b");
+
+        // ids are stable, and look like UUIDs
+        let id = stix_id("indicator", "310-410 (TAC 1, cell ID 2)");
+        assert_eq!(indicator["id"], id.as_str());
+        let uuid = id.strip_prefix("indicator--").unwrap();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "8");
+    }
+}
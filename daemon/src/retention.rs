@@ -0,0 +1,301 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Local, TimeDelta};
+use log::{info, warn};
+use tokio::sync::{RwLock, oneshot};
+use tokio::time::{Instant, interval_at};
+use tokio_util::task::TaskTracker;
+
+use crate::analysis::AnalysisStatus;
+use crate::qmdl_store::RecordingStore;
+
+// How long after startup the retention policy is first applied, and how often
+// after that
+const RETENTION_STARTUP_DELAY: Duration = Duration::from_secs(60);
+const RETENTION_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+fn days(days: u64) -> TimeDelta {
+    TimeDelta::try_days(i64::try_from(days).unwrap_or(i64::MAX)).unwrap_or(TimeDelta::MAX)
+}
+
+// QMDL files are pruned per max_age and max_total_qmdl_bytes, keeping their
+// analyses, which are only deleted, along with the rest of the recording,
+// once they're older than analysis_max_age. That way the findings can be kept
+// long after the much larger QMDL files are gone.
+#[derive(Debug, Clone, Copy)]
+pub struct RetentionPolicy {
+    pub max_age: Option<TimeDelta>,
+    pub max_total_qmdl_bytes: Option<u64>,
+    pub analysis_max_age: Option<TimeDelta>,
+}
+
+impl RetentionPolicy {
+    // Returns None if no limit is set, in which case nothing is pruned
+    pub fn new(
+        max_age_days: Option<u64>,
+        max_total_qmdl_bytes: Option<u64>,
+        analysis_max_age_days: Option<u64>,
+    ) -> Option<Self> {
+        if max_age_days.is_none()
+            && max_total_qmdl_bytes.is_none()
+            && analysis_max_age_days.is_none()
+        {
+            return None;
+        }
+        Some(RetentionPolicy {
+            max_age: max_age_days.map(days),
+            max_total_qmdl_bytes,
+            analysis_max_age: analysis_max_age_days.map(days),
+        })
+    }
+}
+
+// A QMDL file, along with the entries that share it
+#[derive(Debug)]
+struct QmdlFile {
+    entry_names: Vec<String>,
+    start_time: DateTime<Local>,
+    size_bytes: u64,
+    // whether it can be pruned without losing anything we can't get back
+    prunable: bool,
+}
+
+// Picks which files to prune, oldest first, along with why. Files that
+// can't be pruned still count toward the total size.
+fn select_prunes(
+    files: &[QmdlFile],
+    policy: &RetentionPolicy,
+    now: DateTime<Local>,
+) -> Vec<(usize, String)> {
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by_key(|&i| files[i].start_time);
+    let mut total_bytes: u64 = files.iter().map(|file| file.size_bytes).sum();
+    let mut prunes = Vec::new();
+    for i in order {
+        let file = &files[i];
+        if !file.prunable {
+            continue;
+        }
+        let reason = match (policy.max_age, policy.max_total_qmdl_bytes) {
+            (Some(max_age), _) if now - file.start_time > max_age => {
+                format!("older than {} days", max_age.num_days())
+            }
+            (_, Some(max_bytes)) if total_bytes > max_bytes => {
+                format!("recordings take up more than {max_bytes} bytes")
+            }
+            _ => continue,
+        };
+        total_bytes = total_bytes.saturating_sub(file.size_bytes);
+        prunes.push((i, reason));
+    }
+    prunes
+}
+
+fn is_prunable(
+    qmdl_store: &RecordingStore,
+    analysis_status: &AnalysisStatus,
+    entry_index: usize,
+) -> bool {
+    let entry = &qmdl_store.manifest.entries[entry_index];
+    // the analysis is all that's kept, so it has to be complete and current
+    !entry.qmdl_pruned
+        && qmdl_store.current_entry != Some(entry_index)
+        && analysis_status.is_analyzed(&entry.name)
+        && analysis_status.failure(&entry.name).is_none()
+        && analysis_status.in_memory_report(&entry.name).is_none()
+}
+
+fn list_qmdl_files(qmdl_store: &RecordingStore, analysis_status: &AnalysisStatus) -> Vec<QmdlFile> {
+    let mut files: BTreeMap<&str, QmdlFile> = BTreeMap::new();
+    for (entry_index, entry) in qmdl_store.manifest.entries.iter().enumerate() {
+        if entry.qmdl_pruned {
+            continue;
+        }
+        let prunable = is_prunable(qmdl_store, analysis_status, entry_index);
+        let file = files.entry(entry.file_stem()).or_insert_with(|| QmdlFile {
+            entry_names: Vec::new(),
+            start_time: entry.start_time,
+            size_bytes: entry.qmdl_size_bytes as u64,
+            prunable: true,
+        });
+        file.entry_names.push(entry.name.clone());
+        // pruned along with it
+        file.size_bytes += entry.paired_qmdl_size_bytes.unwrap_or(0) as u64;
+        file.start_time = file.start_time.min(entry.start_time);
+        file.prunable &= prunable;
+    }
+    files.into_values().collect()
+}
+
+// Whether a recording can be deleted outright, analysis and all
+fn is_expirable(
+    qmdl_store: &RecordingStore,
+    analysis_status: &AnalysisStatus,
+    entry_index: usize,
+) -> bool {
+    let entry = &qmdl_store.manifest.entries[entry_index];
+    qmdl_store.current_entry != Some(entry_index) && !analysis_status.is_pending(&entry.name)
+}
+
+// Deletes the recordings, analyses included, that are older than max_age
+async fn expire_recordings(
+    max_age: TimeDelta,
+    qmdl_store_lock: &RwLock<RecordingStore>,
+    analysis_status_lock: &RwLock<AnalysisStatus>,
+) {
+    let now = Local::now();
+    let expired: Vec<String> = {
+        let analysis_status = analysis_status_lock.read().await;
+        let qmdl_store = qmdl_store_lock.read().await;
+        qmdl_store
+            .manifest
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(entry_index, entry)| {
+                now - entry.start_time > max_age
+                    && is_expirable(&qmdl_store, &analysis_status, *entry_index)
+            })
+            .map(|(_, entry)| entry.name.clone())
+            .collect()
+    };
+
+    for name in expired {
+        let mut analysis_status = analysis_status_lock.write().await;
+        let mut qmdl_store = qmdl_store_lock.write().await;
+        let still_expirable = qmdl_store
+            .entry_for_name(&name)
+            .is_some_and(|(entry_index, _)| {
+                is_expirable(&qmdl_store, &analysis_status, entry_index)
+            });
+        if !still_expirable {
+            continue;
+        }
+        match qmdl_store.delete_entry(&name).await {
+            Ok(_) => {
+                analysis_status.forget(&name);
+                info!(
+                    "deleted {name} and its analysis: older than {} days",
+                    max_age.num_days()
+                );
+            }
+            Err(err) => warn!("failed to delete {name}: {err}"),
+        }
+    }
+}
+
+// Deletes the recordings whose analyses have expired, then the QMDL files the
+// policy calls for, keeping their analyses
+async fn apply_retention_policy(
+    policy: &RetentionPolicy,
+    qmdl_store_lock: &RwLock<RecordingStore>,
+    analysis_status_lock: &RwLock<AnalysisStatus>,
+) {
+    if let Some(max_age) = policy.analysis_max_age {
+        expire_recordings(max_age, qmdl_store_lock, analysis_status_lock).await;
+    }
+    let (files, prunes) = {
+        let analysis_status = analysis_status_lock.read().await;
+        let qmdl_store = qmdl_store_lock.read().await;
+        let files = list_qmdl_files(&qmdl_store, &analysis_status);
+        let prunes = select_prunes(&files, policy, Local::now());
+        (files, prunes)
+    };
+
+    for (file_index, reason) in prunes {
+        let file = &files[file_index];
+        let analysis_status = analysis_status_lock.read().await;
+        let mut qmdl_store = qmdl_store_lock.write().await;
+        // things may have changed since the files were listed, e.g. one of
+        // the entries got queued for re-analysis
+        let still_prunable = file.entry_names.iter().all(|name| {
+            qmdl_store
+                .entry_for_name(name)
+                .is_some_and(|(entry_index, _)| {
+                    is_prunable(&qmdl_store, &analysis_status, entry_index)
+                })
+        });
+        if !still_prunable {
+            continue;
+        }
+        let Some((entry_index, _)) = qmdl_store.entry_for_name(&file.entry_names[0]) else {
+            continue;
+        };
+        let names = file.entry_names.join(", ");
+        match qmdl_store.prune_entry_qmdl(entry_index).await {
+            Ok(bytes) => info!("pruned QMDL file of {names} ({bytes} bytes): {reason}"),
+            Err(err) => warn!("failed to prune QMDL file of {names}: {err}"),
+        }
+    }
+}
+
+pub fn run_retention_thread(
+    task_tracker: &TaskTracker,
+    policy: RetentionPolicy,
+    qmdl_store_lock: Arc<RwLock<RecordingStore>>,
+    analysis_status_lock: Arc<RwLock<AnalysisStatus>>,
+    mut shutdown_rx: oneshot::Receiver<()>,
+) {
+    task_tracker.spawn(async move {
+        let mut ticks = interval_at(
+            Instant::now() + RETENTION_STARTUP_DELAY,
+            RETENTION_CHECK_INTERVAL,
+        );
+        loop {
+            tokio::select! {
+                _ = &mut shutdown_rx => return,
+                _ = ticks.tick() => {
+                    apply_retention_policy(&policy, &qmdl_store_lock, &analysis_status_lock)
+                        .await;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_prunes() {
+        let now = Local::now();
+        let file = |name: &str, age_days: i64, size_bytes: u64, prunable: bool| QmdlFile {
+            entry_names: vec![name.to_string()],
+            start_time: now - TimeDelta::days(age_days),
+            size_bytes,
+            prunable,
+        };
+        let files = [
+            file("new", 1, 100, true),
+            file("ancient", 30, 100, true),
+            file("old", 10, 100, true),
+            // e.g. the one being recorded
+            file("older", 20, 100, false),
+        ];
+        let selected = |policy: RetentionPolicy| -> Vec<&str> {
+            select_prunes(&files, &policy, now)
+                .into_iter()
+                .map(|(i, _)| files[i].entry_names[0].as_str())
+                .collect()
+        };
+
+        assert_eq!(
+            selected(RetentionPolicy::new(Some(15), None, None).unwrap()),
+            ["ancient"]
+        );
+        // the unprunable file still counts toward the total
+        assert_eq!(
+            selected(RetentionPolicy::new(None, Some(200), None).unwrap()),
+            ["ancient", "old"]
+        );
+        assert_eq!(
+            selected(RetentionPolicy::new(Some(15), Some(300), None).unwrap()),
+            ["ancient"]
+        );
+        assert!(RetentionPolicy::new(None, None, None).is_none());
+        assert!(RetentionPolicy::new(None, None, Some(365)).is_some());
+    }
+}
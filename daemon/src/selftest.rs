@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use axum::Json;
+use axum::extract::State;
+use rayhunter::analysis::analyzer::{Analyzer, AnalyzerConfig, Harness};
+use rayhunter::analysis::imsi_requested::ImsiRequestedAnalyzer;
+use rayhunter::analysis::null_cipher::NullCipherAnalyzer;
+use rayhunter::fixtures::{analyze_containers, containers_from_qmdl};
+use serde::Serialize;
+
+use crate::server::ServerState;
+
+// A short capture that should set off two analyzers: a SIB1, then a NAS
+// Identity Request for the IMSI that no Attach Request came before, then an
+// RRC Security Mode Command selecting the null cipher (EEA0)
+const SAMPLE_QMDL: &[u8] = include_bytes!("../fixtures/selftest.qmdl");
+
+fn expected_analyzers() -> Vec<String> {
+    vec![
+        ImsiRequestedAnalyzer::new().get_name().to_string(),
+        NullCipherAnalyzer {}.get_name().to_string(),
+    ]
+}
+
+#[derive(Debug, Serialize)]
+pub struct SelfTestCheck {
+    pub analyzer: String,
+    // whether the analyzer is enabled at all. Disabled ones can't find
+    // anything.
+    pub enabled: bool,
+    pub found: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SelfTestResult {
+    pub passed: bool,
+    pub checks: Vec<SelfTestCheck>,
+    // the analyzers that didn't find what they should have
+    pub missing: Vec<String>,
+}
+
+// Runs the configured analyzers over the built-in sample and checks each of
+// the expected analyzers found something in it
+pub fn run_selftest(analyzer_config: &AnalyzerConfig) -> SelfTestResult {
+    let mut harness = Harness::new_with_config(analyzer_config);
+    let enabled: Vec<String> = harness
+        .get_metadata()
+        .analyzers
+        .into_iter()
+        .map(|analyzer| analyzer.name)
+        .collect();
+    let (_, findings) = analyze_containers(&mut harness, containers_from_qmdl(SAMPLE_QMDL));
+    let checks: Vec<SelfTestCheck> = expected_analyzers()
+        .into_iter()
+        .map(|analyzer| SelfTestCheck {
+            enabled: enabled.contains(&analyzer),
+            found: findings.iter().any(|finding| finding.analyzer == analyzer),
+            analyzer,
+        })
+        .collect();
+    let missing: Vec<String> = checks
+        .iter()
+        .filter(|check| !check.found)
+        .map(|check| check.analyzer.clone())
+        .collect();
+    SelfTestResult {
+        passed: missing.is_empty(),
+        checks,
+        missing,
+    }
+}
+
+pub async fn post_selftest(State(state): State<Arc<ServerState>>) -> Json<SelfTestResult> {
+    Json(run_selftest(&state.config.analyzers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+    use rayhunter::diag::Nas4GMessageDirection;
+    use rayhunter::fixtures::FixtureBuilder;
+    use rayhunter::gsmtap::LteRrcSubtype;
+
+    #[test]
+    fn test_sample_matches_builder() {
+        let sib1 = [
+            0x48, 0x4c, 0x46, 0x90, 0x10, 0x60, 0x00, 0x18, 0xfd, 0x1a, 0x92, 0x07, 0xe2, 0x21,
+            0x03, 0x10, 0x8a, 0xc2, 0x1b, 0xdc, 0x09, 0x80, 0x22, 0x92, 0xcd, 0xd2, 0x00, 0x00,
+        ];
+        let builder = FixtureBuilder::new()
+            .lte_rrc(LteRrcSubtype::BcchDlSch, &sib1)
+            .advance(TimeDelta::seconds(1))
+            .nas(Nas4GMessageDirection::Downlink, &[0x07, 0x55, 0x01])
+            .advance(TimeDelta::seconds(1))
+            .lte_rrc(LteRrcSubtype::DlDcch, &[0x30, 0x00, 0x20]);
+        assert_eq!(builder.to_qmdl(), SAMPLE_QMDL);
+    }
+
+    #[test]
+    fn test_selftest() {
+        let result = run_selftest(&AnalyzerConfig::default());
+        assert!(result.passed, "{result:?}");
+
+        let config = AnalyzerConfig {
+            null_cipher: false,
+            ..AnalyzerConfig::default()
+        };
+        let result = run_selftest(&config);
+        assert!(!result.passed);
+        assert_eq!(
+            result.missing,
+            [NullCipherAnalyzer {}.get_name().to_string()]
+        );
+        assert!(!result.checks[1].enabled);
+    }
+}
@@ -5,23 +5,36 @@ use async_zip::tokio::write::ZipFileWriter;
 use axum::Json;
 use axum::body::Body;
 use axum::extract::Path;
-use axum::extract::State;
-use axum::http::header::{self, CONTENT_LENGTH, CONTENT_TYPE};
-use axum::http::{HeaderValue, StatusCode};
+use axum::extract::{Query, State};
+use axum::http::header::{self, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
-use log::{error, warn};
+use chrono::{DateTime, Local};
+use futures::StreamExt;
+use log::{error, info, warn};
+use rayhunter::diag::DataType;
+use rayhunter::qmdl::QmdlReader;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io::SeekFrom;
+use std::ops::RangeInclusive;
 use std::sync::Arc;
-use tokio::fs::write;
-use tokio::io::{AsyncReadExt, copy, duplex};
+use tempfile::TempPath;
+use tokio::fs::{File, write};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, copy, duplex};
 use tokio::sync::mpsc::Sender;
-use tokio::sync::{RwLock, oneshot};
+use tokio::sync::{RwLock, Semaphore, broadcast, oneshot};
 use tokio_util::compat::FuturesAsyncWriteCompatExt;
 use tokio_util::io::ReaderStream;
 
-use crate::analysis::{AnalysisCtrlMessage, AnalysisStatus};
+use crate::analysis::{AnalysisCtrlMessage, AnalysisStatus, AnalysisUpdate, queue_qmdl};
+use crate::compaction::CompactionStatus;
 use crate::config::Config;
+use crate::middleware::current_request_id;
+use crate::notification_queue::NotificationQueue;
 use crate::pcap::generate_pcap_data;
-use crate::qmdl_store::RecordingStore;
+use crate::qmdl_store::{ImportMetadata, ManifestEntry, RecordingStore, RecordingStoreError};
+use crate::recent_findings::RecentFindings;
 use crate::{DiagDeviceCtrlMessage, display};
 
 pub struct ServerState {
@@ -32,7 +45,13 @@ pub struct ServerState {
     pub ui_update_sender: Sender<display::DisplayState>,
     pub analysis_status_lock: Arc<RwLock<AnalysisStatus>>,
     pub analysis_sender: Sender<AnalysisCtrlMessage>,
+    pub analysis_update_sender: broadcast::Sender<AnalysisUpdate>,
+    pub compaction_status_lock: Arc<RwLock<CompactionStatus>>,
     pub daemon_restart_tx: Arc<RwLock<Option<oneshot::Sender<()>>>>,
+    // bounds how many analysis files get read at once for summaries
+    pub summary_read_permits: Arc<Semaphore>,
+    pub recent_findings: RecentFindings,
+    pub notification_queue: Option<NotificationQueue>,
 }
 
 pub async fn get_qmdl(
@@ -65,6 +84,347 @@ pub async fn get_qmdl(
     Ok((headers, body).into_response())
 }
 
+// Parses a Range header asking for a single range of bytes out of `len`.
+// Returns Ok(None) for anything we don't support (e.g. several ranges),
+// which just gets the whole file, and Err(()) if the range is unsatisfiable.
+fn parse_byte_range(range: &str, len: u64) -> Result<Option<RangeInclusive<u64>>, ()> {
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return Ok(None);
+    };
+    if spec.contains(',') {
+        return Ok(None);
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return Ok(None);
+    };
+    let (start, end) = (start.trim(), end.trim());
+    let range = if start.is_empty() {
+        // the last `end` bytes
+        let Ok(suffix) = end.parse::<u64>() else {
+            return Ok(None);
+        };
+        if suffix == 0 || len == 0 {
+            return Err(());
+        }
+        len.saturating_sub(suffix)..=len - 1
+    } else {
+        let Ok(start) = start.parse::<u64>() else {
+            return Ok(None);
+        };
+        let end = if end.is_empty() {
+            u64::MAX
+        } else {
+            match end.parse::<u64>() {
+                Ok(end) if end >= start => end,
+                _ => return Ok(None),
+            }
+        };
+        if start >= len {
+            return Err(());
+        }
+        start..=end.min(len - 1)
+    };
+    Ok(Some(range))
+}
+
+// Serves a recording's QMDL like get_qmdl, but also honors single byte
+// ranges, so that tools can fetch just the messages around a finding (e.g.
+// using the offsets in its dead letters) without downloading the whole
+// capture
+pub async fn get_recording_raw(
+    State(state): State<Arc<ServerState>>,
+    Path(qmdl_name): Path<String>,
+    request_headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let qmdl_idx = qmdl_name.trim_end_matches(".qmdl");
+    let qmdl_store = state.qmdl_store_lock.read().await;
+    let (entry_index, entry) = qmdl_store.entry_for_name(qmdl_idx).ok_or((
+        StatusCode::NOT_FOUND,
+        format!("couldn't find qmdl file with name {qmdl_idx}"),
+    ))?;
+    let mut qmdl_file = qmdl_store
+        .open_entry_qmdl(entry_index)
+        .await
+        .map_err(|err| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("error opening QMDL file: {err}"),
+            )
+        })?;
+    let len = entry.qmdl_size_bytes as u64;
+    drop(qmdl_store);
+
+    let range = match request_headers
+        .get(RANGE)
+        .and_then(|range| range.to_str().ok())
+    {
+        Some(range) => parse_byte_range(range, len).map_err(|_| {
+            (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                format!("range {range:?} is outside of the {len} byte recording"),
+            )
+        })?,
+        None => None,
+    };
+    let Some(range) = range else {
+        let headers = [
+            (CONTENT_TYPE, "application/octet-stream".to_string()),
+            (CONTENT_LENGTH, len.to_string()),
+            (ACCEPT_RANGES, "bytes".to_string()),
+        ];
+        let body = Body::from_stream(ReaderStream::new(qmdl_file.take(len)));
+        return Ok((headers, body).into_response());
+    };
+
+    // offsets into imported recordings that are stored gzipped don't line up
+    // with the offsets of the messages in them
+    let mut magic = [0; 2];
+    let is_gzipped = qmdl_file.read_exact(&mut magic).await.is_ok() && magic == [0x1f, 0x8b];
+    if is_gzipped {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("{qmdl_idx} is stored compressed, so it can't be read by byte range"),
+        ));
+    }
+    qmdl_file
+        .seek(SeekFrom::Start(*range.start()))
+        .await
+        .map_err(|err| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("error seeking in QMDL file: {err}"),
+            )
+        })?;
+    let range_len = range.end() - range.start() + 1;
+    let headers = [
+        (CONTENT_TYPE, "application/octet-stream".to_string()),
+        (CONTENT_LENGTH, range_len.to_string()),
+        (ACCEPT_RANGES, "bytes".to_string()),
+        (
+            CONTENT_RANGE,
+            format!("bytes {}-{}/{len}", range.start(), range.end()),
+        ),
+    ];
+    let body = Body::from_stream(ReaderStream::new(qmdl_file.take(range_len)));
+    Ok((StatusCode::PARTIAL_CONTENT, headers, body).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportParams {
+    pub name: Option<String>,
+    pub start_time: Option<DateTime<Local>>,
+    pub notes: Option<String>,
+    // whether to queue the recording for analysis, defaulting to true
+    pub analyze: Option<bool>,
+}
+
+// How many messages at the start of an upload we'll look at for one we can
+// parse before rejecting it
+const QMDL_VALIDATION_MESSAGES: usize = 16;
+
+// Checks whether a file looks like QMDL (possibly gzipped) by trying to parse
+// the first few messages in it. Captures from other tools may contain
+// messages we don't understand, so a single parseable one is enough.
+async fn is_plausible_qmdl(path: &std::path::Path, size_bytes: usize) -> std::io::Result<bool> {
+    let file = File::open(path).await?;
+    let mut reader = QmdlReader::new_maybe_compressed(file, Some(size_bytes)).await?;
+    let mut messages_checked = 0;
+    while messages_checked < QMDL_VALIDATION_MESSAGES {
+        let Some(container) = reader.get_next_messages_container().await? else {
+            break;
+        };
+        if container.data_type != DataType::UserSpace {
+            continue;
+        }
+        for message in container.into_messages() {
+            if message.is_ok() {
+                return Ok(true);
+            }
+            messages_checked += 1;
+        }
+    }
+    Ok(false)
+}
+
+// A QMDL file uploaded in a request body, written to a temporary file in the
+// store
+struct QmdlUpload {
+    path: TempPath,
+    sha256: String,
+    size_bytes: usize,
+}
+
+// Writes the QMDL file in the request body to a temporary file in the store,
+// so we're not holding the store lock for the whole transfer. The temp file
+// is removed on drop if anything fails before the store takes ownership of
+// it.
+async fn receive_qmdl_upload(
+    state: &ServerState,
+    body: Body,
+) -> Result<QmdlUpload, (StatusCode, String)> {
+    let internal_error = |err: std::io::Error| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("failed to write imported recording: {err}"),
+        )
+    };
+    let store_path = state.qmdl_store_lock.read().await.path.clone();
+    let (upload_file, upload_path) = tempfile::Builder::new()
+        .prefix("import-")
+        .tempfile_in(&store_path)
+        .map_err(internal_error)?
+        .into_parts();
+    let mut upload_file = File::from_std(upload_file);
+    let mut hasher = Sha256::new();
+    let mut size_bytes = 0;
+    let mut upload_stream = body.into_data_stream();
+    while let Some(chunk) = upload_stream.next().await {
+        let chunk = chunk.map_err(|err| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("failed to read uploaded recording: {err}"),
+            )
+        })?;
+        hasher.update(&chunk);
+        size_bytes += chunk.len();
+        upload_file
+            .write_all(&chunk)
+            .await
+            .map_err(internal_error)?;
+    }
+    upload_file.flush().await.map_err(internal_error)?;
+    let sha256: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    if !is_plausible_qmdl(&upload_path, size_bytes)
+        .await
+        .map_err(internal_error)?
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "uploaded file doesn't look like a QMDL recording".to_string(),
+        ));
+    }
+    Ok(QmdlUpload {
+        path: upload_path,
+        sha256,
+        size_bytes,
+    })
+}
+
+// Adds the QMDL file in the request body to the store as a new recording,
+// optionally with a name and other metadata given as query parameters. If its
+// contents match an already imported recording, the new entry just shares
+// that recording's files (and analysis) instead of storing them twice.
+pub async fn import_recording(
+    State(state): State<Arc<ServerState>>,
+    Query(params): Query<ImportParams>,
+    body: Body,
+) -> Result<(StatusCode, Json<ManifestEntry>), (StatusCode, String)> {
+    if state.config.debug_mode {
+        return Err((StatusCode::FORBIDDEN, "server is in debug mode".to_string()));
+    }
+    let upload = receive_qmdl_upload(&state, body).await?;
+
+    let metadata = ImportMetadata {
+        name: params.name,
+        start_time: params.start_time,
+        notes: params.notes,
+    };
+    let mut analysis_status = state.analysis_status_lock.write().await;
+    let mut qmdl_store = state.qmdl_store_lock.write().await;
+    let entry_index = qmdl_store
+        .import_entry(&upload.path, upload.sha256, upload.size_bytes, metadata)
+        .await
+        .map_err(|err| {
+            let status = match err {
+                RecordingStoreError::EntryExistsError => StatusCode::CONFLICT,
+                RecordingStoreError::InvalidEntryNameError(_) => StatusCode::BAD_REQUEST,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            (status, format!("failed to import recording: {err}"))
+        })?;
+    let entry = qmdl_store.manifest.entries[entry_index].clone();
+    if let Some(original) = &entry.duplicate_of {
+        info!(
+            "imported recording {} is a duplicate of {original}",
+            entry.name
+        );
+        analysis_status.mark_finished(&entry.name);
+    } else if !params.analyze.unwrap_or(true) {
+        analysis_status.mark_unanalyzed(&entry.name);
+    } else if queue_qmdl(&entry.name, &mut analysis_status) {
+        state
+            .analysis_sender
+            .send(AnalysisCtrlMessage::NewFilesQueued(current_request_id()))
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("failed to queue imported recording for analysis: {e:?}"),
+                )
+            })?;
+    }
+    Ok((StatusCode::CREATED, Json(entry)))
+}
+
+// Adds the QMDL file in the request body to a recording as its paired file,
+// for recordings captured in two streams (e.g. control and user plane), and
+// queues the recording to be analyzed again with both streams interleaved.
+// A recording that shares its files with a duplicate can't be paired.
+pub async fn pair_recording(
+    State(state): State<Arc<ServerState>>,
+    Path(name): Path<String>,
+    body: Body,
+) -> Result<Json<ManifestEntry>, (StatusCode, String)> {
+    if state.config.debug_mode {
+        return Err((StatusCode::FORBIDDEN, "server is in debug mode".to_string()));
+    }
+    let upload = receive_qmdl_upload(&state, body).await?;
+
+    let mut analysis_status = state.analysis_status_lock.write().await;
+    let mut qmdl_store = state.qmdl_store_lock.write().await;
+    let (entry_index, _) = qmdl_store.entry_for_name(&name).ok_or((
+        StatusCode::NOT_FOUND,
+        format!("Couldn't find QMDL entry with name \"{name}\""),
+    ))?;
+    if qmdl_store.current_entry == Some(entry_index) {
+        return Err((
+            StatusCode::CONFLICT,
+            "can't pair the recording that's in progress".to_string(),
+        ));
+    }
+    qmdl_store
+        .pair_entry_qmdl(entry_index, &upload.path, upload.size_bytes)
+        .await
+        .map_err(|err| {
+            let status = match err {
+                RecordingStoreError::QmdlPrunedError | RecordingStoreError::SharedFilesError => {
+                    StatusCode::CONFLICT
+                }
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            (status, format!("failed to pair recording: {err}"))
+        })?;
+    let entry = qmdl_store.manifest.entries[entry_index].clone();
+    info!("paired a second QMDL stream with recording {name}");
+    if queue_qmdl(&entry.name, &mut analysis_status) {
+        state
+            .analysis_sender
+            .send(AnalysisCtrlMessage::NewFilesQueued(current_request_id()))
+            .await
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("failed to queue paired recording for analysis: {e:?}"),
+                )
+            })?;
+    }
+    Ok(Json(entry))
+}
+
 pub async fn serve_static(
     State(_): State<Arc<ServerState>>,
     Path(path): Path<String>,
@@ -107,16 +467,30 @@ pub async fn serve_static(
     }
 }
 
+// The API token is left out, as anyone who may read the config isn't
+// necessarily allowed to change things
 pub async fn get_config(
     State(state): State<Arc<ServerState>>,
 ) -> Result<Json<Config>, (StatusCode, String)> {
-    Ok(Json(state.config.clone()))
+    let mut config = state.config.clone();
+    config.api_token = None;
+    Ok(Json(config))
 }
 
 pub async fn set_config(
     State(state): State<Arc<ServerState>>,
-    Json(config): Json<Config>,
+    Json(mut config): Json<Config>,
 ) -> Result<(StatusCode, String), (StatusCode, String)> {
+    // get_config never hands out the API token, so a config that doesn't set
+    // one keeps the current token rather than turning authentication off
+    if config.api_token.is_none() {
+        config.api_token = state.config.api_token.clone();
+    }
+    // the daemon would refuse to start with this config after restarting
+    config
+        .check_required_analyzers()
+        .and_then(|_| config.check_server())
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
     let config_str = toml::to_string_pretty(&config).map_err(|err| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -175,6 +549,7 @@ pub async fn get_zip(
     };
 
     let qmdl_store_lock = state.qmdl_store_lock.clone();
+    let modem_profile = state.config.analyzers.modem_profile.clone();
 
     let (reader, writer) = duplex(8192);
 
@@ -217,8 +592,13 @@ pub async fn get_zip(
                         .take(qmdl_size_bytes as u64)
                 };
 
-                if let Err(e) =
-                    generate_pcap_data(&mut entry_writer, qmdl_file_for_pcap, qmdl_size_bytes).await
+                if let Err(e) = generate_pcap_data(
+                    &mut entry_writer,
+                    qmdl_file_for_pcap,
+                    qmdl_size_bytes,
+                    &modem_profile,
+                )
+                .await
                 {
                     // if we fail to generate the PCAP file, we should still continue and give the
                     // user the QMDL.
@@ -295,10 +675,11 @@ mod tests {
         let (tx, _rx) = tokio::sync::mpsc::channel(1);
         let (ui_tx, _ui_rx) = tokio::sync::mpsc::channel(1);
         let (analysis_tx, _analysis_rx) = tokio::sync::mpsc::channel(1);
+        let (analysis_update_tx, _) = tokio::sync::broadcast::channel(1);
 
         let analysis_status = {
             let store = store_lock.try_read().unwrap();
-            crate::analysis::AnalysisStatus::new(&store)
+            crate::analysis::AnalysisStatus::new(&store, false, 0)
         };
 
         Arc::new(ServerState {
@@ -309,7 +690,12 @@ mod tests {
             ui_update_sender: ui_tx,
             analysis_status_lock: Arc::new(RwLock::new(analysis_status)),
             analysis_sender: analysis_tx,
+            analysis_update_sender: analysis_update_tx,
+            compaction_status_lock: Arc::new(RwLock::new(CompactionStatus::default())),
             daemon_restart_tx: Arc::new(RwLock::new(None)),
+            summary_read_permits: Arc::new(Semaphore::new(1)),
+            recent_findings: RecentFindings::new(0),
+            notification_queue: None,
         })
     }
 
@@ -345,4 +731,63 @@ mod tests {
             vec![format!("{entry_name}.qmdl"), format!("{entry_name}.pcapng"),]
         );
     }
+
+    #[test]
+    fn test_parse_byte_range() {
+        assert_eq!(parse_byte_range("bytes=2-4", 7), Ok(Some(2..=4)));
+        assert_eq!(parse_byte_range("bytes=5-", 7), Ok(Some(5..=6)));
+        assert_eq!(parse_byte_range("bytes=5-100", 7), Ok(Some(5..=6)));
+        assert_eq!(parse_byte_range("bytes=-2", 7), Ok(Some(5..=6)));
+        assert_eq!(parse_byte_range("bytes=-100", 7), Ok(Some(0..=6)));
+        assert_eq!(parse_byte_range("bytes=7-", 7), Err(()));
+        assert_eq!(parse_byte_range("bytes=-0", 7), Err(()));
+        // unsupported ranges get the whole file
+        assert_eq!(parse_byte_range("bytes=0-1,3-4", 7), Ok(None));
+        assert_eq!(parse_byte_range("lines=0-1", 7), Ok(None));
+        assert_eq!(parse_byte_range("bytes=4-2", 7), Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_get_recording_raw_range() {
+        let (_temp_dir, store_lock) = create_test_qmdl_store().await;
+        let test_qmdl_data = vec![0x7E, 0x00, 0x00, 0x00, 0x10, 0x00, 0x7E];
+        let entry_name = create_test_entry_with_data(&store_lock, &test_qmdl_data).await;
+        let state = create_test_server_state(store_lock);
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(RANGE, HeaderValue::from_static("bytes=2-4"));
+        let response = get_recording_raw(
+            State(state.clone()),
+            Path(entry_name.clone()),
+            request_headers,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes 2-4/7");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body.as_ref(), &test_qmdl_data[2..=4]);
+
+        let mut request_headers = HeaderMap::new();
+        request_headers.insert(RANGE, HeaderValue::from_static("bytes=10-"));
+        let err = get_recording_raw(
+            State(state.clone()),
+            Path(entry_name.clone()),
+            request_headers,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.0, StatusCode::RANGE_NOT_SATISFIABLE);
+
+        let response = get_recording_raw(State(state), Path(entry_name), HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body.as_ref(), test_qmdl_data.as_slice());
+    }
 }
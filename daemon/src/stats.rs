@@ -1,14 +1,19 @@
 use std::sync::Arc;
 
+use crate::analysis::AnalysisStatus;
+use crate::compaction::CompactionStatus;
 use crate::qmdl_store::ManifestEntry;
 use crate::server::ServerState;
+use crate::summary::summarize_analysis_path;
 
 use axum::Json;
-use axum::extract::State;
+use axum::extract::{Query, State};
 use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
 use log::error;
+use rayhunter::analysis::analyzer::{AnalyzerMetadata, Harness, REPORT_VERSION};
 use rayhunter::util::RuntimeMetadata;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tokio::process::Command;
 
 #[derive(Debug, Serialize)]
@@ -123,20 +128,142 @@ pub async fn get_system_stats(
     }
 }
 
+// Identifies exactly which build and analyzers a daemon is running, for bug
+// reports and for clients checking they're compatible with it
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+    pub rayhunter_version: String,
+    pub git_commit: String,
+    pub build_date: Option<DateTime<Utc>>,
+    pub report_version: u32,
+    pub analyzer_set_hash: String,
+    pub analyzers: Vec<AnalyzerMetadata>,
+}
+
+pub async fn get_version(State(state): State<Arc<ServerState>>) -> Json<VersionInfo> {
+    let harness = Harness::new_with_config(&state.config.analyzers);
+    let build_date = env!("RAYHUNTER_BUILD_TIMESTAMP")
+        .parse()
+        .ok()
+        .and_then(|timestamp| DateTime::from_timestamp(timestamp, 0));
+    Json(VersionInfo {
+        rayhunter_version: env!("CARGO_PKG_VERSION").to_string(),
+        git_commit: env!("RAYHUNTER_GIT_COMMIT").to_string(),
+        build_date,
+        report_version: REPORT_VERSION,
+        analyzer_set_hash: harness.get_analyzer_set_hash(),
+        analyzers: harness.get_metadata().analyzers,
+    })
+}
+
+// The state of rayhunter's background work, for monitoring a device that's
+// left running unattended
+#[derive(Debug, Serialize)]
+pub struct HealthStatus {
+    pub recording: bool,
+    pub analysis: AnalysisStatus,
+    pub compaction: CompactionStatus,
+    // post-analysis hook runs waiting to be retried
+    pub pending_notifications: usize,
+}
+
+pub async fn get_health(State(state): State<Arc<ServerState>>) -> Json<HealthStatus> {
+    let recording = state.qmdl_store_lock.read().await.current_entry.is_some();
+    let analysis = state.analysis_status_lock.read().await.clone();
+    let compaction = state.compaction_status_lock.read().await.clone();
+    let pending_notifications = match &state.notification_queue {
+        Some(queue) => queue.len().await,
+        None => 0,
+    };
+    Json(HealthStatus {
+        recording,
+        analysis,
+        compaction,
+        pending_notifications,
+    })
+}
+
 #[derive(Serialize)]
 pub struct ManifestStats {
     pub entries: Vec<ManifestEntry>,
     pub current_entry: Option<ManifestEntry>,
+    // the number of (non-current) entries before pagination was applied
+    pub total_entries: usize,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestSort {
+    #[default]
+    StartTime,
+    Size,
+    Warnings,
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct ManifestParams {
+    pub offset: usize,
+    pub limit: Option<usize>,
+    pub sort: ManifestSort,
+    pub order: SortOrder,
 }
 
 pub async fn get_qmdl_manifest(
     State(state): State<Arc<ServerState>>,
+    Query(params): Query<ManifestParams>,
 ) -> Result<Json<ManifestStats>, (StatusCode, String)> {
-    let qmdl_store = state.qmdl_store_lock.read().await;
-    let mut entries = qmdl_store.manifest.entries.clone();
-    let current_entry = qmdl_store.current_entry.map(|index| entries.remove(index));
+    let (mut entries, current_entry, store_path) = {
+        let qmdl_store = state.qmdl_store_lock.read().await;
+        let mut entries = qmdl_store.manifest.entries.clone();
+        let current_entry = qmdl_store.current_entry.map(|index| entries.remove(index));
+        (entries, current_entry, qmdl_store.path.clone())
+    };
+    let total_entries = entries.len();
+
+    match params.sort {
+        // entries are already kept in chronological order
+        ManifestSort::StartTime => {}
+        ManifestSort::Size => entries.sort_by_key(|entry| entry.qmdl_size_bytes),
+        ManifestSort::Warnings => {
+            // this means reading every analysis file, but only as a stream,
+            // and only when a client actually asks for it
+            let mut keyed = Vec::with_capacity(entries.len());
+            for entry in entries {
+                let path = entry.get_analysis_filepath(&store_path);
+                let warning_count = match summarize_analysis_path(&path).await {
+                    Ok(summary) => summary.map_or(0, |summary| summary.warning_count),
+                    Err(err) => {
+                        error!("failed to summarize analysis for {}: {err}", entry.name);
+                        0
+                    }
+                };
+                keyed.push((warning_count, entry));
+            }
+            keyed.sort_by_key(|(warning_count, _)| *warning_count);
+            entries = keyed.into_iter().map(|(_, entry)| entry).collect();
+        }
+    }
+    if params.order == SortOrder::Desc {
+        entries.reverse();
+    }
+
+    let entries = entries
+        .into_iter()
+        .skip(params.offset)
+        .take(params.limit.unwrap_or(usize::MAX))
+        .collect();
     Ok(Json(ManifestStats {
         entries,
         current_entry,
+        total_entries,
     }))
 }
@@ -0,0 +1,366 @@
+use std::collections::BTreeMap;
+use std::future;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::header::{CONTENT_TYPE, VARY};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, FixedOffset};
+use futures::{StreamExt, stream};
+use log::warn;
+use rayhunter::analysis::analyzer::{AnalysisRow, CaptureMetadata, EventType, Severity};
+use rayhunter::analysis::report_format::ReportFormat;
+use rayhunter::qmdl::QmdlInput;
+use serde::Serialize;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+use crate::compaction::collapsed_repeats;
+use crate::etag::ETagBuilder;
+use crate::server::ServerState;
+use crate::wire_format::WireFormat;
+
+// A condensed view of a recording's analysis report, for clients that only
+// need to know whether anything suspicious happened rather than every row
+#[derive(Debug, Serialize, Default, PartialEq)]
+pub struct AnalysisSummary {
+    pub warning_count: usize,
+    pub low_severity_count: usize,
+    pub medium_severity_count: usize,
+    pub high_severity_count: usize,
+    pub max_severity: Option<Severity>,
+    pub informational_count: usize,
+    pub skipped_message_count: usize,
+    pub first_warning_time: Option<DateTime<FixedOffset>>,
+    pub last_warning_time: Option<DateTime<FixedOffset>>,
+    // warnings with the same correlation ID count as one incident, since
+    // they came from several analyzers reacting to the same messages
+    pub incident_count: usize,
+    #[serde(skip)]
+    last_correlation_id: Option<u64>,
+    // identifies the analyzer config the report was produced with, if it
+    // was recorded
+    pub analyzer_config_hash: Option<String>,
+    // where the report's recording came from, if it was recorded
+    pub capture: Option<CaptureMetadata>,
+}
+
+impl AnalysisSummary {
+    fn add_row(&mut self, row: &AnalysisRow) {
+        if row.skipped_message_reason.is_some() {
+            self.skipped_message_count += 1;
+        }
+        let has_warning = row
+            .events
+            .iter()
+            .flatten()
+            .any(|event| matches!(event.event_type, EventType::QualitativeWarning { .. }));
+        // rows sharing a correlation ID are always written one after another
+        if has_warning
+            && (row.correlation_id.is_none() || row.correlation_id != self.last_correlation_id)
+        {
+            self.incident_count += 1;
+        }
+        if has_warning {
+            self.last_correlation_id = row.correlation_id;
+        }
+        for event in row.events.iter().flatten() {
+            // compacted reports collapse repeats of a finding into one event
+            let (repeats, last_seen) = collapsed_repeats(event);
+            let occurrences = repeats + 1;
+            match &event.event_type {
+                EventType::Informational => self.informational_count += occurrences,
+                EventType::QualitativeWarning { severity } => {
+                    self.warning_count += occurrences;
+                    match severity {
+                        Severity::Low => self.low_severity_count += occurrences,
+                        Severity::Medium => self.medium_severity_count += occurrences,
+                        Severity::High => self.high_severity_count += occurrences,
+                    }
+                    self.max_severity = self.max_severity.max(Some(*severity));
+                    if let Some(timestamp) = row.packet_timestamp {
+                        self.first_warning_time.get_or_insert(timestamp);
+                        let last = last_seen.unwrap_or(timestamp);
+                        self.last_warning_time = self.last_warning_time.max(Some(last));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Reads an analysis file line-by-line, only keeping the running totals in
+// memory. Returns None if the file doesn't even contain the report metadata,
+// i.e. it's never been analyzed. Reports from older versions are upgraded as
+// they're read, and ones from a newer version fail with InvalidData.
+pub async fn summarize_analysis_file<R: AsyncRead + Unpin>(
+    file: R,
+) -> Result<Option<AnalysisSummary>, std::io::Error> {
+    let mut lines = BufReader::new(file).lines();
+    let Some(metadata) = lines.next_line().await? else {
+        return Ok(None);
+    };
+    let metadata = serde_json::from_str::<serde_json::Value>(&metadata).ok();
+    // metadata that can't be read at all is assumed to be current
+    let format = match &metadata {
+        Some(metadata) => ReportFormat::from_metadata(metadata)
+            .map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))?,
+        None => ReportFormat::default(),
+    };
+    let mut summary = AnalysisSummary {
+        analyzer_config_hash: metadata
+            .as_ref()
+            .and_then(|metadata| Some(metadata["analyzer_config_hash"].as_str()?.to_string())),
+        capture: metadata
+            .as_ref()
+            .and_then(|metadata| serde_json::from_value(metadata.get("capture")?.clone()).ok()),
+        ..Default::default()
+    };
+    while let Some(line) = lines.next_line().await? {
+        match format.parse_row(&line) {
+            Ok(rows) => rows.iter().for_each(|row| summary.add_row(row)),
+            Err(err) => warn!("skipping unparseable analysis row: {err}"),
+        }
+    }
+    Ok(Some(summary))
+}
+
+pub async fn summarize_analysis_path(
+    path: &std::path::Path,
+) -> Result<Option<AnalysisSummary>, std::io::Error> {
+    match File::open(path).await {
+        Ok(file) => summarize_analysis_file(QmdlInput::detect(file).await?).await,
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+pub async fn get_analysis_summary(
+    State(state): State<Arc<ServerState>>,
+    Path(qmdl_name): Path<String>,
+    format: WireFormat,
+    request_headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let (name, analysis_path) = {
+        let qmdl_store = state.qmdl_store_lock.read().await;
+        let (_, entry) = if qmdl_name == "live" {
+            qmdl_store.get_current_entry().ok_or((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "No QMDL data's being recorded to analyze, try starting a new recording!"
+                    .to_string(),
+            ))?
+        } else {
+            qmdl_store.entry_for_name(&qmdl_name).ok_or((
+                StatusCode::NOT_FOUND,
+                format!("Couldn't find QMDL entry with name \"{qmdl_name}\""),
+            ))?
+        };
+        (
+            entry.name.clone(),
+            entry.get_analysis_filepath(&qmdl_store.path),
+        )
+    };
+    // if there's no analysis file, summarizing it will say so
+    let etag = match tokio::fs::metadata(&analysis_path).await {
+        Ok(metadata) => Some(ETagBuilder::new(format).file(&name, &metadata).finish()),
+        Err(_) => None,
+    };
+    if let Some(etag) = etag.as_ref().filter(|etag| etag.matches(&request_headers)) {
+        return Ok(etag.not_modified());
+    }
+    let summary = summarize_analysis_path(&analysis_path)
+        .await
+        .map_err(|e| match e.kind() {
+            // a report from a newer version of rayhunter
+            ErrorKind::InvalidData => (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:?}")),
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("QMDL entry \"{qmdl_name}\" hasn't been analyzed"),
+        ))?;
+    let etag_header = etag.as_ref().map(|etag| etag.header());
+    Ok((etag_header, format.respond(&summary)).into_response())
+}
+
+// Returns a JSON object mapping each analyzed recording's name to its summary.
+// Summaries are computed one recording at a time as the response body is
+// streamed out, so we never hold more than one in memory.
+//
+// MessagePack needs a map's length up front, so for clients that ask for it
+// they're all collected first instead.
+//
+// The ETag covers every analysis file listed, so it changes whenever a
+// recording is analyzed, re-analyzed or deleted.
+pub async fn get_analysis_summaries(
+    State(state): State<Arc<ServerState>>,
+    format: WireFormat,
+    request_headers: HeaderMap,
+) -> Response {
+    let analysis_paths: Vec<(String, PathBuf)> = {
+        let analysis_status = state.analysis_status_lock.read().await;
+        let qmdl_store = state.qmdl_store_lock.read().await;
+        qmdl_store
+            .manifest
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(entry_index, _)| qmdl_store.current_entry != Some(*entry_index))
+            .filter(|(_, entry)| analysis_status.is_analyzed(&entry.name))
+            .map(|(_, entry)| {
+                let path = entry.get_analysis_filepath(&qmdl_store.path);
+                (entry.name.clone(), path)
+            })
+            .collect()
+    };
+
+    let mut etag = ETagBuilder::new(format);
+    for (name, path) in &analysis_paths {
+        // files that are missing are left out of the summaries too
+        if let Ok(metadata) = tokio::fs::metadata(path).await {
+            etag = etag.file(name, &metadata);
+        }
+    }
+    let etag = etag.finish();
+    if etag.matches(&request_headers) {
+        return etag.not_modified();
+    }
+
+    // the permits are shared with every other request, so a few clients
+    // polling at once can't add up to more reads than the device can handle
+    let permits = state.summary_read_permits.clone();
+    let concurrency = state.config.summary_read_concurrency.max(1);
+    let summaries = stream::iter(analysis_paths)
+        .map(move |(name, path)| {
+            let permits = permits.clone();
+            async move {
+                let _permit = permits.acquire_owned().await.ok()?;
+                match summarize_analysis_path(&path).await {
+                    Ok(maybe_summary) => maybe_summary.map(|summary| (name, summary)),
+                    Err(err) => {
+                        warn!("failed to summarize analysis for {name}: {err}");
+                        None
+                    }
+                }
+            }
+        })
+        // keeps the summaries in manifest order
+        .buffered(concurrency)
+        .filter_map(future::ready);
+    if format == WireFormat::MessagePack {
+        let summaries: BTreeMap<String, AnalysisSummary> = summaries.collect().await;
+        return (etag.header(), format.respond(&summaries)).into_response();
+    }
+
+    let summaries = summaries.enumerate().map(|(i, (name, summary))| {
+        let separator = if i == 0 { "" } else { "," };
+        let name = serde_json::to_string(&name)?;
+        let summary = serde_json::to_string(&summary)?;
+        Ok::<String, serde_json::Error>(format!("{separator}{name}:{summary}"))
+    });
+    let body_stream = stream::once(future::ready(Ok("{".to_string())))
+        .chain(summaries)
+        .chain(stream::once(future::ready(Ok("}".to_string()))));
+
+    let headers = [(CONTENT_TYPE, "application/json"), (VARY, "Accept")];
+    (headers, etag.header(), Body::from_stream(body_stream)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tokio::io::AsyncSeekExt;
+
+    #[tokio::test]
+    async fn test_summarize_analysis_file() {
+        let mut file = tempfile::tempfile().unwrap();
+        let report = [
+            r#"{"analyzers":[],"rayhunter":{},"report_version":2}"#,
+            r#"{"packet_timestamp":"2024-01-01T00:00:00+00:00","skipped_message_reason":null,"events":[{"event_type":{"type":"QualitativeWarning","severity":"Low"},"message":"a"},null]}"#,
+            r#"{"packet_timestamp":null,"skipped_message_reason":"bad message","events":[]}"#,
+            r#"{"packet_timestamp":"2024-01-01T00:05:00+00:00","skipped_message_reason":null,"events":[{"event_type":{"type":"Informational"},"message":"b"},{"event_type":{"type":"QualitativeWarning","severity":"High"},"message":"c"}]}"#,
+        ];
+        writeln!(file, "{}", report.join("\n")).unwrap();
+
+        let mut file = File::from_std(file);
+        file.rewind().await.unwrap();
+        let summary = summarize_analysis_file(file).await.unwrap().unwrap();
+        assert_eq!(
+            summary,
+            AnalysisSummary {
+                warning_count: 2,
+                low_severity_count: 1,
+                medium_severity_count: 0,
+                high_severity_count: 1,
+                max_severity: Some(Severity::High),
+                informational_count: 1,
+                skipped_message_count: 1,
+                first_warning_time: Some(
+                    DateTime::parse_from_rfc3339("2024-01-01T00:00:00+00:00").unwrap()
+                ),
+                last_warning_time: Some(
+                    DateTime::parse_from_rfc3339("2024-01-01T00:05:00+00:00").unwrap()
+                ),
+                incident_count: 2,
+                last_correlation_id: None,
+                analyzer_config_hash: None,
+                capture: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_summarize_correlated_warnings() {
+        let mut file = tempfile::tempfile().unwrap();
+        let report = [
+            r#"{"analyzers":[],"rayhunter":{},"report_version":4,"analyzer_config_hash":"0123abcd","capture":{"recording":"1700000000","device":"orbic"}}"#,
+            r#"{"packet_timestamp":"2024-01-01T00:00:00+00:00","skipped_message_reason":null,"events":[{"event_type":{"type":"QualitativeWarning","severity":"Low"},"message":"a"}],"correlation_id":0}"#,
+            r#"{"packet_timestamp":"2024-01-01T00:00:00+00:00","skipped_message_reason":null,"events":[{"event_type":{"type":"QualitativeWarning","severity":"Medium"},"message":"b"}],"correlation_id":0}"#,
+            r#"{"packet_timestamp":"2024-01-01T00:01:00+00:00","skipped_message_reason":null,"events":[{"event_type":{"type":"QualitativeWarning","severity":"Low"},"message":"c"}],"correlation_id":1}"#,
+        ];
+        writeln!(file, "{}", report.join("\n")).unwrap();
+
+        let mut file = File::from_std(file);
+        file.rewind().await.unwrap();
+        let summary = summarize_analysis_file(file).await.unwrap().unwrap();
+        assert_eq!(summary.warning_count, 3);
+        assert_eq!(summary.incident_count, 2);
+        assert_eq!(summary.analyzer_config_hash.as_deref(), Some("0123abcd"));
+        let capture = summary.capture.unwrap();
+        assert_eq!(capture.recording.as_deref(), Some("1700000000"));
+        assert_eq!(capture.device, Some(rayhunter::Device::Orbic));
+    }
+
+    #[tokio::test]
+    async fn test_summarize_report_versions() {
+        let mut file = tempfile::tempfile().unwrap();
+        let report = [
+            r#"{"analyzers":[{"name":"a","description":""}],"rayhunter":{}}"#,
+            r#"{"skipped_message_reasons":["bad message"],"analysis":[{"timestamp":"2024-01-01T00:00:00+00:00","events":[{"event_type":{"type":"QualitativeWarning","severity":"Medium"},"message":"a"}]}]}"#,
+        ];
+        writeln!(file, "{}", report.join("\n")).unwrap();
+        let mut file = File::from_std(file);
+        file.rewind().await.unwrap();
+        let summary = summarize_analysis_file(file).await.unwrap().unwrap();
+        assert_eq!(summary.warning_count, 1);
+        assert_eq!(summary.medium_severity_count, 1);
+        assert_eq!(summary.skipped_message_count, 1);
+
+        let future = r#"{"analyzers":[],"rayhunter":{},"report_version":1000}"#;
+        let err = summarize_analysis_file(future.as_bytes())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn test_summarize_empty_analysis_file() {
+        let file = File::from_std(tempfile::tempfile().unwrap());
+        assert_eq!(summarize_analysis_file(file).await.unwrap(), None);
+    }
+}
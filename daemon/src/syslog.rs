@@ -0,0 +1,178 @@
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{DateTime, FixedOffset, Local, SecondsFormat};
+use log::warn;
+use rayhunter::analysis::analyzer::{Event, EventType, Severity};
+use rayhunter::util::LogLimiter;
+
+use crate::config::Config;
+
+// LOG_DAEMON
+const FACILITY: u8 = 3;
+// An SD-ID needs an enterprise number; 32473 is the one reserved for
+// documentation and examples by RFC 5612
+const SD_ID: &str = "rayhunter@32473";
+
+static SEND_ERRORS: LogLimiter = LogLimiter::new(10);
+
+fn syslog_level(severity: Severity) -> u8 {
+    match severity {
+        Severity::Low => 5,    // LOG_NOTICE
+        Severity::Medium => 4, // LOG_WARNING
+        Severity::High => 3,   // LOG_ERR
+    }
+}
+
+// Escapes an SD-PARAM value per RFC 5424 section 6.3.3
+fn escape_param_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | ']') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// SD-PARAM names are limited to 32 printable ASCII characters, excluding
+// '=', ' ', ']' and '"'
+fn sanitize_param_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_graphic() && !matches!(c, '=' | ']' | '"'))
+        .take(32)
+        .collect()
+}
+
+// Formats a warning as an RFC 5424 syslog message, with the event's details
+// and evidence as structured data. Returns None for informational events.
+fn format_finding(
+    now: DateTime<Local>,
+    recording: &str,
+    analyzer: &str,
+    packet_timestamp: Option<DateTime<FixedOffset>>,
+    event: &Event,
+) -> Option<String> {
+    let EventType::QualitativeWarning { severity } = event.event_type else {
+        return None;
+    };
+    let pri = FACILITY * 8 + syslog_level(severity);
+    let mut params = vec![
+        ("recording".to_string(), recording.to_string()),
+        ("analyzer".to_string(), analyzer.to_string()),
+        ("severity".to_string(), format!("{severity:?}")),
+    ];
+    if let Some(timestamp) = packet_timestamp {
+        params.push((
+            "packet_time".to_string(),
+            timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+        ));
+    }
+    for (name, value) in &event.evidence {
+        params.push((sanitize_param_name(name), value.clone()));
+    }
+    let structured_data: String = params
+        .iter()
+        .map(|(name, value)| format!(" {name}=\"{}\"", escape_param_value(value)))
+        .collect();
+    Some(format!(
+        "<{pri}>1 {} - rayhunter {} finding [{SD_ID}{structured_data}] {}",
+        now.to_rfc3339_opts(SecondsFormat::Millis, true),
+        std::process::id(),
+        event.message,
+    ))
+}
+
+// Sends each warning found during analysis to the system logger, so findings
+// can be collected alongside other system logs. journald picks these up from
+// the syslog socket too.
+#[derive(Clone)]
+pub struct FindingSyslog {
+    socket: Arc<UnixDatagram>,
+    socket_path: PathBuf,
+}
+
+impl FindingSyslog {
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if !config.syslog_findings {
+            return None;
+        }
+        let socket = match UnixDatagram::unbound() {
+            Ok(socket) => socket,
+            Err(err) => {
+                warn!("failed to create syslog socket, findings won't be sent to syslog: {err}");
+                return None;
+            }
+        };
+        // never block the analysis on a busy logger, dropping findings
+        // instead (they're still in the analysis file)
+        if let Err(err) = socket.set_nonblocking(true) {
+            warn!("failed to make syslog socket non-blocking: {err}");
+        }
+        Some(FindingSyslog {
+            socket: Arc::new(socket),
+            socket_path: PathBuf::from(&config.syslog_socket_path),
+        })
+    }
+
+    pub fn emit(
+        &self,
+        recording: &str,
+        analyzer: &str,
+        packet_timestamp: Option<DateTime<FixedOffset>>,
+        event: &Event,
+    ) {
+        let Some(message) =
+            format_finding(Local::now(), recording, analyzer, packet_timestamp, event)
+        else {
+            return;
+        };
+        if let Err(err) = self.socket.send_to(message.as_bytes(), &self.socket_path) {
+            if SEND_ERRORS.check("syslog send errors") {
+                warn!(
+                    "failed to send finding to syslog at {}: {err}",
+                    self.socket_path.display()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_finding() {
+        let now = DateTime::parse_from_rfc3339("2024-01-01T00:00:00+00:00")
+            .unwrap()
+            .with_timezone(&Local);
+        let event = Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::High,
+            },
+            message: "Cell suggested use of null cipher".to_string(),
+            explanation: String::new(),
+            evidence: vec![(
+                "message_type".to_string(),
+                "a \"quoted\" value]".to_string(),
+            )],
+        };
+        let message = format_finding(now, "1720000000", "Null Cipher", None, &event).unwrap();
+        assert!(message.starts_with("<27>1 "));
+        assert!(message.contains(&format!(
+            "finding [{SD_ID} recording=\"1720000000\" analyzer=\"Null Cipher\" severity=\"High\" message_type=\"a \\\"quoted\\\" value\\]\"] Cell suggested use of null cipher"
+        )));
+
+        let info = Event {
+            event_type: EventType::Informational,
+            ..event
+        };
+        assert_eq!(
+            format_finding(now, "1720000000", "Null Cipher", None, &info),
+            None
+        );
+    }
+}
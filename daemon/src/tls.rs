@@ -0,0 +1,24 @@
+use axum_server::tls_rustls::RustlsConfig;
+
+use crate::config::Config;
+use crate::error::RayhunterError;
+
+// Loads the configured certificate and key, if the server should use TLS.
+// This happens before anything else starts up, so a bad certificate stops
+// the daemon right away rather than once it's already recording.
+pub async fn load_tls_config(config: &Config) -> Result<Option<RustlsConfig>, RayhunterError> {
+    let Some((cert_path, key_path)) = config.tls_paths()? else {
+        return Ok(None);
+    };
+    // this fails if a provider is already installed, e.g. when the daemon
+    // restarts after a config change, which is fine
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    let tls_config = RustlsConfig::from_pem_file(cert_path, key_path)
+        .await
+        .map_err(|err| {
+            RayhunterError::TlsConfigError(format!(
+                "couldn't load certificate {cert_path} and key {key_path}: {err}"
+            ))
+        })?;
+    Ok(Some(tls_config))
+}
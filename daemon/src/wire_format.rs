@@ -0,0 +1,160 @@
+use std::convert::Infallible;
+use std::io;
+
+use axum::body::Body;
+use axum::extract::FromRequestParts;
+use axum::http::header::{ACCEPT, CONTENT_TYPE, VARY};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use log::warn;
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+pub const MESSAGEPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+// The encoding a client asked for in its Accept header. NDJSON reports stay
+// NDJSON on disk, this only changes what's sent over the wire. Clients that
+// don't ask for MessagePack get JSON, as they always have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireFormat {
+    Json,
+    MessagePack,
+}
+
+impl WireFormat {
+    // Picks whichever of the two formats the Accept header lists first,
+    // ignoring quality values
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let Some(accept) = headers.get(ACCEPT).and_then(|value| value.to_str().ok()) else {
+            return WireFormat::Json;
+        };
+        for media_range in accept.split(',') {
+            let media_type = media_range.split(';').next().unwrap_or("").trim();
+            match media_type.to_ascii_lowercase().as_str() {
+                "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                    return WireFormat::MessagePack;
+                }
+                "application/json" | "application/x-ndjson" => return WireFormat::Json,
+                _ => {}
+            }
+        }
+        WireFormat::Json
+    }
+
+    // Serializes a single value as the response body
+    pub fn respond<T: Serialize>(self, value: &T) -> Response {
+        let vary = [(VARY, "Accept")];
+        match self {
+            WireFormat::Json => (vary, axum::Json(value)).into_response(),
+            WireFormat::MessagePack => match encode_messagepack(value) {
+                Ok(bytes) => {
+                    (vary, [(CONTENT_TYPE, MESSAGEPACK_CONTENT_TYPE)], bytes).into_response()
+                }
+                Err(err) => (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("failed to encode MessagePack: {err}"),
+                )
+                    .into_response(),
+            },
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for WireFormat {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(WireFormat::from_headers(&parts.headers))
+    }
+}
+
+// Structs are encoded as maps keyed by field name rather than as arrays, so
+// the MessagePack has the same shape as the JSON
+pub fn encode_messagepack<T: Serialize + ?Sized>(
+    value: &T,
+) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+    rmp_serde::to_vec_named(value)
+}
+
+// Re-encodes one line of an NDJSON report as a MessagePack value
+fn ndjson_line_to_messagepack(line: &str) -> Result<Vec<u8>, String> {
+    let value: serde_json::Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+    encode_messagepack(&value).map_err(|e| e.to_string())
+}
+
+// Re-encodes an in-memory NDJSON report as a sequence of MessagePack values,
+// one per line
+pub fn ndjson_to_messagepack(report: &str) -> Result<Vec<u8>, String> {
+    let mut encoded = Vec::new();
+    for line in report.lines().filter(|line| !line.is_empty()) {
+        encoded.extend(ndjson_line_to_messagepack(line)?);
+    }
+    Ok(encoded)
+}
+
+// Streams an NDJSON report as a sequence of MessagePack values, one per line.
+// Lines that don't parse (e.g. one that's still being written to by a live
+// analysis) are skipped.
+pub fn ndjson_to_messagepack_body<R>(reader: R) -> Body
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let lines = BufReader::new(reader).lines();
+    let encoded = futures::stream::try_unfold(lines, |mut lines| async move {
+        while let Some(line) = lines.next_line().await? {
+            if line.is_empty() {
+                continue;
+            }
+            match ndjson_line_to_messagepack(&line) {
+                Ok(encoded) => return Ok::<_, io::Error>(Some((encoded, lines))),
+                Err(err) => warn!("skipping unparseable report line: {err}"),
+            }
+        }
+        Ok(None)
+    });
+    Body::from_stream(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn format_for(accept: Option<&'static str>) -> WireFormat {
+        let mut headers = HeaderMap::new();
+        if let Some(accept) = accept {
+            headers.insert(ACCEPT, HeaderValue::from_static(accept));
+        }
+        WireFormat::from_headers(&headers)
+    }
+
+    #[test]
+    fn test_negotiation() {
+        assert_eq!(format_for(None), WireFormat::Json);
+        assert_eq!(format_for(Some("*/*")), WireFormat::Json);
+        assert_eq!(
+            format_for(Some("application/msgpack")),
+            WireFormat::MessagePack
+        );
+        assert_eq!(
+            format_for(Some(
+                "text/html, application/x-msgpack;q=0.9, application/json"
+            )),
+            WireFormat::MessagePack
+        );
+        assert_eq!(
+            format_for(Some("application/json, application/msgpack")),
+            WireFormat::Json
+        );
+    }
+
+    #[test]
+    fn test_ndjson_to_messagepack() {
+        let report = "{\"a\":1}\n{\"b\":[true]}\n";
+        let encoded = ndjson_to_messagepack(report).unwrap();
+        let mut expected = encode_messagepack(&serde_json::json!({"a": 1})).unwrap();
+        expected.extend(encode_messagepack(&serde_json::json!({"b": [true]})).unwrap());
+        assert_eq!(encoded, expected);
+    }
+}
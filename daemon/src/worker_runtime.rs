@@ -0,0 +1,81 @@
+use log::{info, warn};
+use tokio::runtime::{Builder, Handle, Runtime};
+
+use crate::config::Config;
+
+// Pins the calling thread to the given cores, as far as the platform allows.
+// Cores that don't exist are left out, and if none of them do, the thread is
+// left where it is.
+fn pin_current_thread(cores: &[usize]) {
+    if cores.is_empty() {
+        return;
+    }
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        // SAFETY: cpu_set_t is a plain bitmask, which CPU_SET only sets bits
+        // of within its bounds, and sched_setaffinity only reads it
+        let result = unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            for &core in cores
+                .iter()
+                .filter(|&&core| core < libc::CPU_SETSIZE as usize)
+            {
+                libc::CPU_SET(core, &mut set);
+            }
+            libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+        };
+        if result != 0 {
+            warn!(
+                "failed to pin analysis thread to cores {cores:?}: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    warn!("pinning analysis threads to cores {cores:?} isn't supported on this platform");
+}
+
+// A runtime that's shut down without waiting for its tasks when dropped,
+// since a runtime can't be dropped normally from within another one
+pub struct AnalysisRuntime(Option<Runtime>);
+
+impl AnalysisRuntime {
+    pub fn handle(&self) -> Handle {
+        // only ever None while it's being dropped
+        self.0.as_ref().unwrap().handle().clone()
+    }
+}
+
+impl Drop for AnalysisRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+// Builds a runtime of its own for the analysis thread, if the config asks for
+// one, so that analyzing recordings can't starve the diag reader or the web
+// server of the shared runtime's workers. Its threads are pinned to
+// analysis_worker_cores, if any are given, leaving the other cores to the
+// rest of the daemon. Returns None to use the shared runtime.
+pub fn build_analysis_runtime(config: &Config) -> Result<Option<AnalysisRuntime>, std::io::Error> {
+    if config.analysis_worker_threads == 0 {
+        if !config.analysis_worker_cores.is_empty() {
+            warn!("analysis_worker_cores is ignored unless analysis_worker_threads is set");
+        }
+        return Ok(None);
+    }
+    info!(
+        "Starting {} analysis worker thread(s), pinned to cores {:?}",
+        config.analysis_worker_threads, config.analysis_worker_cores
+    );
+    let cores = config.analysis_worker_cores.clone();
+    let runtime = Builder::new_multi_thread()
+        .worker_threads(config.analysis_worker_threads)
+        .thread_name("rayhunter-analysis")
+        .on_thread_start(move || pin_current_thread(&cores))
+        .enable_all()
+        .build()?;
+    Ok(Some(AnalysisRuntime(Some(runtime))))
+}
@@ -64,6 +64,8 @@ const SAMPLE_V2_REPORT_NDJSON: NewlineDeliminatedJson = [
             {
                 event_type: { type: 'QualitativeWarning', severity: 'Low' },
                 message: 'Something nasty happened',
+                explanation: 'Because of reasons',
+                evidence: [['field', 'value']],
             },
         ],
     },
@@ -96,6 +98,8 @@ describe('analysis report parsing', () => {
             expect(row.packet_timestamp.getTime()).toEqual(expected_timestamp.getTime());
             if (event !== null && event.type === EventType.Warning) {
                 expect(event.severity).toEqual(Severity.Low);
+                expect(event.explanation).toEqual('');
+                expect(event.evidence).toEqual([]);
             } else {
                 throw 'wrong event type';
             }
@@ -130,6 +134,8 @@ describe('analysis report parsing', () => {
             expect(row.packet_timestamp.getTime()).toEqual(expected_timestamp.getTime());
             if (event !== null && event.type === EventType.Warning) {
                 expect(event.severity).toEqual(Severity.Low);
+                expect(event.explanation).toEqual('Because of reasons');
+                expect(event.evidence).toEqual([['field', 'value']]);
             } else {
                 throw 'wrong event type';
             }
@@ -137,4 +143,9 @@ describe('analysis report parsing', () => {
             throw 'wrong row type';
         }
     });
+
+    it('refuses reports from newer versions', () => {
+        const report = [{ ...SAMPLE_V2_REPORT_NDJSON[0], report_version: 1000 }];
+        expect(() => parse_finished_report(report)).toThrow('report version 1000');
+    });
 });
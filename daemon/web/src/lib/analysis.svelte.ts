@@ -1,5 +1,5 @@
 import { parse_ndjson, type NewlineDeliminatedJson } from './ndjson';
-import { req } from './utils.svelte';
+import { req, type AnalyzerConfig } from './utils.svelte';
 
 export type AnalysisReport = {
     metadata: ReportMetadata;
@@ -17,10 +17,21 @@ export class ReportMetadata {
     public analyzers: AnalyzerMetadata[];
     public rayhunter: RayhunterMetadata;
     public report_version: number;
+    // the analyzer config the report was produced with, if it was recorded
+    public analyzer_config?: AnalyzerConfig;
+    public analyzer_config_hash?: string;
+    // set if only a sample of the messages was analyzed
+    public sampling?: SamplingMetadata;
+    // where the recording came from, if it was recorded
+    public capture?: CaptureMetadata;
 
     constructor(ndjson: any) {
         this.analyzers = ndjson.analyzers;
         this.rayhunter = ndjson.rayhunter;
+        this.analyzer_config = ndjson.analyzer_config;
+        this.analyzer_config_hash = ndjson.analyzer_config_hash;
+        this.sampling = ndjson.sampling;
+        this.capture = ndjson.capture;
         if (ndjson.report_version === undefined) {
             this.report_version = 1;
             // we consider our legacy (unversioned) heuristics to be v0 --
@@ -35,6 +46,29 @@ export class ReportMetadata {
     }
 }
 
+export type SamplingMetadata = {
+    sample_every: number;
+    excluded_analyzers: string[];
+};
+
+export type CaptureMetadata = {
+    recording?: string;
+    device?: string;
+    start_time?: string;
+    last_message_time?: string;
+    qmdl_size_bytes?: number;
+    recorded_with?: string;
+    system_os?: string;
+    arch?: string;
+    diag_versions: {
+        lte_rrc_ota: number[];
+        nas_4g: number[];
+        // LTE RRC versions whose layout is unknown, so weren't analyzed
+        unsupported_lte_rrc_ota?: number[];
+    };
+    notes?: string;
+};
+
 export type RayhunterMetadata = {
     rayhunter_version: string;
     system_os: string;
@@ -62,6 +96,8 @@ export type PacketAnalysis = {
     type: AnalysisRowType.Analysis;
     packet_timestamp: Date;
     events: Event[];
+    // shared by rows whose findings came from the same messages
+    correlation_id?: number;
 };
 
 export type Event = QualitativeWarning | InformationalEvent | null;
@@ -74,6 +110,8 @@ export type QualitativeWarning = {
     type: EventType.Warning;
     severity: Severity;
     message: string;
+    explanation: string;
+    evidence: [string, string][];
 };
 
 export enum Severity {
@@ -85,6 +123,8 @@ export enum Severity {
 export type InformationalEvent = {
     type: EventType.Informational;
     message: string;
+    explanation: string;
+    evidence: [string, string][];
 };
 
 function get_event(event_json: any): Event {
@@ -92,6 +132,9 @@ function get_event(event_json: any): Event {
         return {
             type: EventType.Informational,
             message: event_json.message,
+            // older reports don't include these
+            explanation: event_json.explanation ?? '',
+            evidence: event_json.evidence ?? [],
         };
     } else {
         return {
@@ -103,6 +146,8 @@ function get_event(event_json: any): Event {
                       ? Severity.Medium
                       : Severity.Low,
             message: event_json.message,
+            explanation: event_json.explanation ?? '',
+            evidence: event_json.evidence ?? [],
         };
     }
 }
@@ -154,6 +199,7 @@ function get_v2_rows(row_jsons: any[]): AnalysisRow[] {
                 type: AnalysisRowType.Analysis,
                 packet_timestamp: new Date(row_json.packet_timestamp),
                 events,
+                correlation_id: row_json.correlation_id ?? undefined,
             });
         }
     }
@@ -186,12 +232,22 @@ function get_report_stats(rows: AnalysisRow[]): ReportStatistics {
     };
 }
 
+// the newest report version this UI can read, i.e. the daemon's
+// REPORT_VERSION
+export const SUPPORTED_REPORT_VERSION = 4;
+
 export function parse_finished_report(report_json: NewlineDeliminatedJson): AnalysisReport {
     const metadata = new ReportMetadata(report_json[0]);
+    if (metadata.report_version > SUPPORTED_REPORT_VERSION) {
+        throw new Error(
+            `report version ${metadata.report_version} is newer than this version of rayhunter can read (up to ${SUPPORTED_REPORT_VERSION})`
+        );
+    }
     let rows;
     if (metadata.report_version === 1) {
         rows = get_v1_rows(report_json.slice(1));
     } else {
+        // v3 only added optional fields to events, so it shares v2's parser
         rows = get_v2_rows(report_json.slice(1));
     }
     const statistics = get_report_stats(rows);
@@ -9,12 +9,28 @@ export enum AnalysisStatus {
     Queued,
     // analysis is finished, and the new report can be accessed
     Finished,
+    // this entry has never been analyzed, or its analysis file is missing
+    Unanalyzed,
 }
 
 type AnalysisStatusJson = {
     running: string | null;
     queued: string[];
+    // the most recently finished entries, up to analysis_status_max_finished
     finished: string[];
+    // how many older finished entries were left out of `finished`
+    finished_trimmed: number;
+    unanalyzed: string[];
+    // why the last analysis of each of these entries failed
+    failed: Record<string, string>;
+    // entries whose last analysis was stopped early for running too long
+    timed_out: string[];
+    // entries whose last analysis found no RRC or NAS messages to analyze,
+    // though the recording wasn't empty
+    no_relevant_messages: string[];
+    // entries left unanalyzed because the analysis queue grew past
+    // analysis_max_queue_depth
+    skipped: string[];
 };
 
 export type AnalysisResult = {
@@ -25,6 +41,10 @@ export type AnalysisResult = {
 export class AnalysisManager {
     public status: Map<string, AnalysisStatus> = new Map();
     public reports: Map<string, AnalysisReport | string> = new Map();
+    // whether some finished entries were left out of the last status, so
+    // entries it doesn't mention at all are finished
+    public finished_trimmed = false;
+    private failed: Record<string, string> = {};
 
     public async run_analysis(name: string) {
         await req('POST', `/api/analysis/${name}`);
@@ -42,23 +62,40 @@ export class AnalysisManager {
             this.status.set(entry, AnalysisStatus.Queued);
         }
 
+        for (const entry of status.unanalyzed) {
+            this.status.set(entry, AnalysisStatus.Unanalyzed);
+            this.reports.delete(entry);
+        }
+
+        this.finished_trimmed = status.finished_trimmed > 0;
+        this.failed = status.failed;
         for (const entry of status.finished) {
-            // if entry was already finished, nothing to do
-            if (this.status.get(entry) === AnalysisStatus.Finished) {
-                continue;
-            }
+            this.mark_finished(entry);
+        }
+    }
 
-            this.status.set(entry, AnalysisStatus.Finished);
+    public mark_finished(entry: string) {
+        // if entry was already finished, nothing to do
+        if (this.status.get(entry) === AnalysisStatus.Finished) {
+            return;
+        }
 
-            // fetch the analysis report
-            this.reports.delete(entry);
-            get_report(entry)
-                .then((report) => {
-                    this.reports.set(entry, report);
-                })
-                .catch((err) => {
-                    this.reports.set(entry, `Failed to get analysis: ${err}`);
-                });
+        this.status.set(entry, AnalysisStatus.Finished);
+
+        const failure = this.failed[entry];
+        if (failure !== undefined) {
+            this.reports.set(entry, `Analysis failed: ${failure}`);
+            return;
         }
+
+        // fetch the analysis report
+        this.reports.delete(entry);
+        get_report(entry)
+            .then((report) => {
+                this.reports.set(entry, report);
+            })
+            .catch((err) => {
+                this.reports.set(entry, `Failed to get analysis: ${err}`);
+            });
     }
 }
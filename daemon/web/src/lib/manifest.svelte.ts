@@ -31,6 +31,11 @@ export class Manifest {
 
     async set_analysis_status(manager: AnalysisManager) {
         for (const entry of this.entries) {
+            // entries the status left out for being too old to list are
+            // finished
+            if (manager.finished_trimmed && !manager.status.has(entry.name)) {
+                manager.mark_finished(entry.name);
+            }
             entry.analysis_status = manager.status.get(entry.name);
             entry.analysis_report = manager.reports.get(entry.name);
         }
@@ -99,6 +104,10 @@ export class ManifestEntry {
         return `/api/analysis-report/${this.name}`;
     }
 
+    get_analysis_csv_url(): string {
+        return `/api/analysis/${this.name}/report.csv`;
+    }
+
     get_delete_url(): string {
         return `/api/delete-recording/${this.name}`;
     }
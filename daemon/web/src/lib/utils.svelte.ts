@@ -7,6 +7,82 @@ export interface AnalyzerConfig {
     lte_sib6_and_7_downgrade: boolean;
     null_cipher: boolean;
     nas_null_cipher: boolean;
+    network_detach: boolean;
+    unexpected_security_algorithms: boolean;
+    pci_conflict: boolean;
+    periodic_tau_timer: boolean;
+    cs_fallback_downgrade: boolean;
+    reselection_churn: boolean;
+    auth_without_identity: boolean;
+    unexpected_plmn: boolean;
+    counter_replay: boolean;
+    unexpected_band: boolean;
+    uplink_starvation: boolean;
+    access_barring: boolean;
+    duplex_config_change: boolean;
+    unprotected_reconfiguration: boolean;
+    weak_cell_attach: boolean;
+    security_mode_failure: boolean;
+    out_of_spec_ie: boolean;
+    cell_distance: boolean;
+    steering_reject: boolean;
+    emergency_numbers: boolean;
+    cell_selection: boolean;
+    reestablishment_storm: boolean;
+    repaging: boolean;
+    power_control: boolean;
+    cell_identity_mismatch: boolean;
+    cell_diversity: boolean;
+    sim_ota: boolean;
+    nitz: boolean;
+    idle_mode_steering: boolean;
+    tmsi_lifetime: boolean;
+    antenna_config: boolean;
+    service_reject: boolean;
+    cell_swap: boolean;
+    uac_barring: boolean;
+    rrc_integrity: boolean;
+    repeated_identity_request: boolean;
+    etws_mismatch: boolean;
+    positioning_measurement: boolean;
+    band_indicator_mismatch: boolean;
+    handover_ping_pong: boolean;
+    sib_schedule: boolean;
+    attach_reject_downgrade: boolean;
+    nas_rrc_consistency: boolean;
+    location_reporting: boolean;
+    reconfiguration_churn: boolean;
+    dedicated_sib1_mismatch: boolean;
+    si_periodicity_change: boolean;
+    network_triggered_tau: boolean;
+    placeholder_identity: boolean;
+    value_tag_consistency: boolean;
+    connection_hold: boolean;
+    ims_reject_downgrade: boolean;
+    plmn_overlap: boolean;
+    measurement_gap: boolean;
+    interrogation_ratio: boolean;
+    // the diag log codes to decode, defaulting to the ones the enabled
+    // analyzers need
+    log_codes?: number[] | null;
+    options?: {
+        // whether a crashing analyzer is disabled instead of failing the
+        // analysis
+        isolate_panics?: boolean;
+    };
+    // the severity to report each analyzer's findings at instead of its own,
+    // keyed by the analyzer's toggle name
+    severity_overrides?: Record<string, 'Informational' | 'Low' | 'Medium' | 'High'>;
+    warmup?: Record<string, { min_messages?: number; min_secs?: number }>;
+    // the PDU layouts of LTE RRC ext header versions the parser doesn't know
+    modem_profile?: {
+        lte_rrc_ota?: {
+            ext_header_version: number;
+            layout: 'v2' | 'v9' | 'v14' | 'v19' | 'v20';
+        }[];
+    };
+    // per-analyzer tunables, keyed by the analyzer's toggle name
+    settings: Record<string, Record<string, unknown>>;
 }
 
 export interface Config {
@@ -16,8 +92,25 @@ export interface Config {
     analyzers: AnalyzerConfig;
 }
 
+// If the daemon is configured with an API token, ask for it the first time a
+// request is rejected and keep it in a cookie, which the daemon also accepts
+// for downloads and websockets
+async function fetch_with_token(url: string, init: RequestInit): Promise<Response> {
+    const response = await fetch(url, init);
+    if (response.status !== 401) {
+        return response;
+    }
+    const token = window.prompt('This action needs the API token configured for rayhunter:');
+    if (!token) {
+        return response;
+    }
+    const value = encodeURIComponent(token.trim());
+    document.cookie = `rayhunter_api_token=${value}; path=/; SameSite=Strict`;
+    return await fetch(url, init);
+}
+
 export async function req(method: string, url: string): Promise<string> {
-    const response = await fetch(url, {
+    const response = await fetch_with_token(url, {
         method: method,
     });
     const body = await response.text();
@@ -42,7 +135,7 @@ export async function get_config(): Promise<Config> {
 }
 
 export async function set_config(config: Config): Promise<void> {
-    const response = await fetch('/api/config', {
+    const response = await fetch_with_token('/api/config', {
         method: 'POST',
         headers: {
             'Content-Type': 'application/json',
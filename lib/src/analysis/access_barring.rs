@@ -0,0 +1,216 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    AC_BarringConfig, DL_CCCH_MessageType, DL_CCCH_MessageType_c1, SystemInformationBlockType2,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1, unpack_sib2};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+// ac-BarringFactor and ac-BarringTime, per 36.331
+const BARRING_FACTORS: [&str; 16] = [
+    "p00", "p05", "p10", "p15", "p20", "p25", "p30", "p40", "p50", "p60", "p70", "p75", "p80",
+    "p85", "p90", "p95",
+];
+const BARRING_TIMES_SECS: [u16; 8] = [4, 8, 16, 32, 64, 128, 256, 512];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessBarringSettings {
+    // how long a cell has to keep barring access before it's reported
+    pub min_barring_secs: u64,
+    // report as a warning of this severity rather than informationally
+    pub warning_severity: Option<Severity>,
+}
+
+impl Default for AccessBarringSettings {
+    fn default() -> Self {
+        Self {
+            min_barring_secs: 600,
+            warning_severity: None,
+        }
+    }
+}
+
+impl AnalyzerSettings for AccessBarringSettings {
+    const KEY: &'static str = "access_barring";
+}
+
+fn describe_barring_config(name: &str, config: &AC_BarringConfig) -> String {
+    let factor = BARRING_FACTORS
+        .get(config.ac_barring_factor.0 as usize)
+        .unwrap_or(&"unknown");
+    let time = BARRING_TIMES_SECS
+        .get(config.ac_barring_time.0 as usize)
+        .map(|secs| format!("{secs}s"))
+        .unwrap_or_else(|| "unknown".to_string());
+    let special: String = config
+        .ac_barring_for_special_ac
+        .0
+        .iter()
+        .map(|barred| if *barred { '1' } else { '0' })
+        .collect();
+    format!("{name} factor {factor}, time {time}, AC 11-15 {special}")
+}
+
+// Describes the access barring a SIB2 advertises, or None if it doesn't bar
+// anything
+fn describe_barring(sib2: &SystemInformationBlockType2) -> Option<String> {
+    let info = sib2.ac_barring_info.as_ref()?;
+    let mut parts = Vec::new();
+    if info.ac_barring_for_emergency.0 {
+        parts.push("emergency calls barred".to_string());
+    }
+    if let Some(config) = &info.ac_barring_for_mo_signalling {
+        parts.push(describe_barring_config("MO signalling", config));
+    }
+    if let Some(config) = &info.ac_barring_for_mo_data {
+        parts.push(describe_barring_config("MO data", config));
+    }
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join("; "))
+    }
+}
+
+struct Barring {
+    since: DateTime<FixedOffset>,
+    since_frame: usize,
+    description: String,
+    // whether the UE got an RRC connection on the cell while it was barring
+    ue_served: bool,
+}
+
+// Access class barring is how a congested cell sheds load, and it doesn't
+// usually last. A cell that keeps barring access for a long time while still
+// serving the UE isn't behaving like a congested one, and could be using
+// barring to pick which UEs it lets in. Legitimate barring looks similar
+// enough that this is informational unless configured otherwise.
+pub struct AccessBarringAnalyzer {
+    packet_num: usize,
+    min_duration: TimeDelta,
+    event_type: EventType,
+    serving_cell: Option<CellGlobalIdentity>,
+    barring: Option<Barring>,
+    reported: HashSet<CellGlobalIdentity>,
+}
+
+impl AccessBarringAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: AccessBarringSettings = analyzer_config.get_settings();
+        let min_secs = i64::try_from(settings.min_barring_secs).unwrap_or(i64::MAX);
+        let event_type = match settings.warning_severity {
+            Some(severity) => EventType::QualitativeWarning { severity },
+            None => EventType::Informational,
+        };
+        Self {
+            packet_num: 0,
+            min_duration: TimeDelta::try_seconds(min_secs).unwrap_or(TimeDelta::MAX),
+            event_type,
+            serving_cell: None,
+            barring: None,
+            reported: HashSet::new(),
+        }
+    }
+
+    fn check_barring(&mut self, now: DateTime<FixedOffset>) -> Option<Event> {
+        let barring = self.barring.as_ref()?;
+        let cell = self.serving_cell.as_ref()?;
+        let duration = now - barring.since;
+        if !barring.ue_served || duration < self.min_duration || self.reported.contains(cell) {
+            return None;
+        }
+        self.reported.insert(cell.clone());
+        let duration_secs = duration.num_seconds();
+        Some(Event {
+            event_type: self.event_type.clone(),
+            message: format!(
+                "Cell has been barring access for {duration_secs}s while still serving the UE: {cell}"
+            ),
+            explanation: "The serving cell's SIB2 has advertised access class barring for a long time, yet the UE was still let onto it. Congested cells bar access briefly to shed load, so persistent barring may mean the cell is choosing which UEs to admit.".to_string(),
+            evidence: vec![
+                ("cell".to_string(), cell.to_string()),
+                ("barring".to_string(), barring.description.clone()),
+                ("barring_secs".to_string(), duration_secs.to_string()),
+                ("barring_since_frame".to_string(), barring.since_frame.to_string()),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+}
+
+impl Analyzer for AccessBarringAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Access Class Barring")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the serving cell keeps advertising access class barring in its SIB2 for longer than min_barring_secs while still letting the UE connect. Congestion can cause this too, so it's informational unless warning_severity is set.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            let cell = CellGlobalIdentity::from_sib1(sib1);
+            if cell != self.serving_cell {
+                self.serving_cell = cell;
+                self.barring = None;
+            }
+            return None;
+        }
+        let now = ctx.timestamp?;
+        if let Some(sib2) = unpack_sib2(ie) {
+            match describe_barring(sib2) {
+                Some(description) => match self.barring.as_mut() {
+                    Some(barring) => barring.description = description,
+                    None => {
+                        self.barring = Some(Barring {
+                            since: now,
+                            since_frame: self.packet_num,
+                            description,
+                            ue_served: false,
+                        });
+                    }
+                },
+                None => self.barring = None,
+            }
+            return self.check_barring(now);
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        if let LteInformationElement::DlCcch(msg_cont) = &**inner {
+            if let DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionSetup(_)) =
+                &msg_cont.message
+            {
+                if let Some(barring) = self.barring.as_mut() {
+                    barring.ue_served = true;
+                }
+                return self.check_barring(now);
+            }
+        }
+        None
+    }
+}
@@ -1,17 +1,84 @@
 use chrono::{DateTime, FixedOffset};
+use crc::{CRC_32_ISO_HDLC, Crc};
+use log::{debug, error};
 use pcap_file_tokio::pcapng::blocks::enhanced_packet::EnhancedPacketBlock;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::panic::{AssertUnwindSafe, catch_unwind};
 
+use crate::Device;
+use crate::diag::{DiagLayoutVersions, LogBody, Message, MessagesContainer};
 use crate::gsmtap::{GsmtapHeader, GsmtapMessage, GsmtapType};
+use crate::gsmtap_parser::{self, ModemProfile};
+use crate::log_codes::ANALYZER_LOG_CODES;
 use crate::util::RuntimeMetadata;
-use crate::{diag::MessagesContainer, gsmtap_parser};
 
 use super::{
+    access_barring::{AccessBarringAnalyzer, AccessBarringSettings},
+    antenna_config::{AntennaConfigAnalyzer, AntennaConfigSettings},
+    attach_reject_downgrade::{AttachRejectDowngradeAnalyzer, AttachRejectDowngradeSettings},
+    auth_without_identity::AuthWithoutIdentityAnalyzer,
+    band_indicator_mismatch::BandIndicatorMismatchAnalyzer,
+    cell_distance::{CellDistanceAnalyzer, CellDistanceSettings},
+    cell_diversity::{CellDiversityAnalyzer, CellDiversitySettings},
+    cell_identity_mismatch::CellIdentityMismatchAnalyzer,
+    cell_selection::{CellSelectionAnalyzer, CellSelectionSettings},
+    cell_swap::{CellSwapAnalyzer, CellSwapSettings},
+    connection_hold::{ConnectionHoldAnalyzer, ConnectionHoldSettings},
     connection_redirect_downgrade::ConnectionRedirect2GDowngradeAnalyzer,
-    imsi_requested::ImsiRequestedAnalyzer, information_element::InformationElement,
-    nas_null_cipher::NasNullCipherAnalyzer, null_cipher::NullCipherAnalyzer,
+    counter_replay::CounterReplayAnalyzer,
+    cs_fallback::CsFallbackAnalyzer,
+    dedicated_sib1_mismatch::DedicatedSib1MismatchAnalyzer,
+    duplex_change::DuplexChangeAnalyzer,
+    emergency_numbers::{EmergencyNumbersAnalyzer, EmergencyNumbersSettings},
+    etws_mismatch::{EtwsMismatchAnalyzer, EtwsMismatchSettings},
+    handover_ping_pong::{HandoverPingPongAnalyzer, HandoverPingPongSettings},
+    idle_mode_steering::{IdleModeSteeringAnalyzer, IdleModeSteeringSettings},
+    ims_reject_downgrade::{ImsRejectDowngradeAnalyzer, ImsRejectDowngradeSettings},
+    imsi_requested::ImsiRequestedAnalyzer,
+    information_element::InformationElement,
+    interrogation_ratio::{InterrogationRatioAnalyzer, InterrogationRatioSettings},
+    location_reporting::{LocationReportingAnalyzer, LocationReportingSettings},
+    measurement_gap::{MeasurementGapAnalyzer, MeasurementGapSettings},
+    nas_null_cipher::NasNullCipherAnalyzer,
+    nas_rrc_consistency::{NasRrcConsistencyAnalyzer, NasRrcConsistencySettings},
+    network_detach::NetworkDetachAnalyzer,
+    network_triggered_tau::{NetworkTriggeredTauAnalyzer, NetworkTriggeredTauSettings},
+    nitz::{NitzAnalyzer, NitzSettings},
+    null_cipher::NullCipherAnalyzer,
+    out_of_spec_ie::{OutOfSpecIeAnalyzer, OutOfSpecIeSettings},
+    pci_conflict::PciConflictAnalyzer,
+    periodic_tau_timer::PeriodicTauTimerAnalyzer,
+    periodic_tau_timer::PeriodicTauTimerSettings,
+    placeholder_identity::PlaceholderIdentityAnalyzer,
+    plmn_overlap::{PlmnOverlapAnalyzer, PlmnOverlapSettings},
+    positioning_measurement::{PositioningMeasurementAnalyzer, PositioningMeasurementSettings},
+    power_control::{PowerControlAnalyzer, PowerControlSettings},
     priority_2g_downgrade::LteSib6And7DowngradeAnalyzer,
+    reconfiguration_churn::{ReconfigurationChurnAnalyzer, ReconfigurationChurnSettings},
+    reestablishment_storm::{ReestablishmentStormAnalyzer, ReestablishmentStormSettings},
+    repaging::{RepagingAnalyzer, RepagingSettings},
+    repeated_identity_request::{RepeatedIdentityRequestAnalyzer, RepeatedIdentityRequestSettings},
+    reselection_churn::{ReselectionChurnAnalyzer, ReselectionChurnSettings},
+    rrc_integrity::RrcIntegrityAnalyzer,
+    security_mode_failure::{SecurityModeFailureAnalyzer, SecurityModeFailureSettings},
+    security_policy::{SecurityPolicyAnalyzer, SecurityPolicySettings},
+    service_reject::{ServiceRejectAnalyzer, ServiceRejectSettings},
+    si_periodicity_change::{SiPeriodicityChangeAnalyzer, SiPeriodicityChangeSettings},
+    sib_schedule::{SibScheduleAnalyzer, SibScheduleSettings},
+    sim_ota::{SimOtaAnalyzer, SimOtaSettings},
+    steering_reject::SteeringRejectAnalyzer,
+    tmsi_lifetime::{TmsiLifetimeAnalyzer, TmsiLifetimeSettings},
+    uac_barring::{UacBarringAnalyzer, UacBarringSettings},
+    unexpected_band::{UnexpectedBandAnalyzer, UnexpectedBandSettings},
+    unexpected_plmn::{UnexpectedPlmnAnalyzer, UnexpectedPlmnSettings},
+    unprotected_reconfiguration::UnprotectedReconfigurationAnalyzer,
+    uplink_starvation::{UplinkStarvationAnalyzer, UplinkStarvationSettings},
+    value_tag_consistency::ValueTagConsistencyAnalyzer,
+    weak_cell_attach::{WeakCellAttachAnalyzer, WeakCellAttachSettings},
 };
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -22,6 +89,80 @@ pub struct AnalyzerConfig {
     pub lte_sib6_and_7_downgrade: bool,
     pub null_cipher: bool,
     pub nas_null_cipher: bool,
+    pub network_detach: bool,
+    pub unexpected_security_algorithms: bool,
+    pub pci_conflict: bool,
+    pub periodic_tau_timer: bool,
+    pub cs_fallback_downgrade: bool,
+    pub reselection_churn: bool,
+    pub auth_without_identity: bool,
+    pub unexpected_plmn: bool,
+    pub counter_replay: bool,
+    pub unexpected_band: bool,
+    pub uplink_starvation: bool,
+    pub access_barring: bool,
+    pub duplex_config_change: bool,
+    pub unprotected_reconfiguration: bool,
+    pub weak_cell_attach: bool,
+    pub security_mode_failure: bool,
+    pub out_of_spec_ie: bool,
+    pub cell_distance: bool,
+    pub steering_reject: bool,
+    pub emergency_numbers: bool,
+    pub cell_selection: bool,
+    pub reestablishment_storm: bool,
+    pub repaging: bool,
+    pub power_control: bool,
+    pub cell_identity_mismatch: bool,
+    pub cell_diversity: bool,
+    pub sim_ota: bool,
+    pub nitz: bool,
+    pub idle_mode_steering: bool,
+    pub tmsi_lifetime: bool,
+    pub antenna_config: bool,
+    pub service_reject: bool,
+    pub cell_swap: bool,
+    pub uac_barring: bool,
+    pub rrc_integrity: bool,
+    pub repeated_identity_request: bool,
+    pub etws_mismatch: bool,
+    pub positioning_measurement: bool,
+    pub band_indicator_mismatch: bool,
+    pub handover_ping_pong: bool,
+    pub sib_schedule: bool,
+    pub attach_reject_downgrade: bool,
+    pub nas_rrc_consistency: bool,
+    pub location_reporting: bool,
+    pub reconfiguration_churn: bool,
+    pub dedicated_sib1_mismatch: bool,
+    pub si_periodicity_change: bool,
+    pub network_triggered_tau: bool,
+    pub placeholder_identity: bool,
+    pub value_tag_consistency: bool,
+    pub connection_hold: bool,
+    pub ims_reject_downgrade: bool,
+    pub plmn_overlap: bool,
+    pub measurement_gap: bool,
+    pub interrogation_ratio: bool,
+    // the diag log codes to decode, with everything else skipped. If this
+    // isn't set, it's the ones the enabled analyzers need.
+    pub log_codes: Option<Vec<u16>>,
+    // how the harness runs the analyzers, kept apart from their toggles
+    pub options: HarnessOptions,
+    // overrides the severity of each analyzer's findings, keyed by the
+    // analyzer's toggle name. This and settings are sorted maps, so the config
+    // is written out the same way every time it's put in a report's metadata.
+    pub severity_overrides: BTreeMap<String, SeverityOverride>,
+    // how much each analyzer has to see before its findings are reported,
+    // keyed by the analyzer's toggle name, instead of its own
+    // [Analyzer::get_warmup]
+    pub warmup: BTreeMap<String, Warmup>,
+    // the diag log layouts of the modem the captures come from, for ones the
+    // parser doesn't know
+    pub modem_profile: ModemProfile,
+    // tunable parameters for individual analyzers, keyed by the analyzer's
+    // config key (see [AnalyzerSettings])
+    pub settings: BTreeMap<String, Value>,
 }
 
 impl Default for AnalyzerConfig {
@@ -32,46 +173,496 @@ impl Default for AnalyzerConfig {
             lte_sib6_and_7_downgrade: true,
             null_cipher: true,
             nas_null_cipher: true,
+            network_detach: true,
+            unexpected_security_algorithms: false,
+            pci_conflict: true,
+            periodic_tau_timer: true,
+            cs_fallback_downgrade: true,
+            reselection_churn: true,
+            auth_without_identity: true,
+            unexpected_plmn: false,
+            counter_replay: true,
+            unexpected_band: false,
+            uplink_starvation: true,
+            access_barring: true,
+            duplex_config_change: true,
+            unprotected_reconfiguration: true,
+            weak_cell_attach: true,
+            security_mode_failure: true,
+            out_of_spec_ie: true,
+            cell_distance: true,
+            steering_reject: true,
+            emergency_numbers: true,
+            cell_selection: true,
+            reestablishment_storm: true,
+            repaging: true,
+            power_control: true,
+            cell_identity_mismatch: true,
+            cell_diversity: true,
+            sim_ota: true,
+            nitz: true,
+            idle_mode_steering: true,
+            tmsi_lifetime: true,
+            antenna_config: true,
+            service_reject: true,
+            cell_swap: true,
+            uac_barring: true,
+            rrc_integrity: true,
+            repeated_identity_request: true,
+            etws_mismatch: true,
+            positioning_measurement: true,
+            band_indicator_mismatch: true,
+            handover_ping_pong: true,
+            sib_schedule: true,
+            attach_reject_downgrade: true,
+            nas_rrc_consistency: true,
+            location_reporting: true,
+            reconfiguration_churn: true,
+            dedicated_sib1_mismatch: true,
+            si_periodicity_change: true,
+            network_triggered_tau: true,
+            placeholder_identity: true,
+            value_tag_consistency: true,
+            connection_hold: true,
+            ims_reject_downgrade: true,
+            plmn_overlap: true,
+            measurement_gap: true,
+            interrogation_ratio: true,
+            log_codes: None,
+            options: HarnessOptions::default(),
+            severity_overrides: BTreeMap::new(),
+            warmup: BTreeMap::new(),
+            modem_profile: ModemProfile::default(),
+            settings: BTreeMap::new(),
         }
     }
 }
 
-pub const REPORT_VERSION: u32 = 2;
+/// Options for how a [Harness] runs its analyzers, as opposed to which ones
+/// it runs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HarnessOptions {
+    // keeps going with the other analyzers when one panics, rather than
+    // failing the whole analysis. The panicking analyzer is disabled for the
+    // rest of the recording.
+    pub isolate_panics: bool,
+}
+
+impl Default for HarnessOptions {
+    fn default() -> Self {
+        HarnessOptions {
+            isolate_panics: true,
+        }
+    }
+}
+
+/// The tunable parameters of an [Analyzer], read from
+/// [AnalyzerConfig::settings] under `KEY` when the analyzer is constructed.
+/// Parameters that are missing or have the wrong type keep their `Default`
+/// value.
+pub trait AnalyzerSettings: Default + Serialize + DeserializeOwned {
+    /// The analyzer's key in [AnalyzerConfig::settings], which should match
+    /// its on/off toggle in [AnalyzerConfig]
+    const KEY: &'static str;
+}
+
+impl AnalyzerConfig {
+    /// Returns the settings for an analyzer, using defaults for any that are
+    /// missing or invalid. Use [AnalyzerConfig::validate_settings] to find
+    /// out which those were.
+    pub fn get_settings<T: AnalyzerSettings>(&self) -> T {
+        self.parse_settings().0
+    }
+
+    fn parse_settings<T: AnalyzerSettings>(&self) -> (T, Vec<String>) {
+        let mut problems = Vec::new();
+        let Some(value) = self.settings.get(T::KEY) else {
+            return (T::default(), problems);
+        };
+        let Some(overrides) = value.as_object() else {
+            problems.push(format!(
+                "analyzer settings for {} should be a table, ignoring them",
+                T::KEY
+            ));
+            return (T::default(), problems);
+        };
+        let mut merged = match serde_json::to_value(T::default()) {
+            Ok(Value::Object(defaults)) => defaults,
+            _ => Map::new(),
+        };
+        // apply each override separately, so a single bad value doesn't
+        // discard the others
+        for (name, value) in overrides {
+            if !merged.contains_key(name) {
+                problems.push(format!(
+                    "unknown analyzer setting {}.{name}, ignoring it",
+                    T::KEY
+                ));
+                continue;
+            }
+            let mut candidate = merged.clone();
+            candidate.insert(name.clone(), value.clone());
+            match serde_json::from_value::<T>(Value::Object(candidate.clone())) {
+                Ok(_) => merged = candidate,
+                Err(err) => problems.push(format!(
+                    "invalid value for analyzer setting {}.{name}, using the default: {err}",
+                    T::KEY
+                )),
+            }
+        }
+        let settings = serde_json::from_value(Value::Object(merged)).unwrap_or_default();
+        (settings, problems)
+    }
+
+    /// Checks [AnalyzerConfig::settings] for unknown analyzers or parameters
+    /// and values of the wrong type, returning a description of each problem
+    pub fn validate_settings(&self) -> Vec<String> {
+        let known_keys = [
+            SecurityPolicySettings::KEY,
+            PeriodicTauTimerSettings::KEY,
+            ReselectionChurnSettings::KEY,
+            UnexpectedPlmnSettings::KEY,
+            UnexpectedBandSettings::KEY,
+            UplinkStarvationSettings::KEY,
+            AccessBarringSettings::KEY,
+            WeakCellAttachSettings::KEY,
+            SecurityModeFailureSettings::KEY,
+            OutOfSpecIeSettings::KEY,
+            CellDistanceSettings::KEY,
+            EmergencyNumbersSettings::KEY,
+            CellSelectionSettings::KEY,
+            ReestablishmentStormSettings::KEY,
+            RepagingSettings::KEY,
+            PowerControlSettings::KEY,
+            CellDiversitySettings::KEY,
+            SimOtaSettings::KEY,
+            NitzSettings::KEY,
+            IdleModeSteeringSettings::KEY,
+            TmsiLifetimeSettings::KEY,
+            AntennaConfigSettings::KEY,
+            ServiceRejectSettings::KEY,
+            CellSwapSettings::KEY,
+            UacBarringSettings::KEY,
+            RepeatedIdentityRequestSettings::KEY,
+            EtwsMismatchSettings::KEY,
+            PositioningMeasurementSettings::KEY,
+            HandoverPingPongSettings::KEY,
+            SibScheduleSettings::KEY,
+            AttachRejectDowngradeSettings::KEY,
+            NasRrcConsistencySettings::KEY,
+            LocationReportingSettings::KEY,
+            ReconfigurationChurnSettings::KEY,
+            SiPeriodicityChangeSettings::KEY,
+            NetworkTriggeredTauSettings::KEY,
+            ConnectionHoldSettings::KEY,
+            ImsRejectDowngradeSettings::KEY,
+            PlmnOverlapSettings::KEY,
+            MeasurementGapSettings::KEY,
+            InterrogationRatioSettings::KEY,
+        ];
+        let mut problems: Vec<String> = self
+            .settings
+            .keys()
+            .filter(|key| !known_keys.contains(&key.as_str()))
+            .map(|key| format!("no analyzer settings for {key:?}, ignoring them"))
+            .collect();
+        problems.extend(self.parse_settings::<SecurityPolicySettings>().1);
+        problems.extend(self.parse_settings::<PeriodicTauTimerSettings>().1);
+        problems.extend(self.parse_settings::<ReselectionChurnSettings>().1);
+        problems.extend(self.parse_settings::<UnexpectedPlmnSettings>().1);
+        problems.extend(self.parse_settings::<UnexpectedBandSettings>().1);
+        problems.extend(self.parse_settings::<UplinkStarvationSettings>().1);
+        problems.extend(self.parse_settings::<AccessBarringSettings>().1);
+        problems.extend(self.parse_settings::<WeakCellAttachSettings>().1);
+        problems.extend(self.parse_settings::<SecurityModeFailureSettings>().1);
+        problems.extend(self.parse_settings::<OutOfSpecIeSettings>().1);
+        problems.extend(self.parse_settings::<CellDistanceSettings>().1);
+        problems.extend(self.parse_settings::<EmergencyNumbersSettings>().1);
+        problems.extend(self.parse_settings::<CellSelectionSettings>().1);
+        problems.extend(self.parse_settings::<ReestablishmentStormSettings>().1);
+        problems.extend(self.parse_settings::<RepagingSettings>().1);
+        problems.extend(self.parse_settings::<PowerControlSettings>().1);
+        problems.extend(self.parse_settings::<CellDiversitySettings>().1);
+        problems.extend(self.parse_settings::<SimOtaSettings>().1);
+        problems.extend(self.parse_settings::<NitzSettings>().1);
+        problems.extend(self.parse_settings::<IdleModeSteeringSettings>().1);
+        problems.extend(self.parse_settings::<TmsiLifetimeSettings>().1);
+        problems.extend(self.parse_settings::<AntennaConfigSettings>().1);
+        problems.extend(self.parse_settings::<ServiceRejectSettings>().1);
+        problems.extend(self.parse_settings::<CellSwapSettings>().1);
+        problems.extend(self.parse_settings::<UacBarringSettings>().1);
+        problems.extend(self.parse_settings::<RepeatedIdentityRequestSettings>().1);
+        problems.extend(self.parse_settings::<EtwsMismatchSettings>().1);
+        problems.extend(self.parse_settings::<PositioningMeasurementSettings>().1);
+        problems.extend(self.parse_settings::<HandoverPingPongSettings>().1);
+        problems.extend(self.parse_settings::<SibScheduleSettings>().1);
+        problems.extend(self.parse_settings::<AttachRejectDowngradeSettings>().1);
+        problems.extend(self.parse_settings::<NasRrcConsistencySettings>().1);
+        problems.extend(self.parse_settings::<LocationReportingSettings>().1);
+        problems.extend(self.parse_settings::<ReconfigurationChurnSettings>().1);
+        problems.extend(self.parse_settings::<SiPeriodicityChangeSettings>().1);
+        problems.extend(self.parse_settings::<NetworkTriggeredTauSettings>().1);
+        problems.extend(self.parse_settings::<ConnectionHoldSettings>().1);
+        problems.extend(self.parse_settings::<ImsRejectDowngradeSettings>().1);
+        problems.extend(self.parse_settings::<PlmnOverlapSettings>().1);
+        problems.extend(self.parse_settings::<MeasurementGapSettings>().1);
+        problems.extend(self.parse_settings::<InterrogationRatioSettings>().1);
+        problems.extend(self.check_severity_overrides());
+        problems.extend(self.check_warmup());
+        problems
+    }
+
+    // Returns every field as JSON, keyed by name
+    fn to_fields(&self) -> Map<String, Value> {
+        match serde_json::to_value(self) {
+            Ok(Value::Object(fields)) => fields,
+            _ => Map::new(),
+        }
+    }
+
+    // Returns each analyzer's on/off toggle, keyed by name
+    fn toggles(&self) -> Map<String, Value> {
+        let mut toggles = self.to_fields();
+        toggles.retain(|_, value| is_toggle(value));
+        toggles
+    }
+
+    // Severity overrides only make sense for analyzers that exist
+    fn check_severity_overrides(&self) -> Vec<String> {
+        let toggles = self.toggles();
+        self.severity_overrides
+            .keys()
+            .filter(|name| !toggles.contains_key(name.as_str()))
+            .map(|name| format!("no analyzer {name:?} to override the severity of, ignoring it"))
+            .collect()
+    }
+
+    // As are warmups
+    fn check_warmup(&self) -> Vec<String> {
+        let toggles = self.toggles();
+        self.warmup
+            .keys()
+            .filter(|name| !toggles.contains_key(name.as_str()))
+            .map(|name| format!("no analyzer {name:?} to set the warmup of, ignoring it"))
+            .collect()
+    }
+
+    /// Checks that each of the analyzers named in `required` (by their toggle
+    /// in [AnalyzerConfig]) exists and is enabled, returning a description of
+    /// each one that isn't
+    pub fn check_required(&self, required: &[String]) -> Vec<String> {
+        let toggles = self.toggles();
+        required
+            .iter()
+            .filter_map(|name| match toggles.get(name) {
+                Some(Value::Bool(true)) => None,
+                Some(Value::Bool(false)) => Some(format!("required analyzer {name} is disabled")),
+                _ => Some(format!("required analyzer {name:?} doesn't exist")),
+            })
+            .collect()
+    }
+
+    /// Returns a short identifier for this config, which is the same for
+    /// any two configs that set up the same analyzers in the same way
+    pub fn get_config_hash(&self) -> String {
+        // every map in here is sorted, so the JSON is the same for equal
+        // configs
+        let json = serde_json::to_value(self)
+            .map(|value| value.to_string())
+            .unwrap_or_default();
+        let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+        format!("{:08x}", crc.checksum(json.as_bytes()))
+    }
+
+    /// Returns a copy of this config with only the analyzer named `toggle`
+    /// enabled, keeping its settings and severity override, for running an
+    /// analyzer in isolation
+    pub fn only(&self, toggle: &str) -> Result<AnalyzerConfig, String> {
+        if !self.toggles().contains_key(toggle) {
+            return Err(format!("analyzer {toggle:?} doesn't exist"));
+        }
+        let mut fields = self.to_fields();
+        for (name, value) in fields.iter_mut() {
+            if is_toggle(value) {
+                *value = Value::Bool(name == toggle);
+            }
+        }
+        serde_json::from_value(Value::Object(fields)).map_err(|err| err.to_string())
+    }
+}
+
+// Whether a field of [AnalyzerConfig] turns an analyzer on or off, which all
+// of its boolean fields do
+fn is_toggle(value: &Value) -> bool {
+    value.is_boolean()
+}
+
+// Reports from older versions are upgraded by [super::report_format] as
+// they're read, and the web UI keeps its own copy of this as
+// SUPPORTED_REPORT_VERSION
+pub const REPORT_VERSION: u32 = 4;
 
 /// Qualitative measure of how severe a Warning event type is.
 /// The levels should break down like this:
 ///   * Low: if combined with a large number of other Warnings, user should investigate
 ///   * Medium: if combined with a few other Warnings, user should investigate
 ///   * High: user should investigate
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Severity {
     Low,
     Medium,
     High,
 }
 
+/// What to report an analyzer's findings as instead of what the analyzer
+/// says, configured in [AnalyzerConfig::severity_overrides]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeverityOverride {
+    Informational,
+    Low,
+    Medium,
+    High,
+}
+
+impl SeverityOverride {
+    fn apply(self, event: &mut Event) {
+        event.event_type = match self {
+            SeverityOverride::Informational => EventType::Informational,
+            SeverityOverride::Low => EventType::QualitativeWarning {
+                severity: Severity::Low,
+            },
+            SeverityOverride::Medium => EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            SeverityOverride::High => EventType::QualitativeWarning {
+                severity: Severity::High,
+            },
+        };
+    }
+}
+
 /// `QualitativeWarning` events will always be shown to the user in some manner,
 /// while `Informational` ones may be hidden based on user settings.
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum EventType {
     Informational,
     QualitativeWarning { severity: Severity },
 }
 
+/// How much an [Analyzer] has to have seen before its findings are reported,
+/// for analyzers that judge what's abnormal by what they've seen so far (see
+/// [Analyzer::get_warmup] and [AnalyzerConfig::warmup]). Until both are met
+/// the analyzer still gets every message, so it can build up its state, but
+/// whatever it finds is dropped. The duration is measured by the messages'
+/// timestamps, so messages without one only count towards `min_messages`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Warmup {
+    /// how many messages the analyzer has to have been given
+    pub min_messages: usize,
+    /// how long after the first message it was given
+    pub min_secs: u64,
+}
+
+// Where an analyzer is in its warmup
+#[derive(Clone, Copy)]
+struct WarmupProgress {
+    warmup: Warmup,
+    messages: usize,
+    first_timestamp: Option<DateTime<FixedOffset>>,
+    done: bool,
+}
+
+impl WarmupProgress {
+    fn new(warmup: Warmup) -> Self {
+        WarmupProgress {
+            warmup,
+            messages: 0,
+            first_timestamp: None,
+            done: warmup == Warmup::default(),
+        }
+    }
+
+    // Counts a message the analyzer's been given, returning whether its
+    // findings on it count
+    fn observe(&mut self, ctx: &PacketContext) -> bool {
+        if self.done {
+            return true;
+        }
+        self.messages += 1;
+        if self.first_timestamp.is_none() {
+            self.first_timestamp = ctx.timestamp;
+        }
+        let elapsed_secs = self
+            .first_timestamp
+            .zip(ctx.timestamp)
+            .map(|(first, now)| (now - first).num_seconds());
+        self.done = self.messages >= self.warmup.min_messages
+            && (self.warmup.min_secs == 0
+                || elapsed_secs
+                    .is_some_and(|secs| secs >= 0 && secs as u64 >= self.warmup.min_secs));
+        self.done
+    }
+}
+
 /// Events are user-facing signals that can be emitted by an [Analyzer] upon a
 /// message being received. They can be used to signifiy an IC detection
 /// warning, or just to display some relevant information to the user.
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Event {
     pub event_type: EventType,
     pub message: String,
+    /// A longer, human-readable explanation of why the analyzer emitted this
+    /// event, to help users judge whether it's a false positive
+    #[serde(default)]
+    pub explanation: String,
+    /// The decoded fields that triggered this event, as (name, value) pairs
+    #[serde(default)]
+    pub evidence: Vec<(String, String)>,
+}
+
+/// Information about the packet an [InformationElement] was parsed from that
+/// isn't part of the message itself. Which fields are available depends on the
+/// capture format and message type.
+#[derive(Debug, Clone, Default)]
+pub struct PacketContext {
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    /// The (E)ARFCN the message was sent or received on
+    pub earfcn: Option<u32>,
+    /// The physical cell ID of the cell an LTE RRC message came from. Only
+    /// available when analyzing QMDL files.
+    pub phy_cell_id: Option<u16>,
+    /// The radio bearer an LTE RRC message was carried on, where 0 is SRB0
+    /// (the common control channel) and 1 and 2 are SRB1 and SRB2. Only
+    /// available when analyzing QMDL files.
+    pub bearer_id: Option<u8>,
 }
 
 /// An [Analyzer] represents one type of heuristic for detecting an IMSI Catcher
 /// (IC). While maintaining some amount of state is useful, be mindful of how
 /// much memory your [Analyzer] uses at runtime, since rayhunter may run for
 /// many hours at a time with dozens of [Analyzers](Analyzer) working in parallel.
+///
+/// Analyzers outside this crate can be added to a [Harness] directly with
+/// [Harness::add_analyzer], or built from an [AnalyzerConfig] alongside the
+/// built-in ones by registering them in an [AnalyzerRegistry]. Either way,
+/// they're held to the same contract:
+///
+/// * A [Harness] owns one instance of each analyzer for as long as it
+///   analyzes a recording (or a merged set of them), and a new [Harness] is
+///   built for every analysis, so any state an analyzer keeps is the state
+///   of that one session. There's no reset: state should be scoped by what
+///   the analyzer sees, e.g. dropped when an RRC connection is released.
+/// * Every message is passed to every analyzer, in the order the modem
+///   logged them, minus those whose log codes no analyzer wants (see
+///   [Analyzer::get_log_codes]).
+/// * An analyzer that panics is disabled for the rest of the analysis by
+///   default, rather than taking the others down with it.
+/// * The name and version end up in every report's metadata, and reports are
+///   only re-analyzed when they change, so they should stay the same until
+///   the heuristic does.
 pub trait Analyzer {
     /// Returns a user-friendly, concise name for your heuristic.
     fn get_name(&self) -> Cow<str>;
@@ -84,15 +675,51 @@ pub trait Analyzer {
     fn get_description(&self) -> Cow<str>;
 
     /// Analyze a single [InformationElement], possibly returning an [Event] if your
-    /// heuristic deems it relevant. Again, be mindful of any state your
-    /// [Analyzer] updates per message, since it may be run over hundreds or
-    /// thousands of them alongside many other [Analyzers](Analyzer).
-    fn analyze_information_element(&mut self, ie: &InformationElement) -> Option<Event>;
+    /// heuristic deems it relevant. `ctx` describes the packet the element was
+    /// parsed from. Again, be mindful of any state your [Analyzer] updates per
+    /// message, since it may be run over hundreds or thousands of them
+    /// alongside many other [Analyzers](Analyzer).
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event>;
 
     /// Returns a version number for this Analyzer. This should only ever
     /// increase in value, and do so whenever substantial changes are made to
     /// the Analyzer's heuristic.
     fn get_version(&self) -> u32;
+
+    /// Returns a short description of the [Analyzer]'s internal state, e.g.
+    /// which state of its state machine it's in, for debugging it with
+    /// [crate::fixtures::Replay]. It's never shown to users.
+    fn get_state(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the diag log codes of the messages this [Analyzer] looks at,
+    /// so that the ones no analyzer needs can be skipped without being
+    /// decoded. Defaults to all of those that are ever analyzed (see
+    /// [crate::log_codes]).
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_LOG_CODES
+    }
+
+    /// Returns whether this [Analyzer] only works if it sees every message,
+    /// e.g. because it warns when an expected reply never comes, so it can't
+    /// be trusted when only a sample of the messages is analyzed (see
+    /// [Harness::set_sampling]). Defaults to false.
+    fn needs_every_message(&self) -> bool {
+        false
+    }
+
+    /// Returns how much this [Analyzer] has to see before its findings are
+    /// reported, for analyzers that need a baseline of normal behavior
+    /// before they can flag anything as abnormal, which can be overridden in
+    /// [AnalyzerConfig::warmup]. Defaults to none.
+    fn get_warmup(&self) -> Warmup {
+        Warmup::default()
+    }
 }
 
 #[derive(Serialize, Debug)]
@@ -108,13 +735,85 @@ pub struct ReportMetadata {
     pub rayhunter: RuntimeMetadata,
     // anytime the format of the report changes, bump this by 1
     pub report_version: u32,
+    // the recordings a merged analysis of several of them covers, in the
+    // order they were analyzed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recordings: Option<Vec<String>>,
+    // the config the analyzers were set up with, so the analysis can be
+    // reproduced, along with its [AnalyzerConfig::get_config_hash]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub analyzer_config: Option<AnalyzerConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub analyzer_config_hash: Option<String>,
+    // set if only a sample of the messages was analyzed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sampling: Option<SamplingMetadata>,
+    // where the analyzed messages came from, if the analysis was of a
+    // recording
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture: Option<CaptureMetadata>,
 }
 
-#[derive(Serialize, Debug)]
+/// What's known about the capture a report was made from when it's analyzed,
+/// so that a report shared on its own still says what device and modem it
+/// came from. Anything that isn't known, e.g. for imported recordings, is
+/// left out.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CaptureMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recording: Option<String>,
+    /// The device the capture was recorded on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<Device>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<DateTime<FixedOffset>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_message_time: Option<DateTime<FixedOffset>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qmdl_size_bytes: Option<usize>,
+    /// The rayhunter version and system that recorded the capture, which
+    /// needn't be the ones analyzing it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recorded_with: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_os: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    /// The diag log layout versions the modem's firmware produced, as far as
+    /// they were seen while recording
+    #[serde(default)]
+    pub diag_versions: DiagLayoutVersions,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SamplingMetadata {
+    // one in this many containers of messages was analyzed
+    pub sample_every: usize,
+    // the analyzers that were left out because they need to see every
+    // message
+    pub excluded_analyzers: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct AnalysisRow {
     pub packet_timestamp: Option<DateTime<FixedOffset>>,
     pub skipped_message_reason: Option<String>,
+    /// One slot per analyzer, in the order the report's metadata lists them:
+    /// the built-in analyzers in the order [Harness::new_with_config] adds
+    /// them, then any from an [AnalyzerRegistry] in the order they were
+    /// registered. Since that order is fixed and the analyzers run one after
+    /// the other, analyzing the same input with the same config always
+    /// writes the same report.
     pub events: Vec<Option<Event>>,
+    /// Shared by every row with findings that came out of the same container
+    /// of messages (or pcap packet), since several analyzers often react to
+    /// the same underlying event, so that they can be grouped into one
+    /// incident. Unique within a report. Rows without findings don't have
+    /// one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<u64>,
 }
 
 impl AnalysisRow {
@@ -130,10 +829,96 @@ impl AnalysisRow {
         }
         false
     }
+
+    pub fn has_events(&self) -> bool {
+        self.events.iter().any(Option::is_some)
+    }
+}
+
+/// A QMDL message the harness couldn't decode
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DeadLetter {
+    /// where the message starts in the QMDL file
+    pub offset: usize,
+    /// the message's diag log code, if it got far enough to tell
+    pub log_code: Option<u16>,
+    /// the message's length in the QMDL file, i.e. HDLC-encapsulated
+    pub length: usize,
+    pub reason: String,
+}
+
+/// Builds an [Analyzer] from the config its [Harness] is built with, so it
+/// can read its own settings with [AnalyzerConfig::get_settings]
+pub type AnalyzerFactory = Box<dyn Fn(&AnalyzerConfig) -> Box<dyn Analyzer + Send> + Send + Sync>;
+
+/// Analyzers from outside this crate, which [Harness::new_with_registry]
+/// adds after the built-in ones the config enables
+#[derive(Default)]
+pub struct AnalyzerRegistry {
+    factories: Vec<(String, AnalyzerFactory)>,
+}
+
+impl AnalyzerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers an analyzer under the given key, which is what its severity
+    /// can be overridden by in [AnalyzerConfig::severity_overrides]. It
+    /// should differ from the built-in analyzers' toggles.
+    pub fn register_analyzer<F>(&mut self, key: &str, factory: F)
+    where
+        F: Fn(&AnalyzerConfig) -> Box<dyn Analyzer + Send> + Send + Sync + 'static,
+    {
+        self.factories.push((key.to_string(), Box::new(factory)));
+    }
+
+    /// Returns the keys of the registered analyzers, in the order they were
+    /// registered
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.factories.iter().map(|(key, _)| key.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.factories.is_empty()
+    }
 }
 
 pub struct Harness {
     analyzers: Vec<Box<dyn Analyzer + Send>>,
+    // the severity each analyzer's findings are reported as instead, if it's
+    // overridden, in the same order as the analyzers
+    severity_overrides: Vec<Option<SeverityOverride>>,
+    // the overrides configured for each analyzer, by toggle name
+    configured_overrides: BTreeMap<String, SeverityOverride>,
+    // how far each analyzer is through its warmup, in the same order as the
+    // analyzers
+    warmups: Vec<WarmupProgress>,
+    // the warmups configured for each analyzer, by toggle name
+    configured_warmups: BTreeMap<String, Warmup>,
+    diag_versions: DiagLayoutVersions,
+    // the layouts the modem uses beyond the ones the parser knows
+    modem_profile: ModemProfile,
+    // how far into the QMDL file the next container starts
+    qmdl_offset: usize,
+    // None unless the caller asked for them
+    dead_letters: Option<Vec<DeadLetter>>,
+    // the correlation ID the next group of related findings gets
+    next_correlation_id: u64,
+    // the log codes of the messages to decode, or None for all of them
+    log_codes: Option<HashSet<u16>>,
+    // the config the analyzers came from, if they did
+    analyzer_config: Option<AnalyzerConfig>,
+    // whether a panicking analyzer is disabled instead of unwinding through
+    // the harness
+    isolate_panics: bool,
+    // whether each analyzer has panicked, in the same order as the analyzers
+    panicked: Vec<bool>,
+    // how many QMDL log messages with log codes the analyzers look at have
+    // been seen so far
+    relevant_messages: usize,
+    // None unless only a sample of the containers is analyzed
+    sampling: Option<SamplingMetadata>,
 }
 
 impl Default for Harness {
@@ -146,34 +931,535 @@ impl Harness {
     pub fn new() -> Self {
         Self {
             analyzers: Vec::new(),
+            severity_overrides: Vec::new(),
+            configured_overrides: BTreeMap::new(),
+            warmups: Vec::new(),
+            configured_warmups: BTreeMap::new(),
+            diag_versions: DiagLayoutVersions::default(),
+            modem_profile: ModemProfile::default(),
+            qmdl_offset: 0,
+            dead_letters: None,
+            next_correlation_id: 0,
+            log_codes: None,
+            analyzer_config: None,
+            isolate_panics: true,
+            panicked: Vec::new(),
+            relevant_messages: 0,
+            sampling: None,
         }
     }
 
     pub fn new_with_config(analyzer_config: &AnalyzerConfig) -> Self {
+        Self::new_with_registry(analyzer_config, &AnalyzerRegistry::new())
+    }
+
+    /// Like [Harness::new_with_config], but also adds every analyzer in the
+    /// registry, built from the same config
+    pub fn new_with_registry(
+        analyzer_config: &AnalyzerConfig,
+        registry: &AnalyzerRegistry,
+    ) -> Self {
         let mut harness = Harness::new();
+        harness.configured_overrides = analyzer_config.severity_overrides.clone();
+        harness.configured_warmups = analyzer_config.warmup.clone();
+        harness.modem_profile = analyzer_config.modem_profile.clone();
+        harness.analyzer_config = Some(analyzer_config.clone());
+        harness.isolate_panics = analyzer_config.options.isolate_panics;
 
         if analyzer_config.imsi_requested {
-            harness.add_analyzer(Box::new(ImsiRequestedAnalyzer::new()));
+            harness
+                .add_configured_analyzer("imsi_requested", Box::new(ImsiRequestedAnalyzer::new()));
         }
         if analyzer_config.connection_redirect_2g_downgrade {
-            harness.add_analyzer(Box::new(ConnectionRedirect2GDowngradeAnalyzer {}));
+            harness.add_configured_analyzer(
+                "connection_redirect_2g_downgrade",
+                Box::new(ConnectionRedirect2GDowngradeAnalyzer {}),
+            );
         }
         if analyzer_config.lte_sib6_and_7_downgrade {
-            harness.add_analyzer(Box::new(LteSib6And7DowngradeAnalyzer {}));
+            harness.add_configured_analyzer(
+                "lte_sib6_and_7_downgrade",
+                Box::new(LteSib6And7DowngradeAnalyzer {}),
+            );
         }
         if analyzer_config.null_cipher {
-            harness.add_analyzer(Box::new(NullCipherAnalyzer {}));
+            harness.add_configured_analyzer("null_cipher", Box::new(NullCipherAnalyzer {}));
         }
 
         if analyzer_config.nas_null_cipher {
-            harness.add_analyzer(Box::new(NasNullCipherAnalyzer::new()))
+            harness
+                .add_configured_analyzer("nas_null_cipher", Box::new(NasNullCipherAnalyzer::new()))
+        }
+        if analyzer_config.network_detach {
+            harness
+                .add_configured_analyzer("network_detach", Box::new(NetworkDetachAnalyzer::new()));
+        }
+        if analyzer_config.unexpected_security_algorithms {
+            harness.add_configured_analyzer(
+                "unexpected_security_algorithms",
+                Box::new(SecurityPolicyAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.pci_conflict {
+            harness.add_configured_analyzer("pci_conflict", Box::new(PciConflictAnalyzer::new()));
+        }
+        if analyzer_config.periodic_tau_timer {
+            harness.add_configured_analyzer(
+                "periodic_tau_timer",
+                Box::new(PeriodicTauTimerAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.cs_fallback_downgrade {
+            harness.add_configured_analyzer(
+                "cs_fallback_downgrade",
+                Box::new(CsFallbackAnalyzer::new()),
+            );
+        }
+        if analyzer_config.reselection_churn {
+            harness.add_configured_analyzer(
+                "reselection_churn",
+                Box::new(ReselectionChurnAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.auth_without_identity {
+            harness.add_configured_analyzer(
+                "auth_without_identity",
+                Box::new(AuthWithoutIdentityAnalyzer::new()),
+            );
+        }
+        if analyzer_config.unexpected_plmn {
+            harness.add_configured_analyzer(
+                "unexpected_plmn",
+                Box::new(UnexpectedPlmnAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.counter_replay {
+            harness
+                .add_configured_analyzer("counter_replay", Box::new(CounterReplayAnalyzer::new()));
+        }
+        if analyzer_config.unexpected_band {
+            harness.add_configured_analyzer(
+                "unexpected_band",
+                Box::new(UnexpectedBandAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.uplink_starvation {
+            harness.add_configured_analyzer(
+                "uplink_starvation",
+                Box::new(UplinkStarvationAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.access_barring {
+            harness.add_configured_analyzer(
+                "access_barring",
+                Box::new(AccessBarringAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.duplex_config_change {
+            harness.add_configured_analyzer(
+                "duplex_config_change",
+                Box::new(DuplexChangeAnalyzer::new()),
+            );
+        }
+        if analyzer_config.unprotected_reconfiguration {
+            harness.add_configured_analyzer(
+                "unprotected_reconfiguration",
+                Box::new(UnprotectedReconfigurationAnalyzer::new()),
+            );
+        }
+        if analyzer_config.weak_cell_attach {
+            harness.add_configured_analyzer(
+                "weak_cell_attach",
+                Box::new(WeakCellAttachAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.security_mode_failure {
+            harness.add_configured_analyzer(
+                "security_mode_failure",
+                Box::new(SecurityModeFailureAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.out_of_spec_ie {
+            harness.add_configured_analyzer(
+                "out_of_spec_ie",
+                Box::new(OutOfSpecIeAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.cell_distance {
+            harness.add_configured_analyzer(
+                "cell_distance",
+                Box::new(CellDistanceAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.steering_reject {
+            harness.add_configured_analyzer(
+                "steering_reject",
+                Box::new(SteeringRejectAnalyzer::new()),
+            );
+        }
+        if analyzer_config.emergency_numbers {
+            harness.add_configured_analyzer(
+                "emergency_numbers",
+                Box::new(EmergencyNumbersAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.cell_selection {
+            harness.add_configured_analyzer(
+                "cell_selection",
+                Box::new(CellSelectionAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.reestablishment_storm {
+            harness.add_configured_analyzer(
+                "reestablishment_storm",
+                Box::new(ReestablishmentStormAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.repaging {
+            harness.add_configured_analyzer(
+                "repaging",
+                Box::new(RepagingAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.power_control {
+            harness.add_configured_analyzer(
+                "power_control",
+                Box::new(PowerControlAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.cell_identity_mismatch {
+            harness.add_configured_analyzer(
+                "cell_identity_mismatch",
+                Box::new(CellIdentityMismatchAnalyzer::new()),
+            );
+        }
+        if analyzer_config.cell_diversity {
+            harness.add_configured_analyzer(
+                "cell_diversity",
+                Box::new(CellDiversityAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.sim_ota {
+            harness
+                .add_configured_analyzer("sim_ota", Box::new(SimOtaAnalyzer::new(analyzer_config)));
+        }
+        if analyzer_config.nitz {
+            harness.add_configured_analyzer("nitz", Box::new(NitzAnalyzer::new(analyzer_config)));
+        }
+        if analyzer_config.idle_mode_steering {
+            harness.add_configured_analyzer(
+                "idle_mode_steering",
+                Box::new(IdleModeSteeringAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.tmsi_lifetime {
+            harness.add_configured_analyzer(
+                "tmsi_lifetime",
+                Box::new(TmsiLifetimeAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.antenna_config {
+            harness.add_configured_analyzer(
+                "antenna_config",
+                Box::new(AntennaConfigAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.service_reject {
+            harness.add_configured_analyzer(
+                "service_reject",
+                Box::new(ServiceRejectAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.cell_swap {
+            harness.add_configured_analyzer(
+                "cell_swap",
+                Box::new(CellSwapAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.uac_barring {
+            harness.add_configured_analyzer(
+                "uac_barring",
+                Box::new(UacBarringAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.rrc_integrity {
+            harness.add_configured_analyzer("rrc_integrity", Box::new(RrcIntegrityAnalyzer::new()));
+        }
+        if analyzer_config.repeated_identity_request {
+            harness.add_configured_analyzer(
+                "repeated_identity_request",
+                Box::new(RepeatedIdentityRequestAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.etws_mismatch {
+            harness.add_configured_analyzer(
+                "etws_mismatch",
+                Box::new(EtwsMismatchAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.positioning_measurement {
+            harness.add_configured_analyzer(
+                "positioning_measurement",
+                Box::new(PositioningMeasurementAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.band_indicator_mismatch {
+            harness.add_configured_analyzer(
+                "band_indicator_mismatch",
+                Box::new(BandIndicatorMismatchAnalyzer::new()),
+            );
+        }
+        if analyzer_config.handover_ping_pong {
+            harness.add_configured_analyzer(
+                "handover_ping_pong",
+                Box::new(HandoverPingPongAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.sib_schedule {
+            harness.add_configured_analyzer(
+                "sib_schedule",
+                Box::new(SibScheduleAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.attach_reject_downgrade {
+            harness.add_configured_analyzer(
+                "attach_reject_downgrade",
+                Box::new(AttachRejectDowngradeAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.nas_rrc_consistency {
+            harness.add_configured_analyzer(
+                "nas_rrc_consistency",
+                Box::new(NasRrcConsistencyAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.location_reporting {
+            harness.add_configured_analyzer(
+                "location_reporting",
+                Box::new(LocationReportingAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.reconfiguration_churn {
+            harness.add_configured_analyzer(
+                "reconfiguration_churn",
+                Box::new(ReconfigurationChurnAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.dedicated_sib1_mismatch {
+            harness.add_configured_analyzer(
+                "dedicated_sib1_mismatch",
+                Box::new(DedicatedSib1MismatchAnalyzer::new()),
+            );
+        }
+        if analyzer_config.si_periodicity_change {
+            harness.add_configured_analyzer(
+                "si_periodicity_change",
+                Box::new(SiPeriodicityChangeAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.network_triggered_tau {
+            harness.add_configured_analyzer(
+                "network_triggered_tau",
+                Box::new(NetworkTriggeredTauAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.placeholder_identity {
+            harness.add_configured_analyzer(
+                "placeholder_identity",
+                Box::new(PlaceholderIdentityAnalyzer::new()),
+            );
+        }
+        if analyzer_config.value_tag_consistency {
+            harness.add_configured_analyzer(
+                "value_tag_consistency",
+                Box::new(ValueTagConsistencyAnalyzer::new()),
+            );
+        }
+        if analyzer_config.connection_hold {
+            harness.add_configured_analyzer(
+                "connection_hold",
+                Box::new(ConnectionHoldAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.ims_reject_downgrade {
+            harness.add_configured_analyzer(
+                "ims_reject_downgrade",
+                Box::new(ImsRejectDowngradeAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.plmn_overlap {
+            harness.add_configured_analyzer(
+                "plmn_overlap",
+                Box::new(PlmnOverlapAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.measurement_gap {
+            harness.add_configured_analyzer(
+                "measurement_gap",
+                Box::new(MeasurementGapAnalyzer::new(analyzer_config)),
+            );
+        }
+        if analyzer_config.interrogation_ratio {
+            harness.add_configured_analyzer(
+                "interrogation_ratio",
+                Box::new(InterrogationRatioAnalyzer::new(analyzer_config)),
+            );
+        }
+        // registered analyzers come after every built-in one, so adding a
+        // built-in analyzer doesn't move them
+        for (key, factory) in &registry.factories {
+            harness.add_configured_analyzer(key, factory(analyzer_config));
         }
 
+        let log_codes = match &analyzer_config.log_codes {
+            Some(log_codes) => log_codes.iter().copied().collect(),
+            None => harness.get_required_log_codes(),
+        };
+        harness.set_log_codes(Some(log_codes));
         harness
     }
 
+    /// Returns the log codes of the messages the analyzers look at
+    pub fn get_required_log_codes(&self) -> HashSet<u16> {
+        self.analyzers
+            .iter()
+            .flat_map(|analyzer| analyzer.get_log_codes().iter().copied())
+            .collect()
+    }
+
+    /// Only decodes log messages with the given log codes from now on, or
+    /// all of them for None. Others still get a row, just an empty one.
+    pub fn set_log_codes(&mut self, log_codes: Option<HashSet<u16>>) {
+        self.log_codes = log_codes;
+    }
+
+    /// Returns how many QMDL log messages with the log codes being decoded
+    /// (see [Harness::set_log_codes]) have been analyzed so far. When this
+    /// stays at 0 for a whole recording, the modem wasn't logging any of the
+    /// messages the analyzers look at.
+    pub fn get_relevant_message_count(&self) -> usize {
+        self.relevant_messages
+    }
+
+    // Marks the LTE RRC OTA versions seen so far that no layout is known for,
+    // saying so once for each, since every message they're used for is going
+    // to be skipped
+    fn check_diag_versions(&mut self) {
+        for &version in &self.diag_versions.lte_rrc_ota {
+            if self.modem_profile.lte_rrc_pdu_layout(version).is_some()
+                || !self.diag_versions.unsupported_lte_rrc_ota.insert(version)
+            {
+                continue;
+            }
+            error!(
+                "the modem logs LTE RRC messages with ext header version {version}, whose layout is unknown, so they can't be analyzed. If you know which layout it uses, add it to the analyzer config's modem_profile."
+            );
+        }
+    }
+
+    /// Returns the diag layout versions seen in the QMDL messages analyzed so far
+    pub fn get_diag_versions(&self) -> &DiagLayoutVersions {
+        &self.diag_versions
+    }
+
+    /// Starts keeping track of the QMDL messages that fail to decode, which
+    /// can be collected with [Harness::take_dead_letters]
+    pub fn record_dead_letters(&mut self) {
+        self.dead_letters.get_or_insert_with(Vec::new);
+    }
+
+    /// Returns the messages that failed to decode since this was last called
+    pub fn take_dead_letters(&mut self) -> Vec<DeadLetter> {
+        self.dead_letters
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn add_dead_letter(
+        &mut self,
+        offset: usize,
+        length: usize,
+        log_code: Option<u16>,
+        reason: &str,
+    ) {
+        if let Some(dead_letters) = self.dead_letters.as_mut() {
+            dead_letters.push(DeadLetter {
+                offset,
+                log_code,
+                length,
+                reason: reason.to_string(),
+            });
+        }
+    }
+
     pub fn add_analyzer(&mut self, analyzer: Box<dyn Analyzer + Send>) {
+        self.warmups
+            .push(WarmupProgress::new(analyzer.get_warmup()));
+        self.analyzers.push(analyzer);
+        self.severity_overrides.push(None);
+        self.panicked.push(false);
+    }
+
+    // Adds an analyzer enabled by the given toggle in [AnalyzerConfig],
+    // along with any severity override and warmup configured for it
+    fn add_configured_analyzer(&mut self, toggle: &str, analyzer: Box<dyn Analyzer + Send>) {
+        let warmup = self
+            .configured_warmups
+            .get(toggle)
+            .copied()
+            .unwrap_or_else(|| analyzer.get_warmup());
+        self.warmups.push(WarmupProgress::new(warmup));
         self.analyzers.push(analyzer);
+        self.severity_overrides
+            .push(self.configured_overrides.get(toggle).copied());
+        self.panicked.push(false);
+    }
+
+    /// Marks the analysis as covering only one in every `sample_every`
+    /// containers, which the caller picks out, skipping the rest with
+    /// [Harness::skip_qmdl_messages]. Analyzers that need every message are
+    /// removed, so this has to be called before the metadata is taken, and
+    /// it's recorded there along with the analyzers removed. Does nothing if
+    /// `sample_every` is 0 or 1.
+    pub fn set_sampling(&mut self, sample_every: usize) {
+        if sample_every <= 1 {
+            return;
+        }
+        let mut excluded_analyzers = Vec::new();
+        let mut index = 0;
+        while index < self.analyzers.len() {
+            if self.analyzers[index].needs_every_message() {
+                let analyzer = self.analyzers.remove(index);
+                self.severity_overrides.remove(index);
+                self.warmups.remove(index);
+                self.panicked.remove(index);
+                excluded_analyzers.push(analyzer.get_name().to_string());
+            } else {
+                index += 1;
+            }
+        }
+        self.sampling = Some(SamplingMetadata {
+            sample_every,
+            excluded_analyzers,
+        });
+    }
+
+    /// Whether an analyzer that panics is disabled, logging the panic, so
+    /// the others can carry on. Otherwise the panic unwinds to the caller.
+    pub fn set_isolate_panics(&mut self, isolate_panics: bool) {
+        self.isolate_panics = isolate_panics;
+    }
+
+    /// Returns the names of the analyzers that have been disabled after
+    /// panicking
+    pub fn get_panicked_analyzers(&self) -> Vec<String> {
+        self.analyzers
+            .iter()
+            .zip(&self.panicked)
+            .filter(|(_, panicked)| **panicked)
+            .map(|(analyzer, _)| analyzer.get_name().to_string())
+            .collect()
     }
 
     pub fn analyze_pcap_packet(&mut self, packet: EnhancedPacketBlock) -> AnalysisRow {
@@ -182,6 +1468,7 @@ impl Harness {
             packet_timestamp: Some(epoch + packet.timestamp),
             skipped_message_reason: None,
             events: Vec::new(),
+            correlation_id: None,
         };
         let gsmtap_offset = 20 + 8;
         let gsmtap_data = &packet.data[gsmtap_offset..];
@@ -195,69 +1482,207 @@ impl Harness {
         };
         let packet_offset = gsmtap_offset + 16;
         let packet_data = &packet.data[packet_offset..];
+        let ctx = PacketContext {
+            timestamp: row.packet_timestamp,
+            earfcn: Some(gsmtap_header.arfcn as u32),
+            phy_cell_id: None,
+            bearer_id: None,
+        };
         let gsmtap_message = GsmtapMessage {
             header: gsmtap_header,
             payload: packet_data.to_vec(),
         };
         row.events = match InformationElement::try_from(&gsmtap_message) {
-            Ok(element) => self.analyze_information_element(&element),
+            Ok(element) => self.analyze_information_element(&element, &ctx),
             Err(err) => {
                 row.skipped_message_reason =
                     Some(format!("failed to convert gsmtap message to IE: {err:?}"));
                 return row;
             }
         };
+        self.correlate(std::slice::from_mut(&mut row));
         row
     }
 
+    // Gives the rows with findings among the given ones a shared correlation
+    // ID, if there are any
+    fn correlate(&mut self, rows: &mut [AnalysisRow]) {
+        if !rows.iter().any(AnalysisRow::has_events) {
+            return;
+        }
+        let correlation_id = self.next_correlation_id;
+        self.next_correlation_id += 1;
+        for row in rows.iter_mut().filter(|row| row.has_events()) {
+            row.correlation_id = Some(correlation_id);
+        }
+    }
+
+    /// Skips over a container without analyzing it, keeping the offsets of
+    /// the ones after it right
+    pub fn skip_qmdl_messages(&mut self, container: &MessagesContainer) {
+        self.qmdl_offset += container
+            .messages
+            .iter()
+            .map(|msg| msg.data.len())
+            .sum::<usize>();
+    }
+
     pub fn analyze_qmdl_messages(&mut self, container: MessagesContainer) -> Vec<AnalysisRow> {
         let mut rows = Vec::new();
-        for maybe_qmdl_message in container.into_messages() {
+        let container_offset = self.qmdl_offset;
+        self.qmdl_offset += container
+            .messages
+            .iter()
+            .map(|msg| msg.data.len())
+            .sum::<usize>();
+        let messages = container.into_messages_with_offsets_filtered(self.log_codes.as_ref());
+        for (offset, length, maybe_qmdl_message) in messages {
+            let offset = container_offset + offset;
             rows.push(AnalysisRow {
                 packet_timestamp: None,
                 skipped_message_reason: None,
                 events: Vec::new(),
+                correlation_id: None,
             });
             // unwrap is safe here since we just pushed a value
             let row = rows.last_mut().unwrap();
+            // no analyzer needs it
+            let Some(maybe_qmdl_message) = maybe_qmdl_message else {
+                continue;
+            };
             let qmdl_message = match maybe_qmdl_message {
                 Ok(msg) => msg,
                 Err(err) => {
-                    row.skipped_message_reason = Some(format!("{err:?}"));
+                    let reason = format!("{err:?}");
+                    self.add_dead_letter(offset, length, err.log_code(), &reason);
+                    row.skipped_message_reason = Some(reason);
                     continue;
                 }
             };
-
-            let gsmtap_message = match gsmtap_parser::parse(qmdl_message) {
-                Ok(msg) => msg,
-                Err(err) => {
-                    row.skipped_message_reason = Some(format!("{err:?}"));
-                    continue;
-                }
+            let log_code = match &qmdl_message {
+                Message::Log { log_type, .. } => Some(*log_type),
+                _ => None,
             };
+            if log_code.is_some_and(|log_code| {
+                self.log_codes
+                    .as_ref()
+                    .is_none_or(|log_codes| log_codes.contains(&log_code))
+            }) {
+                self.relevant_messages += 1;
+            }
+
+            if self.diag_versions.observe(&qmdl_message) {
+                debug!(
+                    "detected new diag layout versions: {:?}",
+                    self.diag_versions
+                );
+                self.check_diag_versions();
+            }
+
+            // grab anything gsmtap doesn't have room for before the message
+            // is consumed
+            let mut ctx = PacketContext::default();
+            if let Message::Log {
+                body: LogBody::LteRrcOtaMessage { packet, .. },
+                ..
+            } = &qmdl_message
+            {
+                ctx.earfcn = Some(packet.get_earfcn());
+                ctx.phy_cell_id = Some(packet.get_phy_cell_id());
+                ctx.bearer_id = Some(packet.get_bearer_id());
+            }
+
+            let gsmtap_message =
+                match gsmtap_parser::parse_with_profile(qmdl_message, &self.modem_profile) {
+                    Ok(msg) => msg,
+                    Err(err) => {
+                        let reason = format!("{err:?}");
+                        self.add_dead_letter(offset, length, log_code, &reason);
+                        row.skipped_message_reason = Some(reason);
+                        continue;
+                    }
+                };
 
             let Some((timestamp, gsmtap_msg)) = gsmtap_message else {
                 continue;
             };
             row.packet_timestamp = Some(timestamp.to_datetime());
+            ctx.timestamp = row.packet_timestamp;
 
             let element = match InformationElement::try_from(&gsmtap_msg) {
                 Ok(element) => element,
                 Err(err) => {
-                    row.skipped_message_reason = Some(format!("{err:?}"));
+                    let reason = format!("{err:?}");
+                    self.add_dead_letter(offset, length, log_code, &reason);
+                    row.skipped_message_reason = Some(reason);
                     continue;
                 }
             };
 
-            row.events = self.analyze_information_element(&element);
+            row.events = self.analyze_information_element(&element, &ctx);
         }
+        self.correlate(&mut rows);
         rows
     }
 
-    pub fn analyze_information_element(&mut self, ie: &InformationElement) -> Vec<Option<Event>> {
+    pub fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Vec<Option<Event>> {
+        let isolate_panics = self.isolate_panics;
         self.analyzers
             .iter_mut()
-            .map(|analyzer| analyzer.analyze_information_element(ie))
+            .zip(&self.severity_overrides)
+            .zip(&mut self.panicked)
+            .zip(&mut self.warmups)
+            .map(|(((analyzer, severity_override), panicked), warmup)| {
+                if *panicked {
+                    return None;
+                }
+                let warmed_up = warmup.observe(ctx);
+                let mut event = if isolate_panics {
+                    // the analyzer is never used again after a panic, so
+                    // whatever state it left itself in doesn't matter
+                    let result =
+                        catch_unwind(AssertUnwindSafe(|| analyzer.analyze_information_element(ie, ctx)));
+                    match result {
+                        Ok(event) => event?,
+                        Err(payload) => {
+                            let reason = payload
+                                .downcast_ref::<&str>()
+                                .map(|reason| reason.to_string())
+                                .or_else(|| payload.downcast_ref::<String>().cloned())
+                                .unwrap_or_default();
+                            error!(
+                                "analyzer {} panicked ({reason}), disabling it. message at {:?}: {ie:?}",
+                                analyzer.get_name(),
+                                ctx.timestamp
+                            );
+                            *panicked = true;
+                            return None;
+                        }
+                    }
+                } else {
+                    analyzer.analyze_information_element(ie, ctx)?
+                };
+                if !warmed_up {
+                    return None;
+                }
+                if let Some(severity_override) = severity_override {
+                    severity_override.apply(&mut event);
+                }
+                Some(event)
+            })
+            .collect()
+    }
+
+    /// Returns each analyzer's [Analyzer::get_state], in the same order as
+    /// the events in an [AnalysisRow]
+    pub fn get_analyzer_states(&self) -> Vec<Option<String>> {
+        self.analyzers
+            .iter()
+            .map(|analyzer| analyzer.get_state())
             .collect()
     }
 
@@ -277,6 +1702,380 @@ impl Harness {
             analyzers,
             rayhunter,
             report_version: REPORT_VERSION,
+            recordings: None,
+            analyzer_config: self.analyzer_config.clone(),
+            analyzer_config_hash: self
+                .analyzer_config
+                .as_ref()
+                .map(AnalyzerConfig::get_config_hash),
+            sampling: self.sampling.clone(),
+            capture: None,
+        }
+    }
+
+    /// Returns a short identifier for the active set of analyzers and their
+    /// versions, which changes whenever an analyzer is enabled, disabled, or
+    /// has its version bumped. Reports produced by harnesses with the same
+    /// identifier should be comparable.
+    pub fn get_analyzer_set_hash(&self) -> String {
+        let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+        let mut digest = crc.digest();
+        for analyzer in &self.analyzers {
+            digest.update(analyzer.get_name().as_bytes());
+            digest.update(&analyzer.get_version().to_le_bytes());
+        }
+        format!("{:08x}", digest.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyzer_settings() {
+        let mut config = AnalyzerConfig::default();
+        let settings: PeriodicTauTimerSettings = config.get_settings();
+        assert_eq!(settings.t3412_min_secs, 2 * 60);
+        assert!(config.validate_settings().is_empty());
+
+        config.settings.insert(
+            "periodic_tau_timer".to_string(),
+            serde_json::json!({"t3412_min_secs": 60, "t3412_max_secs": "forever", "t3412": 1}),
+        );
+        config
+            .settings
+            .insert("not_an_analyzer".to_string(), serde_json::json!({}));
+        let settings: PeriodicTauTimerSettings = config.get_settings();
+        assert_eq!(settings.t3412_min_secs, 60);
+        assert_eq!(settings.t3412_max_secs, 12 * 60 * 60);
+        assert_eq!(config.validate_settings().len(), 3);
+    }
+
+    #[test]
+    fn test_required_analyzers() {
+        let mut config = AnalyzerConfig::default();
+        let required = ["null_cipher".to_string(), "imsi_requested".to_string()];
+        assert!(config.check_required(&required).is_empty());
+
+        config.null_cipher = false;
+        let required = [
+            "null_cipher".to_string(),
+            "imsi_requested".to_string(),
+            "settings".to_string(),
+            "no_such_analyzer".to_string(),
+        ];
+        assert_eq!(config.check_required(&required).len(), 3);
+    }
+
+    struct AlwaysWarns;
+
+    impl Analyzer for AlwaysWarns {
+        fn get_name(&self) -> Cow<str> {
+            Cow::from("Always Warns")
+        }
+
+        fn get_description(&self) -> Cow<str> {
+            Cow::from("")
+        }
+
+        fn get_version(&self) -> u32 {
+            1
+        }
+
+        fn analyze_information_element(
+            &mut self,
+            _ie: &InformationElement,
+            _ctx: &PacketContext,
+        ) -> Option<Event> {
+            Some(Event {
+                event_type: EventType::QualitativeWarning {
+                    severity: Severity::Low,
+                },
+                message: String::new(),
+                explanation: String::new(),
+                evidence: Vec::new(),
+            })
+        }
+    }
+
+    #[test]
+    fn test_severity_overrides() {
+        let mut config = AnalyzerConfig::default();
+        config
+            .severity_overrides
+            .insert("null_cipher".to_string(), SeverityOverride::High);
+        config
+            .severity_overrides
+            .insert("no_such_analyzer".to_string(), SeverityOverride::Low);
+        assert_eq!(config.validate_settings().len(), 1);
+
+        let mut harness = Harness::new();
+        harness.configured_overrides = config.severity_overrides.clone();
+        harness.add_configured_analyzer("null_cipher", Box::new(AlwaysWarns));
+        harness.add_analyzer(Box::new(AlwaysWarns));
+        let ie = InformationElement::GSM;
+        let events = harness.analyze_information_element(&ie, &PacketContext::default());
+        let severities: Vec<_> = events
+            .iter()
+            .map(|event| event.as_ref().unwrap().event_type.clone())
+            .collect();
+        assert!(matches!(
+            severities[..],
+            [
+                EventType::QualitativeWarning {
+                    severity: Severity::High
+                },
+                EventType::QualitativeWarning {
+                    severity: Severity::Low
+                }
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_warmup() {
+        let mut config = AnalyzerConfig::default();
+        config.warmup.insert(
+            "null_cipher".to_string(),
+            Warmup {
+                min_messages: 3,
+                min_secs: 0,
+            },
+        );
+        config
+            .warmup
+            .insert("no_such_analyzer".to_string(), Warmup::default());
+        assert_eq!(config.validate_settings().len(), 1);
+
+        let ie = InformationElement::GSM;
+        let warned = |harness: &mut Harness, ctx: &PacketContext| -> Vec<bool> {
+            harness
+                .analyze_information_element(&ie, ctx)
+                .iter()
+                .map(Option::is_some)
+                .collect()
+        };
+        let mut harness = Harness::new();
+        harness.configured_warmups = config.warmup.clone();
+        harness.add_configured_analyzer("null_cipher", Box::new(AlwaysWarns));
+        harness.add_analyzer(Box::new(AlwaysWarns));
+        let ctx = PacketContext::default();
+        assert_eq!(warned(&mut harness, &ctx), [false, true]);
+        assert_eq!(warned(&mut harness, &ctx), [false, true]);
+        assert_eq!(warned(&mut harness, &ctx), [true, true]);
+
+        // durations are measured by the messages' timestamps
+        let mut harness = Harness::new();
+        harness.configured_warmups.insert(
+            "null_cipher".to_string(),
+            Warmup {
+                min_messages: 0,
+                min_secs: 60,
+            },
+        );
+        harness.add_configured_analyzer("null_cipher", Box::new(AlwaysWarns));
+        let start = DateTime::parse_from_rfc3339("2024-01-01T00:00:00+00:00").unwrap();
+        let at = |secs| PacketContext {
+            timestamp: Some(start + chrono::TimeDelta::seconds(secs)),
+            ..PacketContext::default()
+        };
+        assert_eq!(warned(&mut harness, &PacketContext::default()), [false]);
+        assert_eq!(warned(&mut harness, &at(0)), [false]);
+        assert_eq!(warned(&mut harness, &at(59)), [false]);
+        assert_eq!(warned(&mut harness, &at(60)), [true]);
+    }
+
+    struct Panics;
+
+    impl Analyzer for Panics {
+        fn get_name(&self) -> Cow<str> {
+            Cow::from("Panics")
+        }
+
+        fn get_description(&self) -> Cow<str> {
+            Cow::from("")
+        }
+
+        fn get_version(&self) -> u32 {
+            1
+        }
+
+        fn analyze_information_element(
+            &mut self,
+            _ie: &InformationElement,
+            _ctx: &PacketContext,
+        ) -> Option<Event> {
+            panic!("malformed message");
+        }
+    }
+
+    #[test]
+    fn test_isolate_panics() {
+        let mut harness = Harness::new();
+        harness.add_analyzer(Box::new(Panics));
+        harness.add_analyzer(Box::new(AlwaysWarns));
+        let ie = InformationElement::GSM;
+        for _ in 0..2 {
+            let events = harness.analyze_information_element(&ie, &PacketContext::default());
+            assert!(events[0].is_none());
+            assert!(events[1].is_some());
+        }
+        assert_eq!(harness.get_panicked_analyzers(), vec!["Panics".to_string()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "malformed message")]
+    fn test_unisolated_panics() {
+        let mut harness = Harness::new();
+        harness.set_isolate_panics(false);
+        harness.add_analyzer(Box::new(Panics));
+        harness.analyze_information_element(&InformationElement::GSM, &PacketContext::default());
+    }
+
+    #[test]
+    fn test_sampling() {
+        let mut harness = Harness::new();
+        harness.add_analyzer(Box::new(AlwaysWarns));
+        harness.add_analyzer(Box::new(RepagingAnalyzer::new(&AnalyzerConfig::default())));
+        harness.set_sampling(1);
+        assert!(harness.get_metadata().sampling.is_none());
+
+        harness.set_sampling(10);
+        let metadata = harness.get_metadata();
+        assert_eq!(metadata.analyzers.len(), 1);
+        let sampling = metadata.sampling.unwrap();
+        assert_eq!(sampling.sample_every, 10);
+        assert_eq!(sampling.excluded_analyzers.len(), 1);
+        let events = harness
+            .analyze_information_element(&InformationElement::GSM, &PacketContext::default());
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn test_required_log_codes() {
+        let config = AnalyzerConfig::default().only("null_cipher").unwrap();
+        // harness options aren't analyzers, so they're left alone
+        assert!(config.options.isolate_panics);
+        let harness = Harness::new_with_config(&config);
+        assert_eq!(harness.get_required_log_codes(), HashSet::from([0xb0c0]));
+        assert_eq!(harness.log_codes, Some(HashSet::from([0xb0c0])));
+
+        // configured log codes win over the ones the analyzers need
+        let config = AnalyzerConfig {
+            log_codes: Some(vec![0xb0ec]),
+            ..config
+        };
+        let harness = Harness::new_with_config(&config);
+        assert_eq!(harness.log_codes, Some(HashSet::from([0xb0ec])));
+    }
+
+    #[test]
+    fn test_analyzer_registry() {
+        let mut config = AnalyzerConfig::default();
+        config
+            .severity_overrides
+            .insert("always_warns".to_string(), SeverityOverride::High);
+        let mut registry = AnalyzerRegistry::new();
+        registry.register_analyzer("always_warns", |_| Box::new(AlwaysWarns));
+        assert_eq!(registry.keys().collect::<Vec<_>>(), vec!["always_warns"]);
+
+        let mut harness = Harness::new_with_registry(&config, &registry);
+        let names: Vec<String> = harness
+            .get_metadata()
+            .analyzers
+            .into_iter()
+            .map(|analyzer| analyzer.name)
+            .collect();
+        // it comes after every built-in analyzer the config enables
+        let built_in = Harness::new_with_config(&config).get_metadata().analyzers;
+        assert_eq!(names.len(), built_in.len() + 1);
+        assert_eq!(names.last().map(String::as_str), Some("Always Warns"));
+        // its log codes count too
+        let log_codes = harness.log_codes.clone().unwrap();
+        assert!(
+            ANALYZER_LOG_CODES
+                .iter()
+                .all(|code| log_codes.contains(code))
+        );
+        let events = harness
+            .analyze_information_element(&InformationElement::GSM, &PacketContext::default());
+        assert!(matches!(
+            events.last(),
+            Some(Some(Event {
+                event_type: EventType::QualitativeWarning {
+                    severity: Severity::High
+                },
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_config_snapshot() {
+        let config = AnalyzerConfig::default();
+        let metadata = Harness::new_with_config(&config).get_metadata();
+        assert_eq!(
+            metadata.analyzer_config_hash,
+            Some(config.get_config_hash())
+        );
+        let snapshot = serde_json::to_value(&metadata).unwrap()["analyzer_config"].clone();
+        let restored: AnalyzerConfig = serde_json::from_value(snapshot).unwrap();
+        assert_eq!(restored.get_config_hash(), config.get_config_hash());
+
+        let other = AnalyzerConfig {
+            null_cipher: false,
+            ..AnalyzerConfig::default()
+        };
+        assert_ne!(other.get_config_hash(), config.get_config_hash());
+
+        // analyzers added by hand don't come from a config
+        assert_eq!(Harness::new().get_metadata().analyzer_config_hash, None);
+    }
+
+    #[test]
+    fn test_reports_are_deterministic() {
+        let config_with = |overrides: &[&str]| {
+            let mut config = AnalyzerConfig::default();
+            for (i, toggle) in overrides.iter().enumerate() {
+                config
+                    .severity_overrides
+                    .insert(toggle.to_string(), SeverityOverride::High);
+                config
+                    .settings
+                    .insert(toggle.to_string(), serde_json::json!({ "i": i }));
+            }
+            config
+        };
+        let report = |config: &AnalyzerConfig| {
+            let mut harness = Harness::new_with_config(config);
+            harness.add_analyzer(Box::new(AlwaysWarns));
+            let row = AnalysisRow {
+                packet_timestamp: None,
+                skipped_message_reason: None,
+                events: harness.analyze_information_element(
+                    &InformationElement::GSM,
+                    &PacketContext::default(),
+                ),
+                correlation_id: None,
+            };
+            format!(
+                "{}\n{}",
+                serde_json::to_string(&harness.get_metadata()).unwrap(),
+                serde_json::to_string(&row).unwrap()
+            )
+        };
+        let toggles = ["null_cipher", "imsi_requested", "nitz", "cell_swap"];
+        let first = report(&config_with(&toggles));
+        assert_eq!(report(&config_with(&toggles)), first);
+        // nor does the order the config was put together in matter
+        let mut reversed = config_with(&[]);
+        for toggle in toggles.iter().rev() {
+            reversed
+                .severity_overrides
+                .insert(toggle.to_string(), SeverityOverride::High);
         }
+        reversed.settings = config_with(&toggles).settings;
+        assert_eq!(report(&reversed), first);
     }
 }
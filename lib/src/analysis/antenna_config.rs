@@ -0,0 +1,356 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_DCCH_MessageType, DL_DCCH_MessageType_c1, MasterInformationBlock, MobilityControlInfo,
+    PDSCH_ConfigCommon, RRCConnectionReconfigurationCriticalExtensions,
+    RRCConnectionReconfigurationCriticalExtensions_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1, unpack_sib2};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AntennaConfigSettings {
+    // how many times in a row a cell has to be seen with a new configuration
+    // before the change is reported
+    pub min_observations: usize,
+}
+
+impl Default for AntennaConfigSettings {
+    fn default() -> Self {
+        Self {
+            min_observations: 2,
+        }
+    }
+}
+
+impl AnalyzerSettings for AntennaConfigSettings {
+    const KEY: &'static str = "antenna_config";
+}
+
+// The physical layer configuration a cell broadcasts in its MIB, which never
+// changes while the cell is up
+#[derive(Debug, Clone, PartialEq)]
+struct MibConfig {
+    dl_bandwidth: u8,
+    phich_duration: u8,
+    phich_resource: u8,
+}
+
+impl MibConfig {
+    fn from_mib(mib: &MasterInformationBlock) -> Self {
+        MibConfig {
+            dl_bandwidth: mib.dl_bandwidth.0,
+            phich_duration: mib.phich_config.phich_duration.0,
+            phich_resource: mib.phich_config.phich_resource.0,
+        }
+    }
+}
+
+impl fmt::Display for MibConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let resource_blocks = [6, 15, 25, 50, 75, 100]
+            .get(usize::from(self.dl_bandwidth))
+            .copied()
+            .unwrap_or_default();
+        let duration = if self.phich_duration == 0 {
+            "normal"
+        } else {
+            "extended"
+        };
+        let resource = ["1/6", "1/2", "1", "2"]
+            .get(usize::from(self.phich_resource))
+            .copied()
+            .unwrap_or("?");
+        write!(
+            f,
+            "{resource_blocks} RB bandwidth, {duration} PHICH duration, PHICH Ng {resource}"
+        )
+    }
+}
+
+// The downlink reference signal configuration SIB2 carries. P-b depends on
+// how many antenna ports the cell transmits on.
+#[derive(Debug, Clone, PartialEq)]
+struct ReferenceSignalConfig {
+    reference_signal_power: i8,
+    p_b: u8,
+}
+
+impl ReferenceSignalConfig {
+    fn from_pdsch(pdsch: &PDSCH_ConfigCommon) -> Self {
+        ReferenceSignalConfig {
+            reference_signal_power: pdsch.reference_signal_power.0,
+            p_b: pdsch.p_b.0,
+        }
+    }
+}
+
+impl fmt::Display for ReferenceSignalConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "reference signal power {} dBm, P-b {}",
+            self.reference_signal_power, self.p_b
+        )
+    }
+}
+
+// A configuration seen for a cell, along with a different one it may be
+// changing to
+struct Tracked<T> {
+    confirmed: Option<T>,
+    // the different configuration seen since, and how many times in a row
+    candidate: Option<(T, usize)>,
+}
+
+impl<T> Default for Tracked<T> {
+    fn default() -> Self {
+        Tracked {
+            confirmed: None,
+            candidate: None,
+        }
+    }
+}
+
+impl<T: PartialEq + Clone> Tracked<T> {
+    // Returns the old and new configurations once a new one has been seen
+    // min_observations times in a row
+    fn observe(&mut self, config: T, min_observations: usize) -> Option<(T, T)> {
+        let Some(confirmed) = &self.confirmed else {
+            self.confirmed = Some(config);
+            return None;
+        };
+        if *confirmed == config {
+            self.candidate = None;
+            return None;
+        }
+        let observations = match self.candidate.take() {
+            Some((candidate, observations)) if candidate == config => observations + 1,
+            _ => 1,
+        };
+        if observations < min_observations {
+            self.candidate = Some((config, observations));
+            return None;
+        }
+        let old = self.confirmed.replace(config.clone())?;
+        Some((old, config))
+    }
+}
+
+#[derive(Default)]
+struct CellState {
+    global_identity: Option<CellGlobalIdentity>,
+    system_info_value_tag: Option<u8>,
+    mib: Tracked<MibConfig>,
+    reference_signal: Tracked<ReferenceSignalConfig>,
+    // from the handovers to the cell, in AntennaInfoCommon's encoding
+    antenna_ports: Tracked<u8>,
+}
+
+// A cell's antenna and MIMO configuration is part of its hardware setup, and
+// a cell doesn't change it while it's up: the MIB's bandwidth and PHICH
+// configuration are fixed, the reference signal power and P-b in SIB2 only
+// change along with the cell's system information (which is announced by a
+// new systemInfoValueTag in SIB1), and every handover to a cell tells the UE
+// the same number of antenna ports. A cell that abruptly changes any of these
+// may have been replaced by a different transmitter using its identity.
+//
+// Cells are told apart by their EARFCN and PCI, so this only works on QMDL
+// captures. One observation could be a decoding glitch, so a new
+// configuration has to be seen min_observations times in a row before the
+// change is reported, and findings are informational, for corroborating other
+// warnings.
+pub struct AntennaConfigAnalyzer {
+    packet_num: usize,
+    min_observations: usize,
+    cells: HashMap<(u32, u16), CellState>,
+}
+
+impl AntennaConfigAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: AntennaConfigSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            min_observations: settings.min_observations.max(1),
+            cells: HashMap::new(),
+        }
+    }
+
+    fn report(
+        &self,
+        cell: (u32, u16),
+        source: &str,
+        old: &dyn fmt::Display,
+        new: &dyn fmt::Display,
+    ) -> Event {
+        let (earfcn, phy_cell_id) = cell;
+        let mut evidence = Vec::new();
+        if let Some(cgi) = self
+            .cells
+            .get(&cell)
+            .and_then(|state| state.global_identity.as_ref())
+        {
+            evidence.push(("cell".to_string(), cgi.to_string()));
+        }
+        evidence.extend([
+            ("earfcn".to_string(), earfcn.to_string()),
+            ("pci".to_string(), phy_cell_id.to_string()),
+            ("source".to_string(), source.to_string()),
+            ("old_config".to_string(), old.to_string()),
+            ("new_config".to_string(), new.to_string()),
+            (
+                "observations".to_string(),
+                self.min_observations.to_string(),
+            ),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        Event {
+            event_type: EventType::Informational,
+            message: format!(
+                "PCI {phy_cell_id} on EARFCN {earfcn} changed its {source} configuration from {old} to {new}"
+            ),
+            explanation: "A cell's antenna and MIMO configuration comes from its hardware setup and doesn't normally change while it's running, yet this cell started advertising a different one without announcing a change to its system information. That can mean a different transmitter, such as a fake base station, took over the cell's identity, though it can also happen when an operator reconfigures a cell. On its own this isn't a strong signal, but it can support other warnings about the same cell.".to_string(),
+            evidence,
+        }
+    }
+
+    fn handle_mib(&mut self, mib: &MasterInformationBlock, ctx: &PacketContext) -> Option<Event> {
+        let cell = (ctx.earfcn?, ctx.phy_cell_id?);
+        let min_observations = self.min_observations;
+        let state = self.cells.entry(cell).or_default();
+        let (old, new) = state
+            .mib
+            .observe(MibConfig::from_mib(mib), min_observations)?;
+        Some(self.report(cell, "MIB", &old, &new))
+    }
+
+    fn handle_sib1(&mut self, value_tag: u8, cgi: Option<CellGlobalIdentity>, ctx: &PacketContext) {
+        let (Some(earfcn), Some(phy_cell_id)) = (ctx.earfcn, ctx.phy_cell_id) else {
+            return;
+        };
+        let state = self.cells.entry((earfcn, phy_cell_id)).or_default();
+        state.global_identity = cgi;
+        // the cell announced new system information, so SIB2 may legitimately
+        // change: start over from whatever it says next
+        if state
+            .system_info_value_tag
+            .is_some_and(|previous| previous != value_tag)
+        {
+            state.reference_signal = Tracked::default();
+        }
+        state.system_info_value_tag = Some(value_tag);
+    }
+
+    fn handle_pdsch_config(
+        &mut self,
+        pdsch: &PDSCH_ConfigCommon,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        let cell = (ctx.earfcn?, ctx.phy_cell_id?);
+        let min_observations = self.min_observations;
+        let state = self.cells.entry(cell).or_default();
+        let (old, new) = state
+            .reference_signal
+            .observe(ReferenceSignalConfig::from_pdsch(pdsch), min_observations)?;
+        Some(self.report(cell, "SIB2 reference signal", &old, &new))
+    }
+
+    fn handle_handover(
+        &mut self,
+        mobility: &MobilityControlInfo,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        let antenna_info = mobility
+            .radio_resource_config_common
+            .antenna_info_common
+            .as_ref()?;
+        // a handover without a carrier frequency stays on the current one
+        let earfcn = match &mobility.carrier_freq {
+            Some(carrier) => u32::from(carrier.dl_carrier_freq.0),
+            None => ctx.earfcn?,
+        };
+        let cell = (earfcn, mobility.target_phys_cell_id.0);
+        let min_observations = self.min_observations;
+        let state = self.cells.entry(cell).or_default();
+        let (old, new) = state
+            .antenna_ports
+            .observe(antenna_info.antenna_ports_count.0, min_observations)?;
+        let ports = |count: u8| format!("{} antenna ports", 1u8 << count.min(2));
+        let (old, new) = (ports(old), ports(new));
+        Some(self.report(cell, "handover antenna", &old, &new))
+    }
+}
+
+impl Analyzer for AntennaConfigAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Antenna Configuration Change")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether a cell changes its antenna or MIMO configuration (MIB bandwidth and PHICH configuration, SIB2 reference signal power and P-b, or the antenna ports handovers to it give) without announcing new system information. Informational, and only after seeing the new configuration min_observations times.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            let cgi = CellGlobalIdentity::from_sib1(sib1);
+            self.handle_sib1(sib1.system_info_value_tag.0, cgi, ctx);
+            return None;
+        }
+        if let Some(sib2) = unpack_sib2(ie) {
+            return self
+                .handle_pdsch_config(&sib2.radio_resource_config_common.pdsch_config_common, ctx);
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::BcchBch(bcch_bch) => self.handle_mib(&bcch_bch.message, ctx),
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(
+                    reconfiguration,
+                )) = &msg_cont.message
+                else {
+                    return None;
+                };
+                let RRCConnectionReconfigurationCriticalExtensions::C1(
+                    RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(
+                        r8,
+                    ),
+                ) = &reconfiguration.critical_extensions
+                else {
+                    return None;
+                };
+                self.handle_handover(r8.mobility_control_info.as_ref()?, ctx)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::borrow::Cow;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    CellChangeOrderTargetRAT_Type, DL_DCCH_MessageType, DL_DCCH_MessageType_c1,
+    HandoverTargetRAT_Type, MobilityFromEUTRACommand, MobilityFromEUTRACommand_r8_IEsPurpose,
+    MobilityFromEUTRACommand_r9_IEsPurpose, MobilityFromEUTRACommandCriticalExtensions,
+    MobilityFromEUTRACommandCriticalExtensions_c1, RRCConnectionReleaseCriticalExtensions,
+    RRCConnectionReleaseCriticalExtensions_c1, RedirectedCarrierInfo,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, emm_cause_name, emm_cause_value, unpack_sib1};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AttachRejectDowngradeSettings {
+    // how many attach rejects without an attach accept in between have to
+    // come before the UE is moved off LTE
+    pub min_rejects: usize,
+    // how long ago a reject can have been to still count
+    pub window_secs: u64,
+}
+
+impl Default for AttachRejectDowngradeSettings {
+    fn default() -> Self {
+        Self {
+            min_rejects: 2,
+            window_secs: 600,
+        }
+    }
+}
+
+impl AnalyzerSettings for AttachRejectDowngradeSettings {
+    const KEY: &'static str = "attach_reject_downgrade";
+}
+
+// Causes that make the UE disable its LTE radio (for the PLMN, or
+// altogether) and carry on with 2G/3G, per 24.301 section 5.5.1.2.5
+fn disables_eutra(cause: u8) -> bool {
+    matches!(cause, 7 | 14)
+}
+
+fn handover_rat(rat_type: &HandoverTargetRAT_Type) -> Option<&'static str> {
+    match rat_type.0 {
+        HandoverTargetRAT_Type::GERAN => Some("2G"),
+        HandoverTargetRAT_Type::UTRA => Some("3G"),
+        _ => None,
+    }
+}
+
+fn cell_change_order_rat(rat_type: &CellChangeOrderTargetRAT_Type) -> &'static str {
+    match rat_type {
+        CellChangeOrderTargetRAT_Type::Geran(_) => "2G",
+    }
+}
+
+// The 2G/3G RAT a MobilityFromEUTRACommand sends the UE to, whether it's a
+// handover or a cell change order, and whether or not it's for CS fallback
+fn mobility_target(command: &MobilityFromEUTRACommand) -> Option<&'static str> {
+    let MobilityFromEUTRACommandCriticalExtensions::C1(c1) = &command.critical_extensions else {
+        return None;
+    };
+    match c1 {
+        MobilityFromEUTRACommandCriticalExtensions_c1::MobilityFromEUTRACommand_r8(r8) => {
+            match &r8.purpose {
+                MobilityFromEUTRACommand_r8_IEsPurpose::Handover(handover) => {
+                    handover_rat(&handover.target_rat_type)
+                }
+                MobilityFromEUTRACommand_r8_IEsPurpose::CellChangeOrder(order) => {
+                    Some(cell_change_order_rat(&order.target_rat_type))
+                }
+            }
+        }
+        MobilityFromEUTRACommandCriticalExtensions_c1::MobilityFromEUTRACommand_r9(r9) => {
+            match &r9.purpose {
+                MobilityFromEUTRACommand_r9_IEsPurpose::Handover(handover) => {
+                    handover_rat(&handover.target_rat_type)
+                }
+                MobilityFromEUTRACommand_r9_IEsPurpose::CellChangeOrder(order) => {
+                    Some(cell_change_order_rat(&order.target_rat_type))
+                }
+                MobilityFromEUTRACommand_r9_IEsPurpose::E_CSFB_r9(_) => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+struct AttachReject {
+    cause: Option<u8>,
+    timestamp: Option<DateTime<FixedOffset>>,
+    frame: usize,
+}
+
+fn format_cause(cause: Option<u8>) -> String {
+    match cause {
+        Some(cause) => format!("#{cause} ({})", emm_cause_name(cause)),
+        None => "unreadable cause".to_string(),
+    }
+}
+
+// The steering and service reject analyzers look at rejects, and the
+// connection redirect and CS fallback ones at the network moving the UE off
+// LTE, each on their own. Put together, a run of attach rejects that ends
+// with the UE on 2G or 3G is how a fake base station walks a UE down to a
+// network it can intercept: it won't let the UE attach on LTE, and then
+// either redirects it to 2G/3G or rejects it with a cause (#7 or #14) that
+// makes the UE turn its LTE radio off and carry on there.
+//
+// 2G and 3G signalling isn't decoded, so the final RAT is the one the
+// network sends the UE to, with an RRC Connection Release redirect or a
+// Mobility From EUTRA Command, rather than the one it ends up attaching on.
+// An attach accept, or rejects older than window_secs, start the count over.
+pub struct AttachRejectDowngradeAnalyzer {
+    packet_num: usize,
+    min_rejects: usize,
+    window: TimeDelta,
+    serving_cell: Option<CellGlobalIdentity>,
+    rejects: Vec<AttachReject>,
+}
+
+impl AttachRejectDowngradeAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: AttachRejectDowngradeSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            min_rejects: settings.min_rejects.max(1),
+            window: TimeDelta::try_seconds(i64::try_from(settings.window_secs).unwrap_or(i64::MAX))
+                .unwrap_or(TimeDelta::MAX),
+            serving_cell: None,
+            rejects: Vec::new(),
+        }
+    }
+
+    fn expire_rejects(&mut self, now: Option<DateTime<FixedOffset>>) {
+        let Some(now) = now else {
+            return;
+        };
+        let window = self.window;
+        self.rejects.retain(|reject| {
+            reject
+                .timestamp
+                .is_none_or(|timestamp| now - timestamp <= window)
+        });
+    }
+
+    fn handle_reject(&mut self, cause: Option<u8>, ctx: &PacketContext) -> Option<Event> {
+        self.expire_rejects(ctx.timestamp);
+        self.rejects.push(AttachReject {
+            cause,
+            timestamp: ctx.timestamp,
+            frame: self.packet_num,
+        });
+        let cause = cause.filter(|cause| disables_eutra(*cause))?;
+        self.handle_downgrade(
+            "2G/3G",
+            &format!("Attach Reject with cause {}", format_cause(Some(cause))),
+        )
+    }
+
+    fn handle_downgrade(&mut self, rat: &str, via: &str) -> Option<Event> {
+        if self.rejects.len() < self.min_rejects {
+            self.rejects.clear();
+            return None;
+        }
+        let rejects = std::mem::take(&mut self.rejects);
+        let causes: Vec<String> = rejects
+            .iter()
+            .map(|reject| format_cause(reject.cause))
+            .collect();
+        let severity = if rat == "3G" {
+            Severity::Medium
+        } else {
+            Severity::High
+        };
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("rejects".to_string(), rejects.len().to_string()),
+            ("reject_causes".to_string(), causes.join(", ")),
+            ("final_rat".to_string(), rat.to_string()),
+            ("via".to_string(), via.to_string()),
+            (
+                "first_reject_frame".to_string(),
+                rejects[0].frame.to_string(),
+            ),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        Some(Event {
+            event_type: EventType::QualitativeWarning { severity },
+            message: format!(
+                "UE was sent to {rat} after {} attach rejects ({}), via {via}",
+                rejects.len(),
+                causes.join(", ")
+            ),
+            explanation: "The network rejected the UE's attempts to attach on LTE several times and then moved it to an older generation of network. A network that can't serve the UE on LTE might do this, but it's also how a fake base station downgrades a UE to 2G or 3G, where its communications are much easier to intercept.".to_string(),
+            evidence,
+        })
+    }
+
+    fn handle_dl_dcch(&mut self, message: &DL_DCCH_MessageType_c1) -> Option<Event> {
+        match message {
+            DL_DCCH_MessageType_c1::MobilityFromEUTRACommand(command) => {
+                let rat = mobility_target(command)?;
+                self.handle_downgrade(rat, "Mobility From EUTRA Command")
+            }
+            DL_DCCH_MessageType_c1::RrcConnectionRelease(release) => {
+                let RRCConnectionReleaseCriticalExtensions::C1(
+                    RRCConnectionReleaseCriticalExtensions_c1::RrcConnectionRelease_r8(r8),
+                ) = &release.critical_extensions
+                else {
+                    return None;
+                };
+                let rat = match r8.redirected_carrier_info.as_ref()? {
+                    RedirectedCarrierInfo::Geran(_) => "2G",
+                    RedirectedCarrierInfo::Utra_FDD(_) | RedirectedCarrierInfo::Utra_TDD(_) => "3G",
+                    _ => return None,
+                };
+                self.handle_downgrade(rat, "RRC Connection Release redirect")
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Analyzer for AttachRejectDowngradeAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Attach Reject Downgrade")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether at least min_rejects LTE attach rejects within window_secs, without an attach accept in between, end with the network moving the UE to 2G/3G, by redirecting it or by rejecting it with a cause that makes it disable LTE (#7 or #14). The reject causes and the RAT the UE was sent to are reported.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::NAS(NASMessage::EMMMessage(emm)) => match emm {
+                EMMMessage::EMMAttachReject(reject) => {
+                    self.handle_reject(emm_cause_value(&reject.emm_cause), ctx)
+                }
+                EMMMessage::EMMAttachAccept(_) => {
+                    self.rejects.clear();
+                    None
+                }
+                _ => None,
+            },
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(c1) = &msg_cont.message else {
+                    return None;
+                };
+                self.expire_rejects(ctx.timestamp);
+                self.handle_dl_dcch(c1)
+            }
+            _ => None,
+        }
+    }
+}
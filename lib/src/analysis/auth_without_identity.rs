@@ -0,0 +1,143 @@
+use std::borrow::Cow;
+
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use telcom_parser::lte_rrc::{
+    DL_DCCH_MessageType, DL_DCCH_MessageType_c1, UL_CCCH_MessageType, UL_CCCH_MessageType_c1,
+};
+
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
+use super::information_element::{InformationElement, LteInformationElement};
+
+struct Session {
+    // the NAS messages relevant to identification seen so far, in order
+    messages: Vec<String>,
+    ue_identified: bool,
+    reported: bool,
+}
+
+// Within an RRC connection, the network should only authenticate a UE that
+// has identified itself, whether in its initial NAS message (Attach, TAU or
+// Service Request) or in an Identity Response. An Authentication Request
+// before any of those means the network is authenticating someone it doesn't
+// know, which is out of protocol order and has been seen in attack tooling.
+pub struct AuthWithoutIdentityAnalyzer {
+    packet_num: usize,
+    // None if we don't know where we are in the session, e.g. because the
+    // capture started while the UE was already connected
+    session: Option<Session>,
+}
+
+impl Default for AuthWithoutIdentityAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AuthWithoutIdentityAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            packet_num: 0,
+            session: None,
+        }
+    }
+
+    fn handle_nas(&mut self, emm: &EMMMessage) -> Option<Event> {
+        let (name, identifies_ue) = match emm {
+            EMMMessage::EMMAttachRequest(_) => ("Attach Request", true),
+            EMMMessage::EMMTrackingAreaUpdateRequest(_) => ("Tracking Area Update Request", true),
+            EMMMessage::EMMServiceRequest(_) => ("Service Request", true),
+            EMMMessage::EMMExtServiceRequest(_) => ("Extended Service Request", true),
+            EMMMessage::EMMDetachRequestMO(_) => ("Detach Request", true),
+            EMMMessage::EMMIdentityRequest(_) => ("Identity Request", false),
+            EMMMessage::EMMIdentityResponse(_) => ("Identity Response", true),
+            EMMMessage::EMMAuthenticationRequest(_) => ("Authentication Request", false),
+            _ => return None,
+        };
+        let packet_num = self.packet_num;
+        let session = self.session.as_mut()?;
+        session
+            .messages
+            .push(format!("{name} (frame {packet_num})"));
+        session.ue_identified |= identifies_ue;
+        if !matches!(emm, EMMMessage::EMMAuthenticationRequest(_))
+            || session.ue_identified
+            || session.reported
+        {
+            return None;
+        }
+
+        session.reported = true;
+        let sequence = session.messages.join(" -> ");
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!(
+                "Authentication requested before the UE identified itself: {sequence}"
+            ),
+            explanation: "The network started authenticating the UE before the UE sent any message identifying itself (an Attach, Tracking Area Update or Service Request, or an Identity Response). A real network has to know who it's authenticating, so this ordering suggests a fake network.".to_string(),
+            evidence: vec![
+                ("sequence".to_string(), sequence),
+                ("frame".to_string(), packet_num.to_string()),
+            ],
+        })
+    }
+}
+
+impl Analyzer for AuthWithoutIdentityAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Authentication Without Identity")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the network sends an Authentication Request within a connection before the UE has identified itself with an Attach, Tracking Area Update or Service Request, or an Identity Response.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::UlCcch(msg_cont) => {
+                if let UL_CCCH_MessageType::C1(
+                    UL_CCCH_MessageType_c1::RrcConnectionRequest(_)
+                    | UL_CCCH_MessageType_c1::RrcConnectionReestablishmentRequest(_),
+                ) = &msg_cont.message
+                {
+                    self.session = Some(Session {
+                        messages: Vec::new(),
+                        ue_identified: false,
+                        reported: false,
+                    });
+                }
+                None
+            }
+            LteInformationElement::DlDcch(msg_cont) => {
+                if let DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionRelease(_)) =
+                    &msg_cont.message
+                {
+                    self.session = None;
+                }
+                None
+            }
+            LteInformationElement::NAS(NASMessage::EMMMessage(emm)) => self.handle_nas(emm),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
+use super::information_element::InformationElement;
+use super::util::{
+    CellGlobalIdentity, advertised_bands, lte_band, lte_band_dl_frequencies, lte_dl_frequency,
+    unpack_sib1,
+};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+fn format_frequency(frequency: u32) -> String {
+    format!("{}.{}MHz", frequency / 10, frequency % 10)
+}
+
+fn format_bands(bands: &[u16]) -> String {
+    bands
+        .iter()
+        .map(u16::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// A cell's SIB1 says which band it's in, and, since bands overlap, which
+// other bands its carrier also falls into (multiBandInfoList). The UE found
+// the cell on a particular EARFCN before it could read any of that, so a real
+// cell's carrier is always in one of the bands it advertises. A fake cell
+// broadcasting a SIB1 cloned from a cell on another band isn't.
+//
+// Cells whose band can't be made out (it's signalled in an extension that's
+// missing) are skipped, as are bands that aren't known, and each cell is
+// reported once per EARFCN. The EARFCN is only known for QMDL captures.
+pub struct BandIndicatorMismatchAnalyzer {
+    packet_num: usize,
+    reported: HashSet<(Option<CellGlobalIdentity>, u32)>,
+}
+
+impl Default for BandIndicatorMismatchAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BandIndicatorMismatchAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            packet_num: 0,
+            reported: HashSet::new(),
+        }
+    }
+}
+
+impl Analyzer for BandIndicatorMismatchAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Band Indicator Mismatch")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the carrier a cell was received on falls outside every band its SIB1 advertises, counting the extended band indicators for bands above 64 and the additional bands in multiBandInfoList.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let sib1 = unpack_sib1(ie)?;
+        let earfcn = ctx.earfcn?;
+        let frequency = lte_dl_frequency(earfcn)?;
+        let bands = advertised_bands(sib1);
+        let primary = bands.primary?;
+
+        let mut advertised = vec![primary];
+        advertised.extend(&bands.additional);
+        let mut all_known = true;
+        for band in &advertised {
+            match lte_band_dl_frequencies(*band) {
+                Some(frequencies) if frequencies.contains(&frequency) => return None,
+                Some(_) => {}
+                None => all_known = false,
+            }
+        }
+        // the carrier could be in one of the bands we don't know
+        if !all_known {
+            return None;
+        }
+        let cell = CellGlobalIdentity::from_sib1(sib1);
+        if !self.reported.insert((cell.clone(), earfcn)) {
+            return None;
+        }
+
+        let physical = match lte_band(earfcn) {
+            Some(earfcn_band) => format!("band {earfcn_band}"),
+            None => "unknown".to_string(),
+        };
+        let mut evidence = Vec::new();
+        if let Some(cell) = &cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("earfcn".to_string(), earfcn.to_string()),
+            ("frequency".to_string(), format_frequency(frequency)),
+            ("earfcn_band".to_string(), physical.clone()),
+            ("freq_band_indicator".to_string(), primary.to_string()),
+        ]);
+        if !bands.additional.is_empty() {
+            evidence.push((
+                "multi_band_info".to_string(),
+                format_bands(&bands.additional),
+            ));
+        }
+        evidence.push(("frame".to_string(), self.packet_num.to_string()));
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::High,
+            },
+            message: format!(
+                "Cell advertises band {} but was received on EARFCN {earfcn} ({}, {physical})",
+                format_bands(&advertised),
+                format_frequency(frequency)
+            ),
+            explanation: "The band the cell says it's in doesn't cover the frequency it's actually transmitting on. A real cell's configuration can't get this wrong, while a fake base station that's copied another cell's broadcast can.".to_string(),
+            evidence,
+        })
+    }
+}
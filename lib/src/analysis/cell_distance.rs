@@ -0,0 +1,303 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+use std::time::SystemTime;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::InformationElement;
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+// The furthest an LTE cell can serve a UE from: the largest timing advance
+// (1282 steps of 16 Ts) covers about 100km
+const LTE_MAX_CELL_RADIUS_M: f64 = 100_000.0;
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CellDistanceSettings {
+    // CSV of known cell locations in OpenCelliD's format. Without it (and
+    // device_location), this analyzer does nothing.
+    pub cell_database_path: Option<String>,
+    // where the device is kept, as [latitude, longitude]. There's no
+    // location feed, so this only suits a device that stays put.
+    pub device_location: Option<[f64; 2]>,
+    // how many times its estimated range from the database a cell can be
+    // away before it's reported
+    pub range_factor: f64,
+    // the distance every cell is allowed no matter how small its estimated
+    // range, since those estimates are rough
+    pub min_plausible_distance_m: f64,
+}
+
+impl Default for CellDistanceSettings {
+    fn default() -> Self {
+        Self {
+            cell_database_path: None,
+            device_location: None,
+            range_factor: 3.0,
+            min_plausible_distance_m: 5000.0,
+        }
+    }
+}
+
+impl AnalyzerSettings for CellDistanceSettings {
+    const KEY: &'static str = "cell_distance";
+}
+
+// MCC, MNC and cell identity, compared numerically since OpenCelliD doesn't
+// keep the MNC's leading zeroes
+type CellKey = (u16, u16, u32);
+
+fn cell_key(cell: &CellGlobalIdentity) -> Option<CellKey> {
+    let (mcc, mnc) = cell.plmn.split_once('-')?;
+    Some((mcc.parse().ok()?, mnc.parse().ok()?, cell.cell_identity))
+}
+
+struct KnownCell {
+    latitude: f64,
+    longitude: f64,
+    // OpenCelliD's estimate of the cell's coverage radius
+    range_m: f64,
+}
+
+// Parses an OpenCelliD-style row, whose columns start with
+// radio,mcc,net,area,cell,unit,lon,lat,range
+fn parse_cell_row(fields: &[&str]) -> Option<(CellKey, KnownCell)> {
+    let key = (
+        fields.get(1)?.parse().ok()?,
+        fields.get(2)?.parse().ok()?,
+        fields.get(4)?.parse().ok()?,
+    );
+    let cell = KnownCell {
+        longitude: fields.get(6)?.parse().ok()?,
+        latitude: fields.get(7)?.parse().ok()?,
+        range_m: fields.get(8)?.parse().ok()?,
+    };
+    Some((key, cell))
+}
+
+// Cell databases that have already been read, by path, along with when each
+// file was last modified so that a replaced one is read again. Every Harness
+// builds its own CellDistanceAnalyzer, some requests build several, and a real
+// database can be hundreds of MB, so they're only read once and shared.
+type LoadedDatabases = HashMap<String, (Option<SystemTime>, Arc<CellDatabase>)>;
+static LOADED_DATABASES: LazyLock<Mutex<LoadedDatabases>> = LazyLock::new(Mutex::default);
+
+/// Where known LTE cells are, from a cell database CSV in OpenCelliD's format
+/// (the same one as the `cell_distance` analyzer's `cell_database_path`)
+#[derive(Default)]
+pub struct CellDatabase {
+    cells: HashMap<CellKey, KnownCell>,
+}
+
+impl CellDatabase {
+    /// Reads the LTE cells out of a cell database CSV. Rows that can't be
+    /// parsed are skipped.
+    pub fn load(path: &str) -> Result<Self, std::io::Error> {
+        Ok(CellDatabase {
+            cells: load_cell_database(path)?,
+        })
+    }
+
+    /// Like [CellDatabase::load], but only reads the file if it hasn't been
+    /// read before or has been modified since, and otherwise shares the copy
+    /// that was read then. Reading a large database is slow, so async callers
+    /// should make sure it's read off the runtime first.
+    pub fn load_shared(path: &str) -> Result<Arc<Self>, std::io::Error> {
+        let modified = std::fs::metadata(path)?.modified().ok();
+        // held while reading, so that a database asked for by several
+        // harnesses at once is still only read once
+        let mut loaded = LOADED_DATABASES
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let unchanged = loaded
+            .get(path)
+            .filter(|(loaded_modified, _)| modified.is_some() && *loaded_modified == modified);
+        if let Some((_, cell_database)) = unchanged {
+            return Ok(cell_database.clone());
+        }
+        let cell_database = Arc::new(CellDatabase::load(path)?);
+        info!("loaded {} LTE cells from {path}", cell_database.len());
+        loaded.insert(path.to_string(), (modified, cell_database.clone()));
+        Ok(cell_database)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    fn get(&self, cell: &CellGlobalIdentity) -> Option<&KnownCell> {
+        self.cells.get(&cell_key(cell)?)
+    }
+
+    /// Where the cell is, as [latitude, longitude], if it's in the database
+    pub fn location(&self, cell: &CellGlobalIdentity) -> Option<[f64; 2]> {
+        self.get(cell)
+            .map(|known_cell| [known_cell.latitude, known_cell.longitude])
+    }
+}
+
+fn load_cell_database(path: &str) -> Result<HashMap<CellKey, KnownCell>, std::io::Error> {
+    let mut cells = HashMap::new();
+    let mut skipped = 0;
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        // this also skips the header
+        if fields.first() != Some(&"LTE") {
+            continue;
+        }
+        match parse_cell_row(&fields) {
+            Some((key, cell)) => {
+                cells.insert(key, cell);
+            }
+            None => skipped += 1,
+        }
+    }
+    if skipped > 0 {
+        warn!("skipped {skipped} unparseable rows of cell database {path}");
+    }
+    Ok(cells)
+}
+
+// Great-circle distance between two points given in degrees
+fn distance_m(from: [f64; 2], to: [f64; 2]) -> f64 {
+    let [lat1, lon1] = from.map(f64::to_radians);
+    let [lat2, lon2] = to.map(f64::to_radians);
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().asin()
+}
+
+// A fake base station has to claim some real cell's identity, and it's
+// usually nowhere near where that cell actually is. Given a fixed location the
+// device is configured to be at and a database of where cells are, this flags
+// serving cells too far away to plausibly be reaching it. It's a plausibility
+// check for a stationary device, not location tracking: it doesn't follow
+// GPS, so a device that moves away from its configured location will see
+// false positives.
+//
+// Ideally the UE's timing advance would give the distance to whatever it's
+// actually talking to, but the modem doesn't log the MAC layer, and the RRC
+// decoder doesn't parse the measurements (E-CID) that carry it. So the bound
+// is the database's range estimate instead, never more than LTE's maximum
+// cell radius.
+pub struct CellDistanceAnalyzer {
+    packet_num: usize,
+    device_location: Option<[f64; 2]>,
+    cells: Arc<CellDatabase>,
+    range_factor: f64,
+    min_plausible_distance_m: f64,
+    reported: HashSet<CellGlobalIdentity>,
+}
+
+impl CellDistanceAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: CellDistanceSettings = analyzer_config.get_settings();
+        let cells = match (&settings.cell_database_path, settings.device_location) {
+            (Some(path), Some(_)) => match CellDatabase::load_shared(path) {
+                Ok(cells) => cells,
+                Err(err) => {
+                    warn!(
+                        "failed to load cell database {path}, not checking cell distances: {err}"
+                    );
+                    Arc::default()
+                }
+            },
+            _ => Arc::default(),
+        };
+        Self {
+            packet_num: 0,
+            device_location: settings.device_location,
+            cells,
+            range_factor: settings.range_factor,
+            min_plausible_distance_m: settings.min_plausible_distance_m,
+            reported: HashSet::new(),
+        }
+    }
+}
+
+impl Analyzer for CellDistanceAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Cell Distance From Fixed Location")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether a serving cell is further from the device's configured fixed location than it could plausibly reach, according to a database of known cell locations. It doesn't use GPS or timing advance, so it's only meaningful for a device that stays put. Only runs if cell_database_path and device_location are configured.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let device_location = self.device_location?;
+        let sib1 = unpack_sib1(ie)?;
+        let cell = CellGlobalIdentity::from_sib1(sib1)?;
+        let known_cell = self.cells.get(&cell)?;
+        let cell_location = [known_cell.latitude, known_cell.longitude];
+        let distance = distance_m(device_location, cell_location);
+        let plausible_distance = (known_cell.range_m * self.range_factor)
+            .max(self.min_plausible_distance_m)
+            .min(LTE_MAX_CELL_RADIUS_M);
+        if distance <= plausible_distance || !self.reported.insert(cell.clone()) {
+            return None;
+        }
+
+        let distance_km = distance / 1000.0;
+        let plausible_km = plausible_distance / 1000.0;
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!(
+                "Serving cell is {distance_km:.1}km from the configured device location according to the cell database, but plausibly reaches only {plausible_km:.1}km: {cell}"
+            ),
+            explanation: "The cell identity the serving cell broadcasts belongs to a cell that's known to be too far from the configured device location to be serving the device. A fake base station may be impersonating it, though the database may be out of date, or the device may have moved away from that location.".to_string(),
+            evidence: vec![
+                ("cell".to_string(), cell.to_string()),
+                ("distance_m".to_string(), format!("{distance:.0}")),
+                (
+                    "plausible_distance_m".to_string(),
+                    format!("{plausible_distance:.0}"),
+                ),
+                (
+                    "database_range_m".to_string(),
+                    format!("{:.0}", known_cell.range_m),
+                ),
+                (
+                    "cell_location".to_string(),
+                    format!("{:.5}, {:.5}", cell_location[0], cell_location[1]),
+                ),
+                (
+                    "device_location".to_string(),
+                    format!("{:.5}, {:.5}", device_location[0], device_location[1]),
+                ),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+}
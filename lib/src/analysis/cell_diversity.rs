@@ -0,0 +1,258 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_DCCH_MessageType, DL_DCCH_MessageType_c1, MobilityControlInfo,
+    RRCConnectionReconfigurationCriticalExtensions,
+    RRCConnectionReconfigurationCriticalExtensions_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CellDiversitySettings {
+    // how many different cells the UE may be served by within window_secs
+    pub max_distinct_cells: usize,
+    pub window_secs: u64,
+}
+
+impl Default for CellDiversitySettings {
+    fn default() -> Self {
+        Self {
+            max_distinct_cells: 6,
+            window_secs: 60,
+        }
+    }
+}
+
+impl AnalyzerSettings for CellDiversitySettings {
+    const KEY: &'static str = "cell_diversity";
+}
+
+// A serving cell, identified by its SIB1 if the UE read it, or otherwise by
+// the carrier and PCI it was handed over to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ServingCell {
+    Global(CellGlobalIdentity),
+    Physical { earfcn: u32, phy_cell_id: u16 },
+}
+
+impl fmt::Display for ServingCell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServingCell::Global(cell) => write!(f, "{cell}"),
+            ServingCell::Physical {
+                earfcn,
+                phy_cell_id,
+            } => write!(f, "PCI {phy_cell_id} on EARFCN {earfcn}"),
+        }
+    }
+}
+
+// Reselection churn counts how often the UE changes cells. This counts how
+// many different cells it goes through instead, idle or connected: a UE
+// bouncing between two cells isn't remarkable, but one that's served by a
+// new cell every few seconds is moving implausibly fast, or is being walked
+// through cells an attacker controls.
+//
+// Handovers are counted by their target, as the UE doesn't necessarily read
+// the SIB1 of the cell it's handed to. Targets are matched up with the cells
+// whose SIB1s were seen on the same carrier and PCI, so that a cell isn't
+// counted twice when it shows up both ways.
+pub struct CellDiversityAnalyzer {
+    packet_num: usize,
+    max_distinct_cells: usize,
+    window: TimeDelta,
+    serving_cell: Option<ServingCell>,
+    // (EARFCN, PCI) -> the cell whose SIB1 was seen there
+    known_cells: HashMap<(u32, u16), CellGlobalIdentity>,
+    // each cell the UE was served by within the window, along with when it
+    // last started serving the UE, oldest first
+    recent: VecDeque<(DateTime<FixedOffset>, ServingCell)>,
+    first_frame: Option<usize>,
+}
+
+impl CellDiversityAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: CellDiversitySettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            max_distinct_cells: settings.max_distinct_cells,
+            window: TimeDelta::try_seconds(i64::try_from(settings.window_secs).unwrap_or(i64::MAX))
+                .unwrap_or(TimeDelta::MAX),
+            serving_cell: None,
+            known_cells: HashMap::new(),
+            recent: VecDeque::new(),
+            first_frame: None,
+        }
+    }
+
+    fn handle_sib1(&mut self, cell: CellGlobalIdentity, ctx: &PacketContext) -> Option<Event> {
+        if let (Some(earfcn), Some(phy_cell_id)) = (ctx.earfcn, ctx.phy_cell_id) {
+            let physical = ServingCell::Physical {
+                earfcn,
+                phy_cell_id,
+            };
+            // the UE may have been handed over to this cell before it read
+            // its SIB1
+            for (_, recent) in self.recent.iter_mut() {
+                if *recent == physical {
+                    *recent = ServingCell::Global(cell.clone());
+                }
+            }
+            if self.serving_cell.as_ref() == Some(&physical) {
+                self.serving_cell = Some(ServingCell::Global(cell.clone()));
+            }
+            self.known_cells.insert((earfcn, phy_cell_id), cell.clone());
+        }
+        self.handle_cell(ServingCell::Global(cell), ctx.timestamp)
+    }
+
+    fn handle_handover(
+        &mut self,
+        mobility: &MobilityControlInfo,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        // a handover without a carrier frequency stays on the current one
+        let earfcn = match &mobility.carrier_freq {
+            Some(carrier) => u32::from(carrier.dl_carrier_freq.0),
+            None => ctx.earfcn?,
+        };
+        let phy_cell_id = mobility.target_phys_cell_id.0;
+        let cell = match self.known_cells.get(&(earfcn, phy_cell_id)) {
+            Some(cell) => ServingCell::Global(cell.clone()),
+            None => ServingCell::Physical {
+                earfcn,
+                phy_cell_id,
+            },
+        };
+        self.handle_cell(cell, ctx.timestamp)
+    }
+
+    fn handle_cell(
+        &mut self,
+        cell: ServingCell,
+        timestamp: Option<DateTime<FixedOffset>>,
+    ) -> Option<Event> {
+        if self.serving_cell.as_ref() == Some(&cell) {
+            return None;
+        }
+        self.serving_cell = Some(cell.clone());
+        let timestamp = timestamp?;
+        while let Some((oldest, _)) = self.recent.front() {
+            if timestamp - *oldest <= self.window {
+                break;
+            }
+            self.recent.pop_front();
+        }
+        // only the latest time a cell started serving the UE matters
+        self.recent.retain(|(_, recent)| *recent != cell);
+        self.recent.push_back((timestamp, cell));
+        if self.recent.len() == 1 {
+            self.first_frame = Some(self.packet_num);
+        }
+        if self.recent.len() <= self.max_distinct_cells {
+            return None;
+        }
+
+        let count = self.recent.len();
+        let span_secs = self
+            .recent
+            .front()
+            .map(|(oldest, _)| (timestamp - *oldest).num_seconds())
+            .unwrap_or_default();
+        let cells: Vec<String> = self.recent.iter().map(|(_, c)| c.to_string()).collect();
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("distinct_cells".to_string(), count.to_string()),
+            ("span_secs".to_string(), span_secs.to_string()),
+            (
+                "max_distinct_cells".to_string(),
+                self.max_distinct_cells.to_string(),
+            ),
+            ("cells".to_string(), cells.join("; ")),
+        ]);
+        if let Some(first_frame) = self.first_frame {
+            evidence.push(("first_frame".to_string(), first_frame.to_string()));
+        }
+        evidence.push(("frame".to_string(), self.packet_num.to_string()));
+        // start counting afresh, so the UE being moved around for a while is
+        // reported once per window rather than on every new cell
+        self.recent.clear();
+        self.first_frame = None;
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Low,
+            },
+            message: format!(
+                "UE was served by {count} different cells within {span_secs}s (max {})",
+                self.max_distinct_cells
+            ),
+            explanation: "The UE moved through more cells in a short time than it plausibly could by itself. Being walked through many cells like this can help an attacker locate the UE, or may come from a misbehaving fake base station setup. Travelling fast through an area with many small cells can trigger this too.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for CellDiversityAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("High Cell Diversity")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the UE is served by more than max_distinct_cells different cells within window_secs, counting both idle mode reselections and handovers.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            let cell = CellGlobalIdentity::from_sib1(sib1)?;
+            return self.handle_sib1(cell, ctx);
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        let LteInformationElement::DlDcch(msg_cont) = &**inner else {
+            return None;
+        };
+        let DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(
+            reconfiguration,
+        )) = &msg_cont.message
+        else {
+            return None;
+        };
+        let RRCConnectionReconfigurationCriticalExtensions::C1(
+            RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(r8),
+        ) = &reconfiguration.critical_extensions
+        else {
+            return None;
+        };
+        self.handle_handover(r8.mobility_control_info.as_ref()?, ctx)
+    }
+}
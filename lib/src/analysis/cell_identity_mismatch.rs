@@ -0,0 +1,182 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
+use super::information_element::InformationElement;
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+// Where the cell was first seen, and how
+struct Sighting {
+    tracking_area_code: u32,
+    earfcn: Option<u32>,
+    phy_cell_id: Option<u16>,
+    frame: usize,
+}
+
+// One piece of the cell's identity that two layers disagree on, with what
+// each of them said
+struct Conflict {
+    field: &'static str,
+    sources: [(&'static str, String); 2],
+}
+
+// A cell's identity is assembled from several layers: the UE finds the
+// carrier and the physical cell ID while synchronizing to the cell, before it
+// can decode the MIB, while the PLMN, tracking area and cell ID only come
+// with SIB1. A real cell is configured once and consistently, so the same
+// global cell ID stays in the same tracking area, on the same carrier and
+// PCI. A fake cell that's been pieced together from a cloned SIB1 often gets
+// one of them wrong.
+//
+// Whether the carrier is in the band SIB1 advertises is left to the band
+// indicator mismatch analyzer.
+pub struct CellIdentityMismatchAnalyzer {
+    packet_num: usize,
+    // (PLMN, cell ID) -> where that cell was first seen
+    cells: HashMap<(String, u32), Sighting>,
+    // conflicts we've already warned about, so a cell only gets reported
+    // once for each rather than on every SIB1 it broadcasts
+    reported: HashSet<(CellGlobalIdentity, &'static str)>,
+}
+
+impl Default for CellIdentityMismatchAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CellIdentityMismatchAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            packet_num: 0,
+            cells: HashMap::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    fn check_sighting(
+        &mut self,
+        cell: &CellGlobalIdentity,
+        ctx: &PacketContext,
+        conflicts: &mut Vec<Conflict>,
+    ) -> Option<usize> {
+        let key = (cell.plmn.clone(), cell.cell_identity);
+        let Some(first) = self.cells.get(&key) else {
+            self.cells.insert(
+                key,
+                Sighting {
+                    tracking_area_code: cell.tracking_area_code,
+                    earfcn: ctx.earfcn,
+                    phy_cell_id: ctx.phy_cell_id,
+                    frame: self.packet_num,
+                },
+            );
+            return None;
+        };
+        if first.tracking_area_code != cell.tracking_area_code {
+            conflicts.push(Conflict {
+                field: "tracking_area_code",
+                sources: [
+                    ("earlier_sib1", first.tracking_area_code.to_string()),
+                    ("sib1", cell.tracking_area_code.to_string()),
+                ],
+            });
+        }
+        if let (Some(first_earfcn), Some(earfcn)) = (first.earfcn, ctx.earfcn) {
+            if first_earfcn != earfcn {
+                conflicts.push(Conflict {
+                    field: "earfcn",
+                    sources: [
+                        ("earlier_physical_layer", first_earfcn.to_string()),
+                        ("physical_layer", earfcn.to_string()),
+                    ],
+                });
+            }
+        }
+        if let (Some(first_pci), Some(pci)) = (first.phy_cell_id, ctx.phy_cell_id) {
+            if first_pci != pci {
+                conflicts.push(Conflict {
+                    field: "phy_cell_id",
+                    sources: [
+                        ("earlier_physical_layer", first_pci.to_string()),
+                        ("physical_layer", pci.to_string()),
+                    ],
+                });
+            }
+        }
+        Some(first.frame)
+    }
+}
+
+impl Analyzer for CellIdentityMismatchAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Inconsistent Cell Identity")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the pieces of a cell's identity disagree between layers: the same global cell ID shows up with a different tracking area, EARFCN or PCI.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        2
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let sib1 = unpack_sib1(ie)?;
+        let cell = CellGlobalIdentity::from_sib1(sib1)?;
+        let mut conflicts = Vec::new();
+        let first_frame = self.check_sighting(&cell, ctx, &mut conflicts);
+        conflicts.retain(|conflict| self.reported.insert((cell.clone(), conflict.field)));
+        if conflicts.is_empty() {
+            return None;
+        }
+
+        let fields: Vec<&str> = conflicts.iter().map(|conflict| conflict.field).collect();
+        let summaries: Vec<String> = conflicts
+            .iter()
+            .map(|conflict| {
+                let [(first_source, first), (second_source, second)] = &conflict.sources;
+                format!(
+                    "{} is {first} per {first_source} but {second} per {second_source}",
+                    conflict.field
+                )
+            })
+            .collect();
+        let mut evidence = vec![
+            ("cell".to_string(), cell.to_string()),
+            ("conflicts".to_string(), fields.join(", ")),
+        ];
+        for conflict in &conflicts {
+            for (source, value) in &conflict.sources {
+                evidence.push((format!("{}_{source}", conflict.field), value.clone()));
+            }
+        }
+        if let Some(first_frame) = first_frame {
+            evidence.push(("first_frame".to_string(), first_frame.to_string()));
+        }
+        evidence.push(("frame".to_string(), self.packet_num.to_string()));
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!(
+                "Cell's identity is inconsistent between layers ({}): {cell}",
+                summaries.join("; ")
+            ),
+            explanation: "The same cell ID was seen with different settings, either in its SIB1 or at the physical layer. Real cells are configured consistently, while fake base stations that copy a legitimate cell's broadcast often get some of it wrong. Network re-planning can occasionally change a cell's tracking area or PCI, though.".to_string(),
+            evidence,
+        })
+    }
+}
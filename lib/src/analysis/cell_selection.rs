@@ -0,0 +1,172 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::SystemInformationBlockType1CellSelectionInfo;
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::InformationElement;
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CellSelectionSettings {
+    // how many dB below the most lenient neighbour a cell's q-RxLevMin has to
+    // be before it's reported
+    pub min_gap_db: u8,
+    // how many other cells of the same PLMN have to have been seen to compare
+    // against
+    pub min_neighbours: usize,
+}
+
+impl Default for CellSelectionSettings {
+    fn default() -> Self {
+        Self {
+            min_gap_db: 10,
+            min_neighbours: 2,
+        }
+    }
+}
+
+impl AnalyzerSettings for CellSelectionSettings {
+    const KEY: &'static str = "cell_selection";
+}
+
+// q-RxLevMin and q-RxLevMinOffset are both sent in 2dB steps, per 36.331
+#[derive(Debug, Clone, Copy)]
+struct SelectionThresholds {
+    q_rx_lev_min_dbm: i16,
+    q_rx_lev_min_offset_db: Option<i16>,
+}
+
+impl SelectionThresholds {
+    fn from_sib1(info: &SystemInformationBlockType1CellSelectionInfo) -> Self {
+        Self {
+            q_rx_lev_min_dbm: i16::from(info.q_rx_lev_min.0) * 2,
+            q_rx_lev_min_offset_db: info
+                .q_rx_lev_min_offset
+                .as_ref()
+                .map(|offset| i16::from(offset.0) * 2),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self.q_rx_lev_min_offset_db {
+            Some(offset) => format!(
+                "q-RxLevMin {}dBm, q-RxLevMinOffset {offset}dB",
+                self.q_rx_lev_min_dbm
+            ),
+            None => format!("q-RxLevMin {}dBm", self.q_rx_lev_min_dbm),
+        }
+    }
+}
+
+// A cell's q-RxLevMin is the weakest signal a UE will still camp on it at.
+// Setting it very low lets a cell hold on to UEs that should have long since
+// moved to a better one, which suits a fake cell that wants to keep them.
+// Rural cells legitimately use low values too, so a cell is only compared
+// against the other cells of its PLMN seen in the same recording, which in an
+// area like that will be set similarly.
+pub struct CellSelectionAnalyzer {
+    packet_num: usize,
+    min_gap_db: i16,
+    min_neighbours: usize,
+    // the thresholds each cell advertised in its latest SIB1
+    cells: HashMap<CellGlobalIdentity, SelectionThresholds>,
+    reported: HashSet<CellGlobalIdentity>,
+}
+
+impl CellSelectionAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: CellSelectionSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            min_gap_db: i16::from(settings.min_gap_db),
+            min_neighbours: settings.min_neighbours.max(1),
+            cells: HashMap::new(),
+            reported: HashSet::new(),
+        }
+    }
+}
+
+impl Analyzer for CellSelectionAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Lenient Cell Selection")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether a cell's SIB1 sets q-RxLevMin at least min_gap_db lower than any other cell of its PLMN seen in the recording, which lets it keep UEs that would otherwise leave for a better cell.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let sib1 = unpack_sib1(ie)?;
+        let cell = CellGlobalIdentity::from_sib1(sib1)?;
+        let thresholds = SelectionThresholds::from_sib1(&sib1.cell_selection_info);
+        self.cells.insert(cell.clone(), thresholds);
+        if self.reported.contains(&cell) {
+            return None;
+        }
+
+        let neighbours: Vec<(&CellGlobalIdentity, &SelectionThresholds)> = self
+            .cells
+            .iter()
+            .filter(|(other, _)| **other != cell && other.plmn == cell.plmn)
+            .collect();
+        if neighbours.len() < self.min_neighbours {
+            return None;
+        }
+        let (lenient_neighbour, neighbour_thresholds) = neighbours
+            .iter()
+            .min_by_key(|(_, thresholds)| thresholds.q_rx_lev_min_dbm)?;
+        let gap = neighbour_thresholds.q_rx_lev_min_dbm - thresholds.q_rx_lev_min_dbm;
+        if gap < self.min_gap_db {
+            return None;
+        }
+
+        let neighbour_values: Vec<String> = neighbours
+            .iter()
+            .map(|(other, thresholds)| format!("{other}: {}", thresholds.describe()))
+            .collect();
+        let event = Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!(
+                "Cell's q-RxLevMin is {gap}dB lower than that of any of the {} other cells seen on its PLMN: {cell}",
+                neighbours.len()
+            ),
+            explanation: "The cell advertises a minimum camping signal level far below the other cells of its network nearby, so UEs will stay on it at signal levels where they'd otherwise pick another cell. A fake base station can do this to keep UEs it has captured. Cells at the edge of coverage are sometimes configured this way too.".to_string(),
+            evidence: vec![
+                ("cell".to_string(), cell.to_string()),
+                ("cell_selection".to_string(), thresholds.describe()),
+                (
+                    "most_lenient_neighbour".to_string(),
+                    format!("{lenient_neighbour}: {}", neighbour_thresholds.describe()),
+                ),
+                ("gap_db".to_string(), gap.to_string()),
+                ("neighbours".to_string(), neighbour_values.join("; ")),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        };
+        self.reported.insert(cell);
+        Some(event)
+    }
+}
@@ -0,0 +1,183 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::InformationElement;
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CellSwapSettings {
+    // how many SIB1s a cell has to be seen broadcasting with the same global
+    // identity before a change counts
+    pub min_stable_observations: usize,
+    // a change after the PCI hasn't been seen for longer than this is put
+    // down to the UE having moved on to another cell reusing it
+    pub max_gap_secs: u64,
+}
+
+impl Default for CellSwapSettings {
+    fn default() -> Self {
+        Self {
+            min_stable_observations: 3,
+            max_gap_secs: 300,
+        }
+    }
+}
+
+impl AnalyzerSettings for CellSwapSettings {
+    const KEY: &'static str = "cell_swap";
+}
+
+// The global identity last seen on a PCI and EARFCN, and for how long
+struct Occupant {
+    cell: CellGlobalIdentity,
+    first_seen: DateTime<FixedOffset>,
+    last_seen: DateTime<FixedOffset>,
+    observations: usize,
+}
+
+// The conflicting PCI analyzer reports any PCI and EARFCN that's ever been
+// used by two cells. This looks at when that happens: a cell the UE has been
+// hearing steadily on a PCI and EARFCN that, from one SIB1 to the next,
+// starts broadcasting a different global identity. Two neighbouring cells
+// can't share a PCI on the same carrier, so rather than another cell coming
+// into range, the transmitter was most likely swapped for a different one,
+// such as a fake base station taking over the PCI of the cell the UE was
+// camped on.
+//
+// PCIs are reused across distant cells, so a change after the PCI hasn't
+// been heard for max_gap_secs isn't reported, nor is one from a cell that
+// hadn't been seen min_stable_observations times yet. Each change is reported
+// once.
+pub struct CellSwapAnalyzer {
+    packet_num: usize,
+    min_stable_observations: usize,
+    max_gap: TimeDelta,
+    // (EARFCN, PCI) -> the cell last seen on it
+    occupants: HashMap<(u32, u16), Occupant>,
+    reported: HashSet<((u32, u16), CellGlobalIdentity, CellGlobalIdentity)>,
+}
+
+impl CellSwapAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: CellSwapSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            min_stable_observations: settings.min_stable_observations,
+            max_gap: TimeDelta::try_seconds(
+                i64::try_from(settings.max_gap_secs).unwrap_or(i64::MAX),
+            )
+            .unwrap_or(TimeDelta::MAX),
+            occupants: HashMap::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    fn handle_sib1(
+        &mut self,
+        key: (u32, u16),
+        cell: CellGlobalIdentity,
+        timestamp: DateTime<FixedOffset>,
+    ) -> Option<Event> {
+        let new_occupant = Occupant {
+            cell: cell.clone(),
+            first_seen: timestamp,
+            last_seen: timestamp,
+            observations: 1,
+        };
+        let Some(occupant) = self.occupants.get_mut(&key) else {
+            self.occupants.insert(key, new_occupant);
+            return None;
+        };
+        // the global cell ID is the PLMN and cell identity, without the
+        // tracking area
+        if occupant.cell.plmn == cell.plmn && occupant.cell.cell_identity == cell.cell_identity {
+            occupant.cell = cell;
+            occupant.last_seen = timestamp;
+            occupant.observations += 1;
+            return None;
+        }
+        let previous = std::mem::replace(occupant, new_occupant);
+        let gap = timestamp - previous.last_seen;
+        if previous.observations < self.min_stable_observations
+            || gap > self.max_gap
+            || !self
+                .reported
+                .insert((key, previous.cell.clone(), cell.clone()))
+        {
+            return None;
+        }
+
+        let (earfcn, phy_cell_id) = key;
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!(
+                "The cell on PCI {phy_cell_id} and EARFCN {earfcn} changed its global identity from {} to {cell} after {}s",
+                previous.cell,
+                gap.num_seconds()
+            ),
+            explanation: "A cell the UE had been hearing steadily suddenly started broadcasting a different global cell identity, without the physical cell ID or frequency changing. Cells near each other can't share those, so this looks like the transmitter being replaced by another one, such as a fake base station taking over the cell's place. It can also happen when an operator reconfigures a cell.".to_string(),
+            evidence: vec![
+                ("cell".to_string(), cell.to_string()),
+                ("earfcn".to_string(), earfcn.to_string()),
+                ("pci".to_string(), phy_cell_id.to_string()),
+                ("previous_cell".to_string(), previous.cell.to_string()),
+                (
+                    "previous_first_seen".to_string(),
+                    previous.first_seen.to_rfc3339(),
+                ),
+                (
+                    "previous_last_seen".to_string(),
+                    previous.last_seen.to_rfc3339(),
+                ),
+                (
+                    "previous_observations".to_string(),
+                    previous.observations.to_string(),
+                ),
+                ("changed_at".to_string(), timestamp.to_rfc3339()),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+}
+
+impl Analyzer for CellSwapAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Cell Identity Change")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether a cell seen steadily on a PCI and EARFCN (min_stable_observations SIB1s) abruptly starts broadcasting a different global cell identity, within max_gap_secs of last being seen with the old one.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let sib1 = unpack_sib1(ie)?;
+        let key = (ctx.earfcn?, ctx.phy_cell_id?);
+        let cell = CellGlobalIdentity::from_sib1(sib1)?;
+        self.handle_sib1(key, cell, ctx.timestamp?)
+    }
+}
@@ -0,0 +1,270 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_CCCH_MessageType, DL_CCCH_MessageType_c1, DL_DCCH_MessageType, DL_DCCH_MessageType_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConnectionHoldSettings {
+    // how many connections to a cell have to have been set up and released
+    // before its hold times are judged
+    pub min_samples: usize,
+    // a cell whose connections last less than this, typically, is releasing
+    // them before they can be used
+    pub min_hold_ms: u64,
+    // and one whose connections last longer than this is keeping them open
+    pub max_hold_secs: u64,
+}
+
+impl Default for ConnectionHoldSettings {
+    fn default() -> Self {
+        Self {
+            min_samples: 5,
+            min_hold_ms: 1000,
+            max_hold_secs: 1800,
+        }
+    }
+}
+
+impl AnalyzerSettings for ConnectionHoldSettings {
+    const KEY: &'static str = "connection_hold";
+}
+
+// How many of each cell's latest connections are kept to judge it by
+const MAX_SAMPLES: usize = 32;
+
+fn median(samples: &VecDeque<i64>) -> Option<i64> {
+    let mut sorted: Vec<i64> = samples.iter().copied().collect();
+    sorted.sort_unstable();
+    sorted.get(sorted.len() / 2).copied()
+}
+
+fn format_ms(samples: &VecDeque<i64>) -> String {
+    samples
+        .iter()
+        .map(i64::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Anomaly {
+    Short,
+    Long,
+}
+
+struct Connection {
+    cell: Option<CellGlobalIdentity>,
+    started: DateTime<FixedOffset>,
+}
+
+#[derive(Default)]
+struct CellHolds {
+    // how long each of the latest connections lasted, oldest first, in ms
+    holds: VecDeque<i64>,
+    // how long the UE was idle before each of them was set up, in ms
+    reconnect_gaps: VecDeque<i64>,
+}
+
+// How long a cell keeps a UE's RRC connection open is up to its inactivity
+// timer, which a real network sets once for all its cells and which falls
+// somewhere between seconds and minutes. A cell that releases connections
+// almost as soon as they're set up makes the UE connect again and again,
+// each time sending the network something about itself, and one that holds
+// connections open for a long time keeps the UE where the network can
+// measure it and page it at will. Either can be a fake base station probing
+// the UE.
+//
+// Any single connection can be short or long for ordinary reasons, like a
+// lost signal or a long download, so a cell's typical (median) hold time is
+// only judged once min_samples of its connections have been set up and
+// released. Each cell's reported at most once for each kind of anomaly.
+// Connections are counted against the cell that was serving when they were
+// set up, and the one that's already open when the capture starts isn't
+// counted.
+pub struct ConnectionHoldAnalyzer {
+    packet_num: usize,
+    min_samples: usize,
+    min_hold_ms: i64,
+    max_hold_ms: i64,
+    serving_cell: Option<CellGlobalIdentity>,
+    connection: Option<Connection>,
+    last_release: Option<DateTime<FixedOffset>>,
+    cells: HashMap<Option<CellGlobalIdentity>, CellHolds>,
+    reported: HashSet<(Option<CellGlobalIdentity>, Anomaly)>,
+}
+
+impl ConnectionHoldAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: ConnectionHoldSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            min_samples: settings.min_samples.max(1),
+            min_hold_ms: i64::try_from(settings.min_hold_ms).unwrap_or(i64::MAX),
+            max_hold_ms: i64::try_from(settings.max_hold_secs.saturating_mul(1000))
+                .unwrap_or(i64::MAX),
+            serving_cell: None,
+            connection: None,
+            last_release: None,
+            cells: HashMap::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    fn handle_setup(&mut self, timestamp: DateTime<FixedOffset>) {
+        let cell = self.serving_cell.clone();
+        if let Some(released) = self.last_release.take() {
+            let gaps = &mut self.cells.entry(cell.clone()).or_default().reconnect_gaps;
+            if gaps.len() == MAX_SAMPLES {
+                gaps.pop_front();
+            }
+            gaps.push_back((timestamp - released).num_milliseconds());
+        }
+        self.connection = Some(Connection {
+            cell,
+            started: timestamp,
+        });
+    }
+
+    fn handle_release(&mut self, timestamp: DateTime<FixedOffset>) -> Option<Event> {
+        self.last_release = Some(timestamp);
+        let connection = self.connection.take()?;
+        let cell_holds = self.cells.entry(connection.cell.clone()).or_default();
+        if cell_holds.holds.len() == MAX_SAMPLES {
+            cell_holds.holds.pop_front();
+        }
+        cell_holds
+            .holds
+            .push_back((timestamp - connection.started).num_milliseconds());
+        if cell_holds.holds.len() < self.min_samples {
+            return None;
+        }
+
+        let median_hold = median(&cell_holds.holds)?;
+        let anomaly = if median_hold < self.min_hold_ms {
+            Anomaly::Short
+        } else if median_hold > self.max_hold_ms {
+            Anomaly::Long
+        } else {
+            return None;
+        };
+        if !self.reported.insert((connection.cell.clone(), anomaly)) {
+            return None;
+        }
+        let (message, explanation, bound) = match anomaly {
+            Anomaly::Short => (
+                format!(
+                    "Cell releases connections after a median of {median_hold}ms, under {}ms",
+                    self.min_hold_ms
+                ),
+                "The cell typically released the UE's connection almost as soon as it was set up, so the UE kept having to connect again. A real network's inactivity timer gives connections seconds to minutes, so this suggests a cell that's making the UE reconnect to learn something from it each time, as a fake base station might.",
+                ("min_hold_ms".to_string(), self.min_hold_ms.to_string()),
+            ),
+            Anomaly::Long => (
+                format!(
+                    "Cell holds connections open for a median of {}s, over {}s",
+                    median_hold / 1000,
+                    self.max_hold_ms / 1000
+                ),
+                "The cell typically kept the UE's connection open far longer than a real network's inactivity timer would. A connected UE can be measured and tracked, so this suggests a cell that's keeping the UE connected to watch it, as a fake base station might.",
+                (
+                    "max_hold_secs".to_string(),
+                    (self.max_hold_ms / 1000).to_string(),
+                ),
+            ),
+        };
+        let mut evidence = Vec::new();
+        if let Some(cell) = &connection.cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("median_hold_ms".to_string(), median_hold.to_string()),
+            ("hold_ms".to_string(), format_ms(&cell_holds.holds)),
+            (
+                "reconnect_gap_ms".to_string(),
+                format_ms(&cell_holds.reconnect_gaps),
+            ),
+            bound,
+            ("samples".to_string(), cell_holds.holds.len().to_string()),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Low,
+            },
+            message,
+            explanation: explanation.to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for ConnectionHoldAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Connection Hold Time")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether a cell typically (by the median of its latest connections, once there have been min_samples of them) releases RRC connections less than min_hold_ms after setting them up, or holds them open for more than max_hold_secs.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::DlCcch(msg_cont) => {
+                let DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionSetup(_)) =
+                    &msg_cont.message
+                else {
+                    return None;
+                };
+                self.handle_setup(ctx.timestamp?);
+                None
+            }
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionRelease(_)) =
+                    &msg_cont.message
+                else {
+                    return None;
+                };
+                self.handle_release(ctx.timestamp?)
+            }
+            _ => None,
+        }
+    }
+}
@@ -1,8 +1,9 @@
 use std::borrow::Cow;
 
-use super::analyzer::{Analyzer, Event, EventType, Severity};
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
 use super::information_element::{InformationElement, LteInformationElement};
 use super::util::unpack;
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
 use telcom_parser::lte_rrc::{
     DL_DCCH_MessageType, DL_DCCH_MessageType_c1, RRCConnectionReleaseCriticalExtensions,
     RRCConnectionReleaseCriticalExtensions_c1, RedirectedCarrierInfo,
@@ -26,7 +27,15 @@ impl Analyzer for ConnectionRedirect2GDowngradeAnalyzer {
         1
     }
 
-    fn analyze_information_element(&mut self, ie: &InformationElement) -> Option<Event> {
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
         unpack!(InformationElement::LTE(lte_ie) = ie);
         let message = match &**lte_ie {
             LteInformationElement::DlDcch(msg_cont) => &msg_cont.message,
@@ -38,15 +47,25 @@ impl Analyzer for ConnectionRedirect2GDowngradeAnalyzer {
         unpack!(RRCConnectionReleaseCriticalExtensions_c1::RrcConnectionRelease_r8(r8_ies) = c1);
         unpack!(Some(carrier_info) = &r8_ies.redirected_carrier_info);
         match carrier_info {
-            RedirectedCarrierInfo::Geran(_carrier_freqs_geran) => Some(Event {
+            RedirectedCarrierInfo::Geran(carrier_freqs_geran) => Some(Event {
                 event_type: EventType::QualitativeWarning {
                     severity: Severity::High,
                 },
                 message: "Detected 2G downgrade".to_owned(),
+                explanation: "The cell released the connection and redirected the UE to a 2G (GERAN) carrier. 2G has no mutual authentication and weak or no encryption, which makes it a favorite target for catchers.".to_owned(),
+                evidence: vec![(
+                    "redirected_carrier_info".to_owned(),
+                    format!("{carrier_freqs_geran:?}"),
+                )],
             }),
             _ => Some(Event {
                 event_type: EventType::Informational,
                 message: format!("RRCConnectionRelease CarrierInfo: {carrier_info:?}"),
+                explanation: "The cell released the connection and redirected the UE to another carrier.".to_owned(),
+                evidence: vec![(
+                    "redirected_carrier_info".to_owned(),
+                    format!("{carrier_info:?}"),
+                )],
             }),
         }
     }
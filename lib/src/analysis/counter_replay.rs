@@ -0,0 +1,251 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use telcom_parser::lte_rrc::{
+    CounterCheckCriticalExtensions, CounterCheckCriticalExtensions_c1,
+    CounterCheckResponseCriticalExtensions, DL_DCCH_MessageType, DL_DCCH_MessageType_c1,
+    RRCConnectionReconfigurationCriticalExtensions,
+    RRCConnectionReconfigurationCriticalExtensions_c1, UL_CCCH_MessageType, UL_CCCH_MessageType_c1,
+    UL_DCCH_MessageType, UL_DCCH_MessageType_c1,
+};
+
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
+use super::information_element::{InformationElement, LteInformationElement};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+// The network's Counter Check only carries the 25 most significant bits of
+// each COUNT, so everything is compared at that granularity
+const COUNT_LSB_BITS: u32 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Direction {
+    Uplink,
+    Downlink,
+}
+
+// Which side reported a COUNT. The two sides can legitimately disagree by
+// whatever is in flight, so each is only compared against itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Reporter {
+    Network,
+    Ue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct CountField {
+    drb_identity: u8,
+    direction: Direction,
+    reporter: Reporter,
+}
+
+impl CountField {
+    fn name(&self) -> String {
+        let direction = match self.direction {
+            Direction::Uplink => "uplink",
+            Direction::Downlink => "downlink",
+        };
+        let message = match self.reporter {
+            Reporter::Network => "Counter Check",
+            Reporter::Ue => "Counter Check Response",
+        };
+        format!("DRB {} {direction} COUNT in {message}", self.drb_identity)
+    }
+}
+
+// PDCP COUNTs only ever increase within an AS security context, since
+// reusing one would reuse the keystream. The network can ask the UE to
+// report them with RRC Counter Check, so a COUNT that goes backward between
+// two checks in the same context means messages were replayed, or someone
+// other than the real network is doing the asking. COUNTs repeating is fine,
+// a bearer may simply not have carried any traffic in between.
+//
+// NAS sequence numbers aren't covered, since the modem logs NAS messages
+// after stripping their security header.
+pub struct CounterReplayAnalyzer {
+    packet_num: usize,
+    // COUNT MSBs seen in the current security context, and the frame each
+    // was seen in. Cleared whenever a new context may have started.
+    counts: HashMap<CountField, (u32, usize)>,
+    reported: HashSet<CountField>,
+}
+
+impl Default for CounterReplayAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CounterReplayAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            packet_num: 0,
+            counts: HashMap::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.counts.clear();
+        self.reported.clear();
+    }
+
+    fn check_count(&mut self, field: CountField, count_msb: u32) -> Option<Event> {
+        let previous = self.counts.insert(field, (count_msb, self.packet_num));
+        let (previous_msb, previous_frame) = previous?;
+        if count_msb >= previous_msb || !self.reported.insert(field) {
+            return None;
+        }
+        let name = field.name();
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!(
+                "{name} went backward from {previous_msb} to {count_msb} (MSBs, frames {previous_frame} and {})",
+                self.packet_num
+            ),
+            explanation: "A PDCP COUNT reported for a radio bearer was lower than one reported earlier in the same security context. COUNTs never decrease within a context, so this suggests replayed messages or a network that isn't tracking the real bearer state.".to_string(),
+            evidence: vec![
+                ("field".to_string(), name),
+                ("previous_value".to_string(), previous_msb.to_string()),
+                ("previous_frame".to_string(), previous_frame.to_string()),
+                ("value".to_string(), count_msb.to_string()),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+
+    fn check_counts(
+        &mut self,
+        reporter: Reporter,
+        counts: impl IntoIterator<Item = (u8, u32, u32)>,
+    ) -> Option<Event> {
+        let mut first_event = None;
+        for (drb_identity, uplink, downlink) in counts {
+            for (direction, count_msb) in
+                [(Direction::Uplink, uplink), (Direction::Downlink, downlink)]
+            {
+                let field = CountField {
+                    drb_identity,
+                    direction,
+                    reporter,
+                };
+                // keep going so every COUNT gets recorded
+                let event = self.check_count(field, count_msb);
+                first_event = first_event.or(event);
+            }
+        }
+        first_event
+    }
+
+    fn handle_dl_dcch(&mut self, message: &DL_DCCH_MessageType_c1) -> Option<Event> {
+        match message {
+            // a new AS security context resets the COUNTs
+            DL_DCCH_MessageType_c1::SecurityModeCommand(_)
+            | DL_DCCH_MessageType_c1::RrcConnectionRelease(_) => {
+                self.reset();
+                None
+            }
+            // as does a handover, which derives new keys
+            DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(reconfiguration) => {
+                if let RRCConnectionReconfigurationCriticalExtensions::C1(
+                    RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(r8),
+                ) = &reconfiguration.critical_extensions
+                {
+                    if r8.mobility_control_info.is_some() {
+                        self.reset();
+                    }
+                }
+                None
+            }
+            DL_DCCH_MessageType_c1::CounterCheck(check) => {
+                let CounterCheckCriticalExtensions::C1(
+                    CounterCheckCriticalExtensions_c1::CounterCheck_r8(r8),
+                ) = &check.critical_extensions
+                else {
+                    return None;
+                };
+                let counts = r8.drb_count_msb_info_list.0.iter().map(|info| {
+                    (
+                        info.drb_identity.0,
+                        info.count_msb_uplink.0,
+                        info.count_msb_downlink.0,
+                    )
+                });
+                self.check_counts(Reporter::Network, counts)
+            }
+            _ => None,
+        }
+    }
+
+    fn handle_ul_dcch(&mut self, message: &UL_DCCH_MessageType_c1) -> Option<Event> {
+        let UL_DCCH_MessageType_c1::CounterCheckResponse(response) = message else {
+            return None;
+        };
+        let CounterCheckResponseCriticalExtensions::CounterCheckResponse_r8(r8) =
+            &response.critical_extensions
+        else {
+            return None;
+        };
+        let counts = r8.drb_count_info_list.0.iter().map(|info| {
+            (
+                info.drb_identity.0,
+                info.count_uplink.0 >> COUNT_LSB_BITS,
+                info.count_downlink.0 >> COUNT_LSB_BITS,
+            )
+        });
+        self.check_counts(Reporter::Ue, counts)
+    }
+}
+
+impl Analyzer for CounterReplayAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("COUNT Replay")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether a PDCP COUNT reported in an RRC Counter Check or Counter Check Response goes backward within a security context, which suggests replayed messages.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::UlCcch(msg_cont) => {
+                if let UL_CCCH_MessageType::C1(
+                    UL_CCCH_MessageType_c1::RrcConnectionRequest(_)
+                    | UL_CCCH_MessageType_c1::RrcConnectionReestablishmentRequest(_),
+                ) = &msg_cont.message
+                {
+                    self.reset();
+                }
+                None
+            }
+            LteInformationElement::DlDcch(msg_cont) => match &msg_cont.message {
+                DL_DCCH_MessageType::C1(c1) => self.handle_dl_dcch(c1),
+                _ => None,
+            },
+            LteInformationElement::UlDcch(msg_cont) => match &msg_cont.message {
+                UL_DCCH_MessageType::C1(c1) => self.handle_ul_dcch(c1),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::borrow::Cow;
+
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use telcom_parser::lte_rrc::{
+    CellChangeOrderTargetRAT_Type, DL_DCCH_MessageType, DL_DCCH_MessageType_c1,
+    HandoverTargetRAT_Type, MobilityFromEUTRACommand, MobilityFromEUTRACommand_r8_IEsPurpose,
+    MobilityFromEUTRACommand_r9_IEsPurpose, MobilityFromEUTRACommandCriticalExtensions,
+    MobilityFromEUTRACommandCriticalExtensions_c1, RRCConnectionReleaseCriticalExtensions,
+    RRCConnectionReleaseCriticalExtensions_c1, RedirectedCarrierInfo,
+};
+
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
+use super::information_element::{InformationElement, LteInformationElement};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TargetRat {
+    Geran,
+    Utra,
+}
+
+impl TargetRat {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            TargetRat::Geran => "2G",
+            TargetRat::Utra => "3G",
+        }
+    }
+
+    fn from_handover_type(rat_type: &HandoverTargetRAT_Type) -> Option<Self> {
+        match rat_type.0 {
+            HandoverTargetRAT_Type::GERAN => Some(TargetRat::Geran),
+            HandoverTargetRAT_Type::UTRA => Some(TargetRat::Utra),
+            _ => None,
+        }
+    }
+
+    fn from_cell_change_order_type(rat_type: &CellChangeOrderTargetRAT_Type) -> Self {
+        match rat_type {
+            CellChangeOrderTargetRAT_Type::Geran(_) => TargetRat::Geran,
+        }
+    }
+}
+
+// Returns the RAT a MobilityFromEUTRACommand sends the UE to, if it's a CS
+// fallback to 2G/3G
+pub(crate) fn csfb_target(command: &MobilityFromEUTRACommand) -> Option<TargetRat> {
+    let MobilityFromEUTRACommandCriticalExtensions::C1(c1) = &command.critical_extensions else {
+        return None;
+    };
+    match c1 {
+        MobilityFromEUTRACommandCriticalExtensions_c1::MobilityFromEUTRACommand_r8(r8) => {
+            if !r8.cs_fallback_indicator.0 {
+                return None;
+            }
+            match &r8.purpose {
+                MobilityFromEUTRACommand_r8_IEsPurpose::Handover(handover) => {
+                    TargetRat::from_handover_type(&handover.target_rat_type)
+                }
+                MobilityFromEUTRACommand_r8_IEsPurpose::CellChangeOrder(order) => Some(
+                    TargetRat::from_cell_change_order_type(&order.target_rat_type),
+                ),
+            }
+        }
+        MobilityFromEUTRACommandCriticalExtensions_c1::MobilityFromEUTRACommand_r9(r9) => {
+            if !r9.cs_fallback_indicator.0 {
+                return None;
+            }
+            match &r9.purpose {
+                MobilityFromEUTRACommand_r9_IEsPurpose::Handover(handover) => {
+                    TargetRat::from_handover_type(&handover.target_rat_type)
+                }
+                MobilityFromEUTRACommand_r9_IEsPurpose::CellChangeOrder(order) => Some(
+                    TargetRat::from_cell_change_order_type(&order.target_rat_type),
+                ),
+                // enhanced CSFB is for CDMA2000 1xRTT
+                MobilityFromEUTRACommand_r9_IEsPurpose::E_CSFB_r9(_) => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// CS fallback (CSFB) moves a UE from LTE to 2G/3G so it can make or receive a
+// voice call, which a catcher can abuse to push a UE onto a network with
+// weaker security. Since we only parse LTE, we can't see what happens once
+// the UE's on the CS network (like a GPRS suspension), so instead we
+// correlate the fallback with the UE's own Extended Service Request, which it
+// always sends when setting up or answering a CSFB call.
+pub struct CsFallbackAnalyzer {
+    packet_num: usize,
+    // the CSFB-related messages seen since the UE last (re)attached to LTE
+    sequence: Vec<String>,
+    call_setup_seen: bool,
+}
+
+impl Default for CsFallbackAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CsFallbackAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            packet_num: 0,
+            sequence: Vec::new(),
+            call_setup_seen: false,
+        }
+    }
+
+    fn record(&mut self, message: &str) {
+        self.sequence
+            .push(format!("{message} (frame {})", self.packet_num));
+    }
+
+    fn reset(&mut self) {
+        self.sequence.clear();
+        self.call_setup_seen = false;
+    }
+
+    fn handle_fallback(&mut self, rat: TargetRat, via: &str) -> Option<Event> {
+        self.record(&format!("{via} to {}", rat.name()));
+        let sequence = self.sequence.join(" -> ");
+        let mut evidence = vec![
+            ("target_rat".to_string(), rat.name().to_string()),
+            ("sequence".to_string(), sequence.clone()),
+        ];
+        let event = if self.call_setup_seen {
+            Event {
+                event_type: EventType::Informational,
+                message: format!(
+                    "CS fallback to {} for a voice call: {sequence}",
+                    rat.name()
+                ),
+                explanation: "The UE was moved off of LTE for a voice call it set up or answered. This is how calls normally work on networks without VoLTE.".to_string(),
+                evidence,
+            }
+        } else {
+            let severity = match rat {
+                TargetRat::Geran => Severity::Medium,
+                TargetRat::Utra => Severity::Low,
+            };
+            evidence.push(("call_setup_seen".to_string(), "false".to_string()));
+            Event {
+                event_type: EventType::QualitativeWarning { severity },
+                message: format!(
+                    "Network initiated CS fallback to {} without a voice call setup: {sequence}",
+                    rat.name()
+                ),
+                explanation: "The network moved the UE off of LTE using CS fallback, but the UE never sent the Extended Service Request it sends when setting up or answering a call. A catcher can use this to push a UE onto a network with weaker security.".to_string(),
+                evidence,
+            }
+        };
+        self.reset();
+        Some(event)
+    }
+}
+
+impl Analyzer for CsFallbackAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("CS Fallback Downgrade")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the network moves the UE to a 2G/3G cell using CS fallback without the UE having set up or answered a voice call.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::NAS(NASMessage::EMMMessage(emm)) => match emm {
+                EMMMessage::EMMCSServiceNotification(_) => {
+                    self.record("CS Service Notification");
+                    None
+                }
+                EMMMessage::EMMExtServiceRequest(_) => {
+                    self.record("Extended Service Request");
+                    self.call_setup_seen = true;
+                    None
+                }
+                // back on LTE, so whatever fallback was pending is over
+                EMMMessage::EMMAttachRequest(_) | EMMMessage::EMMTrackingAreaUpdateRequest(_) => {
+                    self.reset();
+                    None
+                }
+                _ => None,
+            },
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(c1) = &msg_cont.message else {
+                    return None;
+                };
+                match c1 {
+                    DL_DCCH_MessageType_c1::MobilityFromEUTRACommand(command) => {
+                        let rat = csfb_target(command)?;
+                        self.handle_fallback(rat, "MobilityFromEUTRACommand")
+                    }
+                    // a release with redirection is only CSFB if one's already
+                    // underway, otherwise it's a plain redirect (which the
+                    // connection redirect analyzer looks at)
+                    DL_DCCH_MessageType_c1::RrcConnectionRelease(release)
+                        if !self.sequence.is_empty() =>
+                    {
+                        let RRCConnectionReleaseCriticalExtensions::C1(
+                            RRCConnectionReleaseCriticalExtensions_c1::RrcConnectionRelease_r8(r8),
+                        ) = &release.critical_extensions
+                        else {
+                            return None;
+                        };
+                        let rat = match r8.redirected_carrier_info.as_ref()? {
+                            RedirectedCarrierInfo::Geran(_) => TargetRat::Geran,
+                            RedirectedCarrierInfo::Utra_FDD(_)
+                            | RedirectedCarrierInfo::Utra_TDD(_) => TargetRat::Utra,
+                            _ => return None,
+                        };
+                        self.handle_fallback(rat, "RRCConnectionRelease redirect")
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use telcom_parser::decode;
+use telcom_parser::lte_rrc::{
+    DL_DCCH_MessageType, DL_DCCH_MessageType_c1, RRCConnectionReconfiguration,
+    RRCConnectionReconfigurationCriticalExtensions,
+    RRCConnectionReconfigurationCriticalExtensions_c1, SystemInformationBlockType1,
+};
+
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+// The SIB1 a reconfiguration hands the UE directly, and whether it's for the
+// cell the reconfiguration hands the UE over to rather than the serving one
+fn dedicated_sib1(
+    reconfiguration: &RRCConnectionReconfiguration,
+) -> Option<(SystemInformationBlockType1, bool)> {
+    let RRCConnectionReconfigurationCriticalExtensions::C1(
+        RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(r8),
+    ) = &reconfiguration.critical_extensions
+    else {
+        return None;
+    };
+    let encoded = r8
+        .non_critical_extension
+        .as_ref()?
+        .non_critical_extension
+        .as_ref()?
+        .non_critical_extension
+        .as_ref()?
+        .non_critical_extension
+        .as_ref()?
+        .system_information_block_type1_dedicated_r11
+        .as_ref()?;
+    let sib1 = decode::<SystemInformationBlockType1>(&encoded.0).ok()?;
+    Some((sib1, r8.mobility_control_info.is_some()))
+}
+
+// Which parts of two identities of the same cell differ
+fn differences(
+    broadcast: &CellGlobalIdentity,
+    dedicated: &CellGlobalIdentity,
+) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if broadcast.plmn != dedicated.plmn {
+        fields.push("plmn");
+    }
+    if broadcast.tracking_area_code != dedicated.tracking_area_code {
+        fields.push("tracking_area_code");
+    }
+    if broadcast.cell_identity != dedicated.cell_identity {
+        fields.push("cell_identity");
+    }
+    fields
+}
+
+// In connected mode, the network can hand the UE the serving cell's SIB1 in
+// an RRC Connection Reconfiguration (systemInformationBlockType1Dedicated,
+// added in release 11) instead of leaving it to read the broadcast one. Both
+// come from the same cell's configuration, so they should identify the same
+// cell. A fake base station that's cloned a real cell's broadcast, or relays
+// another cell's dedicated signalling, can get one of them wrong.
+//
+// A dedicated SIB1 in a handover is the target cell's, so it's checked
+// against the next SIB1 the UE reads from broadcast rather than the one it
+// last read. Each pair of identities is only reported once.
+pub struct DedicatedSib1MismatchAnalyzer {
+    packet_num: usize,
+    // the identity the serving cell broadcasts, and the frame it was read in
+    broadcast_cell: Option<(CellGlobalIdentity, usize)>,
+    // the identity a handover's dedicated SIB1 gave the target cell, and the
+    // frame it was given in, until the target's broadcast SIB1 is read
+    handover_target: Option<(CellGlobalIdentity, usize)>,
+    reported: HashSet<(CellGlobalIdentity, CellGlobalIdentity)>,
+}
+
+impl Default for DedicatedSib1MismatchAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DedicatedSib1MismatchAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            packet_num: 0,
+            broadcast_cell: None,
+            handover_target: None,
+            reported: HashSet::new(),
+        }
+    }
+
+    fn check(
+        &mut self,
+        broadcast: (CellGlobalIdentity, usize),
+        dedicated: (CellGlobalIdentity, usize),
+    ) -> Option<Event> {
+        let (broadcast, broadcast_frame) = broadcast;
+        let (dedicated, dedicated_frame) = dedicated;
+        let fields = differences(&broadcast, &dedicated);
+        if fields.is_empty() || !self.reported.insert((broadcast.clone(), dedicated.clone())) {
+            return None;
+        }
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::High,
+            },
+            message: format!(
+                "The serving cell broadcasts its identity as {broadcast}, but its dedicated SIB1 says {dedicated}"
+            ),
+            explanation: "The network sent the UE a copy of the cell's system information while it was connected, and it doesn't identify the same cell as the system information the cell broadcasts. A real cell is configured once, so both should match. A fake base station that has copied a real cell's broadcast, or is relaying another cell's signalling, can give itself away like this.".to_string(),
+            evidence: vec![
+                ("broadcast_cell".to_string(), broadcast.to_string()),
+                ("dedicated_cell".to_string(), dedicated.to_string()),
+                ("mismatched".to_string(), fields.join(", ")),
+                ("broadcast_frame".to_string(), broadcast_frame.to_string()),
+                ("dedicated_frame".to_string(), dedicated_frame.to_string()),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+
+    fn handle_broadcast(&mut self, cell: CellGlobalIdentity) -> Option<Event> {
+        let broadcast = (cell, self.packet_num);
+        self.broadcast_cell = Some(broadcast.clone());
+        let dedicated = self.handover_target.take()?;
+        self.check(broadcast, dedicated)
+    }
+
+    fn handle_reconfiguration(
+        &mut self,
+        reconfiguration: &RRCConnectionReconfiguration,
+    ) -> Option<Event> {
+        let (sib1, handover) = dedicated_sib1(reconfiguration)?;
+        let dedicated = (CellGlobalIdentity::from_sib1(&sib1)?, self.packet_num);
+        if handover {
+            self.broadcast_cell = None;
+            self.handover_target = Some(dedicated);
+            return None;
+        }
+        let broadcast = self.broadcast_cell.clone()?;
+        self.check(broadcast, dedicated)
+    }
+}
+
+impl Analyzer for DedicatedSib1MismatchAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Dedicated SIB1 Mismatch")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the SIB1 the network hands the UE in an RRC Connection Reconfiguration while it's connected identifies a different cell (PLMN, tracking area code or cell ID) than the SIB1 the serving cell broadcasts. For a handover, the dedicated SIB1 is checked against the target cell's broadcast one.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            return self.handle_broadcast(CellGlobalIdentity::from_sib1(sib1)?);
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        let LteInformationElement::DlDcch(msg_cont) = &**inner else {
+            return None;
+        };
+        match &msg_cont.message {
+            DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(
+                reconfiguration,
+            )) => self.handle_reconfiguration(reconfiguration),
+            _ => None,
+        }
+    }
+}
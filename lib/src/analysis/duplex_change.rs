@@ -0,0 +1,188 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+use telcom_parser::lte_rrc::SystemInformationBlockType1;
+
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
+use super::information_element::InformationElement;
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+// How many SIB1s in a row have to agree on a new configuration before it's
+// reported, so a single misdecoded message can't trigger a warning
+const CONFIRMING_OBSERVATIONS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DuplexConfig {
+    Fdd,
+    Tdd {
+        subframe_assignment: u8,
+        special_subframe_patterns: u8,
+    },
+}
+
+impl DuplexConfig {
+    // Per 36.331, a SIB1 carries a TDD configuration if and only if the cell
+    // is TDD
+    fn from_sib1(sib1: &SystemInformationBlockType1) -> Self {
+        match &sib1.tdd_config {
+            Some(tdd_config) => DuplexConfig::Tdd {
+                subframe_assignment: tdd_config.subframe_assignment.0,
+                special_subframe_patterns: tdd_config.special_subframe_patterns.0,
+            },
+            None => DuplexConfig::Fdd,
+        }
+    }
+}
+
+impl fmt::Display for DuplexConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DuplexConfig::Fdd => write!(f, "FDD"),
+            DuplexConfig::Tdd {
+                subframe_assignment,
+                special_subframe_patterns,
+            } => write!(
+                f,
+                "TDD (subframe assignment sa{subframe_assignment}, special subframe pattern ssp{special_subframe_patterns})"
+            ),
+        }
+    }
+}
+
+struct Candidate {
+    config: DuplexConfig,
+    observations: usize,
+    first_frame: usize,
+}
+
+struct CellDuplex {
+    config: DuplexConfig,
+    // the last frame the established config was seen in
+    last_frame: usize,
+    candidate: Option<Candidate>,
+}
+
+// A cell's duplex mode and TDD uplink-downlink configuration are fixed by its
+// spectrum and by coordination with neighbouring cells, so a real cell has no
+// reason to change them while it's running. A fake cell imitating a real one
+// may not get them right, or may flip between configurations as it's
+// reconfigured. Cells are told apart by their identity and frequency, so a
+// change that comes with a reselection doesn't count.
+pub struct DuplexChangeAnalyzer {
+    packet_num: usize,
+    cells: HashMap<(CellGlobalIdentity, Option<u32>), CellDuplex>,
+}
+
+impl Default for DuplexChangeAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DuplexChangeAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            packet_num: 0,
+            cells: HashMap::new(),
+        }
+    }
+}
+
+impl Analyzer for DuplexChangeAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Duplex Config Change")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether an LTE cell switches between FDD and TDD, or changes its TDD uplink-downlink configuration, without the cell or its frequency changing. The new configuration has to be seen in several SIB1s in a row before it's reported.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let sib1 = unpack_sib1(ie)?;
+        let cell = CellGlobalIdentity::from_sib1(sib1)?;
+        let config = DuplexConfig::from_sib1(sib1);
+        let packet_num = self.packet_num;
+
+        let Some(cell_duplex) = self.cells.get_mut(&(cell.clone(), ctx.earfcn)) else {
+            self.cells.insert(
+                (cell, ctx.earfcn),
+                CellDuplex {
+                    config,
+                    last_frame: packet_num,
+                    candidate: None,
+                },
+            );
+            return None;
+        };
+        if config == cell_duplex.config {
+            cell_duplex.last_frame = packet_num;
+            cell_duplex.candidate = None;
+            return None;
+        }
+        if !matches!(&cell_duplex.candidate, Some(candidate) if candidate.config == config) {
+            cell_duplex.candidate = Some(Candidate {
+                config,
+                observations: 0,
+                first_frame: packet_num,
+            });
+        }
+        let candidate = cell_duplex.candidate.as_mut()?;
+        candidate.observations += 1;
+        if candidate.observations < CONFIRMING_OBSERVATIONS {
+            return None;
+        }
+
+        let previous = cell_duplex.config;
+        let previous_frame = cell_duplex.last_frame;
+        let first_frame = candidate.first_frame;
+        let observations = candidate.observations;
+        // the new configuration is now the one later changes are compared to
+        cell_duplex.config = config;
+        cell_duplex.last_frame = packet_num;
+        cell_duplex.candidate = None;
+
+        let mut evidence = vec![
+            ("cell".to_string(), cell.to_string()),
+            ("previous_config".to_string(), previous.to_string()),
+            (
+                "previous_config_frame".to_string(),
+                previous_frame.to_string(),
+            ),
+            ("new_config".to_string(), config.to_string()),
+            (
+                "new_config_first_frame".to_string(),
+                first_frame.to_string(),
+            ),
+            ("observations".to_string(), observations.to_string()),
+            ("frame".to_string(), packet_num.to_string()),
+        ];
+        if let Some(earfcn) = ctx.earfcn {
+            evidence.insert(1, ("earfcn".to_string(), earfcn.to_string()));
+        }
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!("Cell changed its duplex configuration from {previous} to {config}: {cell}"),
+            explanation: "The same cell, on the same frequency, started advertising a different duplex mode or TDD uplink-downlink configuration in its SIB1. Real cells keep these fixed, so this suggests a fake cell that's imitating a real one or being reconfigured.".to_string(),
+            evidence,
+        })
+    }
+}
@@ -0,0 +1,302 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use log::warn;
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+
+// Emergency service category bits, per 24.008 10.5.4.33
+const SERVICE_CATEGORIES: [&str; 5] = [
+    "police",
+    "ambulance",
+    "fire brigade",
+    "marine guard",
+    "mountain rescue",
+];
+
+// Per 22.101, UEs always treat these as emergency numbers no matter what the
+// network or SIM says, so a network leaving them out of its list changes
+// nothing
+const UNIVERSAL_NUMBERS: [&str; 2] = ["112", "911"];
+
+fn default_expected_numbers() -> HashMap<String, Vec<String>> {
+    let numbers: [(&str, &[&str]); 12] = [
+        // UK
+        ("234", &["999"]),
+        ("235", &["999"]),
+        // Germany
+        ("262", &["110"]),
+        // France
+        ("208", &["15", "17", "18"]),
+        // Italy
+        ("222", &["113", "115", "118"]),
+        // Australia
+        ("505", &["000"]),
+        // Japan
+        ("440", &["110", "118", "119"]),
+        ("441", &["110", "118", "119"]),
+        // South Korea
+        ("450", &["119"]),
+        // China
+        ("460", &["110", "119", "120"]),
+        // India
+        ("404", &["100", "101", "102"]),
+        ("405", &["100", "101", "102"]),
+    ];
+    numbers
+        .into_iter()
+        .map(|(mcc, numbers)| {
+            let numbers = numbers.iter().map(|number| number.to_string()).collect();
+            (mcc.to_string(), numbers)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmergencyNumbersSettings {
+    // local emergency numbers each country (by MCC) is expected to include in
+    // its lists. Setting this replaces the built-in mapping entirely.
+    pub expected_numbers: HashMap<String, Vec<String>>,
+    // merged over expected_numbers, for adding or correcting a few countries
+    // without losing the built-in ones
+    pub additional_expected_numbers: HashMap<String, Vec<String>>,
+    // emergency numbers are short, so anything longer than this looks like a
+    // subscriber number that calls are being diverted to
+    pub max_number_digits: usize,
+    pub severity: Severity,
+}
+
+impl Default for EmergencyNumbersSettings {
+    fn default() -> Self {
+        Self {
+            expected_numbers: default_expected_numbers(),
+            additional_expected_numbers: HashMap::new(),
+            max_number_digits: 6,
+            severity: Severity::High,
+        }
+    }
+}
+
+impl AnalyzerSettings for EmergencyNumbersSettings {
+    const KEY: &'static str = "emergency_numbers";
+}
+
+struct EmergencyNumber {
+    digits: String,
+    categories: u8,
+}
+
+impl EmergencyNumber {
+    fn describe(&self) -> String {
+        let categories: Vec<&str> = SERVICE_CATEGORIES
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| self.categories & (1 << bit) != 0)
+            .map(|(_, name)| *name)
+            .collect();
+        if categories.is_empty() {
+            self.digits.clone()
+        } else {
+            format!("{} ({})", self.digits, categories.join(", "))
+        }
+    }
+}
+
+// Decodes the value of an Emergency Number List IE (24.008 10.5.3.13): a
+// sequence of length-prefixed entries, each a service category octet followed
+// by the number in BCD. Returns None if the list is malformed.
+fn decode_emergency_numbers(data: &[u8]) -> Option<Vec<EmergencyNumber>> {
+    let mut numbers = Vec::new();
+    let mut rest = data;
+    while let Some((&length, tail)) = rest.split_first() {
+        let length = length as usize;
+        if length == 0 || length > tail.len() {
+            return None;
+        }
+        let (entry, tail) = tail.split_at(length);
+        let mut digits = String::new();
+        for octet in &entry[1..] {
+            for digit in [octet & 0x0f, octet >> 4] {
+                match digit {
+                    0..=9 => digits.push(char::from(b'0' + digit)),
+                    // filler in the last octet of an odd-length number
+                    0x0f => {}
+                    _ => return None,
+                }
+            }
+        }
+        numbers.push(EmergencyNumber {
+            digits,
+            categories: entry[0] & 0x1f,
+        });
+        rest = tail;
+    }
+    Some(numbers)
+}
+
+// Despite often being thought of as broadcast, LTE cells don't advertise an
+// emergency number list in any SIB. The network hands one to the UE in its
+// Attach Accept or Tracking Area Update Accept instead, and the UE treats the
+// numbers on it as emergency numbers for as long as it stays in the country.
+// A list that leaves out the country's local emergency numbers, or that turns
+// an ordinary phone number into an emergency one, can keep someone from
+// reaching help or divert their emergency calls.
+//
+// Networks don't have to send a list at all, so only lists that are sent are
+// checked, and only against the MCC of the cell they were sent on.
+pub struct EmergencyNumbersAnalyzer {
+    packet_num: usize,
+    expected_numbers: HashMap<String, Vec<String>>,
+    max_number_digits: usize,
+    severity: Severity,
+    serving_cell: Option<CellGlobalIdentity>,
+    reported: HashSet<(Option<CellGlobalIdentity>, Vec<String>)>,
+}
+
+impl EmergencyNumbersAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: EmergencyNumbersSettings = analyzer_config.get_settings();
+        let mut expected_numbers = HashMap::new();
+        for (mcc, numbers) in settings
+            .expected_numbers
+            .into_iter()
+            .chain(settings.additional_expected_numbers)
+        {
+            let mcc = mcc.trim().to_string();
+            if mcc.len() == 3 && mcc.chars().all(|c| c.is_ascii_digit()) {
+                expected_numbers.insert(mcc, numbers);
+            } else {
+                warn!("ignoring invalid MCC in analyzer config: {mcc:?}");
+            }
+        }
+        Self {
+            packet_num: 0,
+            expected_numbers,
+            max_number_digits: settings.max_number_digits,
+            severity: settings.severity,
+            serving_cell: None,
+            reported: HashSet::new(),
+        }
+    }
+
+    fn check_list(&mut self, procedure: &str, data: &[u8]) -> Option<Event> {
+        let Some(numbers) = decode_emergency_numbers(data) else {
+            warn!("couldn't decode emergency number list in {procedure}");
+            return None;
+        };
+        let mcc = self
+            .serving_cell
+            .as_ref()
+            .and_then(|cell| cell.plmn.split('-').next());
+        let missing: Vec<&str> = mcc
+            .and_then(|mcc| self.expected_numbers.get(mcc))
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .filter(|expected| !UNIVERSAL_NUMBERS.contains(expected))
+            .filter(|expected| !numbers.iter().any(|number| number.digits == *expected))
+            .collect();
+        let too_long: Vec<&str> = numbers
+            .iter()
+            .filter(|number| number.digits.len() > self.max_number_digits)
+            .map(|number| number.digits.as_str())
+            .collect();
+        if missing.is_empty() && too_long.is_empty() {
+            return None;
+        }
+        let digits: Vec<String> = numbers.iter().map(|number| number.digits.clone()).collect();
+        if !self.reported.insert((self.serving_cell.clone(), digits)) {
+            return None;
+        }
+
+        let mut problems = Vec::new();
+        if !missing.is_empty() {
+            problems.push(format!(
+                "leaves out local emergency number(s) {}",
+                missing.join(", ")
+            ));
+        }
+        if !too_long.is_empty() {
+            problems.push(format!(
+                "lists unusually long number(s) {}",
+                too_long.join(", ")
+            ));
+        }
+        let advertised: Vec<String> = numbers.iter().map(EmergencyNumber::describe).collect();
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("procedure".to_string(), procedure.to_string()),
+            ("advertised_numbers".to_string(), advertised.join("; ")),
+            ("missing_numbers".to_string(), missing.join(", ")),
+            ("long_numbers".to_string(), too_long.join(", ")),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: self.severity,
+            },
+            message: format!(
+                "{procedure}'s emergency number list {}",
+                problems.join(" and ")
+            ),
+            explanation: "The network gave the UE an emergency number list that doesn't match what's expected for the country. The UE only treats numbers on that list (along with 112, 911 and those on the SIM) as emergency numbers, so a tampered list can make emergency calls fail or send them somewhere else. Operators occasionally change their lists, so check the expected numbers are current.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for EmergencyNumbersAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Abnormal Emergency Number List")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the emergency number list in an Attach Accept or Tracking Area Update Accept leaves out the local emergency numbers expected for the serving cell's country, or includes numbers too long to be emergency numbers.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        let LteInformationElement::NAS(NASMessage::EMMMessage(emm)) = &**inner else {
+            return None;
+        };
+        match emm {
+            EMMMessage::EMMAttachAccept(accept) => {
+                let list = &accept.emerg_num_list.as_ref()?.inner;
+                self.check_list("Attach Accept", list)
+            }
+            EMMMessage::EMMTrackingAreaUpdateAccept(accept) => {
+                let list = &accept.emerg_num_list.as_ref()?.inner;
+                self.check_list("Tracking Area Update Accept", list)
+            }
+            _ => None,
+        }
+    }
+}
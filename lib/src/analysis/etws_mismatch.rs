@@ -0,0 +1,281 @@
+use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{PCCH_MessageType, PCCH_MessageType_c1};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, bits_to_u64, unpack_sib1, unpack_sib10, unpack_sib11};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EtwsMismatchSettings {
+    // how long a page and the warning broadcast it announces may be apart,
+    // either way round, before they're considered unmatched
+    pub max_delay_secs: u64,
+    pub severity: Severity,
+}
+
+impl Default for EtwsMismatchSettings {
+    fn default() -> Self {
+        Self {
+            max_delay_secs: 30,
+            severity: Severity::Medium,
+        }
+    }
+}
+
+impl AnalyzerSettings for EtwsMismatchSettings {
+    const KEY: &'static str = "etws_mismatch";
+}
+
+// An ETWS warning, identified by the message identifier and serial number
+// its SIB10 or SIB11 carries. A warning is broadcast over and over for as
+// long as it's active, and a new serial number makes it a new one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Warning {
+    sib: &'static str,
+    message_identifier: u64,
+    serial_number: u64,
+}
+
+enum Unmatched {
+    Paging,
+    Broadcast(Warning),
+}
+
+fn is_etws_paging(ie: &InformationElement) -> bool {
+    let InformationElement::LTE(inner) = ie else {
+        return false;
+    };
+    let LteInformationElement::PCCH(pcch_msg) = &**inner else {
+        return false;
+    };
+    match &pcch_msg.message {
+        PCCH_MessageType::C1(PCCH_MessageType_c1::Paging(paging)) => {
+            paging.etws_indication.is_some()
+        }
+        _ => false,
+    }
+}
+
+// A page or broadcast still waiting for its counterpart
+struct Pending {
+    unmatched: Unmatched,
+    timestamp: DateTime<FixedOffset>,
+    frame: usize,
+    cell: Option<CellGlobalIdentity>,
+}
+
+// Networks announce an Earthquake and Tsunami Warning System (ETWS) warning
+// by paging with etws-Indication set, which tells every UE to go and read
+// SIB10 (the primary notification) or SIB11 (the secondary one). The two
+// always go together, so a page with no warning broadcast after it is a
+// fake cell making UEs reacquire system information on demand, and a warning
+// broadcast no page ever announced is likely a spoofed alert from a cell that
+// never bothered to page (or a UE that camped on the cell after the paging
+// stopped, which is why the severity can be tuned down).
+//
+// Pages and broadcasts within max_delay_secs of each other, in either order,
+// match. Something left unmatched is reported once that much time has passed,
+// as seen from the timestamp of a later message, so one right at the end of a
+// recording isn't reported. Messages without timestamps are skipped.
+pub struct EtwsMismatchAnalyzer {
+    packet_num: usize,
+    max_delay: TimeDelta,
+    severity: Severity,
+    serving_cell: Option<CellGlobalIdentity>,
+    last_paging: Option<(DateTime<FixedOffset>, usize)>,
+    last_broadcast: Option<(DateTime<FixedOffset>, usize)>,
+    // the warnings broadcasts have been seen for, so each is only checked
+    // the first time
+    seen_warnings: HashSet<Warning>,
+    // oldest first
+    pending: VecDeque<Pending>,
+}
+
+impl EtwsMismatchAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: EtwsMismatchSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            max_delay: TimeDelta::seconds(settings.max_delay_secs as i64),
+            severity: settings.severity,
+            serving_cell: None,
+            last_paging: None,
+            last_broadcast: None,
+            seen_warnings: HashSet::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn is_recent(
+        &self,
+        seen: Option<(DateTime<FixedOffset>, usize)>,
+        now: DateTime<FixedOffset>,
+    ) -> bool {
+        seen.is_some_and(|(timestamp, _)| now - timestamp <= self.max_delay)
+    }
+
+    fn push_pending(&mut self, unmatched: Unmatched, now: DateTime<FixedOffset>) {
+        self.pending.push_back(Pending {
+            unmatched,
+            timestamp: now,
+            frame: self.packet_num,
+            cell: self.serving_cell.clone(),
+        });
+    }
+
+    fn handle_etws_paging(&mut self, now: DateTime<FixedOffset>) {
+        // whatever was broadcast recently was announced after all
+        self.pending
+            .retain(|pending| !matches!(pending.unmatched, Unmatched::Broadcast(_)));
+        let already_pending = self
+            .pending
+            .iter()
+            .any(|pending| matches!(pending.unmatched, Unmatched::Paging));
+        if !already_pending && !self.is_recent(self.last_broadcast, now) {
+            self.push_pending(Unmatched::Paging, now);
+        }
+        self.last_paging = Some((now, self.packet_num));
+    }
+
+    fn handle_broadcast(&mut self, warning: Warning, now: DateTime<FixedOffset>) {
+        self.pending
+            .retain(|pending| !matches!(pending.unmatched, Unmatched::Paging));
+        if self.seen_warnings.insert(warning.clone()) && !self.is_recent(self.last_paging, now) {
+            self.push_pending(Unmatched::Broadcast(warning), now);
+        }
+        self.last_broadcast = Some((now, self.packet_num));
+    }
+
+    // Reports the oldest page or broadcast that has gone unmatched for too
+    // long, if any
+    fn check_expired(&mut self, now: DateTime<FixedOffset>) -> Option<Event> {
+        if now - self.pending.front()?.timestamp <= self.max_delay {
+            return None;
+        }
+        let pending = self.pending.pop_front()?;
+        let describe_last = |last: Option<(DateTime<FixedOffset>, usize)>| match last {
+            Some((_, frame)) => format!("frame {frame}"),
+            None => "none".to_string(),
+        };
+        let mut evidence = Vec::new();
+        if let Some(cell) = &pending.cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        let (message, explanation) = match &pending.unmatched {
+            Unmatched::Paging => {
+                evidence.extend([
+                    ("paging_frame".to_string(), pending.frame.to_string()),
+                    (
+                        "last_broadcast".to_string(),
+                        describe_last(self.last_broadcast),
+                    ),
+                ]);
+                (
+                    format!(
+                        "Cell paged for an ETWS emergency warning but broadcast none within {} seconds",
+                        self.max_delay.num_seconds()
+                    ),
+                    "The cell's paging said an earthquake or tsunami warning was being broadcast, which makes every UE stop and read the cell's system information, but no warning followed. Real networks only page for warnings they're broadcasting, while a fake base station can page this way to make UEs re-read whatever system information it broadcasts, whenever it likes.",
+                )
+            }
+            Unmatched::Broadcast(warning) => {
+                evidence.extend([
+                    ("sib".to_string(), warning.sib.to_string()),
+                    (
+                        "message_identifier".to_string(),
+                        warning.message_identifier.to_string(),
+                    ),
+                    (
+                        "serial_number".to_string(),
+                        warning.serial_number.to_string(),
+                    ),
+                    ("broadcast_frame".to_string(), pending.frame.to_string()),
+                    ("last_paging".to_string(), describe_last(self.last_paging)),
+                ]);
+                (
+                    format!(
+                        "Cell broadcast an ETWS emergency warning in {} without paging for it within {} seconds",
+                        warning.sib,
+                        self.max_delay.num_seconds()
+                    ),
+                    "The cell broadcast an earthquake or tsunami warning that was never announced by paging, as real networks do so that every UE sees it. A spoofed emergency alert from a fake base station looks like this, though so does a real warning the UE only picked up after the network had stopped paging for it.",
+                )
+            }
+        };
+        evidence.push(("frame".to_string(), self.packet_num.to_string()));
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: self.severity,
+            },
+            message,
+            explanation: explanation.to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for EtwsMismatchAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("ETWS Paging Mismatch")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether paging with the ETWS indication set is followed by an emergency warning broadcast in SIB10 or SIB11, and whether each new warning broadcast was announced by such paging, within max_delay_secs either way.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+        }
+        let now = ctx.timestamp?;
+        if let Some(sib10) = unpack_sib10(ie) {
+            self.handle_broadcast(
+                Warning {
+                    sib: "SIB10",
+                    message_identifier: bits_to_u64(&sib10.message_identifier.0),
+                    serial_number: bits_to_u64(&sib10.serial_number.0),
+                },
+                now,
+            );
+        } else if let Some(sib11) = unpack_sib11(ie) {
+            self.handle_broadcast(
+                Warning {
+                    sib: "SIB11",
+                    message_identifier: bits_to_u64(&sib11.message_identifier.0),
+                    serial_number: bits_to_u64(&sib11.serial_number.0),
+                },
+                now,
+            );
+        } else if is_etws_paging(ie) {
+            self.handle_etws_paging(now);
+        }
+        self.check_expired(now)
+    }
+}
@@ -0,0 +1,229 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_DCCH_MessageType, DL_DCCH_MessageType_c1, MobilityControlInfo,
+    RRCConnectionReconfigurationCriticalExtensions,
+    RRCConnectionReconfigurationCriticalExtensions_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HandoverPingPongSettings {
+    // how many times the UE may be handed back and forth between the same
+    // two cells within window_secs
+    pub max_oscillations: usize,
+    pub window_secs: u64,
+    // how long without a handover before the counts start over
+    pub stable_secs: u64,
+}
+
+impl Default for HandoverPingPongSettings {
+    fn default() -> Self {
+        Self {
+            max_oscillations: 3,
+            window_secs: 60,
+            stable_secs: 120,
+        }
+    }
+}
+
+impl AnalyzerSettings for HandoverPingPongSettings {
+    const KEY: &'static str = "handover_ping_pong";
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct PhysicalCell {
+    earfcn: u32,
+    phy_cell_id: u16,
+}
+
+impl fmt::Display for PhysicalCell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PCI {} on EARFCN {}", self.phy_cell_id, self.earfcn)
+    }
+}
+
+fn secs(secs: u64) -> TimeDelta {
+    TimeDelta::try_seconds(i64::try_from(secs).unwrap_or(i64::MAX)).unwrap_or(TimeDelta::MAX)
+}
+
+// A handover the network ordered, from the cell the UE was on to another
+struct Handover {
+    source: PhysicalCell,
+    target: PhysicalCell,
+    timestamp: DateTime<FixedOffset>,
+}
+
+// Counts the network handing the UE over from one cell to another and
+// straight back again. This is the connected mode counterpart to
+// reselection_churn, which only counts the UE changing cells by itself while
+// idle: here it's the network ordering each move, with an
+// RRCConnectionReconfiguration carrying mobilityControlInfo. Some ping-pong
+// happens at the edge between two cells with badly tuned handover
+// parameters, but a fake base station juggling the UE does it too.
+//
+// Cells are identified by their carrier and PCI, since the UE doesn't
+// necessarily read the SIB1 of a cell it's handed over to. The source of a
+// handover is the cell the order came from, or, for captures that don't say,
+// where the previous handover went. The counts start over once the UE stays
+// put for stable_secs, and each pair of cells is reported at most once per
+// window.
+pub struct HandoverPingPongAnalyzer {
+    packet_num: usize,
+    max_oscillations: usize,
+    window: TimeDelta,
+    stable: TimeDelta,
+    last_handover: Option<Handover>,
+    // when the UE was handed back between each pair of cells, keyed by the
+    // pair in order, oldest first
+    oscillations: HashMap<(PhysicalCell, PhysicalCell), VecDeque<DateTime<FixedOffset>>>,
+}
+
+impl HandoverPingPongAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: HandoverPingPongSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            max_oscillations: settings.max_oscillations,
+            window: secs(settings.window_secs),
+            stable: secs(settings.stable_secs),
+            last_handover: None,
+            oscillations: HashMap::new(),
+        }
+    }
+
+    fn handle_handover(
+        &mut self,
+        mobility: &MobilityControlInfo,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        let timestamp = ctx.timestamp?;
+        // a handover without a carrier frequency stays on the current one
+        let target = PhysicalCell {
+            earfcn: match &mobility.carrier_freq {
+                Some(carrier) => u32::from(carrier.dl_carrier_freq.0),
+                None => ctx.earfcn?,
+            },
+            phy_cell_id: mobility.target_phys_cell_id.0,
+        };
+        let source = match (ctx.earfcn, ctx.phy_cell_id) {
+            (Some(earfcn), Some(phy_cell_id)) => PhysicalCell {
+                earfcn,
+                phy_cell_id,
+            },
+            _ => self.last_handover.as_ref()?.target,
+        };
+        let previous = self.last_handover.replace(Handover {
+            source,
+            target,
+            timestamp,
+        })?;
+        if timestamp - previous.timestamp > self.stable {
+            self.oscillations.clear();
+            return None;
+        }
+        if previous.source != target || previous.target != source {
+            return None;
+        }
+
+        let pair = (source.min(target), source.max(target));
+        let recent = self.oscillations.entry(pair).or_default();
+        while let Some(oldest) = recent.front() {
+            if timestamp - *oldest <= self.window {
+                break;
+            }
+            recent.pop_front();
+        }
+        recent.push_back(timestamp);
+        if recent.len() <= self.max_oscillations {
+            return None;
+        }
+
+        let count = recent.len();
+        let span_secs = recent
+            .front()
+            .map(|oldest| (timestamp - *oldest).num_seconds())
+            .unwrap_or_default();
+        // start counting afresh, so ongoing ping-pong is reported once per
+        // window rather than on every handover
+        recent.clear();
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Low,
+            },
+            message: format!(
+                "UE was handed over back and forth between {} and {} {count} times within {span_secs}s (max {})",
+                pair.0, pair.1, self.max_oscillations
+            ),
+            explanation: "The network kept handing the UE over to the same cell and straight back. Interference or badly tuned handover settings at the edge between two cells can cause this, but so can an attacker juggling the UE between a real cell and a fake one.".to_string(),
+            evidence: vec![
+                ("cell_a".to_string(), pair.0.to_string()),
+                ("cell_b".to_string(), pair.1.to_string()),
+                ("oscillations".to_string(), count.to_string()),
+                ("span_secs".to_string(), span_secs.to_string()),
+                (
+                    "max_oscillations".to_string(),
+                    self.max_oscillations.to_string(),
+                ),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+}
+
+impl Analyzer for HandoverPingPongAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Handover Ping-Pong")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the network hands the UE over between the same two cells and back more than max_oscillations times within window_secs. Only connected mode handovers are counted, unlike reselection_churn. Interference or badly tuned handover parameters may trigger this too.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        let LteInformationElement::DlDcch(msg_cont) = &**inner else {
+            return None;
+        };
+        let DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(
+            reconfiguration,
+        )) = &msg_cont.message
+        else {
+            return None;
+        };
+        let RRCConnectionReconfigurationCriticalExtensions::C1(
+            RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(r8),
+        ) = &reconfiguration.critical_extensions
+        else {
+            return None;
+        };
+        self.handle_handover(r8.mobility_control_info.as_ref()?, ctx)
+    }
+}
@@ -0,0 +1,245 @@
+use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_DCCH_MessageType, DL_DCCH_MessageType_c1, IdleModeMobilityControlInfo,
+    RRCConnectionReleaseCriticalExtensions, RRCConnectionReleaseCriticalExtensions_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdleModeSteeringSettings {
+    // how many releases have to steer the UE the same way within
+    // window_secs to be reported
+    pub min_repeats: usize,
+    pub window_secs: u64,
+}
+
+impl Default for IdleModeSteeringSettings {
+    fn default() -> Self {
+        Self {
+            min_repeats: 3,
+            window_secs: 600,
+        }
+    }
+}
+
+impl AnalyzerSettings for IdleModeSteeringSettings {
+    const KEY: &'static str = "idle_mode_steering";
+}
+
+// One frequency a release gives the UE a dedicated reselection priority for
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct SteeredFrequency {
+    priority: u8,
+    rat: &'static str,
+    arfcn: u16,
+}
+
+impl fmt::Display for SteeredFrequency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} (priority {})",
+            self.rat, self.arfcn, self.priority
+        )
+    }
+}
+
+fn steered_frequencies(info: &IdleModeMobilityControlInfo) -> Vec<SteeredFrequency> {
+    let mut frequencies = Vec::new();
+    if let Some(list) = &info.freq_priority_list_eutra {
+        frequencies.extend(list.0.iter().map(|freq| SteeredFrequency {
+            priority: freq.cell_reselection_priority.0,
+            rat: "EUTRA",
+            arfcn: freq.carrier_freq.0,
+        }));
+    }
+    if let Some(list) = &info.freq_priority_list_geran {
+        frequencies.extend(list.0.iter().map(|freqs| SteeredFrequency {
+            priority: freqs.cell_reselection_priority.0,
+            rat: "GERAN",
+            arfcn: freqs.carrier_freqs.starting_arfcn.0,
+        }));
+    }
+    if let Some(list) = &info.freq_priority_list_utra_fdd {
+        frequencies.extend(list.0.iter().map(|freq| SteeredFrequency {
+            priority: freq.cell_reselection_priority.0,
+            rat: "UTRA-FDD",
+            arfcn: freq.carrier_freq.0,
+        }));
+    }
+    if let Some(list) = &info.freq_priority_list_utra_tdd {
+        frequencies.extend(list.0.iter().map(|freq| SteeredFrequency {
+            priority: freq.cell_reselection_priority.0,
+            rat: "UTRA-TDD",
+            arfcn: freq.carrier_freq.0,
+        }));
+    }
+    // highest priority first
+    frequencies.sort_by(|a, b| b.cmp(a));
+    frequencies
+}
+
+// Besides redirecting the UE (see the connection redirect analyzers), a
+// release can carry idleModeMobilityControlInfo: dedicated reselection
+// priorities for other frequencies, which override the ones broadcast in the
+// SIBs for as long as T320 runs. That quietly steers which cells the UE
+// camps on afterwards, so a fake cell can use it to keep pulling the UE back
+// to a frequency it controls without an obvious redirect.
+//
+// Networks do use this for load balancing, so a single release isn't
+// reported. This warns when min_repeats releases within window_secs give the
+// UE the same priorities, once for each set of priorities.
+pub struct IdleModeSteeringAnalyzer {
+    packet_num: usize,
+    min_repeats: usize,
+    window: TimeDelta,
+    serving_cell: Option<CellGlobalIdentity>,
+    // each steering release within the window, oldest first
+    recent: VecDeque<(DateTime<FixedOffset>, Vec<SteeredFrequency>)>,
+    reported: HashSet<Vec<SteeredFrequency>>,
+}
+
+impl IdleModeSteeringAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: IdleModeSteeringSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            min_repeats: settings.min_repeats,
+            window: TimeDelta::try_seconds(i64::try_from(settings.window_secs).unwrap_or(i64::MAX))
+                .unwrap_or(TimeDelta::MAX),
+            serving_cell: None,
+            recent: VecDeque::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    fn handle_release(
+        &mut self,
+        info: &IdleModeMobilityControlInfo,
+        timestamp: Option<DateTime<FixedOffset>>,
+    ) -> Option<Event> {
+        let frequencies = steered_frequencies(info);
+        if frequencies.is_empty() {
+            return None;
+        }
+        let timestamp = timestamp?;
+        while let Some((oldest, _)) = self.recent.front() {
+            if timestamp - *oldest <= self.window {
+                break;
+            }
+            self.recent.pop_front();
+        }
+        self.recent.push_back((timestamp, frequencies.clone()));
+        let repeats = self
+            .recent
+            .iter()
+            .filter(|(_, recent)| *recent == frequencies)
+            .count();
+        if repeats < self.min_repeats || !self.reported.insert(frequencies.clone()) {
+            return None;
+        }
+
+        let top_priority = frequencies[0].priority;
+        let targets: Vec<String> = frequencies
+            .iter()
+            .take_while(|freq| freq.priority == top_priority)
+            .map(|freq| format!("{} {}", freq.rat, freq.arfcn))
+            .collect();
+        // steering idle UEs toward 2G is steering them away from LTE's
+        // security
+        let to_geran = frequencies
+            .iter()
+            .take_while(|freq| freq.priority == top_priority)
+            .any(|freq| freq.rat == "GERAN");
+        let severity = if to_geran {
+            Severity::Medium
+        } else {
+            Severity::Low
+        };
+        let steered: Vec<String> = frequencies.iter().map(|freq| freq.to_string()).collect();
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("steered_frequencies".to_string(), steered.join("; ")),
+            ("preferred_frequencies".to_string(), targets.join(", ")),
+            ("repeats".to_string(), repeats.to_string()),
+        ]);
+        if let Some(t320) = &info.t320 {
+            evidence.push(("t320".to_string(), t320.0.to_string()));
+        }
+        evidence.push(("frame".to_string(), self.packet_num.to_string()));
+        Some(Event {
+            event_type: EventType::QualitativeWarning { severity },
+            message: format!(
+                "{repeats} connection releases steered the UE's idle mode reselection toward {}",
+                targets.join(", ")
+            ),
+            explanation: "The network released the UE's connection several times with the same dedicated reselection priorities, which override the ones cells broadcast and decide which cells the UE camps on while idle. Networks use these to balance load, but a fake base station can use them to keep steering the UE toward frequencies it controls, more quietly than redirecting it.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for IdleModeSteeringAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Idle Mode Steering")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether RRCConnectionRelease messages repeatedly (min_repeats times within window_secs) give the UE the same dedicated idle mode reselection priorities (idleModeMobilityControlInfo), steering which frequencies it camps on.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        let LteInformationElement::DlDcch(msg_cont) = &**inner else {
+            return None;
+        };
+        let DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionRelease(release)) =
+            &msg_cont.message
+        else {
+            return None;
+        };
+        let RRCConnectionReleaseCriticalExtensions::C1(
+            RRCConnectionReleaseCriticalExtensions_c1::RrcConnectionRelease_r8(r8),
+        ) = &release.critical_extensions
+        else {
+            return None;
+        };
+        self.handle_release(r8.idle_mode_mobility_control_info.as_ref()?, ctx.timestamp)
+    }
+}
@@ -0,0 +1,352 @@
+use std::borrow::Cow;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_DCCH_MessageType, DL_DCCH_MessageType_c1, RRCConnectionReleaseCriticalExtensions,
+    RRCConnectionReleaseCriticalExtensions_c1, RedirectedCarrierInfo,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::cs_fallback::{TargetRat, csfb_target};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{
+    CellGlobalIdentity, emm_cause_name, emm_cause_value, esm_cause_name, esm_message_name,
+    unpack_sib1,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImsRejectDowngradeSettings {
+    // how many PDN connections the network has to reject before a CS
+    // fallback for it to be reported
+    pub min_rejects: usize,
+    // how long ago a reject, or a sign of downgrading, can have been to still
+    // count
+    pub window_secs: u64,
+    // report as a warning of this severity rather than informationally when
+    // the network has also shown signs of downgrading the UE
+    pub correlated_severity: Option<Severity>,
+}
+
+impl Default for ImsRejectDowngradeSettings {
+    fn default() -> Self {
+        Self {
+            min_rejects: 2,
+            window_secs: 600,
+            correlated_severity: None,
+        }
+    }
+}
+
+impl AnalyzerSettings for ImsRejectDowngradeSettings {
+    const KEY: &'static str = "ims_reject_downgrade";
+}
+
+// Attach and TAU reject causes that make the UE disable LTE, per 24.301
+// section 5.5.1.2.5
+fn disables_eutra(cause: u8) -> bool {
+    matches!(cause, 7 | 14)
+}
+
+// Reads an ESM message's cause back out of its Debug representation
+fn esm_cause_value(debug: &str) -> Option<u8> {
+    let (_, after) = debug.split_once("esm_cause")?;
+    let digits: String = after
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+fn format_cause(cause: Option<u8>) -> String {
+    match cause {
+        Some(cause) => format!("#{cause} ({})", esm_cause_name(cause)),
+        None => "unreadable cause".to_string(),
+    }
+}
+
+struct Reject {
+    cause: Option<u8>,
+    timestamp: Option<DateTime<FixedOffset>>,
+    frame: usize,
+}
+
+struct DowngradeSign {
+    description: String,
+    timestamp: Option<DateTime<FixedOffset>>,
+}
+
+// A UE that can't register with IMS can't make VoLTE calls, so its calls fall
+// back to 2G/3G's circuit switched network instead, where a fake base station
+// has a much easier time intercepting them. Blocking IMS is therefore a way
+// to downgrade a UE's voice calls without ever touching its data.
+//
+// IMS registration itself is SIP, which the modem doesn't log, but before it
+// can register the UE needs a PDN connection to the IMS APN. A UE that's
+// already attached gets its default PDN connection from the attach, so the
+// PDN connections the network rejects afterwards are, on a phone, nearly
+// always the IMS one. This reports min_rejects of those rejects within
+// window_secs followed by a CS fallback, with the reject causes.
+//
+// IMS is often unavailable for ordinary reasons, like the subscription or
+// the roaming partner, so this is informational unless correlated_severity
+// is set, and then only escalated when the network also rejected an attach
+// or TAU with a cause that disables LTE, redirected the UE to 2G, or sent the
+// fallback itself to 2G within window_secs, as the attach reject and
+// connection redirect downgrade analyzers would report.
+pub struct ImsRejectDowngradeAnalyzer {
+    packet_num: usize,
+    min_rejects: usize,
+    window: TimeDelta,
+    correlated_severity: Option<Severity>,
+    serving_cell: Option<CellGlobalIdentity>,
+    attached: bool,
+    // whether the UE has sent an Extended Service Request for a CS fallback
+    // that hasn't happened yet
+    fallback_requested: bool,
+    rejects: Vec<Reject>,
+    downgrade_signs: Vec<DowngradeSign>,
+}
+
+impl ImsRejectDowngradeAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: ImsRejectDowngradeSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            min_rejects: settings.min_rejects.max(1),
+            window: TimeDelta::try_seconds(i64::try_from(settings.window_secs).unwrap_or(i64::MAX))
+                .unwrap_or(TimeDelta::MAX),
+            correlated_severity: settings.correlated_severity,
+            serving_cell: None,
+            attached: false,
+            fallback_requested: false,
+            rejects: Vec::new(),
+            downgrade_signs: Vec::new(),
+        }
+    }
+
+    fn expire(&mut self, now: Option<DateTime<FixedOffset>>) {
+        let Some(now) = now else {
+            return;
+        };
+        let window = self.window;
+        let fresh =
+            |timestamp: &Option<DateTime<FixedOffset>>| timestamp.is_none_or(|t| now - t <= window);
+        self.rejects.retain(|reject| fresh(&reject.timestamp));
+        self.downgrade_signs.retain(|sign| fresh(&sign.timestamp));
+    }
+
+    fn add_downgrade_sign(&mut self, description: String, ctx: &PacketContext) {
+        self.downgrade_signs.push(DowngradeSign {
+            description: format!("{description} (frame {})", self.packet_num),
+            timestamp: ctx.timestamp,
+        });
+    }
+
+    fn handle_emm(&mut self, message: &EMMMessage, ctx: &PacketContext) {
+        let (name, cause) = match message {
+            EMMMessage::EMMAttachAccept(_) => {
+                self.attached = true;
+                return;
+            }
+            EMMMessage::EMMDetachRequestMO(_) | EMMMessage::EMMDetachRequestMT(_) => {
+                self.attached = false;
+                return;
+            }
+            EMMMessage::EMMExtServiceRequest(_) => {
+                self.fallback_requested = true;
+                return;
+            }
+            // back on LTE, so whatever fallback was underway is over
+            EMMMessage::EMMAttachRequest(_) | EMMMessage::EMMTrackingAreaUpdateRequest(_) => {
+                self.fallback_requested = false;
+                return;
+            }
+            EMMMessage::EMMAttachReject(reject) => {
+                self.attached = false;
+                ("Attach Reject", emm_cause_value(&reject.emm_cause))
+            }
+            EMMMessage::EMMTrackingAreaUpdateReject(reject) => (
+                "Tracking Area Update Reject",
+                emm_cause_value(&reject.emm_cause),
+            ),
+            _ => return,
+        };
+        if let Some(cause) = cause.filter(|cause| disables_eutra(*cause)) {
+            self.add_downgrade_sign(format!("{name} #{cause} ({})", emm_cause_name(cause)), ctx);
+        }
+    }
+
+    fn handle_esm(&mut self, message: &NASMessage, ctx: &PacketContext) {
+        // the default PDN connection a UE gets while attaching isn't IMS
+        if !self.attached {
+            return;
+        }
+        let debug = format!("{message:?}");
+        if esm_message_name(&debug) != Some("ESMPDNConnectivityReject") {
+            return;
+        }
+        self.rejects.push(Reject {
+            cause: esm_cause_value(&debug),
+            timestamp: ctx.timestamp,
+            frame: self.packet_num,
+        });
+    }
+
+    fn handle_fallback(&mut self, rat: TargetRat, via: &str, ctx: &PacketContext) -> Option<Event> {
+        self.fallback_requested = false;
+        if rat == TargetRat::Geran {
+            self.add_downgrade_sign(format!("CS fallback via {via} to 2G"), ctx);
+        }
+        if self.rejects.len() < self.min_rejects {
+            self.rejects.clear();
+            return None;
+        }
+        let rejects = std::mem::take(&mut self.rejects);
+        let causes: Vec<String> = rejects
+            .iter()
+            .map(|reject| format_cause(reject.cause))
+            .collect();
+        let signs: Vec<&str> = self
+            .downgrade_signs
+            .iter()
+            .map(|sign| sign.description.as_str())
+            .collect();
+        let event_type = match self.correlated_severity {
+            Some(severity) if !signs.is_empty() => EventType::QualitativeWarning { severity },
+            _ => EventType::Informational,
+        };
+
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("ims_rejects".to_string(), rejects.len().to_string()),
+            ("reject_causes".to_string(), causes.join(", ")),
+            ("target_rat".to_string(), rat.name().to_string()),
+            ("via".to_string(), via.to_string()),
+        ]);
+        if !signs.is_empty() {
+            evidence.push(("downgrade_signs".to_string(), signs.join(", ")));
+        }
+        evidence.extend([
+            (
+                "first_reject_frame".to_string(),
+                rejects[0].frame.to_string(),
+            ),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        Some(Event {
+            event_type,
+            message: format!(
+                "Voice fell back to {} after the network rejected {} IMS PDN connections ({})",
+                rat.name(),
+                rejects.len(),
+                causes.join(", ")
+            ),
+            explanation: "The network kept rejecting the PDN connection the UE needs to register for VoLTE, and the UE's call then fell back to the older circuit switched network. IMS is often unavailable for ordinary reasons, like the UE's subscription, but blocking it is also how a fake base station pushes calls onto 2G or 3G, where they're much easier to intercept.".to_string(),
+            evidence,
+        })
+    }
+
+    fn handle_dl_dcch(
+        &mut self,
+        message: &DL_DCCH_MessageType_c1,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        match message {
+            DL_DCCH_MessageType_c1::MobilityFromEUTRACommand(command) => {
+                let rat = csfb_target(command)?;
+                self.handle_fallback(rat, "Mobility From EUTRA Command", ctx)
+            }
+            DL_DCCH_MessageType_c1::RrcConnectionRelease(release) => {
+                let RRCConnectionReleaseCriticalExtensions::C1(
+                    RRCConnectionReleaseCriticalExtensions_c1::RrcConnectionRelease_r8(r8),
+                ) = &release.critical_extensions
+                else {
+                    return None;
+                };
+                let rat = match r8.redirected_carrier_info.as_ref()? {
+                    RedirectedCarrierInfo::Geran(_) => TargetRat::Geran,
+                    RedirectedCarrierInfo::Utra_FDD(_) | RedirectedCarrierInfo::Utra_TDD(_) => {
+                        TargetRat::Utra
+                    }
+                    _ => return None,
+                };
+                // a redirect is only a CS fallback if the UE asked for one,
+                // otherwise a redirect to 2G is a downgrade of its own
+                if !self.fallback_requested {
+                    if rat == TargetRat::Geran {
+                        self.add_downgrade_sign(
+                            "RRC Connection Release redirect to 2G".to_string(),
+                            ctx,
+                        );
+                    }
+                    return None;
+                }
+                self.handle_fallback(rat, "RRC Connection Release redirect", ctx)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Analyzer for ImsRejectDowngradeAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("IMS Reject Downgrade")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether at least min_rejects PDN connection rejects within window_secs, after the UE has attached (so for its IMS connection, which VoLTE needs), are followed by a CS fallback of a voice call to 2G/3G. Informational, unless correlated_severity is set and the network also showed other signs of downgrading the UE. The reject causes are reported.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        self.expire(ctx.timestamp);
+        match &**inner {
+            LteInformationElement::NAS(NASMessage::EMMMessage(emm)) => {
+                self.handle_emm(emm, ctx);
+                None
+            }
+            LteInformationElement::NAS(message) => {
+                self.handle_esm(message, ctx);
+                None
+            }
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(c1) = &msg_cont.message else {
+                    return None;
+                };
+                self.handle_dl_dcch(c1, ctx)
+            }
+            _ => None,
+        }
+    }
+}
@@ -2,8 +2,9 @@ use std::borrow::Cow;
 
 use telcom_parser::lte_rrc::{PCCH_MessageType, PCCH_MessageType_c1, PagingUE_Identity};
 
-use super::analyzer::{Analyzer, Event, EventType, Severity};
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
 use super::information_element::{InformationElement, LteInformationElement};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
 
 pub struct ImsiProvidedAnalyzer {}
 
@@ -20,7 +21,15 @@ impl Analyzer for ImsiProvidedAnalyzer {
         1
     }
 
-    fn analyze_information_element(&mut self, ie: &InformationElement) -> Option<Event> {
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
         let pcch_msg = match ie {
             InformationElement::LTE(lte_ie) => match &**lte_ie {
                 LteInformationElement::PCCH(pcch_msg) => pcch_msg,
@@ -38,6 +47,8 @@ impl Analyzer for ImsiProvidedAnalyzer {
                         severity: Severity::High,
                     },
                     message: "IMSI was provided to cell".to_string(),
+                    explanation: "The cell paged the UE by its IMSI rather than its temporary identity. Paging by IMSI exposes the permanent identifier over the air, and is normally only done when the network has lost track of the UE's temporary identity.".to_string(),
+                    evidence: vec![("paging_ue_identity".to_string(), "IMSI".to_string())],
                 });
             }
         }
@@ -3,7 +3,7 @@ use std::borrow::Cow;
 use pycrate_rs::nas::NASMessage;
 use pycrate_rs::nas::emm::EMMMessage;
 
-use super::analyzer::{Analyzer, Event, EventType, Severity};
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
 use super::information_element::{InformationElement, LteInformationElement};
 use log::debug;
 
@@ -67,6 +67,11 @@ impl ImsiRequestedAnalyzer {
                         self.packet_num
                     )
                     .to_string(),
+                    explanation: "The network asked for the UE's permanent identity (IMSI or IMEI) outside of an attach. Legitimate networks normally identify a UE by its temporary identity, so an unprompted identity request is a common IMSI-catcher tactic.".to_string(),
+                    evidence: vec![
+                        ("previous_state".to_string(), format!("{current:?}")),
+                        ("frame".to_string(), self.packet_num.to_string()),
+                    ],
                 });
             }
 
@@ -81,6 +86,11 @@ impl ImsiRequestedAnalyzer {
                         self.packet_num
                     )
                     .to_string(),
+                    explanation: "The connection ended after the network requested the UE's identity but before authentication completed. A catcher that only wants to harvest identities typically drops the UE right after getting one.".to_string(),
+                    evidence: vec![
+                        ("previous_state".to_string(), format!("{:?}", State::IdentityRequest)),
+                        ("frame".to_string(), self.packet_num.to_string()),
+                    ],
                 });
                 self.timeout_counter = 0;
             }
@@ -112,7 +122,18 @@ impl Analyzer for ImsiRequestedAnalyzer {
         2
     }
 
-    fn analyze_information_element(&mut self, ie: &InformationElement) -> Option<Event> {
+    fn get_state(&self) -> Option<String> {
+        Some(format!(
+            "{:?} (timeout counter {})",
+            self.state, self.timeout_counter
+        ))
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
         self.packet_num += 1;
 
         if let InformationElement::LTE(inner) = ie {
@@ -175,6 +196,16 @@ impl Analyzer for ImsiRequestedAnalyzer {
                         self.packet_num
                     )
                     .to_string(),
+                    explanation: format!(
+                        "No authentication followed an identity request within {TIMEOUT_THRESHHOLD} messages. This is often benign, e.g. when the capture missed some messages, but is worth a look alongside other warnings."
+                    ),
+                    evidence: vec![
+                        (
+                            "messages_since_identity_request".to_string(),
+                            self.timeout_counter.to_string(),
+                        ),
+                        ("frame".to_string(), self.packet_num.to_string()),
+                    ],
                 });
                 self.timeout_counter = 0;
             }
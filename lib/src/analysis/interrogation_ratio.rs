@@ -0,0 +1,257 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_DCCH_MessageType, DL_DCCH_MessageType_c1, RRCConnectionReconfigurationCriticalExtensions,
+    RRCConnectionReconfigurationCriticalExtensions_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, esm_message_name, unpack_sib1};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InterrogationRatioSettings {
+    // how many times a cell has to have asked the UE for something before
+    // its ratio is judged
+    pub min_interrogations: usize,
+    // how many interrogations per service delivered are too many
+    pub max_ratio: f64,
+    // report as a warning of this severity rather than informationally
+    pub warning_severity: Option<Severity>,
+}
+
+impl Default for InterrogationRatioSettings {
+    fn default() -> Self {
+        Self {
+            min_interrogations: 10,
+            max_ratio: 5.0,
+            warning_severity: None,
+        }
+    }
+}
+
+impl AnalyzerSettings for InterrogationRatioSettings {
+    const KEY: &'static str = "interrogation_ratio";
+}
+
+enum Kind {
+    // the network asking the UE for something about itself
+    Interrogation,
+    // the network giving the UE something it can use
+    Delivery,
+}
+
+fn classify_emm(emm: &EMMMessage) -> Option<(Kind, &'static str)> {
+    match emm {
+        EMMMessage::EMMIdentityRequest(_) => Some((Kind::Interrogation, "Identity Request")),
+        EMMMessage::EMMAuthenticationRequest(_) => {
+            Some((Kind::Interrogation, "Authentication Request"))
+        }
+        EMMMessage::EMMAttachAccept(_) => Some((Kind::Delivery, "Attach Accept")),
+        EMMMessage::EMMTrackingAreaUpdateAccept(_) => {
+            Some((Kind::Delivery, "Tracking Area Update Accept"))
+        }
+        EMMMessage::EMMServiceAccept(_) => Some((Kind::Delivery, "Service Accept")),
+        EMMMessage::EMMDownlinkNASTransport(_) => Some((Kind::Delivery, "Downlink NAS Transport")),
+        EMMMessage::EMMCSServiceNotification(_) => {
+            Some((Kind::Delivery, "CS Service Notification"))
+        }
+        _ => None,
+    }
+}
+
+fn classify_dl_dcch(message: &DL_DCCH_MessageType_c1) -> Option<(Kind, &'static str)> {
+    match message {
+        DL_DCCH_MessageType_c1::UeCapabilityEnquiry(_) => {
+            Some((Kind::Interrogation, "UECapabilityEnquiry"))
+        }
+        DL_DCCH_MessageType_c1::UeInformationRequest_r9(_) => {
+            Some((Kind::Interrogation, "UEInformationRequest"))
+        }
+        DL_DCCH_MessageType_c1::CounterCheck(_) => Some((Kind::Interrogation, "CounterCheck")),
+        // only reconfigurations that set up data bearers deliver anything
+        DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(reconfiguration) => {
+            let RRCConnectionReconfigurationCriticalExtensions::C1(
+                RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(
+                    r8,
+                ),
+            ) = &reconfiguration.critical_extensions
+            else {
+                return None;
+            };
+            r8.radio_resource_config_dedicated
+                .as_ref()?
+                .drb_to_add_mod_list
+                .as_ref()?;
+            Some((Kind::Delivery, "data bearer setup"))
+        }
+        _ => None,
+    }
+}
+
+fn format_counts(counts: &BTreeMap<&'static str, usize>) -> String {
+    counts
+        .iter()
+        .map(|(name, count)| format!("{name}: {count}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Default)]
+struct CellCounts {
+    interrogations: BTreeMap<&'static str, usize>,
+    deliveries: BTreeMap<&'static str, usize>,
+}
+
+// In a normal session the network asks the UE for a few things, who it is,
+// proof that it's a subscriber, what it can do, and then gives it service:
+// it accepts its registration, sets up bearers for its data, delivers its
+// SMS and calls. A cell that keeps asking without ever getting around to
+// giving the UE anything is more interested in the UE than in serving it,
+// which is what a fake base station fingerprinting or tracking UEs looks
+// like.
+//
+// This is a coarse signal: a real network that can't serve the UE, or
+// registrations that keep failing, skew the ratio too, so it's only
+// informational unless configured otherwise, and is best read alongside
+// findings about the same cell. Counts are kept per serving cell (as of its
+// last SIB1) over the whole capture, and each cell's reported at most once.
+// A cell that delivered nothing has its ratio worked out as though it had
+// delivered once.
+pub struct InterrogationRatioAnalyzer {
+    packet_num: usize,
+    event_type: EventType,
+    min_interrogations: usize,
+    max_ratio: f64,
+    serving_cell: Option<CellGlobalIdentity>,
+    cells: HashMap<Option<CellGlobalIdentity>, CellCounts>,
+    reported: HashSet<Option<CellGlobalIdentity>>,
+}
+
+impl InterrogationRatioAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: InterrogationRatioSettings = analyzer_config.get_settings();
+        let event_type = match settings.warning_severity {
+            Some(severity) => EventType::QualitativeWarning { severity },
+            None => EventType::Informational,
+        };
+        Self {
+            packet_num: 0,
+            event_type,
+            min_interrogations: settings.min_interrogations.max(1),
+            max_ratio: settings.max_ratio,
+            serving_cell: None,
+            cells: HashMap::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    fn count(&mut self, kind: Kind, name: &'static str) -> Option<Event> {
+        let counts = self.cells.entry(self.serving_cell.clone()).or_default();
+        let counted = match kind {
+            Kind::Interrogation => &mut counts.interrogations,
+            Kind::Delivery => &mut counts.deliveries,
+        };
+        *counted.entry(name).or_default() += 1;
+
+        let interrogations: usize = counts.interrogations.values().sum();
+        let deliveries: usize = counts.deliveries.values().sum();
+        if interrogations < self.min_interrogations {
+            return None;
+        }
+        let ratio = interrogations as f64 / deliveries.max(1) as f64;
+        if ratio <= self.max_ratio || self.reported.contains(&self.serving_cell) {
+            return None;
+        }
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("ratio".to_string(), format!("{ratio:.1}")),
+            ("interrogations".to_string(), interrogations.to_string()),
+            ("deliveries".to_string(), deliveries.to_string()),
+            (
+                "interrogation_messages".to_string(),
+                format_counts(&counts.interrogations),
+            ),
+            (
+                "delivery_messages".to_string(),
+                format_counts(&counts.deliveries),
+            ),
+            ("max_ratio".to_string(), self.max_ratio.to_string()),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        self.reported.insert(self.serving_cell.clone());
+        Some(Event {
+            event_type: self.event_type.clone(),
+            message: format!(
+                "Cell asked the UE for information {interrogations} times while delivering service {deliveries} times (ratio {ratio:.1})"
+            ),
+            explanation: "The cell kept asking the UE about itself (its identity, its capabilities, its measurements) far more often than it gave the UE any actual service, like accepting its registration or setting up bearers for its data. A real network asks in order to serve, so a cell that mostly asks may be a fake base station fingerprinting or tracking UEs, though a network that's failing to serve the UE for ordinary reasons looks similar.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for InterrogationRatioAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Interrogation Ratio")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether a cell, once it has sent at least min_interrogations requests for information about the UE (Identity and Authentication Requests, UECapabilityEnquiry, UEInformationRequest and CounterCheck), has sent more than max_ratio of them for every service it delivered (registration and service accepts, data bearer setups, SMS and call notifications). Informational unless warning_severity is set.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        let (kind, name) = match &**inner {
+            LteInformationElement::NAS(NASMessage::EMMMessage(emm)) => classify_emm(emm)?,
+            // ESM messages are told apart by their debug representation, and
+            // the ones that activate a bearer context deliver service
+            LteInformationElement::NAS(message) => {
+                let debug = format!("{message:?}");
+                if !esm_message_name(&debug)?.starts_with("ESMAct") {
+                    return None;
+                }
+                (Kind::Delivery, "EPS bearer activation")
+            }
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(c1) = &msg_cont.message else {
+                    return None;
+                };
+                classify_dl_dcch(c1)?
+            }
+            _ => return None,
+        };
+        self.count(kind, name)
+    }
+}
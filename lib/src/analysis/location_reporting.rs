@@ -0,0 +1,273 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_DCCH_MessageType, DL_DCCH_MessageType_c1, UEInformationRequest_r9,
+    UEInformationRequest_r9CriticalExtensions, UEInformationRequest_r9CriticalExtensions_c1,
+    UEInformationResponse_r9, UEInformationResponse_r9CriticalExtensions,
+    UEInformationResponse_r9CriticalExtensions_c1, UL_DCCH_MessageType, UL_DCCH_MessageType_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LocationReportingSettings {
+    // report as a warning of this severity rather than informationally
+    pub warning_severity: Option<Severity>,
+    // how far back requests are counted for how often they're made
+    pub window_secs: u64,
+}
+
+impl Default for LocationReportingSettings {
+    fn default() -> Self {
+        Self {
+            warning_severity: None,
+            window_secs: 3600,
+        }
+    }
+}
+
+impl AnalyzerSettings for LocationReportingSettings {
+    const KEY: &'static str = "location_reporting";
+}
+
+// Whether a UE Information Request asks for a report that the UE includes
+// its location in: its MDT logged measurements, or the report of a failed
+// connection establishment
+fn requests_location(request: &UEInformationRequest_r9) -> bool {
+    let UEInformationRequest_r9CriticalExtensions::C1(
+        UEInformationRequest_r9CriticalExtensions_c1::UeInformationRequest_r9(r9),
+    ) = &request.critical_extensions
+    else {
+        return false;
+    };
+    let Some(v1020) = r9
+        .non_critical_extension
+        .as_ref()
+        .and_then(|v930| v930.non_critical_extension.as_ref())
+    else {
+        return false;
+    };
+    v1020.log_meas_report_req_r10.is_some()
+        || v1020
+            .non_critical_extension
+            .as_ref()
+            .is_some_and(|v1130| v1130.conn_est_fail_report_req_r11.is_some())
+}
+
+// The reports in a UE Information Response that include the UE's location,
+// and how many locations each one has
+fn reported_locations(response: &UEInformationResponse_r9) -> Vec<(&'static str, usize)> {
+    let UEInformationResponse_r9CriticalExtensions::C1(
+        UEInformationResponse_r9CriticalExtensions_c1::UeInformationResponse_r9(r9),
+    ) = &response.critical_extensions
+    else {
+        return Vec::new();
+    };
+    let Some(v1020) = r9
+        .non_critical_extension
+        .as_ref()
+        .and_then(|v930| v930.non_critical_extension.as_ref())
+    else {
+        return Vec::new();
+    };
+    let mut reports = Vec::new();
+    if let Some(log_meas_report) = &v1020.log_meas_report_r10 {
+        let locations = log_meas_report
+            .log_meas_info_list_r10
+            .0
+            .iter()
+            .filter(|info| info.location_info_r10.is_some())
+            .count();
+        if locations > 0 {
+            reports.push(("MDT logged measurements", locations));
+        }
+    }
+    if v1020
+        .non_critical_extension
+        .as_ref()
+        .and_then(|v1130| v1130.conn_est_fail_report_r11.as_ref())
+        .is_some_and(|report| report.location_info_r11.is_some())
+    {
+        reports.push(("connection establishment failure report", 1));
+    }
+    reports
+}
+
+// Over RRC, the network can have the UE report its location, usually the
+// GNSS fix it has at the time, along with its measurements. Networks use
+// this for minimization of drive tests (MDT) and to troubleshoot coverage,
+// but a network, or a fake base station, asking for it over and over is
+// tracking the handset. So every report that gets sent with the UE's
+// location in it is reported, along with how many times in the last
+// window_secs the network asked for reports like that. It's informational
+// unless configured otherwise. The positioning_measurement analyzer covers
+// the network setting the UE up to take these measurements in the first place.
+//
+// Measurement reports can carry the UE's location too, when their report
+// config sets includeLocationInfo, but that, like the location in the report
+// itself, is an extension of the report config and measurement results that
+// telcom-parser doesn't decode yet, so only the UE Information Response
+// reports are looked at.
+pub struct LocationReportingAnalyzer {
+    packet_num: usize,
+    event_type: EventType,
+    window: TimeDelta,
+    serving_cell: Option<CellGlobalIdentity>,
+    // when the network asked for reports with the UE's location in them,
+    // oldest first
+    requests: VecDeque<DateTime<FixedOffset>>,
+    total_requests: usize,
+}
+
+impl LocationReportingAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: LocationReportingSettings = analyzer_config.get_settings();
+        let event_type = match settings.warning_severity {
+            Some(severity) => EventType::QualitativeWarning { severity },
+            None => EventType::Informational,
+        };
+        Self {
+            packet_num: 0,
+            event_type,
+            window: TimeDelta::try_seconds(i64::try_from(settings.window_secs).unwrap_or(i64::MAX))
+                .unwrap_or(TimeDelta::MAX),
+            serving_cell: None,
+            requests: VecDeque::new(),
+            total_requests: 0,
+        }
+    }
+
+    fn expire_requests(&mut self, now: Option<DateTime<FixedOffset>>) {
+        let Some(now) = now else {
+            return;
+        };
+        while self
+            .requests
+            .front()
+            .is_some_and(|requested| now - *requested > self.window)
+        {
+            self.requests.pop_front();
+        }
+    }
+
+    fn handle_request(&mut self, ctx: &PacketContext) {
+        self.total_requests += 1;
+        self.expire_requests(ctx.timestamp);
+        if let Some(timestamp) = ctx.timestamp {
+            self.requests.push_back(timestamp);
+        }
+    }
+
+    fn handle_response(
+        &mut self,
+        reports: Vec<(&'static str, usize)>,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        if reports.is_empty() {
+            return None;
+        }
+        self.expire_requests(ctx.timestamp);
+        let locations: usize = reports.iter().map(|(_, locations)| locations).sum();
+        let methods: Vec<&str> = reports.iter().map(|(method, _)| *method).collect();
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("reports".to_string(), methods.join(", ")),
+            ("locations".to_string(), locations.to_string()),
+            (
+                "requests_in_window".to_string(),
+                self.requests.len().to_string(),
+            ),
+            (
+                "window_secs".to_string(),
+                self.window.num_seconds().to_string(),
+            ),
+            (
+                "total_requests".to_string(),
+                self.total_requests.to_string(),
+            ),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        Some(Event {
+            event_type: self.event_type.clone(),
+            message: format!(
+                "UE reported its location to the network in its {} ({} request(s) for it in the last {}s)",
+                methods.join(" and "),
+                self.requests.len(),
+                self.window.num_seconds()
+            ),
+            explanation: "The network asked the UE for measurements that include where it is, and the UE sent them. Networks use these to troubleshoot their coverage, but a network that keeps asking for them, or a fake base station, is tracking where the handset goes.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for LocationReportingAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Location Reporting")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the UE sends the network its location in a UE Information Response (MDT logged measurements, or a connection establishment failure report), and counts how often the network asked for one within window_secs. Informational unless warning_severity is set, since networks use these to troubleshoot coverage.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::UeInformationRequest_r9(
+                    request,
+                )) = &msg_cont.message
+                else {
+                    return None;
+                };
+                if requests_location(request) {
+                    self.handle_request(ctx);
+                }
+                None
+            }
+            LteInformationElement::UlDcch(msg_cont) => {
+                let UL_DCCH_MessageType::C1(UL_DCCH_MessageType_c1::UeInformationResponse_r9(
+                    response,
+                )) = &msg_cont.message
+                else {
+                    return None;
+                };
+                self.handle_response(reported_locations(response), ctx)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, VecDeque};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_CCCH_MessageType, DL_CCCH_MessageType_c1, DL_DCCH_MessageType, DL_DCCH_MessageType_c1,
+    MeasConfig, MeasGapConfig, MeasGapConfig_setupGapOffset, MeasObjectToAddModMeasObject,
+    RRCConnectionReconfigurationCriticalExtensions,
+    RRCConnectionReconfigurationCriticalExtensions_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MeasurementGapSettings {
+    // how many times measurement gaps may be set up within window_secs
+    pub max_setups: usize,
+    pub window_secs: u64,
+    // how long gaps may stay configured without being released
+    pub max_active_secs: u64,
+    // report as a warning of this severity rather than informationally
+    pub warning_severity: Option<Severity>,
+}
+
+impl Default for MeasurementGapSettings {
+    fn default() -> Self {
+        Self {
+            max_setups: 5,
+            window_secs: 300,
+            max_active_secs: 600,
+            warning_severity: None,
+        }
+    }
+}
+
+impl AnalyzerSettings for MeasurementGapSettings {
+    const KEY: &'static str = "measurement_gap";
+}
+
+fn secs(secs: u64) -> TimeDelta {
+    TimeDelta::try_seconds(i64::try_from(secs).unwrap_or(i64::MAX)).unwrap_or(TimeDelta::MAX)
+}
+
+// Describes a gap pattern by its gap length and repetition period, per
+// 36.133 table 8.1.2.1-1 and 38.133 table 9.1.2-1
+fn describe_gap(offset: &MeasGapConfig_setupGapOffset) -> String {
+    use MeasGapConfig_setupGapOffset as Gap;
+    let (pattern, gap, offset) = match offset {
+        Gap::Gp0(offset) => ("gp0", "6ms gap every 40ms", u16::from(offset.0)),
+        Gap::Gp1(offset) => ("gp1", "6ms gap every 80ms", u16::from(offset.0)),
+        Gap::Gp2_r14(offset) => ("gp2", "3ms gap every 40ms", u16::from(offset.0)),
+        Gap::Gp3_r14(offset) => ("gp3", "3ms gap every 80ms", u16::from(offset.0)),
+        Gap::Gp_ncsg0_r14(offset) => ("gp-ncsg0", "small gaps every 40ms", u16::from(offset.0)),
+        Gap::Gp_ncsg1_r14(offset) => ("gp-ncsg1", "small gaps every 80ms", u16::from(offset.0)),
+        Gap::Gp_ncsg2_r14(offset) => ("gp-ncsg2", "small gaps every 40ms", u16::from(offset.0)),
+        Gap::Gp_ncsg3_r14(offset) => ("gp-ncsg3", "small gaps every 80ms", u16::from(offset.0)),
+        Gap::Gp_nonUniform1_r14(offset) => ("gp-nonUniform1", "gaps every 1280ms", offset.0),
+        Gap::Gp_nonUniform2_r14(offset) => ("gp-nonUniform2", "gaps every 2560ms", offset.0),
+        Gap::Gp_nonUniform3_r14(offset) => ("gp-nonUniform3", "gaps every 5120ms", offset.0),
+        Gap::Gp_nonUniform4_r14(offset) => ("gp-nonUniform4", "gaps every 10240ms", offset.0),
+        Gap::Gp4_r15(offset) => ("gp4", "6ms gap every 20ms", u16::from(offset.0)),
+        Gap::Gp5_r15(offset) => ("gp5", "6ms gap every 160ms", u16::from(offset.0)),
+        Gap::Gp6_r15(offset) => ("gp6", "4ms gap every 20ms", u16::from(offset.0)),
+        Gap::Gp7_r15(offset) => ("gp7", "4ms gap every 40ms", u16::from(offset.0)),
+        Gap::Gp8_r15(offset) => ("gp8", "4ms gap every 80ms", u16::from(offset.0)),
+        Gap::Gp9_r15(offset) => ("gp9", "4ms gap every 160ms", u16::from(offset.0)),
+        Gap::Gp10_r15(offset) => ("gp10", "3ms gap every 20ms", u16::from(offset.0)),
+        Gap::Gp11_r15(offset) => ("gp11", "3ms gap every 160ms", u16::from(offset.0)),
+    };
+    format!("{pattern} ({gap}, offset {offset})")
+}
+
+// Where the UE measures during the gaps
+fn describe_carrier(meas_object: &MeasObjectToAddModMeasObject) -> String {
+    match meas_object {
+        MeasObjectToAddModMeasObject::MeasObjectEUTRA(eutra) => {
+            format!("LTE EARFCN {}", eutra.carrier_freq.0)
+        }
+        MeasObjectToAddModMeasObject::MeasObjectUTRA(utra) => {
+            format!("UMTS UARFCN {}", utra.carrier_freq.0)
+        }
+        MeasObjectToAddModMeasObject::MeasObjectGERAN(geran) => {
+            format!("GSM from ARFCN {}", geran.carrier_freqs.starting_arfcn.0)
+        }
+        MeasObjectToAddModMeasObject::MeasObjectCDMA2000(_) => "CDMA2000".to_string(),
+        MeasObjectToAddModMeasObject::MeasObjectWLAN_r13(_) => "WLAN".to_string(),
+        MeasObjectToAddModMeasObject::MeasObjectNR_r15(_) => "NR".to_string(),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Anomaly {
+    Frequent,
+    Persistent,
+}
+
+// The measurement gaps currently configured
+struct ActiveGap {
+    pattern: String,
+    since: DateTime<FixedOffset>,
+    reported: bool,
+}
+
+// Measurement gaps are regular breaks in a connection, during which the UE
+// stops listening to its serving cell to measure other frequencies and RATs.
+// The network sets them up when it wants the UE to look for somewhere to
+// hand it over to, and releases them once it's found somewhere or given up,
+// since the UE can't receive anything during them. Gaps that are set up over
+// and over, or never released, keep the UE scanning whatever frequencies the
+// network lists, which a fake base station can use to steer it towards ones
+// it controls, or to learn what the UE can hear.
+//
+// Networks use gaps routinely, especially at the edge of their coverage, so
+// this is informational unless configured otherwise. Gaps are taken to be
+// released when the connection is, and set up again whenever a
+// reconfiguration sets them up, whether or not they were already. The
+// carriers the UE is measuring are included as they were when the finding
+// was reported.
+pub struct MeasurementGapAnalyzer {
+    packet_num: usize,
+    event_type: EventType,
+    max_setups: usize,
+    window: TimeDelta,
+    max_active: TimeDelta,
+    serving_cell: Option<CellGlobalIdentity>,
+    gap: Option<ActiveGap>,
+    // when gaps were set up, oldest first
+    setups: VecDeque<DateTime<FixedOffset>>,
+    // the connection's measurement objects, by ID
+    carriers: BTreeMap<u8, String>,
+}
+
+impl MeasurementGapAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: MeasurementGapSettings = analyzer_config.get_settings();
+        let event_type = match settings.warning_severity {
+            Some(severity) => EventType::QualitativeWarning { severity },
+            None => EventType::Informational,
+        };
+        Self {
+            packet_num: 0,
+            event_type,
+            max_setups: settings.max_setups,
+            window: secs(settings.window_secs),
+            max_active: secs(settings.max_active_secs),
+            serving_cell: None,
+            gap: None,
+            setups: VecDeque::new(),
+            carriers: BTreeMap::new(),
+        }
+    }
+
+    fn handle_connection_change(&mut self) {
+        self.gap = None;
+        self.carriers.clear();
+    }
+
+    fn handle_meas_config(
+        &mut self,
+        meas_config: &MeasConfig,
+        timestamp: DateTime<FixedOffset>,
+    ) -> Option<Event> {
+        if let Some(removed) = &meas_config.meas_object_to_remove_list {
+            for id in &removed.0 {
+                self.carriers.remove(&id.0);
+            }
+        }
+        if let Some(added) = &meas_config.meas_object_to_add_mod_list {
+            for meas_object in &added.0 {
+                self.carriers.insert(
+                    meas_object.meas_object_id.0,
+                    describe_carrier(&meas_object.meas_object),
+                );
+            }
+        }
+        let setup = match meas_config.meas_gap_config.as_ref()? {
+            MeasGapConfig::Release(_) => {
+                self.gap = None;
+                return None;
+            }
+            MeasGapConfig::Setup(setup) => setup,
+        };
+        let pattern = describe_gap(&setup.gap_offset);
+        // a new pattern doesn't make gaps that were already there any newer
+        let since = match &self.gap {
+            Some(gap) => gap.since,
+            None => timestamp,
+        };
+        let reported = self.gap.as_ref().is_some_and(|gap| gap.reported);
+        self.gap = Some(ActiveGap {
+            pattern,
+            since,
+            reported,
+        });
+
+        while self
+            .setups
+            .front()
+            .is_some_and(|setup| timestamp - *setup > self.window)
+        {
+            self.setups.pop_front();
+        }
+        self.setups.push_back(timestamp);
+        if self.setups.len() <= self.max_setups {
+            return self.check_persistence(timestamp);
+        }
+        let event = self.make_event(Anomaly::Frequent, timestamp);
+        // each window's worth of setups is reported once
+        self.setups.clear();
+        event
+    }
+
+    fn check_persistence(&mut self, timestamp: DateTime<FixedOffset>) -> Option<Event> {
+        let gap = self.gap.as_mut()?;
+        if gap.reported || timestamp - gap.since <= self.max_active {
+            return None;
+        }
+        gap.reported = true;
+        self.make_event(Anomaly::Persistent, timestamp)
+    }
+
+    fn make_event(&self, anomaly: Anomaly, timestamp: DateTime<FixedOffset>) -> Option<Event> {
+        let gap = self.gap.as_ref()?;
+        let active_secs = (timestamp - gap.since).num_seconds();
+        let (message, explanation) = match anomaly {
+            Anomaly::Frequent => (
+                format!(
+                    "Network set up measurement gaps {} times within {}s",
+                    self.setups.len(),
+                    self.window.num_seconds()
+                ),
+                "The network kept setting up measurement gaps, breaks during which the UE stops listening to its cell to scan other frequencies. Networks set them up to find somewhere to hand the UE over to, but doing it over and over keeps the UE scanning whatever frequencies the network lists, which a fake base station could use to steer it or to learn what it can hear.",
+            ),
+            Anomaly::Persistent => (
+                format!(
+                    "Network kept measurement gaps configured for {active_secs}s without releasing them"
+                ),
+                "The network set up measurement gaps, breaks during which the UE stops listening to its cell to scan other frequencies, and left them configured far longer than it takes to find somewhere to hand the UE over to. This keeps the UE scanning whatever frequencies the network lists, which a fake base station could use to steer it or to learn what it can hear.",
+            ),
+        };
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.push(("gap_pattern".to_string(), gap.pattern.clone()));
+        if anomaly == Anomaly::Frequent {
+            let setups: Vec<String> = self.setups.iter().map(|setup| setup.to_rfc3339()).collect();
+            evidence.push(("setups".to_string(), setups.join(", ")));
+        }
+        evidence.push(("active_since".to_string(), gap.since.to_rfc3339()));
+        evidence.push(("active_secs".to_string(), active_secs.to_string()));
+        if !self.carriers.is_empty() {
+            let carriers: Vec<&str> = self.carriers.values().map(String::as_str).collect();
+            evidence.push(("measured_carriers".to_string(), carriers.join(", ")));
+        }
+        evidence.push(("frame".to_string(), self.packet_num.to_string()));
+        Some(Event {
+            event_type: self.event_type.clone(),
+            message,
+            explanation: explanation.to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for MeasurementGapAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Measurement Gap Configuration")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the network sets up measurement gaps, which make the UE scan other frequencies, more than max_setups times within window_secs, or keeps them configured for longer than max_active_secs. Informational unless warning_severity is set, since networks use them routinely.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        let timestamp = ctx.timestamp?;
+        match &**inner {
+            LteInformationElement::DlCcch(msg_cont) => {
+                match &msg_cont.message {
+                    DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionSetup(_)) => {
+                        self.handle_connection_change();
+                    }
+                    // re-establishment releases the gaps, but keeps what
+                    // the UE was measuring
+                    DL_CCCH_MessageType::C1(
+                        DL_CCCH_MessageType_c1::RrcConnectionReestablishment(_),
+                    ) => self.gap = None,
+                    _ => {}
+                }
+                None
+            }
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(c1) = &msg_cont.message else {
+                    return self.check_persistence(timestamp);
+                };
+                match c1 {
+                    DL_DCCH_MessageType_c1::RrcConnectionRelease(_) => {
+                        self.handle_connection_change();
+                        None
+                    }
+                    DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(reconfiguration) => {
+                        let RRCConnectionReconfigurationCriticalExtensions::C1(
+                            RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(r8),
+                        ) = &reconfiguration.critical_extensions
+                        else {
+                            return self.check_persistence(timestamp);
+                        };
+                        match &r8.meas_config {
+                            Some(meas_config) => self.handle_meas_config(meas_config, timestamp),
+                            None => self.check_persistence(timestamp),
+                        }
+                    }
+                    _ => self.check_persistence(timestamp),
+                }
+            }
+            _ => self.check_persistence(timestamp),
+        }
+    }
+}
@@ -1,9 +1,65 @@
+pub mod access_barring;
 pub mod analyzer;
+pub mod antenna_config;
+pub mod attach_reject_downgrade;
+pub mod auth_without_identity;
+pub mod band_indicator_mismatch;
+pub mod cell_distance;
+pub mod cell_diversity;
+pub mod cell_identity_mismatch;
+pub mod cell_selection;
+pub mod cell_swap;
+pub mod connection_hold;
 pub mod connection_redirect_downgrade;
+pub mod counter_replay;
+pub mod cs_fallback;
+pub mod dedicated_sib1_mismatch;
+pub mod duplex_change;
+pub mod emergency_numbers;
+pub mod etws_mismatch;
+pub mod handover_ping_pong;
+pub mod idle_mode_steering;
+pub mod ims_reject_downgrade;
 pub mod imsi_provided;
 pub mod imsi_requested;
 pub mod information_element;
+pub mod interrogation_ratio;
+pub mod location_reporting;
+pub mod measurement_gap;
 pub mod nas_null_cipher;
+pub mod nas_rrc_consistency;
+pub mod network_detach;
+pub mod network_triggered_tau;
+pub mod nitz;
 pub mod null_cipher;
+pub mod out_of_spec_ie;
+pub mod pci_conflict;
+pub mod periodic_tau_timer;
+pub mod placeholder_identity;
+pub mod plmn_overlap;
+pub mod positioning_measurement;
+pub mod power_control;
 pub mod priority_2g_downgrade;
+pub mod reconfiguration_churn;
+pub mod reestablishment_storm;
+pub mod repaging;
+pub mod repeated_identity_request;
+pub mod report_format;
+pub mod reselection_churn;
+pub mod rrc_integrity;
+pub mod security_mode_failure;
+pub mod security_policy;
+pub mod service_reject;
+pub mod si_periodicity_change;
+pub mod sib_schedule;
+pub mod sim_ota;
+pub mod steering_reject;
+pub mod tmsi_lifetime;
+pub mod uac_barring;
+pub mod unexpected_band;
+pub mod unexpected_plmn;
+pub mod unprotected_reconfiguration;
+pub mod uplink_starvation;
 pub mod util;
+pub mod value_tag_consistency;
+pub mod weak_cell_attach;
@@ -4,8 +4,9 @@ use pycrate_rs::nas::NASMessage;
 use pycrate_rs::nas::emm::EMMMessage;
 use pycrate_rs::nas::generated::emm::emm_security_mode_command::NASSecAlgoCiphAlgo::EPSEncryptionAlgorithmEEA0Null;
 
-use super::analyzer::{Analyzer, Event, EventType, Severity};
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
 use super::information_element::{InformationElement, LteInformationElement};
+use crate::log_codes::ANALYZER_NAS_LOG_CODES;
 
 pub struct NasNullCipherAnalyzer {
     packet_num: usize,
@@ -38,7 +39,15 @@ impl Analyzer for NasNullCipherAnalyzer {
         1
     }
 
-    fn analyze_information_element(&mut self, ie: &InformationElement) -> Option<Event> {
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_NAS_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
         self.packet_num += 1;
         let payload = match ie {
             InformationElement::LTE(inner) => match &**inner {
@@ -58,6 +67,14 @@ impl Analyzer for NasNullCipherAnalyzer {
                         "NAS Security mode command requested null cipher(packet {})",
                         self.packet_num
                     ),
+                    explanation: "The MME's NAS Security Mode Command selected EEA0, so NAS messages between the UE and the core network won't be encrypted. Real networks have no reason to do this for a normal SIM.".to_string(),
+                    evidence: vec![
+                        (
+                            "ciph_algo".to_string(),
+                            format!("{:?}", req.nas_sec_algo.inner.ciph_algo),
+                        ),
+                        ("frame".to_string(), self.packet_num.to_string()),
+                    ],
                 });
             }
         }
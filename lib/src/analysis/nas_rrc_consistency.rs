@@ -0,0 +1,328 @@
+use std::borrow::Cow;
+
+use pycrate_rs::nas::NASMessage;
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_CCCH_MessageType, DL_CCCH_MessageType_c1, DL_DCCH_MessageType, DL_DCCH_MessageType_c1,
+    DLInformationTransfer_r8_IEsDedicatedInfoType,
+    DLInformationTransfer_r15_IEsDedicatedInfoType_r15, DLInformationTransferCriticalExtensions,
+    DLInformationTransferCriticalExtensions_c1, RRCConnectionReconfigurationCriticalExtensions,
+    RRCConnectionReconfigurationCriticalExtensions_c1, UL_DCCH_MessageType, UL_DCCH_MessageType_c1,
+    ULInformationTransfer_r8_IEsDedicatedInfoType,
+    ULInformationTransfer_r16_IEsDedicatedInfoType_r16, ULInformationTransferCriticalExtensions,
+    ULInformationTransferCriticalExtensions_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NasRrcConsistencySettings {
+    // how many NAS messages or RRC containers in a connection may go without
+    // a counterpart before it's reported
+    pub max_unmatched: usize,
+}
+
+impl Default for NasRrcConsistencySettings {
+    fn default() -> Self {
+        Self { max_unmatched: 2 }
+    }
+}
+
+impl AnalyzerSettings for NasRrcConsistencySettings {
+    const KEY: &'static str = "nas_rrc_consistency";
+}
+
+// how many message names to list in the evidence
+const MAX_LISTED: usize = 10;
+
+// The name of a NAS message, e.g. "EMMAttachRequest", from its Debug output
+fn nas_message_name(message: &NASMessage) -> String {
+    let debug = format!("{message:?}");
+    let mut parts = debug.split('(');
+    let outer = parts.next().unwrap_or_default();
+    parts.next().unwrap_or(outer).to_string()
+}
+
+fn list_names(names: &[String]) -> String {
+    let mut listed = names[..names.len().min(MAX_LISTED)].join(", ");
+    if names.len() > MAX_LISTED {
+        listed.push_str(&format!(", and {} more", names.len() - MAX_LISTED));
+    }
+    listed
+}
+
+// How many NAS messages an uplink RRC message carries
+fn ul_dcch_containers(message: &UL_DCCH_MessageType_c1) -> usize {
+    match message {
+        // the setup complete always carries the NAS message that the
+        // connection was set up for
+        UL_DCCH_MessageType_c1::RrcConnectionSetupComplete(_) => 1,
+        UL_DCCH_MessageType_c1::UlInformationTransfer(transfer) => {
+            let ULInformationTransferCriticalExtensions::C1(c1) = &transfer.critical_extensions
+            else {
+                return 0;
+            };
+            match c1 {
+                ULInformationTransferCriticalExtensions_c1::UlInformationTransfer_r8(r8) => {
+                    match r8.dedicated_info_type {
+                        ULInformationTransfer_r8_IEsDedicatedInfoType::DedicatedInfoNAS(_) => 1,
+                        _ => 0,
+                    }
+                }
+                ULInformationTransferCriticalExtensions_c1::UlInformationTransfer_r16(r16) => {
+                    match r16.dedicated_info_type_r16 {
+                        Some(
+                            ULInformationTransfer_r16_IEsDedicatedInfoType_r16::DedicatedInfoNAS(_),
+                        ) => 1,
+                        _ => 0,
+                    }
+                }
+                _ => 0,
+            }
+        }
+        _ => 0,
+    }
+}
+
+// How many NAS messages a downlink RRC message carries
+fn dl_dcch_containers(message: &DL_DCCH_MessageType_c1) -> usize {
+    match message {
+        DL_DCCH_MessageType_c1::DlInformationTransfer(transfer) => {
+            let DLInformationTransferCriticalExtensions::C1(c1) = &transfer.critical_extensions
+            else {
+                return 0;
+            };
+            match c1 {
+                DLInformationTransferCriticalExtensions_c1::DlInformationTransfer_r8(r8) => {
+                    match r8.dedicated_info_type {
+                        DLInformationTransfer_r8_IEsDedicatedInfoType::DedicatedInfoNAS(_) => 1,
+                        _ => 0,
+                    }
+                }
+                DLInformationTransferCriticalExtensions_c1::DlInformationTransfer_r15(r15) => {
+                    match r15.dedicated_info_type_r15 {
+                        Some(
+                            DLInformationTransfer_r15_IEsDedicatedInfoType_r15::DedicatedInfoNAS(_),
+                        ) => 1,
+                        _ => 0,
+                    }
+                }
+                _ => 0,
+            }
+        }
+        DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(reconfiguration) => {
+            let RRCConnectionReconfigurationCriticalExtensions::C1(
+                RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(
+                    r8,
+                ),
+            ) = &reconfiguration.critical_extensions
+            else {
+                return 0;
+            };
+            r8.dedicated_info_nas_list
+                .as_ref()
+                .map(|list| list.0.len())
+                .unwrap_or_default()
+        }
+        _ => 0,
+    }
+}
+
+// What was seen of NAS and of the RRC containers carrying it during one RRC
+// connection
+#[derive(Default)]
+struct Connection {
+    start_frame: usize,
+    nas_messages: Vec<String>,
+    containers: usize,
+    container_messages: Vec<String>,
+}
+
+// NAS messages travel between the UE and the MME inside RRC messages: the
+// RRCConnectionSetupComplete, UL/DLInformationTransfer, and the
+// dedicatedInfoNASList of an RRCConnectionReconfiguration. The modem logs the
+// NAS messages themselves separately from the RRC ones, so over an RRC
+// connection the two should roughly add up. NAS messages with no RRC
+// container at all to carry them, or RRC containers whose NAS messages never
+// show up, suggest signalling that didn't go through the UE's normal stack,
+// like a fake base station or a modem being fed messages from elsewhere.
+//
+// The counts only roughly add up because ESM messages piggybacked on an EMM
+// one (like the PDN connectivity request in an attach request) are logged as
+// two NAS messages in one container, and because NAS messages that fail to
+// decode aren't counted. So a connection's reported when NAS messages show up
+// in a connection without a single container, or when there are more than
+// max_unmatched containers than NAS messages. NAS messages seen while idle
+// are counted towards the next connection, since the uplink ones are logged
+// before the RRC message that carries them. The connection that's already
+// going when the capture starts isn't checked.
+pub struct NasRrcConsistencyAnalyzer {
+    packet_num: usize,
+    max_unmatched: usize,
+    connection: Option<Connection>,
+    idle_nas_messages: Vec<String>,
+}
+
+impl NasRrcConsistencyAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: NasRrcConsistencySettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            max_unmatched: settings.max_unmatched,
+            connection: None,
+            idle_nas_messages: Vec::new(),
+        }
+    }
+
+    fn handle_nas(&mut self, message: &NASMessage) {
+        let name = nas_message_name(message);
+        match &mut self.connection {
+            Some(connection) => connection.nas_messages.push(name),
+            None => self.idle_nas_messages.push(name),
+        }
+    }
+
+    fn handle_containers(&mut self, name: &str, count: usize) {
+        let Some(connection) = &mut self.connection else {
+            return;
+        };
+        if count == 0 {
+            return;
+        }
+        connection.containers += count;
+        connection.container_messages.push(name.to_string());
+    }
+
+    fn handle_setup(&mut self) {
+        self.connection = Some(Connection {
+            start_frame: self.packet_num,
+            nas_messages: std::mem::take(&mut self.idle_nas_messages),
+            ..Default::default()
+        });
+    }
+
+    fn handle_release(&mut self) -> Option<Event> {
+        let connection = self.connection.take();
+        self.idle_nas_messages.clear();
+        let connection = connection?;
+        let nas_count = connection.nas_messages.len();
+
+        let (message, severity) = if connection.containers == 0 && nas_count > self.max_unmatched {
+            (
+                format!(
+                    "{nas_count} NAS messages were seen during an RRC connection that carried none: {}",
+                    list_names(&connection.nas_messages)
+                ),
+                Severity::Medium,
+            )
+        } else if connection.containers > nas_count + self.max_unmatched {
+            (
+                format!(
+                    "RRC messages carried {} NAS messages during a connection, but only {nas_count} were seen: {}",
+                    connection.containers,
+                    list_names(&connection.container_messages)
+                ),
+                Severity::Low,
+            )
+        } else {
+            return None;
+        };
+        Some(Event {
+            event_type: EventType::QualitativeWarning { severity },
+            message,
+            explanation: "NAS messages reach the UE inside RRC messages, so during a connection the two should line up. NAS messages that no RRC message carried, or RRC messages whose NAS messages never showed up, can mean signalling bypassed the UE's normal radio stack, as with a fake base station. Messages that fail to decode can cause this too.".to_string(),
+            evidence: vec![
+                ("nas_messages".to_string(), list_names(&connection.nas_messages)),
+                (
+                    "rrc_containers".to_string(),
+                    connection.containers.to_string(),
+                ),
+                (
+                    "rrc_messages".to_string(),
+                    list_names(&connection.container_messages),
+                ),
+                (
+                    "connection_start_frame".to_string(),
+                    connection.start_frame.to_string(),
+                ),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+}
+
+impl Analyzer for NasRrcConsistencyAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("NAS/RRC Consistency")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the NAS messages seen during an RRC connection line up with the RRC messages carrying NAS: NAS messages in a connection without a single RRC container, or more than max_unmatched RRC containers whose NAS messages never showed up. NAS messages that fail to decode may trigger this too.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::NAS(message) => self.handle_nas(message),
+            LteInformationElement::DlCcch(msg_cont) => {
+                if let DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionSetup(_)) =
+                    &msg_cont.message
+                {
+                    self.handle_setup();
+                }
+            }
+            LteInformationElement::UlDcch(msg_cont) => {
+                if let UL_DCCH_MessageType::C1(c1) = &msg_cont.message {
+                    let name = match c1 {
+                        UL_DCCH_MessageType_c1::RrcConnectionSetupComplete(_) => {
+                            "RRCConnectionSetupComplete"
+                        }
+                        _ => "ULInformationTransfer",
+                    };
+                    self.handle_containers(name, ul_dcch_containers(c1));
+                }
+            }
+            LteInformationElement::DlDcch(msg_cont) => {
+                if let DL_DCCH_MessageType::C1(c1) = &msg_cont.message {
+                    match c1 {
+                        DL_DCCH_MessageType_c1::RrcConnectionRelease(_) => {
+                            return self.handle_release();
+                        }
+                        DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(_) => {
+                            self.handle_containers(
+                                "RRCConnectionReconfiguration",
+                                dl_dcch_containers(c1),
+                            );
+                        }
+                        _ => {
+                            self.handle_containers("DLInformationTransfer", dl_dcch_containers(c1))
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
@@ -0,0 +1,126 @@
+use std::borrow::Cow;
+
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+
+// A network-initiated detach (especially with "re-attach not required") can be
+// used to kick a UE off of a legitimate network so that it reselects to an
+// attacker's cell.
+pub struct NetworkDetachAnalyzer {
+    packet_num: usize,
+    serving_cell: Option<CellGlobalIdentity>,
+    // the serving cell at the time of the last network-initiated detach, if we
+    // haven't yet seen the UE camp somewhere else or re-attach
+    detached_from: Option<Option<CellGlobalIdentity>>,
+}
+
+impl Default for NetworkDetachAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetworkDetachAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            packet_num: 0,
+            serving_cell: None,
+            detached_from: None,
+        }
+    }
+
+    fn handle_sib1(&mut self, cell: CellGlobalIdentity) -> Option<Event> {
+        let mut event = None;
+        if let Some(Some(previous_cell)) = &self.detached_from {
+            if *previous_cell != cell {
+                event = Some(Event {
+                    event_type: EventType::QualitativeWarning {
+                        severity: Severity::Medium,
+                    },
+                    message: format!(
+                        "UE reselected from {previous_cell} to {cell} after a network-initiated detach (frame {})",
+                        self.packet_num
+                    ),
+                    explanation: "Shortly after the network detached the UE, the UE camped on a different cell. This is what you'd expect to see if a catcher kicked the UE off of its legitimate cell to lure it onto its own, though it can also happen when simply moving around.".to_string(),
+                    evidence: vec![
+                        ("previous_cell".to_string(), previous_cell.to_string()),
+                        ("new_cell".to_string(), cell.to_string()),
+                        ("frame".to_string(), self.packet_num.to_string()),
+                    ],
+                });
+                self.detached_from = None;
+            }
+        }
+        self.serving_cell = Some(cell);
+        event
+    }
+}
+
+impl Analyzer for NetworkDetachAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Network-Initiated Detach")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the network sends a Detach Request to the UE, and whether the UE then reselects to a different cell.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+
+        if let Some(sib1) = unpack_sib1(ie) {
+            return self.handle_sib1(CellGlobalIdentity::from_sib1(sib1)?);
+        }
+
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        let LteInformationElement::NAS(payload) = &**inner else {
+            return None;
+        };
+        match payload {
+            NASMessage::EMMMessage(EMMMessage::EMMDetachRequestMT(req)) => {
+                self.detached_from = Some(self.serving_cell.clone());
+                Some(Event {
+                    event_type: EventType::QualitativeWarning {
+                        severity: Severity::Low,
+                    },
+                    message: format!(
+                        "Network sent Detach Request (type: {:?}, cause: {:?}) (frame {})",
+                        req.eps_detach_type, req.emm_cause, self.packet_num
+                    ),
+                    explanation: "The network told the UE to detach. Networks do this occasionally, e.g. during maintenance, but it's also a way to force the UE to look for another cell.".to_string(),
+                    evidence: vec![
+                        (
+                            "eps_detach_type".to_string(),
+                            format!("{:?}", req.eps_detach_type),
+                        ),
+                        ("emm_cause".to_string(), format!("{:?}", req.emm_cause)),
+                        ("frame".to_string(), self.packet_num.to_string()),
+                    ],
+                })
+            }
+            // a fresh attach starts a new session, so any pending detach is
+            // no longer relevant
+            NASMessage::EMMMessage(EMMMessage::EMMAttachRequest(_)) => {
+                self.detached_from = None;
+                None
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::borrow::Cow;
+
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_DCCH_MessageType, DL_DCCH_MessageType_c1, RRCConnectionReleaseCriticalExtensions,
+    RRCConnectionReleaseCriticalExtensions_c1, ReleaseCause,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkTriggeredTauSettings {
+    // how many tracking area updates the network may trigger in one session
+    // while the UE stays in the tracking area it's registered in
+    pub max_spurious_taus: usize,
+}
+
+impl Default for NetworkTriggeredTauSettings {
+    fn default() -> Self {
+        Self {
+            max_spurious_taus: 2,
+        }
+    }
+}
+
+impl AnalyzerSettings for NetworkTriggeredTauSettings {
+    const KEY: &'static str = "network_triggered_tau";
+}
+
+// The tracking area a UE is in or registered in: its PLMN and TAC
+fn tracking_area(cell: &CellGlobalIdentity) -> (String, u32) {
+    (cell.plmn.clone(), cell.tracking_area_code)
+}
+
+// What's been seen since the UE last attached
+#[derive(Default)]
+struct Session {
+    // the tracking area the UE was in when its last attach or tracking area
+    // update was accepted
+    registered: Option<(String, u32)>,
+    // whether the last RRC connection was released with
+    // loadBalancingTAUrequired, and the TAU it calls for hasn't been sent yet
+    awaiting_tau: bool,
+    tau_requests: usize,
+    triggered_taus: usize,
+    spurious_taus: usize,
+    reported: bool,
+}
+
+// The network can make a UE perform a tracking area update by releasing its
+// RRC connection with the loadBalancingTAUrequired cause, which operators use
+// to move UEs off an overloaded MME. Every TAU tells the network where the
+// UE is, so a network that keeps doing it while the UE stays in the tracking
+// area it's already registered in, where there's nothing for the update to
+// change, may be checking that the UE's still there and where it is.
+//
+// TAUs the UE sends on its own, periodically or because it moved, aren't
+// counted. The count is kept for each session (from one attach to the next,
+// or to a detach), so the network balancing its MMEs now and then isn't
+// added up over a whole capture. Each session's reported at most once.
+pub struct NetworkTriggeredTauAnalyzer {
+    packet_num: usize,
+    max_spurious_taus: usize,
+    serving_cell: Option<CellGlobalIdentity>,
+    session: Session,
+}
+
+impl NetworkTriggeredTauAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: NetworkTriggeredTauSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            max_spurious_taus: settings.max_spurious_taus,
+            serving_cell: None,
+            session: Session::default(),
+        }
+    }
+
+    fn register(&mut self) {
+        self.session.registered = self.serving_cell.as_ref().map(tracking_area);
+    }
+
+    fn handle_tau_request(&mut self) -> Option<Event> {
+        let session = &mut self.session;
+        session.tau_requests += 1;
+        if !std::mem::take(&mut session.awaiting_tau) {
+            return None;
+        }
+        session.triggered_taus += 1;
+        let cell = self.serving_cell.as_ref()?;
+        if session.registered.as_ref() != Some(&tracking_area(cell)) {
+            return None;
+        }
+        session.spurious_taus += 1;
+        if session.reported || session.spurious_taus <= self.max_spurious_taus {
+            return None;
+        }
+        session.reported = true;
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Low,
+            },
+            message: format!(
+                "Network made the UE update its tracking area {} times without it leaving tracking area {} (max {})",
+                session.spurious_taus, cell.tracking_area_code, self.max_spurious_taus
+            ),
+            explanation: "The network released the UE's connection and told it to perform a tracking area update, over and over, while the UE stayed in the tracking area it was already registered in. Operators do this to balance load between their core network servers, but doing it repeatedly with nothing to update also confirms that the UE is still there, and where.".to_string(),
+            evidence: vec![
+                ("cell".to_string(), cell.to_string()),
+                (
+                    "spurious_taus".to_string(),
+                    session.spurious_taus.to_string(),
+                ),
+                (
+                    "network_triggered_taus".to_string(),
+                    session.triggered_taus.to_string(),
+                ),
+                ("tau_requests".to_string(), session.tau_requests.to_string()),
+                (
+                    "max_spurious_taus".to_string(),
+                    self.max_spurious_taus.to_string(),
+                ),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+
+    fn handle_emm(&mut self, message: &EMMMessage) -> Option<Event> {
+        match message {
+            EMMMessage::EMMTrackingAreaUpdateRequest(_) => self.handle_tau_request(),
+            EMMMessage::EMMTrackingAreaUpdateAccept(_) => {
+                self.register();
+                None
+            }
+            EMMMessage::EMMAttachAccept(_) => {
+                self.session = Session::default();
+                self.register();
+                None
+            }
+            EMMMessage::EMMDetachRequestMO(_) | EMMMessage::EMMDetachRequestMT(_) => {
+                self.session = Session::default();
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Analyzer for NetworkTriggeredTauAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Network Triggered TAU")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the network makes the UE perform more than max_spurious_taus tracking area updates in one session, by releasing its connection with the loadBalancingTAUrequired cause, while the UE stays in the tracking area it's registered in. TAUs the UE sends on its own aren't counted.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::NAS(NASMessage::EMMMessage(emm)) => self.handle_emm(emm),
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionRelease(release)) =
+                    &msg_cont.message
+                else {
+                    return None;
+                };
+                let RRCConnectionReleaseCriticalExtensions::C1(
+                    RRCConnectionReleaseCriticalExtensions_c1::RrcConnectionRelease_r8(r8),
+                ) = &release.critical_extensions
+                else {
+                    return None;
+                };
+                self.session.awaiting_tau =
+                    r8.release_cause.0 == ReleaseCause::LOAD_BALANCING_TA_UREQUIRED;
+                None
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,363 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::SystemInformationBlockType16_r11;
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1, unpack_sib16};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NitzSettings {
+    // how far the network's time may be from the device's clock. Clocks that
+    // haven't synced in a while drift, so this is generous.
+    pub max_clock_offset_secs: u64,
+    // whether to check the advertised time zone against the country the
+    // serving cell's MCC belongs to
+    pub check_time_zone: bool,
+}
+
+impl Default for NitzSettings {
+    fn default() -> Self {
+        Self {
+            max_clock_offset_secs: 3600,
+            check_time_zone: true,
+        }
+    }
+}
+
+impl AnalyzerSettings for NitzSettings {
+    const KEY: &'static str = "nitz";
+}
+
+// The offsets from UTC, in quarter hours, that each country's local time can
+// be at, daylight saving time included. Countries that aren't listed aren't
+// checked, and neither are those whose territories span most of the globe.
+const MCC_TIME_ZONES: &[(&[u16], &[(i8, i8)])] = &[
+    (&[202], &[(8, 12)]),     // Greece
+    (&[204], &[(4, 8)]),      // Netherlands
+    (&[206], &[(4, 8)]),      // Belgium
+    (&[208], &[(4, 8)]),      // France (metropolitan)
+    (&[214], &[(0, 8)]),      // Spain, including the Canaries
+    (&[222], &[(4, 8)]),      // Italy
+    (&[226], &[(8, 12)]),     // Romania
+    (&[228], &[(4, 8)]),      // Switzerland
+    (&[232], &[(4, 8)]),      // Austria
+    (&[234, 235], &[(0, 4)]), // United Kingdom
+    (&[238], &[(4, 8)]),      // Denmark
+    (&[240], &[(4, 8)]),      // Sweden
+    (&[242], &[(4, 8)]),      // Norway
+    (&[244], &[(8, 12)]),     // Finland
+    (&[250], &[(8, 48)]),     // Russia
+    (&[255], &[(8, 12)]),     // Ukraine
+    (&[260], &[(4, 8)]),      // Poland
+    (&[262], &[(4, 8)]),      // Germany
+    (&[268], &[(-4, 4)]),     // Portugal, including the Azores
+    (&[272], &[(0, 4)]),      // Ireland
+    (&[286], &[(12, 12)]),    // Turkey
+    (&[302], &[(-32, -10)]),  // Canada
+    // the US's PLMNs also cover its Pacific territories
+    (
+        &[310, 311, 312, 313, 314, 315, 316],
+        &[(-44, -16), (40, 40)],
+    ),
+    (&[334], &[(-32, -20)]),         // Mexico
+    (&[404, 405, 406], &[(22, 22)]), // India
+    (&[420], &[(12, 12)]),           // Saudi Arabia
+    (&[424], &[(16, 16)]),           // United Arab Emirates
+    (&[425], &[(8, 12)]),            // Israel
+    (&[440, 441], &[(36, 36)]),      // Japan
+    (&[450], &[(36, 36)]),           // South Korea
+    (&[454], &[(32, 32)]),           // Hong Kong
+    (&[460], &[(32, 32)]),           // China
+    (&[466], &[(32, 32)]),           // Taiwan
+    (&[505], &[(28, 44)]),           // Australia
+    (&[530], &[(48, 55)]),           // New Zealand, including the Chathams
+    (&[602], &[(8, 12)]),            // Egypt
+    (&[621], &[(4, 4)]),             // Nigeria
+    (&[655], &[(8, 8)]),             // South Africa
+    (&[716], &[(-20, -20)]),         // Peru
+    (&[722], &[(-12, -12)]),         // Argentina
+    (&[724], &[(-20, -8)]),          // Brazil
+    (&[730], &[(-24, -12)]),         // Chile, including Easter Island
+    (&[732], &[(-20, -20)]),         // Colombia
+];
+
+fn expected_time_zones(mcc: u16) -> Option<&'static [(i8, i8)]> {
+    MCC_TIME_ZONES
+        .iter()
+        .find(|(mccs, _)| mccs.contains(&mcc))
+        .map(|(_, zones)| *zones)
+}
+
+fn format_time_zone(quarter_hours: i8) -> String {
+    let sign = if quarter_hours < 0 { '-' } else { '+' };
+    let minutes = i32::from(quarter_hours).abs() * 15;
+    format!("UTC{sign}{}:{:02}", minutes / 60, minutes % 60)
+}
+
+// A semi-octet, with swapped nibbles, per 24.008 10.5.3.9
+fn decode_semi_octet(byte: u8) -> u32 {
+    u32::from(byte & 0x0f) * 10 + u32::from(byte >> 4)
+}
+
+// The time zone octet is a semi-octet too, but with its sign in bit 3, per
+// 24.008 10.5.3.8
+fn decode_time_zone(byte: u8) -> i8 {
+    let quarter_hours = (u32::from(byte & 0x07) * 10 + u32::from(byte >> 4)) as i8;
+    if byte & 0x08 != 0 {
+        -quarter_hours
+    } else {
+        quarter_hours
+    }
+}
+
+// The network's time and time zone, as it advertised them
+struct NetworkTime {
+    source: &'static str,
+    time: Option<DateTime<Utc>>,
+    // in quarter hours
+    time_zone: Option<i8>,
+}
+
+impl NetworkTime {
+    // Universal time and local time zone, from an EMM Information message
+    // (24.008 10.5.3.9)
+    fn from_universal_time(source: &'static str, octets: &[u8]) -> Option<Self> {
+        let [year, month, day, hour, minute, second, time_zone] =
+            octets.get(..7)?.try_into().ok()?;
+        let time = NaiveDate::from_ymd_opt(
+            2000 + decode_semi_octet(year) as i32,
+            decode_semi_octet(month),
+            decode_semi_octet(day),
+        )
+        .and_then(|date| {
+            date.and_hms_opt(
+                decode_semi_octet(hour),
+                decode_semi_octet(minute),
+                decode_semi_octet(second),
+            )
+        })
+        .map(|time| time.and_utc());
+        Some(NetworkTime {
+            source,
+            time,
+            time_zone: Some(decode_time_zone(time_zone)),
+        })
+    }
+
+    // SIB16's time is in 10ms units since the start of 1900, per 36.331
+    fn from_sib16(sib16: &SystemInformationBlockType16_r11) -> Option<Self> {
+        const MILLIS_FROM_1900_TO_UNIX_EPOCH: i64 = 2_208_988_800_000;
+        let time_info = sib16.time_info_r11.as_ref()?;
+        let millis = i64::try_from(time_info.time_info_utc_r11.0).ok()? * 10;
+        Some(NetworkTime {
+            source: "sib16",
+            time: DateTime::from_timestamp_millis(millis - MILLIS_FROM_1900_TO_UNIX_EPOCH),
+            time_zone: time_info
+                .local_time_offset_r11
+                .as_ref()
+                .map(|offset| offset.0),
+        })
+    }
+}
+
+// Networks tell UEs the time and their time zone (NITZ) in EMM Information
+// messages, and some also broadcast it in SIB16. Phones usually set their
+// clock from it, so a fake cell can use it to shift a phone's clock, which
+// can break certificate and token validation or confuse the timestamps of
+// what the phone records. Fake cells that don't bother to get it right also
+// give themselves away.
+//
+// This warns when the advertised time is further than max_clock_offset_secs
+// from the device's own clock, and when the advertised time zone isn't one
+// the serving cell's country uses. Each is reported once per cell and source.
+pub struct NitzAnalyzer {
+    packet_num: usize,
+    max_clock_offset_secs: u64,
+    check_time_zone: bool,
+    serving_cell: Option<CellGlobalIdentity>,
+    reported: HashSet<(Option<CellGlobalIdentity>, &'static str, &'static str)>,
+}
+
+impl NitzAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: NitzSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            max_clock_offset_secs: settings.max_clock_offset_secs,
+            check_time_zone: settings.check_time_zone,
+            serving_cell: None,
+            reported: HashSet::new(),
+        }
+    }
+
+    fn first_report(&mut self, source: &'static str, check: &'static str) -> bool {
+        self.reported
+            .insert((self.serving_cell.clone(), source, check))
+    }
+
+    fn evidence(&self, source: &str) -> Vec<(String, String)> {
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.push(("source".to_string(), source.to_string()));
+        evidence
+    }
+
+    fn check_clock(
+        &mut self,
+        network_time: &NetworkTime,
+        device_time: Option<DateTime<FixedOffset>>,
+    ) -> Option<Event> {
+        let (advertised, device_time) = (network_time.time?, device_time?);
+        let offset_secs = advertised.signed_duration_since(device_time).num_seconds();
+        if offset_secs.unsigned_abs() <= self.max_clock_offset_secs
+            || !self.first_report(network_time.source, "clock")
+        {
+            return None;
+        }
+        let mut evidence = self.evidence(network_time.source);
+        evidence.extend([
+            ("advertised_time".to_string(), advertised.to_rfc3339()),
+            ("device_time".to_string(), device_time.to_rfc3339()),
+            ("offset_secs".to_string(), offset_secs.to_string()),
+            (
+                "max_clock_offset_secs".to_string(),
+                self.max_clock_offset_secs.to_string(),
+            ),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!(
+                "Network advertised a time {offset_secs}s off from the device's clock (max {}s): {advertised}",
+                self.max_clock_offset_secs
+            ),
+            explanation: "The time the network told the UE is far from the device's own clock. Phones set their clock from the network, so a fake base station can use this to shift it, which can break security checks that depend on the time. A device clock that has drifted far, e.g. after being off for a long time, can also cause this.".to_string(),
+            evidence,
+        })
+    }
+
+    fn check_time_zone(&mut self, network_time: &NetworkTime) -> Option<Event> {
+        if !self.check_time_zone {
+            return None;
+        }
+        let time_zone = network_time.time_zone?;
+        let cell = self.serving_cell.as_ref()?;
+        let mcc: u16 = cell.plmn.split('-').next()?.parse().ok()?;
+        let expected = expected_time_zones(mcc)?;
+        if expected
+            .iter()
+            .any(|(min, max)| (*min..=*max).contains(&time_zone))
+            || !self.first_report(network_time.source, "time_zone")
+        {
+            return None;
+        }
+        let expected: Vec<String> = expected
+            .iter()
+            .map(|(min, max)| {
+                if min == max {
+                    format_time_zone(*min)
+                } else {
+                    format!("{} to {}", format_time_zone(*min), format_time_zone(*max))
+                }
+            })
+            .collect();
+        let mut evidence = self.evidence(network_time.source);
+        evidence.extend([
+            (
+                "advertised_time_zone".to_string(),
+                format_time_zone(time_zone),
+            ),
+            ("mcc".to_string(), mcc.to_string()),
+            ("expected_time_zones".to_string(), expected.join(", ")),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Low,
+            },
+            message: format!(
+                "Network advertised time zone {} but MCC {mcc} uses {}",
+                format_time_zone(time_zone),
+                expected.join(", ")
+            ),
+            explanation: "The time zone the network told the UE isn't one used in the country the cell claims to be in. Real networks advertise their local time zone, while fake base stations often come preconfigured for wherever they were built or last used.".to_string(),
+            evidence,
+        })
+    }
+
+    fn handle_network_time(
+        &mut self,
+        network_time: NetworkTime,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        // the clock is the more serious of the two, so it's reported first
+        // if both are off
+        self.check_clock(&network_time, ctx.timestamp)
+            .or_else(|| self.check_time_zone(&network_time))
+    }
+}
+
+impl Analyzer for NitzAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Suspicious Network Time")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the time the network advertises (NITZ, in EMM Information or SIB16) is more than max_clock_offset_secs from the device's clock, or its time zone isn't used in the serving cell's country.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        if let Some(sib16) = unpack_sib16(ie) {
+            let network_time = NetworkTime::from_sib16(sib16)?;
+            return self.handle_network_time(network_time, ctx);
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        let LteInformationElement::NAS(NASMessage::EMMMessage(EMMMessage::EMMInformation(info))) =
+            &**inner
+        else {
+            return None;
+        };
+        let network_time = match (&info.univ_time_and_time_zone, &info.local_time_zone) {
+            (Some(universal), _) => {
+                NetworkTime::from_universal_time("emm_information", &universal.inner)?
+            }
+            (None, Some(local)) => NetworkTime {
+                source: "emm_information",
+                time: None,
+                time_zone: Some(decode_time_zone(*local.inner.first()?)),
+            },
+            (None, None) => return None,
+        };
+        self.handle_network_time(network_time, ctx)
+    }
+}
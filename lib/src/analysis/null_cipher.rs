@@ -8,8 +8,9 @@ use telcom_parser::lte_rrc::{
     SecurityModeCommandCriticalExtensions, SecurityModeCommandCriticalExtensions_c1,
 };
 
-use super::analyzer::{Analyzer, Event, EventType, Severity};
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
 use super::information_element::{InformationElement, LteInformationElement};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
 
 pub struct NullCipherAnalyzer {}
 
@@ -131,7 +132,15 @@ impl Analyzer for NullCipherAnalyzer {
         1
     }
 
-    fn analyze_information_element(&mut self, ie: &InformationElement) -> Option<Event> {
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
         let dcch_msg = match ie {
             InformationElement::LTE(lte_ie) => match &**lte_ie {
                 LteInformationElement::DlDcch(dcch_msg) => dcch_msg,
@@ -142,13 +151,15 @@ impl Analyzer for NullCipherAnalyzer {
         let DL_DCCH_MessageType::C1(c1) = &dcch_msg.message else {
             return None;
         };
-        let null_cipher_detected = match c1 {
-            DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(reconfiguration) => {
-                self.check_rrc_connection_reconfiguration_cipher(reconfiguration)
-            }
-            DL_DCCH_MessageType_c1::SecurityModeCommand(command) => {
-                self.check_security_mode_command_cipher(command)
-            }
+        let (null_cipher_detected, message_type) = match c1 {
+            DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(reconfiguration) => (
+                self.check_rrc_connection_reconfiguration_cipher(reconfiguration),
+                "RRCConnectionReconfiguration",
+            ),
+            DL_DCCH_MessageType_c1::SecurityModeCommand(command) => (
+                self.check_security_mode_command_cipher(command),
+                "SecurityModeCommand",
+            ),
             _ => return None,
         };
         if null_cipher_detected {
@@ -157,6 +168,13 @@ impl Analyzer for NullCipherAnalyzer {
                     severity: Severity::High,
                 },
                 message: "Cell suggested use of null cipher".to_string(),
+                explanation: format!(
+                    "The cell's {message_type} selected EEA0, meaning the UE's traffic over the air won't be encrypted. Networks essentially never do this outside of emergency calls without a SIM."
+                ),
+                evidence: vec![
+                    ("message_type".to_string(), message_type.to_string()),
+                    ("ciphering_algorithm".to_string(), "EEA0".to_string()),
+                ],
             });
         }
         None
@@ -0,0 +1,156 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{PLMN_Identity, SystemInformationBlockType1};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::InformationElement;
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+// Sizes of the SIB1 bit strings, per 36.331
+const CELL_IDENTITY_BITS: usize = 28;
+const TRACKING_AREA_CODE_BITS: usize = 16;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutOfSpecIeSettings {
+    // report as a warning of this severity rather than informationally
+    pub warning_severity: Option<Severity>,
+}
+
+impl AnalyzerSettings for OutOfSpecIeSettings {
+    const KEY: &'static str = "out_of_spec_ie";
+}
+
+fn check_plmn(plmn: &PLMN_Identity, index: usize, problems: &mut Vec<String>) {
+    match &plmn.mcc {
+        Some(mcc) => {
+            if mcc.0.len() != 3 {
+                problems.push(format!("PLMN {index} has a {}-digit MCC", mcc.0.len()));
+            }
+            if mcc.0.iter().any(|digit| digit.0 > 9) {
+                problems.push(format!("PLMN {index} has an MCC digit above 9"));
+            }
+        }
+        // later entries inherit the MCC of the one before them, but the first
+        // has nothing to inherit from
+        None if index == 0 => problems.push("the first PLMN has no MCC".to_string()),
+        None => {}
+    }
+    if !(2..=3).contains(&plmn.mnc.0.len()) {
+        problems.push(format!("PLMN {index} has a {}-digit MNC", plmn.mnc.0.len()));
+    }
+    if plmn.mnc.0.iter().any(|digit| digit.0 > 9) {
+        problems.push(format!("PLMN {index} has an MNC digit above 9"));
+    }
+}
+
+// Lists the ways a SIB1 breaks 36.331 despite having decoded
+fn sib1_problems(sib1: &SystemInformationBlockType1) -> Vec<String> {
+    let mut problems = Vec::new();
+    let info = &sib1.cell_access_related_info;
+    let cell_identity_bits = info.cell_identity.0.len();
+    if cell_identity_bits != CELL_IDENTITY_BITS {
+        problems.push(format!(
+            "cellIdentity is {cell_identity_bits} bits long, not {CELL_IDENTITY_BITS}"
+        ));
+    }
+    let tac_bits = info.tracking_area_code.0.len();
+    if tac_bits != TRACKING_AREA_CODE_BITS {
+        problems.push(format!(
+            "trackingAreaCode is {tac_bits} bits long, not {TRACKING_AREA_CODE_BITS}"
+        ));
+    }
+    for (index, plmn_info) in info.plmn_identity_list.0.iter().enumerate() {
+        check_plmn(&plmn_info.plmn_identity, index, &mut problems);
+    }
+    problems
+}
+
+// The ASN.1 decoder is lenient about some things the spec is strict about, so
+// a SIB1 can decode fine while still being malformed. Commercial base
+// stations get these details right, whereas a crudely built fake cell might
+// not. Vendors have their quirks too, so this is informational unless
+// configured otherwise.
+pub struct OutOfSpecIeAnalyzer {
+    packet_num: usize,
+    event_type: EventType,
+    // cells already reported for, along with what was wrong each time
+    reported: HashSet<(Option<CellGlobalIdentity>, Vec<String>)>,
+}
+
+impl OutOfSpecIeAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: OutOfSpecIeSettings = analyzer_config.get_settings();
+        let event_type = match settings.warning_severity {
+            Some(severity) => EventType::QualitativeWarning { severity },
+            None => EventType::Informational,
+        };
+        Self {
+            packet_num: 0,
+            event_type,
+            reported: HashSet::new(),
+        }
+    }
+}
+
+impl Analyzer for OutOfSpecIeAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Out-of-Spec Information Element")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether a cell's SIB1 decodes but breaks 36.331, e.g. a cell identity or tracking area code of the wrong length, or a PLMN with the wrong number of digits. Vendor quirks can trigger this too, so it's informational unless warning_severity is set.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let sib1 = unpack_sib1(ie)?;
+        let problems = sib1_problems(sib1);
+        if problems.is_empty() {
+            return None;
+        }
+        let cell = CellGlobalIdentity::from_sib1(sib1);
+        if !self.reported.insert((cell.clone(), problems.clone())) {
+            return None;
+        }
+
+        let problems = problems.join("; ");
+        let mut evidence = vec![
+            ("ie".to_string(), "SystemInformationBlockType1".to_string()),
+            ("problems".to_string(), problems.clone()),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ];
+        let message = match &cell {
+            Some(cell) => {
+                evidence.insert(0, ("cell".to_string(), cell.to_string()));
+                format!("SIB1 of {cell} is out of spec: {problems}")
+            }
+            None => format!("SIB1 is out of spec: {problems}"),
+        };
+        Some(Event {
+            event_type: self.event_type.clone(),
+            message,
+            explanation: "The cell broadcast a SIB1 that could be decoded but doesn't follow the 3GPP specification. Real base stations rarely get this wrong, while a hastily built fake one might.".to_string(),
+            evidence,
+        })
+    }
+}
@@ -0,0 +1,89 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
+use super::information_element::InformationElement;
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+// A physical cell ID is only locally unique, but within a single capture the
+// same PCI on the same channel should always belong to the same cell. An
+// attacker cloning a legitimate cell's PCI while advertising its own cell
+// identity breaks that.
+pub struct PciConflictAnalyzer {
+    // (EARFCN, PCI) -> the cell first seen with it
+    cells: HashMap<(u32, u16), CellGlobalIdentity>,
+    // conflicts we've already warned about, so a cloned cell only gets
+    // reported once rather than on every SIB1 it broadcasts
+    reported: HashSet<((u32, u16), CellGlobalIdentity)>,
+}
+
+impl Default for PciConflictAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PciConflictAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            cells: HashMap::new(),
+            reported: HashSet::new(),
+        }
+    }
+}
+
+impl Analyzer for PciConflictAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Conflicting PCI")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the same physical cell ID on the same EARFCN is used by cells advertising different global cell identities. Legitimate PCI reuse is possible across distant cells, so this may false-positive when a capture spans a long trip.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        let sib1 = unpack_sib1(ie)?;
+        let key = (ctx.earfcn?, ctx.phy_cell_id?);
+        let cell = CellGlobalIdentity::from_sib1(sib1)?;
+        let known_cell = self.cells.entry(key).or_insert_with(|| cell.clone());
+        if known_cell.plmn == cell.plmn && known_cell.cell_identity == cell.cell_identity {
+            return None;
+        }
+        let message = format!(
+            "PCI {} on EARFCN {} is used by both {known_cell} and {cell}",
+            key.1, key.0
+        );
+        let evidence = vec![
+            ("earfcn".to_string(), key.0.to_string()),
+            ("phy_cell_id".to_string(), key.1.to_string()),
+            ("first_cell".to_string(), known_cell.to_string()),
+            ("conflicting_cell".to_string(), cell.to_string()),
+        ];
+        if !self.reported.insert((key, cell)) {
+            return None;
+        }
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::High,
+            },
+            message,
+            explanation: "Two cells with different global identities were seen using the same physical cell ID on the same channel. That's what a catcher cloning a nearby legitimate cell's PCI looks like, though distant cells may legitimately reuse a PCI if the capture covers a lot of ground.".to_string(),
+            evidence,
+        })
+    }
+}
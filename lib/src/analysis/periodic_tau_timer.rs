@@ -0,0 +1,140 @@
+use std::borrow::Cow;
+
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use crate::log_codes::ANALYZER_NAS_LOG_CODES;
+
+// Decodes a GPRS timer IE (24.008 10.5.7.3) into seconds, with None meaning
+// the timer is deactivated
+fn decode_gprs_timer(unit: u8, value: u8) -> Option<u64> {
+    let value = value as u64;
+    match unit {
+        0b000 => Some(value * 2),
+        0b010 => Some(value * 6 * 60),
+        0b111 => None,
+        // 0b001 is minutes, and per the spec any other unit is interpreted
+        // as minutes too
+        _ => Some(value * 60),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PeriodicTauTimerSettings {
+    // the range of T3412 values considered normal
+    pub t3412_min_secs: u64,
+    pub t3412_max_secs: u64,
+}
+
+impl Default for PeriodicTauTimerSettings {
+    fn default() -> Self {
+        Self {
+            t3412_min_secs: 2 * 60,
+            t3412_max_secs: 12 * 60 * 60,
+        }
+    }
+}
+
+impl AnalyzerSettings for PeriodicTauTimerSettings {
+    const KEY: &'static str = "periodic_tau_timer";
+}
+
+// A catcher may set a very short periodic TAU timer (T3412) to keep the UE
+// chatty and easy to track, or a very long (or deactivated) one so the UE
+// doesn't check back in with the real network.
+pub struct PeriodicTauTimerAnalyzer {
+    packet_num: usize,
+    min_secs: u64,
+    max_secs: u64,
+}
+
+impl PeriodicTauTimerAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: PeriodicTauTimerSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            min_secs: settings.t3412_min_secs,
+            max_secs: settings.t3412_max_secs,
+        }
+    }
+
+    fn check_t3412(&self, procedure: &str, unit: u8, value: u8) -> Option<Event> {
+        let (severity, observed) = match decode_gprs_timer(unit, value) {
+            Some(secs) if secs < self.min_secs => (Severity::Medium, format!("{secs}s")),
+            Some(secs) if secs > self.max_secs => (Severity::Low, format!("{secs}s")),
+            Some(_) => return None,
+            None => (Severity::Low, "deactivated".to_string()),
+        };
+        Some(Event {
+            event_type: EventType::QualitativeWarning { severity },
+            message: format!(
+                "{procedure} set T3412 (periodic TAU timer) to {observed}, outside of the expected {}s-{}s (frame {})",
+                self.min_secs, self.max_secs, self.packet_num
+            ),
+            explanation: "A very short periodic TAU timer keeps the UE signalling often, which makes it easier to track, while a very long or deactivated one keeps it from checking back in with the real network.".to_string(),
+            evidence: vec![
+                ("procedure".to_string(), procedure.to_string()),
+                ("t3412_unit".to_string(), unit.to_string()),
+                ("t3412_value".to_string(), value.to_string()),
+                ("t3412".to_string(), observed),
+            ],
+        })
+    }
+}
+
+impl Analyzer for PeriodicTauTimerAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Abnormal Periodic TAU Timer")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether an Attach Accept or Tracking Area Update Accept sets the periodic TAU timer (T3412) outside of the configured range.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_NAS_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        let LteInformationElement::NAS(NASMessage::EMMMessage(emm)) = &**inner else {
+            return None;
+        };
+        match emm {
+            EMMMessage::EMMAttachAccept(accept) => {
+                let timer = &accept.t3412.inner;
+                self.check_t3412("Attach Accept", timer.unit as u8, timer.value as u8)
+            }
+            // T3412 is optional in a TAU accept, in which case the UE keeps
+            // using its current value
+            EMMMessage::EMMTrackingAreaUpdateAccept(accept) => {
+                let timer = &accept.t3412.as_ref()?.inner;
+                self.check_t3412(
+                    "Tracking Area Update Accept",
+                    timer.unit as u8,
+                    timer.value as u8,
+                )
+            }
+            _ => None,
+        }
+    }
+}
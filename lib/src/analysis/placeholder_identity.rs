@@ -0,0 +1,146 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use telcom_parser::lte_rrc::SystemInformationBlockType1;
+
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
+use super::information_element::InformationElement;
+use super::util::{CellGlobalIdentity, advertised_plmns, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+// MCCs no real network broadcasts: 000 isn't allocated, 001 is for test
+// networks (001-01 is the default PLMN of most open source and lab base
+// station software), and 999 is for private internal networks
+const TEST_MCCS: &[&str] = &["000", "001", "999"];
+
+// The largest 28-bit cell identity
+const MAX_CELL_IDENTITY: u32 = (1 << 28) - 1;
+
+// TACs TS 23.003 reserves: 0000 is for when the UE has no valid tracking
+// area, and FFFE for when it's been deleted
+const RESERVED_TACS: &[u32] = &[0x0000, 0xfffe];
+
+// The parts of a cell's identity that aren't something a real deployment
+// would broadcast, and whether any of them is a test PLMN
+fn placeholder_values(
+    cell: &CellGlobalIdentity,
+    plmns: &[String],
+) -> Option<(Vec<(String, String)>, bool)> {
+    let mut values = Vec::new();
+    let test_plmns: Vec<&str> = plmns
+        .iter()
+        .filter(|plmn| {
+            let mcc = plmn.split('-').next().unwrap_or_default();
+            TEST_MCCS.contains(&mcc)
+        })
+        .map(String::as_str)
+        .collect();
+    if !test_plmns.is_empty() {
+        values.push(("test_plmns".to_string(), test_plmns.join(", ")));
+    }
+    if RESERVED_TACS.contains(&cell.tracking_area_code) {
+        values.push((
+            "reserved_tracking_area_code".to_string(),
+            format!("{:#06x}", cell.tracking_area_code),
+        ));
+    }
+    if cell.cell_identity == 0 || cell.cell_identity == MAX_CELL_IDENTITY {
+        values.push((
+            "placeholder_cell_identity".to_string(),
+            format!("{:#09x}", cell.cell_identity),
+        ));
+    }
+    (!values.is_empty()).then_some((values, !test_plmns.is_empty()))
+}
+
+// Real cells are given a PLMN the operator holds, a tracking area code from
+// the operator's plan, and a cell identity made of the eNodeB's ID and the
+// cell's number on it. A fake base station set up in a hurry tends to keep
+// whatever its software defaults to instead: a test PLMN like 001-01, a cell
+// identity of 0, or a TAC that's reserved and never assigned. Test PLMNs are
+// the stronger sign, since a real network can't broadcast one, so they're
+// reported with a higher severity than a value that's merely unusual. Each
+// cell is reported once.
+pub struct PlaceholderIdentityAnalyzer {
+    packet_num: usize,
+    reported: HashSet<CellGlobalIdentity>,
+}
+
+impl Default for PlaceholderIdentityAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PlaceholderIdentityAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            packet_num: 0,
+            reported: HashSet::new(),
+        }
+    }
+
+    fn handle_sib1(&mut self, sib1: &SystemInformationBlockType1) -> Option<Event> {
+        let cell = CellGlobalIdentity::from_sib1(sib1)?;
+        let plmns = advertised_plmns(sib1);
+        let (values, test_plmn) = placeholder_values(&cell, &plmns)?;
+        if !self.reported.insert(cell.clone()) {
+            return None;
+        }
+        let described: Vec<String> = values
+            .iter()
+            .map(|(field, value)| format!("{} {value}", field.replace('_', " ")))
+            .collect();
+        let mut evidence = vec![
+            ("cell".to_string(), cell.to_string()),
+            ("advertised_plmns".to_string(), plmns.join(", ")),
+        ];
+        evidence.extend(values);
+        evidence.push(("frame".to_string(), self.packet_num.to_string()));
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: if test_plmn {
+                    Severity::High
+                } else {
+                    Severity::Medium
+                },
+            },
+            message: format!(
+                "Cell broadcasts placeholder identity values: {}",
+                described.join(", ")
+            ),
+            explanation: "The cell identifies itself with values a real network wouldn't use: a test PLMN, a reserved tracking area code, or a cell ID of all zeroes or all ones. These are what base station software defaults to, so they suggest a fake base station that was set up without the care to copy a real cell's identity.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for PlaceholderIdentityAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Placeholder Cell Identity")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether a cell's SIB1 advertises a test PLMN (MCC 000, 001 or 999), a reserved tracking area code (0x0000 or 0xFFFE), or a cell identity of all zeroes or all ones, none of which a real deployment would use.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let sib1 = unpack_sib1(ie)?;
+        self.handle_sib1(sib1)
+    }
+}
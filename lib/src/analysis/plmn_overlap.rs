@@ -0,0 +1,192 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::SystemInformationBlockType1;
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::InformationElement;
+use super::util::{CellGlobalIdentity, advertised_plmns, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlmnOverlapSettings {
+    // how soon after leaving a PLMN the UE can be served by it again before
+    // the two look like they were serving it at the same time
+    pub window_secs: u64,
+}
+
+impl Default for PlmnOverlapSettings {
+    fn default() -> Self {
+        Self { window_secs: 10 }
+    }
+}
+
+impl AnalyzerSettings for PlmnOverlapSettings {
+    const KEY: &'static str = "plmn_overlap";
+}
+
+// A stretch of the capture during which the serving cells all shared a PLMN
+struct Serving {
+    plmns: Vec<String>,
+    cell: Option<CellGlobalIdentity>,
+    first_seen: DateTime<FixedOffset>,
+    last_seen: DateTime<FixedOffset>,
+}
+
+impl Serving {
+    fn shares_plmn(&self, plmns: &[String]) -> bool {
+        self.plmns.iter().any(|plmn| plmns.contains(plmn))
+    }
+}
+
+// A UE is served by one PLMN at a time. It can move to another, when it
+// roams or its network hands it off to a partner, but moving takes a
+// reselection or a handover and usually a registration, so it doesn't move
+// back and forth within seconds. A capture in which the serving cell's PLMN
+// goes from one to another and straight back again looks like two networks
+// serving the UE at once, which happens when something is overlaying the
+// real network, or when the capture itself is corrupt or was merged from
+// two devices' captures.
+//
+// Serving cells are taken from their SIB1s, and cells that advertise a PLMN
+// in common (like shared cells, which advertise several) count as the same
+// network. A UE can also read a neighbor's SIB1 when the network asks it to
+// report the neighbor's identity, which looks the same, so this is only
+// ever Low. Each pair of networks is reported at most once.
+pub struct PlmnOverlapAnalyzer {
+    packet_num: usize,
+    window: TimeDelta,
+    current: Option<Serving>,
+    // what was serving before the current PLMN took over
+    previous: Option<Serving>,
+    reported: HashSet<(String, String)>,
+}
+
+impl PlmnOverlapAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: PlmnOverlapSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            window: TimeDelta::try_seconds(i64::try_from(settings.window_secs).unwrap_or(i64::MAX))
+                .unwrap_or(TimeDelta::MAX),
+            current: None,
+            previous: None,
+            reported: HashSet::new(),
+        }
+    }
+
+    fn handle_sib1(
+        &mut self,
+        sib1: &SystemInformationBlockType1,
+        timestamp: DateTime<FixedOffset>,
+    ) -> Option<Event> {
+        let plmns = advertised_plmns(sib1);
+        if plmns.is_empty() {
+            return None;
+        }
+        let cell = CellGlobalIdentity::from_sib1(sib1);
+        let current = self
+            .current
+            .as_mut()
+            .filter(|current| current.shares_plmn(&plmns));
+        if let Some(current) = current {
+            current.last_seen = current.last_seen.max(timestamp);
+            current.cell = cell;
+            return None;
+        }
+        let serving = Serving {
+            plmns,
+            cell,
+            first_seen: timestamp,
+            last_seen: timestamp,
+        };
+        // the PLMN that served before the one that's just been replaced,
+        // if it's the one that's back again
+        let returned = self.previous.take().filter(|previous| {
+            previous.shares_plmn(&serving.plmns) && timestamp - previous.last_seen <= self.window
+        });
+        self.previous = self.current.replace(serving);
+        let (returned, other, back) = (returned?, self.previous.as_ref()?, self.current.as_ref()?);
+
+        let (plmn, other_plmn) = (&returned.plmns[0], &other.plmns[0]);
+        let pair = if plmn < other_plmn {
+            (plmn.clone(), other_plmn.clone())
+        } else {
+            (other_plmn.clone(), plmn.clone())
+        };
+        if !self.reported.insert(pair) {
+            return None;
+        }
+        let gap = (timestamp - returned.last_seen).num_seconds();
+        let mut evidence = vec![
+            ("plmns".to_string(), returned.plmns.join(", ")),
+            ("other_plmns".to_string(), other.plmns.join(", ")),
+        ];
+        if let Some(cell) = &back.cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        if let Some(cell) = &other.cell {
+            evidence.push(("other_cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("left_at".to_string(), returned.last_seen.to_rfc3339()),
+            (
+                "other_first_seen".to_string(),
+                other.first_seen.to_rfc3339(),
+            ),
+            ("other_last_seen".to_string(), other.last_seen.to_rfc3339()),
+            ("returned_at".to_string(), timestamp.to_rfc3339()),
+            ("gap_secs".to_string(), gap.to_string()),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Low,
+            },
+            message: format!(
+                "UE went from PLMN {plmn} to PLMN {other_plmn} and back again within {gap}s"
+            ),
+            explanation: "The PLMN serving the UE changed to another and straight back again, faster than the UE could really move between networks. A UE is only served by one network at a time, so this suggests something overlaying the real network, like a fake base station, or a capture that's corrupt or was merged from more than one device.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for PlmnOverlapAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Overlapping PLMNs")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the UE is served by cells of one PLMN, then another, then the first again, with less than window_secs between leaving the first and coming back to it. Cells that advertise a PLMN in common count as the same network.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let sib1 = unpack_sib1(ie)?;
+        self.handle_sib1(sib1, ctx.timestamp?)
+    }
+}
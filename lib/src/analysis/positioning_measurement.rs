@@ -0,0 +1,278 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_DCCH_MessageType, DL_DCCH_MessageType_c1, EstablishmentCause,
+    LoggedMeasurementConfiguration_r10CriticalExtensions,
+    LoggedMeasurementConfiguration_r10CriticalExtensions_c1, MeasConfig,
+    RRCConnectionReconfigurationCriticalExtensions,
+    RRCConnectionReconfigurationCriticalExtensions_c1, RRCConnectionRequestCriticalExtensions,
+    ReportConfigEUTRAReportAmount, ReportConfigEUTRATriggerType,
+    ReportConfigEUTRATriggerType_periodicalPurpose, ReportConfigInterRATReportAmount,
+    ReportConfigInterRATTriggerType, ReportConfigInterRATTriggerType_periodicalPurpose,
+    ReportConfigToAddModReportConfig, UL_CCCH_MessageType, UL_CCCH_MessageType_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+// reportInterval, per 36.331
+const REPORT_INTERVALS: [&str; 13] = [
+    "120ms", "240ms", "480ms", "640ms", "1024ms", "2048ms", "5120ms", "10240ms", "1min", "6min",
+    "12min", "30min", "60min",
+];
+const LOGGING_DURATIONS: [&str; 6] = ["10min", "20min", "40min", "60min", "90min", "120min"];
+const LOGGING_INTERVALS: [&str; 8] = [
+    "1280ms", "2560ms", "5120ms", "10240ms", "20480ms", "30720ms", "40960ms", "61440ms",
+];
+
+fn describe(names: &[&str], index: u8) -> String {
+    match names.get(usize::from(index)) {
+        Some(name) => name.to_string(),
+        None => format!("spare ({index})"),
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PositioningMeasurementSettings {
+    // report as a warning of this severity rather than informationally
+    pub warning_severity: Option<Severity>,
+}
+
+impl AnalyzerSettings for PositioningMeasurementSettings {
+    const KEY: &'static str = "positioning_measurement";
+}
+
+// A measurement configuration that's useful for locating the UE
+struct Positioning {
+    method: &'static str,
+    details: Vec<(String, String)>,
+}
+
+// Periodic reports of the strongest cells, with no end to them, give the
+// network a running E-CID position fix: the serving and neighbouring cells'
+// signal strengths, every report_interval, for as long as the connection lasts
+fn find_periodic_reporting(meas_config: &MeasConfig) -> Option<Positioning> {
+    meas_config
+        .report_config_to_add_mod_list
+        .as_ref()?
+        .0
+        .iter()
+        .find_map(|report_config| {
+            let (rat, max_report_cells, report_interval) = match &report_config.report_config {
+                ReportConfigToAddModReportConfig::ReportConfigEUTRA(eutra) => {
+                    let ReportConfigEUTRATriggerType::Periodical(periodical) = &eutra.trigger_type
+                    else {
+                        return None;
+                    };
+                    if periodical.purpose.0
+                        != ReportConfigEUTRATriggerType_periodicalPurpose::REPORT_STRONGEST_CELLS
+                        || eutra.report_amount.0 != ReportConfigEUTRAReportAmount::INFINITY
+                    {
+                        return None;
+                    }
+                    ("LTE", eutra.max_report_cells.0, eutra.report_interval.0)
+                }
+                ReportConfigToAddModReportConfig::ReportConfigInterRAT(inter_rat) => {
+                    let ReportConfigInterRATTriggerType::Periodical(periodical) =
+                        &inter_rat.trigger_type
+                    else {
+                        return None;
+                    };
+                    if periodical.purpose.0
+                        != ReportConfigInterRATTriggerType_periodicalPurpose::REPORT_STRONGEST_CELLS
+                        || inter_rat.report_amount.0 != ReportConfigInterRATReportAmount::INFINITY
+                    {
+                        return None;
+                    }
+                    (
+                        "inter-RAT",
+                        inter_rat.max_report_cells.0,
+                        inter_rat.report_interval.0,
+                    )
+                }
+            };
+            Some(Positioning {
+                method: "E-CID (unlimited periodic strongest cell reports)",
+                details: vec![
+                    (
+                        "report_config_id".to_string(),
+                        report_config.report_config_id.0.to_string(),
+                    ),
+                    ("rat".to_string(), rat.to_string()),
+                    (
+                        "report_interval".to_string(),
+                        describe(&REPORT_INTERVALS, report_interval),
+                    ),
+                    ("max_report_cells".to_string(), max_report_cells.to_string()),
+                ],
+            })
+        })
+}
+
+// RRC can't start OTDOA or GNSS positioning itself (that's LPP, which runs
+// over NAS), but it can configure measurements that locate the UE just as
+// well: unlimited periodic reports of the strongest cells it hears, which
+// give an E-CID fix every few hundred milliseconds, or logged measurements
+// for minimization of drive tests (MDT), which the UE records along with its
+// GNSS location where it has one. Networks use both for optimization and for
+// E911, so this is informational unless configured otherwise, and
+// configurations on connections set up for emergency calls aren't reported
+// at all.
+//
+// Emergency connections are recognized by their establishment cause, so an
+// emergency call placed on a connection that was already up isn't. Each
+// method is reported once per connection.
+pub struct PositioningMeasurementAnalyzer {
+    packet_num: usize,
+    event_type: EventType,
+    serving_cell: Option<CellGlobalIdentity>,
+    emergency_connection: bool,
+    reported: HashSet<&'static str>,
+}
+
+impl PositioningMeasurementAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: PositioningMeasurementSettings = analyzer_config.get_settings();
+        let event_type = match settings.warning_severity {
+            Some(severity) => EventType::QualitativeWarning { severity },
+            None => EventType::Informational,
+        };
+        Self {
+            packet_num: 0,
+            event_type,
+            serving_cell: None,
+            emergency_connection: false,
+            reported: HashSet::new(),
+        }
+    }
+
+    fn report(&mut self, positioning: Positioning) -> Option<Event> {
+        if self.emergency_connection || !self.reported.insert(positioning.method) {
+            return None;
+        }
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.push(("method".to_string(), positioning.method.to_string()));
+        evidence.extend(positioning.details);
+        evidence.push(("frame".to_string(), self.packet_num.to_string()));
+        Some(Event {
+            event_type: self.event_type.clone(),
+            message: format!(
+                "Network configured measurements usable for locating the UE outside an emergency call: {}",
+                positioning.method
+            ),
+            explanation: "The network set the UE up to keep reporting measurements that reveal where it is. Networks do this to locate emergency callers and to tune their coverage, but this connection wasn't set up for an emergency call, and the same measurements let the network, or a fake base station, track the UE's location.".to_string(),
+            evidence,
+        })
+    }
+
+    fn handle_dl_dcch(&mut self, message: &DL_DCCH_MessageType_c1) -> Option<Event> {
+        match message {
+            DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(reconfiguration) => {
+                let RRCConnectionReconfigurationCriticalExtensions::C1(
+                    RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(r8),
+                ) = &reconfiguration.critical_extensions
+                else {
+                    return None;
+                };
+                let positioning = find_periodic_reporting(r8.meas_config.as_ref()?)?;
+                self.report(positioning)
+            }
+            DL_DCCH_MessageType_c1::LoggedMeasurementConfiguration_r10(logged) => {
+                let LoggedMeasurementConfiguration_r10CriticalExtensions::C1(
+                    LoggedMeasurementConfiguration_r10CriticalExtensions_c1::LoggedMeasurementConfiguration_r10(ies),
+                ) = &logged.critical_extensions
+                else {
+                    return None;
+                };
+                self.report(Positioning {
+                    method: "MDT logged measurements",
+                    details: vec![
+                        (
+                            "logging_duration".to_string(),
+                            describe(&LOGGING_DURATIONS, ies.logging_duration_r10.0),
+                        ),
+                        (
+                            "logging_interval".to_string(),
+                            describe(&LOGGING_INTERVALS, ies.logging_interval_r10.0),
+                        ),
+                    ],
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Analyzer for PositioningMeasurementAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Positioning Measurement Request")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the network configures measurements that can locate the UE (unlimited periodic strongest cell reports for E-CID, or MDT logged measurements) on a connection that wasn't set up for an emergency call. Informational unless warning_severity is set, since E911 and network optimization use these too.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::UlCcch(msg_cont) => {
+                // re-establishments carry on with the same connection, so
+                // only new connections start over
+                if let UL_CCCH_MessageType::C1(UL_CCCH_MessageType_c1::RrcConnectionRequest(
+                    request,
+                )) = &msg_cont.message
+                {
+                    self.emergency_connection = matches!(
+                        &request.critical_extensions,
+                        RRCConnectionRequestCriticalExtensions::RrcConnectionRequest_r8(r8)
+                            if r8.establishment_cause.0 == EstablishmentCause::EMERGENCY
+                    );
+                    self.reported.clear();
+                }
+                None
+            }
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(c1) = &msg_cont.message else {
+                    return None;
+                };
+                self.handle_dl_dcch(c1)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use std::borrow::Cow;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_CCCH_MessageType, DL_CCCH_MessageType_c1, DL_DCCH_MessageType, DL_DCCH_MessageType_c1,
+    RRCConnectionReconfigurationCriticalExtensions,
+    RRCConnectionReconfigurationCriticalExtensions_c1, RRCConnectionSetupCriticalExtensions,
+    RRCConnectionSetupCriticalExtensions_c1, RadioResourceConfigDedicated,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1, unpack_sib2};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PowerControlSettings {
+    // the PUSCH target received power (p0-NominalPUSCH plus p0-UE-PUSCH) at or
+    // above which the UE ends up transmitting at or near full power wherever
+    // it is. Cells normally aim for somewhere around -80 to -100dBm.
+    pub min_target_dbm: i16,
+    // how long the UE has to be held at that target to be reported
+    pub min_duration_secs: u64,
+    // findings are informational unless this is set
+    pub severity: Option<Severity>,
+}
+
+impl Default for PowerControlSettings {
+    fn default() -> Self {
+        Self {
+            min_target_dbm: -60,
+            min_duration_secs: 60,
+            severity: None,
+        }
+    }
+}
+
+impl AnalyzerSettings for PowerControlSettings {
+    const KEY: &'static str = "power_control";
+}
+
+// Per 36.331, a connection that's never given uplinkPowerControlDedicated uses
+// a p0-UE-PUSCH of 0dB
+const DEFAULT_P0_UE_PUSCH: i8 = 0;
+
+fn p0_ue_pusch(config: &RadioResourceConfigDedicated) -> Option<i8> {
+    let physical = config.physical_config_dedicated.as_ref()?;
+    let power_control = physical.uplink_power_control_dedicated.as_ref()?;
+    Some(power_control.p0_ue_pusch.0)
+}
+
+struct FullPower {
+    since: DateTime<FixedOffset>,
+    frame: usize,
+    target_dbm: i16,
+    p0_nominal_pusch: i8,
+    p0_ue_pusch: i8,
+}
+
+// The UE sets its uplink power to reach the target received power the cell
+// configures, turning it up by the estimated path loss, and only stops at its
+// maximum. A cell that sets an unreasonably high target keeps every UE on it
+// transmitting at full power for as long as they're connected, which drains
+// their batteries and makes them easier to locate by their signal.
+//
+// The transmit power control commands that fine-tune this are sent on the
+// PDCCH, which isn't logged, so this goes by the targets configured over RRC:
+// the cell's p0-NominalPUSCH from SIB2 plus the UE's own p0-UE-PUSCH from
+// its connection setup or reconfigurations. Nothing is checked until the
+// serving cell's SIB2 has been seen, and each episode is reported once it
+// ends, along with how long it lasted.
+pub struct PowerControlAnalyzer {
+    packet_num: usize,
+    min_target_dbm: i16,
+    min_duration: TimeDelta,
+    severity: Option<Severity>,
+    serving_cell: Option<CellGlobalIdentity>,
+    p0_nominal_pusch: Option<i8>,
+    // the UE's p0-UE-PUSCH while it's connected
+    connection: Option<i8>,
+    full_power: Option<FullPower>,
+}
+
+impl PowerControlAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: PowerControlSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            min_target_dbm: settings.min_target_dbm,
+            min_duration: TimeDelta::try_seconds(
+                i64::try_from(settings.min_duration_secs).unwrap_or(i64::MAX),
+            )
+            .unwrap_or(TimeDelta::MAX),
+            severity: settings.severity,
+            serving_cell: None,
+            p0_nominal_pusch: None,
+            connection: None,
+            full_power: None,
+        }
+    }
+
+    // Re-evaluates the UE's target after its configuration changed, returning
+    // a finding if that ended a long enough stretch at full power
+    fn update(&mut self, now: Option<DateTime<FixedOffset>>) -> Option<Event> {
+        let target = match (self.p0_nominal_pusch, self.connection) {
+            (Some(nominal), Some(ue)) => Some((nominal, ue, i16::from(nominal) + i16::from(ue))),
+            _ => None,
+        };
+        match target {
+            Some((nominal, ue, target_dbm)) if target_dbm >= self.min_target_dbm => {
+                if self.full_power.is_none() {
+                    self.full_power = Some(FullPower {
+                        since: now?,
+                        frame: self.packet_num,
+                        target_dbm,
+                        p0_nominal_pusch: nominal,
+                        p0_ue_pusch: ue,
+                    });
+                }
+                None
+            }
+            _ => {
+                let full_power = self.full_power.take()?;
+                self.report(full_power, now?)
+            }
+        }
+    }
+
+    fn report(&self, full_power: FullPower, now: DateTime<FixedOffset>) -> Option<Event> {
+        let duration = now - full_power.since;
+        if duration < self.min_duration {
+            return None;
+        }
+        let duration_secs = duration.num_seconds();
+        let event_type = match self.severity {
+            Some(severity) => EventType::QualitativeWarning { severity },
+            None => EventType::Informational,
+        };
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            (
+                "target_received_power_dbm".to_string(),
+                full_power.target_dbm.to_string(),
+            ),
+            (
+                "p0_nominal_pusch_dbm".to_string(),
+                full_power.p0_nominal_pusch.to_string(),
+            ),
+            (
+                "p0_ue_pusch_db".to_string(),
+                full_power.p0_ue_pusch.to_string(),
+            ),
+            ("duration_secs".to_string(), duration_secs.to_string()),
+            ("start_frame".to_string(), full_power.frame.to_string()),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        Some(Event {
+            event_type,
+            message: format!(
+                "UE was made to transmit at full power for {duration_secs}s (PUSCH target {}dBm)",
+                full_power.target_dbm
+            ),
+            explanation: "The cell configured an uplink target power so high that the UE had to transmit at or near its maximum power the whole time it was connected. That drains the battery and makes the UE easier to locate by its signal. A cell struggling with interference may raise its target too, though rarely this far.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for PowerControlAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Forced Full Power")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the cell's PUSCH power control targets (p0-NominalPUSCH plus p0-UE-PUSCH) are at least min_target_dbm for min_duration_secs or longer, which keeps the UE transmitting at full power.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            let cell = CellGlobalIdentity::from_sib1(sib1);
+            if cell != self.serving_cell {
+                // the new cell's SIB2 hasn't been seen yet
+                self.serving_cell = cell;
+                self.p0_nominal_pusch = None;
+                return self.update(ctx.timestamp);
+            }
+            return None;
+        }
+        if let Some(sib2) = unpack_sib2(ie) {
+            let power_control = &sib2
+                .radio_resource_config_common
+                .uplink_power_control_common;
+            self.p0_nominal_pusch = Some(power_control.p0_nominal_pusch.0);
+            return self.update(ctx.timestamp);
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::DlCcch(msg_cont) => {
+                let DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionSetup(setup)) =
+                    &msg_cont.message
+                else {
+                    return None;
+                };
+                let RRCConnectionSetupCriticalExtensions::C1(
+                    RRCConnectionSetupCriticalExtensions_c1::RrcConnectionSetup_r8(r8),
+                ) = &setup.critical_extensions
+                else {
+                    return None;
+                };
+                self.connection = Some(
+                    p0_ue_pusch(&r8.radio_resource_config_dedicated).unwrap_or(DEFAULT_P0_UE_PUSCH),
+                );
+                self.update(ctx.timestamp)
+            }
+            LteInformationElement::DlDcch(msg_cont) => match &msg_cont.message {
+                DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(
+                    reconfiguration,
+                )) => {
+                    let RRCConnectionReconfigurationCriticalExtensions::C1(
+                        RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(r8),
+                    ) = &reconfiguration.critical_extensions
+                    else {
+                        return None;
+                    };
+                    let p0_ue_pusch = r8
+                        .radio_resource_config_dedicated
+                        .as_ref()
+                        .and_then(p0_ue_pusch)?;
+                    self.connection = Some(p0_ue_pusch);
+                    self.update(ctx.timestamp)
+                }
+                DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionRelease(_)) => {
+                    self.connection = None;
+                    self.update(ctx.timestamp)
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
@@ -1,13 +1,31 @@
 use std::borrow::Cow;
 
-use super::analyzer::{Analyzer, Event, EventType, Severity};
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
 use super::information_element::{InformationElement, LteInformationElement};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
 use telcom_parser::lte_rrc::{
     BCCH_DL_SCH_MessageType, BCCH_DL_SCH_MessageType_c1, CellReselectionPriority,
     SystemInformation_r8_IEsSib_TypeAndInfo, SystemInformation_r8_IEsSib_TypeAndInfo_Entry,
     SystemInformationBlockType7, SystemInformationCriticalExtensions,
 };
 
+fn priority_0_event(generation: &str, rat: &str, carrier: String) -> Event {
+    Event {
+        event_type: EventType::QualitativeWarning {
+            severity: Severity::High,
+        },
+        message: format!("LTE cell advertised a {generation} cell for priority 0 reselection"),
+        explanation: format!(
+            "The cell's SIB lists a {rat} carrier with cell reselection priority 0. Advertising {generation} neighbours this way can be used to steer idle UEs off of LTE and onto a network without mutual authentication."
+        ),
+        evidence: vec![
+            ("RAT".to_string(), rat.to_string()),
+            ("carrier".to_string(), carrier),
+            ("cell_reselection_priority".to_string(), "0".to_string()),
+        ],
+    }
+}
+
 /// Based on heuristic T7 from Shinjo Park's "Why We Cannot Win".
 pub struct LteSib6And7DowngradeAnalyzer {}
 
@@ -50,9 +68,14 @@ impl Analyzer for LteSib6And7DowngradeAnalyzer {
         1
     }
 
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
     fn analyze_information_element(
         &mut self,
         ie: &InformationElement,
+        _ctx: &PacketContext,
     ) -> Option<super::analyzer::Event> {
         let sibs = &self.unpack_system_information(ie)?.0;
         for sib in sibs {
@@ -64,10 +87,11 @@ impl Analyzer for LteSib6And7DowngradeAnalyzer {
                                 carrier_info.cell_reselection_priority
                             {
                                 if p == 0 {
-                                    return Some(Event {
-                                        event_type: EventType::QualitativeWarning { severity: Severity::High },
-                                        message: "LTE cell advertised a 3G cell for priority 0 reselection".to_string(),
-                                    });
+                                    return Some(priority_0_event(
+                                        "3G",
+                                        "UTRA FDD",
+                                        format!("{:?}", carrier_info.carrier_freq),
+                                    ));
                                 }
                             }
                         }
@@ -78,10 +102,11 @@ impl Analyzer for LteSib6And7DowngradeAnalyzer {
                                 carrier_info.cell_reselection_priority
                             {
                                 if p == 0 {
-                                    return Some(Event {
-                                        event_type: EventType::QualitativeWarning { severity: Severity::High },
-                                        message: "LTE cell advertised a 3G cell for priority 0 reselection".to_string(),
-                                    });
+                                    return Some(priority_0_event(
+                                        "3G",
+                                        "UTRA TDD",
+                                        format!("{:?}", carrier_info.carrier_freq),
+                                    ));
                                 }
                             }
                         }
@@ -98,14 +123,11 @@ impl Analyzer for LteSib6And7DowngradeAnalyzer {
                             carrier_info.common_info.cell_reselection_priority
                         {
                             if p == 0 {
-                                return Some(Event {
-                                    event_type: EventType::QualitativeWarning {
-                                        severity: Severity::High,
-                                    },
-                                    message:
-                                        "LTE cell advertised a 2G cell for priority 0 reselection"
-                                            .to_string(),
-                                });
+                                return Some(priority_0_event(
+                                    "2G",
+                                    "GERAN",
+                                    format!("{:?}", carrier_info.carrier_freqs),
+                                ));
                             }
                         }
                     }
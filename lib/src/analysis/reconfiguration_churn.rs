@@ -0,0 +1,246 @@
+use std::borrow::Cow;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_CCCH_MessageType, DL_CCCH_MessageType_c1, DL_DCCH_MessageType, DL_DCCH_MessageType_c1,
+    RRCConnectionReconfigurationCriticalExtensions,
+    RRCConnectionReconfigurationCriticalExtensions_c1, RRCConnectionSetupCriticalExtensions,
+    RRCConnectionSetupCriticalExtensions_c1, RadioResourceConfigDedicated,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconfigurationChurnSettings {
+    // how many reconfigurations a connection without a data bearer can get
+    pub max_reconfigurations: usize,
+    // how long a connection has to have lasted before it's checked, so the
+    // reconfigurations that set a connection up aren't counted against it
+    pub min_session_secs: u64,
+}
+
+impl Default for ReconfigurationChurnSettings {
+    fn default() -> Self {
+        Self {
+            max_reconfigurations: 10,
+            min_session_secs: 60,
+        }
+    }
+}
+
+impl AnalyzerSettings for ReconfigurationChurnSettings {
+    const KEY: &'static str = "reconfiguration_churn";
+}
+
+fn drbs_added(config: &RadioResourceConfigDedicated) -> usize {
+    config
+        .drb_to_add_mod_list
+        .as_ref()
+        .map(|list| list.0.len())
+        .unwrap_or_default()
+}
+
+struct Connection {
+    started: DateTime<FixedOffset>,
+    start_frame: usize,
+    reconfigurations: usize,
+    // how many data radio bearers have been added to the connection
+    drbs: usize,
+    reported: bool,
+}
+
+// A connection the network keeps reconfiguring without ever giving it a
+// data radio bearer (DRB) can't be carrying the user's data, so something
+// other than the user is keeping it busy: a cell probing the UE, or keeping
+// it connected so it can be followed. Rayhunter doesn't capture user plane
+// traffic, so the DRBs are the data activity there is to go on: a connection
+// that has one may or may not be moving data, but one without can't be.
+//
+// Handovers are reconfigurations too, but they're left to handover_ping_pong
+// and not counted here. Connections are checked once they've lasted
+// min_session_secs, and each is reported at most once. The connection that's
+// already going when the capture starts isn't checked, since whether it was
+// given a DRB before then isn't known.
+pub struct ReconfigurationChurnAnalyzer {
+    packet_num: usize,
+    max_reconfigurations: usize,
+    min_session: TimeDelta,
+    serving_cell: Option<CellGlobalIdentity>,
+    connection: Option<Connection>,
+}
+
+impl ReconfigurationChurnAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: ReconfigurationChurnSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            max_reconfigurations: settings.max_reconfigurations,
+            min_session: TimeDelta::try_seconds(
+                i64::try_from(settings.min_session_secs).unwrap_or(i64::MAX),
+            )
+            .unwrap_or(TimeDelta::MAX),
+            serving_cell: None,
+            connection: None,
+        }
+    }
+
+    fn handle_reconfiguration(
+        &mut self,
+        drbs: usize,
+        timestamp: DateTime<FixedOffset>,
+    ) -> Option<Event> {
+        let connection = self.connection.as_mut()?;
+        connection.reconfigurations += 1;
+        connection.drbs += drbs;
+        let session = timestamp - connection.started;
+        if connection.reported
+            || connection.drbs > 0
+            || session < self.min_session
+            || connection.reconfigurations <= self.max_reconfigurations
+        {
+            return None;
+        }
+
+        connection.reported = true;
+        let reconfigurations = connection.reconfigurations;
+        let start_frame = connection.start_frame;
+        let session_secs = session.num_seconds();
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("reconfigurations".to_string(), reconfigurations.to_string()),
+            ("data_bearers".to_string(), "0".to_string()),
+            ("session_secs".to_string(), session_secs.to_string()),
+            (
+                "max_reconfigurations".to_string(),
+                self.max_reconfigurations.to_string(),
+            ),
+            (
+                "connection_start_frame".to_string(),
+                start_frame.to_string(),
+            ),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Low,
+            },
+            message: format!(
+                "Network reconfigured a connection without a data bearer {reconfigurations} times in {session_secs}s (max {})",
+                self.max_reconfigurations
+            ),
+            explanation: "The network kept changing the settings of the UE's connection, but never set it up to carry any data. A busy or misbehaving cell might do this, but it's also how a fake base station can keep a UE connected to probe it or follow where it goes.".to_string(),
+            evidence,
+        })
+    }
+
+    fn handle_dl_dcch(
+        &mut self,
+        message: &DL_DCCH_MessageType_c1,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        match message {
+            DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(reconfiguration) => {
+                let RRCConnectionReconfigurationCriticalExtensions::C1(
+                    RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(r8),
+                ) = &reconfiguration.critical_extensions
+                else {
+                    return None;
+                };
+                if r8.mobility_control_info.is_some() {
+                    return None;
+                }
+                let drbs = r8
+                    .radio_resource_config_dedicated
+                    .as_ref()
+                    .map(drbs_added)
+                    .unwrap_or_default();
+                self.handle_reconfiguration(drbs, ctx.timestamp?)
+            }
+            DL_DCCH_MessageType_c1::RrcConnectionRelease(_) => {
+                self.connection = None;
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Analyzer for ReconfigurationChurnAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Reconfiguration Churn")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the network reconfigures a connection more than max_reconfigurations times, not counting handovers, without ever setting up a data radio bearer on it, once the connection has lasted min_session_secs. User plane traffic isn't captured, so a connection without a data bearer is the only kind known to carry no data.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::DlCcch(msg_cont) => {
+                let DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionSetup(setup)) =
+                    &msg_cont.message
+                else {
+                    return None;
+                };
+                let RRCConnectionSetupCriticalExtensions::C1(
+                    RRCConnectionSetupCriticalExtensions_c1::RrcConnectionSetup_r8(r8),
+                ) = &setup.critical_extensions
+                else {
+                    return None;
+                };
+                let start_frame = self.packet_num;
+                self.connection = ctx.timestamp.map(|started| Connection {
+                    started,
+                    start_frame,
+                    reconfigurations: 0,
+                    drbs: drbs_added(&r8.radio_resource_config_dedicated),
+                    reported: false,
+                });
+                None
+            }
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(c1) = &msg_cont.message else {
+                    return None;
+                };
+                self.handle_dl_dcch(c1, ctx)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, VecDeque};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    RRCConnectionReestablishmentRequestCriticalExtensions, ReestablishmentCause,
+    UEInformationResponse_r9CriticalExtensions, UEInformationResponse_r9CriticalExtensions_c1,
+    UL_CCCH_MessageType, UL_CCCH_MessageType_c1, UL_DCCH_MessageType, UL_DCCH_MessageType_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReestablishmentStormSettings {
+    // how many reestablishments not explained by mobility are tolerated
+    // within window_secs
+    pub max_reestablishments_per_window: usize,
+    pub window_secs: u64,
+    // a reestablishment this soon after the UE sent a measurement report is
+    // put down to it moving between cells
+    pub mobility_window_secs: u64,
+}
+
+impl Default for ReestablishmentStormSettings {
+    fn default() -> Self {
+        Self {
+            max_reestablishments_per_window: 5,
+            window_secs: 60,
+            mobility_window_secs: 5,
+        }
+    }
+}
+
+impl AnalyzerSettings for ReestablishmentStormSettings {
+    const KEY: &'static str = "reestablishment_storm";
+}
+
+fn describe_cause(cause: &ReestablishmentCause) -> &'static str {
+    match cause.0 {
+        ReestablishmentCause::RECONFIGURATION_FAILURE => "reconfiguration failure",
+        ReestablishmentCause::HANDOVER_FAILURE => "handover failure",
+        ReestablishmentCause::OTHER_FAILURE => "other failure",
+        _ => "unknown",
+    }
+}
+
+struct Reestablishment {
+    timestamp: DateTime<FixedOffset>,
+    frame: usize,
+    cause: &'static str,
+    // the PCI of the cell the connection failed on
+    phys_cell_id: u16,
+    serving_cell: Option<CellGlobalIdentity>,
+}
+
+// Each RRC connection reestablishment follows a radio link failure, a failed
+// handover or a failed reconfiguration. A UE moving around will see those now
+// and then, but a cell where they keep happening while the UE isn't being
+// handed anywhere may be getting jammed, or may be a fake cell that can't hold
+// a connection. Reestablishments caused by a failed handover, or that shortly
+// follow a measurement report, are put down to mobility and not counted.
+//
+// RLF reports the UE sends afterwards usually describe a failure that already
+// showed up as a reestablishment, so they're included in findings but don't
+// count toward the rate.
+pub struct ReestablishmentStormAnalyzer {
+    packet_num: usize,
+    max_reestablishments: usize,
+    window: TimeDelta,
+    mobility_window: TimeDelta,
+    serving_cell: Option<CellGlobalIdentity>,
+    last_measurement_report: Option<DateTime<FixedOffset>>,
+    reestablishments: VecDeque<Reestablishment>,
+    rlf_reports: VecDeque<DateTime<FixedOffset>>,
+    mobility_reestablishments: usize,
+}
+
+fn secs_to_delta(secs: u64) -> TimeDelta {
+    TimeDelta::try_seconds(i64::try_from(secs).unwrap_or(i64::MAX)).unwrap_or(TimeDelta::MAX)
+}
+
+impl ReestablishmentStormAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: ReestablishmentStormSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            max_reestablishments: settings.max_reestablishments_per_window,
+            window: secs_to_delta(settings.window_secs),
+            mobility_window: secs_to_delta(settings.mobility_window_secs),
+            serving_cell: None,
+            last_measurement_report: None,
+            reestablishments: VecDeque::new(),
+            rlf_reports: VecDeque::new(),
+            mobility_reestablishments: 0,
+        }
+    }
+
+    fn expire(&mut self, now: DateTime<FixedOffset>) {
+        while let Some(oldest) = self.reestablishments.front() {
+            if now - oldest.timestamp <= self.window {
+                break;
+            }
+            self.reestablishments.pop_front();
+        }
+        while let Some(oldest) = self.rlf_reports.front() {
+            if now - *oldest <= self.window {
+                break;
+            }
+            self.rlf_reports.pop_front();
+        }
+    }
+
+    fn handle_reestablishment(
+        &mut self,
+        now: DateTime<FixedOffset>,
+        cause: &ReestablishmentCause,
+        phys_cell_id: u16,
+    ) -> Option<Event> {
+        self.expire(now);
+        let after_measurement_report = self
+            .last_measurement_report
+            .is_some_and(|reported| now - reported <= self.mobility_window);
+        if cause.0 == ReestablishmentCause::HANDOVER_FAILURE || after_measurement_report {
+            self.mobility_reestablishments += 1;
+            return None;
+        }
+        self.reestablishments.push_back(Reestablishment {
+            timestamp: now,
+            frame: self.packet_num,
+            cause: describe_cause(cause),
+            phys_cell_id,
+            serving_cell: self.serving_cell.clone(),
+        });
+        if self.reestablishments.len() <= self.max_reestablishments {
+            return None;
+        }
+
+        let count = self.reestablishments.len();
+        let first = self.reestablishments.front()?;
+        let span_secs = (now - first.timestamp).num_seconds();
+        let pcis: BTreeSet<u16> = self
+            .reestablishments
+            .iter()
+            .map(|reestablishment| reestablishment.phys_cell_id)
+            .collect();
+        let pcis: Vec<String> = pcis.iter().map(u16::to_string).collect();
+        let cells: BTreeSet<String> = self
+            .reestablishments
+            .iter()
+            .filter_map(|reestablishment| reestablishment.serving_cell.as_ref())
+            .map(CellGlobalIdentity::to_string)
+            .collect();
+        let causes: Vec<&str> = self
+            .reestablishments
+            .iter()
+            .map(|reestablishment| reestablishment.cause)
+            .collect();
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("reestablishments".to_string(), count.to_string()),
+            ("span_secs".to_string(), span_secs.to_string()),
+            ("causes".to_string(), causes.join(", ")),
+            ("failed_pcis".to_string(), pcis.join(", ")),
+            (
+                "cells".to_string(),
+                cells.into_iter().collect::<Vec<_>>().join("; "),
+            ),
+            (
+                "rlf_reports".to_string(),
+                self.rlf_reports.len().to_string(),
+            ),
+            (
+                "mobility_reestablishments".to_string(),
+                self.mobility_reestablishments.to_string(),
+            ),
+            ("first_frame".to_string(), first.frame.to_string()),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        let event = Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Low,
+            },
+            message: format!(
+                "UE had to reestablish its RRC connection {count} times in {span_secs}s without moving between cells (PCIs {})",
+                pcis.join(", ")
+            ),
+            explanation: "The UE's connection kept failing and being reestablished, and the failures didn't follow handovers or measurement reports the way they would if the UE was moving. That points to interference or jamming, or a cell that can't keep a connection up, as fake base stations sometimes can't. Poor coverage can cause it too.".to_string(),
+            evidence,
+        };
+        // start counting afresh, so a long storm isn't reported on every
+        // single reestablishment
+        self.reestablishments.clear();
+        self.mobility_reestablishments = 0;
+        Some(event)
+    }
+}
+
+impl Analyzer for ReestablishmentStormAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Reestablishment Storm")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the UE reestablishes its RRC connection more than max_reestablishments_per_window times within window_secs, not counting reestablishments after failed handovers or measurement reports, which suggests interference or jamming.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let now = ctx.timestamp?;
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::UlCcch(msg_cont) => {
+                let UL_CCCH_MessageType::C1(
+                    UL_CCCH_MessageType_c1::RrcConnectionReestablishmentRequest(request),
+                ) = &msg_cont.message
+                else {
+                    return None;
+                };
+                let RRCConnectionReestablishmentRequestCriticalExtensions::RrcConnectionReestablishmentRequest_r8(r8) =
+                    &request.critical_extensions
+                else {
+                    return None;
+                };
+                self.handle_reestablishment(
+                    now,
+                    &r8.reestablishment_cause,
+                    r8.ue_identity.phys_cell_id.0,
+                )
+            }
+            LteInformationElement::UlDcch(msg_cont) => {
+                match &msg_cont.message {
+                    UL_DCCH_MessageType::C1(UL_DCCH_MessageType_c1::MeasurementReport(_)) => {
+                        self.last_measurement_report = Some(now);
+                    }
+                    UL_DCCH_MessageType::C1(UL_DCCH_MessageType_c1::UeInformationResponse_r9(
+                        response,
+                    )) => {
+                        if let UEInformationResponse_r9CriticalExtensions::C1(
+                            UEInformationResponse_r9CriticalExtensions_c1::UeInformationResponse_r9(
+                                r9,
+                            ),
+                        ) = &response.critical_extensions
+                        {
+                            if r9.rlf_report_r9.is_some() {
+                                self.expire(now);
+                                self.rlf_reports.push_back(now);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
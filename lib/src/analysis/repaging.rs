@@ -0,0 +1,286 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_CCCH_MessageType, DL_CCCH_MessageType_c1, DL_DCCH_MessageType, DL_DCCH_MessageType_c1,
+    EstablishmentCause, InitialUE_Identity, PCCH_MessageType, PCCH_MessageType_c1,
+    PagingUE_Identity, RRCConnectionRequestCriticalExtensions, S_TMSI, UL_CCCH_MessageType,
+    UL_CCCH_MessageType_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, bits_to_u64, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepagingSettings {
+    // how many times the UE can be paged again after answering a page before
+    // it's reported. A page or two can cross with the UE's response.
+    pub max_repages_after_response: usize,
+}
+
+impl Default for RepagingSettings {
+    fn default() -> Self {
+        Self {
+            max_repages_after_response: 2,
+        }
+    }
+}
+
+impl AnalyzerSettings for RepagingSettings {
+    const KEY: &'static str = "repaging";
+}
+
+// Paging on a busy cell names plenty of other UEs, so the pages counted for
+// identities the UE never answers to are forgotten once there are this many
+const MAX_PAGED_IDENTITIES: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct PagedIdentity {
+    mmec: u8,
+    m_tmsi: u32,
+}
+
+impl PagedIdentity {
+    fn from_s_tmsi(s_tmsi: &S_TMSI) -> Self {
+        Self {
+            mmec: bits_to_u64(&s_tmsi.mmec.0) as u8,
+            m_tmsi: bits_to_u64(&s_tmsi.m_tmsi.0) as u32,
+        }
+    }
+}
+
+impl fmt::Display for PagedIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MMEC {:02x}, M-TMSI {:08x}", self.mmec, self.m_tmsi)
+    }
+}
+
+struct PagingResponse {
+    identity: PagedIdentity,
+    pages_before_response: usize,
+    frame: usize,
+}
+
+struct Answered {
+    response: PagingResponse,
+    repages: usize,
+    reported: bool,
+}
+
+// When a cell pages a UE, the UE answers by requesting a connection for
+// mobile-terminated access, and once it's connected the network has no reason
+// to page it again. A cell that keeps paging the UE after getting its answer
+// may be confirming the UE is still there rather than trying to reach it,
+// which is a way of tracking it.
+//
+// Only pages by S-TMSI can be tied to the UE's response, as the response
+// names the UE the same way. A page only counts as answered once the cell
+// sets up the connection, and releasing the connection resets everything, as
+// the network may then page the UE again for new traffic.
+pub struct RepagingAnalyzer {
+    packet_num: usize,
+    max_repages: usize,
+    serving_cell: Option<CellGlobalIdentity>,
+    // pages seen for each identity since it last answered one
+    paged: HashMap<PagedIdentity, usize>,
+    pending_response: Option<PagingResponse>,
+    answered: HashMap<PagedIdentity, Answered>,
+}
+
+impl RepagingAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: RepagingSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            max_repages: settings.max_repages_after_response,
+            serving_cell: None,
+            paged: HashMap::new(),
+            pending_response: None,
+            answered: HashMap::new(),
+        }
+    }
+
+    fn handle_page(&mut self, identity: PagedIdentity) -> Option<Event> {
+        let Some(answered) = self.answered.get_mut(&identity) else {
+            if !self.paged.contains_key(&identity) && self.paged.len() >= MAX_PAGED_IDENTITIES {
+                self.paged.clear();
+            }
+            *self.paged.entry(identity).or_default() += 1;
+            return None;
+        };
+        answered.repages += 1;
+        if answered.repages <= self.max_repages || answered.reported {
+            return None;
+        }
+        answered.reported = true;
+
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("s_tmsi".to_string(), identity.to_string()),
+            (
+                "pages_before_response".to_string(),
+                answered.response.pages_before_response.to_string(),
+            ),
+            (
+                "repages_after_response".to_string(),
+                answered.repages.to_string(),
+            ),
+            (
+                "response_frame".to_string(),
+                answered.response.frame.to_string(),
+            ),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!(
+                "UE was paged {} more times after it had answered a page ({identity})",
+                answered.repages
+            ),
+            explanation: "The cell kept paging the UE after the UE had answered and connected, which the network has no need to do. Repeated pages like this can be used to check whether a UE is still in the area, which helps track it.".to_string(),
+            evidence,
+        })
+    }
+
+    fn handle_connection_request(&mut self, identity: &InitialUE_Identity, cause: u8) {
+        if cause != EstablishmentCause::MT_ACCESS {
+            return;
+        }
+        let InitialUE_Identity::S_TMSI(s_tmsi) = identity else {
+            return;
+        };
+        let identity = PagedIdentity::from_s_tmsi(s_tmsi);
+        // only count it as an answer if the UE was actually paged
+        if let Some(pages) = self.paged.get(&identity) {
+            self.pending_response = Some(PagingResponse {
+                identity,
+                pages_before_response: *pages,
+                frame: self.packet_num,
+            });
+        }
+    }
+}
+
+impl Analyzer for RepagingAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Repaging After Response")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the cell keeps paging the UE's S-TMSI more than max_repages_after_response times after the UE has answered a page and connected, which can be used to check whether it's still present.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::PCCH(pcch_msg) => {
+                let PCCH_MessageType::C1(PCCH_MessageType_c1::Paging(paging)) = &pcch_msg.message
+                else {
+                    return None;
+                };
+                let identities: Vec<PagedIdentity> = paging
+                    .paging_record_list
+                    .as_ref()?
+                    .0
+                    .iter()
+                    .filter_map(|record| match &record.ue_identity {
+                        PagingUE_Identity::S_TMSI(s_tmsi) => {
+                            Some(PagedIdentity::from_s_tmsi(s_tmsi))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                // every page is counted, even once one of them is reported
+                let mut event = None;
+                for identity in identities {
+                    if let Some(found) = self.handle_page(identity) {
+                        event.get_or_insert(found);
+                    }
+                }
+                event
+            }
+            LteInformationElement::UlCcch(msg_cont) => {
+                if let UL_CCCH_MessageType::C1(UL_CCCH_MessageType_c1::RrcConnectionRequest(
+                    request,
+                )) = &msg_cont.message
+                {
+                    if let RRCConnectionRequestCriticalExtensions::RrcConnectionRequest_r8(r8) =
+                        &request.critical_extensions
+                    {
+                        self.handle_connection_request(&r8.ue_identity, r8.establishment_cause.0);
+                    }
+                }
+                None
+            }
+            LteInformationElement::DlCcch(msg_cont) => {
+                match &msg_cont.message {
+                    DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionSetup(_)) => {
+                        if let Some(response) = self.pending_response.take() {
+                            self.paged.remove(&response.identity);
+                            self.answered.insert(
+                                response.identity,
+                                Answered {
+                                    response,
+                                    repages: 0,
+                                    reported: false,
+                                },
+                            );
+                        }
+                    }
+                    DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionReject(_)) => {
+                        self.pending_response = None;
+                    }
+                    _ => {}
+                }
+                None
+            }
+            LteInformationElement::DlDcch(msg_cont) => {
+                if let DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionRelease(_)) =
+                    &msg_cont.message
+                {
+                    self.pending_response = None;
+                    self.answered.clear();
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
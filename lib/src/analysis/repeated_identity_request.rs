@@ -0,0 +1,163 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{DL_CCCH_MessageType, DL_CCCH_MessageType_c1};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepeatedIdentityRequestSettings {
+    // on how many separate connections a cell has to request the UE's
+    // identity before it's reported
+    pub min_connections: usize,
+}
+
+impl Default for RepeatedIdentityRequestSettings {
+    fn default() -> Self {
+        Self { min_connections: 2 }
+    }
+}
+
+impl AnalyzerSettings for RepeatedIdentityRequestSettings {
+    const KEY: &'static str = "repeated_identity_request";
+}
+
+// The connections a cell has requested the UE's identity on
+struct CellRequests {
+    connections: usize,
+    // the last connection counted, so requests repeated within one
+    // connection only count once
+    last_connection: usize,
+    first_frame: usize,
+}
+
+// The IMSI requested analyzer looks at Identity Requests within a connection.
+// A network only needs to ask for the UE's identity once, after which it
+// knows the UE by its GUTI, so a cell that asks again every time the UE
+// connects to it is re-identifying the UE over and over, as an IMSI catcher
+// that releases the UE and waits for it to come back would. This counts the
+// separate RRC connections each cell has requested the UE's identity on,
+// reporting every one from the min_connections-th on, with the severity going
+// up with each repetition.
+//
+// Connections are counted for as long as the analysis runs, which is a whole
+// recording, or every recording in a merged analysis. Connections made while
+// the serving cell isn't known yet don't count.
+pub struct RepeatedIdentityRequestAnalyzer {
+    packet_num: usize,
+    min_connections: usize,
+    serving_cell: Option<CellGlobalIdentity>,
+    // how many RRC connections have been set up so far
+    connection: usize,
+    requests: HashMap<CellGlobalIdentity, CellRequests>,
+}
+
+impl RepeatedIdentityRequestAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: RepeatedIdentityRequestSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            min_connections: settings.min_connections.max(2),
+            serving_cell: None,
+            connection: 0,
+            requests: HashMap::new(),
+        }
+    }
+
+    fn handle_identity_request(&mut self) -> Option<Event> {
+        // outside of a connection we saw being set up
+        if self.connection == 0 {
+            return None;
+        }
+        let cell = self.serving_cell.clone()?;
+        let requests = self.requests.entry(cell.clone()).or_insert(CellRequests {
+            connections: 0,
+            last_connection: 0,
+            first_frame: self.packet_num,
+        });
+        if requests.last_connection == self.connection {
+            return None;
+        }
+        requests.last_connection = self.connection;
+        requests.connections += 1;
+        let connections = requests.connections;
+        if connections < self.min_connections {
+            return None;
+        }
+
+        let severity = match connections - self.min_connections {
+            0 => Severity::Low,
+            1 => Severity::Medium,
+            _ => Severity::High,
+        };
+        Some(Event {
+            event_type: EventType::QualitativeWarning { severity },
+            message: format!(
+                "Cell {cell} requested the UE's identity on {connections} separate connections"
+            ),
+            explanation: "A network only needs to learn the UE's permanent identity once, after which it uses a temporary one, but this cell asked for it again every time the UE connected. That's what an IMSI catcher that keeps letting the UE go and waiting for it to come back would do to track it over time.".to_string(),
+            evidence: vec![
+                ("cell".to_string(), cell.to_string()),
+                ("connections".to_string(), connections.to_string()),
+                (
+                    "first_request_frame".to_string(),
+                    requests.first_frame.to_string(),
+                ),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+}
+
+impl Analyzer for RepeatedIdentityRequestAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Repeated Identity Request")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the same cell requests the UE's identity on min_connections or more separate RRC connections, with the severity rising with each further one.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::DlCcch(msg_cont) => {
+                if let DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionSetup(_)) =
+                    &msg_cont.message
+                {
+                    self.connection += 1;
+                }
+                None
+            }
+            LteInformationElement::NAS(NASMessage::EMMMessage(EMMMessage::EMMIdentityRequest(
+                _,
+            ))) => self.handle_identity_request(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Reading analysis reports written by older versions of rayhunter. Every
+//! report's metadata row records the [REPORT_VERSION] it was written with;
+//! rows from older versions are upgraded to the current [AnalysisRow] as
+//! they're read, and reports from newer versions are refused rather than
+//! misread.
+
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::analyzer::{AnalysisRow, Event, REPORT_VERSION};
+
+#[derive(Error, Debug)]
+pub enum ReportFormatError {
+    #[error(
+        "report version {0} is newer than this version of rayhunter can read (up to {REPORT_VERSION})"
+    )]
+    UnsupportedVersion(u64),
+    #[error("report version {0:?} isn't a number")]
+    InvalidVersion(serde_json::Value),
+    #[error("failed to parse report metadata: {0}")]
+    InvalidMetadata(#[from] serde_json::Error),
+}
+
+// A row as version 1 wrote it: all the skipped messages and analyzed packets
+// of one container together
+#[derive(Deserialize)]
+struct V1Row {
+    #[serde(default)]
+    skipped_message_reasons: Vec<String>,
+    #[serde(default)]
+    analysis: Vec<V1PacketAnalysis>,
+}
+
+#[derive(Deserialize)]
+struct V1PacketAnalysis {
+    timestamp: Option<DateTime<FixedOffset>>,
+    events: Vec<Option<Event>>,
+}
+
+/// The format of one analysis report, as given by its metadata row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportFormat {
+    pub version: u32,
+}
+
+impl Default for ReportFormat {
+    fn default() -> Self {
+        ReportFormat {
+            version: REPORT_VERSION,
+        }
+    }
+}
+
+impl ReportFormat {
+    /// Reads the version from a report's metadata. Reports from before
+    /// versions were recorded don't have one, and are version 1.
+    pub fn from_metadata(metadata: &serde_json::Value) -> Result<Self, ReportFormatError> {
+        let Some(version) = metadata.get("report_version") else {
+            return Ok(ReportFormat { version: 1 });
+        };
+        let Some(version) = version.as_u64() else {
+            return Err(ReportFormatError::InvalidVersion(version.clone()));
+        };
+        match u32::try_from(version) {
+            Ok(version) if version <= REPORT_VERSION => Ok(ReportFormat { version }),
+            _ => Err(ReportFormatError::UnsupportedVersion(version)),
+        }
+    }
+
+    pub fn from_metadata_line(line: &str) -> Result<Self, ReportFormatError> {
+        Self::from_metadata(&serde_json::from_str(line)?)
+    }
+
+    /// Parses one row of the report, upgraded to the current format. A
+    /// version 1 row can hold several packets' worth of analysis, so one
+    /// line can make more than one row.
+    pub fn parse_row(&self, line: &str) -> Result<Vec<AnalysisRow>, serde_json::Error> {
+        if self.version > 1 {
+            // later versions only added optional fields
+            return Ok(vec![serde_json::from_str(line)?]);
+        }
+        let row: V1Row = serde_json::from_str(line)?;
+        let skipped = row
+            .skipped_message_reasons
+            .into_iter()
+            .map(|reason| AnalysisRow {
+                packet_timestamp: None,
+                skipped_message_reason: Some(reason),
+                events: Vec::new(),
+                correlation_id: None,
+            });
+        let analyzed = row.analysis.into_iter().map(|analysis| AnalysisRow {
+            packet_timestamp: analysis.timestamp,
+            skipped_message_reason: None,
+            events: analysis.events,
+            correlation_id: None,
+        });
+        Ok(skipped.chain(analyzed).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_report_versions() {
+        let format = |metadata| ReportFormat::from_metadata(&metadata);
+        assert_eq!(format(json!({"analyzers": []})).unwrap().version, 1);
+        assert_eq!(format(json!({"report_version": 2})).unwrap().version, 2);
+        assert_eq!(
+            format(json!({"report_version": REPORT_VERSION})).unwrap(),
+            ReportFormat::default()
+        );
+        assert!(matches!(
+            format(json!({"report_version": REPORT_VERSION + 1})),
+            Err(ReportFormatError::UnsupportedVersion(_))
+        ));
+        assert!(matches!(
+            format(json!({"report_version": "2"})),
+            Err(ReportFormatError::InvalidVersion(_))
+        ));
+    }
+
+    #[test]
+    fn test_upgrade_v1_row() {
+        let line = r#"{"skipped_message_reasons":["bad message"],"analysis":[{"timestamp":"2024-01-01T00:00:00+00:00","events":[null,{"event_type":{"type":"Informational"},"message":"a"}]}]}"#;
+        let rows = ReportFormat { version: 1 }.parse_row(line).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[0].skipped_message_reason.as_deref(),
+            Some("bad message")
+        );
+        assert!(rows[1].packet_timestamp.is_some());
+        assert_eq!(rows[1].events.len(), 2);
+        assert!(rows[1].has_events());
+    }
+}
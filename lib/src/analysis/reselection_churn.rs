@@ -0,0 +1,168 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_CCCH_MessageType, DL_CCCH_MessageType_c1, DL_DCCH_MessageType, DL_DCCH_MessageType_c1,
+    UL_CCCH_MessageType,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReselectionChurnSettings {
+    // how many idle mode reselections per minute are tolerated
+    pub max_reselections_per_minute: usize,
+}
+
+impl Default for ReselectionChurnSettings {
+    fn default() -> Self {
+        Self {
+            max_reselections_per_minute: 6,
+        }
+    }
+}
+
+impl AnalyzerSettings for ReselectionChurnSettings {
+    const KEY: &'static str = "reselection_churn";
+}
+
+// Counts idle-mode cell reselections, i.e. the UE camping on a new cell while
+// it doesn't have an RRC connection. Cell changes in connected mode are
+// handovers ordered by the network, so they're deliberately not counted here.
+pub struct ReselectionChurnAnalyzer {
+    max_per_minute: usize,
+    connected: bool,
+    serving_cell: Option<CellGlobalIdentity>,
+    // reselections within the last minute, oldest first
+    recent: VecDeque<(DateTime<FixedOffset>, CellGlobalIdentity)>,
+}
+
+impl ReselectionChurnAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: ReselectionChurnSettings = analyzer_config.get_settings();
+        Self {
+            max_per_minute: settings.max_reselections_per_minute,
+            connected: false,
+            serving_cell: None,
+            recent: VecDeque::new(),
+        }
+    }
+
+    fn handle_cell(
+        &mut self,
+        cell: CellGlobalIdentity,
+        timestamp: Option<DateTime<FixedOffset>>,
+    ) -> Option<Event> {
+        let previous_cell = self.serving_cell.replace(cell.clone());
+        if self.connected || previous_cell.is_none_or(|previous| previous == cell) {
+            return None;
+        }
+        let timestamp = timestamp?;
+        while let Some((oldest, _)) = self.recent.front() {
+            if timestamp - *oldest > TimeDelta::minutes(1) {
+                self.recent.pop_front();
+            } else {
+                break;
+            }
+        }
+        self.recent.push_back((timestamp, cell));
+        if self.recent.len() <= self.max_per_minute {
+            return None;
+        }
+
+        let sequence: Vec<String> = self.recent.iter().map(|(_, c)| c.to_string()).collect();
+        let sequence = sequence.join(" -> ");
+        let count = self.recent.len();
+        // start counting afresh, so ongoing churn is reported once a minute
+        // rather than on every reselection
+        self.recent.clear();
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Low,
+            },
+            message: format!(
+                "UE reselected cells {count} times within a minute (max {}): {sequence}",
+                self.max_per_minute
+            ),
+            explanation: "The UE kept camping on different cells while idle. This can be caused by interference or poor coverage, but also by an attacker juggling the UE between cells.".to_string(),
+            evidence: vec![
+                ("reselections".to_string(), count.to_string()),
+                (
+                    "max_reselections_per_minute".to_string(),
+                    self.max_per_minute.to_string(),
+                ),
+                ("cells".to_string(), sequence),
+            ],
+        })
+    }
+}
+
+impl Analyzer for ReselectionChurnAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Cell Reselection Churn")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the UE reselects between idle mode cells more often than the configured rate. Handovers in connected mode aren't counted. Poor coverage or interference may trigger this too.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        if let Some(sib1) = unpack_sib1(ie) {
+            let cell = CellGlobalIdentity::from_sib1(sib1)?;
+            return self.handle_cell(cell, ctx.timestamp);
+        }
+
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::UlCcch(msg_cont) => {
+                // connection (re)establishment requests are the only UL-CCCH
+                // messages
+                if let UL_CCCH_MessageType::C1(_) = &msg_cont.message {
+                    self.connected = true;
+                }
+            }
+            LteInformationElement::DlCcch(msg_cont) => {
+                if let DL_CCCH_MessageType::C1(
+                    DL_CCCH_MessageType_c1::RrcConnectionReject(_)
+                    | DL_CCCH_MessageType_c1::RrcConnectionReestablishmentReject(_),
+                ) = &msg_cont.message
+                {
+                    self.connected = false;
+                }
+            }
+            LteInformationElement::DlDcch(msg_cont) => {
+                if let DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionRelease(_)) =
+                    &msg_cont.message
+                {
+                    self.connected = false;
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+}
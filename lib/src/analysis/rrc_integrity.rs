@@ -0,0 +1,252 @@
+use std::borrow::Cow;
+
+use telcom_parser::lte_rrc::{
+    DL_CCCH_MessageType, DL_CCCH_MessageType_c1, DL_DCCH_MessageType, DL_DCCH_MessageType_c1,
+    UL_CCCH_MessageType, UL_DCCH_MessageType, UL_DCCH_MessageType_c1,
+};
+
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
+use super::information_element::{InformationElement, LteInformationElement};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+// SRB2 is only set up once AS security is active
+const SRB2: u8 = 2;
+
+struct Connection {
+    // whether the network has started integrity protection on the connection
+    // with a Security Mode Command (or a re-establishment, which reuses the
+    // existing keys)
+    integrity_active: bool,
+    security_mode_command_frame: Option<usize>,
+    reported: bool,
+}
+
+// The downlink DCCH messages 36.331 only lets the network send once AS
+// security is active, with nothing in them worth sending otherwise
+fn requires_security(message: &DL_DCCH_MessageType_c1) -> Option<&'static str> {
+    match message {
+        DL_DCCH_MessageType_c1::MobilityFromEUTRACommand(_) => Some("MobilityFromEUTRACommand"),
+        DL_DCCH_MessageType_c1::HandoverFromEUTRAPreparationRequest(_) => {
+            Some("HandoverFromEUTRAPreparationRequest")
+        }
+        DL_DCCH_MessageType_c1::CounterCheck(_) => Some("CounterCheck"),
+        DL_DCCH_MessageType_c1::UeInformationRequest_r9(_) => Some("UEInformationRequest"),
+        DL_DCCH_MessageType_c1::LoggedMeasurementConfiguration_r10(_) => {
+            Some("LoggedMeasurementConfiguration")
+        }
+        _ => None,
+    }
+}
+
+fn describe_bearer(bearer_id: Option<u8>) -> String {
+    match bearer_id {
+        Some(bearer_id) => format!("SRB{bearer_id}"),
+        None => "unknown".to_string(),
+    }
+}
+
+// Every message on SRB1 and SRB2 is integrity protected once the network has
+// sent a Security Mode Command. The modem only logs RRC messages after PDCP
+// has checked the MAC-I and stripped it, so a missing or wrong MAC-I can't be
+// seen directly, but its consequences can: a message that 36.331 only allows
+// with security active, or anything at all carried on SRB2 (which can't be
+// set up without security), arriving on a connection where integrity
+// protection was never started couldn't have been protected. A compliant
+// network never does this, while a fake cell relaying or injecting messages
+// without the UE's keys has to.
+//
+// RRC Connection Reconfigurations are left to the unprotected reconfiguration
+// analyzer. Connections that started before the capture did are skipped,
+// since their security state isn't known, and each connection is reported
+// once. The bearer is only known for QMDL captures.
+pub struct RrcIntegrityAnalyzer {
+    packet_num: usize,
+    connection: Option<Connection>,
+}
+
+impl Default for RrcIntegrityAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RrcIntegrityAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            packet_num: 0,
+            connection: None,
+        }
+    }
+
+    fn start_connection(&mut self, integrity_active: bool) {
+        self.connection = Some(Connection {
+            integrity_active,
+            security_mode_command_frame: None,
+            reported: false,
+        });
+    }
+
+    fn check_message(
+        &mut self,
+        message_type: &str,
+        direction: &str,
+        reason: &str,
+        bearer_id: Option<u8>,
+    ) -> Option<Event> {
+        let connection = self.connection.as_mut()?;
+        if connection.integrity_active || connection.reported {
+            return None;
+        }
+        connection.reported = true;
+
+        let mut evidence = vec![
+            ("message_type".to_string(), message_type.to_string()),
+            ("direction".to_string(), direction.to_string()),
+            ("bearer".to_string(), describe_bearer(bearer_id)),
+            ("reason".to_string(), reason.to_string()),
+        ];
+        if let Some(frame) = connection.security_mode_command_frame {
+            evidence.push(("security_mode_command_frame".to_string(), frame.to_string()));
+        }
+        evidence.push(("frame".to_string(), self.packet_num.to_string()));
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::High,
+            },
+            message: format!(
+                "{direction} {message_type} on {} without integrity protection having been activated ({reason})",
+                describe_bearer(bearer_id)
+            ),
+            explanation: "Once a connection is secured, every RRC signalling message on it has to carry a valid integrity checksum, and some messages may only be sent on a secured connection. This one was sent on a connection where integrity protection was never started, so it can't have been protected. Real networks don't do this, while a fake base station or a man-in-the-middle without the UE's keys might.".to_string(),
+            evidence,
+        })
+    }
+
+    fn handle_dl_dcch(
+        &mut self,
+        message: &DL_DCCH_MessageType_c1,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        match message {
+            DL_DCCH_MessageType_c1::SecurityModeCommand(_) => {
+                let packet_num = self.packet_num;
+                if let Some(connection) = self.connection.as_mut() {
+                    connection.integrity_active = true;
+                    connection.security_mode_command_frame = Some(packet_num);
+                }
+                None
+            }
+            DL_DCCH_MessageType_c1::RrcConnectionRelease(_) => {
+                self.connection = None;
+                None
+            }
+            // handled by the unprotected reconfiguration analyzer
+            DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(_) => None,
+            _ => {
+                if let Some(message_type) = requires_security(message) {
+                    return self.check_message(
+                        message_type,
+                        "Downlink",
+                        "only allowed with security active",
+                        ctx.bearer_id,
+                    );
+                }
+                if ctx.bearer_id == Some(SRB2) {
+                    return self.check_message(
+                        "DL-DCCH message",
+                        "Downlink",
+                        "SRB2 requires security",
+                        ctx.bearer_id,
+                    );
+                }
+                None
+            }
+        }
+    }
+}
+
+impl Analyzer for RrcIntegrityAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Missing RRC Integrity Protection")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether RRC signalling that has to be integrity protected (messages only allowed with AS security active, or anything on SRB2) is sent on a connection where the network never activated integrity protection with a Security Mode Command.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::UlCcch(msg_cont) => {
+                // the UE is starting over with a new connection
+                if let UL_CCCH_MessageType::C1(_) = &msg_cont.message {
+                    self.connection = None;
+                }
+                None
+            }
+            LteInformationElement::DlCcch(msg_cont) => {
+                match &msg_cont.message {
+                    DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionSetup(_)) => {
+                        self.start_connection(false);
+                    }
+                    // re-establishment resumes the existing security context
+                    DL_CCCH_MessageType::C1(
+                        DL_CCCH_MessageType_c1::RrcConnectionReestablishment(_),
+                    ) => {
+                        self.start_connection(true);
+                    }
+                    _ => {}
+                }
+                None
+            }
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(c1) = &msg_cont.message else {
+                    return None;
+                };
+                self.handle_dl_dcch(c1, ctx)
+            }
+            LteInformationElement::UlDcch(msg_cont) => {
+                if let UL_DCCH_MessageType::C1(UL_DCCH_MessageType_c1::SecurityModeFailure(_)) =
+                    &msg_cont.message
+                {
+                    // the UE rejected the command, so protection never started
+                    if let Some(connection) = self.connection.as_mut() {
+                        connection.integrity_active = false;
+                    }
+                    return None;
+                }
+                if ctx.bearer_id != Some(SRB2) {
+                    return None;
+                }
+                self.check_message(
+                    "UL-DCCH message",
+                    "Uplink",
+                    "SRB2 requires security",
+                    ctx.bearer_id,
+                )
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::borrow::Cow;
+
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_DCCH_MessageType, DL_DCCH_MessageType_c1, SecurityModeCommand,
+    SecurityModeCommandCriticalExtensions, SecurityModeCommandCriticalExtensions_c1,
+    UL_DCCH_MessageType, UL_DCCH_MessageType_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecurityModeFailureSettings {
+    // how many failed security mode procedures in a row it takes to warn
+    pub min_failures: usize,
+    pub severity: Severity,
+}
+
+impl Default for SecurityModeFailureSettings {
+    fn default() -> Self {
+        Self {
+            min_failures: 2,
+            severity: Severity::High,
+        }
+    }
+}
+
+impl AnalyzerSettings for SecurityModeFailureSettings {
+    const KEY: &'static str = "security_mode_failure";
+}
+
+// Describes the algorithms an RRC Security Mode Command selected
+fn describe_command(command: &SecurityModeCommand) -> Option<String> {
+    let SecurityModeCommandCriticalExtensions::C1(
+        SecurityModeCommandCriticalExtensions_c1::SecurityModeCommand_r8(r8),
+    ) = &command.critical_extensions
+    else {
+        return None;
+    };
+    let algorithms = &r8.security_config_smc.security_algorithm_config;
+    Some(format!(
+        "EEA{}/EIA{}",
+        algorithms.ciphering_algorithm.0, algorithms.integrity_prot_algorithm.0
+    ))
+}
+
+// A UE fails a security mode procedure when it can't verify the command's
+// integrity, which only happens if whoever sent it doesn't hold the keys
+// derived from the UE's SIM. A real network gets it right, so failure after
+// failure points at a fake cell trying its luck. Both the RRC (AS) and NAS
+// procedures are counted, and any successful one starts the count over.
+//
+// RRC Security Mode Failure doesn't carry a cause, so the algorithms the
+// failed command asked for are reported instead. NAS Security Mode Reject
+// does, and it's included.
+pub struct SecurityModeFailureAnalyzer {
+    packet_num: usize,
+    min_failures: usize,
+    severity: Severity,
+    serving_cell: Option<CellGlobalIdentity>,
+    // the algorithms of the last RRC Security Mode Command
+    last_command: Option<String>,
+    // descriptions of the failures since the last success
+    failures: Vec<String>,
+    reported: bool,
+}
+
+impl SecurityModeFailureAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: SecurityModeFailureSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            // a single failure isn't unusual enough on its own
+            min_failures: settings.min_failures.max(1),
+            severity: settings.severity,
+            serving_cell: None,
+            last_command: None,
+            failures: Vec::new(),
+            reported: false,
+        }
+    }
+
+    fn handle_success(&mut self) {
+        self.failures.clear();
+        self.reported = false;
+    }
+
+    fn handle_failure(&mut self, failure: String) -> Option<Event> {
+        self.failures
+            .push(format!("{failure} (frame {})", self.packet_num));
+        if self.failures.len() < self.min_failures || self.reported {
+            return None;
+        }
+
+        self.reported = true;
+        let count = self.failures.len();
+        let mut evidence = vec![
+            ("failures".to_string(), count.to_string()),
+            ("sequence".to_string(), self.failures.join(" -> ")),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ];
+        if let Some(cell) = &self.serving_cell {
+            evidence.insert(0, ("cell".to_string(), cell.to_string()));
+        }
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: self.severity,
+            },
+            message: format!(
+                "UE failed {count} security mode procedures in a row, most recently: {failure}"
+            ),
+            explanation: "The UE repeatedly couldn't complete the security handshake with the network. It fails when the network's messages aren't protected with keys derived from the UE's SIM, which a real network always has, so this suggests a fake base station that doesn't hold them.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for SecurityModeFailureAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Repeated Security Mode Failure")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the UE fails at least min_failures RRC or NAS security mode procedures without one succeeding in between, which happens when the network doesn't hold the UE's keys.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::DlDcch(msg_cont) => {
+                if let DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::SecurityModeCommand(
+                    command,
+                )) = &msg_cont.message
+                {
+                    self.last_command = describe_command(command);
+                }
+                None
+            }
+            LteInformationElement::UlDcch(msg_cont) => match &msg_cont.message {
+                UL_DCCH_MessageType::C1(UL_DCCH_MessageType_c1::SecurityModeComplete(_)) => {
+                    self.handle_success();
+                    None
+                }
+                UL_DCCH_MessageType::C1(UL_DCCH_MessageType_c1::SecurityModeFailure(_)) => {
+                    let failure = match self.last_command.take() {
+                        Some(algorithms) => {
+                            format!(
+                                "RRC Security Mode Failure for a command selecting {algorithms}"
+                            )
+                        }
+                        None => "RRC Security Mode Failure".to_string(),
+                    };
+                    self.handle_failure(failure)
+                }
+                _ => None,
+            },
+            LteInformationElement::NAS(NASMessage::EMMMessage(emm)) => match emm {
+                EMMMessage::EMMSecurityModeComplete(_) => {
+                    self.handle_success();
+                    None
+                }
+                EMMMessage::EMMSecurityModeReject(reject) => self.handle_failure(format!(
+                    "NAS Security Mode Reject (cause: {:?})",
+                    reject.emm_cause
+                )),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
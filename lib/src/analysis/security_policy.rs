@@ -0,0 +1,166 @@
+use std::borrow::Cow;
+
+use log::warn;
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_DCCH_MessageType, DL_DCCH_MessageType_c1, SecurityModeCommandCriticalExtensions,
+    SecurityModeCommandCriticalExtensions_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+
+// Parses an algorithm name like "EEA2" or "eia1" into its number, given the
+// expected prefix
+fn parse_algorithm(name: &str, prefix: &str) -> Option<u8> {
+    let name = name.trim().to_ascii_uppercase();
+    name.strip_prefix(prefix)?.parse().ok()
+}
+
+fn parse_algorithms(names: &[String], prefix: &str) -> Vec<u8> {
+    let mut algorithms = Vec::new();
+    for name in names {
+        match parse_algorithm(name, prefix) {
+            Some(algorithm) => algorithms.push(algorithm),
+            None => warn!("ignoring invalid {prefix} algorithm in analyzer config: {name:?}"),
+        }
+    }
+    algorithms
+}
+
+fn format_algorithms(algorithms: &[u8], prefix: &str) -> String {
+    let names: Vec<String> = algorithms.iter().map(|a| format!("{prefix}{a}")).collect();
+    names.join("/")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecurityPolicySettings {
+    // algorithm names like "EEA2"/"EIA2" the home network is expected to
+    // negotiate
+    pub allowed_ciphering_algorithms: Vec<String>,
+    pub allowed_integrity_algorithms: Vec<String>,
+}
+
+impl Default for SecurityPolicySettings {
+    fn default() -> Self {
+        Self {
+            allowed_ciphering_algorithms: vec!["EEA1".into(), "EEA2".into(), "EEA3".into()],
+            allowed_integrity_algorithms: vec!["EIA1".into(), "EIA2".into(), "EIA3".into()],
+        }
+    }
+}
+
+impl AnalyzerSettings for SecurityPolicySettings {
+    const KEY: &'static str = "unexpected_security_algorithms";
+}
+
+// For users who know which AS ciphering/integrity algorithms their home
+// network uses, this warns whenever an attach or TAU is accepted after the
+// cell negotiated something else. A catcher may let the UE attach normally
+// while quietly picking weaker settings.
+pub struct SecurityPolicyAnalyzer {
+    packet_num: usize,
+    allowed_ciphering: Vec<u8>,
+    allowed_integrity: Vec<u8>,
+    // (ciphering, integrity) from the most recent RRC Security Mode Command
+    negotiated: Option<(u8, u8)>,
+}
+
+impl SecurityPolicyAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: SecurityPolicySettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            allowed_ciphering: parse_algorithms(&settings.allowed_ciphering_algorithms, "EEA"),
+            allowed_integrity: parse_algorithms(&settings.allowed_integrity_algorithms, "EIA"),
+            negotiated: None,
+        }
+    }
+
+    fn check_accept(&self, procedure: &str) -> Option<Event> {
+        let (ciphering, integrity) = self.negotiated?;
+        if self.allowed_ciphering.contains(&ciphering)
+            && self.allowed_integrity.contains(&integrity)
+        {
+            return None;
+        }
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!(
+                "{procedure} accepted with EEA{ciphering}/EIA{integrity}, expected one of {} and one of {} (frame {})",
+                format_algorithms(&self.allowed_ciphering, "EEA"),
+                format_algorithms(&self.allowed_integrity, "EIA"),
+                self.packet_num,
+            ),
+            explanation: "The cell negotiated ciphering or integrity algorithms that aren't in your configured allowed set, and the network then accepted the UE's registration. A catcher may let a UE attach normally while quietly picking weaker settings.".to_string(),
+            evidence: vec![
+                ("procedure".to_string(), procedure.to_string()),
+                ("ciphering_algorithm".to_string(), format!("EEA{ciphering}")),
+                ("integrity_algorithm".to_string(), format!("EIA{integrity}")),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+}
+
+impl Analyzer for SecurityPolicyAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Unexpected Security Algorithms")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether an attach or tracking area update is accepted after the cell negotiated ciphering or integrity algorithms outside of the configured allowed set.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::SecurityModeCommand(smc)) =
+                    &msg_cont.message
+                else {
+                    return None;
+                };
+                let SecurityModeCommandCriticalExtensions::C1(
+                    SecurityModeCommandCriticalExtensions_c1::SecurityModeCommand_r8(r8),
+                ) = &smc.critical_extensions
+                else {
+                    return None;
+                };
+                let config = &r8.security_config_smc.security_algorithm_config;
+                self.negotiated = Some((
+                    config.ciphering_algorithm.0,
+                    config.integrity_prot_algorithm.0,
+                ));
+                None
+            }
+            LteInformationElement::NAS(NASMessage::EMMMessage(EMMMessage::EMMAttachAccept(_))) => {
+                self.check_accept("Attach")
+            }
+            LteInformationElement::NAS(NASMessage::EMMMessage(
+                EMMMessage::EMMTrackingAreaUpdateAccept(_),
+            )) => self.check_accept("Tracking area update"),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_DCCH_MessageType, DL_DCCH_MessageType_c1, RRCConnectionReconfigurationCriticalExtensions,
+    RRCConnectionReconfigurationCriticalExtensions_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, emm_cause_name, emm_cause_value, unpack_sib1};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServiceRejectSettings {
+    // how many service rejects without a successful service request in
+    // between are reported
+    pub min_rejects: usize,
+}
+
+impl Default for ServiceRejectSettings {
+    fn default() -> Self {
+        Self { min_rejects: 3 }
+    }
+}
+
+impl AnalyzerSettings for ServiceRejectSettings {
+    const KEY: &'static str = "service_reject";
+}
+
+// Causes after which the UE stops trying to get service on the cell, or
+// anywhere in the PLMN, rather than just trying again later
+fn is_denial_cause(cause: u8) -> bool {
+    matches!(cause, 3 | 6 | 7 | 8 | 11 | 12 | 13 | 15)
+}
+
+// The steering reject analyzer looks at single rejects that send the UE off
+// to another cell. A network can also keep a connected UE from getting
+// service by rejecting its service requests over and over, which a fake base
+// station can use to deny service, or to wear the UE down until it falls back
+// to another RAT or cell. A congested or faulty network rejects service
+// requests now and then too, so this warns when min_rejects service rejects
+// happen without a successful service request in between, with how often
+// each cause was given, once per run of rejects.
+//
+// A service request counts as successful when the network accepts it with a
+// Service Accept, or sets up data bearers for the UE, which is how it grants
+// most service requests.
+pub struct ServiceRejectAnalyzer {
+    packet_num: usize,
+    min_rejects: usize,
+    serving_cell: Option<CellGlobalIdentity>,
+    // how many rejects with each cause since the last successful service
+    // request, or None if the cause couldn't be read
+    causes: BTreeMap<Option<u8>, usize>,
+    first_reject_frame: Option<usize>,
+    reported: bool,
+}
+
+impl ServiceRejectAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: ServiceRejectSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            min_rejects: settings.min_rejects.max(1),
+            serving_cell: None,
+            causes: BTreeMap::new(),
+            first_reject_frame: None,
+            reported: false,
+        }
+    }
+
+    fn handle_success(&mut self) {
+        self.causes.clear();
+        self.first_reject_frame = None;
+        self.reported = false;
+    }
+
+    fn handle_reject(&mut self, cause: Option<u8>) -> Option<Event> {
+        *self.causes.entry(cause).or_default() += 1;
+        let first_reject_frame = *self.first_reject_frame.get_or_insert(self.packet_num);
+        let rejects: usize = self.causes.values().sum();
+        if rejects < self.min_rejects || self.reported {
+            return None;
+        }
+        self.reported = true;
+
+        let distribution: Vec<String> = self
+            .causes
+            .iter()
+            .map(|(cause, count)| match cause {
+                Some(cause) => format!("#{cause} ({}) x{count}", emm_cause_name(*cause)),
+                None => format!("unreadable cause x{count}"),
+            })
+            .collect();
+        let most_common = self
+            .causes
+            .iter()
+            .max_by_key(|(_, count)| **count)
+            .map(|(cause, _)| *cause)?;
+        let severity = if self.causes.keys().flatten().any(|c| is_denial_cause(*c)) {
+            Severity::Medium
+        } else {
+            Severity::Low
+        };
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("rejects".to_string(), rejects.to_string()),
+            ("causes".to_string(), distribution.join(", ")),
+            (
+                "first_reject_frame".to_string(),
+                first_reject_frame.to_string(),
+            ),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        let most_common = match most_common {
+            Some(cause) => format!("#{cause} ({})", emm_cause_name(cause)),
+            None => "an unreadable cause".to_string(),
+        };
+        Some(Event {
+            event_type: EventType::QualitativeWarning { severity },
+            message: format!(
+                "Network rejected {rejects} service requests in a row, mostly with cause {most_common}"
+            ),
+            explanation: "The network kept rejecting the UE's requests to use the network without letting any of them through. Congested or faulty networks do reject service requests now and then, but a fake base station can do this to deny the UE service or push it onto another cell or an older, less secure network, particularly with causes that make the UE give up on the cell or network.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for ServiceRejectAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Repeated Service Reject")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the network rejects min_rejects service requests without a successful one (a Service Accept or data bearers being set up) in between, reporting how often each EMM cause was given.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::NAS(NASMessage::EMMMessage(emm)) => match emm {
+                EMMMessage::EMMServiceReject(reject) => {
+                    self.handle_reject(emm_cause_value(&reject.emm_cause))
+                }
+                EMMMessage::EMMServiceAccept(_) => {
+                    self.handle_success();
+                    None
+                }
+                _ => None,
+            },
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(
+                    reconfiguration,
+                )) = &msg_cont.message
+                else {
+                    return None;
+                };
+                let RRCConnectionReconfigurationCriticalExtensions::C1(
+                    RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(
+                        r8,
+                    ),
+                ) = &reconfiguration.critical_extensions
+                else {
+                    return None;
+                };
+                let sets_up_bearers = r8
+                    .radio_resource_config_dedicated
+                    .as_ref()
+                    .is_some_and(|radio_resources| radio_resources.drb_to_add_mod_list.is_some());
+                if sets_up_bearers {
+                    self.handle_success();
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::SystemInformationBlockType1;
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::InformationElement;
+use super::util::{CellGlobalIdentity, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SiPeriodicityChangeSettings {
+    // how many times in a row a cell's SIB1 has to announce the same
+    // periodicities before they're taken as its baseline
+    pub min_baseline_reads: usize,
+}
+
+impl Default for SiPeriodicityChangeSettings {
+    fn default() -> Self {
+        Self {
+            min_baseline_reads: 3,
+        }
+    }
+}
+
+impl AnalyzerSettings for SiPeriodicityChangeSettings {
+    const KEY: &'static str = "si_periodicity_change";
+}
+
+// The SI messages SIB1 schedules, each identified by the SIBs it carries, and
+// how often each is broadcast, in radio frames
+type Periodicities = BTreeMap<BTreeSet<u8>, i64>;
+
+fn periodicities(sib1: &SystemInformationBlockType1) -> Periodicities {
+    sib1.scheduling_info_list
+        .0
+        .iter()
+        .enumerate()
+        .map(|(index, info)| {
+            let mut sibs: BTreeSet<u8> = info
+                .sib_mapping_info
+                .0
+                .iter()
+                .map(|sib_type| sib_type.0.saturating_add(3))
+                .collect();
+            // SIB2 always goes in the first SI message, without being mapped
+            if index == 0 {
+                sibs.insert(2);
+            }
+            // rf8, rf16, ... rf512
+            (sibs, 8 << info.si_periodicity.0.min(6))
+        })
+        .collect()
+}
+
+fn format_sibs(sibs: &BTreeSet<u8>) -> String {
+    sibs.iter()
+        .map(|sib| format!("SIB{sib}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// The SI messages scheduled both before and after, whose periodicity changed
+fn changed_periodicities(old: &Periodicities, new: &Periodicities) -> Vec<(String, i64, i64)> {
+    old.iter()
+        .filter_map(|(sibs, old_frames)| {
+            let new_frames = new.get(sibs)?;
+            (new_frames != old_frames).then(|| (format_sibs(sibs), *old_frames, *new_frames))
+        })
+        .collect()
+}
+
+struct Baseline {
+    periodicities: Periodicities,
+    value_tag: u8,
+    reads: usize,
+}
+
+// A cell's SIB1 announces how often each of its SI messages is broadcast
+// (si-Periodicity in its schedulingInfoList). That's part of the cell's
+// configuration, so it stays put, and when an operator does change it, the
+// cell bumps systemInfoValueTag so that UEs know to read its system
+// information again. A cell whose periodicities change with the value tag
+// left as it was is being run by something other than an ordinary eNodeB
+// configuration, like a fake base station toggling its broadcast or a
+// different transmitter taking over the cell's identity. The MIB and SIB1
+// themselves are broadcast on fixed schedules, so only the SI messages are
+// checked.
+//
+// A cell's periodicities only become its baseline once min_baseline_reads
+// SIB1s in a row have announced them. Changes to which SI messages are
+// scheduled start the baseline over rather than being reported, since the
+// SIB schedule mismatch analyzer covers what a schedule contains. Cells are
+// told apart by their global identity along with the carrier and PCI where
+// the capture records them.
+pub struct SiPeriodicityChangeAnalyzer {
+    packet_num: usize,
+    min_baseline_reads: usize,
+    cells: HashMap<(CellGlobalIdentity, Option<(u32, u16)>), Baseline>,
+    reported: HashSet<(CellGlobalIdentity, Periodicities, Periodicities)>,
+}
+
+impl SiPeriodicityChangeAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: SiPeriodicityChangeSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            min_baseline_reads: settings.min_baseline_reads.max(1),
+            cells: HashMap::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    fn handle_sib1(
+        &mut self,
+        sib1: &SystemInformationBlockType1,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        let cell = CellGlobalIdentity::from_sib1(sib1)?;
+        let new = periodicities(sib1);
+        let value_tag = sib1.system_info_value_tag.0;
+        let key = (cell.clone(), ctx.earfcn.zip(ctx.phy_cell_id));
+        let fresh = |periodicities| Baseline {
+            periodicities,
+            value_tag,
+            reads: 1,
+        };
+
+        let Some(known) = self.cells.get_mut(&key) else {
+            self.cells.insert(key, fresh(new));
+            return None;
+        };
+        if known.periodicities == new {
+            known.reads += 1;
+            known.value_tag = value_tag;
+            return None;
+        }
+        let changes = changed_periodicities(&known.periodicities, &new);
+        // a baseline that's still being established, a change that comes
+        // with a new value tag, and a change to which SI messages are
+        // scheduled all just start over
+        if known.reads < self.min_baseline_reads
+            || known.value_tag != value_tag
+            || changes.is_empty()
+        {
+            *known = fresh(new);
+            return None;
+        }
+        let old = std::mem::replace(known, fresh(new.clone()));
+        if !self
+            .reported
+            .insert((cell.clone(), old.periodicities.clone(), new))
+        {
+            return None;
+        }
+
+        let described: Vec<String> = changes
+            .iter()
+            .map(|(sibs, old_frames, new_frames)| {
+                format!("{sibs} from every {old_frames} to every {new_frames} frames")
+            })
+            .collect();
+        let periods = |pick: fn(&(String, i64, i64)) -> i64| {
+            changes
+                .iter()
+                .map(|change| format!("{}: {}", change.0, pick(change)))
+                .collect::<Vec<_>>()
+                .join("; ")
+        };
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!(
+                "Cell changed how often it broadcasts its system information without changing its value tag: {}",
+                described.join(", ")
+            ),
+            explanation: "The cell changed how often it broadcasts some of its system information, without the change in its systemInfoValueTag that tells UEs its system information changed. A real cell's broadcast schedule comes from its configuration and is changed along with the value tag, so this suggests the broadcast has been taken over or is being toggled by something like a fake base station.".to_string(),
+            evidence: vec![
+                ("cell".to_string(), cell.to_string()),
+                ("old_periodicity_frames".to_string(), periods(|change| change.1)),
+                ("new_periodicity_frames".to_string(), periods(|change| change.2)),
+                ("value_tag".to_string(), value_tag.to_string()),
+                ("baseline_reads".to_string(), old.reads.to_string()),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+}
+
+impl Analyzer for SiPeriodicityChangeAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("SI Periodicity Change")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether a cell's SIB1 changes how often one of its SI messages is broadcast, once at least min_baseline_reads SIB1s in a row have announced the same periodicities, without changing its systemInfoValueTag as a real reconfiguration would.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let sib1 = unpack_sib1(ie)?;
+        self.handle_sib1(sib1, ctx)
+    }
+}
@@ -0,0 +1,367 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    SystemInformation_r8_IEsSib_TypeAndInfo_Entry as SibEntry, SystemInformationBlockType1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::InformationElement;
+use super::util::{CellGlobalIdentity, unpack_sib1, unpack_system_information};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SibScheduleSettings {
+    // how long a cell's SIBs have to be watched before the number of times
+    // they arrive is compared against the schedule
+    pub min_observation_secs: u64,
+    // how many times more often than scheduled an SI message may arrive,
+    // allowing for the modem logging retransmissions
+    pub max_rate_factor: u32,
+}
+
+impl Default for SibScheduleSettings {
+    fn default() -> Self {
+        Self {
+            min_observation_secs: 300,
+            max_rate_factor: 2,
+        }
+    }
+}
+
+impl AnalyzerSettings for SibScheduleSettings {
+    const KEY: &'static str = "sib_schedule";
+}
+
+// SIBs from 19 on are scheduled in an extension of SIB1 this doesn't read,
+// and the ETWS and CMAS warnings in SIBs 10 to 12 only come and go with the
+// warnings, so they're all left out of the comparison
+fn is_compared(sib: u8) -> bool {
+    (2..=18).contains(&sib) && !(10..=12).contains(&sib)
+}
+
+fn sib_number(entry: &SibEntry) -> Option<u8> {
+    let sib = match entry {
+        SibEntry::Sib2(_) => 2,
+        SibEntry::Sib3(_) => 3,
+        SibEntry::Sib4(_) => 4,
+        SibEntry::Sib5(_) => 5,
+        SibEntry::Sib6(_) => 6,
+        SibEntry::Sib7(_) => 7,
+        SibEntry::Sib8(_) => 8,
+        SibEntry::Sib9(_) => 9,
+        SibEntry::Sib13_v920(_) => 13,
+        SibEntry::Sib14_v1130(_) => 14,
+        SibEntry::Sib15_v1130(_) => 15,
+        SibEntry::Sib16_v1130(_) => 16,
+        SibEntry::Sib17_v1250(_) => 17,
+        SibEntry::Sib18_v1250(_) => 18,
+        _ => return None,
+    };
+    Some(sib)
+}
+
+fn format_sibs(sibs: &BTreeSet<u8>) -> String {
+    sibs.iter()
+        .map(|sib| format!("SIB{sib}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// An SI message SIB1 schedules, and the SIBs it carries
+#[derive(Debug, Clone, PartialEq)]
+struct ScheduledSi {
+    periodicity_frames: i64,
+    sibs: BTreeSet<u8>,
+}
+
+fn schedule(sib1: &SystemInformationBlockType1) -> Vec<ScheduledSi> {
+    sib1.scheduling_info_list
+        .0
+        .iter()
+        .enumerate()
+        .map(|(index, info)| {
+            // rf8, rf16, ... rf512
+            let periodicity_frames = 8 << info.si_periodicity.0.min(6);
+            let mut sibs: BTreeSet<u8> = info
+                .sib_mapping_info
+                .0
+                .iter()
+                .map(|sib_type| sib_type.0.saturating_add(3))
+                .filter(|sib| is_compared(*sib))
+                .collect();
+            // SIB2 always goes in the first SI message, without being mapped
+            if index == 0 {
+                sibs.insert(2);
+            }
+            ScheduledSi {
+                periodicity_frames,
+                sibs,
+            }
+        })
+        .collect()
+}
+
+// What's been seen of one cell's system information
+struct CellSchedule {
+    cell: Option<CellGlobalIdentity>,
+    schedule: Vec<ScheduledSi>,
+    first_seen: Option<DateTime<FixedOffset>>,
+    // how many times each scheduled SI message has arrived
+    arrivals: Vec<usize>,
+    // the SI messages, by index, and the unexpected sets of SIBs already
+    // reported
+    reported_rates: HashSet<usize>,
+    reported_contents: HashSet<BTreeSet<u8>>,
+}
+
+impl CellSchedule {
+    fn new(cell: Option<CellGlobalIdentity>, schedule: Vec<ScheduledSi>) -> Self {
+        Self {
+            cell,
+            arrivals: vec![0; schedule.len()],
+            schedule,
+            first_seen: None,
+            reported_rates: HashSet::new(),
+            reported_contents: HashSet::new(),
+        }
+    }
+}
+
+// SIB1 lists which SI messages a cell broadcasts, how often, and which SIBs
+// go in each of them (schedulingInfoList). A real cell's SI messages follow
+// that schedule to the letter, since it's generated from the same
+// configuration, while a fake base station that copied another cell's SIB1
+// or assembles its broadcast by hand can easily get it wrong.
+//
+// Each SI message is checked against the schedule as it arrives: it has to
+// carry exactly the SIBs mapped to one of the scheduled SI messages. Once a
+// cell has been watched for min_observation_secs, the number of times each SI
+// message arrived is compared against its periodicity too. The UE only reads
+// system information when it needs to, so an SI message is only considered
+// missing if one that's broadcast as rarely or more rarely kept arriving, and
+// only arriving more than max_rate_factor times as often as scheduled counts
+// as too often. Cells are told apart by carrier and PCI where the capture
+// records them, and a new schedule starts the comparison over.
+pub struct SibScheduleAnalyzer {
+    packet_num: usize,
+    min_observation: TimeDelta,
+    max_rate_factor: i64,
+    cells: HashMap<Option<(u32, u16)>, CellSchedule>,
+}
+
+impl SibScheduleAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: SibScheduleSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            min_observation: TimeDelta::try_seconds(
+                i64::try_from(settings.min_observation_secs).unwrap_or(i64::MAX),
+            )
+            .unwrap_or(TimeDelta::MAX),
+            max_rate_factor: i64::from(settings.max_rate_factor.max(1)),
+            cells: HashMap::new(),
+        }
+    }
+
+    fn handle_sib1(&mut self, key: Option<(u32, u16)>, sib1: &SystemInformationBlockType1) {
+        let cell = CellGlobalIdentity::from_sib1(sib1);
+        let schedule = schedule(sib1);
+        let unchanged = self
+            .cells
+            .get(&key)
+            .is_some_and(|known| known.cell == cell && known.schedule == schedule);
+        if !unchanged {
+            self.cells.insert(key, CellSchedule::new(cell, schedule));
+        }
+    }
+
+    fn event(
+        &self,
+        cell: &Option<CellGlobalIdentity>,
+        severity: Severity,
+        message: String,
+        details: Vec<(String, String)>,
+    ) -> Event {
+        let mut evidence = Vec::new();
+        if let Some(cell) = cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend(details);
+        evidence.push(("frame".to_string(), self.packet_num.to_string()));
+        Event {
+            event_type: EventType::QualitativeWarning { severity },
+            message,
+            explanation: "The cell's system information doesn't follow the schedule its own SIB1 announces. A real cell's broadcast is generated from the same configuration as its schedule, while a fake base station that copied another cell's SIB1, or puts its broadcast together by hand, can easily get them out of step.".to_string(),
+            evidence,
+        }
+    }
+
+    fn handle_system_information(
+        &mut self,
+        key: Option<(u32, u16)>,
+        sibs: &[SibEntry],
+        timestamp: Option<DateTime<FixedOffset>>,
+    ) -> Option<Event> {
+        let received: BTreeSet<u8> = sibs
+            .iter()
+            .filter_map(sib_number)
+            .filter(|sib| is_compared(*sib))
+            .collect();
+        if received.is_empty() {
+            return None;
+        }
+        let known = self.cells.get_mut(&key)?;
+        let Some(index) = known.schedule.iter().position(|si| si.sibs == received) else {
+            if !known.reported_contents.insert(received.clone()) {
+                return None;
+            }
+            let scheduled: BTreeSet<u8> = known
+                .schedule
+                .iter()
+                .flat_map(|si| si.sibs.iter().copied())
+                .collect();
+            let unscheduled: BTreeSet<u8> = received.difference(&scheduled).copied().collect();
+            // the SIBs missing from the SI message the received one is most
+            // like
+            let missing: BTreeSet<u8> = known
+                .schedule
+                .iter()
+                .max_by_key(|si| si.sibs.intersection(&received).count())
+                .filter(|si| si.sibs.intersection(&received).next().is_some())
+                .map(|si| si.sibs.difference(&received).copied().collect())
+                .unwrap_or_default();
+            let mut details = vec![("received".to_string(), format_sibs(&received))];
+            if !unscheduled.is_empty() {
+                details.push(("unscheduled".to_string(), format_sibs(&unscheduled)));
+            }
+            if !missing.is_empty() {
+                details.push(("missing".to_string(), format_sibs(&missing)));
+            }
+            let cell = known.cell.clone();
+            return Some(self.event(
+                &cell,
+                Severity::Medium,
+                format!(
+                    "Cell broadcast an SI message with {} that SIB1 doesn't schedule",
+                    format_sibs(&received)
+                ),
+                details,
+            ));
+        };
+
+        let timestamp = timestamp?;
+        known.arrivals[index] += 1;
+        let first_seen = *known.first_seen.get_or_insert(timestamp);
+        let elapsed = timestamp - first_seen;
+        if elapsed < self.min_observation {
+            return None;
+        }
+        // radio frames are 10ms
+        let elapsed_frames = elapsed.num_milliseconds() / 10;
+        let allowed = |si: &ScheduledSi| elapsed_frames / si.periodicity_frames + 1;
+
+        let arrived = known.arrivals[index];
+        let si = &known.schedule[index];
+        if arrived as i64 > allowed(si) * self.max_rate_factor && known.reported_rates.insert(index)
+        {
+            let (sibs, periodicity) = (si.sibs.clone(), si.periodicity_frames);
+            let cell = known.cell.clone();
+            let elapsed_secs = elapsed.num_seconds();
+            return Some(self.event(
+                &cell,
+                Severity::Low,
+                format!(
+                    "Cell broadcast its SI message with {} {arrived} times in {elapsed_secs}s, more often than scheduled",
+                    format_sibs(&sibs)
+                ),
+                vec![
+                    ("sibs".to_string(), format_sibs(&sibs)),
+                    ("arrivals".to_string(), arrived.to_string()),
+                    ("periodicity_frames".to_string(), periodicity.to_string()),
+                    ("observed_secs".to_string(), elapsed_secs.to_string()),
+                ],
+            ));
+        }
+
+        // the UE kept reading this SI message, so it would have read the ones
+        // that are broadcast at least as often too
+        let (periodicity, kept_reading) = (si.periodicity_frames, arrived >= 3);
+        if !kept_reading {
+            return None;
+        }
+        let missing = known.schedule.iter().enumerate().position(|(other, si)| {
+            known.arrivals[other] == 0
+                && si.periodicity_frames <= periodicity
+                && !known.reported_rates.contains(&other)
+        })?;
+        known.reported_rates.insert(missing);
+        let sibs = known.schedule[missing].sibs.clone();
+        let missing_periodicity = known.schedule[missing].periodicity_frames;
+        let read = known.schedule[index].sibs.clone();
+        let cell = known.cell.clone();
+        let elapsed_secs = elapsed.num_seconds();
+        Some(self.event(
+            &cell,
+            Severity::Low,
+            format!(
+                "Cell never broadcast its scheduled SI message with {} in {elapsed_secs}s",
+                format_sibs(&sibs)
+            ),
+            vec![
+                ("missing".to_string(), format_sibs(&sibs)),
+                (
+                    "periodicity_frames".to_string(),
+                    missing_periodicity.to_string(),
+                ),
+                ("kept_receiving".to_string(), format_sibs(&read)),
+                ("arrivals".to_string(), arrived.to_string()),
+                ("observed_secs".to_string(), elapsed_secs.to_string()),
+            ],
+        ))
+    }
+}
+
+impl Analyzer for SibScheduleAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("SIB Schedule Mismatch")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether a cell's SI messages match the schedule in its SIB1: each has to carry exactly the SIBs mapped to one of the scheduled SI messages, and, once the cell has been watched for min_observation_secs, none may arrive more than max_rate_factor times as often as scheduled or never arrive while rarer ones keep arriving.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let key = ctx.earfcn.zip(ctx.phy_cell_id);
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.handle_sib1(key, sib1);
+            return None;
+        }
+        let sibs = unpack_system_information(ie)?;
+        self.handle_system_information(key, sibs, ctx.timestamp)
+    }
+}
@@ -0,0 +1,321 @@
+use std::borrow::Cow;
+
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimOtaSettings {
+    // originating addresses (digits only, e.g. "1234" or "+15551234567") the
+    // carrier legitimately sends OTA updates from, which aren't reported
+    pub originator_allowlist: Vec<String>,
+    // only show the last two digits of the originator in findings
+    pub redact_originator: bool,
+    // findings are informational unless this is set
+    pub severity: Option<Severity>,
+}
+
+impl AnalyzerSettings for SimOtaSettings {
+    const KEY: &'static str = "sim_ota";
+}
+
+// TP-PID values addressing the SIM rather than the user, per 23.040 9.2.3.9
+const PID_ANSI_136_R_DATA: u8 = 0x7c;
+const PID_SIM_DATA_DOWNLOAD: u8 = 0x7f;
+// The user data header element that marks a secured command packet, per
+// 31.115
+const IEI_COMMAND_PACKET: u8 = 0x70;
+
+// Well known Toolkit Application References (TARs), per 31.220 and the S@T
+// specification. Anything else is reported by its TAR alone.
+fn describe_tar(tar: [u8; 3]) -> &'static str {
+    match tar {
+        [0x00, 0x00, 0x00] => "Issuer Security Domain (remote application management)",
+        [0x50, 0x53, 0x48] => "S@T Browser",
+        [0xb0, 0x00, 0x00..=0xff] => "USIM remote file management",
+        _ => "unknown application",
+    }
+}
+
+// Whether the data coding scheme says the message is 8-bit data for the SIM
+// (class 2), per 23.038 section 4
+fn is_sim_specific_data(dcs: u8) -> bool {
+    let general_class_2_8bit = dcs & 0xc0 == 0 && dcs & 0x10 != 0 && dcs & 0x0c == 0x04;
+    let data_class_2_8bit = dcs & 0xf0 == 0xf0 && dcs & 0x04 != 0;
+    (general_class_2_8bit || data_class_2_8bit) && dcs & 0x03 == 0x02
+}
+
+// Semi-octet encoded digits, as in addresses, stopping at the filler
+fn decode_digits(bytes: &[u8], count: usize) -> String {
+    bytes
+        .iter()
+        .flat_map(|byte| [byte & 0x0f, byte >> 4])
+        .take(count)
+        .take_while(|digit| *digit != 0x0f)
+        .map(|digit| match digit {
+            0..=9 => char::from(b'0' + digit),
+            0x0a => '*',
+            0x0b => '#',
+            _ => '?',
+        })
+        .collect()
+}
+
+struct SimSms {
+    originator: String,
+    protocol_identifier: u8,
+    data_coding_scheme: u8,
+    // the command packet's security parameter indicator and target
+    // application, if it's a secured packet
+    spi: Option<[u8; 2]>,
+    tar: Option<[u8; 3]>,
+}
+
+impl SimSms {
+    // Unwraps an SMS-DELIVER from the CP-DATA and RP-DATA layers a Downlink
+    // NAS Transport carries it in (24.011 7.2 and 7.3), returning it if it's
+    // addressed to the SIM
+    fn from_cp_data(cp_data: &[u8]) -> Option<Self> {
+        // protocol discriminator 9 is SMS, 0x01 is CP-DATA
+        if cp_data.len() < 3 || cp_data[0] & 0x0f != 0x09 || cp_data[1] != 0x01 {
+            return None;
+        }
+        let rpdu = cp_data.get(3..3 + usize::from(cp_data[2]))?;
+        // RP-DATA from the network to the UE
+        if *rpdu.first()? & 0x07 != 0x01 {
+            return None;
+        }
+        // skip the message reference and the originator (SMSC) and
+        // destination addresses
+        let mut offset = 2;
+        offset += 1 + usize::from(*rpdu.get(offset)?);
+        offset += 1 + usize::from(*rpdu.get(offset)?);
+        let tpdu_len = usize::from(*rpdu.get(offset)?);
+        let tpdu = rpdu.get(offset + 1..offset + 1 + tpdu_len)?;
+        Self::from_sms_deliver(tpdu)
+    }
+
+    // Parses an SMS-DELIVER TPDU, per 23.040 9.2.2.1
+    fn from_sms_deliver(tpdu: &[u8]) -> Option<Self> {
+        let first_octet = *tpdu.first()?;
+        if first_octet & 0x03 != 0x00 {
+            return None;
+        }
+        let has_header = first_octet & 0x40 != 0;
+        let address_digits = usize::from(*tpdu.get(1)?);
+        let address_type = *tpdu.get(2)?;
+        let address = tpdu.get(3..3 + address_digits.div_ceil(2))?;
+        let originator = match (address_type >> 4) & 0x07 {
+            // alphanumeric addresses are packed GSM 7-bit text
+            0x05 => format!(
+                "alphanumeric {}",
+                address
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect::<String>()
+            ),
+            0x01 => format!("+{}", decode_digits(address, address_digits)),
+            _ => decode_digits(address, address_digits),
+        };
+        let mut offset = 3 + address.len();
+        let protocol_identifier = *tpdu.get(offset)?;
+        let data_coding_scheme = *tpdu.get(offset + 1)?;
+        // skip the service centre timestamp and user data length
+        offset += 2 + 7 + 1;
+        let user_data = tpdu.get(offset..).unwrap_or_default();
+
+        let command_packet = if has_header {
+            Self::find_command_packet(user_data)
+        } else {
+            None
+        };
+        let addressed_to_sim = matches!(
+            protocol_identifier,
+            PID_SIM_DATA_DOWNLOAD | PID_ANSI_136_R_DATA
+        ) || is_sim_specific_data(data_coding_scheme)
+            || command_packet.is_some();
+        if !addressed_to_sim {
+            return None;
+        }
+        Some(SimSms {
+            originator,
+            protocol_identifier,
+            data_coding_scheme,
+            spi: command_packet.and_then(|packet| packet.get(3..5)?.try_into().ok()),
+            tar: command_packet.and_then(|packet| packet.get(7..10)?.try_into().ok()),
+        })
+    }
+
+    // Returns the command packet following the user data header, if the
+    // header says there's one (31.115 4.2)
+    fn find_command_packet(user_data: &[u8]) -> Option<&[u8]> {
+        let header_len = usize::from(*user_data.first()?);
+        let header = user_data.get(1..1 + header_len)?;
+        let mut offset = 0;
+        while offset + 1 < header.len() {
+            let (iei, len) = (header[offset], usize::from(header[offset + 1]));
+            if iei == IEI_COMMAND_PACKET {
+                return user_data.get(1 + header_len..);
+            }
+            offset += 2 + len;
+        }
+        None
+    }
+
+    // The packet is secured if the SPI asks for a checksum, signature or
+    // ciphering, per 31.115 4.2 / 102.225 5.1.1
+    fn is_secured(spi: [u8; 2]) -> bool {
+        spi[0] & 0x0b != 0
+    }
+}
+
+// Binary SMS addressed to the SIM itself rather than the user, such as
+// SMS-PP data downloads and OTA command packets, are how carriers manage SIM
+// cards remotely. They're also how attacks like Simjacker reach applications
+// on the SIM, so this reports each one along with where it came from and the
+// SIM application it targets. Carriers do send these legitimately, so
+// findings are informational unless configured otherwise, and originators on
+// the allowlist aren't reported at all.
+//
+// Only SMS delivered over NAS are visible here. SMS over IMS is carried in
+// SIP, which isn't logged.
+pub struct SimOtaAnalyzer {
+    packet_num: usize,
+    originator_allowlist: Vec<String>,
+    redact_originator: bool,
+    severity: Option<Severity>,
+    serving_cell: Option<CellGlobalIdentity>,
+}
+
+impl SimOtaAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: SimOtaSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            originator_allowlist: settings
+                .originator_allowlist
+                .iter()
+                .map(|originator| originator.trim().trim_start_matches('+').to_string())
+                .collect(),
+            redact_originator: settings.redact_originator,
+            severity: settings.severity,
+            serving_cell: None,
+        }
+    }
+
+    fn is_allowlisted(&self, originator: &str) -> bool {
+        let originator = originator.trim_start_matches('+');
+        self.originator_allowlist
+            .iter()
+            .any(|allowed| allowed == originator)
+    }
+
+    fn display_originator(&self, originator: &str) -> String {
+        if !self.redact_originator {
+            return originator.to_string();
+        }
+        let chars: Vec<char> = originator.chars().collect();
+        let shown = chars.len().saturating_sub(2);
+        chars
+            .iter()
+            .enumerate()
+            .map(|(i, c)| if i < shown { '*' } else { *c })
+            .collect()
+    }
+
+    fn handle_sms(&self, sms: SimSms) -> Option<Event> {
+        if self.is_allowlisted(&sms.originator) {
+            return None;
+        }
+        let originator = self.display_originator(&sms.originator);
+        let event_type = match self.severity {
+            Some(severity) => EventType::QualitativeWarning { severity },
+            None => EventType::Informational,
+        };
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("originator".to_string(), originator.clone()),
+            (
+                "protocol_identifier".to_string(),
+                format!("{:#04x}", sms.protocol_identifier),
+            ),
+            (
+                "data_coding_scheme".to_string(),
+                format!("{:#04x}", sms.data_coding_scheme),
+            ),
+        ]);
+        let target = match sms.tar {
+            Some(tar) => {
+                let tar_hex: String = tar.iter().map(|byte| format!("{byte:02x}")).collect();
+                evidence.push(("tar".to_string(), tar_hex.clone()));
+                evidence.push(("application".to_string(), describe_tar(tar).to_string()));
+                format!(" targeting {} (TAR {tar_hex})", describe_tar(tar))
+            }
+            None => String::new(),
+        };
+        if let Some(spi) = sms.spi {
+            evidence.push(("spi".to_string(), format!("{:02x}{:02x}", spi[0], spi[1])));
+            evidence.push(("secured".to_string(), SimSms::is_secured(spi).to_string()));
+        }
+        evidence.push(("frame".to_string(), self.packet_num.to_string()));
+        let unsecured = sms.spi.is_some_and(|spi| !SimSms::is_secured(spi));
+        Some(Event {
+            event_type,
+            message: format!(
+                "Network sent a {}binary SMS addressed to the SIM from {originator}{target}",
+                if unsecured { "unsecured " } else { "" }
+            ),
+            explanation: "This SMS was addressed to the SIM card rather than to you. Carriers use messages like this to update SIM cards over the air, but attackers can use them to reach applications on the SIM, as in the Simjacker attack, particularly when the message isn't secured. If the originator is one of your carrier's, add it to the allowlist.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for SimOtaAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("SIM-Targeted SMS")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the network delivers binary SMS addressed to the SIM (SMS-PP data download, OTA command packets), which carriers use to manage SIMs but attacks like Simjacker abuse. Informational by default, and originators on the allowlist are ignored.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        let LteInformationElement::NAS(NASMessage::EMMMessage(
+            EMMMessage::EMMDownlinkNASTransport(transport),
+        )) = &**inner
+        else {
+            return None;
+        };
+        let sms = SimSms::from_cp_data(&transport.nas_container.inner)?;
+        self.handle_sms(sms)
+    }
+}
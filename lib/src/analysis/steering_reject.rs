@@ -0,0 +1,179 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt::Debug;
+
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, emm_cause_value, unpack_sib1};
+
+// EMM causes that tell the UE to look for service elsewhere, per 24.301
+// annex A
+const STEERING_CAUSES: [(u8, &str); 4] = [
+    (11, "PLMN not allowed"),
+    (12, "Tracking area not allowed"),
+    (13, "Roaming not allowed in this tracking area"),
+    (15, "No suitable cells in tracking area"),
+];
+
+fn steering_cause(cause: &impl Debug) -> Option<(u8, &'static str)> {
+    let value = emm_cause_value(cause)?;
+    STEERING_CAUSES.iter().copied().find(|(v, _)| *v == value)
+}
+
+struct SteeringReject {
+    procedure: &'static str,
+    cause: u8,
+    cause_name: &'static str,
+    cell: Option<CellGlobalIdentity>,
+    frame: usize,
+}
+
+// Some reject causes make the UE bar the cell's tracking area (or the whole
+// PLMN) and go looking for another one. A real network uses them when the UE
+// isn't allowed there, but a catcher can use them to push the UE off its
+// legitimate cell and onto the catcher's own. Such a reject is worth noting
+// on its own, and more so if the UE then camps on a cell it's never been on
+// before.
+pub struct SteeringRejectAnalyzer {
+    packet_num: usize,
+    serving_cell: Option<CellGlobalIdentity>,
+    seen_cells: HashSet<CellGlobalIdentity>,
+    // the last steering reject, until the UE camps on another cell
+    pending: Option<SteeringReject>,
+}
+
+impl Default for SteeringRejectAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SteeringRejectAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            packet_num: 0,
+            serving_cell: None,
+            seen_cells: HashSet::new(),
+            pending: None,
+        }
+    }
+
+    fn handle_reject(&mut self, procedure: &'static str, cause: &impl Debug) -> Option<Event> {
+        let (cause, cause_name) = steering_cause(cause)?;
+        let mut evidence = vec![
+            ("procedure".to_string(), procedure.to_string()),
+            ("emm_cause".to_string(), format!("#{cause} ({cause_name})")),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ];
+        if let Some(cell) = &self.serving_cell {
+            evidence.insert(0, ("cell".to_string(), cell.to_string()));
+        }
+        self.pending = Some(SteeringReject {
+            procedure,
+            cause,
+            cause_name,
+            cell: self.serving_cell.clone(),
+            frame: self.packet_num,
+        });
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Low,
+            },
+            message: format!(
+                "Network rejected {procedure} with cause #{cause} ({cause_name}) (frame {})",
+                self.packet_num
+            ),
+            explanation: "The network rejected the UE with a cause that makes it look for service on another cell. Real networks do this when the UE isn't allowed in an area, but it's also a way to steer a UE toward a fake base station.".to_string(),
+            evidence,
+        })
+    }
+
+    fn handle_sib1(&mut self, cell: CellGlobalIdentity) -> Option<Event> {
+        let first_visit = self.seen_cells.insert(cell.clone());
+        if self.serving_cell.as_ref() == Some(&cell) {
+            return None;
+        }
+        self.serving_cell = Some(cell.clone());
+        // whatever happens next, this was the camp event following the reject
+        let reject = self.pending.take()?;
+        if !first_visit {
+            return None;
+        }
+
+        let mut evidence = vec![
+            ("new_cell".to_string(), cell.to_string()),
+            ("procedure".to_string(), reject.procedure.to_string()),
+            (
+                "emm_cause".to_string(),
+                format!("#{} ({})", reject.cause, reject.cause_name),
+            ),
+            ("reject_frame".to_string(), reject.frame.to_string()),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ];
+        let previous_cell = match &reject.cell {
+            Some(previous_cell) => {
+                evidence.insert(0, ("previous_cell".to_string(), previous_cell.to_string()));
+                previous_cell.to_string()
+            }
+            None => "an unknown cell".to_string(),
+        };
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!(
+                "UE moved from {previous_cell} to never-before-seen cell {cell} after a {} reject with cause #{} ({})",
+                reject.procedure, reject.cause, reject.cause_name
+            ),
+            explanation: "Right after the network rejected the UE with a cause telling it to look elsewhere, the UE camped on a cell it hadn't been on before. That's what being steered onto a fake base station looks like, though it also happens when legitimately moving into another operator's or area's coverage.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for SteeringRejectAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Steering Reject")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the network rejects an attach, tracking area update or service request with a cause that sends the UE looking for another cell (e.g. #15, no suitable cells in tracking area), and whether the UE then camps on a cell it hasn't been on before.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            return self.handle_sib1(CellGlobalIdentity::from_sib1(sib1)?);
+        }
+
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        let LteInformationElement::NAS(NASMessage::EMMMessage(emm)) = &**inner else {
+            return None;
+        };
+        match emm {
+            EMMMessage::EMMAttachReject(reject) => self.handle_reject("Attach", &reject.emm_cause),
+            EMMMessage::EMMTrackingAreaUpdateReject(reject) => {
+                self.handle_reject("Tracking Area Update", &reject.emm_cause)
+            }
+            EMMMessage::EMMServiceReject(reject) => {
+                self.handle_reject("Service Request", &reject.emm_cause)
+            }
+            _ => None,
+        }
+    }
+}
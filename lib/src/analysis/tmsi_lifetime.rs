@@ -0,0 +1,257 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, unpack_sib1};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TmsiLifetimeSettings {
+    // how soon after a reallocation the permanent identity has to be asked
+    // for again for the temporary identity to count as short-lived
+    pub max_lifetime_secs: u64,
+    // how many short-lived temporary identities within window_secs are
+    // reported
+    pub min_exposures: usize,
+    pub window_secs: u64,
+}
+
+impl Default for TmsiLifetimeSettings {
+    fn default() -> Self {
+        Self {
+            max_lifetime_secs: 300,
+            min_exposures: 3,
+            window_secs: 3600,
+        }
+    }
+}
+
+impl AnalyzerSettings for TmsiLifetimeSettings {
+    const KEY: &'static str = "tmsi_lifetime";
+}
+
+// A reallocation or identity request, kept for the finding's timeline
+struct TimelineEntry {
+    timestamp: DateTime<FixedOffset>,
+    frame: usize,
+    what: &'static str,
+}
+
+// The IMSI requested analyzer reports identity requests that come out of
+// nowhere, which can happen legitimately now and then. This looks for the
+// pattern they make together with temporary identity (GUTI/TMSI)
+// reallocations when a network is tracking the UE: it keeps handing the UE a
+// new temporary identity, then shortly afterwards acts as if it doesn't know
+// it and asks for the IMSI, so the temporary identity never gets to protect
+// the IMSI.
+//
+// A temporary identity counts as short-lived when an identity request follows
+// its reallocation within max_lifetime_secs. This warns when min_exposures of
+// those happen within window_secs, with the timeline of reallocations and
+// identity requests leading up to it. IMSI paging isn't counted, as pages in
+// a cell are mostly for other UEs.
+pub struct TmsiLifetimeAnalyzer {
+    packet_num: usize,
+    max_lifetime: TimeDelta,
+    min_exposures: usize,
+    window: TimeDelta,
+    serving_cell: Option<CellGlobalIdentity>,
+    // when the UE was last given a temporary identity that hasn't been
+    // followed by an identity request yet
+    last_reallocation: Option<DateTime<FixedOffset>>,
+    // each reallocation and identity request within the window, oldest
+    // first
+    timeline: VecDeque<TimelineEntry>,
+    // when each short-lived temporary identity within the window was
+    // followed by an identity request, oldest first
+    exposures: VecDeque<DateTime<FixedOffset>>,
+}
+
+fn seconds(secs: u64) -> TimeDelta {
+    TimeDelta::try_seconds(i64::try_from(secs).unwrap_or(i64::MAX)).unwrap_or(TimeDelta::MAX)
+}
+
+impl TmsiLifetimeAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: TmsiLifetimeSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            max_lifetime: seconds(settings.max_lifetime_secs),
+            min_exposures: settings.min_exposures,
+            window: seconds(settings.window_secs),
+            serving_cell: None,
+            last_reallocation: None,
+            timeline: VecDeque::new(),
+            exposures: VecDeque::new(),
+        }
+    }
+
+    fn expire(&mut self, timestamp: DateTime<FixedOffset>) {
+        while let Some(oldest) = self.timeline.front() {
+            if timestamp - oldest.timestamp <= self.window {
+                break;
+            }
+            self.timeline.pop_front();
+        }
+        while let Some(oldest) = self.exposures.front() {
+            if timestamp - *oldest <= self.window {
+                break;
+            }
+            self.exposures.pop_front();
+        }
+    }
+
+    fn handle_reallocation(
+        &mut self,
+        what: &'static str,
+        timestamp: Option<DateTime<FixedOffset>>,
+    ) {
+        let Some(timestamp) = timestamp else {
+            return;
+        };
+        self.expire(timestamp);
+        self.timeline.push_back(TimelineEntry {
+            timestamp,
+            frame: self.packet_num,
+            what,
+        });
+        self.last_reallocation = Some(timestamp);
+    }
+
+    fn handle_identity_request(
+        &mut self,
+        timestamp: Option<DateTime<FixedOffset>>,
+    ) -> Option<Event> {
+        let timestamp = timestamp?;
+        self.expire(timestamp);
+        self.timeline.push_back(TimelineEntry {
+            timestamp,
+            frame: self.packet_num,
+            what: "Identity Request",
+        });
+        // only the first identity request after a reallocation says
+        // anything about how long the temporary identity lasted
+        let reallocation = self.last_reallocation.take()?;
+        let lifetime = timestamp - reallocation;
+        if lifetime > self.max_lifetime {
+            return None;
+        }
+        self.exposures.push_back(timestamp);
+        if self.exposures.len() < self.min_exposures {
+            return None;
+        }
+
+        let count = self.exposures.len();
+        let start = self.timeline.front().map(|entry| entry.timestamp)?;
+        let timeline: Vec<String> = self
+            .timeline
+            .iter()
+            .map(|entry| {
+                format!(
+                    "+{}s {} (frame {})",
+                    (entry.timestamp - start).num_seconds(),
+                    entry.what,
+                    entry.frame
+                )
+            })
+            .collect();
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("short_lived_identities".to_string(), count.to_string()),
+            (
+                "last_lifetime_secs".to_string(),
+                lifetime.num_seconds().to_string(),
+            ),
+            (
+                "max_lifetime_secs".to_string(),
+                self.max_lifetime.num_seconds().to_string(),
+            ),
+            ("timeline_start".to_string(), start.to_rfc3339()),
+            ("timeline".to_string(), timeline.join("; ")),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        // start counting afresh, so ongoing tracking is reported once per
+        // window rather than on every identity request
+        self.timeline.clear();
+        self.exposures.clear();
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!(
+                "The network asked for the UE's identity shortly after reallocating its temporary identity {count} times (last after {}s)",
+                lifetime.num_seconds()
+            ),
+            explanation: "The network repeatedly gave the UE a new temporary identity and then, shortly afterwards, asked for its permanent identity (IMSI) anyway. Temporary identities exist so the IMSI doesn't have to be sent over the air, so a network that keeps making them useless this way is exposing the IMSI again and again, which is a pattern used to track a UE. A network that's losing track of UEs because of a fault can cause this too.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for TmsiLifetimeAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Short-Lived Temporary Identity")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the network repeatedly (min_exposures times within window_secs) sends an Identity Request within max_lifetime_secs of reallocating the UE's temporary identity (GUTI), forcing repeated IMSI exposure.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        let LteInformationElement::NAS(NASMessage::EMMMessage(emm)) = &**inner else {
+            return None;
+        };
+        match emm {
+            EMMMessage::EMMGUTIReallocCommand(_) => {
+                self.handle_reallocation("GUTI Reallocation Command", ctx.timestamp);
+                None
+            }
+            EMMMessage::EMMAttachAccept(accept) if accept.guti.is_some() => {
+                self.handle_reallocation("Attach Accept with a new GUTI", ctx.timestamp);
+                None
+            }
+            EMMMessage::EMMTrackingAreaUpdateAccept(accept) if accept.guti.is_some() => {
+                self.handle_reallocation(
+                    "Tracking Area Update Accept with a new GUTI",
+                    ctx.timestamp,
+                );
+                None
+            }
+            EMMMessage::EMMIdentityRequest(_) => self.handle_identity_request(ctx.timestamp),
+            _ => None,
+        }
+    }
+}
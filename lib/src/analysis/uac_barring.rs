@@ -0,0 +1,259 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashSet};
+use std::ops::RangeInclusive;
+
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    SystemInformationBlockType25_r15, UAC_BarringInfoSetList_r15, UAC_BarringPerCatList_r15,
+    UAC_BarringPerPLMN_r15Uac_AC_BarringListType_r15,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::InformationElement;
+use super::util::{CellGlobalIdentity, unpack_sib1, unpack_sib25};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+// uac-BarringFactor as the percentage of access attempts let through, per
+// 36.331
+const BARRING_FACTOR_PERCENTS: [u8; 16] =
+    [0, 5, 10, 15, 20, 25, 30, 40, 50, 60, 70, 75, 80, 85, 90, 95];
+// The access categories ordinary UEs use to get service: MO signalling and
+// MO data
+const NORMAL_CATEGORIES: [u8; 2] = [3, 7];
+// The standardized access categories, per 24.301 4.8.2. 32 and up are
+// operator-defined.
+const STANDARD_CATEGORIES: RangeInclusive<u8> = 1..=10;
+
+fn describe_category(category: u8) -> String {
+    let name = match category {
+        1 => "delay tolerant",
+        2 => "emergency",
+        3 => "MO signalling",
+        4 => "MMTEL voice",
+        5 => "MMTEL video",
+        6 => "SMS",
+        7 => "MO data",
+        8 => "MO signalling on RRC level",
+        9 => "MO IMS registration",
+        10 => "MO exception data",
+        32..=63 => "operator-defined",
+        _ => "reserved",
+    };
+    format!("{category} ({name})")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UacBarringSettings {
+    // a category counts as denied when at most this percentage of its
+    // access attempts are let through
+    pub max_denied_percent: u8,
+    // report as a warning of this severity rather than informationally
+    pub warning_severity: Option<Severity>,
+}
+
+impl Default for UacBarringSettings {
+    fn default() -> Self {
+        Self {
+            max_denied_percent: 0,
+            warning_severity: None,
+        }
+    }
+}
+
+impl AnalyzerSettings for UacBarringSettings {
+    const KEY: &'static str = "uac_barring";
+}
+
+// Returns the percentage of access attempts let through by the info set at
+// the given (1-based) index
+fn info_set_percent(info_sets: &UAC_BarringInfoSetList_r15, index: u8) -> Option<u8> {
+    let info_set = info_sets.0.get(usize::from(index).checked_sub(1)?)?;
+    BARRING_FACTOR_PERCENTS
+        .get(usize::from(info_set.uac_barring_factor_r15.0))
+        .copied()
+}
+
+// Returns the percentage of access attempts let through for each barred
+// category in a list
+fn barred_categories(
+    list: &UAC_BarringPerCatList_r15,
+    info_sets: &UAC_BarringInfoSetList_r15,
+) -> BTreeMap<u8, u8> {
+    list.0
+        .iter()
+        .filter_map(|barring| {
+            let percent = info_set_percent(info_sets, barring.uac_barring_info_set_index_r15.0)?;
+            Some((barring.access_category_r15.0, percent))
+        })
+        .collect()
+}
+
+// Returns the barring that applies to UEs of the cell's primary PLMN, and
+// where it came from. A per-PLMN list replaces the common one.
+fn primary_plmn_barring(
+    sib25: &SystemInformationBlockType25_r15,
+) -> (BTreeMap<u8, u8>, &'static str) {
+    let info_sets = &sib25.uac_barring_info_set_list_r15;
+    let per_plmn = sib25
+        .uac_barring_per_plmn_list_r15
+        .as_ref()
+        .and_then(|list| {
+            list.0
+                .iter()
+                .find(|plmn| plmn.plmn_identity_index_r15.0 == 1)
+        })
+        .and_then(|plmn| plmn.uac_ac_barring_list_type_r15.as_ref());
+    match per_plmn {
+        Some(UAC_BarringPerPLMN_r15Uac_AC_BarringListType_r15::Uac_ExplicitAC_BarringList_r15(
+            list,
+        )) => (barred_categories(list, info_sets), "primary PLMN"),
+        // one info set for each category, in order
+        Some(UAC_BarringPerPLMN_r15Uac_AC_BarringListType_r15::Uac_ImplicitAC_BarringList_r15(
+            list,
+        )) => {
+            let barring = list
+                .0
+                .iter()
+                .zip(1..)
+                .filter_map(|(index, category)| {
+                    Some((category, info_set_percent(info_sets, index.0)?))
+                })
+                .collect();
+            (barring, "primary PLMN")
+        }
+        None => match &sib25.uac_barring_for_common_r15 {
+            Some(list) => (barred_categories(list, info_sets), "common"),
+            None => (BTreeMap::new(), "common"),
+        },
+    }
+}
+
+// Unified access control (UAC) bars access per access category rather than
+// per access class, so besides shedding load it can let some kinds of access
+// through while denying others. A cell that denies ordinary UEs access for
+// signalling or data, while still letting other categories in (e.g. voice or
+// emergency calls), can use that to choose which UEs get service, or to keep
+// targeted UEs from registering. Congested cells do this legitimately, so this
+// is informational unless configured otherwise.
+//
+// The UAC parameters for 5G are in the NR SIB1, which isn't decoded, so this
+// looks at the ones LTE cells connected to a 5G core broadcast in SIB25.
+// Barring for the cell's primary PLMN is used, as the UE's own PLMN isn't
+// known. Each barring configuration is reported once per cell.
+pub struct UacBarringAnalyzer {
+    packet_num: usize,
+    max_denied_percent: u8,
+    event_type: EventType,
+    serving_cell: Option<CellGlobalIdentity>,
+    reported: HashSet<(Option<CellGlobalIdentity>, Vec<(u8, u8)>)>,
+}
+
+impl UacBarringAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: UacBarringSettings = analyzer_config.get_settings();
+        let event_type = match settings.warning_severity {
+            Some(severity) => EventType::QualitativeWarning { severity },
+            None => EventType::Informational,
+        };
+        Self {
+            packet_num: 0,
+            max_denied_percent: settings.max_denied_percent,
+            event_type,
+            serving_cell: None,
+            reported: HashSet::new(),
+        }
+    }
+
+    fn handle_sib25(&mut self, sib25: &SystemInformationBlockType25_r15) -> Option<Event> {
+        let (barring, source) = primary_plmn_barring(sib25);
+        let is_denied = |category: &u8| {
+            barring
+                .get(category)
+                .is_some_and(|percent| *percent <= self.max_denied_percent)
+        };
+        let denied: Vec<u8> = NORMAL_CATEGORIES.into_iter().filter(is_denied).collect();
+        let permitted: Vec<u8> = STANDARD_CATEGORIES
+            .filter(|category| !NORMAL_CATEGORIES.contains(category) && !is_denied(category))
+            .collect();
+        // barring everything is plain congestion control
+        if denied.is_empty() || permitted.is_empty() {
+            return None;
+        }
+        let key = (
+            self.serving_cell.clone(),
+            barring.iter().map(|(c, p)| (*c, *p)).collect(),
+        );
+        if !self.reported.insert(key) {
+            return None;
+        }
+
+        let describe = |categories: &[u8]| -> String {
+            categories
+                .iter()
+                .map(|category| describe_category(*category))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let barred: Vec<String> = barring
+            .iter()
+            .map(|(category, percent)| format!("{} at {percent}%", describe_category(*category)))
+            .collect();
+        let mut evidence = Vec::new();
+        if let Some(cell) = &self.serving_cell {
+            evidence.push(("cell".to_string(), cell.to_string()));
+        }
+        evidence.extend([
+            ("denied_categories".to_string(), describe(&denied)),
+            ("permitted_categories".to_string(), describe(&permitted)),
+            ("barred_categories".to_string(), barred.join("; ")),
+            ("barring_source".to_string(), source.to_string()),
+            ("frame".to_string(), self.packet_num.to_string()),
+        ]);
+        Some(Event {
+            event_type: self.event_type.clone(),
+            message: format!(
+                "Cell denies access category {} while permitting {} other categories",
+                describe(&denied),
+                permitted.len()
+            ),
+            explanation: "The cell's unified access control settings deny ordinary signalling or data access while still letting other kinds of access through. Congested cells can do this to shed load, but it can also be used to pick which UEs get service, or to keep some from registering at all.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for UacBarringAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Selective Access Category Barring")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether a cell's unified access control (UAC) barring in SIB25 denies normal access (MO signalling or data, at most max_denied_percent let through) while permitting other access categories. Congestion can cause this too, so it's informational unless warning_severity is set.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.serving_cell = CellGlobalIdentity::from_sib1(sib1);
+            return None;
+        }
+        self.handle_sib25(unpack_sib25(ie)?)
+    }
+}
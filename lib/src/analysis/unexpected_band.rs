@@ -0,0 +1,165 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::InformationElement;
+use super::util::{CellGlobalIdentity, advertised_plmns, lte_band, normalize_plmn, unpack_sib1};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+fn default_plmn_bands() -> HashMap<String, Vec<u16>> {
+    let bands: [(&str, &[u16]); 11] = [
+        // US
+        ("310-410", &[2, 4, 5, 12, 14, 17, 29, 30, 66]),
+        ("311-480", &[2, 4, 5, 13, 46, 48, 66]),
+        ("310-260", &[2, 4, 5, 12, 41, 66, 71]),
+        // UK
+        ("234-10", &[1, 3, 8, 20, 40]),
+        ("234-15", &[1, 3, 7, 8, 20, 32, 38]),
+        ("234-20", &[1, 3, 20, 32]),
+        ("234-30", &[1, 3, 7, 20, 38]),
+        // Germany
+        ("262-01", &[1, 3, 7, 8, 20, 28, 32]),
+        ("262-02", &[1, 3, 7, 8, 20, 28, 32, 38]),
+        ("262-03", &[1, 3, 7, 8, 20, 28]),
+        // France
+        ("208-01", &[1, 3, 7, 20, 28]),
+    ];
+    bands
+        .into_iter()
+        .map(|(plmn, bands)| (plmn.to_string(), bands.to_vec()))
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UnexpectedBandSettings {
+    // LTE bands each PLMN ("MCC-MNC") is licensed to use. Setting this
+    // replaces the built-in mapping entirely.
+    pub plmn_bands: HashMap<String, Vec<u16>>,
+    // merged over plmn_bands, for adding or correcting a few PLMNs without
+    // losing the built-in ones
+    pub additional_plmn_bands: HashMap<String, Vec<u16>>,
+}
+
+impl Default for UnexpectedBandSettings {
+    fn default() -> Self {
+        Self {
+            plmn_bands: default_plmn_bands(),
+            additional_plmn_bands: HashMap::new(),
+        }
+    }
+}
+
+impl AnalyzerSettings for UnexpectedBandSettings {
+    const KEY: &'static str = "unexpected_band";
+}
+
+// Operators only transmit in the bands they hold licenses for, while a
+// catcher will often broadcast a cloned PLMN on whatever frequency its
+// hardware or operator picked. This compares the band of the frequency each
+// SIB1 was received on with the bands known for the PLMNs it advertises.
+// PLMNs missing from the mapping are ignored.
+pub struct UnexpectedBandAnalyzer {
+    packet_num: usize,
+    plmn_bands: HashMap<String, Vec<u16>>,
+    reported: HashSet<(CellGlobalIdentity, u32)>,
+}
+
+impl UnexpectedBandAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: UnexpectedBandSettings = analyzer_config.get_settings();
+        let mut plmn_bands = HashMap::new();
+        for (plmn, bands) in settings
+            .plmn_bands
+            .into_iter()
+            .chain(settings.additional_plmn_bands)
+        {
+            match normalize_plmn(&plmn) {
+                Some(plmn) => {
+                    plmn_bands.insert(plmn, bands);
+                }
+                None => warn!("ignoring invalid PLMN in analyzer config: {plmn:?}"),
+            }
+        }
+        Self {
+            packet_num: 0,
+            plmn_bands,
+            reported: HashSet::new(),
+        }
+    }
+}
+
+impl Analyzer for UnexpectedBandAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Unexpected Band")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether an LTE cell broadcasts on a frequency outside the bands its advertised PLMN is known to be licensed for. The built-in mapping only covers a few operators and may be out of date, so new deployments can trigger false positives.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let sib1 = unpack_sib1(ie)?;
+        let earfcn = ctx.earfcn?;
+        let band = lte_band(earfcn)?;
+        let plmns = advertised_plmns(sib1);
+        let expected: Vec<(&String, &Vec<u16>)> = plmns
+            .iter()
+            .filter_map(|plmn| Some((plmn, self.plmn_bands.get(plmn)?)))
+            .collect();
+        // shared cells may be licensed to any one of their PLMNs
+        if expected.is_empty() || expected.iter().any(|(_, bands)| bands.contains(&band)) {
+            return None;
+        }
+        let cell = CellGlobalIdentity::from_sib1(sib1)?;
+        if !self.reported.insert((cell.clone(), earfcn)) {
+            return None;
+        }
+
+        let expected_bands: Vec<String> = expected
+            .iter()
+            .map(|(plmn, bands)| {
+                let bands: Vec<String> = bands.iter().map(u16::to_string).collect();
+                format!("{plmn}: {}", bands.join("/"))
+            })
+            .collect();
+        let plmns = plmns.join(", ");
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!(
+                "Cell advertising PLMN(s) {plmns} is on EARFCN {earfcn} (band {band}), outside the bands known for them: {cell}"
+            ),
+            explanation: "The cell broadcasts on a band that none of the operators it claims to belong to are known to use. Fake base stations often clone a real operator's PLMN without using its frequencies, though operators do acquire new spectrum, so check the mapping is current.".to_string(),
+            evidence: vec![
+                ("cell".to_string(), cell.to_string()),
+                ("plmns".to_string(), plmns),
+                ("earfcn".to_string(), earfcn.to_string()),
+                ("band".to_string(), band.to_string()),
+                ("expected_bands".to_string(), expected_bands.join("; ")),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+}
@@ -0,0 +1,181 @@
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use log::warn;
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::SystemInformationBlockType1;
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use super::util::{CellGlobalIdentity, advertised_plmns, normalize_plmn, unpack_sib1};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UnexpectedPlmnSettings {
+    // PLMNs as "MCC-MNC" (e.g. "310-410"): the SIM's home and equivalent
+    // PLMNs, plus any roaming partners it's expected to use
+    pub home_plmns: Vec<String>,
+    pub roaming_plmns: Vec<String>,
+}
+
+impl AnalyzerSettings for UnexpectedPlmnSettings {
+    const KEY: &'static str = "unexpected_plmn";
+}
+
+fn normalize_plmns(plmns: &[String]) -> Vec<String> {
+    let mut normalized = Vec::new();
+    for plmn in plmns {
+        match normalize_plmn(plmn) {
+            Some(plmn) => normalized.push(plmn),
+            None => warn!("ignoring invalid PLMN in analyzer config: {plmn:?}"),
+        }
+    }
+    normalized
+}
+
+// A SIM only ever registers on its home PLMN, equivalent PLMNs, or roaming
+// partners. A cell that advertises none of those is either a network the UE
+// shouldn't be using, or a catcher that didn't bother to clone the right
+// MCC/MNC. This needs to know the SIM's PLMNs, so it does nothing unless
+// they're configured.
+pub struct UnexpectedPlmnAnalyzer {
+    packet_num: usize,
+    allowed_plmns: Vec<String>,
+    // the cell the UE is currently camped on, if it advertises no allowed
+    // PLMN, along with what it does advertise
+    unexpected_cell: Option<(CellGlobalIdentity, Vec<String>)>,
+    reported_camping: HashSet<CellGlobalIdentity>,
+    reported_attach: HashSet<CellGlobalIdentity>,
+}
+
+impl UnexpectedPlmnAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: UnexpectedPlmnSettings = analyzer_config.get_settings();
+        let mut allowed_plmns = normalize_plmns(&settings.home_plmns);
+        if allowed_plmns.is_empty() {
+            warn!("no home PLMNs configured, the unexpected PLMN analyzer won't report anything");
+        } else {
+            allowed_plmns.extend(normalize_plmns(&settings.roaming_plmns));
+        }
+        Self {
+            packet_num: 0,
+            allowed_plmns,
+            unexpected_cell: None,
+            reported_camping: HashSet::new(),
+            reported_attach: HashSet::new(),
+        }
+    }
+
+    fn make_event(
+        &self,
+        severity: Severity,
+        message: String,
+        explanation: &str,
+        cell: &CellGlobalIdentity,
+        observed: &[String],
+    ) -> Event {
+        Event {
+            event_type: EventType::QualitativeWarning { severity },
+            message,
+            explanation: explanation.to_string(),
+            evidence: vec![
+                ("cell".to_string(), cell.to_string()),
+                ("observed_plmns".to_string(), observed.join(", ")),
+                ("allowed_plmns".to_string(), self.allowed_plmns.join(", ")),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        }
+    }
+
+    fn handle_sib1(&mut self, sib1: &SystemInformationBlockType1) -> Option<Event> {
+        let cell = CellGlobalIdentity::from_sib1(sib1)?;
+        let observed = advertised_plmns(sib1);
+        if observed
+            .iter()
+            .any(|plmn| self.allowed_plmns.contains(plmn))
+        {
+            self.unexpected_cell = None;
+            return None;
+        }
+        self.unexpected_cell = Some((cell.clone(), observed.clone()));
+        if !self.reported_camping.insert(cell.clone()) {
+            return None;
+        }
+        Some(self.make_event(
+            Severity::Low,
+            format!(
+                "UE camped on a cell advertising unexpected PLMN(s) {}: {cell}",
+                observed.join(", ")
+            ),
+            "The cell the UE is camped on doesn't advertise any of the home or roaming PLMNs configured for this SIM. This may be a catcher, or a roaming partner that's missing from the config.",
+            &cell,
+            &observed,
+        ))
+    }
+
+    fn handle_registration(&mut self, procedure: &str) -> Option<Event> {
+        let (cell, observed) = self.unexpected_cell.as_ref()?;
+        if self.reported_attach.contains(cell) {
+            return None;
+        }
+        let event = self.make_event(
+            Severity::Medium,
+            format!(
+                "UE sent {procedure} on a cell advertising unexpected PLMN(s) {}: {cell}",
+                observed.join(", ")
+            ),
+            "The UE tried to register with a cell that doesn't advertise any of the home or roaming PLMNs configured for this SIM. A SIM shouldn't register on networks outside of those, so unless a roaming partner is missing from the config, this is suspicious.",
+            cell,
+            observed,
+        );
+        self.reported_attach.insert(cell.clone());
+        Some(event)
+    }
+}
+
+impl Analyzer for UnexpectedPlmnAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Unexpected PLMN")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the UE camps on or attaches to a cell which doesn't advertise any of the configured home or roaming PLMNs (MCC/MNC). Requires the SIM's PLMNs to be configured, and roaming partners missing from the config will trigger false positives.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if self.allowed_plmns.is_empty() {
+            return None;
+        }
+        if let Some(sib1) = unpack_sib1(ie) {
+            return self.handle_sib1(sib1);
+        }
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::NAS(NASMessage::EMMMessage(emm)) => match emm {
+                EMMMessage::EMMAttachRequest(_) => self.handle_registration("Attach Request"),
+                EMMMessage::EMMTrackingAreaUpdateRequest(_) => {
+                    self.handle_registration("Tracking Area Update Request")
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
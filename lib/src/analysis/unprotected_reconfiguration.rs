@@ -0,0 +1,271 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use telcom_parser::lte_rrc::{
+    DL_CCCH_MessageType, DL_CCCH_MessageType_c1, DL_DCCH_MessageType, DL_DCCH_MessageType_c1,
+    RRCConnectionReconfiguration, RRCConnectionReconfigurationCriticalExtensions,
+    RRCConnectionReconfigurationCriticalExtensions_c1, UL_CCCH_MessageType, UL_DCCH_MessageType,
+    UL_DCCH_MessageType_c1,
+};
+
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
+use super::information_element::{InformationElement, LteInformationElement};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+// Where an RRC connection is in activating AS security
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SecurityState {
+    NotActivated,
+    // the network sent a Security Mode Command, but the UE hasn't completed
+    // it yet
+    Commanded { command_frame: usize },
+    Active,
+}
+
+impl fmt::Display for SecurityState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecurityState::NotActivated => write!(f, "not activated"),
+            SecurityState::Commanded { command_frame } => write!(
+                f,
+                "Security Mode Command sent in frame {command_frame}, not yet completed"
+            ),
+            SecurityState::Active => write!(f, "active"),
+        }
+    }
+}
+
+struct Connection {
+    security: SecurityState,
+    reported: bool,
+}
+
+// Describes what a reconfiguration does that 36.331 (5.3.5.1) only allows once
+// AS security is active: setting up SRB2 or data bearers, or a handover. None
+// if it does none of those.
+fn describe_protected_contents(reconfiguration: &RRCConnectionReconfiguration) -> Option<String> {
+    let RRCConnectionReconfigurationCriticalExtensions::C1(
+        RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(r8),
+    ) = &reconfiguration.critical_extensions
+    else {
+        return None;
+    };
+    let mut contents = Vec::new();
+    if let Some(radio_resources) = &r8.radio_resource_config_dedicated {
+        if let Some(srbs) = &radio_resources.srb_to_add_mod_list {
+            if srbs.0.iter().any(|srb| srb.srb_identity.0 == 2) {
+                contents.push("sets up SRB2".to_string());
+            }
+        }
+        if let Some(drbs) = &radio_resources.drb_to_add_mod_list {
+            let ids: Vec<String> = drbs
+                .0
+                .iter()
+                .map(|drb| drb.drb_identity.0.to_string())
+                .collect();
+            contents.push(format!("sets up DRB(s) {}", ids.join(", ")));
+        }
+    }
+    if r8.mobility_control_info.is_some() {
+        contents.push("orders a handover".to_string());
+    }
+    if contents.is_empty() {
+        None
+    } else {
+        Some(contents.join(", "))
+    }
+}
+
+// Once AS security is active every RRC message on the connection is integrity
+// protected, but the modem only logs messages after PDCP has checked and
+// stripped that protection, so a message that arrived unprotected can't be
+// told apart directly. What can be seen is the network skipping security
+// altogether: 36.331 only allows bearers to be set up, or a handover to be
+// ordered, with security active, so a reconfiguration doing so without it
+// can't have been protected. A real network never does this, while a fake
+// cell without the UE's keys has no other way to give it a data connection.
+//
+// Security only counts as active once the UE's Security Mode Complete has
+// been seen, or the connection was re-established (which reuses an existing
+// context). If the capture doesn't include uplink messages, a Security Mode
+// Command is taken to have completed.
+pub struct UnprotectedReconfigurationAnalyzer {
+    packet_num: usize,
+    uplink_captured: bool,
+    connection: Option<Connection>,
+}
+
+impl Default for UnprotectedReconfigurationAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UnprotectedReconfigurationAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            packet_num: 0,
+            uplink_captured: false,
+            connection: None,
+        }
+    }
+
+    fn start_connection(&mut self, security: SecurityState) {
+        self.connection = Some(Connection {
+            security,
+            reported: false,
+        });
+    }
+
+    fn set_security(&mut self, security: SecurityState) {
+        if let Some(connection) = self.connection.as_mut() {
+            connection.security = security;
+        }
+    }
+
+    fn check_reconfiguration(
+        &mut self,
+        reconfiguration: &RRCConnectionReconfiguration,
+    ) -> Option<Event> {
+        let connection = self.connection.as_mut()?;
+        let security = connection.security;
+        let unprotected = match security {
+            SecurityState::NotActivated => true,
+            SecurityState::Commanded { .. } => self.uplink_captured,
+            SecurityState::Active => false,
+        };
+        if !unprotected || connection.reported {
+            return None;
+        }
+        let contents = describe_protected_contents(reconfiguration)?;
+        connection.reported = true;
+
+        let mut evidence = vec![
+            (
+                "message_type".to_string(),
+                "RRCConnectionReconfiguration".to_string(),
+            ),
+            ("contents".to_string(), contents.clone()),
+            ("security_state".to_string(), security.to_string()),
+            ("expected_security_state".to_string(), "active".to_string()),
+        ];
+        if let SecurityState::Commanded { command_frame } = security {
+            evidence.push((
+                "security_mode_command_frame".to_string(),
+                command_frame.to_string(),
+            ));
+        }
+        evidence.push(("frame".to_string(), self.packet_num.to_string()));
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::High,
+            },
+            message: format!(
+                "RRC Connection Reconfiguration {contents} before AS security was activated (security {security})"
+            ),
+            explanation: "The network reconfigured the connection in a way that's only allowed once AS security is active, but security was never activated on it, so the message couldn't have been integrity protected. Real networks don't do this, while a fake base station without the UE's keys might.".to_string(),
+            evidence,
+        })
+    }
+}
+
+impl Analyzer for UnprotectedReconfigurationAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Unprotected RRC Reconfiguration")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether an RRC Connection Reconfiguration that sets up bearers or orders a handover arrives before AS security has been activated on the connection, meaning it couldn't have been integrity protected.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::UlCcch(msg_cont) => {
+                // the UE is starting over with a new connection
+                if let UL_CCCH_MessageType::C1(_) = &msg_cont.message {
+                    self.uplink_captured = true;
+                    self.connection = None;
+                }
+                None
+            }
+            LteInformationElement::DlCcch(msg_cont) => {
+                match &msg_cont.message {
+                    DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionSetup(_)) => {
+                        self.start_connection(SecurityState::NotActivated);
+                    }
+                    // re-establishment resumes the existing security context
+                    DL_CCCH_MessageType::C1(
+                        DL_CCCH_MessageType_c1::RrcConnectionReestablishment(_),
+                    ) => {
+                        self.start_connection(SecurityState::Active);
+                    }
+                    _ => {}
+                }
+                None
+            }
+            LteInformationElement::UlDcch(msg_cont) => {
+                self.uplink_captured = true;
+                match &msg_cont.message {
+                    UL_DCCH_MessageType::C1(UL_DCCH_MessageType_c1::SecurityModeComplete(_)) => {
+                        self.set_security(SecurityState::Active);
+                    }
+                    UL_DCCH_MessageType::C1(UL_DCCH_MessageType_c1::SecurityModeFailure(_)) => {
+                        self.set_security(SecurityState::NotActivated);
+                    }
+                    _ => {}
+                }
+                None
+            }
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(c1) = &msg_cont.message else {
+                    return None;
+                };
+                match c1 {
+                    DL_DCCH_MessageType_c1::SecurityModeCommand(_) => {
+                        // without uplink there'd be no way to see it complete
+                        let security = if self.uplink_captured {
+                            SecurityState::Commanded {
+                                command_frame: self.packet_num,
+                            }
+                        } else {
+                            SecurityState::Active
+                        };
+                        self.set_security(security);
+                        None
+                    }
+                    DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(reconfiguration) => {
+                        self.check_reconfiguration(reconfiguration)
+                    }
+                    DL_DCCH_MessageType_c1::RrcConnectionRelease(_) => {
+                        self.connection = None;
+                        None
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
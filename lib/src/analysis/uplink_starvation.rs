@@ -0,0 +1,184 @@
+use std::borrow::Cow;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_CCCH_MessageType, DL_CCCH_MessageType_c1, DL_DCCH_MessageType, DL_DCCH_MessageType_c1,
+    UL_CCCH_MessageType,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UplinkStarvationSettings {
+    // how long a connected UE can go without sending anything while the
+    // network keeps talking to it
+    pub max_silent_uplink_secs: u64,
+}
+
+impl Default for UplinkStarvationSettings {
+    fn default() -> Self {
+        Self {
+            max_silent_uplink_secs: 30,
+        }
+    }
+}
+
+impl AnalyzerSettings for UplinkStarvationSettings {
+    const KEY: &'static str = "uplink_starvation";
+}
+
+struct Connection {
+    // when the UE last sent an RRC message on this connection, or when the
+    // connection was set up if it hasn't yet
+    last_uplink: DateTime<FixedOffset>,
+    last_uplink_frame: usize,
+    downlink_messages: usize,
+    reported: bool,
+}
+
+// A cell can hold a UE hostage by admitting it and then never granting it any
+// uplink resources. Rayhunter doesn't capture MAC scheduling, so this looks
+// at its effect instead: over an RRC connection the network keeps sending
+// messages on, the UE can't get a single one of its own out.
+//
+// Not every capture includes the UE's own RRC messages, so nothing is
+// reported until at least one has been seen.
+pub struct UplinkStarvationAnalyzer {
+    packet_num: usize,
+    max_silence: TimeDelta,
+    uplink_captured: bool,
+    connection: Option<Connection>,
+}
+
+impl UplinkStarvationAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: UplinkStarvationSettings = analyzer_config.get_settings();
+        let max_silent_secs = i64::try_from(settings.max_silent_uplink_secs).unwrap_or(i64::MAX);
+        Self {
+            packet_num: 0,
+            max_silence: TimeDelta::try_seconds(max_silent_secs).unwrap_or(TimeDelta::MAX),
+            uplink_captured: false,
+            connection: None,
+        }
+    }
+
+    fn handle_downlink(&mut self, timestamp: DateTime<FixedOffset>) -> Option<Event> {
+        let connection = self.connection.as_mut()?;
+        connection.downlink_messages += 1;
+        let silence = timestamp - connection.last_uplink;
+        if !self.uplink_captured || connection.reported || silence <= self.max_silence {
+            return None;
+        }
+
+        connection.reported = true;
+        let silent_secs = silence.num_seconds();
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Medium,
+            },
+            message: format!(
+                "UE hasn't sent anything for {silent_secs}s while the network sent {} messages on the same RRC connection",
+                connection.downlink_messages
+            ),
+            explanation: "The network kept sending RRC messages to the UE, but the UE didn't manage to send any of its own for an unusually long time. That can mean the cell isn't granting the UE any uplink resources, a way of keeping it stuck on a cell without service. Very poor uplink coverage can cause it too.".to_string(),
+            evidence: vec![
+                ("silent_uplink_secs".to_string(), silent_secs.to_string()),
+                (
+                    "downlink_messages".to_string(),
+                    connection.downlink_messages.to_string(),
+                ),
+                (
+                    "last_uplink_frame".to_string(),
+                    connection.last_uplink_frame.to_string(),
+                ),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+}
+
+impl Analyzer for UplinkStarvationAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Uplink Starvation")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the UE goes without sending any RRC messages for longer than max_silent_uplink_secs while the network keeps sending it messages over the same connection, which suggests the cell isn't granting it uplink resources. Poor uplink coverage can trigger this too.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let timestamp = ctx.timestamp?;
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::DlCcch(msg_cont) => {
+                if let DL_CCCH_MessageType::C1(
+                    DL_CCCH_MessageType_c1::RrcConnectionSetup(_)
+                    | DL_CCCH_MessageType_c1::RrcConnectionReestablishment(_),
+                ) = &msg_cont.message
+                {
+                    self.connection = Some(Connection {
+                        last_uplink: timestamp,
+                        last_uplink_frame: self.packet_num,
+                        downlink_messages: 0,
+                        reported: false,
+                    });
+                }
+                None
+            }
+            LteInformationElement::UlCcch(msg_cont) => {
+                // a new connection attempt, which also proves the UE got
+                // something out
+                if let UL_CCCH_MessageType::C1(_) = &msg_cont.message {
+                    self.uplink_captured = true;
+                    self.connection = None;
+                }
+                None
+            }
+            LteInformationElement::UlDcch(_) => {
+                self.uplink_captured = true;
+                if let Some(connection) = self.connection.as_mut() {
+                    connection.last_uplink = timestamp;
+                    connection.last_uplink_frame = self.packet_num;
+                }
+                None
+            }
+            LteInformationElement::DlDcch(msg_cont) => {
+                if let DL_DCCH_MessageType::C1(DL_DCCH_MessageType_c1::RrcConnectionRelease(_)) =
+                    &msg_cont.message
+                {
+                    self.connection = None;
+                    return None;
+                }
+                self.handle_downlink(timestamp)
+            }
+            _ => None,
+        }
+    }
+}
@@ -1,3 +1,17 @@
+use std::fmt::{self, Debug};
+use std::ops::{Deref, RangeInclusive};
+
+use telcom_parser::decode;
+use telcom_parser::lte_rrc::{
+    BCCH_DL_SCH_MessageType, BCCH_DL_SCH_MessageType_c1, MAX_FBI, PLMN_Identity,
+    SystemInformation_r8_IEsSib_TypeAndInfo_Entry, SystemInformationBlockType1,
+    SystemInformationBlockType1_v8h0_IEs, SystemInformationBlockType2,
+    SystemInformationBlockType10, SystemInformationBlockType11, SystemInformationBlockType16_r11,
+    SystemInformationBlockType25_r15, SystemInformationCriticalExtensions,
+};
+
+use super::information_element::{InformationElement, LteInformationElement};
+
 // Unpacks a pattern, or returns None.
 //
 // # Examples
@@ -31,3 +45,374 @@ macro_rules! unpack {
 
 // this is apparently how you make a macro publicly usable from this module
 pub(crate) use unpack;
+
+// Returns the SIB1 carried by the given element, if it's an LTE BCCH-DL-SCH
+// message containing one.
+pub(crate) fn unpack_sib1(ie: &InformationElement) -> Option<&SystemInformationBlockType1> {
+    unpack!(InformationElement::LTE(lte_ie) = ie);
+    unpack!(LteInformationElement::BcchDlSch(bcch_dl_sch) = &**lte_ie);
+    unpack!(
+        BCCH_DL_SCH_MessageType::C1(BCCH_DL_SCH_MessageType_c1::SystemInformationBlockType1(
+            sib1
+        )) = &bcch_dl_sch.message
+    );
+    Some(sib1)
+}
+
+// Returns the SIBs carried by the given element, if it's an LTE
+// SystemInformation message
+pub(crate) fn unpack_system_information(
+    ie: &InformationElement,
+) -> Option<&[SystemInformation_r8_IEsSib_TypeAndInfo_Entry]> {
+    let InformationElement::LTE(lte_ie) = ie else {
+        return None;
+    };
+    let LteInformationElement::BcchDlSch(bcch_dl_sch) = &**lte_ie else {
+        return None;
+    };
+    let BCCH_DL_SCH_MessageType::C1(BCCH_DL_SCH_MessageType_c1::SystemInformation(
+        system_information,
+    )) = &bcch_dl_sch.message
+    else {
+        return None;
+    };
+    let SystemInformationCriticalExtensions::SystemInformation_r8(r8) =
+        &system_information.critical_extensions
+    else {
+        return None;
+    };
+    Some(&r8.sib_type_and_info.0)
+}
+
+// Returns the SIB2 carried by the given element, if it's an LTE
+// SystemInformation message containing one.
+pub(crate) fn unpack_sib2(ie: &InformationElement) -> Option<&SystemInformationBlockType2> {
+    unpack_system_information(ie)?
+        .iter()
+        .find_map(|sib| match sib {
+            SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib2(sib2) => Some(sib2),
+            _ => None,
+        })
+}
+
+// Likewise for SIB10, which carries the ETWS primary notification
+pub(crate) fn unpack_sib10(ie: &InformationElement) -> Option<&SystemInformationBlockType10> {
+    unpack_system_information(ie)?
+        .iter()
+        .find_map(|sib| match sib {
+            SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib10(sib10) => Some(sib10),
+            _ => None,
+        })
+}
+
+// Likewise for SIB11, which carries the ETWS secondary notification
+pub(crate) fn unpack_sib11(ie: &InformationElement) -> Option<&SystemInformationBlockType11> {
+    unpack_system_information(ie)?
+        .iter()
+        .find_map(|sib| match sib {
+            SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib11(sib11) => Some(sib11),
+            _ => None,
+        })
+}
+
+// Likewise for SIB16, which carries the network's time
+pub(crate) fn unpack_sib16(ie: &InformationElement) -> Option<&SystemInformationBlockType16_r11> {
+    unpack_system_information(ie)?
+        .iter()
+        .find_map(|sib| match sib {
+            SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib16_v1130(sib16) => Some(sib16),
+            _ => None,
+        })
+}
+
+// Likewise for SIB25, which carries unified access control (UAC) barring
+pub(crate) fn unpack_sib25(ie: &InformationElement) -> Option<&SystemInformationBlockType25_r15> {
+    unpack_system_information(ie)?
+        .iter()
+        .find_map(|sib| match sib {
+            SystemInformation_r8_IEsSib_TypeAndInfo_Entry::Sib25_v1530(sib25) => Some(sib25),
+            _ => None,
+        })
+}
+
+// Reads an EMM cause's value back out of its Debug representation, which
+// works however the NAS parser chooses to wrap it
+pub(crate) fn emm_cause_value(cause: &impl Debug) -> Option<u8> {
+    let debug = format!("{cause:?}");
+    let digits: String = debug
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+// Names of the EMM causes rejects commonly carry, per 24.301 annex A
+pub(crate) fn emm_cause_name(cause: u8) -> &'static str {
+    match cause {
+        3 => "Illegal UE",
+        6 => "Illegal ME",
+        7 => "EPS services not allowed",
+        8 => "EPS and non-EPS services not allowed",
+        9 => "UE identity cannot be derived by the network",
+        10 => "Implicitly detached",
+        11 => "PLMN not allowed",
+        12 => "Tracking area not allowed",
+        13 => "Roaming not allowed in this tracking area",
+        14 => "EPS services not allowed in this PLMN",
+        15 => "No suitable cells in tracking area",
+        17 => "Network failure",
+        22 => "Congestion",
+        25 => "Not authorized for this CSG",
+        39 => "CS service temporarily not available",
+        40 => "No EPS bearer context activated",
+        42 => "Severe network failure",
+        111 => "Protocol error, unspecified",
+        _ => "unknown cause",
+    }
+}
+
+// The ESM message a NAS message is, if it is one, from how its Debug
+// representation starts, e.g. "ESMPDNConnectivityReject" for
+// "ESMMessage(ESMPDNConnectivityReject(..". Like emm_cause_value, this works
+// however the NAS parser chooses to lay out the ESM messages.
+pub(crate) fn esm_message_name(debug: &str) -> Option<&str> {
+    let inner = debug.strip_prefix("ESMMessage(")?;
+    inner.split(['(', ' ', '{']).next()
+}
+
+// Names of the ESM causes PDN connectivity rejects commonly carry, per 24.301
+// section 9.9.4.4
+pub(crate) fn esm_cause_name(cause: u8) -> &'static str {
+    match cause {
+        8 => "Operator determined barring",
+        26 => "Insufficient resources",
+        27 => "Missing or unknown APN",
+        28 => "Unknown PDN type",
+        29 => "User authentication failed",
+        30 => "Request rejected by Serving GW or PDN GW",
+        31 => "Request rejected, unspecified",
+        32 => "Service option not supported",
+        33 => "Requested service option not subscribed",
+        34 => "Service option temporarily out of order",
+        38 => "Network failure",
+        50 => "PDN type IPv4 only allowed",
+        51 => "PDN type IPv6 only allowed",
+        55 => "Multiple PDN connections for a given APN not allowed",
+        65 => "Maximum number of EPS bearers reached",
+        66 => "Requested APN not supported in current RAT and PLMN combination",
+        111 => "Protocol error, unspecified",
+        112 => "APN restriction value incompatible with active EPS bearer context",
+        _ => "unknown cause",
+    }
+}
+
+// Folds an ASN.1 bit string (most significant bit first) into an integer.
+pub(crate) fn bits_to_u64<I>(bits: I) -> u64
+where
+    I: IntoIterator,
+    I::Item: Deref<Target = bool>,
+{
+    bits.into_iter()
+        .fold(0, |acc, bit| (acc << 1) | u64::from(*bit))
+}
+
+// Formats a PLMN identity as "MCC-MNC". Per 36.331, a PLMN without an MCC
+// inherits the one from the previous entry in its list, which callers can pass
+// in as `fallback_mcc`.
+pub(crate) fn format_plmn(plmn: &PLMN_Identity, fallback_mcc: Option<&str>) -> String {
+    let mcc: String = match &plmn.mcc {
+        Some(mcc) => mcc.0.iter().map(|digit| digit.0.to_string()).collect(),
+        None => fallback_mcc.unwrap_or("???").to_string(),
+    };
+    let mnc: String = plmn.mnc.0.iter().map(|digit| digit.0.to_string()).collect();
+    format!("{mcc}-{mnc}")
+}
+
+// Normalizes a configured PLMN to the "MCC-MNC" form format_plmn produces,
+// also accepting the undelimited form used on SIM cards (e.g. "310410")
+pub(crate) fn normalize_plmn(plmn: &str) -> Option<String> {
+    let plmn = plmn.trim();
+    let (mcc, mnc) = match plmn.split_once('-') {
+        Some(parts) => parts,
+        None if plmn.len() > 3 && plmn.is_char_boundary(3) => plmn.split_at(3),
+        None => return None,
+    };
+    let all_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if mcc.len() != 3 || !(2..=3).contains(&mnc.len()) || !all_digits(mcc) || !all_digits(mnc) {
+        return None;
+    }
+    Some(format!("{mcc}-{mnc}"))
+}
+
+// Returns every PLMN a cell advertises in its SIB1, as "MCC-MNC"
+pub(crate) fn advertised_plmns(sib1: &SystemInformationBlockType1) -> Vec<String> {
+    let mut plmns: Vec<String> = Vec::new();
+    for info in &sib1.cell_access_related_info.plmn_identity_list.0 {
+        let fallback_mcc = plmns.last().and_then(|plmn| plmn.split('-').next());
+        let plmn = format_plmn(&info.plmn_identity, fallback_mcc);
+        plmns.push(plmn);
+    }
+    plmns
+}
+
+/// The bands a cell advertises in its SIB1
+pub(crate) struct AdvertisedBands {
+    /// The cell's own band, unless it's signalled in an extension that's
+    /// missing
+    pub primary: Option<u16>,
+    /// The other bands the cell's carrier also falls into
+    pub additional: Vec<u16>,
+}
+
+// Returns the bands a cell advertises in its SIB1. Bands above 64 don't fit
+// in freqBandIndicator or multiBandInfoList, so those say maxFBI instead, and
+// the actual band is in the matching entry of the v9e0 extension, which is
+// nested in an extension of its own (SIB1-v8h0) that has to be decoded
+// separately.
+pub(crate) fn advertised_bands(sib1: &SystemInformationBlockType1) -> AdvertisedBands {
+    let v8h0: Option<SystemInformationBlockType1_v8h0_IEs> = sib1
+        .non_critical_extension
+        .as_ref()
+        .and_then(|v890| v890.late_non_critical_extension.as_ref())
+        .and_then(|late| decode(&late.0).ok());
+    let v9e0 = v8h0
+        .as_ref()
+        .and_then(|v8h0| v8h0.non_critical_extension.as_ref());
+
+    let primary = if i64::from(sib1.freq_band_indicator.0) == MAX_FBI {
+        v9e0.and_then(|v9e0| v9e0.freq_band_indicator_v9e0.as_ref())
+            .map(|band| band.0)
+    } else {
+        Some(u16::from(sib1.freq_band_indicator.0))
+    };
+    let extended = v9e0.and_then(|v9e0| v9e0.multi_band_info_list_v9e0.as_ref());
+    let additional = v8h0
+        .as_ref()
+        .and_then(|v8h0| v8h0.multi_band_info_list.as_ref())
+        .map(|list| {
+            list.0
+                .iter()
+                .enumerate()
+                .filter_map(|(i, band)| {
+                    if i64::from(band.0) != MAX_FBI {
+                        return Some(u16::from(band.0));
+                    }
+                    extended?
+                        .0
+                        .get(i)?
+                        .freq_band_indicator_v9e0
+                        .as_ref()
+                        .map(|band| band.0)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    AdvertisedBands {
+        primary,
+        additional,
+    }
+}
+
+// Downlink frequencies of LTE bands as (band, lowest frequency in 100kHz,
+// first EARFCN, last EARFCN), per 36.101 tables 5.5-1 and 5.7.3-1. Only the
+// commonly deployed bands are listed.
+const LTE_BANDS: [(u16, u32, u32, u32); 28] = [
+    (1, 21100, 0, 599),
+    (2, 19300, 600, 1199),
+    (3, 18050, 1200, 1949),
+    (4, 21100, 1950, 2399),
+    (5, 8690, 2400, 2649),
+    (7, 26200, 2750, 3449),
+    (8, 9250, 3450, 3799),
+    (12, 7290, 5010, 5179),
+    (13, 7460, 5180, 5279),
+    (14, 7580, 5280, 5379),
+    (17, 7340, 5730, 5849),
+    (20, 7910, 6150, 6449),
+    (25, 19300, 8040, 8689),
+    (26, 8590, 8690, 9039),
+    (28, 7580, 9210, 9659),
+    (29, 7170, 9660, 9769),
+    (30, 23500, 9770, 9869),
+    (32, 14520, 9920, 10359),
+    (38, 25700, 37750, 38249),
+    (39, 18800, 38250, 38649),
+    (40, 23000, 38650, 39649),
+    (41, 24960, 39650, 41589),
+    (42, 34000, 41590, 43589),
+    (43, 36000, 43590, 45589),
+    (46, 51500, 46790, 54539),
+    (48, 35500, 55240, 56739),
+    (66, 21100, 66436, 67335),
+    (71, 6170, 68586, 68935),
+];
+
+// Returns the band a downlink EARFCN belongs to
+pub(crate) fn lte_band(earfcn: u32) -> Option<u16> {
+    LTE_BANDS
+        .iter()
+        .find(|(_, _, first, last)| (*first..=*last).contains(&earfcn))
+        .map(|(band, _, _, _)| *band)
+}
+
+// Returns the downlink carrier frequency of an EARFCN, in 100kHz
+pub(crate) fn lte_dl_frequency(earfcn: u32) -> Option<u32> {
+    LTE_BANDS
+        .iter()
+        .find(|(_, _, first, last)| (*first..=*last).contains(&earfcn))
+        .map(|(_, low, first, _)| low + (earfcn - first))
+}
+
+// Returns the downlink frequencies a band covers, in 100kHz. Bands overlap,
+// so a carrier can be in more than one of them.
+pub(crate) fn lte_band_dl_frequencies(band: u16) -> Option<RangeInclusive<u32>> {
+    LTE_BANDS
+        .iter()
+        .find(|(known, _, _, _)| *known == band)
+        .map(|(_, low, first, last)| *low..=low + (last - first))
+}
+
+/// The globally unique identity of an LTE cell as advertised in its SIB1.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CellGlobalIdentity {
+    /// The primary PLMN, formatted as "MCC-MNC"
+    pub plmn: String,
+    pub tracking_area_code: u32,
+    pub cell_identity: u32,
+}
+
+impl CellGlobalIdentity {
+    pub fn from_sib1(sib1: &SystemInformationBlockType1) -> Option<Self> {
+        let info = &sib1.cell_access_related_info;
+        let primary_plmn = info.plmn_identity_list.0.first()?;
+        Some(CellGlobalIdentity {
+            plmn: format_plmn(&primary_plmn.plmn_identity, None),
+            tracking_area_code: bits_to_u64(&info.tracking_area_code.0) as u32,
+            cell_identity: bits_to_u64(&info.cell_identity.0) as u32,
+        })
+    }
+
+    /// Parses a cell back out of how it's displayed, as it is in findings'
+    /// evidence
+    pub fn parse(value: &str) -> Option<Self> {
+        let (plmn, rest) = value.split_once(" (TAC ")?;
+        let (tracking_area_code, rest) = rest.split_once(", cell ID ")?;
+        let cell_identity = rest.strip_suffix(')')?;
+        Some(CellGlobalIdentity {
+            plmn: plmn.to_string(),
+            tracking_area_code: tracking_area_code.parse().ok()?,
+            cell_identity: cell_identity.parse().ok()?,
+        })
+    }
+}
+
+impl fmt::Display for CellGlobalIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (TAC {}, cell ID {})",
+            self.plmn, self.tracking_area_code, self.cell_identity
+        )
+    }
+}
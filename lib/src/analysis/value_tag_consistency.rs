@@ -0,0 +1,283 @@
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::{Hash, Hasher};
+
+use telcom_parser::lte_rrc::{
+    SystemInformation_r8_IEsSib_TypeAndInfo_Entry, SystemInformationBlockType1,
+};
+
+use super::analyzer::{Analyzer, Event, EventType, PacketContext, Severity};
+use super::information_element::InformationElement;
+use super::util::{CellGlobalIdentity, unpack_sib1, unpack_system_information};
+use crate::log_codes::ANALYZER_RRC_LOG_CODES;
+
+type CellKey = (CellGlobalIdentity, Option<(u32, u16)>);
+
+// The parsed messages don't implement Hash, but their Debug representation
+// covers every field, which is all a content hash needs
+fn content_hash(value: &impl Debug) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    format!("{value:?}").hash(&mut hasher);
+    hasher.finish()
+}
+
+// SIB1's content, other than the value tag itself
+fn sib1_hash(sib1: &SystemInformationBlockType1) -> u64 {
+    content_hash(&(
+        &sib1.cell_access_related_info,
+        &sib1.cell_selection_info,
+        &sib1.p_max,
+        &sib1.freq_band_indicator,
+        &sib1.scheduling_info_list,
+        &sib1.tdd_config,
+        &sib1.si_window_length,
+        &sib1.non_critical_extension,
+    ))
+}
+
+// SIBs whose content changes without the value tag changing, per 36.331
+// 5.2.1.3: ETWS and CMAS notifications, EAB parameters, and the network's
+// time
+fn exempt_from_value_tag(sib: &SystemInformation_r8_IEsSib_TypeAndInfo_Entry) -> bool {
+    use SystemInformation_r8_IEsSib_TypeAndInfo_Entry as Sib;
+    matches!(
+        sib,
+        Sib::Sib8(_)
+            | Sib::Sib10(_)
+            | Sib::Sib11(_)
+            | Sib::Sib12_v920(_)
+            | Sib::Sib14_v1130(_)
+            | Sib::Sib16_v1130(_)
+    )
+}
+
+// "SIB13" for a Sib13_v920, from how the SIB's debug representation starts
+fn sib_name(debug: &str) -> String {
+    let variant = debug.split(['(', '_']).next().unwrap_or_default();
+    variant.replacen("Sib", "SIB", 1)
+}
+
+// A value tag change, waiting to be matched with a change in content
+struct PendingChange {
+    old_tag: u8,
+    new_tag: u8,
+    frame: usize,
+    // the SIBs read under the old value tag that haven't been read again
+    // since
+    unread: BTreeSet<String>,
+}
+
+#[derive(Default)]
+struct CellState {
+    value_tag: u8,
+    // each SIB's content hash, and the value tag it was read under
+    sibs: BTreeMap<String, (u64, u8)>,
+    pending: Option<PendingChange>,
+}
+
+// A cell's SIB1 carries systemInfoValueTag, which the cell increments
+// whenever its system information changes so that UEs know to read it
+// again, and which is otherwise left alone. A cell whose SIBs change while
+// the tag stays the same leaves UEs with stale system information, and one
+// that changes the tag with nothing to show for it makes every UE re-read
+// its system information for nothing. Real cells do neither, but something
+// impersonating one, or switching between configurations, can.
+//
+// The SIBs the spec lets change without the tag changing (SIB8, SIB10-12,
+// SIB14 and SIB16) are ignored. A tag change is matched against SIB1 and the
+// SIBs read before it, and is only reported as changing nothing once all of
+// those have been read again unchanged. SIB1 changing under the same tag
+// isn't reported, since UEs read it every time they read the tag anyway. SI
+// messages are taken to come from the cell whose SIB1 was last read.
+pub struct ValueTagConsistencyAnalyzer {
+    packet_num: usize,
+    current_cell: Option<CellKey>,
+    cells: HashMap<CellKey, CellState>,
+    reported: HashSet<(CellGlobalIdentity, String, u8)>,
+}
+
+impl Default for ValueTagConsistencyAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ValueTagConsistencyAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            packet_num: 0,
+            current_cell: None,
+            cells: HashMap::new(),
+            reported: HashSet::new(),
+        }
+    }
+
+    fn handle_sib1(&mut self, sib1: &SystemInformationBlockType1, ctx: &PacketContext) {
+        let Some(cell) = CellGlobalIdentity::from_sib1(sib1) else {
+            self.current_cell = None;
+            return;
+        };
+        let key = (cell, ctx.earfcn.zip(ctx.phy_cell_id));
+        let value_tag = sib1.system_info_value_tag.0;
+        let hash = sib1_hash(sib1);
+        self.current_cell = Some(key.clone());
+
+        let Some(state) = self.cells.get_mut(&key) else {
+            let mut state = CellState {
+                value_tag,
+                ..CellState::default()
+            };
+            state.sibs.insert("SIB1".to_string(), (hash, value_tag));
+            self.cells.insert(key, state);
+            return;
+        };
+        let old = state.sibs.insert("SIB1".to_string(), (hash, value_tag));
+        if state.value_tag == value_tag {
+            return;
+        }
+        let old_tag = std::mem::replace(&mut state.value_tag, value_tag);
+        // SIB1 changing along with the tag is reason enough
+        if old.is_some_and(|(old_hash, _)| old_hash != hash) {
+            state.pending = None;
+            return;
+        }
+        let unread: BTreeSet<String> = state
+            .sibs
+            .keys()
+            .filter(|name| *name != "SIB1")
+            .cloned()
+            .collect();
+        state.pending = (!unread.is_empty()).then_some(PendingChange {
+            old_tag,
+            new_tag: value_tag,
+            frame: self.packet_num,
+            unread,
+        });
+    }
+
+    fn handle_sib(&mut self, sib: &SystemInformation_r8_IEsSib_TypeAndInfo_Entry) -> Option<Event> {
+        if exempt_from_value_tag(sib) {
+            return None;
+        }
+        let key = self.current_cell.as_ref()?;
+        let state = self.cells.get_mut(key)?;
+        let debug = format!("{sib:?}");
+        let name = sib_name(&debug);
+        let hash = content_hash(&debug);
+        let value_tag = state.value_tag;
+        let (old_hash, old_tag) = state.sibs.insert(name.clone(), (hash, value_tag))?;
+        let changed = old_hash != hash;
+
+        if old_tag == value_tag {
+            if !changed
+                || !self
+                    .reported
+                    .insert((key.0.clone(), name.clone(), value_tag))
+            {
+                return None;
+            }
+            return Some(Event {
+                event_type: EventType::QualitativeWarning {
+                    severity: Severity::Medium,
+                },
+                message: format!(
+                    "Cell changed its {name} without changing its systemInfoValueTag ({value_tag})"
+                ),
+                explanation: "The cell's system information changed, but the systemInfoValueTag in its SIB1, which tells UEs when to read its system information again, stayed the same. Real cells change the tag whenever their system information changes, so this suggests the broadcast was taken over or reconfigured by something like a fake base station.".to_string(),
+                evidence: vec![
+                    ("cell".to_string(), key.0.to_string()),
+                    ("sib".to_string(), name),
+                    ("value_tag".to_string(), value_tag.to_string()),
+                    ("content_changed".to_string(), "true".to_string()),
+                    ("old_content_hash".to_string(), format!("{old_hash:016x}")),
+                    ("new_content_hash".to_string(), format!("{hash:016x}")),
+                    ("frame".to_string(), self.packet_num.to_string()),
+                ],
+            });
+        }
+
+        let pending = state.pending.as_mut()?;
+        if !pending.unread.remove(&name) {
+            return None;
+        }
+        if changed {
+            state.pending = None;
+            return None;
+        }
+        if !pending.unread.is_empty() {
+            return None;
+        }
+        let pending = state.pending.take()?;
+        if !self
+            .reported
+            .insert((key.0.clone(), "value_tag".to_string(), pending.new_tag))
+        {
+            return None;
+        }
+        let sibs: Vec<&str> = state.sibs.keys().map(String::as_str).collect();
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Low,
+            },
+            message: format!(
+                "Cell changed its systemInfoValueTag from {} to {} without changing its system information",
+                pending.old_tag, pending.new_tag
+            ),
+            explanation: "The cell changed the systemInfoValueTag in its SIB1, which tells UEs its system information changed and has to be read again, but none of its system information did change. Real cells only change the tag when there's something new to read, so this suggests a broadcast that's being restarted or toggled, as a fake base station's might be.".to_string(),
+            evidence: vec![
+                ("cell".to_string(), key.0.to_string()),
+                ("old_value_tag".to_string(), pending.old_tag.to_string()),
+                ("new_value_tag".to_string(), pending.new_tag.to_string()),
+                ("content_changed".to_string(), "false".to_string()),
+                ("compared_sibs".to_string(), sibs.join(", ")),
+                ("value_tag_frame".to_string(), pending.frame.to_string()),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+}
+
+impl Analyzer for ValueTagConsistencyAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Value Tag Consistency")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether a cell's system information changes without the systemInfoValueTag in its SIB1 changing, or the tag changes without any of its system information changing. SIBs that are allowed to change without the tag (SIB8, SIB10-12, SIB14 and SIB16) are ignored.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn get_log_codes(&self) -> &'static [u16] {
+        ANALYZER_RRC_LOG_CODES
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        if let Some(sib1) = unpack_sib1(ie) {
+            self.handle_sib1(sib1, ctx);
+            return None;
+        }
+        let mut event = None;
+        for sib in unpack_system_information(ie)? {
+            // every SIB has to be recorded, but an event can only be
+            // reported for one of them
+            event = event.or(self.handle_sib(sib));
+        }
+        event
+    }
+}
@@ -0,0 +1,242 @@
+use std::borrow::Cow;
+
+use pycrate_rs::nas::NASMessage;
+use pycrate_rs::nas::emm::EMMMessage;
+use serde::{Deserialize, Serialize};
+use telcom_parser::lte_rrc::{
+    DL_CCCH_MessageType, DL_CCCH_MessageType_c1, DL_DCCH_MessageType, DL_DCCH_MessageType_c1,
+    MeasResults, MeasResultsMeasResultNeighCells, MeasurementReportCriticalExtensions,
+    MeasurementReportCriticalExtensions_c1, RRCConnectionReconfigurationCriticalExtensions,
+    RRCConnectionReconfigurationCriticalExtensions_c1, UL_DCCH_MessageType, UL_DCCH_MessageType_c1,
+};
+
+use super::analyzer::{
+    Analyzer, AnalyzerConfig, AnalyzerSettings, Event, EventType, PacketContext, Severity,
+};
+use super::information_element::{InformationElement, LteInformationElement};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WeakCellAttachSettings {
+    // how many dB stronger than the serving cell the best neighbour has to be
+    pub min_rsrp_gap_db: u8,
+}
+
+impl Default for WeakCellAttachSettings {
+    fn default() -> Self {
+        Self {
+            min_rsrp_gap_db: 15,
+        }
+    }
+}
+
+impl AnalyzerSettings for WeakCellAttachSettings {
+    const KEY: &'static str = "weak_cell_attach";
+}
+
+// Formats an RSRP-Range value, which counts 1dB steps up from -140dBm
+fn rsrp_dbm(rsrp_range: u8) -> String {
+    format!("{}dBm", i16::from(rsrp_range) - 140)
+}
+
+// The serving cell's RSRP, along with the strongest LTE neighbour's PCI and
+// RSRP, if the UE reported any
+struct RadioLevels {
+    serving_rsrp: u8,
+    best_neighbour: Option<(u16, u8)>,
+}
+
+impl RadioLevels {
+    fn from_meas_results(meas_results: &MeasResults) -> Self {
+        let best_neighbour = match &meas_results.meas_result_neigh_cells {
+            Some(MeasResultsMeasResultNeighCells::MeasResultListEUTRA(neighbours)) => neighbours
+                .0
+                .iter()
+                .filter_map(|neighbour| {
+                    let rsrp = neighbour.meas_result.rsrp_result.as_ref()?;
+                    Some((neighbour.phys_cell_id.0, rsrp.0))
+                })
+                .max_by_key(|(_, rsrp)| *rsrp),
+            _ => None,
+        };
+        Self {
+            serving_rsrp: meas_results.meas_result_p_cell.rsrp_result.0,
+            best_neighbour,
+        }
+    }
+
+    // how many dB stronger the best neighbour is, if it's stronger at all
+    fn gap_db(&self) -> Option<u8> {
+        let (_, neighbour_rsrp) = self.best_neighbour?;
+        neighbour_rsrp.checked_sub(self.serving_rsrp)
+    }
+
+    fn describe(&self) -> String {
+        match self.best_neighbour {
+            Some((pci, rsrp)) => format!(
+                "serving cell {}, best neighbour (PCI {pci}) {}",
+                rsrp_dbm(self.serving_rsrp),
+                rsrp_dbm(rsrp)
+            ),
+            None => format!("serving cell {}", rsrp_dbm(self.serving_rsrp)),
+        }
+    }
+}
+
+struct Connection {
+    // whether the UE attached over this connection, and hasn't since been
+    // handed over
+    attached: bool,
+    // the first measurement report showing a much stronger neighbour, and
+    // the frame it was in
+    first_weak_report: Option<(RadioLevels, usize)>,
+    reported: bool,
+}
+
+// A UE picks the strongest cell it can find, so attaching to one that's far
+// weaker than a neighbour it can hear means it was pushed there, e.g. by a
+// fake cell rejecting it everywhere else or jamming the alternatives. This
+// compares the levels the UE reports in its measurement reports after
+// attaching. A real network usually answers a report like that with a
+// handover, so it's only flagged once a second report shows the UE is still
+// stuck on the weaker cell.
+pub struct WeakCellAttachAnalyzer {
+    packet_num: usize,
+    min_gap_db: u8,
+    connection: Option<Connection>,
+}
+
+impl WeakCellAttachAnalyzer {
+    pub fn new(analyzer_config: &AnalyzerConfig) -> Self {
+        let settings: WeakCellAttachSettings = analyzer_config.get_settings();
+        Self {
+            packet_num: 0,
+            min_gap_db: settings.min_rsrp_gap_db,
+            connection: None,
+        }
+    }
+
+    fn handle_measurement_report(&mut self, meas_results: &MeasResults) -> Option<Event> {
+        let connection = self.connection.as_mut()?;
+        if !connection.attached || connection.reported {
+            return None;
+        }
+        let levels = RadioLevels::from_meas_results(meas_results);
+        if levels.gap_db()? < self.min_gap_db {
+            return None;
+        }
+        if connection.first_weak_report.is_none() {
+            connection.first_weak_report = Some((levels, self.packet_num));
+            return None;
+        }
+        let (first_levels, first_frame) = connection.first_weak_report.as_ref()?;
+
+        connection.reported = true;
+        let gap = levels.gap_db()?;
+        let current = levels.describe();
+        Some(Event {
+            event_type: EventType::QualitativeWarning {
+                severity: Severity::Low,
+            },
+            message: format!(
+                "UE attached to a cell {gap}dB weaker than its best neighbour and wasn't handed over ({current})"
+            ),
+            explanation: "After attaching, the UE kept reporting a neighbouring cell much stronger than the one it was on, without the network moving it there. Phones normally pick the strongest cell available, so ending up on a much weaker one suggests it was forced there, which fake base stations do. Unusual network planning or a neighbour on a different operator can also explain it.".to_string(),
+            evidence: vec![
+                ("radio_levels".to_string(), current),
+                ("rsrp_gap_db".to_string(), gap.to_string()),
+                ("first_report_radio_levels".to_string(), first_levels.describe()),
+                ("first_report_frame".to_string(), first_frame.to_string()),
+                ("frame".to_string(), self.packet_num.to_string()),
+            ],
+        })
+    }
+}
+
+impl Analyzer for WeakCellAttachAnalyzer {
+    fn get_name(&self) -> Cow<str> {
+        Cow::from("Weak Cell Attach")
+    }
+
+    fn get_description(&self) -> Cow<str> {
+        Cow::from(
+            "Tests whether the UE attaches to a cell at least min_rsrp_gap_db weaker than the best neighbour it reports, and stays there across measurement reports, which suggests it was forced onto that cell.",
+        )
+    }
+
+    fn get_version(&self) -> u32 {
+        1
+    }
+
+    fn needs_every_message(&self) -> bool {
+        true
+    }
+
+    fn analyze_information_element(
+        &mut self,
+        ie: &InformationElement,
+        _ctx: &PacketContext,
+    ) -> Option<Event> {
+        self.packet_num += 1;
+        let InformationElement::LTE(inner) = ie else {
+            return None;
+        };
+        match &**inner {
+            LteInformationElement::DlCcch(msg_cont) => {
+                if let DL_CCCH_MessageType::C1(DL_CCCH_MessageType_c1::RrcConnectionSetup(_)) =
+                    &msg_cont.message
+                {
+                    self.connection = Some(Connection {
+                        attached: false,
+                        first_weak_report: None,
+                        reported: false,
+                    });
+                }
+                None
+            }
+            LteInformationElement::NAS(NASMessage::EMMMessage(EMMMessage::EMMAttachRequest(_))) => {
+                if let Some(connection) = self.connection.as_mut() {
+                    connection.attached = true;
+                }
+                None
+            }
+            LteInformationElement::DlDcch(msg_cont) => {
+                let DL_DCCH_MessageType::C1(c1) = &msg_cont.message else {
+                    return None;
+                };
+                match c1 {
+                    DL_DCCH_MessageType_c1::RrcConnectionRelease(_) => self.connection = None,
+                    // once it's been handed over, the UE is no longer on the
+                    // cell it attached to
+                    DL_DCCH_MessageType_c1::RrcConnectionReconfiguration(reconfiguration) => {
+                        if let RRCConnectionReconfigurationCriticalExtensions::C1(
+                            RRCConnectionReconfigurationCriticalExtensions_c1::RrcConnectionReconfiguration_r8(r8),
+                        ) = &reconfiguration.critical_extensions
+                        {
+                            if r8.mobility_control_info.is_some() {
+                                self.connection = None;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                None
+            }
+            LteInformationElement::UlDcch(msg_cont) => {
+                let UL_DCCH_MessageType::C1(UL_DCCH_MessageType_c1::MeasurementReport(report)) =
+                    &msg_cont.message
+                else {
+                    return None;
+                };
+                let MeasurementReportCriticalExtensions::C1(
+                    MeasurementReportCriticalExtensions_c1::MeasurementReport_r8(r8),
+                ) = &report.critical_extensions
+                else {
+                    return None;
+                };
+                self.handle_measurement_report(&r8.meas_results)
+            }
+            _ => None,
+        }
+    }
+}
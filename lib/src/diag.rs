@@ -1,10 +1,14 @@
 //! Diag protocol serialization/deserialization
 
+use std::collections::{BTreeSet, HashSet};
+
 use chrono::{DateTime, FixedOffset};
 use crc::{Algorithm, Crc};
 use deku::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::hdlc::{self, hdlc_decapsulate};
+use crate::util::LogLimiter;
 use log::{error, warn};
 use thiserror::Error;
 
@@ -62,6 +66,26 @@ pub enum DiagParsingError {
     HdlcDecapsulationError(hdlc::HdlcError, Vec<u8>),
 }
 
+impl DiagParsingError {
+    /// Returns the log code of the message that failed to parse, if it got
+    /// far enough to be recognizable as a log message
+    pub fn log_code(&self) -> Option<u16> {
+        let DiagParsingError::MessageParsingError(_, data) = self else {
+            return None;
+        };
+        log_code(data)
+    }
+}
+
+// Reads the log code out of a decapsulated log message's header. A log
+// message's id, pending_msgs, outer_length and inner_length come before it.
+fn log_code(data: &[u8]) -> Option<u16> {
+    match data {
+        [16, _, _, _, _, _, low, high, ..] => Some(u16::from_le_bytes([*low, *high])),
+        _ => None,
+    }
+}
+
 // this is sorta based on the params qcsuper uses, plus what seems to be used in
 // https://github.com/fgsect/scat/blob/f1538b397721df3ab8ba12acd26716abcf21f78b/util.py#L47
 pub const CRC_CCITT_ALG: Algorithm<u16> = Algorithm {
@@ -86,31 +110,101 @@ pub struct MessagesContainer {
 
 impl MessagesContainer {
     pub fn into_messages(self) -> Vec<Result<Message, DiagParsingError>> {
+        self.into_messages_with_offsets()
+            .into_iter()
+            .map(|(_, _, result)| result)
+            .collect()
+    }
+
+    /// Like [MessagesContainer::into_messages], but also returns the offset
+    /// each message starts at within the container's data, and its length
+    /// there (i.e. while still HDLC-encapsulated)
+    pub fn into_messages_with_offsets(
+        self,
+    ) -> Vec<(usize, usize, Result<Message, DiagParsingError>)> {
+        self.into_messages_with_offsets_filtered(None)
+            .into_iter()
+            .filter_map(|(offset, length, result)| Some((offset, length, result?)))
+            .collect()
+    }
+
+    /// Like [MessagesContainer::into_messages_with_offsets], but only decodes
+    /// the log messages whose log codes are in `log_codes` (or all of them, if
+    /// it's None). Other log messages are returned as None, which is a lot
+    /// cheaper than decoding them. Messages that aren't logs are always
+    /// decoded.
+    pub fn into_messages_with_offsets_filtered(
+        self,
+        log_codes: Option<&HashSet<u16>>,
+    ) -> Vec<(usize, usize, Option<Result<Message, DiagParsingError>>)> {
         let mut result = Vec::new();
+        let mut offset = 0;
         for msg in self.messages {
             for sub_msg in msg.data.split_inclusive(|&b| b == MESSAGE_TERMINATOR) {
-                match hdlc_decapsulate(sub_msg, &CRC_CCITT) {
-                    Ok(data) => match Message::from_bytes((&data, 0)) {
-                        Ok(((leftover_bytes, _), res)) => {
-                            if !leftover_bytes.is_empty() {
-                                warn!(
-                                    "warning: {} leftover bytes when parsing Message",
-                                    leftover_bytes.len()
-                                );
-                            }
-                            result.push(Ok(res));
-                        }
-                        Err(e) => result.push(Err(DiagParsingError::MessageParsingError(e, data))),
+                let parsed = match hdlc_decapsulate(sub_msg, &CRC_CCITT) {
+                    Ok(data) => match (log_code(&data), log_codes) {
+                        (Some(log_code), Some(log_codes)) if !log_codes.contains(&log_code) => None,
+                        _ => Some(parse_message(data)),
                     },
-                    Err(err) => result.push(Err(DiagParsingError::HdlcDecapsulationError(
+                    Err(err) => Some(Err(DiagParsingError::HdlcDecapsulationError(
                         err,
                         sub_msg.to_vec(),
                     ))),
-                }
+                };
+                result.push((offset, sub_msg.len(), parsed));
+                offset += sub_msg.len();
             }
         }
         result
     }
+
+    /// Returns the earliest and latest timestamps of the log messages in the
+    /// container, if it has any. Only each message's header is read, so this
+    /// is a lot cheaper than parsing the container.
+    pub fn timestamp_range(&self) -> Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+        let mut range: Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> = None;
+        for msg in &self.messages {
+            for sub_msg in msg.data.split_inclusive(|&b| b == MESSAGE_TERMINATOR) {
+                let Ok(data) = hdlc_decapsulate(sub_msg, &CRC_CCITT) else {
+                    continue;
+                };
+                let Some(timestamp) = log_timestamp(&data) else {
+                    continue;
+                };
+                range = Some(match range {
+                    Some((first, last)) => (first.min(timestamp), last.max(timestamp)),
+                    None => (timestamp, timestamp),
+                });
+            }
+        }
+        range
+    }
+}
+
+// Reads the timestamp out of a decapsulated log message's header, which comes
+// right after its log_type
+fn log_timestamp(data: &[u8]) -> Option<DateTime<FixedOffset>> {
+    let [16, _, _, _, _, _, _, _, rest @ ..] = data else {
+        return None;
+    };
+    let ts = u64::from_le_bytes(rest.get(..8)?.try_into().ok()?);
+    Some(Timestamp { ts }.to_datetime())
+}
+
+fn parse_message(data: Vec<u8>) -> Result<Message, DiagParsingError> {
+    match Message::from_bytes((&data, 0)) {
+        Ok(((leftover_bytes, _), res)) => {
+            static LEFTOVER_BYTES: LogLimiter = LogLimiter::new(1000);
+            if !leftover_bytes.is_empty() && LEFTOVER_BYTES.check("leftover bytes warnings") {
+                warn!(
+                    "warning: {} leftover bytes when parsing Message",
+                    leftover_bytes.len()
+                );
+            }
+            Ok(res)
+        }
+        Err(e) => Err(DiagParsingError::MessageParsingError(e, data)),
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, DekuRead, DekuWrite)]
@@ -320,6 +414,24 @@ impl LteRrcOtaPacket {
         }
     }
 
+    pub fn get_phy_cell_id(&self) -> u16 {
+        match self {
+            LteRrcOtaPacket::V0 { phy_cell_id, .. } => *phy_cell_id,
+            LteRrcOtaPacket::V5 { phy_cell_id, .. } => *phy_cell_id,
+            LteRrcOtaPacket::V8 { phy_cell_id, .. } => *phy_cell_id,
+            LteRrcOtaPacket::V25 { phy_cell_id, .. } => *phy_cell_id,
+        }
+    }
+
+    pub fn get_bearer_id(&self) -> u8 {
+        match self {
+            LteRrcOtaPacket::V0 { bearer_id, .. } => *bearer_id,
+            LteRrcOtaPacket::V5 { bearer_id, .. } => *bearer_id,
+            LteRrcOtaPacket::V8 { bearer_id, .. } => *bearer_id,
+            LteRrcOtaPacket::V25 { bearer_id, .. } => *bearer_id,
+        }
+    }
+
     pub fn get_earfcn(&self) -> u32 {
         match self {
             LteRrcOtaPacket::V0 { earfcn, .. } => *earfcn as u32,
@@ -339,6 +451,45 @@ impl LteRrcOtaPacket {
     }
 }
 
+/// The extended header versions seen in a modem's log packets. Different
+/// Qualcomm modem generations lay out the same log codes differently, and the
+/// ext header version is what tells us which layout we're parsing, so
+/// recording these makes it easy to tell which device-specific layouts a
+/// capture relies on.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiagLayoutVersions {
+    pub lte_rrc_ota: BTreeSet<u8>,
+    pub nas_4g: BTreeSet<u8>,
+    /// The LTE RRC OTA versions whose layout is unknown, neither built in nor
+    /// given by the modem profile, so whose messages couldn't be parsed
+    #[serde(default)]
+    pub unsupported_lte_rrc_ota: BTreeSet<u8>,
+}
+
+impl DiagLayoutVersions {
+    /// Records the layout version of the given message, returning whether it
+    /// hadn't been seen before.
+    pub fn observe(&mut self, msg: &Message) -> bool {
+        match msg {
+            Message::Log {
+                body:
+                    LogBody::LteRrcOtaMessage {
+                        ext_header_version, ..
+                    },
+                ..
+            } => self.lte_rrc_ota.insert(*ext_header_version),
+            Message::Log {
+                body:
+                    LogBody::Nas4GMessage {
+                        ext_header_version, ..
+                    },
+                ..
+            } => self.nas_4g.insert(*ext_header_version),
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, DekuRead, DekuWrite)]
 #[deku(endian = "little")]
 pub struct Timestamp {
@@ -613,4 +764,76 @@ mod test {
             Err(DiagParsingError::HdlcDecapsulationError(_, _))
         ));
     }
+
+    #[test]
+    fn test_messages_with_offsets() {
+        let (encapsulated1, message1) = get_test_message(&[1]);
+        let first_len = encapsulated1.data.len();
+        // a log message cut off right after its log type
+        let truncated = hdlc::hdlc_encapsulate(&[16, 0, 20, 0, 20, 0, 0xc0, 0xb0], &CRC_CCITT);
+        let truncated_len = truncated.len();
+        let mut container = make_container(DataType::UserSpace, encapsulated1);
+        container.messages.push(HdlcEncapsulatedMessage {
+            len: truncated_len as u32,
+            data: truncated,
+        });
+        container.num_messages += 1;
+        let result = container.into_messages_with_offsets();
+        assert_eq!(result[0], (0, first_len, Ok(message1)));
+        let (offset, len, Err(err)) = &result[1] else {
+            panic!("expected a parsing error, got {:?}", result[1]);
+        };
+        assert_eq!((*offset, *len), (first_len, truncated_len));
+        assert_eq!(err.log_code(), Some(0xb0c0));
+    }
+
+    #[test]
+    fn test_messages_with_offsets_filtered() {
+        let (encapsulated, message) = get_test_message(&[1]);
+        let len = encapsulated.data.len();
+        let container = make_container(DataType::UserSpace, encapsulated);
+
+        let nas_only = HashSet::from([0xb0ec, 0xb0ed]);
+        let result = container
+            .clone()
+            .into_messages_with_offsets_filtered(Some(&nas_only));
+        assert_eq!(result, vec![(0, len, None)]);
+
+        let rrc = HashSet::from([0xb0c0]);
+        let result = container.into_messages_with_offsets_filtered(Some(&rrc));
+        assert_eq!(result, vec![(0, len, Some(Ok(message)))]);
+    }
+
+    #[test]
+    fn test_timestamp_range() {
+        let encapsulate = |data: &[u8]| {
+            let data = hdlc::hdlc_encapsulate(data, &CRC_CCITT);
+            HdlcEncapsulatedMessage {
+                len: data.len() as u32,
+                data,
+            }
+        };
+        let log_message = |ts: u64| {
+            let mut data = vec![16, 0, 20, 0, 20, 0, 0xc0, 0xb0];
+            data.extend_from_slice(&ts.to_le_bytes());
+            encapsulate(&data)
+        };
+        // not a log message, so it doesn't have a timestamp
+        let mut container = make_container(DataType::UserSpace, encapsulate(&[75, 18, 0, 0]));
+        assert_eq!(container.timestamp_range(), None);
+
+        // 800 and 1600 ticks of 1/800s are one and two seconds after the epoch
+        for ts in [1600 << 16, 800 << 16] {
+            container.messages.push(log_message(ts));
+            container.num_messages += 1;
+        }
+        let epoch = DateTime::parse_from_rfc3339("1980-01-06T00:00:00-00:00").unwrap();
+        assert_eq!(
+            container.timestamp_range(),
+            Some((
+                epoch + chrono::Duration::seconds(1),
+                epoch + chrono::Duration::seconds(2)
+            ))
+        );
+    }
 }
@@ -0,0 +1,513 @@
+//! Helpers for testing analyzers against known message sequences, without
+//! needing a capture from a real device. [FixtureBuilder] builds QMDL out of
+//! raw RRC and NAS messages, [containers_from_qmdl] splits up QMDL (built or
+//! recorded) the same way [crate::qmdl::QmdlReader] does, and
+//! [analyze_containers] runs the result through a [Harness] and collects
+//! whatever its analyzers found. [Replay] runs them through a single
+//! analyzer instead, keeping track of its state after each one.
+//! [assert_golden_findings] checks what an analyzer finds in a fixture
+//! against a golden file of the findings it's expected to make, so changes
+//! to its behavior show up as failing tests.
+//!
+//! Recorded snippets live in `lib/tests/fixtures`, and golden files in
+//! `lib/tests/fixtures/golden`.
+
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use deku::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::analysis::analyzer::{AnalysisRow, AnalyzerConfig, Event, Harness};
+use crate::diag::{
+    CRC_CCITT, DataType, HdlcEncapsulatedMessage, LogBody, LteRrcOtaPacket, MESSAGE_TERMINATOR,
+    Message, MessagesContainer, Nas4GMessageDirection, Timestamp,
+};
+use crate::gsmtap::LteRrcSubtype;
+use crate::hdlc::hdlc_encapsulate;
+
+// LTE RRC OTA logs are built with this ext header version, whose PDU numbers
+// are listed in gsmtap_parser
+const LTE_RRC_EXT_HEADER_VERSION: u8 = 20;
+
+fn lte_rrc_pdu_num(channel: LteRrcSubtype) -> Option<u8> {
+    match channel {
+        LteRrcSubtype::BcchBch => Some(1),
+        LteRrcSubtype::BcchDlSch => Some(2),
+        LteRrcSubtype::MCCH => Some(4),
+        LteRrcSubtype::PCCH => Some(5),
+        LteRrcSubtype::DlCcch => Some(6),
+        LteRrcSubtype::DlDcch => Some(7),
+        LteRrcSubtype::UlCcch => Some(8),
+        LteRrcSubtype::UlDcch => Some(9),
+        _ => None,
+    }
+}
+
+// Converts a time since the diag epoch into a diag timestamp, which counts
+// 1/800s ticks in its upper 48 bits
+fn diag_timestamp(elapsed: TimeDelta) -> Timestamp {
+    let ticks = elapsed.num_milliseconds().max(0) as u64 * 4 / 5;
+    Timestamp { ts: ticks << 16 }
+}
+
+/// A finding an analyzer made while analyzing a fixture
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    pub analyzer: String,
+    /// the index of the message it was made on, counting from 0
+    pub message_index: usize,
+    /// when the message it was made on was logged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<FixedOffset>>,
+    pub event: Event,
+}
+
+/// Builds a QMDL fixture one message at a time. Every message is logged as if
+/// it came from the same cell, and at the same time unless
+/// [FixtureBuilder::advance] is used, so the QMDL produced is always the same.
+pub struct FixtureBuilder {
+    messages: Vec<Message>,
+    elapsed: TimeDelta,
+    earfcn: u32,
+    phy_cell_id: u16,
+}
+
+impl Default for FixtureBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FixtureBuilder {
+    pub fn new() -> Self {
+        FixtureBuilder {
+            messages: Vec::new(),
+            // diag timestamps count from 1980, so start somewhere more recent
+            elapsed: TimeDelta::days(365 * 44),
+            earfcn: 2050,
+            phy_cell_id: 160,
+        }
+    }
+
+    /// Sets the EARFCN and PCI subsequent RRC messages are logged with
+    pub fn cell(mut self, earfcn: u32, phy_cell_id: u16) -> Self {
+        self.earfcn = earfcn;
+        self.phy_cell_id = phy_cell_id;
+        self
+    }
+
+    /// Moves the timestamp subsequent messages are logged with forward
+    pub fn advance(mut self, delta: TimeDelta) -> Self {
+        self.elapsed += delta;
+        self
+    }
+
+    /// Adds a UPER-encoded LTE RRC message received or sent on `channel`.
+    ///
+    /// Panics if the channel can't be logged, e.g. because it's NB-IoT.
+    pub fn lte_rrc(mut self, channel: LteRrcSubtype, payload: &[u8]) -> Self {
+        let pdu_num = lte_rrc_pdu_num(channel)
+            .unwrap_or_else(|| panic!("can't build a fixture for {channel:?} messages"));
+        let length = 31 + payload.len() as u16;
+        self.messages.push(Message::Log {
+            pending_msgs: 0,
+            outer_length: length,
+            inner_length: length,
+            log_type: 0xb0c0,
+            timestamp: diag_timestamp(self.elapsed),
+            body: LogBody::LteRrcOtaMessage {
+                ext_header_version: LTE_RRC_EXT_HEADER_VERSION,
+                packet: LteRrcOtaPacket::V8 {
+                    rrc_rel_maj: 14,
+                    rrc_rel_min: 48,
+                    bearer_id: 0,
+                    phy_cell_id: self.phy_cell_id,
+                    earfcn: self.earfcn,
+                    sfn_subfn: 0,
+                    pdu_num,
+                    sib_mask: 0,
+                    len: payload.len() as u16,
+                    packet: payload.to_vec(),
+                },
+            },
+        });
+        self
+    }
+
+    /// Adds a plain (i.e. not security protected) EMM NAS message
+    pub fn nas(mut self, direction: Nas4GMessageDirection, payload: &[u8]) -> Self {
+        let log_type = match direction {
+            Nas4GMessageDirection::Downlink => 0xb0ec,
+            Nas4GMessageDirection::Uplink => 0xb0ed,
+        };
+        let length = 16 + payload.len() as u16;
+        self.messages.push(Message::Log {
+            pending_msgs: 0,
+            outer_length: length,
+            inner_length: length,
+            log_type,
+            timestamp: diag_timestamp(self.elapsed),
+            body: LogBody::Nas4GMessage {
+                direction,
+                ext_header_version: 1,
+                rrc_rel: 14,
+                rrc_version_minor: 0,
+                rrc_version_major: 14,
+                msg: payload.to_vec(),
+            },
+        });
+        self
+    }
+
+    /// Serializes the messages added so far into QMDL
+    pub fn to_qmdl(&self) -> Vec<u8> {
+        self.messages
+            .iter()
+            .flat_map(|message| {
+                let serialized = message
+                    .to_bytes()
+                    .expect("failed to serialize fixture message");
+                hdlc_encapsulate(&serialized, &CRC_CCITT)
+            })
+            .collect()
+    }
+
+    pub fn to_containers(&self) -> Vec<MessagesContainer> {
+        containers_from_qmdl(&self.to_qmdl())
+    }
+}
+
+/// Splits QMDL into containers of one message each, as
+/// [crate::qmdl::QmdlReader] does when reading a file
+pub fn containers_from_qmdl(qmdl: &[u8]) -> Vec<MessagesContainer> {
+    qmdl.split_inclusive(|&b| b == MESSAGE_TERMINATOR)
+        .map(|data| MessagesContainer {
+            data_type: DataType::UserSpace,
+            num_messages: 1,
+            messages: vec![HdlcEncapsulatedMessage {
+                len: data.len() as u32,
+                data: data.to_vec(),
+            }],
+        })
+        .collect()
+}
+
+/// Runs containers through a harness in order, returning its analysis rows
+/// along with every finding its analyzers made
+pub fn analyze_containers(
+    harness: &mut Harness,
+    containers: impl IntoIterator<Item = MessagesContainer>,
+) -> (Vec<AnalysisRow>, Vec<Finding>) {
+    let analyzer_names: Vec<String> = harness
+        .get_metadata()
+        .analyzers
+        .into_iter()
+        .map(|analyzer| analyzer.name)
+        .collect();
+    let rows: Vec<AnalysisRow> = containers
+        .into_iter()
+        .flat_map(|container| harness.analyze_qmdl_messages(container))
+        .collect();
+    let mut findings = Vec::new();
+    for (message_index, row) in rows.iter().enumerate() {
+        for (analyzer, event) in analyzer_names.iter().zip(&row.events) {
+            if let Some(event) = event {
+                findings.push(Finding {
+                    analyzer: analyzer.clone(),
+                    message_index,
+                    timestamp: row.packet_timestamp,
+                    event: event.clone(),
+                });
+            }
+        }
+    }
+    (rows, findings)
+}
+
+/// What happened to the analyzer being replayed while analyzing one
+/// container
+#[derive(Debug, Clone)]
+pub struct ReplayStep {
+    /// the index of the container, counting from 0
+    pub container_index: usize,
+    pub rows: Vec<AnalysisRow>,
+    /// the analyzer's [crate::analysis::analyzer::Analyzer::get_state] after
+    /// the container
+    pub state: Option<String>,
+    /// whether the state is different from what it was before the container
+    pub state_changed: bool,
+}
+
+impl ReplayStep {
+    /// Returns whatever the analyzer found in this container
+    pub fn events(&self) -> impl Iterator<Item = &Event> {
+        self.rows
+            .iter()
+            .filter_map(|row| row.events.first()?.as_ref())
+    }
+}
+
+/// Replays containers through a single analyzer, enabled by its toggle in
+/// [AnalyzerConfig], recording its state after each container. State is only
+/// checked between containers, so split QMDL up with [containers_from_qmdl]
+/// to see it change message by message.
+pub struct Replay {
+    harness: Harness,
+    container_index: usize,
+    state: Option<String>,
+}
+
+impl Replay {
+    /// Sets up the analyzer named `toggle` with the settings and severity
+    /// override it has in `analyzer_config`, whether or not it's enabled there
+    pub fn new(toggle: &str, analyzer_config: &AnalyzerConfig) -> Result<Self, String> {
+        let harness = Harness::new_with_config(&analyzer_config.only(toggle)?);
+        let state = harness.get_analyzer_states().pop().flatten();
+        Ok(Replay {
+            harness,
+            container_index: 0,
+            state,
+        })
+    }
+
+    /// The analyzer's state before anything has been replayed, or after the
+    /// last container
+    pub fn state(&self) -> Option<&str> {
+        self.state.as_deref()
+    }
+
+    pub fn harness(&self) -> &Harness {
+        &self.harness
+    }
+
+    pub fn step(&mut self, container: MessagesContainer) -> ReplayStep {
+        let rows = self.harness.analyze_qmdl_messages(container);
+        let state = self.harness.get_analyzer_states().pop().flatten();
+        let state_changed = state != self.state;
+        self.state = state.clone();
+        let step = ReplayStep {
+            container_index: self.container_index,
+            rows,
+            state,
+            state_changed,
+        };
+        self.container_index += 1;
+        step
+    }
+
+    pub fn run(
+        &mut self,
+        containers: impl IntoIterator<Item = MessagesContainer>,
+    ) -> Vec<ReplayStep> {
+        containers
+            .into_iter()
+            .map(|container| self.step(container))
+            .collect()
+    }
+}
+
+/// The environment variable that makes [check_golden_findings] write the
+/// findings it gets to the golden file instead of checking them, for when an
+/// analyzer's behavior is meant to change
+pub const UPDATE_GOLDEN_VAR: &str = "RAYHUNTER_UPDATE_GOLDEN";
+
+// Stands in for evidence that's a timestamp in golden files
+const GOLDEN_TIMESTAMP: &str = "<timestamp>";
+
+// What a golden file records of a finding: everything but its timestamp, and
+// with any evidence that's a timestamp blanked out, since those depend on
+// when a fixture was recorded rather than on what the analyzer did
+fn golden_value(finding: &Finding) -> Value {
+    let mut finding = finding.clone();
+    finding.timestamp = None;
+    for (_, value) in finding.event.evidence.iter_mut() {
+        if DateTime::parse_from_rfc3339(value).is_ok() {
+            *value = GOLDEN_TIMESTAMP.to_string();
+        }
+    }
+    serde_json::to_value(finding).expect("failed to serialize finding")
+}
+
+/// Serializes findings as a golden file: one [Finding] per line, as JSON,
+/// without timestamps
+pub fn to_golden_ndjson(findings: &[Finding]) -> String {
+    findings
+        .iter()
+        .map(|finding| format!("{}\n", golden_value(finding)))
+        .collect()
+}
+
+/// Compares findings with the ones a golden file expects, in order and
+/// ignoring timestamps. Blank lines in the golden file are skipped. Returns a
+/// description of every difference if they don't match.
+pub fn compare_golden_findings(findings: &[Finding], golden: &str) -> Result<(), String> {
+    let mut expected = Vec::new();
+    for (line_num, line) in golden.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let finding: Finding = serde_json::from_str(line)
+            .map_err(|err| format!("line {}: couldn't parse finding: {err}", line_num + 1))?;
+        expected.push(golden_value(&finding));
+    }
+    let actual: Vec<Value> = findings.iter().map(golden_value).collect();
+
+    let mut differences = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(expected), Some(actual)) if expected == actual => {}
+            (Some(expected), Some(actual)) => differences.push_str(&format!(
+                "finding {i}: expected {expected}\n  but found {actual}\n"
+            )),
+            (Some(expected), None) => differences.push_str(&format!(
+                "finding {i}: expected {expected}\n  but found nothing\n"
+            )),
+            (None, Some(actual)) => {
+                differences.push_str(&format!("finding {i}: didn't expect {actual}\n"))
+            }
+            (None, None) => {}
+        }
+    }
+    if differences.is_empty() {
+        Ok(())
+    } else {
+        Err(differences)
+    }
+}
+
+/// Analyzes the QMDL fixture at `qmdl_path` with only the analyzer named
+/// `toggle` enabled, with its default settings, and checks its findings
+/// against the golden file at `golden_path` (see [compare_golden_findings]).
+/// If [UPDATE_GOLDEN_VAR] is set, the golden file is written instead.
+pub fn check_golden_findings(
+    toggle: &str,
+    qmdl_path: &Path,
+    golden_path: &Path,
+) -> Result<(), String> {
+    let qmdl = fs::read(qmdl_path)
+        .map_err(|err| format!("couldn't read {}: {err}", qmdl_path.display()))?;
+    let mut harness = Harness::new_with_config(&AnalyzerConfig::default().only(toggle)?);
+    let (_, findings) = analyze_containers(&mut harness, containers_from_qmdl(&qmdl));
+    if std::env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        return fs::write(golden_path, to_golden_ndjson(&findings))
+            .map_err(|err| format!("couldn't write {}: {err}", golden_path.display()));
+    }
+    let golden = fs::read_to_string(golden_path)
+        .map_err(|err| format!("couldn't read {}: {err}", golden_path.display()))?;
+    compare_golden_findings(&findings, &golden).map_err(|differences| {
+        format!(
+            "{toggle}'s findings in {} don't match {}:\n{differences}(if that's intended, set {UPDATE_GOLDEN_VAR}=1 to update it)",
+            qmdl_path.display(),
+            golden_path.display()
+        )
+    })
+}
+
+/// Like [check_golden_findings], but panics if the findings don't match
+pub fn assert_golden_findings(toggle: &str, qmdl_path: &Path, golden_path: &Path) {
+    if let Err(err) = check_golden_findings(toggle, qmdl_path, golden_path) {
+        panic!("{err}");
+    }
+}
+
+/// Checks every golden file in `dir`, which holds a directory for each
+/// analyzer, named after its toggle, of `<case>.qmdl` fixtures and the
+/// `<case>.ndjson` golden files of what the analyzer finds in them. Panics
+/// listing every case that doesn't match.
+pub fn assert_golden_dir(dir: &Path) {
+    let mut failures = Vec::new();
+    let mut checked = 0;
+    for analyzer_dir in fs::read_dir(dir).expect("couldn't read golden file directory") {
+        let analyzer_dir = analyzer_dir
+            .expect("couldn't read golden file directory")
+            .path();
+        let Some(toggle) = analyzer_dir.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if !analyzer_dir.is_dir() {
+            continue;
+        }
+        let mut qmdl_paths: Vec<_> = fs::read_dir(&analyzer_dir)
+            .expect("couldn't read golden file directory")
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "qmdl"))
+            .collect();
+        // so failures are listed in the same order every time
+        qmdl_paths.sort();
+        for qmdl_path in qmdl_paths {
+            checked += 1;
+            let golden_path = qmdl_path.with_extension("ndjson");
+            if let Err(err) = check_golden_findings(toggle, &qmdl_path, &golden_path) {
+                failures.push(err);
+            }
+        }
+    }
+    assert!(checked > 0, "no golden files in {}", dir.display());
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixture_builder() {
+        let builder = FixtureBuilder::new()
+            .lte_rrc(LteRrcSubtype::BcchDlSch, &[0x7e, 0x7d])
+            .advance(TimeDelta::seconds(10))
+            .nas(Nas4GMessageDirection::Downlink, &[0x07, 0x55, 0x01]);
+        let containers = builder.to_containers();
+        assert_eq!(containers.len(), 2);
+
+        let messages: Vec<Message> = containers
+            .into_iter()
+            .flat_map(|container| container.into_messages())
+            .map(|message| message.unwrap())
+            .collect();
+        let timestamps: Vec<_> = messages
+            .iter()
+            .map(|message| match message {
+                Message::Log { timestamp, .. } => timestamp.to_datetime(),
+                _ => panic!("expected a log message"),
+            })
+            .collect();
+        assert_eq!(timestamps[1] - timestamps[0], TimeDelta::seconds(10));
+        assert_eq!(messages, builder.messages);
+    }
+
+    #[test]
+    fn test_compare_golden_findings() {
+        let finding = |message: &str, timestamp: &str| Finding {
+            analyzer: "Test".to_string(),
+            message_index: 3,
+            timestamp: DateTime::parse_from_rfc3339(timestamp).ok(),
+            event: Event {
+                event_type: crate::analysis::analyzer::EventType::Informational,
+                message: message.to_string(),
+                explanation: String::new(),
+                evidence: vec![("seen".to_string(), timestamp.to_string())],
+            },
+        };
+        let golden = to_golden_ndjson(&[finding("a", "2024-01-01T00:00:00+00:00")]);
+        assert!(!golden.contains("2024"));
+
+        // timestamps don't matter, but everything else does
+        let later = finding("a", "2025-06-01T12:00:00+00:00");
+        assert_eq!(compare_golden_findings(&[later.clone()], &golden), Ok(()));
+        assert_eq!(
+            compare_golden_findings(&[later.clone()], &format!("\n{golden}\n")),
+            Ok(())
+        );
+        let differences =
+            compare_golden_findings(&[finding("b", "2024-01-01T00:00:00+00:00")], &golden)
+                .unwrap_err();
+        assert!(differences.starts_with("finding 0: expected"));
+        let differences = compare_golden_findings(&[later.clone(), later], &golden).unwrap_err();
+        assert!(differences.starts_with("finding 1: didn't expect"));
+        assert!(compare_golden_findings(&[], &golden).is_err());
+        assert!(compare_golden_findings(&[], "not json").is_err());
+    }
+}
@@ -1,23 +1,169 @@
 use crate::diag::*;
 use crate::gsmtap::*;
+use crate::util::LogLimiter;
 
 use log::error;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum GsmtapParserError {
-    #[error("Invalid LteRrcOtaMessage ext header version {0}")]
+    #[error(
+        "Unsupported LteRrcOtaMessage ext header version {0}: its PDU layout is unknown, so it can't be parsed. If you know which layout it uses, add it to the modem_profile in the analyzer config."
+    )]
     InvalidLteRrcOtaExtHeaderVersion(u8),
     #[error("Invalid LteRrcOtaMessage header/PDU number combination: {0}/{1}")]
     InvalidLteRrcOtaHeaderPduNum(u8, u8),
 }
 
+/// How a modem numbers the PDU types (i.e. the RRC channels) of its LTE RRC
+/// OTA log packets, which differs between ext header versions. Each layout is
+/// named after the first version known to use it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LteRrcPduLayout {
+    V2,
+    V9,
+    V14,
+    V19,
+    V20,
+}
+
+impl LteRrcPduLayout {
+    /// The layout of an ext header version this parser knows about
+    pub fn for_ext_header_version(ext_header_version: u8) -> Option<Self> {
+        match ext_header_version {
+            0x02 | 0x03 | 0x04 | 0x06 | 0x07 | 0x08 | 0x0d | 0x16 => Some(LteRrcPduLayout::V2),
+            0x09 | 0x0c => Some(LteRrcPduLayout::V9),
+            0x0e..=0x10 => Some(LteRrcPduLayout::V14),
+            0x13 | 0x1a | 0x1b => Some(LteRrcPduLayout::V19),
+            0x14 | 0x18 | 0x19 => Some(LteRrcPduLayout::V20),
+            _ => None,
+        }
+    }
+
+    fn gsmtap_type(self, pdu_num: u8) -> Option<GsmtapType> {
+        let subtype = match self {
+            LteRrcPduLayout::V2 => match pdu_num {
+                1 => LteRrcSubtype::BcchBch,
+                2 => LteRrcSubtype::BcchDlSch,
+                3 => LteRrcSubtype::MCCH,
+                4 => LteRrcSubtype::PCCH,
+                5 => LteRrcSubtype::DlCcch,
+                6 => LteRrcSubtype::DlDcch,
+                7 => LteRrcSubtype::UlCcch,
+                8 => LteRrcSubtype::UlDcch,
+                _ => return None,
+            },
+            LteRrcPduLayout::V9 => match pdu_num {
+                8 => LteRrcSubtype::BcchBch,
+                9 => LteRrcSubtype::BcchDlSch,
+                10 => LteRrcSubtype::MCCH,
+                11 => LteRrcSubtype::PCCH,
+                12 => LteRrcSubtype::DlCcch,
+                13 => LteRrcSubtype::DlDcch,
+                14 => LteRrcSubtype::UlCcch,
+                15 => LteRrcSubtype::UlDcch,
+                _ => return None,
+            },
+            LteRrcPduLayout::V14 => match pdu_num {
+                1 => LteRrcSubtype::BcchBch,
+                2 => LteRrcSubtype::BcchDlSch,
+                4 => LteRrcSubtype::MCCH,
+                5 => LteRrcSubtype::PCCH,
+                6 => LteRrcSubtype::DlCcch,
+                7 => LteRrcSubtype::DlDcch,
+                8 => LteRrcSubtype::UlCcch,
+                9 => LteRrcSubtype::UlDcch,
+                _ => return None,
+            },
+            LteRrcPduLayout::V19 => match pdu_num {
+                1 => LteRrcSubtype::BcchBch,
+                3 => LteRrcSubtype::BcchDlSch,
+                6 => LteRrcSubtype::MCCH,
+                7 => LteRrcSubtype::PCCH,
+                8 => LteRrcSubtype::DlCcch,
+                9 => LteRrcSubtype::DlDcch,
+                10 => LteRrcSubtype::UlCcch,
+                11 => LteRrcSubtype::UlDcch,
+                45 => LteRrcSubtype::BcchBchNb,
+                46 => LteRrcSubtype::BcchDlSchNb,
+                47 => LteRrcSubtype::PcchNb,
+                48 => LteRrcSubtype::DlCcchNb,
+                49 => LteRrcSubtype::DlDcchNb,
+                50 => LteRrcSubtype::UlCcchNb,
+                52 => LteRrcSubtype::UlDcchNb,
+                _ => return None,
+            },
+            LteRrcPduLayout::V20 => match pdu_num {
+                1 => LteRrcSubtype::BcchBch,
+                2 => LteRrcSubtype::BcchDlSch,
+                4 => LteRrcSubtype::MCCH,
+                5 => LteRrcSubtype::PCCH,
+                6 => LteRrcSubtype::DlCcch,
+                7 => LteRrcSubtype::DlDcch,
+                8 => LteRrcSubtype::UlCcch,
+                9 => LteRrcSubtype::UlDcch,
+                54 => LteRrcSubtype::BcchBchNb,
+                55 => LteRrcSubtype::BcchDlSchNb,
+                56 => LteRrcSubtype::PcchNb,
+                57 => LteRrcSubtype::DlCcchNb,
+                58 => LteRrcSubtype::DlDcchNb,
+                59 => LteRrcSubtype::UlCcchNb,
+                61 => LteRrcSubtype::UlDcchNb,
+                _ => return None,
+            },
+        };
+        Some(GsmtapType::LteRrc(subtype))
+    }
+}
+
+/// An ext header version's PDU layout, for a modem whose firmware uses one
+/// this parser doesn't know about
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LteRrcOtaLayout {
+    pub ext_header_version: u8,
+    pub layout: LteRrcPduLayout,
+}
+
+/// The diag log layouts a particular modem uses, beyond the ones this parser
+/// already knows, so captures from newer firmware can be parsed without
+/// waiting for a new release. Only the PDU numbering can be given this way:
+/// the fields of an LTE RRC OTA packet are still read according to the range
+/// its ext header version is in (see [LteRrcOtaPacket]).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModemProfile {
+    // these take precedence over the built-in layout of the same version
+    pub lte_rrc_ota: Vec<LteRrcOtaLayout>,
+}
+
+impl ModemProfile {
+    /// The PDU layout of the given LTE RRC OTA ext header version, per this
+    /// profile or else the built-in ones, or None if it's unknown
+    pub fn lte_rrc_pdu_layout(&self, ext_header_version: u8) -> Option<LteRrcPduLayout> {
+        self.lte_rrc_ota
+            .iter()
+            .find(|layout| layout.ext_header_version == ext_header_version)
+            .map(|layout| layout.layout)
+            .or_else(|| LteRrcPduLayout::for_ext_header_version(ext_header_version))
+    }
+}
+
 pub fn parse(msg: Message) -> Result<Option<(Timestamp, GsmtapMessage)>, GsmtapParserError> {
+    parse_with_profile(msg, &ModemProfile::default())
+}
+
+/// Like [parse], but also knows the layouts in the given [ModemProfile]
+pub fn parse_with_profile(
+    msg: Message,
+    profile: &ModemProfile,
+) -> Result<Option<(Timestamp, GsmtapMessage)>, GsmtapParserError> {
     if let Message::Log {
         timestamp, body, ..
     } = msg
     {
-        match log_to_gsmtap(body)? {
+        match log_to_gsmtap(body, profile)? {
             Some(msg) => Ok(Some((timestamp, msg))),
             None => Ok(None),
         }
@@ -26,114 +172,22 @@ pub fn parse(msg: Message) -> Result<Option<(Timestamp, GsmtapMessage)>, GsmtapP
     }
 }
 
-fn log_to_gsmtap(value: LogBody) -> Result<Option<GsmtapMessage>, GsmtapParserError> {
+fn log_to_gsmtap(
+    value: LogBody,
+    profile: &ModemProfile,
+) -> Result<Option<GsmtapMessage>, GsmtapParserError> {
     match value {
         LogBody::LteRrcOtaMessage {
             ext_header_version,
             packet,
         } => {
-            let gsmtap_type = match ext_header_version {
-                0x02 | 0x03 | 0x04 | 0x06 | 0x07 | 0x08 | 0x0d | 0x16 => match packet.get_pdu_num()
-                {
-                    1 => GsmtapType::LteRrc(LteRrcSubtype::BcchBch),
-                    2 => GsmtapType::LteRrc(LteRrcSubtype::BcchDlSch),
-                    3 => GsmtapType::LteRrc(LteRrcSubtype::MCCH),
-                    4 => GsmtapType::LteRrc(LteRrcSubtype::PCCH),
-                    5 => GsmtapType::LteRrc(LteRrcSubtype::DlCcch),
-                    6 => GsmtapType::LteRrc(LteRrcSubtype::DlDcch),
-                    7 => GsmtapType::LteRrc(LteRrcSubtype::UlCcch),
-                    8 => GsmtapType::LteRrc(LteRrcSubtype::UlDcch),
-                    pdu => {
-                        return Err(GsmtapParserError::InvalidLteRrcOtaHeaderPduNum(
-                            ext_header_version,
-                            pdu,
-                        ));
-                    }
-                },
-                0x09 | 0x0c => match packet.get_pdu_num() {
-                    8 => GsmtapType::LteRrc(LteRrcSubtype::BcchBch),
-                    9 => GsmtapType::LteRrc(LteRrcSubtype::BcchDlSch),
-                    10 => GsmtapType::LteRrc(LteRrcSubtype::MCCH),
-                    11 => GsmtapType::LteRrc(LteRrcSubtype::PCCH),
-                    12 => GsmtapType::LteRrc(LteRrcSubtype::DlCcch),
-                    13 => GsmtapType::LteRrc(LteRrcSubtype::DlDcch),
-                    14 => GsmtapType::LteRrc(LteRrcSubtype::UlCcch),
-                    15 => GsmtapType::LteRrc(LteRrcSubtype::UlDcch),
-                    pdu => {
-                        return Err(GsmtapParserError::InvalidLteRrcOtaHeaderPduNum(
-                            ext_header_version,
-                            pdu,
-                        ));
-                    }
-                },
-                0x0e..=0x10 => match packet.get_pdu_num() {
-                    1 => GsmtapType::LteRrc(LteRrcSubtype::BcchBch),
-                    2 => GsmtapType::LteRrc(LteRrcSubtype::BcchDlSch),
-                    4 => GsmtapType::LteRrc(LteRrcSubtype::MCCH),
-                    5 => GsmtapType::LteRrc(LteRrcSubtype::PCCH),
-                    6 => GsmtapType::LteRrc(LteRrcSubtype::DlCcch),
-                    7 => GsmtapType::LteRrc(LteRrcSubtype::DlDcch),
-                    8 => GsmtapType::LteRrc(LteRrcSubtype::UlCcch),
-                    9 => GsmtapType::LteRrc(LteRrcSubtype::UlDcch),
-                    pdu => {
-                        return Err(GsmtapParserError::InvalidLteRrcOtaHeaderPduNum(
-                            ext_header_version,
-                            pdu,
-                        ));
-                    }
-                },
-                0x13 | 0x1a | 0x1b => match packet.get_pdu_num() {
-                    1 => GsmtapType::LteRrc(LteRrcSubtype::BcchBch),
-                    3 => GsmtapType::LteRrc(LteRrcSubtype::BcchDlSch),
-                    6 => GsmtapType::LteRrc(LteRrcSubtype::MCCH),
-                    7 => GsmtapType::LteRrc(LteRrcSubtype::PCCH),
-                    8 => GsmtapType::LteRrc(LteRrcSubtype::DlCcch),
-                    9 => GsmtapType::LteRrc(LteRrcSubtype::DlDcch),
-                    10 => GsmtapType::LteRrc(LteRrcSubtype::UlCcch),
-                    11 => GsmtapType::LteRrc(LteRrcSubtype::UlDcch),
-                    45 => GsmtapType::LteRrc(LteRrcSubtype::BcchBchNb),
-                    46 => GsmtapType::LteRrc(LteRrcSubtype::BcchDlSchNb),
-                    47 => GsmtapType::LteRrc(LteRrcSubtype::PcchNb),
-                    48 => GsmtapType::LteRrc(LteRrcSubtype::DlCcchNb),
-                    49 => GsmtapType::LteRrc(LteRrcSubtype::DlDcchNb),
-                    50 => GsmtapType::LteRrc(LteRrcSubtype::UlCcchNb),
-                    52 => GsmtapType::LteRrc(LteRrcSubtype::UlDcchNb),
-                    pdu => {
-                        return Err(GsmtapParserError::InvalidLteRrcOtaHeaderPduNum(
-                            ext_header_version,
-                            pdu,
-                        ));
-                    }
-                },
-                0x14 | 0x18 | 0x19 => match packet.get_pdu_num() {
-                    1 => GsmtapType::LteRrc(LteRrcSubtype::BcchBch),
-                    2 => GsmtapType::LteRrc(LteRrcSubtype::BcchDlSch),
-                    4 => GsmtapType::LteRrc(LteRrcSubtype::MCCH),
-                    5 => GsmtapType::LteRrc(LteRrcSubtype::PCCH),
-                    6 => GsmtapType::LteRrc(LteRrcSubtype::DlCcch),
-                    7 => GsmtapType::LteRrc(LteRrcSubtype::DlDcch),
-                    8 => GsmtapType::LteRrc(LteRrcSubtype::UlCcch),
-                    9 => GsmtapType::LteRrc(LteRrcSubtype::UlDcch),
-                    54 => GsmtapType::LteRrc(LteRrcSubtype::BcchBchNb),
-                    55 => GsmtapType::LteRrc(LteRrcSubtype::BcchDlSchNb),
-                    56 => GsmtapType::LteRrc(LteRrcSubtype::PcchNb),
-                    57 => GsmtapType::LteRrc(LteRrcSubtype::DlCcchNb),
-                    58 => GsmtapType::LteRrc(LteRrcSubtype::DlDcchNb),
-                    59 => GsmtapType::LteRrc(LteRrcSubtype::UlCcchNb),
-                    61 => GsmtapType::LteRrc(LteRrcSubtype::UlDcchNb),
-                    pdu => {
-                        return Err(GsmtapParserError::InvalidLteRrcOtaHeaderPduNum(
-                            ext_header_version,
-                            pdu,
-                        ));
-                    }
-                },
-                _ => {
-                    return Err(GsmtapParserError::InvalidLteRrcOtaExtHeaderVersion(
-                        ext_header_version,
-                    ));
-                }
-            };
+            let layout = profile.lte_rrc_pdu_layout(ext_header_version).ok_or(
+                GsmtapParserError::InvalidLteRrcOtaExtHeaderVersion(ext_header_version),
+            )?;
+            let pdu_num = packet.get_pdu_num();
+            let gsmtap_type = layout.gsmtap_type(pdu_num).ok_or(
+                GsmtapParserError::InvalidLteRrcOtaHeaderPduNum(ext_header_version, pdu_num),
+            )?;
             let mut header = GsmtapHeader::new(gsmtap_type);
             header.arfcn = packet.get_earfcn().try_into().unwrap_or(0);
             header.frame_number = packet.get_sfn();
@@ -153,7 +207,10 @@ fn log_to_gsmtap(value: LogBody) -> Result<Option<GsmtapMessage>, GsmtapParserEr
             }))
         }
         _ => {
-            error!("gsmtap_sink: ignoring unhandled log type: {value:?}");
+            static UNHANDLED_LOG_TYPES: LogLimiter = LogLimiter::new(1000);
+            if UNHANDLED_LOG_TYPES.check("unhandled log types") {
+                error!("gsmtap_sink: ignoring unhandled log type: {value:?}");
+            }
             Ok(None)
         }
     }
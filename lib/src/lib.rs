@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 
 pub mod analysis;
 pub mod diag;
+pub mod fixtures;
 pub mod gsmtap;
 pub mod gsmtap_parser;
 pub mod hdlc;
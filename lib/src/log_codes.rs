@@ -103,3 +103,21 @@ pub const WCDMA_SIGNALLING_MESSAGE: u32 = 0x412f;
 pub const LOG_DATA_PROTOCOL_LOGGING_C: u32 = 0x11eb;
 
 pub const LOG_UMTS_NAS_OTA_MESSAGE_LOG_PACKET_C: u32 = 0x713a;
+
+// The log codes of the messages analyzers can see, i.e. the ones that are
+// decoded into an InformationElement.
+
+pub const ANALYZER_RRC_LOG_CODES: &[u16] = &[LOG_LTE_RRC_OTA_MSG_LOG_C as u16];
+pub const ANALYZER_NAS_LOG_CODES: &[u16] = &[
+    LOG_LTE_NAS_ESM_OTA_IN_MSG_LOG_C as u16,
+    LOG_LTE_NAS_ESM_OTA_OUT_MSG_LOG_C as u16,
+    LOG_LTE_NAS_EMM_OTA_IN_MSG_LOG_C as u16,
+    LOG_LTE_NAS_EMM_OTA_OUT_MSG_LOG_C as u16,
+];
+pub const ANALYZER_LOG_CODES: &[u16] = &[
+    LOG_LTE_RRC_OTA_MSG_LOG_C as u16,
+    LOG_LTE_NAS_ESM_OTA_IN_MSG_LOG_C as u16,
+    LOG_LTE_NAS_ESM_OTA_OUT_MSG_LOG_C as u16,
+    LOG_LTE_NAS_EMM_OTA_IN_MSG_LOG_C as u16,
+    LOG_LTE_NAS_EMM_OTA_OUT_MSG_LOG_C as u16,
+];
@@ -1,13 +1,22 @@
 //! Qualcomm Mobile Diagnostic Log (QMDL) files have a very simple format: just
 //! a series of of concatenated HDLC encapsulated diag::Message structs.
 //! QmdlReader and QmdlWriter can read and write MessagesContainers to and from
-//! QMDL files.
+//! QMDL files, and InterleavedQmdlReader reads two QMDL files captured side by
+//! side as one.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use chrono::{DateTime, FixedOffset};
 
 use crate::diag::{DataType, HdlcEncapsulatedMessage, MESSAGE_TERMINATOR, MessagesContainer};
 
+use async_compression::tokio::bufread::GzipDecoder;
 use futures::TryStream;
 use log::error;
-use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
 pub struct QmdlWriter<T>
 where
@@ -41,6 +50,52 @@ where
     }
 }
 
+/// A QMDL input which may or may not be gzip-compressed. Use
+/// [QmdlInput::detect] to sniff the compression from the first bytes of the
+/// input.
+pub enum QmdlInput<T> {
+    Plain(BufReader<T>),
+    Gzip(GzipDecoder<BufReader<T>>),
+}
+
+impl<T> QmdlInput<T>
+where
+    T: AsyncRead + Unpin,
+{
+    pub async fn detect(reader: T) -> Result<Self, std::io::Error> {
+        let mut reader = BufReader::new(reader);
+        if reader.fill_buf().await?.starts_with(&GZIP_MAGIC) {
+            let mut decoder = GzipDecoder::new(reader);
+            // gzip files may contain several concatenated members (e.g. from
+            // `cat a.gz b.gz`), which should decompress to a single stream
+            decoder.multiple_members(true);
+            Ok(QmdlInput::Gzip(decoder))
+        } else {
+            Ok(QmdlInput::Plain(reader))
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        matches!(self, QmdlInput::Gzip(_))
+    }
+}
+
+impl<T> AsyncRead for QmdlInput<T>
+where
+    T: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            QmdlInput::Plain(reader) => Pin::new(reader).poll_read(cx, buf),
+            QmdlInput::Gzip(decoder) => Pin::new(decoder).poll_read(cx, buf),
+        }
+    }
+}
+
 pub struct QmdlReader<T>
 where
     T: AsyncRead,
@@ -62,6 +117,22 @@ where
         }
     }
 
+    /// Creates a reader which transparently decompresses gzipped input.
+    /// `max_bytes` refers to the uncompressed size, so it's ignored for
+    /// compressed input, which is simply read until EOF.
+    pub async fn new_maybe_compressed(
+        reader: T,
+        max_bytes: Option<usize>,
+    ) -> Result<QmdlReader<QmdlInput<T>>, std::io::Error> {
+        let input = QmdlInput::detect(reader).await?;
+        let max_bytes = if input.is_compressed() {
+            None
+        } else {
+            max_bytes
+        };
+        Ok(QmdlReader::new(input, max_bytes))
+    }
+
     pub fn as_stream(
         &mut self,
     ) -> impl TryStream<Ok = MessagesContainer, Error = std::io::Error> + '_ {
@@ -91,6 +162,9 @@ where
 
         let mut buf = Vec::new();
         let bytes_read = self.reader.read_until(MESSAGE_TERMINATOR, &mut buf).await?;
+        if bytes_read == 0 {
+            return Ok(None);
+        }
         self.bytes_read += bytes_read;
 
         // Since QMDL is just a flat list of messages, we can't actually
@@ -109,6 +183,121 @@ where
     }
 }
 
+// A container read ahead from one of an InterleavedQmdlReader's inputs,
+// along with when it was logged
+struct PeekedContainer {
+    container: MessagesContainer,
+    timestamp: Option<DateTime<FixedOffset>>,
+}
+
+// One of an InterleavedQmdlReader's inputs
+struct InterleavedInput<T>
+where
+    T: AsyncRead,
+{
+    reader: QmdlReader<T>,
+    peeked: Option<PeekedContainer>,
+    // the latest timestamp read from this input, which containers without
+    // a timestamp of their own are placed at
+    last_timestamp: Option<DateTime<FixedOffset>>,
+    done: bool,
+}
+
+impl<T> InterleavedInput<T>
+where
+    T: AsyncRead + Unpin,
+{
+    fn new(reader: QmdlReader<T>) -> Self {
+        InterleavedInput {
+            reader,
+            peeked: None,
+            last_timestamp: None,
+            done: false,
+        }
+    }
+
+    async fn peek(&mut self) -> Result<Option<&PeekedContainer>, std::io::Error> {
+        if self.peeked.is_none() && !self.done {
+            match self.reader.get_next_messages_container().await? {
+                Some(container) => {
+                    if let Some((first, _)) = container.timestamp_range() {
+                        self.last_timestamp = Some(first);
+                    }
+                    self.peeked = Some(PeekedContainer {
+                        container,
+                        timestamp: self.last_timestamp,
+                    });
+                }
+                None => self.done = true,
+            }
+        }
+        Ok(self.peeked.as_ref())
+    }
+}
+
+/// Reads two QMDL files that were captured at the same time, like the control
+/// and user plane of a split capture, as though they were one, merging their
+/// containers in the order they were logged. Each input is expected to be in
+/// order already. Containers without a log timestamp stay next to the ones
+/// read before them from the same input, and ties go to the primary input.
+/// Without a secondary input, this just reads the primary one.
+pub struct InterleavedQmdlReader<T>
+where
+    T: AsyncRead,
+{
+    primary: InterleavedInput<T>,
+    secondary: Option<InterleavedInput<T>>,
+}
+
+impl<T> InterleavedQmdlReader<T>
+where
+    T: AsyncRead + Unpin,
+{
+    pub fn new(primary: QmdlReader<T>, secondary: Option<QmdlReader<T>>) -> Self {
+        InterleavedQmdlReader {
+            primary: InterleavedInput::new(primary),
+            secondary: secondary.map(InterleavedInput::new),
+        }
+    }
+
+    pub fn as_stream(
+        &mut self,
+    ) -> impl TryStream<Ok = MessagesContainer, Error = std::io::Error> + '_ {
+        futures::stream::try_unfold(self, |reader| async {
+            let maybe_container = reader.get_next_messages_container().await?;
+            match maybe_container {
+                Some(container) => Ok(Some((container, reader))),
+                None => Ok(None),
+            }
+        })
+    }
+
+    pub async fn get_next_messages_container(
+        &mut self,
+    ) -> Result<Option<MessagesContainer>, std::io::Error> {
+        let Some(secondary) = self.secondary.as_mut() else {
+            return self.primary.reader.get_next_messages_container().await;
+        };
+        let primary_timestamp = self.primary.peek().await?.map(|peeked| peeked.timestamp);
+        let secondary_timestamp = secondary.peek().await?.map(|peeked| peeked.timestamp);
+        let secondary_first = match (primary_timestamp, secondary_timestamp) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(Some(primary)), Some(Some(secondary))) => secondary < primary,
+            // a container from before its input's first timestamp can't be
+            // ordered, so it goes as soon as it's reached
+            (Some(Some(_)), Some(None)) => true,
+            (Some(None), Some(_)) => false,
+        };
+        let input = if secondary_first {
+            secondary
+        } else {
+            &mut self.primary
+        };
+        Ok(input.peeked.take().map(|peeked| peeked.container))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::io::Cursor;
@@ -241,4 +430,85 @@ mod test {
             Ok(None)
         ));
     }
+
+    #[tokio::test]
+    async fn test_gzipped_qmdl_reader() {
+        use async_compression::tokio::write::GzipEncoder;
+
+        let mut encoder = GzipEncoder::new(Vec::new());
+        encoder.write_all(&get_test_message_bytes()).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        let compressed = encoder.into_inner();
+
+        // the compressed size shouldn't be used as a bound on the
+        // decompressed data
+        let limit = Some(compressed.len());
+        let mut reader = QmdlReader::new_maybe_compressed(Cursor::new(compressed), limit)
+            .await
+            .unwrap();
+        for message in get_test_messages() {
+            let expected_container = MessagesContainer {
+                data_type: DataType::UserSpace,
+                num_messages: 1,
+                messages: vec![message],
+            };
+            assert_eq!(
+                expected_container,
+                reader.get_next_messages_container().await.unwrap().unwrap()
+            );
+        }
+        assert!(matches!(
+            reader.get_next_messages_container().await,
+            Ok(None)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_uncompressed_input_detection() {
+        let input = QmdlInput::detect(Cursor::new(get_test_message_bytes()))
+            .await
+            .unwrap();
+        assert!(!input.is_compressed());
+    }
+
+    #[tokio::test]
+    async fn test_interleaved_qmdl_reader() {
+        // a log message with the given timestamp, or some other message
+        // without one
+        let message = |ts: Option<u64>, tag: u8| {
+            let data = match ts {
+                Some(ts) => {
+                    let mut data = vec![16, 0, 20, 0, 20, 0, 0xc0, 0xb0];
+                    data.extend_from_slice(&(ts << 16).to_le_bytes());
+                    data
+                }
+                None => vec![75, 18, 0, tag],
+            };
+            hdlc_encapsulate(&data, &CRC_CCITT)
+        };
+        let a = message(None, 1);
+        let b = message(None, 2);
+        let [t1, t2, t3, t4, t5, t6] = [1, 2, 3, 4, 5, 6].map(|ts| message(Some(ts), 0));
+        let primary = [t1.clone(), t3.clone(), t5.clone()].concat();
+        let secondary = [a.clone(), t2.clone(), b.clone(), t4.clone(), t6.clone()].concat();
+
+        let mut reader = InterleavedQmdlReader::new(
+            QmdlReader::new(Cursor::new(primary.clone()), None),
+            Some(QmdlReader::new(Cursor::new(secondary), None)),
+        );
+        let mut read = Vec::new();
+        while let Some(container) = reader.get_next_messages_container().await.unwrap() {
+            read.push(container.messages[0].data.clone());
+        }
+        // messages without a timestamp stay where they were in their input
+        assert_eq!(read, [a, t1.clone(), t2, b, t3.clone(), t4, t5.clone(), t6]);
+
+        let mut reader =
+            InterleavedQmdlReader::new(QmdlReader::new(Cursor::new(primary), None), None);
+        let mut read = Vec::new();
+        while let Some(container) = reader.get_next_messages_container().await.unwrap() {
+            read.push(container.messages[0].data.clone());
+        }
+        assert_eq!(read, [t1, t3, t5]);
+    }
 }
@@ -1,3 +1,6 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::warn;
 use serde::Serialize;
 
 #[cfg(target_family = "unix")]
@@ -49,3 +52,57 @@ impl RuntimeMetadata {
         }
     }
 }
+
+/// Guards a log message which may repeat many times, e.g. once per message in
+/// a pathological capture. The first `limit` occurrences are logged normally,
+/// after which a notice is logged and further occurrences are suppressed,
+/// apart from a running total every `limit` occurrences.
+///
+/// Since it only needs a shared reference, a `LogLimiter` can be a `static`.
+pub struct LogLimiter {
+    count: AtomicUsize,
+    limit: usize,
+}
+
+impl LogLimiter {
+    pub const fn new(limit: usize) -> Self {
+        LogLimiter {
+            count: AtomicUsize::new(0),
+            limit,
+        }
+    }
+
+    /// Records an occurrence, returning whether it should be logged. `what`
+    /// describes the occurrences in the suppression notices, e.g. "parse
+    /// errors".
+    pub fn check(&self, what: &str) -> bool {
+        let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+        if count <= self.limit {
+            return true;
+        }
+        if count == self.limit + 1 {
+            warn!("logged {} {what}, suppressing further", self.limit);
+        } else if count % self.limit == 0 {
+            warn!("suppressed {} {what} so far", count - self.limit);
+        }
+        false
+    }
+
+    /// The total number of occurrences recorded, logged or not
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_limiter() {
+        let limiter = LogLimiter::new(3);
+        let logged: Vec<bool> = (0..5).map(|_| limiter.check("errors")).collect();
+        assert_eq!(logged, vec![true, true, true, false, false]);
+        assert_eq!(limiter.count(), 5);
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use chrono::TimeDelta;
+use rayhunter::analysis::analyzer::{AnalyzerConfig, EventType, Harness, Severity};
+use rayhunter::analysis::imsi_requested::ImsiRequestedAnalyzer;
+use rayhunter::diag::Nas4GMessageDirection;
+use rayhunter::fixtures::{
+    FixtureBuilder, Replay, analyze_containers, assert_golden_dir, containers_from_qmdl,
+};
+use rayhunter::gsmtap::LteRrcSubtype;
+
+const SIB1: &[u8] = &[
+    0x48, 0x4c, 0x46, 0x90, 0x10, 0x60, 0x00, 0x18, 0xfd, 0x1a, 0x92, 0x07, 0xe2, 0x21, 0x03, 0x10,
+    0x8a, 0xc2, 0x1b, 0xdc, 0x09, 0x80, 0x22, 0x92, 0xcd, 0xd2, 0x00, 0x00,
+];
+const IMSI_IDENTITY_REQUEST: &[u8] = &[0x07, 0x55, 0x01];
+
+#[test]
+fn test_sib1_fixture() {
+    let qmdl = include_bytes!("fixtures/sib1.qmdl");
+    let builder = FixtureBuilder::new().lte_rrc(LteRrcSubtype::BcchDlSch, SIB1);
+    assert_eq!(builder.to_qmdl(), qmdl);
+
+    // every analyzer should be able to handle it
+    let mut harness = Harness::new_with_config(&AnalyzerConfig::default());
+    let (rows, _) = analyze_containers(&mut harness, containers_from_qmdl(qmdl));
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].skipped_message_reason, None);
+    assert!(rows[0].packet_timestamp.is_some());
+    assert_eq!(harness.get_relevant_message_count(), 1);
+}
+
+#[test]
+fn test_no_relevant_messages() {
+    let qmdl = include_bytes!("fixtures/sib1.qmdl");
+    // only look at NAS, which the fixture doesn't have
+    let mut harness = Harness::new();
+    harness.add_analyzer(Box::new(ImsiRequestedAnalyzer::new()));
+    harness.set_log_codes(Some(HashSet::from([0xb0ec])));
+    let (rows, findings) = analyze_containers(&mut harness, containers_from_qmdl(qmdl));
+    assert_eq!(rows.len(), 1);
+    assert!(findings.is_empty());
+    assert_eq!(harness.get_relevant_message_count(), 0);
+}
+
+#[test]
+fn test_identity_request_fixture() {
+    let qmdl = include_bytes!("fixtures/identity_request.qmdl");
+    let builder = FixtureBuilder::new()
+        .lte_rrc(LteRrcSubtype::BcchDlSch, SIB1)
+        .advance(TimeDelta::seconds(1))
+        .nas(Nas4GMessageDirection::Downlink, IMSI_IDENTITY_REQUEST);
+    assert_eq!(builder.to_qmdl(), qmdl);
+
+    let mut harness = Harness::new();
+    harness.add_analyzer(Box::new(ImsiRequestedAnalyzer::new()));
+    let (rows, findings) = analyze_containers(&mut harness, containers_from_qmdl(qmdl));
+    assert!(rows.iter().all(|row| row.skipped_message_reason.is_none()));
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].message_index, 1);
+    assert!(matches!(
+        findings[0].event.event_type,
+        EventType::QualitativeWarning {
+            severity: Severity::High
+        }
+    ));
+}
+
+#[test]
+fn test_replay_identity_request_fixture() {
+    let qmdl = include_bytes!("fixtures/identity_request.qmdl");
+    let mut replay = Replay::new("imsi_requested", &AnalyzerConfig::default()).unwrap();
+    assert_eq!(replay.harness().get_metadata().analyzers.len(), 1);
+    assert_eq!(replay.state(), Some("Unattached (timeout counter 0)"));
+
+    let steps = replay.run(containers_from_qmdl(qmdl));
+    assert_eq!(steps.len(), 2);
+    assert!(!steps[0].state_changed);
+    assert_eq!(steps[0].events().count(), 0);
+    assert!(steps[1].state_changed);
+    assert_eq!(
+        steps[1].state.as_deref(),
+        Some("IdentityRequest (timeout counter 1)")
+    );
+    assert_eq!(steps[1].events().count(), 1);
+
+    assert!(Replay::new("no_such_analyzer", &AnalyzerConfig::default()).is_err());
+}
+
+#[test]
+fn test_golden_findings() {
+    assert_golden_dir(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden"));
+}
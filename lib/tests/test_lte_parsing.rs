@@ -1,7 +1,7 @@
 use deku::prelude::*;
 use rayhunter::{
     diag::{LogBody, LteRrcOtaPacket, Message, Timestamp},
-    gsmtap_parser,
+    gsmtap_parser::{self, GsmtapParserError, LteRrcOtaLayout, LteRrcPduLayout, ModemProfile},
 };
 
 // Tests here are based on https://github.com/fgsect/scat/blob/97442580e628de414c9f7c2a185f4e28d0ee7523/tests/test_diagltelogparser.py
@@ -523,3 +523,33 @@ fn test_lte_rrc_ota() {
     assert_eq!(gsmtap_msg.header.subtype, 5);
     assert_eq!(gsmtap_msg.header.subslot, 5);
 }
+
+#[test]
+fn test_lte_rrc_ota_modem_profile() {
+    // the first v26 packet above, but claiming an ext header version that
+    // isn't known
+    let v28_binary = &[
+        0x10, 0x0, 0x23, 0x0, 0x23, 0x0, 0xc0, 0xb0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x1c,
+        0xf, 0x40, 0xf, 0x40, 0x1, 0xe, 0x1, 0x13, 0x7, 0x0, 0x0, 0x0, 0x0, 0xb, 0x0, 0x0, 0x0,
+        0x0, 0x2, 0x0, 0x10, 0x15,
+    ];
+    let (_, parsed) = Message::from_bytes((v28_binary, 0)).unwrap();
+    assert!(matches!(
+        gsmtap_parser::parse(parsed.clone()),
+        Err(GsmtapParserError::InvalidLteRrcOtaExtHeaderVersion(28))
+    ));
+
+    // a profile that says it's laid out like v26 parses it the same way
+    let profile = ModemProfile {
+        lte_rrc_ota: vec![LteRrcOtaLayout {
+            ext_header_version: 28,
+            layout: LteRrcPduLayout::V19,
+        }],
+    };
+    let (_, gsmtap_msg) = gsmtap_parser::parse_with_profile(parsed, &profile)
+        .unwrap()
+        .unwrap();
+    assert_eq!(&gsmtap_msg.payload, &[0x10, 0x15]);
+    assert_eq!(gsmtap_msg.header.packet_type, 13);
+    assert_eq!(gsmtap_msg.header.subtype, 3);
+}